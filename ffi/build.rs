@@ -0,0 +1,17 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    // Generate the C header from the exported items, so it never drifts from the implementation.
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let include_dir = crate_dir.join("include");
+    std::fs::create_dir_all(&include_dir).expect("failed to create the include directory");
+    cbindgen::generate(&crate_dir)
+        .expect("failed to generate the C header")
+        .write_to_file(include_dir.join("fvm.h"));
+}
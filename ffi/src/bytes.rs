@@ -0,0 +1,118 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Byte strings passed across the C interface.
+
+use std::ptr;
+
+/// A byte string allocated by the FVM and owned by the embedder, who must free it with
+/// [`fvm_bytes_free`]. Empty byte strings have a null `data` pointer.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FvmBytes {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl FvmBytes {
+    /// An empty byte string, which doesn't need to be freed.
+    pub fn empty() -> Self {
+        FvmBytes {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    /// Hands a byte vector over to the embedder.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        if bytes.is_empty() {
+            return Self::empty();
+        }
+        let bytes = bytes.into_boxed_slice();
+        let len = bytes.len();
+        FvmBytes {
+            data: Box::into_raw(bytes) as *mut u8,
+            len,
+        }
+    }
+
+    /// Frees the byte string.
+    ///
+    /// # Safety
+    ///
+    /// The byte string must have been allocated by [`FvmBytes::new`], and not freed already.
+    pub(crate) unsafe fn free(self) {
+        if !self.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                self.data, self.len,
+            )));
+        }
+    }
+}
+
+/// Frees a byte string returned by the FVM. Freeing an empty byte string is a no-op.
+///
+/// # Safety
+///
+/// The byte string must have been returned by the FVM, and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fvm_bytes_free(bytes: FvmBytes) {
+    bytes.free()
+}
+
+/// A buffer owned by the FVM, into which callbacks copy the data they return with
+/// [`fvm_buffer_write`].
+#[derive(Debug, Default)]
+pub struct FvmBuffer(pub(crate) Vec<u8>);
+
+/// Appends `len` bytes at `data` to a buffer passed to a callback.
+///
+/// # Safety
+///
+/// `buf` must be the buffer passed to the running callback, and `data` must point to `len`
+/// readable bytes (or be null if `len` is zero).
+#[no_mangle]
+pub unsafe extern "C" fn fvm_buffer_write(buf: *mut FvmBuffer, data: *const u8, len: usize) {
+    if let Some(buf) = buf.as_mut() {
+        buf.0.extend_from_slice(borrow(data, len));
+    }
+}
+
+/// Borrows `len` bytes at `data`, which may be null if `len` is zero.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes that outlive the returned slice.
+pub(crate) unsafe fn borrow<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(data, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        let bytes = FvmBytes::new(b"abc".to_vec());
+        assert_eq!(unsafe { borrow(bytes.data, bytes.len) }, b"abc");
+        unsafe { fvm_bytes_free(bytes) };
+
+        let empty = FvmBytes::new(Vec::new());
+        assert!(empty.data.is_null());
+        unsafe { fvm_bytes_free(empty) };
+    }
+
+    #[test]
+    fn buffer_write() {
+        let mut buf = FvmBuffer::default();
+        unsafe {
+            fvm_buffer_write(&mut buf, b"ab".as_ptr(), 2);
+            fvm_buffer_write(&mut buf, ptr::null(), 0);
+            fvm_buffer_write(&mut buf, b"c".as_ptr(), 1);
+        }
+        assert_eq!(buf.0, b"abc");
+    }
+}
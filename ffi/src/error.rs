@@ -0,0 +1,107 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Error reporting across the C interface.
+
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+
+use thiserror::Error;
+
+/// The status returned by fallible functions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FvmStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// An argument was invalid (e.g., a malformed CID or message). The call had no effect.
+    InvalidArgument = 1,
+    /// The call failed. If a machine was being used, it should be discarded.
+    Error = 2,
+    /// The FVM panicked. The library is in an unknown state, and the process should exit.
+    Panic = 3,
+}
+
+/// An invalid argument, reported as [`FvmStatus::InvalidArgument`].
+#[derive(Error, Debug)]
+#[error("invalid argument: {0}")]
+pub struct InvalidArgument(pub String);
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// Returns a description of the last error returned on the calling thread, as a nul-terminated
+/// UTF-8 string. The string is owned by the FVM, and is valid until the next call into the FVM on
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn fvm_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+fn set_last_error(msg: String) {
+    // Error messages shouldn't contain nul bytes, but don't lose the message if they do.
+    let msg = CString::new(msg.replace('\0', "\\0")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = msg);
+}
+
+/// Runs the body of an exported function, recording its error (or panic) for [`fvm_last_error`].
+/// Panics must not unwind into the embedder.
+pub(crate) fn catch(f: impl FnOnce() -> anyhow::Result<()>) -> FvmStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => FvmStatus::Ok,
+        Ok(Err(e)) => {
+            set_last_error(format!("{:#}", e));
+            if e.chain().any(|c| c.is::<InvalidArgument>()) {
+                FvmStatus::InvalidArgument
+            } else {
+                FvmStatus::Error
+            }
+        }
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".into());
+            set_last_error(format!("panic: {}", msg));
+            FvmStatus::Panic
+        }
+    }
+}
+
+/// Returns an [`InvalidArgument`] error.
+macro_rules! invalid {
+    ($($arg:tt)*) => {
+        anyhow::Error::from($crate::error::InvalidArgument(format!($($arg)*)))
+    };
+}
+pub(crate) use invalid;
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(fvm_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(catch(|| Ok(())), FvmStatus::Ok);
+        assert_eq!(
+            catch(|| Err(invalid!("bad {}", 1))),
+            FvmStatus::InvalidArgument
+        );
+        assert_eq!(last_error(), "invalid argument: bad 1");
+        assert_eq!(catch(|| Err(anyhow::anyhow!("failed"))), FvmStatus::Error);
+        assert_eq!(last_error(), "failed");
+        assert_eq!(catch(|| panic!("boom")), FvmStatus::Panic);
+        assert_eq!(last_error(), "panic: boom");
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! The blockstore and externs of a machine, implemented by embedder callbacks.
+
+use std::ops::Deref;
+use std::os::raw::c_void;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm::externs::{Chain, Consensus, Externs, Rand, StateReader};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::consensus::{ConsensusFault, ConsensusFaultType};
+
+use crate::bytes::FvmBuffer;
+
+/// Returned by callbacks on success.
+pub const FVM_CALLBACK_OK: i32 = 0;
+/// Returned by `blockstore_get` when the block isn't found. Any other value is an error.
+pub const FVM_CALLBACK_NOT_FOUND: i32 = 1;
+
+/// Copies the block with the given CID into `out`, or returns [`FVM_CALLBACK_NOT_FOUND`].
+pub type GetBlock =
+    extern "C" fn(ctx: *mut c_void, cid: *const u8, cid_len: usize, out: *mut FvmBuffer) -> i32;
+/// Stores a block under the given CID.
+pub type PutBlock = extern "C" fn(
+    ctx: *mut c_void,
+    cid: *const u8,
+    cid_len: usize,
+    data: *const u8,
+    data_len: usize,
+) -> i32;
+/// Writes 32 bytes of randomness to `out`.
+pub type GetRandomness = extern "C" fn(
+    ctx: *mut c_void,
+    pers: i64,
+    round: i64,
+    entropy: *const u8,
+    entropy_len: usize,
+    out: *mut u8,
+) -> i32;
+/// Verifies a consensus fault, writing its details to `out`.
+pub type VerifyConsensusFault = extern "C" fn(
+    ctx: *mut c_void,
+    h1: *const u8,
+    h1_len: usize,
+    h2: *const u8,
+    h2_len: usize,
+    extra: *const u8,
+    extra_len: usize,
+    out: *mut FvmConsensusFault,
+) -> i32;
+/// Copies the binary CID of the tipset at the given epoch into `out`.
+pub type GetTipsetCid = extern "C" fn(ctx: *mut c_void, epoch: i64, out: *mut FvmBuffer) -> i32;
+
+/// The callbacks through which a machine accesses the embedder's blockstore and chain. Every
+/// callback receives `ctx` as its first argument, and returns [`FVM_CALLBACK_OK`] on success.
+///
+/// Null callbacks are reported as errors when the machine needs them.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FvmCallbacks {
+    /// The embedder's context, passed to every callback.
+    pub ctx: *mut c_void,
+    /// Copies the block with the given CID into `out`, or returns [`FVM_CALLBACK_NOT_FOUND`].
+    pub blockstore_get: Option<GetBlock>,
+    /// Stores a block under the given CID.
+    pub blockstore_put: Option<PutBlock>,
+    /// Writes 32 bytes of chain randomness to `out`.
+    pub get_chain_randomness: Option<GetRandomness>,
+    /// Writes 32 bytes of beacon randomness to `out`.
+    pub get_beacon_randomness: Option<GetRandomness>,
+    /// Verifies a consensus fault, writing its details to `out`.
+    pub verify_consensus_fault: Option<VerifyConsensusFault>,
+    /// Copies the CID of the tipset at the given epoch into `out`.
+    pub get_tipset_cid: Option<GetTipsetCid>,
+    /// Called once the machine no longer uses `ctx`. May be null.
+    pub drop: Option<extern "C" fn(ctx: *mut c_void)>,
+}
+
+/// The result of verifying a consensus fault.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FvmConsensusFault {
+    /// 0 if there's no fault, otherwise the fault type (1: double-fork mining, 2: parent grinding,
+    /// 3: time-offset mining).
+    pub fault_type: u8,
+    /// The ID of the miner at fault.
+    pub target: u64,
+    /// The epoch of the fault.
+    pub epoch: i64,
+    /// The gas used by the verification.
+    pub gas_used: i64,
+}
+
+/// The callbacks, shared by the blockstore and the externs of a machine.
+#[derive(Debug)]
+struct Callbacks(FvmCallbacks);
+
+impl Deref for Callbacks {
+    type Target = FvmCallbacks;
+
+    fn deref(&self) -> &FvmCallbacks {
+        &self.0
+    }
+}
+
+impl Drop for Callbacks {
+    fn drop(&mut self) {
+        if let Some(f) = self.0.drop {
+            f(self.0.ctx)
+        }
+    }
+}
+
+fn callback<F>(cb: Option<F>, name: &str) -> anyhow::Result<F> {
+    cb.ok_or_else(|| anyhow!("the {} callback isn't set", name))
+}
+
+fn check(status: i32, name: &str) -> anyhow::Result<()> {
+    match status {
+        FVM_CALLBACK_OK => Ok(()),
+        status => Err(anyhow!(
+            "the {} callback failed with status {}",
+            name,
+            status
+        )),
+    }
+}
+
+/// Creates the blockstore and externs of a machine from the embedder's callbacks.
+pub(crate) fn new(callbacks: FvmCallbacks) -> (FfiBlockstore, FfiExterns) {
+    let callbacks = Rc::new(Callbacks(callbacks));
+    (FfiBlockstore(callbacks.clone()), FfiExterns(callbacks))
+}
+
+/// A blockstore implemented by the embedder's callbacks.
+#[derive(Debug, Clone)]
+pub struct FfiBlockstore(Rc<Callbacks>);
+
+impl Blockstore for FfiBlockstore {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        let cb = callback(self.0.blockstore_get, "blockstore_get")?;
+        let key = k.to_bytes();
+        let mut out = FvmBuffer::default();
+        match cb(self.0.ctx, key.as_ptr(), key.len(), &mut out) {
+            FVM_CALLBACK_NOT_FOUND => Ok(None),
+            status => check(status, "blockstore_get").map(|_| Some(out.0)),
+        }
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        let cb = callback(self.0.blockstore_put, "blockstore_put")?;
+        let key = k.to_bytes();
+        check(
+            cb(
+                self.0.ctx,
+                key.as_ptr(),
+                key.len(),
+                block.as_ptr(),
+                block.len(),
+            ),
+            "blockstore_put",
+        )
+    }
+}
+
+/// Externs implemented by the embedder's callbacks.
+#[derive(Debug)]
+pub struct FfiExterns(Rc<Callbacks>);
+
+impl FfiExterns {
+    fn randomness(
+        &self,
+        cb: Option<GetRandomness>,
+        name: &str,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        let cb = callback(cb, name)?;
+        let mut out = [0u8; 32];
+        check(
+            cb(
+                self.0.ctx,
+                pers,
+                round,
+                entropy.as_ptr(),
+                entropy.len(),
+                out.as_mut_ptr(),
+            ),
+            name,
+        )?;
+        Ok(out)
+    }
+}
+
+impl Rand for FfiExterns {
+    fn get_chain_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        let cb = self.0.get_chain_randomness;
+        self.randomness(cb, "get_chain_randomness", pers, round, entropy)
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        let cb = self.0.get_beacon_randomness;
+        self.randomness(cb, "get_beacon_randomness", pers, round, entropy)
+    }
+}
+
+impl Consensus for FfiExterns {
+    fn verify_consensus_fault(
+        &self,
+        h1: &[u8],
+        h2: &[u8],
+        extra: &[u8],
+    ) -> anyhow::Result<(Option<ConsensusFault>, i64)> {
+        let cb = callback(self.0.verify_consensus_fault, "verify_consensus_fault")?;
+        let mut out = FvmConsensusFault::default();
+        check(
+            cb(
+                self.0.ctx,
+                h1.as_ptr(),
+                h1.len(),
+                h2.as_ptr(),
+                h2.len(),
+                extra.as_ptr(),
+                extra.len(),
+                &mut out,
+            ),
+            "verify_consensus_fault",
+        )?;
+        let fault_type = match out.fault_type {
+            0 => return Ok((None, out.gas_used)),
+            1 => ConsensusFaultType::DoubleForkMining,
+            2 => ConsensusFaultType::ParentGrinding,
+            3 => ConsensusFaultType::TimeOffsetMining,
+            t => return Err(anyhow!("invalid consensus fault type {}", t)),
+        };
+        let fault = ConsensusFault {
+            target: Address::new_id(out.target),
+            epoch: out.epoch,
+            fault_type,
+        };
+        Ok((Some(fault), out.gas_used))
+    }
+}
+
+impl Chain for FfiExterns {
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid> {
+        let cb = callback(self.0.get_tipset_cid, "get_tipset_cid")?;
+        let mut out = FvmBuffer::default();
+        check(cb(self.0.ctx, epoch, &mut out), "get_tipset_cid")?;
+        Cid::try_from(&out.0[..]).context("get_tipset_cid returned an invalid CID")
+    }
+}
+
+impl StateReader for FfiExterns {}
+
+impl Externs for FfiExterns {}
@@ -0,0 +1,47 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! A C-compatible interface to the reference FVM, for embedding it in node implementations written
+//! in other languages (e.g., Go via cgo, or C++). The C declarations are generated from this crate
+//! with cbindgen into `include/fvm.h` when the crate is built.
+//!
+//! The interface is versioned by [`FVM_FFI_ABI_VERSION`], which only changes when a declaration in
+//! the header (or the encoding of the data returned) changes incompatibly. Embedders should check [`fvm_ffi_abi_version`] at startup.
+//!
+//! # Ownership
+//!
+//! - Machines are created with [`fvm_machine_new`](machine::fvm_machine_new) and must be freed with
+//!   [`fvm_machine_free`](machine::fvm_machine_free), exactly once.
+//! - Byte strings returned by the FVM ([`FvmBytes`](bytes::FvmBytes)) are owned by the embedder,
+//!   and must be freed with [`fvm_bytes_free`](bytes::fvm_bytes_free) (or by freeing the structure
+//!   holding them, e.g. with [`fvm_apply_ret_free`](machine::fvm_apply_ret_free)).
+//! - Pointers passed to the FVM are borrowed for the duration of the call only.
+//! - Callbacks return data by copying it into an [`FvmBuffer`](bytes::FvmBuffer) owned by the FVM,
+//!   with [`fvm_buffer_write`](bytes::fvm_buffer_write). The buffer is only valid during the
+//!   callback.
+//! - The callbacks' context pointer stays owned by the embedder. The FVM calls the `drop` callback
+//!   (if any) once it no longer uses the context.
+//!
+//! # Errors
+//!
+//! Fallible functions return an [`FvmStatus`](error::FvmStatus). On failure, a description of the
+//! error can be retrieved with [`fvm_last_error`](error::fvm_last_error).
+//!
+//! # Threads
+//!
+//! A machine may be moved between threads, but must only be used by one thread at a time.
+//! Callbacks are invoked on the thread calling into the FVM.
+
+pub mod bytes;
+pub mod error;
+pub mod externs;
+pub mod machine;
+pub mod trace;
+
+/// The version of the C interface declared in `include/fvm.h`.
+pub const FVM_FFI_ABI_VERSION: u32 = 1;
+
+/// Returns the version of the C interface implemented by this library, [`FVM_FFI_ABI_VERSION`].
+#[no_mangle]
+pub extern "C" fn fvm_ffi_abi_version() -> u32 {
+    FVM_FFI_ABI_VERSION
+}
@@ -0,0 +1,333 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Machine construction and message application.
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm::call_manager::DefaultCallManager;
+use fvm::engine::MultiEngine;
+use fvm::executor::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
+use fvm::machine::{DefaultMachine, MachineBuilder};
+use fvm::DefaultKernel;
+use fvm_ipld_encoding::{from_slice, to_vec};
+use fvm_shared::bigint::{BigInt, Sign};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::version::NetworkVersion;
+use once_cell::sync::OnceCell;
+use serde_tuple::Serialize_tuple;
+
+use crate::bytes::{borrow, FvmBytes};
+use crate::error::{catch, invalid, FvmStatus};
+use crate::externs::{self, FfiBlockstore, FfiExterns, FvmCallbacks};
+use crate::trace;
+
+type FfiExecutor =
+    DefaultExecutor<DefaultKernel<DefaultCallManager<DefaultMachine<FfiBlockstore, FfiExterns>>>>;
+
+/// The engines shared by all machines, created on first use.
+static ENGINES: OnceCell<MultiEngine> = OnceCell::new();
+
+/// Apply an explicit (on-chain) message. See [`ApplyKind::Explicit`].
+pub const FVM_APPLY_EXPLICIT: u32 = 0;
+/// Apply an implicit (system) message. See [`ApplyKind::Implicit`].
+pub const FVM_APPLY_IMPLICIT: u32 = 1;
+
+/// Sets the number of messages that may be executed concurrently (by different machines) in the
+/// process. Must be called before the first machine is created; defaults to 1.
+#[no_mangle]
+pub extern "C" fn fvm_ffi_init(concurrency: u32) -> FvmStatus {
+    catch(|| {
+        if concurrency == 0 {
+            return Err(invalid!("the concurrency must be positive"));
+        }
+        ENGINES
+            .set(MultiEngine::new(concurrency))
+            .map_err(|_| anyhow!("the engines have already been initialized"))
+    })
+}
+
+/// The configuration of a machine. Byte strings are borrowed for the duration of
+/// [`fvm_machine_new`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FvmMachineConfig {
+    pub network_version: u32,
+    /// The initial state root CID, in binary form.
+    pub state_root: *const u8,
+    pub state_root_len: usize,
+    pub epoch: i64,
+    pub timestamp: u64,
+    /// The base fee, as a Filecoin big integer (a sign byte followed by the big-endian magnitude,
+    /// or no bytes for zero).
+    pub base_fee: *const u8,
+    pub base_fee_len: usize,
+    /// The circulating supply, as a Filecoin big integer.
+    pub circulating_supply: *const u8,
+    pub circulating_supply_len: usize,
+    pub chain_id: u64,
+    /// The CID of the built-in actors manifest to use instead of the network's, or empty.
+    pub actors_manifest: *const u8,
+    pub actors_manifest_len: usize,
+    /// Record execution traces, returned in [`FvmApplyRet::trace`].
+    pub tracing: bool,
+}
+
+/// A machine executing messages on top of a state tree, along with its executor.
+pub struct FvmMachine {
+    executor: FfiExecutor,
+}
+
+/// The result of applying a message. Byte strings are owned by the embedder, and freed by
+/// [`fvm_apply_ret_free`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct FvmApplyRet {
+    pub exit_code: u32,
+    pub gas_used: i64,
+    /// The message receipt, DAG-CBOR encoded.
+    pub receipt: FvmBytes,
+    /// The gas outputs, DAG-CBOR encoded as `[base_fee_burn, over_estimation_burn, miner_penalty,
+    /// miner_tip, refund, gas_refund, gas_burned, subsidy]`, where `subsidy` is the part of the
+    /// gas cost covered by a gas subsidy.
+    pub gas_outputs: FvmBytes,
+    /// The events emitted by the message, DAG-CBOR encoded as an array of stamped events.
+    pub events: FvmBytes,
+    /// A description of the message's failure (UTF-8), or empty.
+    pub failure_info: FvmBytes,
+    /// The execution trace, DAG-CBOR encoded (see [`trace`](crate::trace)), or empty if tracing
+    /// is disabled.
+    pub trace: FvmBytes,
+}
+
+#[derive(Serialize_tuple)]
+struct GasOutputs {
+    base_fee_burn: TokenAmount,
+    over_estimation_burn: TokenAmount,
+    miner_penalty: TokenAmount,
+    miner_tip: TokenAmount,
+    refund: TokenAmount,
+    gas_refund: i64,
+    gas_burned: i64,
+    subsidy: TokenAmount,
+}
+
+impl FvmApplyRet {
+    fn new(ret: ApplyRet) -> anyhow::Result<Self> {
+        let gas_outputs = GasOutputs {
+            base_fee_burn: ret.base_fee_burn,
+            over_estimation_burn: ret.over_estimation_burn,
+            miner_penalty: ret.penalty,
+            miner_tip: ret.miner_tip,
+            refund: ret.refund,
+            gas_refund: ret.gas_refund,
+            gas_burned: ret.gas_burned,
+            subsidy: ret.subsidy,
+        };
+        let trace = match trace::build(&ret.exec_trace) {
+            Some(trace) => to_vec(&trace)?,
+            None => Vec::new(),
+        };
+        let failure_info = ret
+            .failure_info
+            .map(|f| f.to_string().into_bytes())
+            .unwrap_or_default();
+        Ok(FvmApplyRet {
+            exit_code: ret.msg_receipt.exit_code.value(),
+            gas_used: ret.msg_receipt.gas_used,
+            receipt: FvmBytes::new(to_vec(&ret.msg_receipt)?),
+            gas_outputs: FvmBytes::new(to_vec(&gas_outputs)?),
+            events: FvmBytes::new(to_vec(&ret.events)?),
+            failure_info: FvmBytes::new(failure_info),
+            trace: FvmBytes::new(trace),
+        })
+    }
+}
+
+fn parse_cid(data: *const u8, len: usize, what: &str) -> anyhow::Result<Cid> {
+    Cid::try_from(unsafe { borrow(data, len) }).map_err(|e| invalid!("invalid {}: {}", what, e))
+}
+
+fn parse_token_amount(data: *const u8, len: usize, what: &str) -> anyhow::Result<TokenAmount> {
+    let bytes = unsafe { borrow(data, len) };
+    let sign = match bytes.first() {
+        None => return Ok(TokenAmount::default()),
+        Some(0) => Sign::Plus,
+        Some(1) => Sign::Minus,
+        Some(b) => return Err(invalid!("invalid {}: bad sign byte {}", what, b)),
+    };
+    Ok(TokenAmount::from_atto(BigInt::from_bytes_be(
+        sign,
+        &bytes[1..],
+    )))
+}
+
+/// Creates a machine, writing it to `out`. The machine must be freed with [`fvm_machine_free`].
+///
+/// The machine takes ownership of the callbacks' context: their `drop` callback is called once the
+/// machine is freed, or before returning if the machine can't be created.
+///
+/// # Safety
+///
+/// `config` must point to a valid configuration, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fvm_machine_new(
+    config: *const FvmMachineConfig,
+    callbacks: FvmCallbacks,
+    out: *mut *mut FvmMachine,
+) -> FvmStatus {
+    catch(|| {
+        let (blockstore, externs) = externs::new(callbacks);
+        let config = config.as_ref().ok_or_else(|| invalid!("null config"))?;
+        if out.is_null() {
+            return Err(invalid!("null output"));
+        }
+
+        let nv = NetworkVersion::try_from(config.network_version)
+            .map_err(|v| invalid!("unknown network version {}", v))?;
+        let state_root = parse_cid(config.state_root, config.state_root_len, "state root")?;
+        let mut builder = MachineBuilder::new(nv, state_root).map_err(|e| invalid!("{}", e))?;
+        builder
+            .epoch(config.epoch)
+            .timestamp(config.timestamp)
+            .base_fee(parse_token_amount(
+                config.base_fee,
+                config.base_fee_len,
+                "base fee",
+            )?)
+            .circulating_supply(parse_token_amount(
+                config.circulating_supply,
+                config.circulating_supply_len,
+                "circulating supply",
+            )?)
+            .chain_id(config.chain_id.into());
+        if config.actors_manifest_len > 0 {
+            builder.override_actors(parse_cid(
+                config.actors_manifest,
+                config.actors_manifest_len,
+                "actors manifest",
+            )?);
+        }
+        if config.tracing {
+            builder.configure_context(|mc| {
+                mc.enable_tracing();
+            });
+        }
+
+        let engines = ENGINES.get_or_init(|| MultiEngine::new(1));
+        let engine = engines.get(&builder.context().network)?;
+        let machine = builder
+            .build(blockstore, externs)
+            .context("failed to build the machine")?;
+        let executor = FfiExecutor::new(engine, machine)?;
+        out.write(Box::into_raw(Box::new(FvmMachine { executor })));
+        Ok(())
+    })
+}
+
+/// Frees a machine, discarding any state changes that haven't been flushed. Freeing a null
+/// machine is a no-op.
+///
+/// # Safety
+///
+/// `machine` must have been created by [`fvm_machine_new`], and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fvm_machine_free(machine: *mut FvmMachine) {
+    if !machine.is_null() {
+        drop(Box::from_raw(machine))
+    }
+}
+
+/// Applies a DAG-CBOR encoded message, writing the result to `out`. The result must be freed with
+/// [`fvm_apply_ret_free`].
+///
+/// `kind` is [`FVM_APPLY_EXPLICIT`] or [`FVM_APPLY_IMPLICIT`], and `raw_length` is the length of
+/// the message as it appears on-chain (used to charge inclusion gas).
+///
+/// # Safety
+///
+/// `machine` must be a live machine, `msg` must point to `msg_len` readable bytes, and `out` must
+/// be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fvm_machine_apply_message(
+    machine: *mut FvmMachine,
+    msg: *const u8,
+    msg_len: usize,
+    kind: u32,
+    raw_length: usize,
+    out: *mut FvmApplyRet,
+) -> FvmStatus {
+    catch(|| {
+        let machine = machine.as_mut().ok_or_else(|| invalid!("null machine"))?;
+        if out.is_null() {
+            return Err(invalid!("null output"));
+        }
+        let msg: Message =
+            from_slice(borrow(msg, msg_len)).map_err(|e| invalid!("invalid message: {}", e))?;
+        let kind = match kind {
+            FVM_APPLY_EXPLICIT => ApplyKind::Explicit,
+            FVM_APPLY_IMPLICIT => ApplyKind::Implicit,
+            k => return Err(invalid!("unknown apply kind {}", k)),
+        };
+
+        let ret = machine.executor.execute_message(msg, kind, raw_length)?;
+        out.write(FvmApplyRet::new(ret)?);
+        Ok(())
+    })
+}
+
+/// Flushes the machine's state tree, writing the new state root CID (in binary form) to `out`. The
+/// CID must be freed with [`fvm_bytes_free`](crate::bytes::fvm_bytes_free).
+///
+/// # Safety
+///
+/// `machine` must be a live machine, and `out` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn fvm_machine_flush(
+    machine: *mut FvmMachine,
+    out: *mut FvmBytes,
+) -> FvmStatus {
+    catch(|| {
+        let machine = machine.as_mut().ok_or_else(|| invalid!("null machine"))?;
+        if out.is_null() {
+            return Err(invalid!("null output"));
+        }
+        let root = machine.executor.flush()?;
+        out.write(FvmBytes::new(root.to_bytes()));
+        Ok(())
+    })
+}
+
+/// Frees the byte strings of an apply result, leaving them empty.
+///
+/// # Safety
+///
+/// `ret` must have been written by [`fvm_machine_apply_message`], and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn fvm_apply_ret_free(ret: *mut FvmApplyRet) {
+    if let Some(ret) = ret.as_mut() {
+        for bytes in [
+            &mut ret.receipt,
+            &mut ret.gas_outputs,
+            &mut ret.events,
+            &mut ret.failure_info,
+            &mut ret.trace,
+        ] {
+            std::mem::replace(bytes, FvmBytes::empty()).free();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_amounts() {
+        let parse = |bytes: &[u8]| parse_token_amount(bytes.as_ptr(), bytes.len(), "amount");
+        assert_eq!(parse(&[]).unwrap(), TokenAmount::default());
+        assert_eq!(parse(&[0, 1, 0]).unwrap(), TokenAmount::from_atto(256));
+        assert_eq!(parse(&[1, 2]).unwrap(), TokenAmount::from_atto(-2));
+        assert!(parse(&[2, 1]).is_err());
+    }
+}
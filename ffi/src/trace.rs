@@ -0,0 +1,213 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Execution traces, as returned across the C interface.
+//!
+//! Traces are returned as a DAG-CBOR encoded call tree, with every structure encoded as a tuple
+//! (i.e., a CBOR array) in field order:
+//!
+//! ```text
+//! Trace         = [msg: TraceMessage, ret: TraceReturn, gas_charges: [TraceGasCharge], subcalls: [Trace]]
+//! TraceMessage  = [from: Address, to: Address, value: TokenAmount, method: uint, params: bytes]
+//! TraceReturn   = [exit_code: uint, return_data: bytes, error: string]
+//! TraceGasCharge = [name: string, total_gas: int, compute_gas: int, other_gas: int]
+//! ```
+//!
+//! Gas amounts are in whole gas units, rounded up. Calls that failed with a syscall error (rather
+//! than returning an exit code) have the exit code `SYS_ASSERTION_FAILED`, and a non-empty `error`.
+//! So do calls left open by a truncated trace.
+
+use std::borrow::Cow;
+
+use fvm::gas::GasCharge;
+use fvm::trace::ExecutionEvent;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::MethodNum;
+use serde_tuple::Serialize_tuple;
+
+/// A call, its gas charges (excluding those of its sub-calls), and its sub-calls.
+#[derive(Serialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub msg: TraceMessage,
+    pub ret: TraceReturn,
+    pub gas_charges: Vec<TraceGasCharge>,
+    pub subcalls: Vec<Trace>,
+}
+
+#[derive(Serialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct TraceMessage {
+    pub from: Address,
+    pub to: Address,
+    pub value: TokenAmount,
+    pub method: MethodNum,
+    pub params: RawBytes,
+}
+
+#[derive(Serialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct TraceReturn {
+    pub exit_code: ExitCode,
+    pub return_data: RawBytes,
+    pub error: String,
+}
+
+#[derive(Serialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct TraceGasCharge {
+    pub name: Cow<'static, str>,
+    pub total_gas: i64,
+    pub compute_gas: i64,
+    pub other_gas: i64,
+}
+
+impl From<&GasCharge> for TraceGasCharge {
+    fn from(charge: &GasCharge) -> Self {
+        TraceGasCharge {
+            name: charge.name.clone(),
+            total_gas: charge.total().round_up(),
+            compute_gas: charge.compute_gas.round_up(),
+            other_gas: charge.other_gas.round_up(),
+        }
+    }
+}
+
+/// Builds the call tree of an execution trace, returning the message's call (if any).
+pub fn build(trace: &[ExecutionEvent]) -> Option<Trace> {
+    let mut stack: Vec<Trace> = Vec::new();
+    let mut root = None;
+
+    let mut close = |stack: &mut Vec<Trace>, ret: TraceReturn| {
+        if let Some(mut call) = stack.pop() {
+            call.ret = ret;
+            match stack.last_mut() {
+                Some(parent) => parent.subcalls.push(call),
+                None => root = Some(call),
+            }
+        }
+    };
+
+    for event in trace {
+        match event {
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                params,
+                value,
+            } => stack.push(Trace {
+                msg: TraceMessage {
+                    from: Address::new_id(*from),
+                    to: *to,
+                    value: value.clone(),
+                    method: *method,
+                    params: params.clone(),
+                },
+                ret: TraceReturn {
+                    exit_code: ExitCode::OK,
+                    return_data: RawBytes::default(),
+                    error: String::new(),
+                },
+                gas_charges: Vec::new(),
+                subcalls: Vec::new(),
+            }),
+            ExecutionEvent::GasCharge(charge) => {
+                if let Some(call) = stack.last_mut() {
+                    call.gas_charges.push(charge.into());
+                }
+            }
+            ExecutionEvent::CallReturn(exit_code, data) => close(
+                &mut stack,
+                TraceReturn {
+                    exit_code: *exit_code,
+                    return_data: data.clone(),
+                    error: String::new(),
+                },
+            ),
+            ExecutionEvent::CallError(err) => close(
+                &mut stack,
+                TraceReturn {
+                    exit_code: ExitCode::SYS_ASSERTION_FAILED,
+                    return_data: RawBytes::default(),
+                    error: err.to_string(),
+                },
+            ),
+            _ => {}
+        }
+    }
+
+    // Close any calls left open by a truncated trace.
+    while !stack.is_empty() {
+        close(
+            &mut stack,
+            TraceReturn {
+                exit_code: ExitCode::SYS_ASSERTION_FAILED,
+                return_data: RawBytes::default(),
+                error: "incomplete trace".into(),
+            },
+        );
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm::gas::Gas;
+    use fvm::kernel::SyscallError;
+    use fvm_shared::error::ErrorNumber;
+
+    use super::*;
+
+    fn call(from: u64, to: u64) -> ExecutionEvent {
+        ExecutionEvent::Call {
+            from,
+            to: Address::new_id(to),
+            method: 2,
+            params: RawBytes::default(),
+            value: TokenAmount::from_atto(1),
+        }
+    }
+
+    #[test]
+    fn builds_call_tree() {
+        let trace = vec![
+            ExecutionEvent::GasCharge(GasCharge::new("OnChainMessage", Gas::new(1), Gas::new(0))),
+            call(100, 101),
+            ExecutionEvent::GasCharge(GasCharge::new(
+                "OnMethodInvocation",
+                Gas::new(2),
+                Gas::new(3),
+            )),
+            call(101, 102),
+            ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "no actor")),
+            call(101, 103),
+            ExecutionEvent::CallReturn(ExitCode::USR_FORBIDDEN, RawBytes::new(vec![1])),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+        ];
+        let root = build(&trace).unwrap();
+
+        assert_eq!(root.msg.to, Address::new_id(101));
+        assert_eq!(root.ret.exit_code, ExitCode::OK);
+        assert_eq!(
+            root.gas_charges,
+            vec![TraceGasCharge {
+                name: "OnMethodInvocation".into(),
+                total_gas: 5,
+                compute_gas: 2,
+                other_gas: 3,
+            }]
+        );
+        assert_eq!(root.subcalls.len(), 2);
+        assert_eq!(
+            root.subcalls[0].ret.error,
+            "syscall error: no actor (exit_code=NotFound)"
+        );
+        assert_eq!(root.subcalls[1].ret.exit_code, ExitCode::USR_FORBIDDEN);
+        assert_eq!(root.subcalls[1].ret.return_data, RawBytes::new(vec![1]));
+
+        // Truncated traces are closed.
+        let root = build(&trace[..4]).unwrap();
+        assert_eq!(root.ret.error, "incomplete trace");
+        assert_eq!(root.subcalls[0].ret.error, "incomplete trace");
+        assert!(build(&[]).is_none());
+    }
+}
@@ -0,0 +1,503 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! An append-only audit log of the syscalls made by actors, for post-incident forensics.
+//!
+//! When an [`AuditLog`] is installed in the [`MachineContext`](crate::machine::MachineContext)
+//! (see [`MachineContext::enable_audit_log`](crate::machine::MachineContext::enable_audit_log)),
+//! every syscall that returns to the actor (successfully or with an error) is appended to the log
+//! as an [`AuditEntry`], recording the message and call frame that made it, digests of its
+//! arguments and its result, and the gas it charged.
+//!
+//! Entries are hash-chained: each entry's [`hash`](AuditEntry::hash) commits to the previous
+//! entry's hash and to the entry's own sequence number and contents. Any removed, reordered, or
+//! altered entry breaks the chain, see [`verify`].
+//!
+//! The log can also record each syscall's full [`SyscallOutcome`] (its return value and the bytes
+//! it wrote into the actor's memory), making the log a "syscall tape" from which an actor's host
+//! interactions can be replayed without the state tree.
+//!
+//! Syscalls that abort the invocation (e.g., `vm::exit`) aren't recorded. Auditing copies the
+//! output buffers of each syscall to find the bytes written by the syscall, so it slows down
+//! execution and should only be enabled when needed. It never affects execution results.
+
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context as _};
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{strict_bytes, to_vec};
+use fvm_shared::ActorID;
+
+use crate::gas::Gas;
+
+/// The length of the digests in an [`AuditEntry`].
+pub const DIGEST_LEN: usize = 32;
+
+/// A digest in an [`AuditEntry`]: a 256-bit blake2b hash.
+pub type Digest = [u8; DIGEST_LEN];
+
+/// A single syscall recorded in the [`AuditLog`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct AuditEntry {
+    /// The position of this entry in the log, starting at 0.
+    pub seq: u64,
+    /// The number of messages applied before the one that made this syscall.
+    pub message: u64,
+    /// The call frame that made this syscall, numbered from 1 (the first actor invoked by the
+    /// message) in invocation order.
+    pub frame: u64,
+    /// The actor that made this syscall.
+    pub actor: ActorID,
+    /// The syscall's module.
+    pub module: String,
+    /// The syscall's name.
+    pub name: String,
+    /// A digest of the syscall's arguments, as passed by the actor.
+    pub args_digest: Digest,
+    /// A digest of the syscall's [`SyscallOutcome`].
+    pub result_digest: Digest,
+    /// The gas charged by the syscall, excluding the flat syscall charge, in milligas.
+    pub gas: i64,
+    /// The syscall's outcome, if the log records outcomes.
+    pub outcome: Option<SyscallOutcome>,
+    /// The hash chaining this entry to the previous one, see [`AuditEntry::chain_hash`].
+    pub hash: Digest,
+}
+
+/// The result of a syscall, as observed by the actor.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SyscallOutcome {
+    /// The syscall's error number, or 0 on success.
+    pub error: u32,
+    /// The syscall's return value, in its in-memory representation. Empty on error or if the
+    /// syscall returns nothing.
+    #[serde(with = "strict_bytes")]
+    pub value: Vec<u8>,
+    /// The bytes the syscall wrote into the actor's memory, in ascending order of offset.
+    pub writes: Vec<MemoryWrite>,
+}
+
+/// A contiguous range of bytes written into an actor's memory by a syscall.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct MemoryWrite {
+    pub offset: u32,
+    #[serde(with = "strict_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl SyscallOutcome {
+    /// Builds the outcome of a syscall, given the actor's memory before and after the syscall.
+    pub fn new(error: u32, value: &[u8], before: &[u8], after: &[u8]) -> Self {
+        let mut writes = Vec::new();
+        diff(&mut writes, 0, before, after);
+        SyscallOutcome {
+            error,
+            value: value.to_vec(),
+            writes,
+        }
+    }
+
+    /// Builds the outcome of a syscall, given the ranges of the actor's memory the syscall could
+    /// write to, each with its offset and its contents when the syscall borrowed it, and the
+    /// actor's memory after the syscall. Where ranges overlap, the first range borrowed holds the
+    /// contents before the syscall.
+    pub fn from_ranges(error: u32, value: &[u8], ranges: &[(u32, Vec<u8>)], after: &[u8]) -> Self {
+        // The disjoint parts of the ranges, with their contents before the syscall.
+        let mut before: Vec<(usize, &[u8])> = Vec::new();
+        for (offset, data) in ranges {
+            let (start, end) = (*offset as usize, *offset as usize + data.len());
+            let mut pos = start;
+            let mut parts = Vec::new();
+            for &(s, d) in &before {
+                if s + d.len() <= pos || s >= end {
+                    continue;
+                }
+                if pos < s {
+                    parts.push((pos, &data[pos - start..s - start]));
+                }
+                pos = pos.max(s + d.len());
+            }
+            if pos < end {
+                parts.push((pos, &data[pos - start..]));
+            }
+            before.extend(parts);
+            before.sort_unstable_by_key(|&(s, _)| s);
+        }
+
+        let mut writes: Vec<MemoryWrite> = Vec::new();
+        for (start, data) in before {
+            let after = after.get(start..).unwrap_or_default();
+            diff(&mut writes, start, data, after);
+        }
+        SyscallOutcome {
+            error,
+            value: value.to_vec(),
+            writes,
+        }
+    }
+
+    /// Returns a digest of the outcome.
+    pub fn digest(&self) -> anyhow::Result<Digest> {
+        Ok(hash(&[&to_vec(self)?]))
+    }
+}
+
+impl AuditEntry {
+    /// Computes the hash chaining this entry to the previous entry (whose hash is `prev`, or all
+    /// zeros for the first entry). This covers every field except the recorded outcome, which is
+    /// covered by the result digest.
+    pub fn chain_hash(&self, prev: &Digest) -> anyhow::Result<Digest> {
+        let fields = to_vec(&(
+            self.seq,
+            self.message,
+            self.frame,
+            self.actor,
+            &self.module,
+            &self.name,
+            &self.args_digest,
+            &self.result_digest,
+            self.gas,
+        ))?;
+        Ok(hash(&[prev, &fields]))
+    }
+}
+
+/// Verifies the hash chain and sequence numbers of a complete audit log (starting at sequence
+/// number 0), along with the digests of any recorded outcomes.
+pub fn verify(entries: &[AuditEntry]) -> anyhow::Result<()> {
+    let mut prev = [0u8; DIGEST_LEN];
+    for (seq, entry) in entries.iter().enumerate() {
+        if entry.seq != seq as u64 {
+            return Err(anyhow!(
+                "expected audit entry {}, found entry {}",
+                seq,
+                entry.seq
+            ));
+        }
+        if let Some(outcome) = &entry.outcome {
+            if outcome.digest()? != entry.result_digest {
+                return Err(anyhow!("outcome of audit entry {} doesn't match", seq));
+            }
+        }
+        if entry.chain_hash(&prev)? != entry.hash {
+            return Err(anyhow!("audit entry {} doesn't match the hash chain", seq));
+        }
+        prev = entry.hash;
+    }
+    Ok(())
+}
+
+/// Reads the entries written to an [`AuditLog`].
+pub fn read_entries(mut r: impl Read) -> anyhow::Result<Vec<AuditEntry>> {
+    let mut entries = Vec::new();
+    loop {
+        let mut len = [0u8; 4];
+        match r.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(entries),
+            Err(e) => return Err(e.into()),
+        }
+        let mut buf = vec![0u8; u32::from_be_bytes(len) as usize];
+        r.read_exact(&mut buf)
+            .context("truncated audit log entry")?;
+        entries.push(fvm_ipld_encoding::from_slice(&buf)?);
+    }
+}
+
+/// An append-only log of syscalls. Entries are written as a big-endian `u32` length followed by
+/// the DAG-CBOR encoded [`AuditEntry`], see [`read_entries`].
+pub struct AuditLog {
+    record_outcomes: bool,
+    state: Mutex<AuditState>,
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("record_outcomes", &self.record_outcomes)
+            .finish_non_exhaustive()
+    }
+}
+
+struct AuditState {
+    writer: Box<dyn Write + Send>,
+    next_seq: u64,
+    prev: Digest,
+    /// Whether a message has been started.
+    started: bool,
+    messages: u64,
+    /// The active call frames, as (frame, actor) pairs.
+    frames: Vec<(u64, ActorID)>,
+}
+
+impl AuditLog {
+    /// Creates an audit log writing to the given writer.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        AuditLog {
+            record_outcomes: false,
+            state: Mutex::new(AuditState {
+                writer: Box::new(writer),
+                next_seq: 0,
+                prev: [0u8; DIGEST_LEN],
+                started: false,
+                messages: 0,
+                frames: Vec::new(),
+            }),
+        }
+    }
+
+    /// Also record the full outcome of each syscall, so the log can be replayed.
+    pub fn record_outcomes(mut self) -> Self {
+        self.record_outcomes = true;
+        self
+    }
+
+    /// Returns true if the full outcome of each syscall is recorded.
+    pub fn records_outcomes(&self) -> bool {
+        self.record_outcomes
+    }
+
+    /// Marks the start of a new message. Called by the call manager.
+    pub fn begin_message(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.started {
+            state.messages += 1;
+        }
+        state.started = true;
+        state.frames.clear();
+    }
+
+    /// Marks the start of an actor invocation. Called by the call manager.
+    pub fn enter_frame(&self, frame: u64, actor: ActorID) {
+        self.state.lock().unwrap().frames.push((frame, actor));
+    }
+
+    /// Marks the end of the current actor invocation. Called by the call manager.
+    pub fn exit_frame(&self) {
+        self.state.lock().unwrap().frames.pop();
+    }
+
+    /// Appends a syscall made by the current call frame to the log.
+    pub fn append(
+        &self,
+        module: &str,
+        name: &str,
+        args: &[u8],
+        gas: Gas,
+        outcome: SyscallOutcome,
+    ) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let (frame, actor) = state
+            .frames
+            .last()
+            .copied()
+            .context("syscall made outside of a call frame")?;
+        let mut entry = AuditEntry {
+            seq: state.next_seq,
+            message: state.messages,
+            frame,
+            actor,
+            module: module.into(),
+            name: name.into(),
+            args_digest: digest(args),
+            result_digest: outcome.digest()?,
+            gas: gas.as_milligas(),
+            outcome: self.record_outcomes.then_some(outcome),
+            hash: [0u8; DIGEST_LEN],
+        };
+        entry.hash = entry.chain_hash(&state.prev)?;
+
+        let bytes = to_vec(&entry)?;
+        state
+            .writer
+            .write_all(&(bytes.len() as u32).to_be_bytes())
+            .and_then(|_| state.writer.write_all(&bytes))
+            .context("failed to write audit log entry")?;
+
+        state.next_seq += 1;
+        state.prev = entry.hash;
+        Ok(())
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        Ok(self.state.lock().unwrap().writer.flush()?)
+    }
+}
+
+/// Appends the ranges of bytes that differ between `before` and `after`, both starting at `offset`
+/// in the actor's memory, to `writes`. Ranges adjacent to the last write extend it.
+fn diff(writes: &mut Vec<MemoryWrite>, offset: usize, before: &[u8], after: &[u8]) {
+    let mut i = 0;
+    let len = before.len().min(after.len());
+    while i < len {
+        if before[i] == after[i] {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && before[i] != after[i] {
+            i += 1;
+        }
+        match writes.last_mut() {
+            Some(last) if last.offset as usize + last.data.len() == offset + start => {
+                last.data.extend_from_slice(&after[start..i]);
+            }
+            _ => writes.push(MemoryWrite {
+                offset: (offset + start) as u32,
+                data: after[start..i].to_vec(),
+            }),
+        }
+    }
+}
+
+/// Digests the given bytes, as done for syscall arguments.
+pub fn digest(data: &[u8]) -> Digest {
+    hash(&[data])
+}
+
+/// Digests the concatenation of the given byte strings.
+fn hash(data: &[&[u8]]) -> Digest {
+    let mut state = blake2b_simd::Params::new()
+        .hash_length(DIGEST_LEN)
+        .to_state();
+    for d in data {
+        state.update(d);
+    }
+    let mut out = [0u8; DIGEST_LEN];
+    out.copy_from_slice(state.finalize().as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use num_traits::Zero;
+
+    use super::{read_entries, verify, AuditLog, SyscallOutcome};
+    use crate::gas::Gas;
+
+    #[derive(Clone, Default)]
+    struct Buf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn hash_chain() {
+        let buf = Buf::default();
+        let log = AuditLog::new(buf.clone()).record_outcomes();
+        log.begin_message();
+        log.enter_frame(1, 100);
+        log.append(
+            "vm",
+            "message_context",
+            &[],
+            Gas::new(10),
+            SyscallOutcome::new(0, &[1, 2], &[0, 0, 0, 0], &[0, 1, 0, 1]),
+        )
+        .unwrap();
+        log.enter_frame(2, 101);
+        log.append(
+            "ipld",
+            "block_open",
+            &[1, 0, 0, 0],
+            Gas::zero(),
+            Default::default(),
+        )
+        .unwrap();
+        log.exit_frame();
+        log.exit_frame();
+        log.begin_message();
+        log.enter_frame(1, 100);
+        log.append(
+            "vm",
+            "message_context",
+            &[],
+            Gas::new(10),
+            Default::default(),
+        )
+        .unwrap();
+
+        let mut entries = read_entries(&buf.0.lock().unwrap()[..]).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.message, e.frame, e.actor))
+                .collect::<Vec<_>>(),
+            [(0, 1, 100), (0, 2, 101), (1, 1, 100)]
+        );
+        let writes = &entries[0].outcome.as_ref().unwrap().writes;
+        assert_eq!(writes.len(), 2);
+        assert_eq!((writes[1].offset, &writes[1].data[..]), (3, &[1][..]));
+        verify(&entries).unwrap();
+
+        // Tampering with any entry breaks the chain.
+        let mut tampered = entries.clone();
+        tampered[1].gas += 1;
+        assert!(verify(&tampered).is_err());
+        entries.remove(1);
+        assert!(verify(&entries).is_err());
+    }
+    #[test]
+    fn message_numbers() {
+        let buf = Buf::default();
+        let log = AuditLog::new(buf.clone());
+        let audited = || {
+            log.begin_message();
+            log.enter_frame(1, 100);
+            log.append(
+                "vm",
+                "message_context",
+                &[],
+                Gas::zero(),
+                Default::default(),
+            )
+            .unwrap();
+            log.exit_frame();
+        };
+        audited();
+        // A message that makes no syscalls is still counted.
+        log.begin_message();
+        log.enter_frame(1, 100);
+        log.exit_frame();
+        audited();
+
+        let entries = read_entries(&buf.0.lock().unwrap()[..]).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.message).collect::<Vec<_>>(),
+            [0, 2]
+        );
+    }
+    #[test]
+    fn outcome_from_ranges() {
+        let before = [0, 0, 0, 0, 0, 0, 0, 0];
+        let after = [0, 1, 1, 1, 0, 0, 1, 0];
+        let full = SyscallOutcome::new(0, &[], &before, &after);
+
+        // Overlapping and adjacent ranges produce the same writes as diffing the whole memory. The
+        // second borrow of 2..4 sees the bytes written through the first one.
+        let ranges = [
+            (1, vec![0, 0]),
+            (3, vec![0, 0, 0]),
+            (2, vec![1, 0]),
+            (6, vec![0]),
+        ];
+        assert_eq!(SyscallOutcome::from_ranges(0, &[], &ranges, &after), full);
+
+        // Writes outside of the ranges aren't recorded.
+        let writes = SyscallOutcome::from_ranges(0, &[], &ranges[..1], &after).writes;
+        assert_eq!(writes.len(), 1);
+        assert_eq!((writes[0].offset, &writes[0].data[..]), (1, &[1, 1][..]));
+    }
+}
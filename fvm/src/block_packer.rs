@@ -0,0 +1,305 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Selecting and ordering messages for inclusion in a block.
+//!
+//! The [`BlockPacker`] implements the gas premium auction: given a pool of (already validated)
+//! messages, it fills a block up to its gas limit with the messages paying the highest
+//! [effective premium](effective_premium) per unit of gas, so that miners and test harnesses share
+//! one implementation.
+//!
+//! Packing is greedy. Each sender's messages must be included in nonce order without gaps, so only
+//! the next message of each sender competes at any point: the packer repeatedly takes the
+//! competing message with the highest effective premium. If that message doesn't fit in the gas
+//! left in the block, its sender's remaining messages are dropped, and packing continues with the
+//! other senders. Messages that can't pay the base fee are never included.
+//!
+//! Ties between messages with the same effective premium are broken by hashing their CIDs with a
+//! chain-dependent seed (e.g., the block's ticket), so ties are broken differently from block to
+//! block, but reproducibly.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+
+use anyhow::Result;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+
+use crate::determinism::Map;
+
+/// Returns the premium per unit of gas paid to the miner by a message, at the given base fee: the
+/// message's gas premium, capped so that the base fee and premium don't exceed its fee cap. It's
+/// negative if the fee cap is below the base fee.
+pub fn effective_premium(msg: &Message, base_fee: &TokenAmount) -> TokenAmount {
+    (&msg.gas_fee_cap - base_fee).min(msg.gas_premium.clone())
+}
+
+/// Selects and orders messages for a block. See the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct BlockPacker {
+    gas_limit: i64,
+    base_fee: TokenAmount,
+    seed: Vec<u8>,
+    nonces: Map<Address, u64>,
+}
+
+/// A message competing for inclusion.
+struct Candidate {
+    premium: TokenAmount,
+    /// The chain-dependent tie-breaker. Lower wins.
+    tie: [u8; 32],
+    msg: Message,
+}
+
+impl Candidate {
+    fn rank(&self) -> (&TokenAmount, Reverse<&[u8; 32]>) {
+        (&self.premium, Reverse(&self.tie))
+    }
+}
+
+impl BlockPacker {
+    /// Creates a packer for blocks with the given gas limit, at the given base fee.
+    pub fn new(block_gas_limit: i64, base_fee: TokenAmount) -> Self {
+        BlockPacker {
+            gas_limit: block_gas_limit,
+            base_fee,
+            seed: Vec::new(),
+            nonces: Map::default(),
+        }
+    }
+
+    /// Sets the seed used to break ties between messages with the same effective premium.
+    pub fn with_seed(mut self, seed: &[u8]) -> Self {
+        self.seed = seed.to_vec();
+        self
+    }
+
+    /// Sets the next nonce of a sender, as found in the state the block applies to. The sender's
+    /// messages are only included starting from this nonce. By default, a sender's messages are
+    /// included starting from the lowest nonce in the pool.
+    pub fn with_sender_nonce(mut self, sender: Address, nonce: u64) -> Self {
+        self.nonces.insert(sender, nonce);
+        self
+    }
+
+    /// Selects messages from the pool, returning them in the order they must be included.
+    ///
+    /// When a sender has several messages with the same nonce, only the one with the highest
+    /// effective premium competes. Fails if a message can't be serialized to compute its CID.
+    pub fn pack(&self, messages: impl IntoIterator<Item = Message>) -> Result<Vec<Message>> {
+        // Group the messages by sender, keeping the best message for each nonce.
+        let mut senders: Map<Address, BTreeMap<u64, Candidate>> = Map::default();
+        for msg in messages {
+            let premium = effective_premium(&msg, &self.base_fee);
+            if premium.is_negative() || msg.gas_limit <= 0 || msg.gas_limit > self.gas_limit {
+                continue;
+            }
+            let candidate = Candidate {
+                premium,
+                tie: self.tie_breaker(&msg)?,
+                msg,
+            };
+            let nonces = senders.entry(candidate.msg.from).or_default();
+            match nonces.entry(candidate.msg.sequence) {
+                Entry::Vacant(e) => {
+                    e.insert(candidate);
+                }
+                Entry::Occupied(mut e) => {
+                    if candidate.rank() > e.get().rank() {
+                        e.insert(candidate);
+                    }
+                }
+            }
+        }
+
+        // Keep each sender's run of consecutive nonces from its next nonce.
+        let mut chains: Vec<VecDeque<Candidate>> = Vec::with_capacity(senders.len());
+        for (sender, nonces) in senders {
+            let mut next = match self.nonces.get(&sender) {
+                Some(&nonce) => nonce,
+                None => match nonces.keys().next() {
+                    Some(&nonce) => nonce,
+                    None => continue,
+                },
+            };
+            let mut chain = VecDeque::new();
+            for (nonce, candidate) in nonces {
+                match nonce.cmp(&next) {
+                    Ordering::Less => continue,
+                    Ordering::Greater => break,
+                    Ordering::Equal => {}
+                }
+                chain.push_back(candidate);
+                next += 1;
+            }
+            chains.push(chain);
+        }
+
+        // Repeatedly take the best of the senders' next messages.
+        let mut heap = BinaryHeap::new();
+        for (i, chain) in chains.iter().enumerate() {
+            if let Some(head) = chain.front() {
+                heap.push((head.premium.clone(), Reverse(head.tie), i));
+            }
+        }
+        let mut gas_left = self.gas_limit;
+        let mut packed = Vec::new();
+        while let Some((_, _, i)) = heap.pop() {
+            let candidate = match chains[i].pop_front() {
+                Some(candidate) => candidate,
+                None => continue,
+            };
+            if candidate.msg.gas_limit > gas_left {
+                // The sender's later messages can't be included without this one.
+                continue;
+            }
+            gas_left -= candidate.msg.gas_limit;
+            packed.push(candidate.msg);
+            if let Some(head) = chains[i].front() {
+                heap.push((head.premium.clone(), Reverse(head.tie), i));
+            }
+        }
+        Ok(packed)
+    }
+
+    fn tie_breaker(&self, msg: &Message) -> Result<[u8; 32]> {
+        let hash = blake2b_simd::Params::new()
+            .hash_length(32)
+            .to_state()
+            .update(&self.seed)
+            .update(&msg.cid()?.to_bytes())
+            .finalize();
+        let mut tie = [0u8; 32];
+        tie.copy_from_slice(hash.as_bytes());
+        Ok(tie)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::RawBytes;
+    use num_traits::Zero;
+
+    use super::*;
+
+    fn msg(from: u64, sequence: u64, gas_limit: i64, premium: u64) -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(from),
+            to: Address::new_id(1000),
+            sequence,
+            value: TokenAmount::zero(),
+            method_num: 0,
+            params: RawBytes::default(),
+            gas_limit,
+            gas_fee_cap: TokenAmount::from_atto(200),
+            gas_premium: TokenAmount::from_atto(premium),
+        }
+    }
+
+    fn packed(packer: &BlockPacker, msgs: &[Message]) -> Vec<(u64, u64)> {
+        packer
+            .pack(msgs.to_vec())
+            .unwrap()
+            .iter()
+            .map(|m| (m.from.id().unwrap(), m.sequence))
+            .collect()
+    }
+
+    #[test]
+    fn effective_premiums() {
+        let base_fee = TokenAmount::from_atto(150);
+        assert_eq!(
+            effective_premium(&msg(1, 0, 1, 10), &base_fee),
+            TokenAmount::from_atto(10)
+        );
+        // Capped by the fee cap.
+        assert_eq!(
+            effective_premium(&msg(1, 0, 1, 100), &base_fee),
+            TokenAmount::from_atto(50)
+        );
+        assert!(effective_premium(&msg(1, 0, 1, 100), &TokenAmount::from_atto(300)).is_negative());
+    }
+
+    #[test]
+    fn premium_order() {
+        let packer = BlockPacker::new(100, TokenAmount::from_atto(100));
+        let msgs = [
+            msg(1, 0, 10, 5),
+            msg(1, 1, 10, 50),
+            msg(2, 0, 10, 20),
+            // Capped to 100 by the fee cap, like the next one.
+            msg(3, 0, 10, 150),
+            msg(3, 1, 10, 100),
+        ];
+        // Sender 1's second message waits for its first.
+        assert_eq!(
+            packed(&packer, &msgs),
+            vec![(3, 0), (3, 1), (2, 0), (1, 0), (1, 1)]
+        );
+    }
+
+    #[test]
+    fn nonce_constraints() {
+        let packer = BlockPacker::new(100, TokenAmount::from_atto(100))
+            .with_sender_nonce(Address::new_id(1), 1);
+        let msgs = [
+            // Already included.
+            msg(1, 0, 10, 50),
+            msg(1, 1, 10, 10),
+            // Replaced by a better message with the same nonce.
+            msg(1, 2, 10, 20),
+            msg(1, 2, 10, 30),
+            // Gap.
+            msg(1, 4, 10, 90),
+            msg(2, 5, 10, 40),
+            msg(2, 6, 10, 40),
+        ];
+        let packed = packer.pack(msgs.to_vec()).unwrap();
+        assert_eq!(
+            packed
+                .iter()
+                .map(|m| (m.from.id().unwrap(), m.sequence))
+                .collect::<Vec<_>>(),
+            vec![(2, 5), (2, 6), (1, 1), (1, 2)]
+        );
+        assert_eq!(packed[3].gas_premium, TokenAmount::from_atto(30));
+    }
+
+    #[test]
+    fn gas_limit() {
+        let packer = BlockPacker::new(100, TokenAmount::from_atto(100));
+        let msgs = [
+            msg(1, 0, 60, 50),
+            // Doesn't fit, so sender 2's later message is dropped too.
+            msg(2, 0, 50, 40),
+            msg(2, 1, 10, 40),
+            msg(3, 0, 40, 30),
+            // Can't pay the base fee.
+            Message {
+                gas_fee_cap: TokenAmount::from_atto(50),
+                ..msg(4, 0, 1, 300)
+            },
+            // Exceeds the block gas limit.
+            msg(5, 0, 101, 90),
+        ];
+        assert_eq!(packed(&packer, &msgs), vec![(1, 0), (3, 0)]);
+    }
+
+    #[test]
+    fn tie_breaking() {
+        let msgs: Vec<_> = (1..=8).map(|from| msg(from, 0, 10, 10)).collect();
+        let order = |seed: &[u8]| {
+            let packer = BlockPacker::new(100, TokenAmount::from_atto(100)).with_seed(seed);
+            packed(&packer, &msgs)
+        };
+        // Reproducible, but seed-dependent.
+        assert_eq!(order(b"a"), order(b"a"));
+        assert_ne!(order(b"a"), order(b"b"));
+        let mut reversed = msgs.clone();
+        reversed.reverse();
+        let packer = BlockPacker::new(100, TokenAmount::from_atto(100)).with_seed(b"a");
+        assert_eq!(packed(&packer, &reversed), order(b"a"));
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Recording the blocks read while executing messages, and prefetching them when replaying the
+//! same messages (e.g., when re-validating historical blocks during a sync from a snapshot).
+//!
+//! Replaying messages over a large state is dominated by random reads from the backing store, but
+//! the blocks a message reads don't change from one execution to the next. So, the first
+//! execution can record an [`AccessTrace`] with an [`AccessRecorder`], and later executions can
+//! hand it to a [`TracePrefetcher`] to load each message's blocks with a [`Prefetcher`], a few
+//! messages ahead of execution:
+//!
+//! 1. Wrap the blockstore given to the machine with [`AccessRecorder::wrap`] (or
+//!    [`TracePrefetcher::wrap`] when replaying).
+//! 2. Call [`AccessRecorder::begin_message`] (or [`TracePrefetcher::begin_message`]) before
+//!    applying each message.
+//! 3. Once done, take the trace with [`AccessRecorder::take`], and store it alongside the block.
+//!
+//! Only reads that reach the wrapped blockstore are recorded, so blocks written during execution
+//! (and read back from the machine's write buffer) aren't. Prefetching never affects execution:
+//! blocks are content-addressed, and blocks that haven't been prefetched (e.g., if the trace is
+//! stale or incomplete, or the prefetcher is full) are read directly.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+
+use super::Prefetcher;
+
+/// The blocks read while executing a sequence of messages.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct AccessTrace {
+    /// The blocks read by each message, in the order they were first read by that message.
+    pub messages: Vec<Vec<Cid>>,
+}
+
+#[derive(Default)]
+struct Recording {
+    trace: AccessTrace,
+    /// The blocks already read by the current message.
+    seen: HashSet<Cid>,
+}
+
+/// Records an [`AccessTrace`] of the reads made through the blockstores it wraps. Clones share the
+/// same trace.
+#[derive(Clone, Default)]
+pub struct AccessRecorder(Arc<Mutex<Recording>>);
+
+impl AccessRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `store`, recording the blocks read through it.
+    pub fn wrap<BS: Blockstore>(&self, store: BS) -> RecordingBlockstore<BS> {
+        RecordingBlockstore {
+            store,
+            recorder: self.clone(),
+        }
+    }
+
+    /// Starts recording the reads of the next message. Reads before the first message (e.g., while
+    /// constructing the machine) aren't recorded.
+    pub fn begin_message(&self) {
+        let mut recording = self.0.lock().expect("access recording poisoned");
+        recording.trace.messages.push(Vec::new());
+        recording.seen.clear();
+    }
+
+    /// Takes the trace recorded so far, and starts a new one.
+    pub fn take(&self) -> AccessTrace {
+        std::mem::take(&mut *self.0.lock().expect("access recording poisoned")).trace
+    }
+
+    fn record(&self, k: &Cid) {
+        let mut recording = self.0.lock().expect("access recording poisoned");
+        let Recording { trace, seen } = &mut *recording;
+        if let Some(reads) = trace.messages.last_mut() {
+            if seen.insert(*k) {
+                reads.push(*k);
+            }
+        }
+    }
+}
+
+/// A blockstore recording the blocks read from it. See [`AccessRecorder`].
+pub struct RecordingBlockstore<BS> {
+    store: BS,
+    recorder: AccessRecorder,
+}
+
+impl<BS: Blockstore> Blockstore for RecordingBlockstore<BS> {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.recorder.record(k);
+        self.store.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.store.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.store.has(k)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.store.put_many_keyed(blocks)
+    }
+}
+
+/// Prefetches the blocks listed in an [`AccessTrace`] ahead of the messages expected to read them,
+/// with a [`Prefetcher`], and serves them to the blockstores it wraps.
+///
+/// The blocks of up to `lookahead` messages past the current one (see [`begin_message`]) are
+/// queued ahead of time, as long as the prefetcher has room for them; the rest are queued by later
+/// calls to [`begin_message`]. Like the blocks hinted by actors, prefetched blocks are handed out
+/// once, and at most `capacity` blocks are loaded-but-unread at once.
+///
+/// [`begin_message`]: TracePrefetcher::begin_message
+pub struct TracePrefetcher {
+    trace: AccessTrace,
+    lookahead: usize,
+    /// The next block to queue, as a message index and an offset into its blocks.
+    next: (usize, usize),
+    prefetcher: Arc<Prefetcher>,
+}
+
+impl TracePrefetcher {
+    /// Starts a prefetcher loading the blocks listed in `trace` from `store` (usually another
+    /// handle to the underlying blockstore), with room for `capacity` blocks.
+    pub fn new<BS>(store: BS, trace: AccessTrace, lookahead: usize, capacity: usize) -> Result<Self>
+    where
+        BS: Blockstore + Send + 'static,
+    {
+        Ok(TracePrefetcher {
+            trace,
+            lookahead,
+            next: (0, 0),
+            prefetcher: Arc::new(Prefetcher::new(store, capacity)?),
+        })
+    }
+
+    /// Wraps `store`, serving the blocks loaded by this prefetcher before falling back on `store`.
+    pub fn wrap<BS: Blockstore>(&self, store: BS) -> PrefetchingBlockstore<BS> {
+        PrefetchingBlockstore {
+            store,
+            prefetcher: self.prefetcher.clone(),
+        }
+    }
+
+    /// Signals that the message at `index` in the trace is about to be applied: drops the blocks
+    /// only listed for earlier messages (e.g., if the trace is stale), and queues the blocks of the
+    /// following `lookahead` messages that haven't been queued yet.
+    pub fn begin_message(&mut self, index: usize) {
+        if self.next.0 < index {
+            self.next = (index, 0);
+        }
+        let len = self.trace.messages.len();
+        let queued: HashSet<&Cid> = self.trace.messages[index.min(len)..(self.next.0 + 1).min(len)]
+            .iter()
+            .flatten()
+            .collect();
+        self.prefetcher.retain(|k| queued.contains(k));
+
+        let end = index
+            .saturating_add(self.lookahead)
+            .saturating_add(1)
+            .min(len);
+        while self.next.0 < end {
+            let (message, offset) = self.next;
+            match self.trace.messages[message].get(offset) {
+                // Stop once the queue is full, and pick up from there with the next message.
+                Some(k) if !self.prefetcher.prefetch(*k) => break,
+                Some(_) => self.next.1 += 1,
+                None => self.next = (message + 1, 0),
+            }
+        }
+    }
+}
+
+/// A blockstore serving the blocks loaded by a [`TracePrefetcher`].
+pub struct PrefetchingBlockstore<BS> {
+    store: BS,
+    prefetcher: Arc<Prefetcher>,
+}
+
+impl<BS: Blockstore> Blockstore for PrefetchingBlockstore<BS> {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        match self.prefetcher.take(k) {
+            Some(data) => Ok(Some(data)),
+            None => self.store.get(k),
+        }
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.store.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.store.has(k)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.store.put_many_keyed(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::{from_slice, to_vec, IPLD_RAW};
+
+    use super::*;
+
+    fn put(store: &impl Blockstore, data: &[u8]) -> Cid {
+        let k = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(data));
+        store.put_keyed(&k, data).unwrap();
+        k
+    }
+
+    /// A blockstore counting reads.
+    #[derive(Default)]
+    struct CountingBlockstore {
+        store: MemoryBlockstore,
+        reads: Cell<usize>,
+    }
+
+    impl Blockstore for CountingBlockstore {
+        fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+            self.reads.set(self.reads.get() + 1);
+            self.store.get(k)
+        }
+
+        fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+            self.store.put_keyed(k, block)
+        }
+    }
+
+    /// Waits for the prefetcher to load a block.
+    fn is_loaded(prefetcher: &TracePrefetcher, k: &Cid) -> bool {
+        for _ in 0..1000 {
+            if prefetcher.prefetcher.contains(k) {
+                return true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        false
+    }
+
+    #[test]
+    fn records_reads() {
+        let store = MemoryBlockstore::default();
+        let (k1, k2, k3) = (
+            put(&store, b"one"),
+            put(&store, b"two"),
+            put(&store, b"three"),
+        );
+
+        let recorder = AccessRecorder::new();
+        let recording = recorder.wrap(&store);
+        // Reads before the first message aren't recorded.
+        recording.get(&k3).unwrap();
+        recorder.begin_message();
+        for k in [k2, k1, k2] {
+            recording.get(&k).unwrap();
+        }
+        recorder.begin_message();
+        recorder.begin_message();
+        recording.get(&k2).unwrap();
+        // Writes aren't recorded.
+        put(&recording, b"four");
+
+        let trace = recorder.take();
+        assert_eq!(
+            trace,
+            AccessTrace {
+                messages: vec![vec![k2, k1], vec![], vec![k2]],
+            }
+        );
+        assert_eq!(
+            from_slice::<AccessTrace>(&to_vec(&trace).unwrap()).unwrap(),
+            trace
+        );
+        assert_eq!(recorder.take(), AccessTrace::default());
+    }
+
+    #[test]
+    fn prefetches_ahead() {
+        let store = MemoryBlockstore::default();
+        let ks: Vec<_> = (0u8..5).map(|i| put(&store, &[i])).collect();
+        let trace = AccessTrace {
+            messages: vec![vec![ks[0], ks[1]], vec![ks[2]], vec![ks[3]], vec![ks[4]]],
+        };
+
+        let mut prefetcher = TracePrefetcher::new(store, trace, 1, 8).unwrap();
+        // The replayed execution's own blockstore is empty, so we can tell where blocks came from.
+        let replay = prefetcher.wrap(CountingBlockstore::default());
+
+        // The blocks of the first two messages are loaded, but not the others.
+        prefetcher.begin_message(0);
+        assert!(is_loaded(&prefetcher, &ks[0]));
+        assert!(is_loaded(&prefetcher, &ks[1]));
+        assert!(is_loaded(&prefetcher, &ks[2]));
+        assert!(!prefetcher.prefetcher.contains(&ks[3]));
+        assert_eq!(replay.get(&ks[0]).unwrap(), Some(vec![0]));
+        assert_eq!(replay.store.reads.get(), 0);
+
+        // Only the blocks of the next message are queued.
+        prefetcher.begin_message(1);
+        assert!(is_loaded(&prefetcher, &ks[3]));
+        assert!(!prefetcher.prefetcher.contains(&ks[4]));
+        assert_eq!(replay.get(&ks[2]).unwrap(), Some(vec![2]));
+        assert_eq!(replay.store.reads.get(), 0);
+
+        // Blocks are handed out once, and blocks that weren't prefetched are read directly.
+        assert_eq!(replay.get(&ks[2]).unwrap(), None);
+        assert_eq!(replay.store.reads.get(), 1);
+
+        // Blocks only listed for earlier messages are dropped.
+        prefetcher.begin_message(3);
+        assert!(is_loaded(&prefetcher, &ks[4]));
+        assert!(!prefetcher.prefetcher.contains(&ks[1]));
+        assert!(!prefetcher.prefetcher.contains(&ks[3]));
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::JoinHandle;
+
+use anyhow::{anyhow, Context as _, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+enum Request {
+    /// Write a batch of blocks.
+    Write(Vec<(Cid, Vec<u8>)>),
+    /// Reply once all previously queued batches have been written, with the first write error (if
+    /// any) since the last sync.
+    Sync(SyncSender<Option<String>>),
+}
+
+/// Writes batches of blocks to a blockstore on a background thread, so that persisting flushed
+/// state can overlap with executing the next messages.
+///
+/// At most `capacity` batches may be queued at once: once the queue is full, [`write`] blocks
+/// until the writer catches up. Write errors are reported by the next [`join`], which should be
+/// called before the written state is relied on (e.g., at the end of a block). Batches queued
+/// after a failed write are discarded until then.
+///
+/// Dropping the writer waits for the queued batches to be written, but discards any errors.
+///
+/// [`write`]: BackgroundWriter::write
+/// [`join`]: BackgroundWriter::join
+pub struct BackgroundWriter {
+    sender: Option<SyncSender<Request>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundWriter {
+    /// Starts a writer writing to `store`, with room for `capacity` queued batches.
+    pub fn new<BS>(store: BS, capacity: usize) -> Result<Self>
+    where
+        BS: Blockstore + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let handle = std::thread::Builder::new()
+            .name("fvm-flush".into())
+            .spawn(move || run(store, receiver))
+            .context("failed to spawn the background writer")?;
+        Ok(BackgroundWriter {
+            sender: Some(sender),
+            handle: Some(handle),
+        })
+    }
+
+    /// Queues a batch of blocks to be written, blocking while the queue is full.
+    pub fn write(&self, blocks: Vec<(Cid, Vec<u8>)>) -> Result<()> {
+        self.send(Request::Write(blocks))
+    }
+
+    /// Waits for all the queued batches to be written, returning the first write error since the
+    /// last join.
+    pub fn join(&self) -> Result<()> {
+        let (reply, done) = mpsc::sync_channel(1);
+        self.send(Request::Sync(reply))?;
+        match done.recv() {
+            Ok(None) => Ok(()),
+            Ok(Some(error)) => Err(anyhow!("background write failed: {}", error)),
+            Err(_) => Err(anyhow!("background writer exited")),
+        }
+    }
+
+    fn send(&self, request: Request) -> Result<()> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(request).ok())
+            .ok_or_else(|| anyhow!("background writer exited"))
+    }
+}
+
+impl Drop for BackgroundWriter {
+    fn drop(&mut self) {
+        // Hang up, and wait for the writer to drain the queue.
+        drop(self.sender.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run<BS: Blockstore>(store: BS, requests: Receiver<Request>) {
+    let mut error: Option<String> = None;
+    for request in requests {
+        match request {
+            Request::Write(blocks) => {
+                if error.is_some() {
+                    continue;
+                }
+                if let Err(e) = store.put_many_keyed(blocks) {
+                    log::error!("background write failed: {:#}", e);
+                    error = Some(format!("{:#}", e));
+                }
+            }
+            Request::Sync(reply) => {
+                let _ = reply.send(error.take());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_ipld_encoding::IPLD_RAW;
+
+    use super::*;
+
+    /// A thread-safe in-memory blockstore that fails writes while `fail` is set.
+    #[derive(Clone, Default)]
+    struct SharedStore {
+        blocks: Arc<Mutex<HashMap<Cid, Vec<u8>>>>,
+        fail: Arc<Mutex<bool>>,
+    }
+
+    impl Blockstore for SharedStore {
+        fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+            Ok(self.blocks.lock().unwrap().get(k).cloned())
+        }
+
+        fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+            if *self.fail.lock().unwrap() {
+                return Err(anyhow!("disk full"));
+            }
+            self.blocks.lock().unwrap().insert(*k, block.into());
+            Ok(())
+        }
+    }
+
+    fn block(data: &[u8]) -> (Cid, Vec<u8>) {
+        (
+            Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(data)),
+            data.to_vec(),
+        )
+    }
+
+    #[test]
+    fn write_and_join() {
+        let store = SharedStore::default();
+        let writer = BackgroundWriter::new(store.clone(), 1).unwrap();
+        let (a, b) = (block(b"a"), block(b"b"));
+        writer.write(vec![a.clone()]).unwrap();
+        writer.write(vec![b.clone()]).unwrap();
+        writer.join().unwrap();
+        assert_eq!(store.get(&a.0).unwrap(), Some(a.1));
+        assert_eq!(store.get(&b.0).unwrap(), Some(b.1));
+    }
+
+    #[test]
+    fn errors() {
+        let store = SharedStore::default();
+        let writer = BackgroundWriter::new(store.clone(), 4).unwrap();
+        *store.fail.lock().unwrap() = true;
+        writer.write(vec![block(b"a")]).unwrap();
+        writer.join().unwrap_err();
+
+        // The error is only reported once, and the writer keeps going.
+        *store.fail.lock().unwrap() = false;
+        writer.join().unwrap();
+        let b = block(b"b");
+        writer.write(vec![b.clone()]).unwrap();
+        drop(writer);
+        assert_eq!(store.get(&b.0).unwrap(), Some(b.1));
+    }
+}
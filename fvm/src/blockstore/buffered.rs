@@ -4,15 +4,16 @@
 
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{Cursor, Read, Seek};
+use std::io::Cursor;
 
 use anyhow::{anyhow, Result};
-use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use cid::Cid;
 use fvm_ipld_blockstore::{Blockstore, Buffered};
-use fvm_ipld_encoding::DAG_CBOR;
+use fvm_ipld_encoding::{scan_for_links, DAG_CBOR};
 use fvm_shared::commcid::{FIL_COMMITMENT_SEALED, FIL_COMMITMENT_UNSEALED};
 
+use super::BackgroundWriter;
+
 /// Wrapper around `Blockstore` to limit and have control over when values are written.
 /// This type is not threadsafe and can only be used in synchronous contexts.
 #[derive(Debug)]
@@ -35,6 +36,22 @@ where
     pub fn into_inner(self) -> BS {
         self.base
     }
+
+    /// Empties the write buffer. Blocks that were already flushed stay in the base store.
+    pub fn discard(&self) {
+        self.write.borrow_mut().clear();
+    }
+
+    /// Like [`Buffered::flush`], but hands the blocks to a [`BackgroundWriter`] instead of writing
+    /// them to the base store. The blocks stay in the write buffer, so they can still be read
+    /// while they're being written.
+    pub fn flush_to(&self, root: &Cid, writer: &BackgroundWriter) -> Result<()> {
+        let mut buffer = Vec::new();
+        let s = self.write.borrow();
+        copy_rec(&s, *root, &mut buffer)?;
+
+        writer.write(buffer.into_iter().map(|(k, v)| (k, v.to_vec())).collect())
+    }
 }
 
 impl<BS> Buffered for BufferedBlockstore<BS>
@@ -55,114 +72,6 @@ where
     }
 }
 
-/// Given a CBOR encoded Buffer, returns a tuple of:
-/// the type of the CBOR object along with extra
-/// elements we expect to read. More info on this can be found in
-/// Appendix C. of RFC 7049 which defines the CBOR specification.
-/// This was implemented because the CBOR library we use does not expose low
-/// methods like this, requiring us to deserialize the whole CBOR payload, which
-/// is unnecessary and quite inefficient for our usecase here.
-fn cbor_read_header_buf<B: Read>(br: &mut B, scratch: &mut [u8]) -> anyhow::Result<(u8, usize)> {
-    let first = br.read_u8()?;
-    let maj = (first & 0xe0) >> 5;
-    let low = first & 0x1f;
-
-    if low < 24 {
-        Ok((maj, low as usize))
-    } else if low == 24 {
-        let val = br.read_u8()?;
-        if val < 24 {
-            return Err(anyhow!(
-                "cbor input was not canonical (lval 24 with value < 24)"
-            ));
-        }
-        Ok((maj, val as usize))
-    } else if low == 25 {
-        br.read_exact(&mut scratch[..2])?;
-        let val = BigEndian::read_u16(&scratch[..2]);
-        if val <= u8::MAX as u16 {
-            return Err(anyhow!(
-                "cbor input was not canonical (lval 25 with value <= MaxUint8)"
-            ));
-        }
-        Ok((maj, val as usize))
-    } else if low == 26 {
-        br.read_exact(&mut scratch[..4])?;
-        let val = BigEndian::read_u32(&scratch[..4]);
-        if val <= u16::MAX as u32 {
-            return Err(anyhow!(
-                "cbor input was not canonical (lval 26 with value <= MaxUint16)"
-            ));
-        }
-        Ok((maj, val as usize))
-    } else if low == 27 {
-        br.read_exact(&mut scratch[..8])?;
-        let val = BigEndian::read_u64(&scratch[..8]);
-        if val <= u32::MAX as u64 {
-            return Err(anyhow!(
-                "cbor input was not canonical (lval 27 with value <= MaxUint32)"
-            ));
-        }
-        Ok((maj, val as usize))
-    } else {
-        Err(anyhow!("invalid header cbor_read_header_buf"))
-    }
-}
-
-/// Given a CBOR serialized IPLD buffer, read through all of it and return all the Links.
-/// This function is useful because it is quite a bit more fast than doing this recursively on a
-/// deserialized IPLD object.
-fn scan_for_links<B: Read + Seek, F>(buf: &mut B, mut callback: F) -> Result<()>
-where
-    F: FnMut(Cid) -> anyhow::Result<()>,
-{
-    let mut scratch: [u8; 100] = [0; 100];
-    let mut remaining = 1;
-    while remaining > 0 {
-        let (maj, extra) = cbor_read_header_buf(buf, &mut scratch)?;
-        match maj {
-            // MajUnsignedInt, MajNegativeInt, MajOther
-            0 | 1 | 7 => {}
-            // MajByteString, MajTextString
-            2 | 3 => {
-                buf.seek(std::io::SeekFrom::Current(extra as i64))?;
-            }
-            // MajTag
-            6 => {
-                // Check if the tag refers to a CID
-                if extra == 42 {
-                    let (maj, extra) = cbor_read_header_buf(buf, &mut scratch)?;
-                    // The actual CID is expected to be a byte string
-                    if maj != 2 {
-                        return Err(anyhow!("expected cbor type byte string in input"));
-                    }
-                    if extra > 100 {
-                        return Err(anyhow!("string in cbor input too long"));
-                    }
-                    buf.read_exact(&mut scratch[..extra])?;
-                    let c = Cid::try_from(&scratch[1..extra])?;
-                    callback(c)?;
-                } else {
-                    remaining += 1;
-                }
-            }
-            // MajArray
-            4 => {
-                remaining += extra;
-            }
-            // MajMap
-            5 => {
-                remaining += extra * 2;
-            }
-            _ => {
-                return Err(anyhow!("unhandled cbor type: {}", maj));
-            }
-        }
-        remaining -= 1;
-    }
-    Ok(())
-}
-
 /// Copies the IPLD DAG under `root` from the cache to the base store.
 fn copy_rec<'a>(
     cache: &'a HashMap<Cid, Vec<u8>>,
@@ -253,15 +162,20 @@ where
         }
     }
 
+    /// Inserts all blocks into the write buffer at once. Blocks already in the buffer (e.g.,
+    /// identical nodes written by several collections) are coalesced without copying them again.
     fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
     where
         Self: Sized,
         D: AsRef<[u8]>,
         I: IntoIterator<Item = (Cid, D)>,
     {
-        self.write
-            .borrow_mut()
-            .extend(blocks.into_iter().map(|(k, v)| (k, v.as_ref().into())));
+        let blocks = blocks.into_iter();
+        let mut write = self.write.borrow_mut();
+        write.reserve(blocks.size_hint().0);
+        for (k, v) in blocks {
+            write.entry(k).or_insert_with(|| v.as_ref().into());
+        }
         Ok(())
     }
 }
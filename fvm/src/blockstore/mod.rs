@@ -2,5 +2,14 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 //! Private blockstores for use in the FVM.
 
+mod access_trace;
+mod background;
 mod buffered;
+mod prefetch;
+pub use access_trace::{
+    AccessRecorder, AccessTrace, PrefetchingBlockstore, RecordingBlockstore, TracePrefetcher,
+};
+pub use background::BackgroundWriter;
 pub use buffered::BufferedBlockstore;
+pub(crate) use fvm_ipld_encoding::scan_for_links;
+pub use prefetch::Prefetcher;
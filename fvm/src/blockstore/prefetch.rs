@@ -0,0 +1,184 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use anyhow::{Context as _, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+type Blocks = Arc<Mutex<HashMap<Cid, Vec<u8>>>>;
+
+/// Loads blocks from a blockstore on a background thread, ahead of the actors that asked for them
+/// (with the `ipld::block_prefetch` syscall) or of the messages expected to read them (see
+/// [`TracePrefetcher`](super::TracePrefetcher)), so that IO can overlap with execution on slow
+/// blockstores.
+///
+/// Prefetching is purely advisory: at most `capacity` blocks may be queued or loaded-but-unused at
+/// once, and requests beyond that are dropped. Loaded blocks are handed out once by [`take`], and
+/// discarded by [`clear`] (e.g., at the end of each message). Since blocks are content-addressed,
+/// a prefetched block is always identical to the block read directly.
+///
+/// [`take`]: Prefetcher::take
+/// [`clear`]: Prefetcher::clear
+pub struct Prefetcher {
+    sender: Option<SyncSender<Cid>>,
+    blocks: Blocks,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Prefetcher {
+    /// Starts a prefetcher loading blocks from `store`, with room for `capacity` blocks.
+    pub fn new<BS>(store: BS, capacity: usize) -> Result<Self>
+    where
+        BS: Blockstore + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let blocks = Blocks::default();
+        let handle = std::thread::Builder::new()
+            .name("fvm-prefetch".into())
+            .spawn({
+                let blocks = blocks.clone();
+                move || run(store, receiver, blocks, capacity)
+            })
+            .context("failed to spawn the prefetcher")?;
+        Ok(Prefetcher {
+            sender: Some(sender),
+            blocks,
+            handle: Some(handle),
+        })
+    }
+
+    /// Queues a block to be loaded, returning false if the request was dropped because the queue is
+    /// full. Never blocks.
+    pub fn prefetch(&self, k: Cid) -> bool {
+        self.sender
+            .as_ref()
+            .map_or(false, |sender| sender.try_send(k).is_ok())
+    }
+
+    /// Takes a block, if it has been loaded.
+    pub fn take(&self, k: &Cid) -> Option<Vec<u8>> {
+        self.blocks
+            .lock()
+            .expect("prefetched blocks poisoned")
+            .remove(k)
+    }
+
+    /// Discards the loaded blocks that haven't been taken.
+    pub fn clear(&self) {
+        self.blocks
+            .lock()
+            .expect("prefetched blocks poisoned")
+            .clear()
+    }
+
+    /// Discards the loaded blocks for which `f` returns false.
+    pub fn retain(&self, mut f: impl FnMut(&Cid) -> bool) {
+        self.blocks
+            .lock()
+            .expect("prefetched blocks poisoned")
+            .retain(|k, _| f(k))
+    }
+
+    /// Returns true if a block has been loaded, and not yet taken.
+    #[cfg(test)]
+    pub(crate) fn contains(&self, k: &Cid) -> bool {
+        self.blocks
+            .lock()
+            .expect("prefetched blocks poisoned")
+            .contains_key(k)
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        // Hang up, and wait for the prefetcher to drain the queue.
+        drop(self.sender.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run<BS: Blockstore>(store: BS, requests: Receiver<Cid>, blocks: Blocks, capacity: usize) {
+    for k in requests {
+        {
+            let blocks = blocks.lock().expect("prefetched blocks poisoned");
+            if blocks.len() >= capacity || blocks.contains_key(&k) {
+                continue;
+            }
+        }
+        match store.get(&k) {
+            Ok(Some(data)) => {
+                blocks
+                    .lock()
+                    .expect("prefetched blocks poisoned")
+                    .insert(k, data);
+            }
+            Ok(None) => {}
+            // The actor will get the real error when it reads the block, if it does.
+            Err(e) => log::debug!("failed to prefetch {}: {:#}", k, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::IPLD_RAW;
+
+    use super::*;
+
+    fn block(data: &[u8]) -> (Cid, Vec<u8>) {
+        (
+            Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(data)),
+            data.to_vec(),
+        )
+    }
+
+    /// Waits for the prefetcher to load a block.
+    fn wait_for(prefetcher: &Prefetcher, k: &Cid) -> Option<Vec<u8>> {
+        for _ in 0..1000 {
+            if let Some(data) = prefetcher.take(k) {
+                return Some(data);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn prefetches_blocks() {
+        let store = MemoryBlockstore::default();
+        let (k1, v1) = block(b"one");
+        let (k2, v2) = block(b"two");
+        let (missing, _) = block(b"missing");
+        store.put_keyed(&k1, &v1).unwrap();
+        store.put_keyed(&k2, &v2).unwrap();
+
+        let prefetcher = Prefetcher::new(store, 4).unwrap();
+        assert!(prefetcher.prefetch(k1));
+        assert_eq!(wait_for(&prefetcher, &k1), Some(v1));
+        // Blocks are only handed out once.
+        assert_eq!(prefetcher.take(&k1), None);
+
+        assert!(prefetcher.prefetch(missing));
+        assert!(prefetcher.prefetch(k2));
+        assert_eq!(wait_for(&prefetcher, &k2), Some(v2));
+        assert_eq!(prefetcher.take(&missing), None);
+
+        assert!(prefetcher.prefetch(k2));
+        for _ in 0..1000 {
+            if prefetcher.blocks.lock().unwrap().contains_key(&k2) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        prefetcher.clear();
+        assert_eq!(prefetcher.take(&k2), None);
+    }
+}
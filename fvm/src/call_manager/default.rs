@@ -1,6 +1,5 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-use std::mem;
 use std::rc::Rc;
 
 use anyhow::{anyhow, Context};
@@ -12,22 +11,29 @@ use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::{ErrorNumber, ExitCode};
 use fvm_shared::event::StampedEvent;
 use fvm_shared::sys::BlockId;
+use fvm_shared::version::NetworkVersion;
 use fvm_shared::{ActorID, MethodNum, METHOD_SEND};
 use num_traits::Zero;
 
-use super::{Backtrace, CallManager, InvocationResult, NO_DATA_BLOCK_ID};
-use crate::call_manager::backtrace::Frame;
+use super::{
+    Backtrace, CallManager, HookPhase, InvocationResult, TransferHook, TransferHookParams,
+    NO_DATA_BLOCK_ID,
+};
+use crate::call_manager::backtrace::{Cause, Frame};
 use crate::call_manager::FinishRet;
-use crate::eam_actor::EAM_ACTOR_ID;
 use crate::engine::Engine;
-use crate::gas::{Gas, GasTimer, GasTracker};
-use crate::kernel::{Block, BlockRegistry, ExecutionError, Kernel, Result, SyscallError};
+use crate::gas::{ActorGasUsage, Gas, GasTimer, GasTracker};
+use crate::kernel::{
+    Block, BlockRegistry, DevRandomness, ExecutionError, FlagRegistry, HasherRegistry, Kernel,
+    LinkTracker, RandomnessCache, Result, SyscallError, SyscallMemo,
+};
 use crate::machine::limiter::MemoryLimiter;
-use crate::machine::Machine;
+use crate::machine::{Machine, TombstonePolicy, BURNT_FUNDS_ACTOR_ID};
+use crate::native::NativeActor;
 use crate::state_tree::ActorState;
 use crate::syscalls::error::Abort;
 use crate::syscalls::{charge_for_exec, update_gas_available};
-use crate::trace::{ExecutionEvent, ExecutionTrace};
+use crate::trace::{ExecutionEvent, ExecutionTrace, TransferFailure};
 use crate::{syscall_error, system_actor};
 
 /// The default [`CallManager`] implementation.
@@ -68,6 +74,25 @@ pub struct InnerDefaultCallManager<M: Machine> {
     limits: M::Limiter,
     /// Accumulator for events emitted in this call stack.
     events: EventsAccumulator,
+    /// Randomness fetched from the externs during this message execution.
+    randomness_cache: RandomnessCache,
+    /// Deterministic pseudo-randomness for this message execution, if enabled.
+    dev_randomness: Option<DevRandomness>,
+    /// Results of pure syscalls computed during this message execution.
+    syscall_memo: SyscallMemo,
+    /// Statistics of the state graphs linked during this message execution.
+    link_tracker: LinkTracker,
+    /// Incremental hashers opened during this message execution.
+    hashers: HasherRegistry,
+    /// Flags set by actors during this message execution.
+    flags: FlagRegistry,
+    /// Gas usage by actor code and method, if enabled.
+    actor_gas: ActorGasUsage,
+    /// The gas attributed to the calls made by each open call frame, if actor gas accounting is
+    /// enabled.
+    actor_gas_frames: Vec<Gas>,
+    /// Whether a transfer hook is running. Sends made by hooks don't trigger further hooks.
+    in_transfer_hook: bool,
 }
 
 #[doc(hidden)]
@@ -95,15 +120,41 @@ where
     fn new(
         machine: M,
         engine: Engine,
-        gas_limit: i64,
+        gas_limit: Option<i64>,
         origin: ActorID,
         origin_address: Address,
         nonce: u64,
         gas_premium: TokenAmount,
     ) -> Self {
         let limits = machine.new_limiter();
-        let gas_tracker =
-            GasTracker::new(Gas::new(gas_limit), Gas::zero(), machine.context().tracing);
+        let tracing = machine.context().tracing;
+        let syscall_memo = SyscallMemo::new(machine.context().network.syscall_memo_bytes);
+        let hashers = HasherRegistry::new(machine.context().network.max_hashers);
+        let mut gas_tracker = match gas_limit {
+            Some(limit) => GasTracker::new(Gas::new(limit), Gas::zero(), tracing),
+            None => GasTracker::new_unlimited(tracing),
+        };
+        if machine.context().network.deterministic_timing {
+            gas_tracker.enable_instruction_clock();
+        }
+        let dev_randomness = if machine.context().network.dev_randomness {
+            let cid = machine
+                .context()
+                .message_cid
+                .expect("dev randomness requires the message CID");
+            Some(DevRandomness::new(cid))
+        } else {
+            None
+        };
+        if let Some(log) = &machine.context().audit_log {
+            log.begin_message();
+        }
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &machine.context().faults {
+            if let Some(n) = faults.config().out_of_gas_at {
+                gas_tracker.inject_out_of_gas_at(n);
+            }
+        }
 
         DefaultCallManager(Some(Box::new(InnerDefaultCallManager {
             engine: Rc::new(engine),
@@ -120,6 +171,15 @@ where
             invocation_count: 0,
             limits,
             events: Default::default(),
+            randomness_cache: Default::default(),
+            dev_randomness,
+            syscall_memo,
+            link_tracker: Default::default(),
+            hashers,
+            flags: Default::default(),
+            actor_gas: Default::default(),
+            actor_gas_frames: Vec::new(),
+            in_transfer_hook: false,
         })))
     }
 
@@ -152,53 +212,40 @@ where
             });
         }
 
-        // If a specific gas limit has been requested, create a child GasTracker and use that
-        // one hereon.
-        let prev_gas_tracker = gas_limit
-            .and_then(|limit| self.gas_tracker.new_child(limit))
-            .map(|new| mem::replace(&mut self.gas_tracker, new));
-
-        let mut result =
-            self.with_stack_frame(|s| s.send_unchecked::<K>(from, to, method, params, value));
-
-        // Restore the original gas tracker and absorb the child's gas usage and traces into it.
-        if let Some(prev) = prev_gas_tracker {
-            let other = mem::replace(&mut self.gas_tracker, prev);
-            // This is capable of raising an OutOfGas, but it is redundant since send_resolved
-            // would've already raised it, so we ignore it here. We could check and assert that's
-            // true, but send_resolved could also error _fatally_ and mask the OutOfGas, so it's
-            // not safe to do so.
-            let _ = self.gas_tracker.absorb(&other);
-
-            // If we were limiting gas, convert the execution error to an exit.
-            if matches!(result, Err(ExecutionError::OutOfGas)) {
-                result = Ok(InvocationResult {
-                    exit_code: ExitCode::SYS_OUT_OF_GAS,
-                    value: None,
-                })
-            }
-        }
+        self.call_with_limit(gas_limit, |s| {
+            s.send_unchecked::<K>(from, to, method, params, value)
+        })
+    }
 
+    fn delegate_call<K>(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        code: Cid,
+        method: MethodNum,
+        params: Option<Block>,
+        value: &TokenAmount,
+        gas_limit: Option<Gas>,
+    ) -> Result<InvocationResult>
+    where
+        K: Kernel<CallManager = Self>,
+    {
         if self.machine.context().tracing {
-            self.trace(match &result {
-                Ok(InvocationResult { exit_code, value }) => ExecutionEvent::CallReturn(
-                    *exit_code,
-                    value
-                        .as_ref()
-                        .map(|blk| RawBytes::from(blk.data().to_vec()))
-                        .unwrap_or_default(),
-                ),
-                Err(ExecutionError::OutOfGas) => {
-                    ExecutionEvent::CallReturn(ExitCode::SYS_OUT_OF_GAS, RawBytes::default())
-                }
-                Err(ExecutionError::Fatal(_)) => {
-                    ExecutionEvent::CallError(SyscallError::new(ErrorNumber::Forbidden, "fatal"))
-                }
-                Err(ExecutionError::Syscall(s)) => ExecutionEvent::CallError(s.clone()),
+            self.trace(ExecutionEvent::Call {
+                from,
+                to: Address::new_id(to),
+                method,
+                params: params
+                    .as_ref()
+                    .map(|blk| blk.data().to_owned().into())
+                    .unwrap_or_default(),
+                value: TokenAmount::zero(),
             });
         }
 
-        result
+        self.call_with_limit(gas_limit, |s| {
+            s.delegate_resolved::<K>(from, to, code, method, params, value)
+        })
     }
 
     fn with_transaction(
@@ -231,25 +278,35 @@ where
             gas_tracker,
             mut exec_trace,
             events,
+            actor_gas,
             ..
         } = *self.0.take().expect("call manager is poisoned");
 
         // TODO: Having to check against zero here is fishy, but this is what lotus does.
-        let gas_used = gas_tracker.gas_used().max(Gas::zero()).round_up();
+        let gas_used = gas_tracker.total_gas_used();
 
         // Finalize any trace events, if we're tracing.
         if machine.context().tracing {
             exec_trace.extend(gas_tracker.drain_trace().map(ExecutionEvent::GasCharge));
         }
 
+        let out_of_gas = gas_tracker.out_of_gas_info();
+
         let events = events.finish();
 
+        // Drop any blocks prefetched for the message.
+        if let Some(prefetcher) = machine.prefetcher() {
+            prefetcher.clear();
+        }
+
         (
             FinishRet {
                 gas_used,
                 backtrace,
                 exec_trace,
                 events,
+                out_of_gas,
+                actor_gas,
             },
             machine,
         )
@@ -356,25 +413,97 @@ where
         self.events.append_event(evt)
     }
 
+    fn trace(&mut self, trace: ExecutionEvent) {
+        // The price of deref magic is that you sometimes need to tell the compiler: no, this is
+        // fine.
+        let s = &mut **self;
+
+        s.exec_trace
+            .extend(s.gas_tracker.drain_trace().map(ExecutionEvent::GasCharge));
+
+        s.exec_trace.push(trace);
+    }
+
+    fn event_bytes(&self) -> usize {
+        self.events.bytes
+    }
+
     // Helper for creating actors. This really doesn't belong on this trait.
     fn invocation_count(&self) -> u64 {
         self.invocation_count
     }
+
+    fn randomness_cache(&self) -> &RandomnessCache {
+        &self.randomness_cache
+    }
+
+    fn dev_randomness(&self) -> Option<&DevRandomness> {
+        self.dev_randomness.as_ref()
+    }
+
+    fn syscall_memo(&self) -> &SyscallMemo {
+        &self.syscall_memo
+    }
+
+    fn link_tracker(&self) -> &LinkTracker {
+        &self.link_tracker
+    }
+
+    fn link_tracker_mut(&mut self) -> &mut LinkTracker {
+        &mut self.link_tracker
+    }
+
+    fn hashers(&self) -> &HasherRegistry {
+        &self.hashers
+    }
+
+    fn flags(&self) -> &FlagRegistry {
+        &self.flags
+    }
 }
 
 impl<M> DefaultCallManager<M>
 where
     M: Machine,
 {
-    fn trace(&mut self, trace: ExecutionEvent) {
-        // The price of deref magic is that you sometimes need to tell the compiler: no, this is
-        // fine.
-        let s = &mut **self;
-
-        s.exec_trace
-            .extend(s.gas_tracker.drain_trace().map(ExecutionEvent::GasCharge));
-
-        s.exec_trace.push(trace);
+    /// Enforces the network's return size limits on the block `ret_id` returned by an actor
+    /// (including on abort), charging for any bytes beyond the soft limit. Missing blocks are
+    /// left to the caller.
+    fn charge_return(
+        &self,
+        block_registry: &BlockRegistry,
+        ret_id: BlockId,
+    ) -> std::result::Result<(), Abort> {
+        if ret_id == NO_DATA_BLOCK_ID {
+            return Ok(());
+        }
+        let size = match block_registry.get(ret_id) {
+            Ok(blk) => blk.size() as usize,
+            Err(_) => return Ok(()),
+        };
+        let (soft_limit, hard_limit) = {
+            let network = &self.machine.context().network;
+            (network.return_size_soft_limit, network.max_return_size)
+        };
+        if size > hard_limit as usize {
+            return Err(Abort::Exit(
+                ExitCode::SYS_RETURN_TOO_LARGE,
+                format!(
+                    "returned {} bytes, exceeding the maximum return size {}",
+                    size, hard_limit
+                ),
+                NO_DATA_BLOCK_ID,
+            ));
+        }
+        if size > soft_limit as usize {
+            let _ = self
+                .charge_gas(
+                    self.price_list()
+                        .on_return_oversize(size - soft_limit as usize),
+                )
+                .map_err(Abort::from_error_as_fatal)?;
+        }
+        Ok(())
     }
 
     fn create_account_actor<K>(&mut self, addr: &Address) -> Result<ActorID>
@@ -435,6 +564,54 @@ where
         t.record(self.machine.create_actor(addr, state))
     }
 
+    /// Makes a call in a new stack frame, constraining the gas tracker to `gas_limit` (if any)
+    /// for the duration of the call, and traces the result.
+    fn call_with_limit<F>(&mut self, gas_limit: Option<Gas>, f: F) -> Result<InvocationResult>
+    where
+        F: FnOnce(&mut Self) -> Result<InvocationResult>,
+    {
+        // If a specific gas limit has been requested, constrain the gas tracker to that limit
+        // for the duration of the call.
+        let limited = gas_limit.map(|limit| self.gas_tracker.push_limit(limit));
+
+        let mut result = self.with_stack_frame(f);
+
+        // Restore the original gas limit.
+        if let Some(lowered) = limited {
+            // A mismatched pop means we've corrupted the gas tracker state, which is fatal.
+            self.gas_tracker.pop_limit()?;
+
+            // If we were limiting gas, convert the execution error to an exit.
+            if lowered && matches!(result, Err(ExecutionError::OutOfGas)) {
+                result = Ok(InvocationResult {
+                    exit_code: ExitCode::SYS_OUT_OF_GAS,
+                    value: None,
+                })
+            }
+        }
+
+        if self.machine.context().tracing {
+            self.trace(match &result {
+                Ok(InvocationResult { exit_code, value }) => ExecutionEvent::CallReturn(
+                    *exit_code,
+                    value
+                        .as_ref()
+                        .map(|blk| RawBytes::from(blk.data().to_vec()))
+                        .unwrap_or_default(),
+                ),
+                Err(ExecutionError::OutOfGas) => {
+                    ExecutionEvent::CallReturn(ExitCode::SYS_OUT_OF_GAS, RawBytes::default())
+                }
+                Err(ExecutionError::Fatal(_)) => {
+                    ExecutionEvent::CallError(SyscallError::new(ErrorNumber::Forbidden, "fatal"))
+                }
+                Err(ExecutionError::Syscall(s)) => ExecutionEvent::CallError(s.clone()),
+            });
+        }
+
+        result
+    }
+
     /// Send without checking the call depth.
     fn send_unchecked<K>(
         &mut self,
@@ -455,9 +632,15 @@ where
                     // Try to create an account actor if the receiver is a key address.
                     self.create_account_actor::<K>(&to)?
                 }
-                // Validate that there's an actor at the target ID (we don't care what is there,
-                // just that something is there).
-                Payload::Delegated(da) if da.namespace() == EAM_ACTOR_ID => {
+                // Create a placeholder actor if the receiver is a delegated address in a namespace
+                // configured to allow it.
+                Payload::Delegated(da)
+                    if self
+                        .machine
+                        .context()
+                        .placeholder_namespaces
+                        .contains(&da.namespace()) =>
+                {
                     self.create_placeholder_actor::<K>(&to)?
                 }
                 _ => return Err(
@@ -483,17 +666,159 @@ where
         K: Kernel<CallManager = Self>,
     {
         // Lookup the actor.
-        let state = self
-            .state_tree()
-            .get_actor(to)?
-            .ok_or_else(|| syscall_error!(NotFound; "actor does not exist: {}", to))?;
+        let state = match self.state_tree().get_actor(to)? {
+            Some(state) => state,
+            None if self.state_tree().is_tombstone(to)? => {
+                return self.send_to_tombstone(from, to, method, value);
+            }
+            None => return Err(syscall_error!(NotFound; "actor does not exist: {}", to).into()),
+        };
+
+        // Find the transfer hooks matching this send.
+        let hooks: Vec<TransferHook> =
+            if self.in_transfer_hook || self.machine.context().transfer_hooks.is_empty() {
+                Vec::new()
+            } else {
+                let actor_type = self.builtin_actors().id_by_code(&state.code);
+                self.machine
+                    .context()
+                    .transfer_hooks
+                    .iter()
+                    .filter(|hook| hook.filter.matches(actor_type, method, value))
+                    .cloned()
+                    .collect()
+            };
+
+        for hook in hooks.iter().filter(|h| h.phase == HookPhase::Before) {
+            let res = self.call_transfer_hook::<K>(hook, from, to, method, value)?;
+            if !res.exit_code.is_success() {
+                return Ok(res);
+            }
+        }
+
+        let code = state.code;
+        let ret = self.with_actor_gas_accounting(code, method, |s| {
+            s.invoke_resolved::<K>(from, to, state, method, params, value)
+        })?;
+        if !ret.exit_code.is_success() {
+            return Ok(ret);
+        }
+
+        for hook in hooks.iter().filter(|h| h.phase == HookPhase::After) {
+            let res = self.call_transfer_hook::<K>(hook, from, to, method, value)?;
+            if !res.exit_code.is_success() {
+                return Ok(res);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    /// Invokes the given code on a resolved actor, in the context of its own state.
+    fn delegate_resolved<K>(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        code: Cid,
+        method: MethodNum,
+        params: Option<Block>,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult>
+    where
+        K: Kernel<CallManager = Self>,
+    {
+        if self.state_tree().get_actor(to)?.is_none() {
+            return Err(syscall_error!(NotFound; "actor does not exist: {}", to).into());
+        }
+
+        self.with_actor_gas_accounting(code, method, |s| {
+            // No value is transferred, so only the method invocation itself is charged.
+            let _ = s.charge_gas(
+                s.price_list()
+                    .on_method_invocation(&TokenAmount::zero(), method),
+            )?;
+            s.invoke_code::<K>(from, to, code, method, params, value)
+        })
+    }
+
+    /// Attributes the gas charged by `f`, minus the gas attributed to the calls it makes, to the
+    /// given actor code and method, if actor gas accounting is enabled.
+    fn with_actor_gas_accounting<F, V>(&mut self, code: Cid, method: MethodNum, f: F) -> Result<V>
+    where
+        F: FnOnce(&mut Self) -> Result<V>,
+    {
+        if !self.machine.context().actor_gas_accounting {
+            return f(self);
+        }
+
+        let start = self.gas_tracker.gas_used();
+        self.actor_gas_frames.push(Gas::zero());
+        let res = f(self);
+        let nested = self.actor_gas_frames.pop().unwrap_or_default();
+
+        let used = self.gas_tracker.gas_used() - start;
+        if let Some(parent) = self.actor_gas_frames.last_mut() {
+            *parent += used;
+        }
+        self.actor_gas.record(code, method, used - nested);
+        res
+    }
+
+    /// Invokes a transfer hook for a send. The caller is responsible for reverting the send if the
+    /// hook fails.
+    fn call_transfer_hook<K>(
+        &mut self,
+        hook: &TransferHook,
+        from: ActorID,
+        to: ActorID,
+        method: MethodNum,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult>
+    where
+        K: Kernel<CallManager = Self>,
+    {
+        let params = to_vec(&TransferHookParams {
+            from,
+            to,
+            method,
+            value: value.clone(),
+        })
+        .map_err(|e| {
+            ExecutionError::Fatal(anyhow!("failed to serialize transfer hook params: {}", e))
+        })?;
 
+        self.in_transfer_hook = true;
+        let res = self.send::<K>(
+            system_actor::SYSTEM_ACTOR_ID,
+            Address::new_id(hook.actor),
+            hook.method,
+            Some(Block::new(DAG_CBOR, params)),
+            &TokenAmount::zero(),
+            None,
+        );
+        self.in_transfer_hook = false;
+        res
+    }
+
+    /// Invoke an actor, transferring the value first.
+    fn invoke_resolved<K>(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        state: ActorState,
+        method: MethodNum,
+        params: Option<Block>,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult>
+    where
+        K: Kernel<CallManager = Self>,
+    {
         // Charge the method gas. Not sure why this comes second, but it does.
         let _ = self.charge_gas(self.price_list().on_method_invocation(value, method))?;
 
         // Transfer, if necessary.
         if !value.is_zero() {
-            self.machine.transfer(from, to, value)?;
+            self.transfer(from, to, value)?;
         }
 
         // Abort early if we have a send.
@@ -502,8 +827,25 @@ where
             return Ok(InvocationResult::default());
         }
 
+        self.invoke_code::<K>(from, to, state.code, method, params, value)
+    }
+
+    /// Invoke the given code as the actor `to`, without transferring any value.
+    fn invoke_code<K>(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        code: Cid,
+        method: MethodNum,
+        params: Option<Block>,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult>
+    where
+        K: Kernel<CallManager = Self>,
+    {
         // Store the parametrs, and initialize the block registry for the target actor.
-        let mut block_registry = BlockRegistry::new();
+        let mut block_registry =
+            BlockRegistry::new(self.machine.context().network.max_block_handles);
         let params_id = if let Some(blk) = params {
             block_registry.put(blk)?
         } else {
@@ -518,89 +860,46 @@ where
         // listed the manifest, and therefore preloaded during system initialization.
         #[cfg(feature = "m2-native")]
         self.engine
-            .prepare_actor_code(&state.code, self.blockstore())
-            .map_err(
-                |_| syscall_error!(NotFound; "actor code cid does not exist {}", &state.code),
-            )?;
+            .prepare_actor_code(&code, self.blockstore())
+            .map_err(|_| syscall_error!(NotFound; "actor code cid does not exist {}", &code))?;
 
         log::trace!("calling {} -> {}::{}", from, to, method);
-        self.map_mut(|cm| {
+        let audit_log = self.machine.context().audit_log.clone();
+        if let Some(log) = &audit_log {
+            log.enter_frame(self.invocation_count, to);
+        }
+        self.flags.enter_call();
+        let ret = self.map_mut(|cm| {
             let engine = cm.engine.clone(); // reference the RC.
 
-            // Make the kernel.
-            let kernel = K::new(cm, block_registry, from, to, method, value.clone());
-
-            // Make a store.
-            let mut store = engine.new_store(kernel);
-
-            // From this point on, there are no more syscall errors, only aborts.
-            let result: std::result::Result<BlockId, Abort> = (|| {
-                use wasmtime_runtime::InstantiationError;
-                // Instantiate the module.
-                let instance = engine
-                    .get_instance(&mut store, &state.code)
-                    .and_then(|i| i.context("actor code not found"))
-                    .map_err(|e| match e.downcast::<InstantiationError>() {
-                        Ok(e) => match e {
-                            // This will be handled in validation.
-                            InstantiationError::Link(e) => Abort::Fatal(anyhow!(e)),
-                            // TODO: We may want a separate OOM exit code? However, normal ooms will usually exit with SYS_ILLEGAL_INSTRUCTION.
-                            InstantiationError::Resource(e) => Abort::Exit(
-                                ExitCode::SYS_ILLEGAL_INSTRUCTION,
-                                e.to_string(),
-                                NO_DATA_BLOCK_ID,
-                            ),
-                            // TODO: we probably shouldn't hit this unless we're running code? We
-                            // should check if we can "validate away" this case.
-                            InstantiationError::Trap(e) => Abort::Exit(
-                                ExitCode::SYS_ILLEGAL_INSTRUCTION,
-                                format!("actor initialization failed: {:?}", e),
-                                0,
-                            ),
-                            // TODO: Consider using the instance limit instead of an explicit stack depth?
-                            InstantiationError::Limit(limit) => Abort::Fatal(anyhow!(
-                                "did not expect to hit wasmtime instance limit: {}",
-                                limit
-                            )),
-                        },
-                        Err(e) => Abort::Fatal(e),
-                    })?;
-
-                // Resolve and store a reference to the exported memory.
-                let memory = instance
-                    .get_memory(&mut store, "memory")
-                    .context("actor has no memory export")
-                    .map_err(Abort::Fatal)?;
-
-                store.data_mut().memory = memory;
-
-                // Lookup the invoke method.
-                let invoke: wasmtime::TypedFunc<(u32,), u32> = instance
-                    .get_typed_func(&mut store, "invoke")
-                    // All actors will have an invoke method.
-                    .map_err(Abort::Fatal)?;
-
-                // Set the available gas.
-                update_gas_available(&mut store)?;
+            // Look for a native implementation of the actor, if enabled.
+            let native = if cm.machine.context().native_actors {
+                engine.native_actor::<K>(&code)
+            } else {
+                None
+            };
 
-                // Invoke it.
-                let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                    invoke.call(&mut store, (params_id,))
-                }))
-                .map_err(|panic| Abort::Fatal(anyhow!("panic within actor: {:?}", panic)))?;
+            let tracing = cm.machine.context().tracing;
+            // Extern-dependent syscalls are refused in read-only contexts from network version 19.
+            let refuse_extern = cm.state_tree().is_read_only()
+                && cm.machine.context().network_version >= NetworkVersion::V19;
 
-                // Charge for any remaining uncharged execution gas, returning an error if we run out.
-                charge_for_exec(&mut store)?;
+            // Make the kernel.
+            let kernel = K::new(cm, block_registry, from, to, method, value.clone());
 
-                // If the invocation failed due to running out of exec_units, we have already
-                // detected it and returned OutOfGas above. Any other invocation failure is returned
-                // here as an Abort
-                Ok(res?)
-            })();
+            let (result, last_error, mut cm, block_registry) = match native {
+                Some(actor) => Self::invoke_native(actor.as_ref(), kernel, params_id),
+                None => {
+                    Self::invoke_wasm(&engine, kernel, &code, params_id, tracing, refuse_extern)
+                }
+            };
 
-            let invocation_data = store.into_data();
-            let last_error = invocation_data.last_error;
-            let (mut cm, block_registry) = invocation_data.kernel.into_inner();
+            // Enforce the return size limits, whether the actor returned or aborted.
+            let ret_id = match &result {
+                Ok(id) | Err(Abort::Exit(_, _, id)) => *id,
+                _ => NO_DATA_BLOCK_ID,
+            };
+            let result = cm.charge_return(&block_registry, ret_id).and(result);
 
             // Resolve the return block's ID into an actual block, converting to an abort if it
             // doesn't exist.
@@ -693,7 +992,168 @@ where
             }
 
             (ret, cm)
-        })
+        });
+        // Roll the flags back along with the state if the invocation failed.
+        let revert = !matches!(&ret, Ok(v) if v.exit_code.is_success());
+        self.flags.exit_call(revert);
+        if let Some(log) = &audit_log {
+            log.exit_frame();
+        }
+        ret
+    }
+
+    /// Invokes the actor's Wasm code, returning the result, the last syscall error (if any), and
+    /// the call manager and block registry reclaimed from the kernel. Memory growth is only
+    /// recorded (and traced) when `tracing`. Extern-dependent syscalls fail when `refuse_extern`.
+    #[allow(clippy::type_complexity)]
+    fn invoke_wasm<K>(
+        engine: &Engine,
+        kernel: K,
+        code: &Cid,
+        params_id: BlockId,
+        tracing: bool,
+        refuse_extern: bool,
+    ) -> (
+        std::result::Result<BlockId, Abort>,
+        Option<Cause>,
+        Self,
+        BlockRegistry,
+    )
+    where
+        K: Kernel<CallManager = Self>,
+    {
+        // Make a store.
+        let mut store = engine.new_store(kernel);
+        store.data_mut().refuse_extern = refuse_extern;
+        if tracing {
+            store.data_mut().memory_growth = Some(Vec::new());
+        }
+
+        // From this point on, there are no more syscall errors, only aborts.
+        let result: std::result::Result<BlockId, Abort> = (|| {
+            use wasmtime_runtime::InstantiationError;
+            // Instantiate the module.
+            let instance = engine
+                .get_instance(&mut store, code)
+                .and_then(|i| i.context("actor code not found"))
+                .map_err(|e| match e.downcast::<InstantiationError>() {
+                    Ok(e) => match e {
+                        // This will be handled in validation.
+                        InstantiationError::Link(e) => Abort::Fatal(anyhow!(e)),
+                        // TODO: We may want a separate OOM exit code? However, normal ooms will usually exit with SYS_ILLEGAL_INSTRUCTION.
+                        InstantiationError::Resource(e) => Abort::Exit(
+                            ExitCode::SYS_ILLEGAL_INSTRUCTION,
+                            e.to_string(),
+                            NO_DATA_BLOCK_ID,
+                        ),
+                        // TODO: we probably shouldn't hit this unless we're running code? We
+                        // should check if we can "validate away" this case.
+                        InstantiationError::Trap(e) => Abort::Exit(
+                            ExitCode::SYS_ILLEGAL_INSTRUCTION,
+                            format!("actor initialization failed: {:?}", e),
+                            0,
+                        ),
+                        // TODO: Consider using the instance limit instead of an explicit stack depth?
+                        InstantiationError::Limit(limit) => Abort::Fatal(anyhow!(
+                            "did not expect to hit wasmtime instance limit: {}",
+                            limit
+                        )),
+                    },
+                    Err(e) => Abort::Fatal(e),
+                })?;
+
+            // Resolve and store a reference to the exported memory.
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .context("actor has no memory export")
+                .map_err(Abort::Fatal)?;
+
+            store.data_mut().memory = memory;
+
+            // Lookup the invoke method.
+            let invoke: wasmtime::TypedFunc<(u32,), u32> = instance
+                .get_typed_func(&mut store, "invoke")
+                // All actors will have an invoke method.
+                .map_err(Abort::Fatal)?;
+
+            // Set the available gas.
+            update_gas_available(&mut store)?;
+
+            // Invoke it.
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                invoke.call(&mut store, (params_id,))
+            }))
+            .map_err(|panic| Abort::Fatal(anyhow!("panic within actor: {:?}", panic)))?;
+
+            // Charge for any remaining uncharged execution gas, returning an error if we run out.
+            charge_for_exec(&mut store)?;
+
+            // If the invocation failed due to running out of exec_units, we have already
+            // detected it and returned OutOfGas above. Any other invocation failure is returned
+            // here as an Abort
+            //
+            // Charge for unwinding the actor's stack if it trapped. Explicit exits are smuggled
+            // through traps too, but they don't carry a trap code and are charged as syscalls.
+            if let Err(trap) = &res {
+                if trap.trap_code().is_some() {
+                    let charge = store.data().kernel.price_list().on_trap();
+                    let _ = store
+                        .data()
+                        .kernel
+                        .charge_gas(&charge.name, charge.compute_gas)
+                        .map_err(Abort::from_error_as_fatal)?;
+                }
+            }
+            res.map_err(|trap| abort_from_trap(engine, code, trap))
+        })();
+
+        // Only set if the engine counts instructions. This excludes sub-calls, which have their
+        // own stores.
+        let instructions = store.fuel_consumed();
+
+        let invocation_data = store.into_data();
+        let last_error = invocation_data.last_error;
+        let (mut cm, block_registry) = invocation_data.kernel.into_inner();
+
+        if let Some(growth) = invocation_data.memory_growth {
+            for (from, to) in growth {
+                cm.trace(ExecutionEvent::MemoryGrowth {
+                    from: from as u64,
+                    to: to as u64,
+                });
+            }
+        }
+        if cm.machine.context().tracing {
+            if let Some(count) = instructions {
+                cm.trace(ExecutionEvent::InstructionCount(count));
+            }
+        }
+
+        (result, last_error, cm, block_registry)
+    }
+
+    /// Invokes a native actor implementation with the kernel, like [`Self::invoke_wasm`].
+    #[allow(clippy::type_complexity)]
+    fn invoke_native<K>(
+        actor: &dyn NativeActor<K>,
+        mut kernel: K,
+        params_id: BlockId,
+    ) -> (
+        std::result::Result<BlockId, Abort>,
+        Option<Cause>,
+        Self,
+        BlockRegistry,
+    )
+    where
+        K: Kernel<CallManager = Self>,
+    {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            actor.invoke(&mut kernel, params_id)
+        }))
+        .map_err(|panic| Abort::Fatal(anyhow!("panic within native actor: {:?}", panic)))
+        .and_then(|res| res.map_err(Abort::from));
+        let (cm, block_registry) = kernel.into_inner();
+        (result, None, cm, block_registry)
     }
 
     /// Temporarily replace `self` with a version that contains `None` for the inner part,
@@ -706,6 +1166,59 @@ where
         replace_with::replace_with_and_return(self, || DefaultCallManager(None), f)
     }
 
+    /// Handle a send to a deleted actor according to the configured [`TombstonePolicy`].
+    fn send_to_tombstone(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        method: MethodNum,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult> {
+        match self.machine.context().tombstone_policy {
+            TombstonePolicy::Burn if method == METHOD_SEND => {
+                let _ = self.charge_gas(self.price_list().on_method_invocation(value, method))?;
+                if !value.is_zero() {
+                    self.transfer(from, BURNT_FUNDS_ACTOR_ID, value)?;
+                }
+                log::trace!(
+                    "burnt {} sent from {} to deleted actor {}",
+                    &value,
+                    from,
+                    to
+                );
+                Ok(InvocationResult::default())
+            }
+            _ => Err(syscall_error!(NotFound; "actor {} has been deleted", to).into()),
+        }
+    }
+
+    /// Transfer funds, replacing any "insufficient funds" error with one describing the sender's
+    /// balance, the attempted amount, and the call depth at which the transfer failed.
+    fn transfer(&mut self, from: ActorID, to: ActorID, value: &TokenAmount) -> Result<()> {
+        match self.machine.transfer(from, to, value) {
+            Err(ExecutionError::Syscall(e)) if e.1 == ErrorNumber::InsufficientFunds => {
+                let balance = self
+                    .state_tree()
+                    .get_actor(from)?
+                    .map(|act| act.balance)
+                    .unwrap_or_default();
+                let failure = TransferFailure {
+                    from,
+                    to,
+                    balance,
+                    amount: value.clone(),
+                    depth: self.call_stack_depth,
+                };
+                let err = syscall_error!(InsufficientFunds; "{}", failure);
+                if self.machine.context().tracing {
+                    self.trace(ExecutionEvent::TransferFailed(failure));
+                }
+                Err(err.into())
+            }
+            res => res,
+        }
+    }
+
     /// Check that we're not violating the call stack depth, then envelope a call
     /// with an increase/decrease of the depth to make sure none of them are missed.
     fn with_stack_frame<F, V>(&mut self, f: F) -> Result<V>
@@ -726,11 +1239,35 @@ where
             |s| s.limiter_mut(),
             f,
         );
+        if matches!(res, Err(ExecutionError::OutOfGas)) {
+            self.gas_tracker
+                .record_out_of_gas_depth(self.call_stack_depth);
+        }
         self.call_stack_depth -= 1;
         res
     }
 }
 
+/// Converts a trap (including an actor abort) into an [`Abort`]. If the trap carries a Wasm
+/// backtrace (only captured when debugging actors) and debug info was registered for the actor's
+/// code, the symbolized backtrace is appended to the abort message.
+fn abort_from_trap(engine: &Engine, code: &Cid, trap: wasmtime::Trap) -> Abort {
+    let backtrace = match (trap.trace(), engine.debug_info(code)) {
+        (Some(frames), Some(info)) if !frames.is_empty() => {
+            info.format_backtrace(frames.iter().map(|f| f.func_index()))
+        }
+        _ => return trap.into(),
+    };
+    match Abort::from(trap) {
+        Abort::Exit(exit_code, message, data) => Abort::Exit(
+            exit_code,
+            format!("{}\nwasm backtrace:\n{}", message, backtrace),
+            data,
+        ),
+        abort => abort,
+    }
+}
+
 /// Stores events in layers as they are emitted by actors. As the call stack progresses, when an
 /// actor exits normally, its events should be merged onto the previous layer (merge_last_layer).
 /// If an actor aborts, the last layer should be discarded (discard_last_layer). This will also
@@ -739,6 +1276,8 @@ where
 #[derive(Default)]
 pub struct EventsAccumulator {
     events: Vec<StampedEvent>,
+    /// The total payload size of the accumulated events.
+    bytes: usize,
     idxs: Vec<usize>,
     read_only_layers: u32,
 }
@@ -750,6 +1289,7 @@ impl EventsAccumulator {
 
     fn append_event(&mut self, evt: StampedEvent) {
         if !self.is_read_only() {
+            self.bytes += evt.event().payload_size();
             self.events.push(evt)
         }
     }
@@ -784,6 +1324,10 @@ impl EventsAccumulator {
                     "no index in the event accumulator when calling discard_last_layer"
                 ))
             })?;
+            self.bytes -= self.events[idx..]
+                .iter()
+                .map(|e| e.event().payload_size())
+                .sum::<usize>();
             self.events.truncate(idx);
         }
         Ok(())
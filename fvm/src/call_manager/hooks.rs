@@ -0,0 +1,103 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Transfer hooks: actors the call manager invokes before or after sends matching a filter.
+//!
+//! Hooks let a network enforce token semantics (e.g., FRC-46 style receiver hooks for system
+//! tokens such as data cap) at the VM layer, instead of trusting every token actor to do so. They
+//! are configured per network with [`NetworkConfig::transfer_hooks`](crate::machine::NetworkConfig::transfer_hooks).
+//!
+//! A hook is invoked by the system actor with [`TransferHookParams`] describing the send. Hooks
+//! run within the send's transaction, and are charged to the message like any other call:
+//!
+//! - If a [`HookPhase::Before`] hook exits with a non-zero exit code, the send isn't performed,
+//!   and fails with the hook's exit code and return value.
+//! - If a [`HookPhase::After`] hook exits with a non-zero exit code, the (successful) send is
+//!   reverted, and fails with the hook's exit code and return value.
+//!
+//! Sends made by hooks (directly or indirectly) don't trigger further hooks.
+
+use fvm_ipld_encoding::tuple::*;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::{ActorID, MethodNum};
+
+/// An actor method invoked before or after the sends matching a filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferHook {
+    /// The sends that trigger the hook.
+    pub filter: TransferFilter,
+    /// Whether the hook runs before or after the matching sends.
+    pub phase: HookPhase,
+    /// The actor to invoke.
+    pub actor: ActorID,
+    /// The method to invoke on the actor.
+    pub method: MethodNum,
+}
+
+/// When a [`TransferHook`] runs, relative to the send that triggered it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPhase {
+    /// Before any value is transferred. The hook may veto the send.
+    Before,
+    /// After the receiver returns successfully. The hook may revert the send.
+    After,
+}
+
+/// Selects the sends that trigger a [`TransferHook`]. A send must match all the specified criteria.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferFilter {
+    /// Only match sends invoking this method.
+    pub method: Option<MethodNum>,
+    /// Only match sends to actors of this builtin actor type (see
+    /// [`Manifest::id_by_code`](crate::machine::Manifest::id_by_code)).
+    pub actor_type: Option<u32>,
+    /// Only match sends that transfer a non-zero value.
+    pub with_value: bool,
+}
+
+impl TransferFilter {
+    /// Returns true if a send of `value` to an actor of type `actor_type`, invoking `method`,
+    /// matches the filter.
+    pub fn matches(&self, actor_type: u32, method: MethodNum, value: &TokenAmount) -> bool {
+        self.method.map_or(true, |m| m == method)
+            && self.actor_type.map_or(true, |t| t == actor_type)
+            && !(self.with_value && value.is_zero())
+    }
+}
+
+/// The parameters passed to a [`TransferHook`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct TransferHookParams {
+    /// The sender of the hooked send.
+    pub from: ActorID,
+    /// The receiver of the hooked send.
+    pub to: ActorID,
+    /// The method invoked by the hooked send.
+    pub method: MethodNum,
+    /// The value transferred by the hooked send.
+    pub value: TokenAmount,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter() {
+        let one = TokenAmount::from_atto(1);
+        let zero = TokenAmount::default();
+
+        let any = TransferFilter::default();
+        assert!(any.matches(0, 0, &zero));
+        assert!(any.matches(7, 42, &one));
+
+        let filter = TransferFilter {
+            method: Some(42),
+            actor_type: Some(7),
+            with_value: true,
+        };
+        assert!(filter.matches(7, 42, &one));
+        assert!(!filter.matches(7, 42, &zero));
+        assert!(!filter.matches(7, 41, &one));
+        assert!(!filter.matches(6, 42, &one));
+    }
+}
@@ -7,8 +7,11 @@ use fvm_shared::error::ExitCode;
 use fvm_shared::{ActorID, MethodNum};
 
 use crate::engine::Engine;
-use crate::gas::{Gas, GasCharge, GasTimer, GasTracker, PriceList};
-use crate::kernel::{self, Result};
+use crate::gas::{ActorGasUsage, Gas, GasCharge, GasTimer, GasTracker, OutOfGasInfo, PriceList};
+use crate::kernel::{
+    self, DevRandomness, FlagRegistry, HasherRegistry, LinkTracker, RandomnessCache, Result,
+    SyscallMemo,
+};
 use crate::machine::{Machine, MachineContext};
 use crate::state_tree::StateTree;
 use crate::Kernel;
@@ -16,12 +19,15 @@ use crate::Kernel;
 pub mod backtrace;
 pub use backtrace::Backtrace;
 
+pub mod hooks;
+pub use hooks::{HookPhase, TransferFilter, TransferHook, TransferHookParams};
+
 mod default;
 
 pub use default::DefaultCallManager;
 use fvm_shared::event::StampedEvent;
 
-use crate::trace::ExecutionTrace;
+use crate::trace::{ExecutionEvent, ExecutionTrace};
 
 /// BlockID representing nil parameters or return data.
 pub const NO_DATA_BLOCK_ID: u32 = 0;
@@ -45,11 +51,12 @@ pub trait CallManager: 'static {
     /// The underlying [`Machine`] on top of which this [`CallManager`] executes.
     type Machine: Machine;
 
-    /// Construct a new call manager.
+    /// Construct a new call manager. If `gas_limit` is `None`, execution isn't limited by gas
+    /// (but gas used is still accounted for).
     fn new(
         machine: Self::Machine,
         engine: Engine,
-        gas_limit: i64,
+        gas_limit: Option<i64>,
         origin: ActorID,
         origin_address: Address,
         nonce: u64,
@@ -68,6 +75,21 @@ pub trait CallManager: 'static {
         gas_limit: Option<Gas>,
     ) -> Result<InvocationResult>;
 
+    /// Invoke a method of the given code on the actor `to`, in the context of its own state,
+    /// without transferring any value. The caller (`from`) and value received (`value`) are those
+    /// of the delegating call. Callers are responsible for checking that delegation is allowed.
+    #[allow(clippy::too_many_arguments)]
+    fn delegate_call<K: Kernel<CallManager = Self>>(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        code: Cid,
+        method: MethodNum,
+        params: Option<kernel::Block>,
+        value: &TokenAmount,
+        gas_limit: Option<Gas>,
+    ) -> Result<InvocationResult>;
+
     /// Execute some operation (usually a send) within a transaction.
     fn with_transaction(
         &mut self,
@@ -156,6 +178,37 @@ pub trait CallManager: 'static {
 
     /// Appends an event to the event accumulator.
     fn append_event(&mut self, evt: StampedEvent);
+
+    /// Appends an event to the execution trace, after any pending gas charges. Callers should only
+    /// record events when tracing is enabled.
+    fn trace(&mut self, event: ExecutionEvent);
+
+    /// Returns the total payload size of the events emitted so far by this message (excluding
+    /// events discarded by aborted calls).
+    fn event_bytes(&self) -> usize;
+
+    /// Returns the cache of randomness fetched during this message execution.
+    fn randomness_cache(&self) -> &RandomnessCache;
+
+    /// Returns the source of dev randomness for this message execution, if dev randomness is
+    /// enabled.
+    fn dev_randomness(&self) -> Option<&DevRandomness>;
+
+    /// Returns the memo of pure syscall results computed during this message execution.
+    fn syscall_memo(&self) -> &SyscallMemo;
+
+    /// Returns the statistics of the state graphs linked during this message execution.
+    fn link_tracker(&self) -> &LinkTracker;
+
+    /// Returns a mutable reference to the statistics of the state graphs linked during this
+    /// message execution, to record new statistics.
+    fn link_tracker_mut(&mut self) -> &mut LinkTracker;
+
+    /// Returns the incremental hashers opened during this message execution.
+    fn hashers(&self) -> &HasherRegistry;
+
+    /// Returns the flags set by actors during this message execution.
+    fn flags(&self) -> &FlagRegistry;
 }
 
 /// The result of a method invocation.
@@ -182,4 +235,8 @@ pub struct FinishRet {
     pub backtrace: Backtrace,
     pub exec_trace: ExecutionTrace,
     pub events: Vec<StampedEvent>,
+    pub out_of_gas: Option<OutOfGasInfo>,
+    /// Gas usage by actor code and method, if enabled with
+    /// [`MachineContext::actor_gas_accounting`].
+    pub actor_gas: ActorGasUsage,
 }
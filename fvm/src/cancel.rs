@@ -0,0 +1,83 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Cooperative cancellation of long-running operations, e.g., on node shutdown or reorg.
+//!
+//! A [`CancellationToken`] is shared between the node and the operations it may want to abort.
+//! Operations only check the token at well-defined points, so they're always interrupted in a
+//! consistent state, and fail with a [`Cancelled`] error reporting the progress made so far:
+//!
+//! - The [`DefaultExecutor`](crate::executor::DefaultExecutor) checks the token installed with
+//!   [`MachineContext::set_cancellation_token`](crate::machine::MachineContext::set_cancellation_token)
+//!   before applying each message and at every syscall. A message cancelled mid-execution is
+//!   rolled back, leaving the state as of the previous message, and the number of messages applied
+//!   is reported as the [`ExecutionProgress`](crate::executor::ExecutionProgress).
+//! - [`run_migration`](crate::migration::run_migration) checks it before migrating each actor, and
+//!   reports the last [`MigrationCheckpoint`](crate::migration::MigrationCheckpoint).
+//! - [`StateTree::export_car_cancellable`](crate::state_tree::StateTree::export_car_cancellable)
+//!   checks it before writing each block, and reports the
+//!   [`ExportProgress`](crate::state_tree::ExportProgress) so far.
+//!
+//! Cancellation doesn't interrupt actor code between syscalls.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag for cancelling operations, shared by all of its clones.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token, not yet cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels all operations checking this token (or any of its clones).
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release)
+    }
+
+    /// Returns true if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Fails with a [`Cancelled`] error carrying `progress` if the token has been cancelled.
+    pub fn check<P>(&self, progress: impl FnOnce() -> P) -> Result<(), Cancelled<P>> {
+        if self.is_cancelled() {
+            Err(Cancelled {
+                progress: progress(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The error returned by an operation interrupted by a [`CancellationToken`], with the progress
+/// it made before being cancelled (if any). Use [`anyhow::Error::downcast_ref`] to recover it from
+/// an operation's error.
+#[derive(Debug, Clone, Default, PartialEq, Eq, thiserror::Error)]
+#[error("operation cancelled")]
+pub struct Cancelled<P = ()> {
+    pub progress: P,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        token.check(|| unreachable!()).unwrap();
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check(|| 42), Err(Cancelled { progress: 42 }));
+
+        let err: anyhow::Error = token.check(|| ()).unwrap_err().into();
+        assert!(err.downcast_ref::<Cancelled>().is_some());
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! This module contains the types and functions to process the cron actor's state.
+//! It does not contain the logic of the cron actor: that lives on-chain as a WASM actor.
+//!
+//! The cron actor is invoked by the system actor at the end of every epoch (`EpochTick`), and in
+//! turn invokes each of its registered entries, in registration order. Entries are set at genesis
+//! or by network upgrades, through this module, or by builtin actors through
+//! [`CronOps`](crate::kernel::CronOps). User actors can't register entries, since cron invocations
+//! aren't paid for by any message.
+
+#[cfg(feature = "testing")]
+use anyhow::Context;
+#[cfg(feature = "testing")]
+use cid::multihash::Code::Blake2b256;
+#[cfg(feature = "testing")]
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+#[cfg(feature = "testing")]
+use fvm_ipld_encoding::CborStore;
+use fvm_shared::address::Address;
+use fvm_shared::{ActorID, MethodNum, METHOD_CONSTRUCTOR, METHOD_SEND};
+
+#[cfg(feature = "testing")]
+use crate::kernel::ClassifyResult;
+use crate::kernel::Result;
+pub use crate::machine::CRON_ACTOR_ID;
+#[cfg(feature = "testing")]
+use crate::state_tree::{ActorState, StateTree};
+use crate::syscall_error;
+
+/// The cron actor's `EpochTick` method, invoked by the system actor at the end of every epoch.
+pub const EPOCH_TICK_METHOD: MethodNum = 2;
+
+/// An actor method invoked by cron on every epoch.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// The actor to invoke. This is always an ID address.
+    pub receiver: Address,
+    /// The method to invoke, with no parameters.
+    pub method_num: MethodNum,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, Default)]
+pub struct State {
+    pub entries: Vec<Entry>,
+}
+
+impl State {
+    /// Loads the cron actor state from the supplied state tree.
+    #[cfg(feature = "testing")]
+    pub fn load<B>(state_tree: &StateTree<B>) -> Result<(Self, ActorState)>
+    where
+        B: Blockstore,
+    {
+        let cron_act = state_tree
+            .get_actor(CRON_ACTOR_ID)?
+            .context("cron actor address could not be resolved")
+            .or_fatal()?;
+
+        let state = state_tree
+            .store()
+            .get_cbor(&cron_act.state)
+            .or_fatal()?
+            .context("cron actor state not found")
+            .or_fatal()?;
+
+        Ok((state, cron_act))
+    }
+
+    /// Writes the state back to the cron actor in the supplied state tree.
+    #[cfg(feature = "testing")]
+    pub fn save<B>(&self, state_tree: &mut StateTree<B>) -> Result<()>
+    where
+        B: Blockstore,
+    {
+        let state = state_tree
+            .store()
+            .put_cbor(self, Blake2b256)
+            .context("failed to store cron actor state")
+            .or_fatal()?;
+        state_tree.mutate_actor(CRON_ACTOR_ID, |actor| {
+            actor.state = state;
+            Ok(())
+        })
+    }
+
+    /// Registers `method_num` on the actor `receiver` to be invoked on every epoch.
+    ///
+    /// Cron invokes entries without parameters or value, so the method can't be a plain send or a
+    /// constructor. Each method may only be registered once per actor, and cron can't invoke
+    /// itself.
+    pub fn register(&mut self, receiver: ActorID, method_num: MethodNum) -> Result<()> {
+        if method_num == METHOD_SEND || method_num == METHOD_CONSTRUCTOR {
+            return Err(
+                syscall_error!(IllegalArgument; "cron can't invoke method {}", method_num).into(),
+            );
+        }
+        if receiver == CRON_ACTOR_ID {
+            return Err(syscall_error!(IllegalArgument; "cron can't invoke itself").into());
+        }
+        let entry = Entry {
+            receiver: Address::new_id(receiver),
+            method_num,
+        };
+        if self.entries.contains(&entry) {
+            return Err(syscall_error!(IllegalArgument;
+                "method {} of actor {} is already registered with cron", method_num, receiver)
+            .into());
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Removes the entry invoking `method_num` on the actor `receiver`.
+    pub fn unregister(&mut self, receiver: ActorID, method_num: MethodNum) -> Result<()> {
+        let addr = Address::new_id(receiver);
+        let len = self.entries.len();
+        self.entries
+            .retain(|e| e.receiver != addr || e.method_num != method_num);
+        if self.entries.len() == len {
+            return Err(syscall_error!(NotFound;
+                "method {} of actor {} isn't registered with cron", method_num, receiver)
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+    use crate::EMPTY_ARR_CID;
+
+    #[test]
+    fn register_and_unregister() {
+        let mut state = State::default();
+        state.register(100, 2).unwrap();
+        state.register(101, 2).unwrap();
+        state.register(100, 3).unwrap();
+
+        // Invalid and duplicate entries are rejected.
+        state.register(100, METHOD_SEND).unwrap_err();
+        state.register(100, METHOD_CONSTRUCTOR).unwrap_err();
+        state.register(CRON_ACTOR_ID, 2).unwrap_err();
+        state.register(100, 2).unwrap_err();
+
+        state.unregister(100, 2).unwrap();
+        state.unregister(100, 2).unwrap_err();
+        assert_eq!(
+            state.entries,
+            vec![
+                Entry {
+                    receiver: Address::new_id(101),
+                    method_num: 2
+                },
+                Entry {
+                    receiver: Address::new_id(100),
+                    method_num: 3
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn load_and_save() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        let state = store.put_cbor(&State::default(), Blake2b256).unwrap();
+        let actor = ActorState::new(*EMPTY_ARR_CID, state, Default::default(), 0, None);
+        tree.set_actor(CRON_ACTOR_ID, actor).unwrap();
+
+        let (mut state, _) = State::load(&tree).unwrap();
+        state.register(100, 2).unwrap();
+        state.save(&mut tree).unwrap();
+
+        let (state, _) = State::load(&tree).unwrap();
+        assert_eq!(state.entries.len(), 1);
+    }
+}
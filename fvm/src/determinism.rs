@@ -0,0 +1,91 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Hash maps and sets for consensus-critical state: the state tree's caches, the kernel's
+//! per-message tables (flags, syscall memos, randomness, link statistics), and the subsidy quotas.
+//!
+//! Iterating over a hash map visits its entries in an arbitrary order, which differs between maps
+//! and between runs. Execution results must never depend on it: consensus-critical code only looks
+//! entries up, or sorts them (or uses a `BTreeMap`) before iterating. Tables indexed by handle,
+//! like the kernel's block registry and the call manager's state, are vectors, so they're
+//! deterministic by construction.
+//!
+//! To check this, the maps and sets defined here can hash their keys with a fixed seed instead of
+//! a random one. With the `determinism-audit` feature (and in this crate's tests), every map hashes
+//! its keys with the seed set by [`set_hasher_seed`] when the map was created, so running the same
+//! messages under different seeds visits the entries in different orders, and must produce the same
+//! results. Otherwise, they're randomly seeded like any `HashMap`.
+
+use std::collections::{HashMap, HashSet};
+
+#[cfg(any(test, feature = "determinism-audit"))]
+pub use self::seeded::{set_hasher_seed, HasherState};
+
+/// A hash map for consensus-critical state. Create it with `Map::default()`.
+pub type Map<K, V> = HashMap<K, V, HasherState>;
+
+/// A hash set for consensus-critical state. Create it with `Set::default()`.
+pub type Set<T> = HashSet<T, HasherState>;
+
+/// The hasher of [`Map`] and [`Set`]: randomly seeded, unless auditing.
+#[cfg(not(any(test, feature = "determinism-audit")))]
+pub type HasherState = std::collections::hash_map::RandomState;
+
+#[cfg(any(test, feature = "determinism-audit"))]
+mod seeded {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static SEED: AtomicU64 = AtomicU64::new(0);
+
+    /// Sets the seed of the maps and sets created from now on, by all threads.
+    pub fn set_hasher_seed(seed: u64) {
+        SEED.store(seed, Ordering::Relaxed)
+    }
+
+    /// The hasher of [`Map`](super::Map) and [`Set`](super::Set): seeded with the seed set by
+    /// [`set_hasher_seed`] when it was created.
+    #[derive(Debug, Clone)]
+    pub struct HasherState(u64);
+
+    impl HasherState {
+        /// Returns a hasher with the given seed, regardless of [`set_hasher_seed`].
+        pub fn with_seed(seed: u64) -> Self {
+            HasherState(seed)
+        }
+    }
+
+    impl Default for HasherState {
+        fn default() -> Self {
+            HasherState(SEED.load(Ordering::Relaxed))
+        }
+    }
+
+    impl BuildHasher for HasherState {
+        type Hasher = DefaultHasher;
+
+        fn build_hasher(&self) -> DefaultHasher {
+            let mut hasher = DefaultHasher::new();
+            hasher.write_u64(self.0);
+            hasher
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(seed: u64) -> Vec<u64> {
+        let mut set = Set::with_hasher(HasherState::with_seed(seed));
+        set.extend(0..64);
+        set.into_iter().collect()
+    }
+
+    #[test]
+    fn seeded_order() {
+        // The order only depends on the seed.
+        assert_eq!(order(1), order(1));
+        assert_ne!(order(1), order(2));
+    }
+}
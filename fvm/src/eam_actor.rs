@@ -1,5 +1,3 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-use fvm_shared::ActorID;
-
-pub const EAM_ACTOR_ID: ActorID = 10;
+pub use fvm_shared::message::eth::EAM_ACTOR_ID;
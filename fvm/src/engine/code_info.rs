@@ -0,0 +1,85 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Metadata about actor code, as reported to actors by
+//! [`ActorOps::get_code_info`](crate::kernel::ActorOps::get_code_info).
+
+use anyhow::Context;
+use wasmparser::{ExternalKind, Parser, Payload};
+
+/// The version of the ABI implemented by this FVM.
+pub const CURRENT_ABI_VERSION: u32 = 1;
+
+/// The largest actor code whose metadata will be read, in bytes.
+pub const MAX_CODE_SIZE: usize = 16 << 20;
+
+/// Metadata about some actor code.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CodeInfo {
+    /// The size of the code, in bytes.
+    pub size: usize,
+    /// The ABI version the code targets: [`CURRENT_ABI_VERSION`] if it exports an `invoke`
+    /// function, or 0 if it's not an actor.
+    pub abi_version: u32,
+}
+
+impl CodeInfo {
+    /// Reads the metadata of the given actor code. Fails if the code isn't a Wasm module.
+    pub fn from_wasm(wasm: &[u8]) -> anyhow::Result<Self> {
+        let mut exports_invoke = false;
+        for payload in Parser::new(0).parse_all(wasm) {
+            if let Payload::ExportSection(exports) =
+                payload.context("failed to parse actor wasm")?
+            {
+                for export in exports {
+                    let export = export?;
+                    exports_invoke |= export.kind == ExternalKind::Func && export.name == "invoke";
+                }
+            }
+        }
+
+        Ok(CodeInfo {
+            size: wasm.len(),
+            abi_version: if exports_invoke {
+                CURRENT_ABI_VERSION
+            } else {
+                0
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+    /// A module defining an `invoke` function, without a body.
+    const INVOKE: &[u8] = &[
+        0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // types
+        0x03, 0x02, 0x01, 0x00, // functions
+        0x07, 0x0a, 0x01, 0x06, b'i', b'n', b'v', b'o', b'k', b'e', 0x00, 0x00, // export
+    ];
+
+    #[test]
+    fn code_info() {
+        let wasm = [HEADER, INVOKE].concat();
+        assert_eq!(
+            CodeInfo::from_wasm(&wasm).unwrap(),
+            CodeInfo {
+                size: wasm.len(),
+                abi_version: CURRENT_ABI_VERSION,
+            }
+        );
+
+        // Not an actor.
+        assert_eq!(
+            CodeInfo::from_wasm(HEADER).unwrap(),
+            CodeInfo {
+                size: HEADER.len(),
+                abi_version: 0,
+            }
+        );
+        assert!(CodeInfo::from_wasm(b"not wasm").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! A sidecar format for actor debug information.
+//!
+//! Actor code deployed on chain is usually stripped of its debug information to keep it small.
+//! Instead, the debug information of some actor code can be kept in a separate [`DebugInfo`] file
+//! (the "sidecar"), keyed by the code's CID, and registered with the engine with
+//! [`EnginePool::register_debug_info`](super::EnginePool::register_debug_info). When
+//! [`NetworkConfig::actor_debugging`](crate::machine::NetworkConfig::actor_debugging) is enabled,
+//! the FVM uses it to symbolize the Wasm backtraces of traps and aborts. Profilers can use it to
+//! symbolize their output the same way.
+//!
+//! The sidecar maps Wasm function indices to function names. The FVM instruments actor code
+//! before executing it, which invalidates instruction offsets, but preserves the indices of the
+//! functions defined by the actor.
+//!
+//! The sidecar is encoded as DAG-CBOR (see [`DebugInfo::to_bytes`]).
+
+use anyhow::{anyhow, Context};
+use cid::Cid;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{from_slice, to_vec};
+use wasmparser::{Name, NameSectionReader, Parser, Payload};
+
+/// The version of the debug information format.
+pub const DEBUG_INFO_VERSION: u64 = 1;
+
+/// The debug information of some actor code.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// The version of the format, [`DEBUG_INFO_VERSION`].
+    pub version: u64,
+    /// The CID of the (stripped) code this debug information describes.
+    pub code: Cid,
+    /// The functions with debug information, sorted by index.
+    pub functions: Vec<FunctionInfo>,
+}
+
+/// The debug information of a Wasm function.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct FunctionInfo {
+    /// The index of the function in the module (including imported functions).
+    pub index: u32,
+    /// The (demangled) name of the function.
+    pub name: String,
+}
+
+impl DebugInfo {
+    /// Creates the debug information of the code with the given CID from the function names in
+    /// the name section of an unstripped build of the code.
+    pub fn from_wasm(code: Cid, wasm: &[u8]) -> anyhow::Result<Self> {
+        let mut functions = Vec::new();
+        for payload in Parser::new(0).parse_all(wasm) {
+            let section = match payload.context("failed to parse actor wasm")? {
+                Payload::CustomSection(section) if section.name() == "name" => section,
+                _ => continue,
+            };
+            let mut reader = NameSectionReader::new(section.data(), section.data_offset())?;
+            while !reader.eof() {
+                if let Name::Function(names) = reader.read()? {
+                    for naming in names {
+                        let naming = naming?;
+                        functions.push(FunctionInfo {
+                            index: naming.index,
+                            name: naming.name.to_owned(),
+                        });
+                    }
+                }
+            }
+        }
+        functions.sort_by_key(|f| f.index);
+        functions.dedup_by_key(|f| f.index);
+        Ok(DebugInfo {
+            version: DEBUG_INFO_VERSION,
+            code,
+            functions,
+        })
+    }
+
+    /// Decodes a debug information sidecar, checking its version and that its functions are
+    /// sorted by index.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let info: DebugInfo = from_slice(bytes).context("failed to decode debug info")?;
+        if info.version != DEBUG_INFO_VERSION {
+            return Err(anyhow!(
+                "unsupported debug info version {} (expected {})",
+                info.version,
+                DEBUG_INFO_VERSION
+            ));
+        }
+        if info.functions.windows(2).any(|w| w[0].index >= w[1].index) {
+            return Err(anyhow!("debug info functions aren't sorted by index"));
+        }
+        Ok(info)
+    }
+
+    /// Encodes the debug information as a sidecar.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(to_vec(self)?)
+    }
+
+    /// Returns the debug information of the function with the given index, if any.
+    pub fn function(&self, index: u32) -> Option<&FunctionInfo> {
+        self.functions
+            .binary_search_by_key(&index, |f| f.index)
+            .ok()
+            .map(|i| &self.functions[i])
+    }
+
+    /// Describes the function with the given index, by name if known.
+    pub fn symbolize(&self, index: u32) -> String {
+        match self.function(index) {
+            Some(info) => info.name.clone(),
+            None => format!("<wasm function {}>", index),
+        }
+    }
+
+    /// Formats a Wasm backtrace, given as function indices from the innermost frame outwards.
+    pub fn format_backtrace(&self, frames: impl IntoIterator<Item = u32>) -> String {
+        frames
+            .into_iter()
+            .enumerate()
+            .map(|(i, index)| format!("{:02}: {}\n", i, self.symbolize(index)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use multihash::{Code, MultihashDigest};
+
+    use super::*;
+
+    // A module defining two functions, named "foo" and "bar" in its name section.
+    const WASM: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // types
+        0x03, 0x03, 0x02, 0x00, 0x00, // functions
+        0x0a, 0x07, 0x02, 0x02, 0x00, 0x0b, 0x02, 0x00, 0x0b, // code
+        0x00, 0x12, 0x04, b'n', b'a', b'm', b'e', // name section
+        0x01, 0x0b, 0x02, 0x00, 0x03, b'f', b'o', b'o', 0x01, 0x03, b'b', b'a', b'r',
+    ];
+
+    #[test]
+    fn from_wasm() {
+        let code = Cid::new_v1(0x55, Code::Blake2b256.digest(WASM));
+        let mut info = DebugInfo::from_wasm(code, WASM).unwrap();
+        assert_eq!(info.code, code);
+        assert_eq!(info.functions.len(), 2);
+        assert_eq!(info.symbolize(0), "foo");
+        assert_eq!(info.symbolize(2), "<wasm function 2>");
+
+        assert_eq!(info.format_backtrace([1, 0]), "00: bar\n01: foo\n");
+
+        let bytes = info.to_bytes().unwrap();
+        assert_eq!(DebugInfo::from_bytes(&bytes).unwrap(), info);
+
+        info.functions.reverse();
+        assert!(DebugInfo::from_bytes(&info.to_bytes().unwrap()).is_err());
+    }
+}
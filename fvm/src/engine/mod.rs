@@ -0,0 +1,1323 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::any::{Any, TypeId};
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex};
+
+use anyhow::{anyhow, Context};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::version::NetworkVersion;
+use fvm_wasm_instrument::gas_metering::GAS_COUNTER_NAME;
+use wasmtime::OptLevel::Speed;
+use wasmtime::{
+    ExternType, Global, GlobalType, InstanceAllocationStrategy, InstanceLimits, Linker, Memory,
+    MemoryType, Module, Mutability, PoolingAllocationStrategy, ProfilingStrategy, Val, ValType,
+};
+
+use crate::gas::{GasTimer, WasmGasPrices};
+use crate::machine::limiter::MemoryLimiter;
+use crate::machine::{Machine, NetworkConfig};
+use crate::native::NativeActor;
+use crate::syscalls::custom::{bind_custom_syscalls, check_module, is_custom_syscall};
+use crate::syscalls::wasi::{bind_wasi, is_wasi_function};
+use crate::syscalls::{
+    bind_dev_syscalls, bind_syscalls, charge_for_init, is_dev_syscall, is_syscall,
+    record_init_time, InvocationData, SyscallModule,
+};
+use crate::Kernel;
+
+pub mod code_info;
+pub mod debug_info;
+
+pub use code_info::CodeInfo;
+pub use debug_info::DebugInfo;
+
+/// Container managing engines with different consensus-affecting configurations.
+///
+/// Engines whose configurations only differ in settings that don't affect how wasmtime compiles
+/// and runs code (e.g., the network version, or the gas prices) share a wasmtime engine, and its
+/// compiled modules. Actor code that's byte-identical across actor bundles (or instruments to
+/// identical code under different configurations) is only compiled, and kept in memory, once (see
+/// [`EnginePool`]). Each pool keeps its own concurrency limit, so a shared wasmtime engine reserves
+/// instances for [`MAX_SHARED_POOLS`] pools; further pools get a new wasmtime engine.
+pub struct MultiEngine {
+    engines: Mutex<HashMap<EngineConfig, EnginePool>>,
+    concurrency: u32,
+    /// The base wasmtime config for new engines, if provided by the embedder.
+    config: Option<wasmtime::Config>,
+}
+
+/// The proper way of getting this struct is to convert from `NetworkConfig`
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct EngineConfig {
+    /// The network version, which determines the available syscalls.
+    pub network_version: NetworkVersion,
+    pub max_call_depth: u32,
+    pub max_wasm_stack: u32,
+    pub max_inst_memory_bytes: u64,
+    pub concurrency: u32,
+    pub wasm_prices: &'static WasmGasPrices,
+    pub actor_redirect: Vec<(Cid, Cid)>,
+    pub memory_snapshots: bool,
+    pub instruction_counting: bool,
+    pub actor_debugging: bool,
+    pub dev_randomness: bool,
+    pub profiling: bool,
+}
+
+impl From<&NetworkConfig> for EngineConfig {
+    fn from(nc: &NetworkConfig) -> Self {
+        EngineConfig {
+            network_version: nc.network_version,
+            max_call_depth: nc.max_call_depth,
+            max_wasm_stack: nc.max_wasm_stack,
+            max_inst_memory_bytes: nc.max_inst_memory_bytes,
+            wasm_prices: &nc.price_list.wasm_rules,
+            actor_redirect: nc.actor_redirect.clone(),
+            memory_snapshots: nc.memory_snapshots,
+            instruction_counting: nc.instruction_counting,
+            actor_debugging: nc.actor_debugging,
+            dev_randomness: nc.dev_randomness,
+            profiling: nc.profiling,
+            concurrency: 1,
+        }
+    }
+}
+
+/// The maximum number of engine pools sharing a wasmtime engine in a [`MultiEngine`]. The shared
+/// engine's instance pool is sized for this many pools at their full concurrency, which mostly
+/// reserves address space: two pools cover the network versions around an upgrade without
+/// reserving more than two separate engines would.
+pub const MAX_SHARED_POOLS: u32 = 2;
+
+/// The settings of an [`EngineConfig`] that determine the wasmtime engine's configuration (see
+/// [`configure_engine`] and [`wasmtime_config`]). Engine pools with the same key can share a
+/// wasmtime engine.
+#[derive(Clone, Eq, PartialEq, Hash)]
+struct EngineKey {
+    max_call_depth: u32,
+    max_inst_memory_bytes: u64,
+    concurrency: u32,
+    memory_snapshots: bool,
+    instruction_counting: bool,
+    actor_debugging: bool,
+    profiling: bool,
+}
+
+impl From<&EngineConfig> for EngineKey {
+    fn from(ec: &EngineConfig) -> Self {
+        EngineKey {
+            max_call_depth: ec.max_call_depth,
+            max_inst_memory_bytes: ec.max_inst_memory_bytes,
+            concurrency: ec.concurrency,
+            memory_snapshots: ec.memory_snapshots,
+            instruction_counting: ec.instruction_counting,
+            actor_debugging: ec.actor_debugging,
+            profiling: ec.profiling,
+        }
+    }
+}
+
+impl MultiEngine {
+    pub fn new(concurrency: u32) -> MultiEngine {
+        if concurrency == 0 {
+            panic!("concurrency must be positive");
+        }
+        MultiEngine {
+            engines: Mutex::new(HashMap::new()),
+            concurrency,
+            config: None,
+        }
+    }
+
+    /// Like [`MultiEngine::new`], but creates engines from the given wasmtime config. See
+    /// [`EnginePool::with_config`].
+    pub fn with_config(concurrency: u32, config: wasmtime::Config) -> MultiEngine {
+        MultiEngine {
+            config: Some(config),
+            ..MultiEngine::new(concurrency)
+        }
+    }
+
+    pub fn get(&self, nc: &NetworkConfig) -> anyhow::Result<EnginePool> {
+        let mut engines = self
+            .engines
+            .lock()
+            .map_err(|_| anyhow::Error::msg("multiengine lock is poisoned"))?;
+
+        let mut ec: EngineConfig = nc.into();
+        ec.concurrency = self.concurrency;
+
+        if let Some(pool) = engines.get(&ec) {
+            return Ok(pool.clone());
+        }
+
+        // Share the wasmtime engine of another pool with the same key, unless it's already shared
+        // by as many pools as it has instances for.
+        let key = EngineKey::from(&ec);
+        let pools = |shared: &Arc<SharedEngine>| {
+            engines
+                .values()
+                .filter(|pool| Arc::ptr_eq(&pool.0.shared, shared))
+                .count()
+        };
+        let shared = engines
+            .iter()
+            .filter(|(other, _)| EngineKey::from(*other) == key)
+            .map(|(_, pool)| &pool.0.shared)
+            .find(|shared| pools(shared) < MAX_SHARED_POOLS as usize)
+            .cloned();
+        let shared = match shared {
+            Some(shared) => shared,
+            None => {
+                let mut engine_ec = ec.clone();
+                engine_ec.concurrency *= MAX_SHARED_POOLS;
+                let c = match &self.config {
+                    Some(c) => {
+                        let mut c = c.clone();
+                        configure_engine(&mut c, &engine_ec)?;
+                        c
+                    }
+                    None => wasmtime_config(&engine_ec)?,
+                };
+                SharedEngine::new(wasmtime::Engine::new(&c)?)
+            }
+        };
+        let pool = EnginePool::from_shared(shared, ec.clone());
+        engines.insert(ec, pool.clone());
+
+        Ok(pool)
+    }
+}
+
+impl Default for MultiEngine {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+fn wasmtime_config(ec: &EngineConfig) -> anyhow::Result<wasmtime::Config> {
+    let mut c = wasmtime::Config::default();
+
+    // Disable debug-related things, wasm-instrument doesn't fix debug info
+    // yet, so those aren't useful, just add overhead
+    c.debug_info(false);
+    c.generate_address_map(false);
+    c.cranelift_debug_verifier(false);
+    c.native_unwind_info(false);
+    // Wasm backtraces are only captured when debugging actors, to be symbolized with their
+    // debug info (see [`debug_info`]). Function indices survive instrumentation.
+    #[allow(deprecated)] // TODO https://github.com/bytecodealliance/wasmtime/issues/5037
+    c.wasm_backtrace(ec.actor_debugging);
+
+    // Reiterate some defaults
+    c.parallel_compilation(true);
+
+    // Doesn't seem to have significant impact on the time it takes to load code
+    // todo(M2): make sure this is guaranteed to run in linear time.
+    c.cranelift_opt_level(Speed);
+
+    configure_engine(&mut c, ec)?;
+    Ok(c)
+}
+
+/// Applies the settings the FVM depends on for deterministic execution under the given engine
+/// config, overriding any conflicting settings in `c`.
+///
+/// Settings that only affect how code is compiled or debugged (e.g., the compilation strategy,
+/// optimization level, parallel compilation, and the compilation cache) are left to the caller.
+/// See [`EnginePool::with_config`].
+pub fn configure_engine(c: &mut wasmtime::Config, ec: &EngineConfig) -> anyhow::Result<()> {
+    let instance_count = (1 + ec.max_call_depth) * ec.concurrency;
+    let instance_memory_maximum_size = ec.max_inst_memory_bytes;
+    if instance_memory_maximum_size % wasmtime_environ::WASM_PAGE_SIZE as u64 != 0 {
+        return Err(anyhow!(
+            "requested memory limit {} not a multiple of the WASM_PAGE_SIZE {}",
+            instance_memory_maximum_size,
+            wasmtime_environ::WASM_PAGE_SIZE
+        ));
+    }
+
+    // wasmtime default: OnDemand
+    // We want to pre-allocate all permissible memory to support the maximum allowed recursion limit.
+    //
+    // Wasm requires new and grown memory to be zeroed, and actors must never observe memory left
+    // behind by a previous instance: wasmtime resets a pooled memory slot (decommitting the pages
+    // it used, or restoring the memory image if `memory_init_cow` is enabled) whenever an instance
+    // is deallocated, on every platform. `validate_engine` checks this for externally configured
+    // engines.
+    c.allocation_strategy(InstanceAllocationStrategy::Pooling {
+        strategy: PoolingAllocationStrategy::ReuseAffinity,
+        instance_limits: InstanceLimits {
+            count: instance_count,
+            // Adjust the maximum amount of host memory that can be committed to an instance to
+            // match the static linear memory size we reserve for each slot.
+            memory_pages: instance_memory_maximum_size / (wasmtime_environ::WASM_PAGE_SIZE as u64),
+            ..Default::default()
+        },
+    });
+
+    // wasmtime default: true
+    // When enabled, wasmtime builds an image of each module's initial linear memory at compile
+    // time and maps it copy-on-write into every new instance (resetting the pooled slot on
+    // deallocation), instead of copying the data segments on each instantiation. We disable this
+    // by default as we always charge for memory regardless and `memory_init_cow` can baloon
+    // compiled wasm modules.
+    c.memory_init_cow(ec.memory_snapshots);
+
+    // wasmtime default: 4GB
+    c.static_memory_maximum_size(instance_memory_maximum_size);
+
+    // wasmtime default: false
+    // We don't want threads, there is no way to ensure determisism
+    c.wasm_threads(false);
+
+    // wasmtime default: true
+    // simd isn't supported in wasm-instrument, but if we add support there, we can probably enable this.
+    // Note: stack limits may need adjusting after this is enabled
+    c.wasm_simd(false);
+
+    // wasmtime default: false
+    c.wasm_multi_memory(false);
+
+    // wasmtime default: false
+    c.wasm_memory64(false);
+
+    // wasmtime default: true
+    // Note: wasm-instrument only supports this at a basic level, for M2 we will
+    // need to add more advanced support
+    c.wasm_bulk_memory(true);
+
+    // wasmtime default: true
+    // we should be able to enable this for M2, just need to make sure that it's
+    // handled correctly in wasm-instrument
+    c.wasm_multi_value(false);
+
+    // wasmtime default: true
+    c.wasm_reference_types(false);
+
+    // wasmtime default: false
+    //
+    // from wasmtime docs:
+    // > When Cranelift is used as a code generation backend this will
+    // > configure it to replace NaNs with a single canonical value. This
+    // > is useful for users requiring entirely deterministic WebAssembly
+    // > computation. This is not required by the WebAssembly spec, so it is
+    // > not enabled by default.
+    c.cranelift_nan_canonicalization(true);
+
+    // wasmtime default: 512KiB
+    // Set to something much higher than the instrumented limiter.
+    // Note: This is in bytes, while the instrumented limit is in stack elements
+    c.max_wasm_stack(4 << 20);
+
+    // Execution cost accouting is done through wasm instrumentation. Fuel is only used to count
+    // instructions, never to limit execution (see `Engine::new_store`).
+    c.consume_fuel(ec.instruction_counting);
+    c.epoch_interruption(false);
+
+    // Let `perf` symbolize compiled actor code (see `NetworkConfig::profiling`).
+    if ec.profiling {
+        c.profiler(ProfilingStrategy::JitDump);
+    }
+
+    // Reiterate some defaults
+    c.guard_before_linear_memory(true);
+
+    #[cfg(feature = "wasmtime/async")]
+    c.async_support(false);
+
+    Ok(())
+}
+
+/// Checks that an externally constructed engine was configured as [`configure_engine`] would for
+/// the given engine config, by compiling and running small probe modules.
+///
+/// This covers the enabled Wasm features, NaN canonicalization, the per-instance memory limit, and
+/// fuel consumption.
+fn validate_engine(engine: &wasmtime::Engine, ec: &EngineConfig) -> anyhow::Result<()> {
+    const HEADER: &[u8] = b"\0asm\x01\0\0\0";
+    let module = |sections: &[&[u8]]| -> Vec<u8> {
+        let mut wasm = HEADER.to_vec();
+        for s in sections {
+            wasm.extend_from_slice(s);
+        }
+        wasm
+    };
+
+    // Modules that must be rejected, each using a single disabled feature.
+    for (feature, section) in [
+        ("simd", &[0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7b][..]),
+        (
+            "reference types",
+            &[0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x6f],
+        ),
+        (
+            "multi-value",
+            &[0x01, 0x06, 0x01, 0x60, 0x00, 0x02, 0x7f, 0x7f],
+        ),
+        ("threads", &[0x05, 0x04, 0x01, 0x03, 0x01, 0x01]),
+        ("memory64", &[0x05, 0x03, 0x01, 0x04, 0x01]),
+        ("multi-memory", &[0x05, 0x05, 0x02, 0x00, 0x01, 0x00, 0x01]),
+    ] {
+        if Module::validate(engine, &module(&[section])).is_ok() {
+            return Err(anyhow!(
+                "engine must not enable the {} wasm feature",
+                feature
+            ));
+        }
+    }
+
+    // Bulk memory (a data count section) must be accepted.
+    Module::validate(engine, &module(&[&[0x0c, 0x01, 0x00]]))
+        .map_err(|e| anyhow!("engine must enable the bulk memory wasm feature: {}", e))?;
+
+    let mut store = wasmtime::Store::new(engine, ());
+
+    // Fuel must be enabled exactly when counting instructions. Adding fuel also lets the probes
+    // below run when it is.
+    if store.add_fuel(u64::MAX).is_ok() != ec.instruction_counting {
+        return Err(anyhow!(
+            "engine must {}consume fuel",
+            if ec.instruction_counting { "" } else { "not " }
+        ));
+    }
+
+    // Computes 0.0/0.0, which must produce the canonical NaN.
+    let nan = Module::new(
+        engine,
+        module(&[
+            &[0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f],
+            &[0x03, 0x02, 0x01, 0x00],
+            &[0x07, 0x07, 0x01, 0x03, b'n', b'a', b'n', 0x00, 0x00],
+            &[
+                0x0a, 0x10, 0x01, 0x0e, 0x00, 0x43, 0x00, 0x00, 0x00, 0x00, 0x43, 0x00, 0x00, 0x00,
+                0x00, 0x95, 0xbc, 0x0b,
+            ],
+        ]),
+    )?;
+    let nan = wasmtime::Instance::new(&mut store, &nan, &[])?
+        .get_typed_func::<(), i32, _>(&mut store, "nan")?
+        .call(&mut store, ())? as u32;
+    if nan != 0x7fc0_0000 {
+        return Err(anyhow!("engine must enable NaN canonicalization"));
+    }
+
+    // A memory one page larger than the instance memory limit must be rejected.
+    let mut pages = ec.max_inst_memory_bytes / wasmtime_environ::WASM_PAGE_SIZE as u64 + 1;
+    let mut memory = vec![0x01, 0x00];
+    loop {
+        let byte = (pages & 0x7f) as u8;
+        pages >>= 7;
+        if pages == 0 {
+            memory.push(byte);
+            break;
+        }
+        memory.push(byte | 0x80);
+    }
+    memory.insert(0, memory.len() as u8);
+    memory.insert(0, 0x05);
+    // The pooling allocator already rejects such modules when compiling them.
+    let rejected = match Module::new(engine, module(&[&memory])) {
+        Ok(too_large) => wasmtime::Instance::new(&mut store, &too_large, &[]).is_err(),
+        Err(_) => true,
+    };
+    if !rejected {
+        return Err(anyhow!(
+            "engine must limit instance memories to {} bytes",
+            ec.max_inst_memory_bytes
+        ));
+    }
+
+    // Instantiates a module exporting a one page memory, grows it, checks that all of it is zero,
+    // then dirties it. The second instance will likely reuse the first one's pooled slot.
+    let zeroed = Module::new(
+        engine,
+        module(&[
+            &[0x05, 0x03, 0x01, 0x00, 0x01],
+            &[
+                0x07, 0x0a, 0x01, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00,
+            ],
+        ]),
+    )?;
+    for _ in 0..2 {
+        let mut store = wasmtime::Store::new(engine, ());
+        let memory = wasmtime::Instance::new(&mut store, &zeroed, &[])?
+            .get_memory(&mut store, "memory")
+            .context("probe module has no memory")?;
+        // This may exceed a (tiny) instance memory limit, in which case we only check the first
+        // page.
+        let _ = memory.grow(&mut store, 1);
+        if memory.data(&store).iter().any(|&b| b != 0) {
+            return Err(anyhow!("engine must zero new and grown memories"));
+        }
+        memory.data_mut(&mut store).fill(0xff);
+    }
+
+    Ok(())
+}
+
+#[derive(Clone)]
+struct ModuleRecord {
+    module: Module,
+    /// Byte size of the original Wasm.
+    size: usize,
+}
+
+/// The wasmtime engine of one or more engine pools, with the modules it compiled.
+struct SharedEngine {
+    engine: wasmtime::Engine,
+
+    /// These two fields are used used in the store constructor to avoid resolve a chicken & egg
+    /// situation: We need the store before we can get the real values, but we need to create the
+    /// `InvocationData` before we can make the store.
+    ///
+    /// Alternatively, we could use `Option`s. But then we need to unwrap everywhere.
+    dummy_gas_global: Global,
+    dummy_memory: Memory,
+
+    /// Compiled modules, by the hash of the code they were compiled from (instrumented Wasm, or
+    /// a serialized module).
+    modules: Mutex<HashMap<[u8; 32], Module>>,
+}
+
+impl SharedEngine {
+    fn new(engine: wasmtime::Engine) -> Arc<Self> {
+        let mut dummy_store = wasmtime::Store::new(&engine, ());
+        let gg_type = GlobalType::new(ValType::I64, Mutability::Var);
+        let dummy_gg = Global::new(&mut dummy_store, gg_type, Val::I64(0))
+            .expect("failed to create dummy gas global");
+
+        let dummy_memory = Memory::new(&mut dummy_store, MemoryType::new(0, Some(0)))
+            .expect("failed to create dummy memory");
+
+        Arc::new(SharedEngine {
+            engine,
+            dummy_memory,
+            dummy_gas_global: dummy_gg,
+            modules: Default::default(),
+        })
+    }
+
+    /// Returns the module compiled from the given code, compiling it with `compile` (and caching
+    /// the result if it succeeds) unless it was already compiled.
+    fn module(
+        &self,
+        code: &[u8],
+        compile: impl FnOnce() -> anyhow::Result<Module>,
+    ) -> anyhow::Result<Module> {
+        let hash = blake2b_simd::Params::new().hash_length(32).hash(code);
+        let key: [u8; 32] = hash.as_bytes().try_into().expect("hash is 32 bytes");
+        if let Some(module) = self.modules.lock().expect("modules poisoned").get(&key) {
+            return Ok(module.clone());
+        }
+        // Compile without holding the lock, so pools can compile different code concurrently.
+        let module = compile()?;
+        Ok(self
+            .modules
+            .lock()
+            .expect("modules poisoned")
+            .entry(key)
+            .or_insert(module)
+            .clone())
+    }
+}
+
+struct EngineInner {
+    limit: Mutex<u32>,
+    condv: Condvar,
+
+    shared: Arc<SharedEngine>,
+
+    /// The modules loaded into this pool, by code CID. Modules compiled from identical code share
+    /// the same compiled module (see [`SharedEngine`]).
+    module_cache: Mutex<HashMap<Cid, ModuleRecord>>,
+    instance_cache: Mutex<HashMap<TypeId, Box<dyn Any + Send>>>,
+    /// Native actor implementations, by kernel type (see [`NativeActors`]).
+    native_actors: Mutex<HashMap<TypeId, Box<dyn Any + Send>>>,
+    /// Syscall modules registered by the embedder.
+    syscall_modules: Mutex<Vec<Arc<dyn SyscallModule>>>,
+    /// Actor debug information, by code CID.
+    debug_info: Mutex<HashMap<Cid, Arc<DebugInfo>>>,
+    config: EngineConfig,
+
+    actor_redirect: HashMap<Cid, Cid>,
+}
+
+/// The native actor implementations registered for a kernel type, by code CID.
+type NativeActors<K> = HashMap<Cid, Arc<dyn NativeActor<K>>>;
+
+/// EnginePool represents a limited pool of engines.
+///
+/// Compiled modules are cached by the hash of the (instrumented) code they were compiled from, so
+/// byte-identical code under multiple CIDs, or loaded by multiple pools sharing a wasmtime engine
+/// (see [`MultiEngine`]), is only compiled once. An embedder-configured compilation cache (see
+/// [`EnginePool::with_config`]) likewise only stores one artifact per distinct code.
+#[derive(Clone)]
+pub struct EnginePool(Arc<EngineInner>);
+
+impl EnginePool {
+    /// Acquire an [`Engine`]. This method will block until an [`Engine`] is available, and will
+    /// release the engine on drop.
+    pub fn acquire(&self) -> Engine {
+        *self
+            .0
+            .condv
+            .wait_while(self.0.limit.lock().unwrap(), |limit| *limit == 0)
+            .unwrap() -= 1;
+        Engine(self.0.clone())
+    }
+
+    /// Try to acquire an [`Engine`]. Returns `None` if the call would block, or if the lock is
+    /// poisoned.
+    ///
+    /// The [`Engine`] is released on drop.
+    pub fn try_acquire(&self) -> Option<Engine> {
+        self.0
+            .limit
+            .try_lock()
+            .ok()
+            .filter(|limit| **limit > 0)
+            .map(|mut limit| {
+                *limit -= 1;
+                Engine(self.0.clone())
+            })
+    }
+
+    pub fn new_default(ec: EngineConfig) -> anyhow::Result<Self> {
+        EnginePool::new(&wasmtime_config(&ec)?, ec)
+    }
+
+    /// Create a new Engine from an embedder-provided wasmtime config, on top of which the FVM's
+    /// determinism-critical settings are applied (see [`configure_engine`]). Use this to tune
+    /// compilation, e.g., the compilation strategy, parallel compilation, or the compilation cache.
+    pub fn with_config(mut c: wasmtime::Config, ec: EngineConfig) -> anyhow::Result<Self> {
+        configure_engine(&mut c, &ec)?;
+        EnginePool::new(&c, ec)
+    }
+
+    /// Create a new Engine from an embedder-provided wasmtime engine. The engine must have been
+    /// configured with [`configure_engine`] for the given engine config, or this will fail.
+    pub fn with_engine(engine: wasmtime::Engine, ec: EngineConfig) -> anyhow::Result<Self> {
+        validate_engine(&engine, &ec).context("wasmtime engine is misconfigured")?;
+        Ok(EnginePool::from_engine(engine, ec))
+    }
+
+    /// Create a new Engine from a wasmtime config. The config is used as-is; prefer
+    /// [`EnginePool::with_config`].
+    pub fn new(c: &wasmtime::Config, ec: EngineConfig) -> anyhow::Result<Self> {
+        Ok(EnginePool::from_engine(wasmtime::Engine::new(c)?, ec))
+    }
+
+    /// Registers a native implementation of the actor with the given code CID, for kernels of type
+    /// `K`. It's only used if [`NetworkConfig::native_actors`] is enabled. See [`crate::native`].
+    pub fn register_native_actor<K: Kernel>(&self, code: Cid, actor: impl NativeActor<K>) {
+        let mut native_actors = self.0.native_actors.lock().expect("native actors poisoned");
+        native_actors
+            .entry(TypeId::of::<K>())
+            .or_insert_with(|| Box::new(NativeActors::<K>::new()))
+            .downcast_mut::<NativeActors<K>>()
+            .expect("invalid native actors entry")
+            .insert(code, Arc::new(actor));
+    }
+
+    /// Registers the debug information of some actor code, replacing any previously registered
+    /// for the same code. It's only used if [`NetworkConfig::actor_debugging`] is enabled. See
+    /// [`crate::engine::debug_info`].
+    pub fn register_debug_info(&self, info: DebugInfo) {
+        self.0
+            .debug_info
+            .lock()
+            .expect("debug info poisoned")
+            .insert(info.code, Arc::new(info));
+    }
+
+    /// Registers a module of additional syscalls, to be linked into actors under the module's
+    /// namespace. Fails if the namespace is reserved or already registered. Modules should be
+    /// registered before any actor code is loaded. See [`crate::syscalls::custom`].
+    pub fn register_syscall_module(&self, module: impl SyscallModule) -> anyhow::Result<()> {
+        {
+            let mut modules = self
+                .0
+                .syscall_modules
+                .lock()
+                .expect("syscall modules poisoned");
+            check_module(&module, &modules)?;
+            modules.push(Arc::new(module));
+        }
+
+        // Rebuild the linkers with the new syscalls. The modules lock must be released first, as
+        // it's taken while holding the instance cache lock when building a linker.
+        self.0
+            .instance_cache
+            .lock()
+            .expect("cache poisoned")
+            .clear();
+        Ok(())
+    }
+
+    fn from_engine(engine: wasmtime::Engine, ec: EngineConfig) -> Self {
+        EnginePool::from_shared(SharedEngine::new(engine), ec)
+    }
+
+    /// Creates a pool on top of a (possibly shared) wasmtime engine. The engine must have been
+    /// configured for an engine config with the same [`EngineKey`], and enough instances for this
+    /// pool's concurrency on top of any other pools'.
+    fn from_shared(shared: Arc<SharedEngine>, ec: EngineConfig) -> Self {
+        let actor_redirect = ec.actor_redirect.iter().cloned().collect();
+
+        EnginePool(Arc::new(EngineInner {
+            limit: Mutex::new(ec.concurrency),
+            condv: Condvar::new(),
+            shared,
+            module_cache: Default::default(),
+            instance_cache: Mutex::new(HashMap::new()),
+            native_actors: Mutex::new(HashMap::new()),
+            syscall_modules: Mutex::new(Vec::new()),
+            debug_info: Mutex::new(HashMap::new()),
+            config: ec,
+            actor_redirect,
+        }))
+    }
+}
+
+/// An import in actor code that doesn't resolve to any syscall.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownImport {
+    pub module: String,
+    pub name: String,
+}
+
+impl Display for UnknownImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.module, self.name)
+    }
+}
+
+/// Returned when loading actor code that imports functions (or other items) not provided by
+/// the FVM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownImportsError(pub Vec<UnknownImport>);
+
+impl std::error::Error for UnknownImportsError {}
+
+impl Display for UnknownImportsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "actor code has unknown imports: ")?;
+        for (i, import) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", import)?;
+        }
+        Ok(())
+    }
+}
+
+struct Cache<K> {
+    linker: wasmtime::Linker<InvocationData<K>>,
+}
+
+/// An `Engine` represents a single, caching wasm engine. It should not be shared between concurrent
+/// call stacks.
+///
+/// The `Engine` will be returned to the [`EnginePool`] on drop.
+pub struct Engine(Arc<EngineInner>);
+
+impl Deref for Engine {
+    type Target = wasmtime::Engine;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0.shared.engine
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        let mut limit = self.0.limit.lock().unwrap();
+        *limit += 1;
+        self.0.condv.notify_one();
+    }
+}
+
+impl Engine {
+    /// Loads an actor's Wasm code from the blockstore by CID, and prepares
+    /// it for execution by instantiating and caching the Wasm module. This
+    /// method errors if the code CID is not found in the store.
+    ///
+    /// Return the original byte code size.
+    pub fn prepare_actor_code<BS: Blockstore>(
+        &self,
+        code_cid: &Cid,
+        blockstore: BS,
+    ) -> anyhow::Result<usize> {
+        let code_cid = self.with_redirect(code_cid);
+        if let Some(item) = self
+            .0
+            .module_cache
+            .lock()
+            .expect("module_cache poisoned")
+            .get(code_cid)
+        {
+            return Ok(item.size);
+        }
+        let wasm = blockstore.get(code_cid)?.ok_or_else(|| {
+            anyhow!(
+                "no wasm bytecode in blockstore for CID {}",
+                &code_cid.to_string()
+            )
+        })?;
+        // compile and cache instantiated WASM module
+        self.prepare_wasm_bytecode(code_cid, &wasm)
+    }
+
+    /// Instantiates and caches the Wasm modules for the bytecodes addressed by
+    /// the supplied CIDs. Only uncached entries are actually fetched and
+    /// instantiated. Blockstore failures and entry inexistence shortcircuit
+    /// make this method return an Err immediately.
+    ///
+    /// Returns the total original byte size of the modules
+    pub fn preload<'a, BS, I>(&self, blockstore: BS, cids: I) -> anyhow::Result<usize>
+    where
+        BS: Blockstore,
+        I: IntoIterator<Item = &'a Cid>,
+    {
+        let mut total_size = 0usize;
+        for cid in cids {
+            log::trace!("preloading code CID {cid}");
+            let size = self.prepare_actor_code(cid, &blockstore).with_context(|| {
+                anyhow!("could not prepare actor with code CID {}", &cid.to_string())
+            })?;
+            total_size += size;
+        }
+        Ok(total_size)
+    }
+
+    fn with_redirect<'a>(&'a self, k: &'a Cid) -> &'a Cid {
+        match &self.0.actor_redirect.get(k) {
+            Some(cid) => cid,
+            None => k,
+        }
+    }
+
+    /// Returns the native implementation registered for the actor code (after redirects), if any.
+    pub fn native_actor<K: Kernel>(&self, code: &Cid) -> Option<Arc<dyn NativeActor<K>>> {
+        let code = self.with_redirect(code);
+        self.0
+            .native_actors
+            .lock()
+            .expect("native actors poisoned")
+            .get(&TypeId::of::<K>())?
+            .downcast_ref::<NativeActors<K>>()
+            .expect("invalid native actors entry")
+            .get(code)
+            .cloned()
+    }
+
+    /// Returns the debug information registered for the actor code (after redirects), if any.
+    pub fn debug_info(&self, code: &Cid) -> Option<Arc<DebugInfo>> {
+        let code = self.with_redirect(code);
+        self.0
+            .debug_info
+            .lock()
+            .expect("debug info poisoned")
+            .get(code)
+            .cloned()
+    }
+
+    /// Loads some Wasm code into the engine and prepares it for execution.
+    pub fn prepare_wasm_bytecode(&self, k: &Cid, wasm: &[u8]) -> anyhow::Result<usize> {
+        let k = self.with_redirect(k);
+        let mut cache = self.0.module_cache.lock().expect("module_cache poisoned");
+        let size = match cache.get(k) {
+            Some(item) => item.size,
+            None => {
+                let m = self.load_raw(wasm)?;
+                let s = m.size;
+                cache.insert(*k, m);
+                s
+            }
+        };
+        Ok(size)
+    }
+
+    fn load_raw(&self, raw_wasm: &[u8]) -> anyhow::Result<ModuleRecord> {
+        let wasm = self.instrument(raw_wasm)?;
+        let module = self
+            .0
+            .shared
+            .module(&wasm, || Module::from_binary(&self.0.shared.engine, &wasm))?;
+        // The module may have been compiled by another pool sharing the engine, so its imports are
+        // checked against this pool's network version and syscall modules regardless.
+        self.check_imports(&module)?;
+        Ok(ModuleRecord {
+            module,
+            size: wasm.len(),
+        })
+    }
+
+    /// Validates and instruments Wasm code.
+    fn instrument(&self, raw_wasm: &[u8]) -> anyhow::Result<Vec<u8>> {
+        // First make sure that non-instrumented wasm is valid
+        Module::validate(&self.0.shared.engine, raw_wasm)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| "failed to validate actor wasm")?;
+
+        // Note: when adding debug mode support (with recorded syscall replay) don't instrument to
+        // avoid breaking debug info
+
+        use fvm_wasm_instrument::{gas_metering, stack_limiter};
+
+        // stack limiter adds post/pre-ambles to call instructions; We want to do that
+        // before injecting gas accounting calls to avoid this overhead in every single
+        // block of code.
+        let raw_wasm = stack_limiter::inject(raw_wasm, self.0.config.max_wasm_stack)
+            .map_err(anyhow::Error::msg)?;
+
+        // inject gas metering based on a price list. This function will
+        // * add a new mutable i64 global import, gas.gas_counter
+        // * push a gas counter function which deduces gas from the global, and
+        //   traps when gas.gas_counter is less than zero
+        // * optionally push a function which wraps memory.grow instruction
+        //   making it charge gas based on memory requested
+        // * divide code into metered blocks, and add a call to the gas counter
+        //   function before entering each metered block
+        // * NOTE: Currently cannot instrument and charge for `table.grow` because the instruction
+        //   (code `0xFC 15`) uses what parity-wasm calls the `BULK_PREFIX` but it was added later in
+        //   https://github.com/WebAssembly/reference-types/issues/29 and is not recognised by the
+        //   parity-wasm module parser, so the contract cannot grow the tables.
+        gas_metering::inject(&raw_wasm, self.0.config.wasm_prices, "gas")
+            .map_err(|_| anyhow::Error::msg("injecting gas counter failed"))
+    }
+
+    fn check_imports(&self, module: &Module) -> anyhow::Result<()> {
+        // Make sure every import resolves to a syscall available at this network version (or the
+        // gas counter injected above, or the WASI shim if debugging) now, rather than failing when
+        // the actor is first instantiated.
+        let syscall_modules = self
+            .0
+            .syscall_modules
+            .lock()
+            .expect("syscall modules poisoned");
+        let unknown: Vec<_> = module
+            .imports()
+            .filter(|import| match import.ty() {
+                ExternType::Func(_) => {
+                    !(is_syscall(
+                        import.module(),
+                        import.name(),
+                        self.0.config.network_version,
+                    ) || is_custom_syscall(&syscall_modules, import.module(), import.name())
+                        || (self.0.config.actor_debugging
+                            && is_wasi_function(import.module(), import.name()))
+                        || (self.0.config.dev_randomness
+                            && is_dev_syscall(import.module(), import.name())))
+                }
+                ExternType::Global(_) => {
+                    (import.module(), import.name()) != ("gas", GAS_COUNTER_NAME)
+                }
+                _ => true,
+            })
+            .map(|import| UnknownImport {
+                module: import.module().to_owned(),
+                name: import.name().to_owned(),
+            })
+            .collect();
+        if !unknown.is_empty() {
+            return Err(UnknownImportsError(unknown).into());
+        }
+        Ok(())
+    }
+
+    /// Validates Wasm code without loading it into the engine: checks that the code is valid Wasm,
+    /// that it can be instrumented, and that every import resolves to a syscall.
+    ///
+    /// If the code imports anything other than known syscalls, the returned error can be
+    /// downcast to an [`UnknownImportsError`] listing the offending imports.
+    pub fn validate_code(&self, wasm: &[u8]) -> anyhow::Result<()> {
+        let wasm = self.instrument(wasm)?;
+        self.check_imports(&Module::from_binary(&self.0.shared.engine, &wasm)?)
+    }
+
+    /// Load compiled wasm code into the engine.
+    ///
+    /// # Safety
+    ///
+    /// See [`wasmtime::Module::deserialize`] for safety information.
+    pub unsafe fn load_compiled(&self, k: &Cid, compiled: &[u8]) -> anyhow::Result<Module> {
+        let k = self.with_redirect(k);
+        let mut cache = self.0.module_cache.lock().expect("module_cache poisoned");
+        let module = match cache.get(k) {
+            Some(m) => m.module.clone(),
+            None => {
+                let module = self.0.shared.module(compiled, || {
+                    Module::deserialize(&self.0.shared.engine, compiled)
+                })?;
+                cache.insert(
+                    *k,
+                    ModuleRecord {
+                        module: module.clone(),
+                        size: compiled.len(),
+                    },
+                );
+                module
+            }
+        };
+        Ok(module)
+    }
+
+    /// Lookup a loaded wasmtime module.
+    pub fn get_module(
+        &self,
+        blockstore: &impl Blockstore,
+        k: &Cid,
+    ) -> anyhow::Result<Option<Module>> {
+        let k = self.with_redirect(k);
+        match self
+            .0
+            .module_cache
+            .lock()
+            .expect("module_cache poisoned")
+            .entry(*k)
+        {
+            Occupied(v) => Ok(Some(v.get().module.clone())),
+            Vacant(v) => blockstore
+                .get(k)
+                .context("failed to lookup wasm module in blockstore")?
+                .map(|raw_wasm| Ok(v.insert(self.load_raw(&raw_wasm)?).module.clone()))
+                .transpose(),
+        }
+    }
+
+    /// Lookup and instantiate a loaded wasmtime module with the given store. This will cache the
+    /// linker, syscalls, etc.
+    pub fn get_instance<K: Kernel>(
+        &self,
+        store: &mut wasmtime::Store<InvocationData<K>>,
+        k: &Cid,
+    ) -> anyhow::Result<Option<wasmtime::Instance>> {
+        let k = self.with_redirect(k);
+        let mut instance_cache = self.0.instance_cache.lock().expect("cache poisoned");
+
+        let type_id = TypeId::of::<K>();
+        let cache: &mut Cache<K> = match instance_cache.entry(type_id) {
+            Occupied(e) => &mut *e
+                .into_mut()
+                .downcast_mut()
+                .expect("invalid instance cache entry"),
+            Vacant(e) => &mut *e
+                .insert({
+                    let mut linker: Linker<InvocationData<K>> = Linker::new(&self.0.shared.engine);
+                    linker.allow_shadowing(true);
+
+                    bind_syscalls(&mut linker, self.0.config.network_version)?;
+                    bind_custom_syscalls(
+                        &mut linker,
+                        &self
+                            .0
+                            .syscall_modules
+                            .lock()
+                            .expect("syscall modules poisoned"),
+                    )?;
+                    if self.0.config.actor_debugging {
+                        bind_wasi(&mut linker)?;
+                    }
+                    if self.0.config.dev_randomness {
+                        bind_dev_syscalls(&mut linker)?;
+                    }
+                    Box::new(Cache { linker })
+                })
+                .downcast_mut()
+                .expect("invalid instance cache entry"),
+        };
+        cache
+            .linker
+            .define("gas", GAS_COUNTER_NAME, store.data_mut().avail_gas_global)?;
+
+        let mut module_cache = self.0.module_cache.lock().expect("module_cache poisoned");
+
+        let instantiate = |store: &mut wasmtime::Store<InvocationData<K>>, module| {
+            // Before we instantiate the module, we should make sure the user has sufficient gas to
+            // pay for the minimum memory requirements. The module instrumentation in `inject` only
+            // adds code to charge for _growing_ the memory, but not for the amount made accessible
+            // initially. The limits are checked by wasmtime during instantiation, though.
+            let t = charge_for_init(store, module)?;
+
+            let inst = cache.linker.instantiate(&mut *store, module)?;
+
+            // Record the time it took for the linker to instantiate the module.
+            // This should also include everything that happens above in this method.
+            // Note that this does _not_ contain the time it took the load the Wasm file,
+            // which could have been cached already.
+            record_init_time(store, t);
+
+            Ok(Some(inst))
+        };
+
+        match module_cache.entry(*k) {
+            Occupied(v) => instantiate(store, &v.get().module),
+            Vacant(v) => match store
+                .data()
+                .kernel
+                .machine()
+                .blockstore()
+                .get(k)
+                .context("failed to lookup wasm module in blockstore")?
+            {
+                Some(raw_wasm) => instantiate(store, &v.insert(self.load_raw(&raw_wasm)?).module),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// Construct a new wasmtime "store" from the given kernel.
+    pub fn new_store<K: Kernel>(&self, mut kernel: K) -> wasmtime::Store<InvocationData<K>> {
+        let memory_bytes = kernel.limiter_mut().memory_used();
+
+        let id = InvocationData {
+            kernel,
+            last_error: None,
+            avail_gas_global: self.0.shared.dummy_gas_global,
+            last_milligas_available: 0,
+            last_memory_bytes: memory_bytes,
+            last_charge_time: GasTimer::start(),
+            last_fuel_consumed: 0,
+            memory: self.0.shared.dummy_memory,
+            memory_growth: None,
+            refuse_extern: false,
+        };
+
+        let mut store = wasmtime::Store::new(&self.0.shared.engine, id);
+        let ggtype = GlobalType::new(ValType::I64, Mutability::Var);
+        let gg = Global::new(&mut store, ggtype, Val::I64(0))
+            .expect("failed to create available_gas global");
+        store.data_mut().avail_gas_global = gg;
+
+        // Fuel only counts instructions: give the store as much as it can hold, so it never runs
+        // out.
+        if self.0.config.instruction_counting {
+            store
+                .add_fuel(u64::MAX)
+                .expect("failed to add fuel to the store");
+        }
+
+        store.limiter(|data| data as &mut dyn wasmtime::ResourceLimiter);
+
+        store
+    }
+}
+
+/// Enforces the limits of the kernel's [`MemoryLimiter`], and records memory growth events for the
+/// execution trace (when tracing).
+impl<K: Kernel> wasmtime::ResourceLimiter for InvocationData<K> {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> bool {
+        let ok = as_wasmtime_limiter(self).memory_growing(current, desired, maximum);
+        if ok {
+            if let Some(growth) = &mut self.memory_growth {
+                growth.push((current, desired));
+            }
+        }
+        ok
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        as_wasmtime_limiter(self).table_growing(current, desired, maximum)
+    }
+}
+
+fn as_wasmtime_limiter<K: Kernel>(
+    data: &mut InvocationData<K>,
+) -> &mut WasmtimeLimiter<K::Limiter> {
+    // SAFETY: This is safe because WasmtimeLimiter is `repr(transparent)`.
+    // Unfortunately, we can't simply wrap the limiter as we need to return a reference.
+    unsafe {
+        let limiter_ref = data.kernel.limiter_mut();
+        // (debug)-assert that these types have the same layout (guaranteed by
+        // `repr(transparent)`).
+        debug_assert_eq!(
+            std::alloc::Layout::for_value(&*limiter_ref),
+            std::alloc::Layout::new::<WasmtimeLimiter<K::Limiter>>()
+        );
+        // Then cast.
+        &mut *(limiter_ref as *mut K::Limiter as *mut WasmtimeLimiter<K::Limiter>)
+    }
+}
+
+#[repr(transparent)]
+struct WasmtimeLimiter<L>(L);
+
+impl<L: MemoryLimiter> wasmtime::ResourceLimiter for WasmtimeLimiter<L> {
+    fn memory_growing(&mut self, current: usize, desired: usize, maximum: Option<usize>) -> bool {
+        if maximum.map_or(false, |m| desired > m) {
+            return false;
+        }
+
+        self.0.grow_instance_memory(current, desired)
+    }
+
+    fn table_growing(&mut self, current: u32, desired: u32, maximum: Option<u32>) -> bool {
+        if maximum.map_or(false, |m| desired > m) {
+            return false;
+        }
+        self.0.grow_instance_table(current, desired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::ResourceLimiter;
+
+    use crate::engine::WasmtimeLimiter;
+    use crate::machine::limiter::MemoryLimiter;
+
+    #[derive(Default)]
+    struct Limiter {
+        memory: usize,
+    }
+    impl MemoryLimiter for Limiter {
+        fn memory_used(&self) -> usize {
+            unimplemented!()
+        }
+
+        fn grow_memory(&mut self, bytes: usize) -> bool {
+            self.memory += bytes;
+            true
+        }
+
+        fn with_stack_frame<T, G, F, R>(_: &mut T, _: G, _: F) -> R
+        where
+            G: Fn(&mut T) -> &mut Self,
+            F: FnOnce(&mut T) -> R,
+        {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn memory() {
+        let mut limits = WasmtimeLimiter(Limiter::default());
+        assert!(limits.memory_growing(0, 3, None));
+        assert_eq!(limits.0.memory, 3);
+
+        // The maximum in the args takes precedence.
+        assert!(!limits.memory_growing(3, 4, Some(2)));
+        assert_eq!(limits.0.memory, 3);
+
+        // Increase by 2.
+        assert!(limits.memory_growing(2, 4, None));
+        assert_eq!(limits.0.memory, 5);
+    }
+
+    #[test]
+    fn external_engine() {
+        use fvm_shared::version::NetworkVersion;
+        use wasmtime::InstanceAllocationStrategy;
+
+        use crate::engine::{configure_engine, EngineConfig, EnginePool};
+        use crate::machine::NetworkConfig;
+
+        let mut nc = NetworkConfig::new(NetworkVersion::V18);
+        nc.max_call_depth = 1;
+        let ec = EngineConfig::from(&nc);
+        let engine = |tune: fn(&mut wasmtime::Config)| {
+            let mut c = wasmtime::Config::default();
+            configure_engine(&mut c, &ec).unwrap();
+            tune(&mut c);
+            EnginePool::with_engine(wasmtime::Engine::new(&c).unwrap(), ec.clone())
+        };
+
+        // Compilation settings are up to the embedder.
+        assert!(engine(|c| {
+            c.parallel_compilation(false);
+        })
+        .is_ok());
+
+        // Determinism-critical settings are checked.
+        assert!(EnginePool::with_engine(wasmtime::Engine::default(), ec.clone()).is_err());
+        assert!(engine(|c| {
+            c.wasm_simd(true);
+        })
+        .is_err());
+        assert!(engine(|c| {
+            c.allocation_strategy(InstanceAllocationStrategy::OnDemand);
+        })
+        .is_err());
+        // Other architectures produce the canonical NaN anyways.
+        #[cfg(target_arch = "x86_64")]
+        assert!(engine(|c| {
+            c.cranelift_nan_canonicalization(false);
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn shared_modules() {
+        use std::sync::Arc;
+
+        use cid::multihash::{Code, MultihashDigest};
+        use cid::Cid;
+        use fvm_shared::version::NetworkVersion;
+        use fvm_shared::IPLD_RAW;
+
+        use crate::engine::MultiEngine;
+        use crate::machine::NetworkConfig;
+
+        // A module with a single empty function.
+        let wasm =
+            b"\0asm\x01\0\0\0\x01\x04\x01\x60\x00\x00\x03\x02\x01\x00\x0a\x04\x01\x02\x00\x0b";
+        let blake2b = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(wasm));
+        let sha256 = Cid::new_v1(IPLD_RAW, Code::Sha2_256.digest(wasm));
+
+        let engines = MultiEngine::new(1);
+        let mut nc = NetworkConfig::new(NetworkVersion::V18);
+        nc.max_call_depth = 1;
+        let a = engines.get(&nc).unwrap();
+
+        // Pools that only differ in settings that don't affect compilation share an engine.
+        nc.actor_redirect = vec![(sha256, blake2b)];
+        let b = engines.get(&nc).unwrap();
+        assert!(Arc::ptr_eq(&a.0.shared, &b.0.shared));
+
+        // The same code is only compiled once, whether under different CIDs or in different pools.
+        a.acquire().prepare_wasm_bytecode(&blake2b, wasm).unwrap();
+        a.acquire().prepare_wasm_bytecode(&sha256, wasm).unwrap();
+        b.acquire().prepare_wasm_bytecode(&blake2b, wasm).unwrap();
+        assert_eq!(a.0.shared.modules.lock().unwrap().len(), 1);
+        assert_eq!(a.0.module_cache.lock().unwrap().len(), 2);
+
+        // Pools sharing an engine keep their own concurrency limits.
+        let engine = a.acquire();
+        assert!(a.try_acquire().is_none());
+        assert!(b.try_acquire().is_some());
+        drop(engine);
+
+        // Engines only have instances for up to MAX_SHARED_POOLS (two) pools.
+        nc.dev_randomness = true;
+        let d = engines.get(&nc).unwrap();
+        assert!(!Arc::ptr_eq(&a.0.shared, &d.0.shared));
+
+        // Settings that affect compilation get their own engine.
+        nc.memory_snapshots = true;
+        let c = engines.get(&nc).unwrap();
+        assert!(!Arc::ptr_eq(&a.0.shared, &c.0.shared));
+    }
+
+    #[test]
+    fn table() {
+        let mut limits = WasmtimeLimiter(Limiter::default());
+        assert!(limits.table_growing(0, 3, None));
+        assert_eq!(limits.0.memory, 3 * 8);
+
+        // The maximum in the args takes precedence.
+        assert!(!limits.table_growing(3, 4, Some(2)));
+        assert_eq!(limits.0.memory, 3 * 8);
+
+        // Increase by 2.
+        assert!(limits.table_growing(2, 4, None));
+        assert_eq!(limits.0.memory, 5 * 8);
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use cid::multihash::{Code, Multihash, MultihashDigest};
+use cid::Cid;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+
+use super::{ApplyKind, ApplyRet};
+use crate::gas::ActorGasUsage;
+use crate::machine::MachineContext;
+use crate::state_tree::ActorState;
+
+/// Memoizes the results of applying messages, so that applying the same message to the same state
+/// again (e.g., when the blocks of competing tipsets share a parent and messages) reuses the first
+/// result instead of executing the message.
+///
+/// Results are keyed by:
+///
+/// - the state root the machine started from (the parent tipset's state root), the message's
+///   position since then, and a digest of the messages applied before it, which together identify
+///   the state the message is applied to;
+/// - the tipset the externs (randomness, tipset CIDs, consensus faults) read from, as given to
+///   [`DefaultExecutor::set_result_cache`];
+/// - a digest of the [`NetworkConfig`](crate::machine::NetworkConfig), including the price list;
+/// - the message's CID, and the epoch context (epoch, timestamp, base fee, and circulating
+///   supply).
+///
+/// A hit returns the cached [`ApplyRet`], applies the cached changes to the state tree, and merges
+/// the cached actor gas usage, in place of executing the message. As the state is identified by
+/// the messages applied to it, the executor's state tree must only be changed by executing messages
+/// while caching is enabled. Enabling the cache after messages were applied, or applying a batch
+/// of messages, disables caching until the executor is [reset](super::DefaultExecutor::reset).
+///
+/// The cache is cheap to clone, and clones share their entries, so it can be shared between
+/// executors. A cached result is only reused if the blocks it references are in the executor's
+/// blockstore. Messages aren't cached when gas subsidies are configured, as their results depend
+/// on the subsidies' quotas.
+///
+/// [`DefaultExecutor::set_result_cache`]: super::DefaultExecutor::set_result_cache
+#[derive(Clone)]
+pub struct ResultCache {
+    inner: Arc<Mutex<CacheInner>>,
+}
+
+struct CacheInner {
+    capacity: usize,
+    entries: HashMap<ResultKey, Arc<CachedResult>>,
+    /// The keys of the entries, oldest first.
+    order: VecDeque<ResultKey>,
+}
+
+/// The key of a [`ResultCache`] entry.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ResultKey {
+    initial_state_root: Cid,
+    position: u64,
+    prefix: Multihash,
+    tipset: Cid,
+    network: Multihash,
+    message: Cid,
+    apply_kind: ApplyKind,
+    raw_length: usize,
+    epoch: ChainEpoch,
+    timestamp: u64,
+    base_fee: TokenAmount,
+    circ_supply: TokenAmount,
+    tracing: bool,
+}
+
+/// The memoized result of applying a message.
+pub(crate) struct CachedResult {
+    pub apply_ret: ApplyRet,
+    /// The gas usage by actor code and method of the message.
+    pub actor_gas: ActorGasUsage,
+    /// The actors changed by the message, with their new state (or `None` if deleted).
+    pub changes: Vec<(ActorID, Option<ActorState>)>,
+}
+
+/// A [`ResultCache`] used by an executor, along with what the executor knows about the state its
+/// messages are applied to.
+pub(crate) struct CacheScope {
+    pub cache: ResultCache,
+    tipset: Cid,
+    network: Multihash,
+    /// The digest of the messages applied since the machine's initial state, or `None` if unknown.
+    prefix: Option<Multihash>,
+}
+
+impl CacheScope {
+    /// Creates a scope for an executor that already applied `messages_applied` messages.
+    pub fn new(
+        cache: ResultCache,
+        tipset: Cid,
+        context: &MachineContext,
+        messages_applied: u64,
+    ) -> Self {
+        // Configurations that are equal but format differently (e.g., hash maps iterated in a
+        // different order) just don't share results.
+        let network = Code::Blake2b256.digest(format!("{:?}", context.network).as_bytes());
+        let mut scope = CacheScope {
+            cache,
+            tipset,
+            network,
+            prefix: None,
+        };
+        if messages_applied == 0 {
+            scope.reset();
+        }
+        scope
+    }
+
+    /// Returns the key of the message applied at `position`, or `None` if the state it's applied
+    /// to is unknown.
+    pub fn key(
+        &self,
+        position: u64,
+        message: Cid,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+        context: &MachineContext,
+    ) -> Option<ResultKey> {
+        Some(ResultKey {
+            initial_state_root: context.initial_state_root,
+            position,
+            prefix: self.prefix?,
+            tipset: self.tipset,
+            network: self.network,
+            message,
+            apply_kind,
+            raw_length,
+            epoch: context.epoch,
+            timestamp: context.timestamp,
+            base_fee: context.base_fee.clone(),
+            circ_supply: context.circ_supply.clone(),
+            tracing: context.tracing,
+        })
+    }
+
+    /// Records that a message was applied.
+    pub fn advance(&mut self, message: &Cid, apply_kind: ApplyKind, raw_length: usize) {
+        if let Some(prefix) = &mut self.prefix {
+            let mut data = prefix.to_bytes();
+            data.extend(message.to_bytes());
+            data.push(apply_kind as u8);
+            data.extend((raw_length as u64).to_le_bytes());
+            *prefix = Code::Blake2b256.digest(&data);
+        }
+    }
+
+    /// Forgets the messages applied, e.g., when they're not known individually.
+    pub fn invalidate(&mut self) {
+        self.prefix = None;
+    }
+
+    /// Records that the machine was reset to its initial state.
+    pub fn reset(&mut self) {
+        self.prefix = Some(Code::Blake2b256.digest(&[]));
+    }
+}
+
+/// Returns the entries of `after` that differ from `before`. Both must be sorted by actor ID, as
+/// returned by [`StateTree::dirty_actors`](crate::state_tree::StateTree::dirty_actors).
+pub(crate) fn changes_since(
+    before: &[(ActorID, Option<ActorState>)],
+    after: Vec<(ActorID, Option<ActorState>)>,
+) -> Vec<(ActorID, Option<ActorState>)> {
+    after
+        .into_iter()
+        .filter(|(id, actor)| match before.binary_search_by_key(id, |(id, _)| *id) {
+            Ok(idx) => &before[idx].1 != actor,
+            Err(_) => true,
+        })
+        .collect()
+}
+
+impl ResultCache {
+    /// Creates a cache holding up to `capacity` results, evicting the oldest results first.
+    pub fn new(capacity: usize) -> Self {
+        ResultCache {
+            inner: Arc::new(Mutex::new(CacheInner {
+                capacity,
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Returns the number of cached results.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets all cached results.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.order.clear();
+    }
+
+    pub(crate) fn get(&self, key: &ResultKey) -> Option<Arc<CachedResult>> {
+        self.inner.lock().unwrap().entries.get(key).cloned()
+    }
+
+    pub(crate) fn insert(&self, key: ResultKey, result: CachedResult) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.capacity == 0 {
+            return;
+        }
+        if inner
+            .entries
+            .insert(key.clone(), Arc::new(result))
+            .is_none()
+        {
+            inner.order.push_back(key);
+        }
+        while inner.entries.len() > inner.capacity {
+            match inner.order.pop_front() {
+                Some(oldest) => inner.entries.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::version::NetworkVersion;
+    use num_traits::Zero;
+
+    use super::*;
+    use crate::machine::NetworkConfig;
+
+    fn cid(data: &[u8]) -> Cid {
+        Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(data))
+    }
+
+    fn key(scope: &CacheScope, message: &[u8], context: &MachineContext) -> ResultKey {
+        scope
+            .key(0, cid(message), ApplyKind::Explicit, 100, context)
+            .unwrap()
+    }
+
+    fn result() -> CachedResult {
+        CachedResult {
+            apply_ret: ApplyRet::prevalidation_fail(
+                ExitCode::SYS_SENDER_INVALID,
+                "invalid",
+                TokenAmount::zero(),
+            ),
+            actor_gas: ActorGasUsage::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn eviction() {
+        let context = NetworkConfig::new(NetworkVersion::V18).for_epoch(0, 0, Cid::default());
+        let scope = CacheScope::new(ResultCache::new(2), cid(b"tipset"), &context, 0);
+        let cache = &scope.cache;
+        cache.insert(key(&scope, b"a", &context), result());
+        cache.insert(key(&scope, b"b", &context), result());
+        // Clones share entries.
+        cache.clone().insert(key(&scope, b"c", &context), result());
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&key(&scope, b"a", &context)).is_none());
+        assert!(cache.get(&key(&scope, b"b", &context)).is_some());
+        assert!(cache.get(&key(&scope, b"c", &context)).is_some());
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn key_components() {
+        let context = NetworkConfig::new(NetworkVersion::V18).for_epoch(0, 0, Cid::default());
+        let scope = CacheScope::new(ResultCache::new(2), cid(b"tipset"), &context, 0);
+        let base = key(&scope, b"a", &context);
+
+        // The epoch context is part of the key.
+        let mut later = context.clone();
+        later.epoch += 1;
+        assert!(key(&scope, b"a", &later) != base);
+
+        // So is the network configuration.
+        let mut config = NetworkConfig::new(NetworkVersion::V18);
+        config.max_call_depth += 1;
+        let other = config.for_epoch(0, 0, Cid::default());
+        let other_scope = CacheScope::new(ResultCache::new(2), cid(b"tipset"), &other, 0);
+        assert!(key(&other_scope, b"a", &other) != base);
+
+        // And the tipset the externs read from.
+        let other_scope = CacheScope::new(ResultCache::new(2), cid(b"other"), &context, 0);
+        assert!(key(&other_scope, b"a", &context) != base);
+
+        // The state is identified by the messages applied before.
+        let mut advanced = CacheScope::new(ResultCache::new(2), cid(b"tipset"), &context, 0);
+        advanced.advance(&cid(b"b"), ApplyKind::Explicit, 100);
+        assert!(key(&advanced, b"a", &context) != base);
+        advanced.reset();
+        assert!(key(&advanced, b"a", &context) == base);
+        advanced.invalidate();
+        assert!(advanced
+            .key(0, cid(b"a"), ApplyKind::Explicit, 100, &context)
+            .is_none());
+
+        // The state is unknown if messages were applied before the cache was enabled.
+        let late = CacheScope::new(ResultCache::new(2), cid(b"tipset"), &context, 1);
+        assert!(late
+            .key(1, cid(b"a"), ApplyKind::Explicit, 100, &context)
+            .is_none());
+    }
+
+    #[test]
+    fn changes() {
+        let act = |seq| Some(ActorState::new(cid(b""), cid(b""), TokenAmount::zero(), seq, None));
+        let before = vec![(1, act(0)), (2, act(0)), (4, None)];
+        let after = vec![(1, act(0)), (2, act(1)), (3, None), (4, None)];
+        assert_eq!(
+            changes_since(&before, after),
+            vec![(2, act(1)), (3, None)]
+        );
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::time::Duration;
+
+use anyhow::anyhow;
+use fvm_shared::address::Address;
+use fvm_shared::error::ExitCode;
+use fvm_shared::message::Message;
+use fvm_shared::receipt::Receipt;
+use fvm_shared::{MethodNum, BLOCK_GAS_LIMIT};
+
+use super::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
+use crate::cron_actor::{CRON_ACTOR_ID, EPOCH_TICK_METHOD};
+use crate::gas::Gas;
+use crate::machine::Machine;
+use crate::system_actor::SYSTEM_ACTOR_ID;
+use crate::trace::{ExecutionEvent, ExecutionTrace};
+use crate::Kernel;
+
+/// The result of a cron dry-run. See [`DefaultExecutor::dry_run_cron`].
+#[derive(Clone, Debug)]
+pub struct CronDryRun {
+    /// The receipt the cron invocation would have produced.
+    pub receipt: Receipt,
+    /// One entry per invocation of an actor registered with cron, in invocation order.
+    pub actors: Vec<CronActorCost>,
+}
+
+/// The cost of cron invoking a single registered actor, including all nested calls made by that
+/// actor.
+#[derive(Clone, Debug)]
+pub struct CronActorCost {
+    /// The invoked actor.
+    pub actor: Address,
+    /// The invoked method.
+    pub method: MethodNum,
+    /// The invocation's exit code, or `None` if it failed with a syscall error.
+    pub exit_code: Option<ExitCode>,
+    /// Gas charged during the invocation.
+    pub gas_used: Gas,
+    /// Wall time measured for the gas charges made during the invocation.
+    pub wall_time: Duration,
+    /// Total size (in bytes) of the blocks linked (written to the state blockstore) during the
+    /// invocation, whether or not they end up reachable from the new state root.
+    pub state_delta_bytes: u64,
+}
+
+impl<K> DefaultExecutor<K>
+where
+    K: Kernel,
+{
+    /// Runs cron for the current epoch, committing the resulting state changes. This is the implicit
+    /// message a node applies at the end of each tipset, after the block rewards.
+    pub fn run_cron(&mut self) -> anyhow::Result<ApplyRet> {
+        let msg = self.cron_message();
+        self.execute_message(msg, ApplyKind::Implicit, 0)
+    }
+
+    /// Runs cron for the current epoch without committing any state changes, and reports the cost
+    /// of each cron-registered actor invocation. This can be used to forecast cron load, e.g.,
+    /// before an upgrade that registers new actors with cron.
+    ///
+    /// Per-actor costs are computed from the execution trace, so tracing must be enabled
+    /// ([`MachineContext::enable_tracing`][crate::machine::MachineContext::enable_tracing]).
+    pub fn dry_run_cron(&mut self) -> anyhow::Result<CronDryRun> {
+        if !self.context().tracing {
+            return Err(anyhow!("cron dry-runs require tracing to be enabled"));
+        }
+
+        let msg = self.cron_message();
+        self.state_tree_mut().begin_transaction(false);
+        let ret = self.execute_message(msg, ApplyKind::Implicit, 0);
+        self.state_tree_mut().end_transaction(true)?;
+        let ret = ret?;
+
+        Ok(CronDryRun {
+            actors: cron_actor_costs(&ret.exec_trace),
+            receipt: ret.msg_receipt,
+        })
+    }
+
+    /// The implicit message from the system actor invoking cron's `EpochTick`.
+    fn cron_message(&self) -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(SYSTEM_ACTOR_ID),
+            to: Address::new_id(CRON_ACTOR_ID),
+            sequence: self.context().epoch as u64,
+            value: Default::default(),
+            method_num: EPOCH_TICK_METHOD,
+            params: Default::default(),
+            gas_limit: BLOCK_GAS_LIMIT * 10000,
+            gas_fee_cap: Default::default(),
+            gas_premium: Default::default(),
+        }
+    }
+}
+
+/// Splits the trace of a cron invocation into the subtrees of the calls made by the cron actor,
+/// and totals the costs of each.
+fn cron_actor_costs(trace: &ExecutionTrace) -> Vec<CronActorCost> {
+    let mut costs: Vec<CronActorCost> = Vec::new();
+    let mut depth = 0;
+    for event in trace {
+        match event {
+            ExecutionEvent::Call { to, method, .. } => {
+                depth += 1;
+                // Depth 1 is the call into cron, depth 2 is cron calling a registered actor.
+                if depth == 2 {
+                    costs.push(CronActorCost {
+                        actor: *to,
+                        method: *method,
+                        exit_code: None,
+                        gas_used: Gas::default(),
+                        wall_time: Duration::default(),
+                        state_delta_bytes: 0,
+                    });
+                }
+            }
+            ExecutionEvent::CallReturn(code, _) if depth > 0 => {
+                if depth == 2 {
+                    if let Some(cost) = costs.last_mut() {
+                        cost.exit_code = Some(*code);
+                    }
+                }
+                depth -= 1;
+            }
+            ExecutionEvent::CallError(_) if depth > 0 => depth -= 1,
+            ExecutionEvent::GasCharge(charge) if depth >= 2 => {
+                if let Some(cost) = costs.last_mut() {
+                    cost.gas_used += charge.total();
+                    if let Some(elapsed) = charge.elapsed.get() {
+                        cost.wall_time += *elapsed;
+                    }
+                }
+            }
+            ExecutionEvent::BlockLink { size, .. } if depth >= 2 => {
+                if let Some(cost) = costs.last_mut() {
+                    cost.state_delta_bytes += size;
+                }
+            }
+            _ => {}
+        }
+    }
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::address::Address;
+    use fvm_shared::error::{ErrorNumber, ExitCode};
+    use num_traits::Zero;
+
+    use super::cron_actor_costs;
+    use crate::gas::{Gas, GasCharge};
+    use crate::kernel::SyscallError;
+    use crate::trace::ExecutionEvent;
+
+    fn call(to: u64) -> ExecutionEvent {
+        ExecutionEvent::Call {
+            from: 0,
+            to: Address::new_id(to),
+            method: 2,
+            params: Default::default(),
+            value: Default::default(),
+        }
+    }
+
+    fn ret() -> ExecutionEvent {
+        ExecutionEvent::CallReturn(ExitCode::OK, Default::default())
+    }
+
+    fn charge(gas: i64) -> ExecutionEvent {
+        ExecutionEvent::GasCharge(GasCharge::new("test", Gas::new(gas), Gas::zero()))
+    }
+
+    #[test]
+    fn splits_trace_by_cron_callee() {
+        let trace = vec![
+            call(3),
+            charge(1),
+            // First registered actor, with a nested call.
+            call(4),
+            charge(10),
+            ExecutionEvent::BlockLink {
+                cid: Default::default(),
+                size: 100,
+            },
+            call(1000),
+            charge(20),
+            ExecutionEvent::BlockLink {
+                cid: Default::default(),
+                size: 50,
+            },
+            ret(),
+            ret(),
+            charge(1),
+            // Second registered actor, which fails.
+            call(5),
+            charge(30),
+            ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "nope")),
+            ret(),
+        ];
+
+        let costs = cron_actor_costs(&trace);
+        assert_eq!(costs.len(), 2);
+
+        assert_eq!(costs[0].actor, Address::new_id(4));
+        assert_eq!(costs[0].exit_code, Some(ExitCode::OK));
+        assert_eq!(costs[0].gas_used, Gas::new(30));
+        assert_eq!(costs[0].state_delta_bytes, 150);
+
+        assert_eq!(costs[1].actor, Address::new_id(5));
+        assert_eq!(costs[1].exit_code, None);
+        assert_eq!(costs[1].gas_used, Gas::new(30));
+        assert_eq!(costs[1].state_delta_bytes, 0);
+    }
+}
@@ -5,24 +5,65 @@ use std::result::Result as StdResult;
 
 use anyhow::{anyhow, Result};
 use cid::Cid;
-use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
-use fvm_shared::address::Payload;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{from_slice, to_vec, RawBytes, DAG_CBOR};
+use fvm_shared::address::{Address, Payload};
 use fvm_shared::econ::TokenAmount;
-use fvm_shared::error::{ErrorNumber, ExitCode};
+use fvm_shared::error::ExitCode;
 use fvm_shared::event::StampedEvent;
+use fvm_shared::message::validation::{ValidateReturn, VALIDATE_MESSAGE_METHOD};
 use fvm_shared::message::Message;
 use fvm_shared::receipt::Receipt;
-use fvm_shared::{ActorID, IPLD_RAW, METHOD_SEND};
+use fvm_shared::{ActorID, MethodNum, IPLD_RAW, METHOD_SEND};
 use num_traits::Zero;
 
-use super::{ApplyFailure, ApplyKind, ApplyRet, Executor};
+use super::cache::{changes_since, CacheScope, CachedResult};
+use super::supply::Balances;
+use super::{
+    ApplyFailure, ApplyKind, ApplyRet, BatchApplyRet, EventAccumulator, ExecutionProgress,
+    Executor, ResultCache,
+};
 use crate::call_manager::{backtrace, Backtrace, CallManager, InvocationResult};
+use crate::cancel::Cancelled;
 use crate::eam_actor::EAM_ACTOR_ID;
 use crate::engine::EnginePool;
-use crate::gas::{Gas, GasCharge, GasOutputs};
+use crate::gas::{
+    ActorGasUsage, Gas, GasCharge, GasOutputs, GasSubsidy, OutOfGasInfo, SubsidyPayer,
+    SubsidyQuotas,
+};
 use crate::kernel::{Block, ClassifyResult, Context as _, ExecutionError, Kernel};
+use crate::machine::limiter::MemoryLimiter;
 use crate::machine::{Machine, BURNT_FUNDS_ACTOR_ID, REWARD_ACTOR_ID};
-use crate::trace::ExecutionTrace;
+use crate::system_actor::SYSTEM_ACTOR_ID;
+use crate::trace::{self, ExecutionTrace, TraceCommitment};
+
+/// The outcome of running a message (or batch of messages) on the machine.
+struct MachineExecRet {
+    result: crate::kernel::error::Result<InvocationResult>,
+    gas_used: i64,
+    backtrace: Backtrace,
+    exec_trace: ExecutionTrace,
+    events_root: Option<Cid>,
+    events: Vec<StampedEvent>, // TODO consider removing if nothing in the client ends up using it.
+    out_of_gas: Option<OutOfGasInfo>,
+    actor_gas: ActorGasUsage,
+}
+
+/// The result of successfully checking a message before applying it.
+struct Preflight {
+    /// The ID of the message's sender.
+    sender_id: ActorID,
+    /// The ID of the actor paying for the message's gas: the sender, or its sponsor.
+    payer_id: ActorID,
+    /// The gas cost prepaid by the payer.
+    gas_cost: TokenAmount,
+    /// The gas charged for including the message on chain.
+    inclusion_cost: GasCharge,
+    /// The gas charged for validating the message, if it was sent by an abstract account.
+    validation_cost: Option<GasCharge>,
+    /// The gas subsidy covering the message, if any.
+    subsidy: Option<GasSubsidy>,
+}
 
 /// The default [`Executor`].
 ///
@@ -35,6 +76,22 @@ pub struct DefaultExecutor<K: Kernel> {
     engine_pool: EnginePool,
     // If the inner value is `None` it means the machine got poisoned and is unusable.
     machine: Option<<K::CallManager as CallManager>::Machine>,
+    /// Gas usage by actor code and method, accumulated since the executor was created or the
+    /// usage was last taken.
+    actor_gas: ActorGasUsage,
+    /// The gas used by subsidized messages, against the subsidies' quotas.
+    subsidy_quotas: SubsidyQuotas,
+    /// The balances as of the last flush, if checking supply conservation.
+    supply_baseline: Option<Balances>,
+    /// The change in the total supply expected by the next flush.
+    expected_supply_change: TokenAmount,
+    /// Memoized message results, if enabled.
+    result_cache: Option<CacheScope>,
+    /// The events of the messages executed since the executor was created, or the events were
+    /// last taken.
+    block_events: EventAccumulator,
+    /// The number of messages applied since the executor was created or reset.
+    messages_applied: u64,
 }
 
 impl<K: Kernel> Deref for DefaultExecutor<K> {
@@ -51,34 +108,35 @@ impl<K: Kernel> DerefMut for DefaultExecutor<K> {
     }
 }
 
-impl<K> Executor for DefaultExecutor<K>
+impl<K> DefaultExecutor<K>
 where
     K: Kernel,
 {
-    type Kernel = K;
-
-    /// This is the entrypoint to execute a message.
-    fn execute_message(
+    /// Applies a message. See [`Executor::execute_message`].
+    fn apply_message(
         &mut self,
-        msg: Message,
+        mut msg: Message,
         apply_kind: ApplyKind,
         raw_length: usize,
     ) -> anyhow::Result<ApplyRet> {
         // Validate if the message was correct, charge for it, and extract some preliminary data.
-        let (sender_id, gas_cost, inclusion_cost) =
-            match self.preflight_message(&msg, apply_kind, raw_length)? {
-                Ok(res) => res,
-                Err(apply_ret) => return Ok(apply_ret),
-            };
+        let Preflight {
+            sender_id,
+            payer_id,
+            gas_cost,
+            inclusion_cost,
+            validation_cost,
+            subsidy,
+        } = match self.preflight_message(&msg, apply_kind, raw_length, true, true)? {
+            Ok(res) => res,
+            Err(apply_ret) => return Ok(apply_ret),
+        };
 
-        struct MachineExecRet {
-            result: crate::kernel::error::Result<InvocationResult>,
-            gas_used: i64,
-            backtrace: Backtrace,
-            exec_trace: ExecutionTrace,
-            events_root: Option<Cid>,
-            events: Vec<StampedEvent>, // TODO consider removing if nothing in the client ends up using it.
-        }
+        self.set_message_cid(&msg)?;
+
+        // Move the params into their block, instead of copying them (they may be large). Nothing
+        // reads them from the message past this point.
+        let params = message_params(msg.method_num, std::mem::take(&mut msg.params));
 
         // Acquire an engine from the pool. This may block if there are concurrently executing
         // messages inside other executors sharing the same pool.
@@ -90,7 +148,7 @@ where
             let mut cm = K::CallManager::new(
                 machine,
                 engine,
-                msg.gas_limit,
+                (apply_kind != ApplyKind::Unlimited).then_some(msg.gas_limit),
                 sender_id,
                 msg.from,
                 msg.sequence,
@@ -98,46 +156,13 @@ where
             );
             // This error is fatal because it should have already been accounted for inside
             // preflight_message.
-            if let Err(e) = cm.charge_gas(inclusion_cost) {
-                return (Err(e), cm.finish().1);
-            }
-
-            let params = (!msg.params.is_empty()).then(|| {
-                Block::new(
-                    if msg.method_num == METHOD_SEND {
-                        // Method zero params are "arbitrary bytes", so we'll just count them as
-                        // raw.
-                        //
-                        // This won't actually affect anything (because no code will see these
-                        // parameters), but it's more correct and makes me happier.
-                        //
-                        // NOTE: this _may_ start to matter once we start _validating_ ipld (m2.2).
-                        IPLD_RAW
-                    } else {
-                        // TODO: This should probably be CBOR
-                        // See #987.
-                        DAG_CBOR
-                    },
-                    msg.params.bytes(),
-                )
-            });
-
-            let result = cm.with_transaction(false, |cm| {
-                // Invoke the message.
-                let ret =
-                    cm.send::<K>(sender_id, msg.to, msg.method_num, params, &msg.value, None)?;
-
-                // Charge for including the result (before we end the transaction).
-                if let Some(value) = &ret.value {
-                    let _ = cm.charge_gas(
-                        cm.context()
-                            .price_list
-                            .on_chain_return_value(value.size() as usize),
-                    )?;
+            for charge in std::iter::once(inclusion_cost).chain(validation_cost) {
+                if let Err(e) = cm.charge_gas(charge) {
+                    return (Err(e), cm.finish().1);
                 }
+            }
 
-                Ok(ret)
-            });
+            let result = apply_call::<K>(&mut cm, sender_id, &msg, params, None);
             let (res, machine) = cm.finish();
 
             // Flush all events to the store.
@@ -154,6 +179,8 @@ where
                     exec_trace: res.exec_trace,
                     events_root,
                     events: res.events,
+                    out_of_gas: res.out_of_gas,
+                    actor_gas: res.actor_gas,
                 }),
                 machine,
             )
@@ -166,115 +193,280 @@ where
             exec_trace,
             events_root,
             events,
+            out_of_gas,
+            actor_gas,
         } = ret;
+        self.actor_gas.merge(&actor_gas);
 
         // Extract the exit code and build the result of the message application.
-        let receipt = match res {
-            Ok(InvocationResult { exit_code, value }) => {
-                // Convert back into a top-level return "value". We throw away the codec here,
-                // unfortunately.
-                let return_data = value
-                    .map(|blk| RawBytes::from(blk.data().to_vec()))
-                    .unwrap_or_default();
+        let receipt = self.make_receipt(&msg, res, gas_used, events_root, &mut backtrace);
 
-                if exit_code.is_success() {
-                    backtrace.clear();
+        let apply_ret = self.complete_message(
+            apply_kind,
+            payer_id,
+            msg,
+            receipt,
+            backtrace,
+            out_of_gas,
+            gas_cost,
+            exec_trace,
+            events,
+            subsidy.as_ref().map(|s| s.payer),
+        )?;
+        if let Some(subsidy) = &subsidy {
+            self.subsidy_quotas
+                .record(subsidy, apply_ret.msg_receipt.gas_used);
+        }
+        Ok(apply_ret)
+    }
+
+    /// Applies a batch of messages. See [`Executor::execute_batch`].
+    fn apply_batch(
+        &mut self,
+        mut msgs: Vec<Message>,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<BatchApplyRet> {
+        if !self.context().message_batching {
+            return Err(anyhow!(
+                "message batching isn't enabled at network version {}",
+                self.context().network_version
+            ));
+        }
+
+        // Validate the batch, and combine it into a single message that pays for gas.
+        let batch_msg = match combine_batch(&msgs, self.context().max_message_params_size) {
+            Ok(msg) => msg,
+            Err(reason) => {
+                let gas_limit = msgs
+                    .iter()
+                    .fold(0i64, |acc, m| acc.saturating_add(m.gas_limit.max(0)));
+                return Ok(BatchApplyRet {
+                    apply_ret: ApplyRet::prevalidation_fail(
+                        ExitCode::SYS_SENDER_STATE_INVALID,
+                        reason,
+                        &self.context().base_fee * gas_limit,
+                    ),
+                    receipts: Vec::new(),
+                });
+            }
+        };
+
+        // Abstract accounts can't send batches, as they'd only validate the first message. Batches
+        // aren't subsidized either.
+        let Preflight {
+            sender_id,
+            gas_cost,
+            inclusion_cost,
+            ..
+        } = match self.preflight_message(&batch_msg, apply_kind, raw_length, false, false)? {
+            Ok(res) => res,
+            Err(apply_ret) => {
+                return Ok(BatchApplyRet {
+                    apply_ret,
+                    receipts: Vec::new(),
+                })
+            }
+        };
+
+        // The preflight check only consumed the first message's sequence number.
+        if apply_kind == ApplyKind::Explicit && msgs.len() > 1 {
+            self.state_tree_mut()
+                .mutate_actor(sender_id, |act| {
+                    act.sequence += msgs.len() as u64 - 1;
+                    Ok(())
+                })
+                .context("failed to update the sender's sequence")?;
+        }
+
+        self.set_message_cid(&batch_msg)?;
+        let engine = self.engine_pool.acquire();
+
+        // Apply the messages in order, in a single transaction that's reverted if any of them
+        // fails. Each message's result and gas usage is recorded.
+        let mut outcomes = Vec::with_capacity(msgs.len());
+        let ret = self.map_machine(|machine| {
+            let mut cm = K::CallManager::new(
+                machine,
+                engine,
+                (apply_kind != ApplyKind::Unlimited).then_some(batch_msg.gas_limit),
+                sender_id,
+                batch_msg.from,
+                batch_msg.sequence,
+                batch_msg.gas_premium.clone(),
+            );
+            if let Err(e) = cm.charge_gas(inclusion_cost) {
+                return (Err(e), cm.finish().1);
+            }
+
+            let result = cm.with_transaction(false, |cm| {
+                let mut last = InvocationResult::default();
+                for msg in &mut msgs {
+                    let before = cm.gas_tracker().gas_used();
+                    let params = message_params(msg.method_num, std::mem::take(&mut msg.params));
+                    // Each message is limited to its own gas limit, not the batch's.
+                    let gas_limit = (apply_kind != ApplyKind::Unlimited).then_some(msg.gas_limit);
+                    let mut res = apply_call::<K>(cm, sender_id, msg, params, gas_limit);
+                    let gas_used = (cm.gas_tracker().gas_used() - before).round_up();
+                    if matches!(gas_limit, Some(limit) if gas_used > limit) {
+                        // The message ran out of gas charging for its return value.
+                        res = Ok(InvocationResult {
+                            exit_code: ExitCode::SYS_OUT_OF_GAS,
+                            value: None,
+                        });
+                    }
+                    match res {
+                        Ok(ret) if ret.exit_code.is_success() => {
+                            outcomes.push((Ok(ret.clone()), gas_used));
+                            last = ret;
+                        }
+                        res => {
+                            // Stop here, and fail the transaction to revert the batch. The real
+                            // failure is reported through the outcome.
+                            let exit_code = match &res {
+                                Ok(ret) => ret.exit_code,
+                                Err(_) => ExitCode::SYS_ASSERTION_FAILED,
+                            };
+                            outcomes.push((res, gas_used));
+                            return Ok(InvocationResult {
+                                exit_code,
+                                value: None,
+                            });
+                        }
+                    }
                 }
-                Receipt {
-                    exit_code,
-                    return_data,
-                    gas_used,
+                Ok(last)
+            });
+            let (res, machine) = cm.finish();
+
+            let events_root = match machine.commit_events(res.events.as_slice()) {
+                Ok(cid) => cid,
+                Err(e) => return (Err(e), machine),
+            };
+
+            (
+                Ok(MachineExecRet {
+                    result,
+                    gas_used: res.gas_used,
+                    backtrace: res.backtrace,
+                    exec_trace: res.exec_trace,
                     events_root,
-                }
-            }
-            Err(ExecutionError::OutOfGas) => Receipt {
-                exit_code: ExitCode::SYS_OUT_OF_GAS,
-                return_data: Default::default(),
-                gas_used,
-                events_root,
-            },
-            Err(ExecutionError::Syscall(err)) => {
-                // Errors indicate the message couldn't be dispatched at all
-                // (as opposed to failing during execution of the receiving actor).
-                // These errors are mapped to exit codes that persist on chain.
-                let exit_code = match err.1 {
-                    ErrorNumber::InsufficientFunds => ExitCode::SYS_INSUFFICIENT_FUNDS,
-                    ErrorNumber::NotFound => ExitCode::SYS_INVALID_RECEIVER,
-                    _ => ExitCode::SYS_ASSERTION_FAILED,
-                };
+                    events: res.events,
+                    out_of_gas: res.out_of_gas,
+                    actor_gas: res.actor_gas,
+                }),
+                machine,
+            )
+        })?;
 
-                backtrace.begin(backtrace::Cause::from_syscall("send", "send", err));
-                Receipt {
-                    exit_code,
-                    return_data: Default::default(),
-                    gas_used,
+        let MachineExecRet {
+            result,
+            gas_used,
+            mut backtrace,
+            exec_trace,
+            events_root,
+            events,
+            out_of_gas,
+            actor_gas,
+        } = ret;
+        self.actor_gas.merge(&actor_gas);
+
+        // Build the receipts of the executed messages. The batch's receipt is that of the failed
+        // message, if any, but with the batch's gas usage.
+        let mut receipts = Vec::with_capacity(outcomes.len());
+        let mut failed = None;
+        for ((res, msg_gas_used), msg) in outcomes.into_iter().zip(&msgs) {
+            let fatal = matches!(res, Err(ExecutionError::Fatal(_)));
+            // Only the failed message (always the last) contributes to the batch's backtrace.
+            let receipt = if matches!(&res, Ok(ret) if ret.exit_code.is_success()) {
+                self.make_receipt(msg, res, msg_gas_used, None, &mut Backtrace::default())
+            } else {
+                self.make_receipt(msg, res, msg_gas_used, None, &mut backtrace)
+            };
+            if !receipt.exit_code.is_success() {
+                failed = Some(Receipt {
+                    gas_used: if fatal { batch_msg.gas_limit } else { gas_used },
                     events_root,
-                }
+                    ..receipt.clone()
+                });
             }
-            Err(ExecutionError::Fatal(err)) => {
-                // We produce a receipt with SYS_ASSERTION_FAILED exit code, and
-                // we consume the full gas amount so that, in case of a network-
-                // wide fatal errors, all nodes behave deterministically.
-                //
-                // We set the backtrace from the fatal error to aid diagnosis.
-                // Note that we use backtrace#set_cause instead of backtrace#begin
-                // because we want to retain the propagation chain that we've
-                // accumulated on the way out.
-                let err = err.context(format!(
-                    "[from={}, to={}, seq={}, m={}, h={}]",
-                    msg.from,
-                    msg.to,
-                    msg.sequence,
-                    msg.method_num,
-                    self.context().epoch,
-                ));
-                backtrace.set_cause(backtrace::Cause::from_fatal(err));
-                Receipt {
-                    exit_code: ExitCode::SYS_ASSERTION_FAILED,
-                    return_data: Default::default(),
-                    gas_used: msg.gas_limit,
-                    events_root,
-                }
+            receipts.push(receipt);
+        }
+        let receipt = match (failed, result) {
+            (_, Err(e)) => {
+                self.make_receipt(&batch_msg, Err(e), gas_used, events_root, &mut backtrace)
+            }
+            (Some(receipt), _) => receipt,
+            (None, res) => {
+                self.make_receipt(&batch_msg, res, gas_used, events_root, &mut backtrace)
             }
         };
 
-        let failure_info = if backtrace.is_empty() || receipt.exit_code.is_success() {
-            None
-        } else {
-            Some(ApplyFailure::MessageBacktrace(backtrace))
-        };
+        let apply_ret = self.complete_message(
+            apply_kind, sender_id, batch_msg, receipt, backtrace, out_of_gas, gas_cost, exec_trace,
+            events, None,
+        )?;
+        Ok(BatchApplyRet {
+            apply_ret,
+            receipts,
+        })
+    }
+}
 
-        match apply_kind {
-            ApplyKind::Explicit => self.finish_message(
-                sender_id,
-                msg,
-                receipt,
-                failure_info,
-                gas_cost,
-                exec_trace,
-                events,
-            ),
-            ApplyKind::Implicit => Ok(ApplyRet {
-                msg_receipt: receipt,
-                penalty: TokenAmount::zero(),
-                miner_tip: TokenAmount::zero(),
-                base_fee_burn: TokenAmount::zero(),
-                over_estimation_burn: TokenAmount::zero(),
-                refund: TokenAmount::zero(),
-                gas_refund: 0,
-                gas_burned: 0,
-                failure_info,
-                exec_trace,
-                events,
-            }),
+impl<K> Executor for DefaultExecutor<K>
+where
+    K: Kernel,
+{
+    type Kernel = K;
+
+    /// This is the entrypoint to execute a message.
+    fn execute_message(
+        &mut self,
+        msg: Message,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<ApplyRet> {
+        let ret = match self.result_cache.take() {
+            Some(mut scope) => {
+                let ret = self.execute_cached(&mut scope, msg, apply_kind, raw_length);
+                self.result_cache = Some(scope);
+                ret
+            }
+            None => self.cancellable(|this| this.apply_message(msg, apply_kind, raw_length)),
+        }?;
+        self.block_events.push(&ret);
+        self.messages_applied += 1;
+        Ok(ret)
+    }
+
+    fn execute_batch(
+        &mut self,
+        msgs: Vec<Message>,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<BatchApplyRet> {
+        let count = msgs.len() as u64;
+        let ret = self.cancellable(|this| this.apply_batch(msgs, apply_kind, raw_length))?;
+        if let Some(scope) = &mut self.result_cache {
+            scope.invalidate();
         }
+        self.block_events.push(&ret.apply_ret);
+        self.messages_applied += count;
+        Ok(ret)
     }
 
     /// Flush the state-tree to the underlying blockstore.
     fn flush(&mut self) -> anyhow::Result<Cid> {
         let k = (**self).flush()?;
+        // Balances are read from the flushed state tree.
+        self.check_supply()?;
         Ok(k)
     }
+
+    fn join_flushes(&mut self) -> anyhow::Result<()> {
+        (**self).join_flushes()?;
+        Ok(())
+    }
 }
 
 impl<K> DefaultExecutor<K>
@@ -298,12 +490,214 @@ where
                 machine.builtin_actors().builtin_actor_codes(),
             )?;
         }
+        let supply_baseline = if machine.context().supply_check {
+            Some(Balances::load(machine.state_tree())?)
+        } else {
+            None
+        };
         Ok(Self {
             engine_pool,
             machine: Some(machine),
+            actor_gas: ActorGasUsage::new(),
+            subsidy_quotas: SubsidyQuotas::default(),
+            supply_baseline,
+            expected_supply_change: TokenAmount::zero(),
+            result_cache: None,
+            block_events: EventAccumulator::new(),
+            messages_applied: 0,
         })
     }
 
+    /// Records tokens minted (or, if negative, destroyed) outside of message execution, e.g., by
+    /// writing balances directly into the state tree. The next flush expects the total supply to
+    /// change by the sum of the recorded amounts. Only relevant when
+    /// [`MachineContext::supply_check`] is enabled.
+    ///
+    /// [`MachineContext::supply_check`]: crate::machine::MachineContext::supply_check
+    pub fn expect_supply_change(&mut self, change: &TokenAmount) {
+        self.expected_supply_change += change;
+    }
+
+    /// Sets the cache of message results to use. `tipset` identifies the tipset the externs read
+    /// chain randomness, tipset CIDs, and consensus faults from (e.g., the parent tipset's key), so
+    /// results are only shared between executors whose externs return the same values. See
+    /// [`ResultCache`].
+    pub fn set_result_cache(&mut self, cache: ResultCache, tipset: Cid) {
+        let scope = CacheScope::new(cache, tipset, self.context(), self.messages_applied);
+        self.result_cache = Some(scope);
+    }
+
+    /// Stops caching message results.
+    pub fn disable_result_cache(&mut self) {
+        self.result_cache = None;
+    }
+
+    /// Applies a message, or reuses the result of applying it to the same state before. See
+    /// [`ResultCache`].
+    fn execute_cached(
+        &mut self,
+        scope: &mut CacheScope,
+        msg: Message,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<ApplyRet> {
+        let message = msg.cid()?;
+        let key = if self.context().gas_subsidies.is_empty() {
+            scope.key(
+                self.messages_applied,
+                message,
+                apply_kind,
+                raw_length,
+                self.context(),
+            )
+        } else {
+            None
+        };
+        if let Some(cached) = key.as_ref().and_then(|key| scope.cache.get(key)) {
+            if self.cancellable(|this| this.apply_cached(&cached))? {
+                scope.advance(&message, apply_kind, raw_length);
+                return Ok(cached.apply_ret.clone());
+            }
+        }
+
+        // Set the usage accumulated so far aside to record the message's own usage.
+        let dirty = self.state_tree().dirty_actors();
+        let actor_gas_before = std::mem::take(&mut self.actor_gas);
+        let res = self.cancellable(|this| this.apply_message(msg, apply_kind, raw_length));
+        let actor_gas = std::mem::replace(&mut self.actor_gas, actor_gas_before);
+        self.actor_gas.merge(&actor_gas);
+        let apply_ret = res?;
+
+        scope.advance(&message, apply_kind, raw_length);
+        if let Some(key) = key {
+            scope.cache.insert(
+                key,
+                CachedResult {
+                    apply_ret: apply_ret.clone(),
+                    actor_gas,
+                    changes: changes_since(&dirty, self.state_tree().dirty_actors()),
+                },
+            );
+        }
+        Ok(apply_ret)
+    }
+
+    /// Applies the state changes and actor gas usage of a cached result, unless the blocks they
+    /// reference aren't in the blockstore. Returns true if the result was applied.
+    fn apply_cached(&mut self, cached: &CachedResult) -> anyhow::Result<bool> {
+        for (_, actor) in &cached.changes {
+            match actor {
+                Some(actor) if !self.blockstore().has(&actor.state)? => return Ok(false),
+                _ => {}
+            }
+        }
+        // Rewrite the events AMT, which the receipt references.
+        self.commit_events(&cached.apply_ret.events)?;
+        for (id, actor) in &cached.changes {
+            match actor {
+                Some(actor) => self.state_tree_mut().set_actor(*id, actor.clone())?,
+                None => self.state_tree_mut().delete_actor(*id)?,
+            }
+        }
+        self.actor_gas.merge(&cached.actor_gas);
+        Ok(true)
+    }
+
+    /// Runs `f`, applying a message, unless the machine's cancellation token has been cancelled.
+    /// If the token is cancelled before `f` returns, the message is rolled back and it fails with
+    /// a [`Cancelled<ExecutionProgress>`](Cancelled) error, so cancelled messages are never
+    /// partially applied. See [`crate::cancel`].
+    fn cancellable<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let token = match &self.context().cancellation {
+            Some(token) => token.clone(),
+            None => return f(self),
+        };
+        let progress = ExecutionProgress {
+            messages: self.messages_applied,
+        };
+        token.check(|| progress)?;
+
+        let checkpoint = Checkpoint::new(self);
+        let ret = f(self);
+        if token.is_cancelled() {
+            checkpoint.rollback(self)?;
+            return Err(Cancelled { progress }.into());
+        }
+        checkpoint.commit(self)?;
+        ret
+    }
+
+    /// Checks that the total supply changed as expected since the previous check, failing with a
+    /// [`SupplyViolation`](super::supply::SupplyViolation) if not. The state tree must have just
+    /// been flushed. Either way, the next check is relative to the current balances.
+    fn check_supply(&mut self) -> anyhow::Result<()> {
+        let before = match &self.supply_baseline {
+            Some(before) => before,
+            None => return Ok(()),
+        };
+        let after = Balances::load(self.state_tree())?;
+        let expected_change = std::mem::take(&mut self.expected_supply_change);
+        let res = after.check_since(before, &expected_change);
+        self.supply_baseline = Some(after);
+        Ok(res?)
+    }
+
+    /// Returns the gas usage by actor code and method accumulated over the messages executed since
+    /// the executor was created, or since the usage was last taken. Usage is only recorded if
+    /// enabled with [`MachineContext::enable_actor_gas_accounting`].
+    ///
+    /// [`MachineContext::enable_actor_gas_accounting`]: crate::machine::MachineContext::enable_actor_gas_accounting
+    pub fn actor_gas_usage(&self) -> &ActorGasUsage {
+        &self.actor_gas
+    }
+
+    /// Returns and resets the accumulated gas usage by actor code and method, e.g., at the end of
+    /// each block. See [`DefaultExecutor::actor_gas_usage`].
+    pub fn take_actor_gas_usage(&mut self) -> ActorGasUsage {
+        std::mem::take(&mut self.actor_gas)
+    }
+
+    /// Returns the events of the messages executed since the executor was created, or since the
+    /// events were last taken. Each message (or batch) executed is recorded in order, whether or not
+    /// it emitted events.
+    pub fn block_events(&self) -> &EventAccumulator {
+        &self.block_events
+    }
+
+    /// Returns and resets the accumulated events at the end of each block. Commit them with
+    /// [`EventAccumulator::flush`]. See [`DefaultExecutor::block_events`].
+    pub fn take_block_events(&mut self) -> EventAccumulator {
+        std::mem::take(&mut self.block_events)
+    }
+
+    /// Returns the executor to the state it was created in: resets the machine to its initial state
+    /// root (see [`Machine::reset`]), and forgets the accumulated actor gas usage, gas subsidy
+    /// usage, block events, and expected supply changes. Fails if the machine was poisoned, or can't be reset.
+    pub fn reset(&mut self) -> anyhow::Result<()> {
+        let machine = self
+            .machine
+            .as_mut()
+            .ok_or_else(|| anyhow!("machine poisoned"))?;
+        machine.reset()?;
+        self.actor_gas = ActorGasUsage::new();
+        self.subsidy_quotas = SubsidyQuotas::default();
+        self.block_events = EventAccumulator::new();
+        self.messages_applied = 0;
+        self.expected_supply_change = TokenAmount::zero();
+        if let Some(scope) = &mut self.result_cache {
+            scope.reset();
+        }
+        if self.supply_baseline.is_some() {
+            self.supply_baseline = Some(Balances::load(machine.state_tree())?);
+        }
+        Ok(())
+    }
+
+    /// Returns the pool of engines used to execute messages (e.g., to register native actors).
+    pub fn engine_pool(&self) -> &EnginePool {
+        &self.engine_pool
+    }
+
     /// Consume consumes the executor and returns the Machine. If the Machine had
     /// been poisoned during execution, the Option will be None.
     pub fn into_machine(self) -> Option<<K::CallManager as CallManager>::Machine> {
@@ -311,16 +705,21 @@ where
     }
 
     // TODO: The return type here is very strange because we have three cases:
-    //  1. Continue: Return sender ID, & gas).
+    //  1. Continue: Return the preflight results).
     //  2. Short-circuit: Return ApplyRet).
     //  3. Fail: Return an error).
     //  We could use custom types, but that would be even more annoying.
+    //
+    // Messages from abstract accounts are only accepted if `allow_validation` is set, and
+    // messages are only subsidized if `allow_subsidy` is set.
     fn preflight_message(
         &mut self,
         msg: &Message,
         apply_kind: ApplyKind,
         raw_length: usize,
-    ) -> Result<StdResult<(ActorID, TokenAmount, GasCharge), ApplyRet>> {
+        allow_validation: bool,
+        allow_subsidy: bool,
+    ) -> Result<StdResult<Preflight, ApplyRet>> {
         msg.check().or_fatal()?;
 
         // TODO We don't like having price lists _inside_ the FVM, but passing
@@ -328,7 +727,7 @@ where
         let pl = &self.context().price_list;
 
         let (inclusion_cost, miner_penalty_amount) = match apply_kind {
-            ApplyKind::Implicit => (
+            ApplyKind::Implicit | ApplyKind::Unlimited => (
                 GasCharge::new("none", Gas::zero(), Gas::zero()),
                 Default::default(),
             ),
@@ -346,6 +745,21 @@ where
                 }
 
                 let miner_penalty_amount = &self.context().base_fee * msg.gas_limit;
+
+                // Verify the params don't exceed the network's limit.
+                let max_params_size = self.context().max_message_params_size;
+                if msg.params.len() > max_params_size as usize {
+                    return Ok(Err(ApplyRet::prevalidation_fail(
+                        ExitCode::SYS_PARAMS_TOO_LARGE,
+                        format!(
+                            "Params too large ({} > {})",
+                            msg.params.len(),
+                            max_params_size
+                        ),
+                        miner_penalty_amount,
+                    )));
+                }
+
                 (inclusion_cost, miner_penalty_amount)
             }
         };
@@ -366,8 +780,15 @@ where
             }
         };
 
-        if apply_kind == ApplyKind::Implicit {
-            return Ok(Ok((sender_id, TokenAmount::zero(), inclusion_cost)));
+        if apply_kind != ApplyKind::Explicit {
+            return Ok(Ok(Preflight {
+                sender_id,
+                payer_id: sender_id,
+                gas_cost: TokenAmount::zero(),
+                inclusion_cost,
+                validation_cost: None,
+                subsidy: None,
+            }));
         }
 
         let mut sender_state = match self
@@ -389,6 +810,7 @@ where
         // - an account actor
         // - an Ethereum Externally Owned Address
         // - a placeholder actor that has an f4 address in the EAM's namespace
+        // - any other actor validating the message, if account abstraction is enabled
 
         let mut sender_is_valid = self.builtin_actors().is_account_actor(&sender_state.code)
             || self
@@ -405,7 +827,14 @@ where
             sender_state.code = *self.builtin_actors().get_ethaccount_code();
         }
 
-        if !sender_is_valid {
+        let needs_validation = !sender_is_valid
+            && allow_validation
+            && self.context().account_abstraction
+            && !self
+                .builtin_actors()
+                .is_placeholder_actor(&sender_state.code);
+
+        if !sender_is_valid && !needs_validation {
             return Ok(Err(ApplyRet::prevalidation_fail(
                 ExitCode::SYS_SENDER_INVALID,
                 "Send not from valid sender",
@@ -425,56 +854,258 @@ where
             )));
         };
 
+        // Let abstract accounts validate the message, and designate who pays for it.
+        let (payer_id, validation_cost) = if needs_validation {
+            let gas_limit = (msg.gas_limit - inclusion_cost.total().round_up())
+                .min(self.context().validation_gas_limit);
+            match self.validate_message(msg, sender_id, gas_limit)? {
+                Ok((payer_id, gas_used)) => (
+                    payer_id,
+                    Some(GasCharge::new(
+                        "OnValidateMessage",
+                        Gas::new(gas_used),
+                        Gas::zero(),
+                    )),
+                ),
+                Err(reason) => {
+                    return Ok(Err(ApplyRet::prevalidation_fail(
+                        ExitCode::SYS_SENDER_INVALID,
+                        format!("Message validation failed: {}", reason),
+                        miner_penalty_amount,
+                    )));
+                }
+            }
+        } else {
+            (sender_id, None)
+        };
+
         sender_state.sequence += 1;
 
-        // Ensure from actor has enough balance to cover the gas cost of the message.
-        let gas_cost: TokenAmount = msg.gas_fee_cap.clone() * msg.gas_limit;
-        if sender_state.balance < gas_cost {
+        // The subsidy's account, if any, pays for gas instead of the sender (or its sponsor).
+        let subsidy = if allow_subsidy {
+            self.find_subsidy(msg)?
+        } else {
+            None
+        };
+        let payer_id = match subsidy.as_ref().map(|s| s.payer) {
+            Some(SubsidyPayer::Account(id)) => id,
+            _ => payer_id,
+        };
+
+        // The sponsor, if any, pays for gas instead of the sender.
+        let mut payer_state = if payer_id == sender_id {
+            None
+        } else {
+            match self
+                .state_tree()
+                .get_actor(payer_id)
+                .with_context(|| format!("failed to lookup actor {}", payer_id))?
+            {
+                Some(act) => Some(act),
+                None => {
+                    return Ok(Err(ApplyRet::prevalidation_fail(
+                        ExitCode::SYS_SENDER_INVALID,
+                        "Sponsor invalid",
+                        miner_penalty_amount,
+                    )));
+                }
+            }
+        };
+        let payer = payer_state.as_mut().unwrap_or(&mut sender_state);
+
+        // Ensure the payer has enough balance to cover the gas cost of the message, unless it's
+        // waived.
+        let gas_cost: TokenAmount = match subsidy.as_ref().map(|s| s.payer) {
+            Some(SubsidyPayer::Waived) => TokenAmount::zero(),
+            _ => msg.gas_fee_cap.clone() * msg.gas_limit,
+        };
+        if payer.balance < gas_cost {
             return Ok(Err(ApplyRet::prevalidation_fail(
                 ExitCode::SYS_SENDER_STATE_INVALID,
                 format!(
                     "Actor balance less than needed: {} < {}",
-                    sender_state.balance, gas_cost
+                    payer.balance, gas_cost
                 ),
                 miner_penalty_amount,
             )));
         }
 
-        sender_state.deduct_funds(&gas_cost)?;
+        payer.deduct_funds(&gas_cost)?;
 
-        // Update the actor in the state tree
+        // Update the actors in the state tree
         self.state_tree_mut().set_actor(sender_id, sender_state)?;
+        if let Some(payer_state) = payer_state {
+            self.state_tree_mut().set_actor(payer_id, payer_state)?;
+        }
+
+        Ok(Ok(Preflight {
+            sender_id,
+            payer_id,
+            gas_cost,
+            inclusion_cost,
+            validation_cost,
+            subsidy,
+        }))
+    }
 
-        Ok(Ok((sender_id, gas_cost, inclusion_cost)))
+    /// Returns the gas subsidy covering a message, if its recipient and method are subsidized
+    /// and the subsidy has enough quota left for its gas limit.
+    fn find_subsidy(&self, msg: &Message) -> Result<Option<GasSubsidy>> {
+        let subsidies = &self.context().gas_subsidies;
+        if subsidies.is_empty() {
+            return Ok(None);
+        }
+        let to = match self
+            .state_tree()
+            .lookup_id(&msg.to)
+            .with_context(|| format!("failed to lookup actor {}", &msg.to))?
+        {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        Ok(self
+            .subsidy_quotas
+            .find(subsidies, to, msg.method_num, msg.gas_limit)
+            .cloned())
+    }
+
+    /// Runs the validation phase of a message sent by an abstract account, with at most
+    /// `gas_limit` gas. Returns the ID of the actor paying for the message's gas and the gas used,
+    /// or the reason the message is invalid.
+    fn validate_message(
+        &mut self,
+        msg: &Message,
+        sender_id: ActorID,
+        gas_limit: i64,
+    ) -> Result<StdResult<(ActorID, i64), String>> {
+        self.set_message_cid(msg)?;
+        let engine = self.engine_pool.acquire();
+        let (max_blocks, max_bytes) = (
+            self.context().max_validation_reads as usize,
+            self.context()
+                .max_validation_read_bytes
+                .try_into()
+                .unwrap_or(usize::MAX),
+        );
+
+        let (res, gas_used) = self.map_machine(|machine| {
+            let mut cm = K::CallManager::new(
+                machine,
+                engine,
+                Some(gas_limit),
+                sender_id,
+                msg.from,
+                msg.sequence,
+                msg.gas_premium.clone(),
+            );
+            cm.limiter_mut().limit_state_reads(max_blocks, max_bytes);
+            let res = validate_call::<K>(&mut cm, sender_id, msg);
+            let (res_cm, machine) = cm.finish();
+            ((res, res_cm.gas_used), machine)
+        });
+
+        match res {
+            Ok(res) => Ok(res.map(|payer_id| (payer_id, gas_used))),
+            Err(ExecutionError::Fatal(e)) => Err(e),
+            Err(e) => Ok(Err(e.to_string())),
+        }
+    }
+
+    /// Builds the final result of applying a message, paying for gas if the message is explicit.
+    #[allow(clippy::too_many_arguments)]
+    fn complete_message(
+        &mut self,
+        apply_kind: ApplyKind,
+        payer_id: ActorID,
+        msg: Message,
+        receipt: Receipt,
+        backtrace: Backtrace,
+        out_of_gas: Option<OutOfGasInfo>,
+        gas_cost: TokenAmount,
+        exec_trace: ExecutionTrace,
+        events: Vec<StampedEvent>,
+        subsidy_payer: Option<SubsidyPayer>,
+    ) -> anyhow::Result<ApplyRet> {
+        let failure_info = if backtrace.is_empty() || receipt.exit_code.is_success() {
+            None
+        } else {
+            Some(ApplyFailure::MessageBacktrace(backtrace))
+        };
+
+        let out_of_gas_info = out_of_gas.filter(|_| receipt.exit_code == ExitCode::SYS_OUT_OF_GAS);
+
+        let trace_commitment = self
+            .context()
+            .trace_commitment
+            .then(|| trace::commitment(&exec_trace));
+
+        match apply_kind {
+            ApplyKind::Explicit => self.finish_message(
+                payer_id,
+                msg,
+                receipt,
+                failure_info,
+                out_of_gas_info,
+                gas_cost,
+                exec_trace,
+                trace_commitment,
+                events,
+                subsidy_payer,
+            ),
+            ApplyKind::Implicit | ApplyKind::Unlimited => Ok(ApplyRet {
+                msg_receipt: receipt,
+                penalty: TokenAmount::zero(),
+                miner_tip: TokenAmount::zero(),
+                base_fee_burn: TokenAmount::zero(),
+                over_estimation_burn: TokenAmount::zero(),
+                refund: TokenAmount::zero(),
+                gas_refund: 0,
+                gas_burned: 0,
+                subsidy: TokenAmount::zero(),
+                failure_info,
+                out_of_gas_info,
+                exec_trace,
+                trace_commitment,
+                events,
+            }),
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
     fn finish_message(
         &mut self,
-        sender_id: ActorID,
+        payer_id: ActorID,
         msg: Message,
         receipt: Receipt,
         failure_info: Option<ApplyFailure>,
+        out_of_gas_info: Option<OutOfGasInfo>,
         gas_cost: TokenAmount,
         exec_trace: ExecutionTrace,
+        trace_commitment: Option<TraceCommitment>,
         events: Vec<StampedEvent>,
+        subsidy_payer: Option<SubsidyPayer>,
     ) -> anyhow::Result<ApplyRet> {
         // NOTE: we don't support old network versions in the FVM, so we always burn.
+        let mut outputs = GasOutputs::compute(
+            receipt.gas_used,
+            msg.gas_limit,
+            &self.context().base_fee,
+            &msg.gas_fee_cap,
+            &msg.gas_premium,
+        );
+        if let Some(payer) = subsidy_payer {
+            outputs.subsidize(payer);
+        }
         let GasOutputs {
             base_fee_burn,
             over_estimation_burn,
             miner_penalty,
             miner_tip,
             refund,
+            subsidy,
             gas_refund,
             gas_burned,
-        } = GasOutputs::compute(
-            receipt.gas_used,
-            msg.gas_limit,
-            &self.context().base_fee,
-            &msg.gas_fee_cap,
-            &msg.gas_premium,
-        );
+        } = outputs;
 
         let mut transfer_to_actor = |addr: ActorID, amt: &TokenAmount| -> anyhow::Result<()> {
             if amt.is_negative() {
@@ -499,8 +1130,8 @@ where
 
         transfer_to_actor(BURNT_FUNDS_ACTOR_ID, &over_estimation_burn)?;
 
-        // refund unused gas
-        transfer_to_actor(sender_id, &refund)?;
+        // refund unused gas to the payer
+        transfer_to_actor(payer_id, &refund)?;
 
         if (&base_fee_burn + &over_estimation_burn + &refund + &miner_tip) != gas_cost {
             // Sanity check. This could be a fatal error.
@@ -515,12 +1146,101 @@ where
             refund,
             gas_refund,
             gas_burned,
+            subsidy,
             failure_info,
+            out_of_gas_info,
             exec_trace,
+            trace_commitment,
             events,
         })
     }
 
+    /// Builds the receipt for a message (or batch of messages) from the result of its execution,
+    /// recording the failure cause in the backtrace.
+    fn make_receipt(
+        &self,
+        msg: &Message,
+        res: crate::kernel::Result<InvocationResult>,
+        gas_used: i64,
+        events_root: Option<Cid>,
+        backtrace: &mut Backtrace,
+    ) -> Receipt {
+        match res {
+            Ok(InvocationResult { exit_code, value }) => {
+                // Convert back into a top-level return "value". We throw away the codec here,
+                // unfortunately.
+                let return_data = value
+                    .map(|blk| RawBytes::from(blk.data().to_vec()))
+                    .unwrap_or_default();
+
+                if exit_code.is_success() {
+                    backtrace.clear();
+                }
+                Receipt {
+                    exit_code,
+                    return_data,
+                    gas_used,
+                    events_root,
+                }
+            }
+            Err(ExecutionError::OutOfGas) => Receipt {
+                exit_code: ExitCode::SYS_OUT_OF_GAS,
+                return_data: Default::default(),
+                gas_used,
+                events_root,
+            },
+            Err(ExecutionError::Syscall(err)) => {
+                // Errors indicate the message couldn't be dispatched at all
+                // (as opposed to failing during execution of the receiving actor).
+                // These errors are mapped to exit codes that persist on chain.
+                let exit_code = err.exit_code();
+
+                backtrace.begin(backtrace::Cause::from_syscall("send", "send", err));
+                Receipt {
+                    exit_code,
+                    return_data: Default::default(),
+                    gas_used,
+                    events_root,
+                }
+            }
+            Err(ExecutionError::Fatal(err)) => {
+                // We produce a receipt with SYS_ASSERTION_FAILED exit code, and
+                // we consume the full gas amount so that, in case of a network-
+                // wide fatal errors, all nodes behave deterministically.
+                //
+                // We set the backtrace from the fatal error to aid diagnosis.
+                // Note that we use backtrace#set_cause instead of backtrace#begin
+                // because we want to retain the propagation chain that we've
+                // accumulated on the way out.
+                let err = err.context(format!(
+                    "[from={}, to={}, seq={}, m={}, h={}]",
+                    msg.from,
+                    msg.to,
+                    msg.sequence,
+                    msg.method_num,
+                    self.context().epoch,
+                ));
+                backtrace.set_cause(backtrace::Cause::from_fatal(err));
+                Receipt {
+                    exit_code: ExitCode::SYS_ASSERTION_FAILED,
+                    return_data: Default::default(),
+                    gas_used: msg.gas_limit,
+                    events_root,
+                }
+            }
+        }
+    }
+
+    /// Records the CID of the message about to be executed in the machine context, if dev
+    /// randomness is enabled (the randomness is derived from it). Otherwise, the CID isn't needed,
+    /// so it isn't computed.
+    fn set_message_cid(&mut self, msg: &Message) -> anyhow::Result<()> {
+        if self.context().network.dev_randomness {
+            self.context_mut().message_cid = Some(msg.cid()?);
+        }
+        Ok(())
+    }
+
     fn map_machine<F, T>(&mut self, f: F) -> T
     where
         F: FnOnce(
@@ -537,3 +1257,197 @@ where
         )
     }
 }
+
+/// The executor's state before applying a message, so that the message can be rolled back if it's
+/// cancelled. Nothing is copied: the state tree opens a transaction, the subsidy quotas track the
+/// quota used, and the gas usage of the message's actors is accumulated apart until committed.
+struct Checkpoint {
+    actor_gas: ActorGasUsage,
+}
+
+impl Checkpoint {
+    fn new<K: Kernel>(executor: &mut DefaultExecutor<K>) -> Self {
+        executor.state_tree_mut().begin_transaction(false);
+        executor.subsidy_quotas.checkpoint();
+        Checkpoint {
+            actor_gas: std::mem::take(&mut executor.actor_gas),
+        }
+    }
+
+    /// Keeps the message's effects.
+    fn commit<K: Kernel>(mut self, executor: &mut DefaultExecutor<K>) -> Result<()> {
+        executor.state_tree_mut().end_transaction(false)?;
+        executor.subsidy_quotas.commit();
+        self.actor_gas.merge(&executor.actor_gas);
+        executor.actor_gas = self.actor_gas;
+        Ok(())
+    }
+
+    /// Discards the message's effects.
+    fn rollback<K: Kernel>(self, executor: &mut DefaultExecutor<K>) -> Result<()> {
+        executor.state_tree_mut().end_transaction(true)?;
+        executor.subsidy_quotas.rollback();
+        executor.actor_gas = self.actor_gas;
+        Ok(())
+    }
+}
+
+/// Returns the parameters block of a message with the given method and parameters, if it has
+/// parameters. The parameters are moved into the block without being copied.
+fn message_params(method_num: MethodNum, params: RawBytes) -> Option<Block> {
+    (!params.is_empty()).then(|| {
+        Block::new(
+            if method_num == METHOD_SEND {
+                // Method zero params are "arbitrary bytes", so we'll just count them as
+                // raw.
+                //
+                // This won't actually affect anything (because no code will see these
+                // parameters), but it's more correct and makes me happier.
+                //
+                // NOTE: this _may_ start to matter once we start _validating_ ipld (m2.2).
+                IPLD_RAW
+            } else {
+                // TODO: This should probably be CBOR
+                // See #987.
+                DAG_CBOR
+            },
+            Vec::from(params),
+        )
+    })
+}
+
+/// Invokes a message with the given parameters block in its own transaction, charging for
+/// including its return value. The invocation is limited to `gas_limit`, if any.
+fn apply_call<K: Kernel>(
+    cm: &mut K::CallManager,
+    sender_id: ActorID,
+    msg: &Message,
+    params: Option<Block>,
+    gas_limit: Option<i64>,
+) -> crate::kernel::Result<InvocationResult> {
+    cm.with_transaction(false, |cm| {
+        // Invoke the message, limited to the given gas (if any).
+        let gas_limit = gas_limit.map(Gas::new);
+        let ret = cm.send::<K>(
+            sender_id,
+            msg.to,
+            msg.method_num,
+            params,
+            &msg.value,
+            gas_limit,
+        )?;
+
+        // Charge for including the result (before we end the transaction).
+        if let Some(value) = &ret.value {
+            let _ = cm.charge_gas(
+                cm.context()
+                    .price_list
+                    .on_chain_return_value(value.size() as usize),
+            )?;
+        }
+
+        Ok(ret)
+    })
+}
+
+/// Invokes the validation method of a message's sender and, if the sender designates one, of its
+/// sponsor, each in a read-only transaction. Returns the ID of the actor paying for the message's
+/// gas, or the reason the message is invalid.
+fn validate_call<K: Kernel>(
+    cm: &mut K::CallManager,
+    sender_id: ActorID,
+    msg: &Message,
+) -> crate::kernel::Result<StdResult<ActorID, String>> {
+    let ret = match invoke_validate::<K>(cm, sender_id, msg, false)? {
+        Ok(ret) => ret,
+        Err(reason) => return Ok(Err(reason)),
+    };
+    let sponsor_id = match ret.sponsor {
+        None => return Ok(Ok(sender_id)),
+        Some(sponsor) => match cm.state_tree().lookup_id(&sponsor)? {
+            Some(id) => id,
+            None => return Ok(Err(format!("sponsor {} not found", sponsor))),
+        },
+    };
+    if sponsor_id == sender_id {
+        return Ok(Ok(sender_id));
+    }
+    Ok(invoke_validate::<K>(cm, sponsor_id, msg, true)?.map(|_| sponsor_id))
+}
+
+/// Invokes the validation method of an actor in a read-only transaction, decoding its result.
+fn invoke_validate<K: Kernel>(
+    cm: &mut K::CallManager,
+    actor_id: ActorID,
+    msg: &Message,
+    sponsor: bool,
+) -> crate::kernel::Result<StdResult<ValidateReturn, String>> {
+    // Serialize the parameters as a borrowed tuple (the same encoding as `ValidateParams`) to
+    // avoid copying the message, which may have large params.
+    let params = Block::new(DAG_CBOR, to_vec(&(msg, sponsor)).or_fatal()?);
+    let ret = cm.with_transaction(true, |cm| {
+        cm.send::<K>(
+            SYSTEM_ACTOR_ID,
+            Address::new_id(actor_id),
+            VALIDATE_MESSAGE_METHOD,
+            Some(params),
+            &TokenAmount::zero(),
+            None,
+        )
+    })?;
+    if !ret.exit_code.is_success() {
+        return Ok(Err(format!(
+            "actor {} rejected the message with exit code {}",
+            actor_id, ret.exit_code
+        )));
+    }
+    Ok(match ret.value {
+        None => Ok(ValidateReturn::default()),
+        Some(value) => from_slice(value.data())
+            .map_err(|e| format!("actor {} returned an invalid result: {}", actor_id, e)),
+    })
+}
+
+/// Checks that a batch of messages can be applied together, returning a single message that
+/// stands in for the batch when paying for gas: the first message, with the batch's total gas
+/// limit and without params. Each message's params must fit within the given size limit.
+fn combine_batch(msgs: &[Message], max_params_size: u32) -> StdResult<Message, String> {
+    let first = msgs.first().ok_or("empty message batch")?;
+    let mut gas_limit: i64 = 0;
+    for (i, msg) in msgs.iter().enumerate() {
+        msg.check()
+            .map_err(|e| format!("invalid message {} in batch: {}", i, e))?;
+        if msg.from != first.from {
+            return Err(format!("message {} in batch has a different sender", i));
+        }
+        if first.sequence.checked_add(i as u64) != Some(msg.sequence) {
+            return Err(format!(
+                "message {} in batch has sequence {}, expected {}",
+                i,
+                msg.sequence,
+                first.sequence.wrapping_add(i as u64)
+            ));
+        }
+        if msg.gas_fee_cap != first.gas_fee_cap || msg.gas_premium != first.gas_premium {
+            return Err(format!("message {} in batch has a different gas price", i));
+        }
+        if msg.params.len() > max_params_size as usize {
+            return Err(format!("message {} in batch has params too large", i));
+        }
+        gas_limit = gas_limit
+            .checked_add(msg.gas_limit)
+            .ok_or("batch gas limit overflows")?;
+    }
+    Ok(Message {
+        version: first.version,
+        from: first.from,
+        to: first.to,
+        sequence: first.sequence,
+        value: first.value.clone(),
+        method_num: first.method_num,
+        params: RawBytes::default(),
+        gas_limit,
+        gas_fee_cap: first.gas_fee_cap.clone(),
+        gas_premium: first.gas_premium.clone(),
+    })
+}
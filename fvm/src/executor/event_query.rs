@@ -0,0 +1,466 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Event queries over executed blocks, for log filtering RPCs.
+//!
+//! Given the events of a range of blocks, as committed by their message receipts or by an
+//! [`EventAccumulator`] (see [`EventSource`]), and an [`EventFilter`] (emitters, entry key and
+//! value prefixes, and an epoch range), [`query_events`] returns the matching events a page at a
+//! time. Blocks whose [`BlockEvents`] bloom filters rule out the filter aren't loaded.
+//!
+//! [`EventAccumulator`]: super::EventAccumulator
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm_ipld_amt::Amt;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::event::{EventBloom, StampedEvent};
+use fvm_shared::receipt::Receipt;
+use fvm_shared::ActorID;
+
+use super::events::{BlockEvent, BlockEvents};
+
+/// A filter over events. An event matches if it was emitted by one of the emitters (or by any
+/// actor, if there are none), and every entry filter matches at least one of its entries.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// The actors whose events match, or empty to match events emitted by any actor.
+    pub emitters: Vec<ActorID>,
+    /// The filters the event's entries must match.
+    pub entries: Vec<EntryFilter>,
+    /// The first epoch to search, if any.
+    pub from_epoch: Option<ChainEpoch>,
+    /// The last epoch to search (inclusive), if any.
+    pub to_epoch: Option<ChainEpoch>,
+}
+
+/// A filter over the entries of an event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryFilter {
+    /// The key the entry must have.
+    pub key: KeyFilter,
+    /// The prefix of the entry's (encoded) value. Empty to match any value.
+    pub value_prefix: Vec<u8>,
+}
+
+/// How an [`EntryFilter`] matches entry keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyFilter {
+    /// The key must be equal to the given key. Exact keys are checked against the bloom filters
+    /// of blocks, when filtering by emitter.
+    Exact(String),
+    /// The key must start with the given prefix.
+    Prefix(String),
+}
+
+/// Where to find the events of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventSource {
+    /// The root of the AMT of the block's message receipts (in the order the messages were
+    /// applied), whose `events_root`s commit to the messages' events.
+    Receipts(Cid),
+    /// The [`BlockEvents`] committed for the block by an [`EventAccumulator`]. If the bloom filter
+    /// rules out the query's filter, the block's events aren't loaded.
+    ///
+    /// [`EventAccumulator`]: super::EventAccumulator
+    BlockEvents(Cid),
+}
+
+impl EventSource {
+    /// Returns the CID of the receipts AMT or [`BlockEvents`], identifying the block in cursors.
+    pub fn cid(&self) -> Cid {
+        match self {
+            EventSource::Receipts(cid) | EventSource::BlockEvents(cid) => *cid,
+        }
+    }
+}
+
+/// The position of an event in a sequence of blocks, for resuming a query after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventCursor {
+    /// The epoch of the block.
+    pub epoch: ChainEpoch,
+    /// The CID of the block's [`EventSource`].
+    pub block: Cid,
+    /// The index of the message that emitted the event, within the block.
+    pub message: u64,
+    /// The index of the event within the message's events.
+    pub index: u64,
+}
+
+/// An event matched by a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchedEvent {
+    /// The epoch of the block containing the event.
+    pub epoch: ChainEpoch,
+    /// The CID of the block's [`EventSource`].
+    pub block: Cid,
+    /// The event, and its position within the block.
+    pub event: BlockEvent,
+}
+
+impl MatchedEvent {
+    /// Returns the cursor for resuming the query after this event.
+    pub fn cursor(&self) -> EventCursor {
+        EventCursor {
+            epoch: self.epoch,
+            block: self.block,
+            message: self.event.message,
+            index: self.event.index,
+        }
+    }
+}
+
+/// A page of events returned by [`query_events`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EventPage {
+    /// The matching events, in order.
+    pub events: Vec<MatchedEvent>,
+    /// The cursor for the next page, if there are more matching events.
+    pub next: Option<EventCursor>,
+}
+
+impl EntryFilter {
+    /// Returns true if the entry filter matches any of the event's entries.
+    pub fn matches(&self, event: &StampedEvent) -> bool {
+        event.event().entries.iter().any(|entry| {
+            let key = match &self.key {
+                KeyFilter::Exact(key) => entry.key == *key,
+                KeyFilter::Prefix(prefix) => entry.key.starts_with(prefix.as_str()),
+            };
+            key && entry.value.starts_with(&self.value_prefix)
+        })
+    }
+}
+
+impl EventFilter {
+    /// Returns true if the filter matches the event. The epoch range isn't checked.
+    pub fn matches(&self, event: &StampedEvent) -> bool {
+        (self.emitters.is_empty() || self.emitters.contains(&event.emitter()))
+            && self.entries.iter().all(|filter| filter.matches(event))
+    }
+
+    /// Returns true if the epoch is within the filter's epoch range.
+    pub fn matches_epoch(&self, epoch: ChainEpoch) -> bool {
+        self.from_epoch.map_or(true, |from| epoch >= from)
+            && self.to_epoch.map_or(true, |to| epoch <= to)
+    }
+
+    /// Returns false if no event summarized by the bloom filter can match the filter. Blooms only
+    /// cover emitters, and keys paired with their emitters, so filters without emitters can't be
+    /// ruled out.
+    pub fn may_match(&self, bloom: &EventBloom) -> bool {
+        self.emitters.is_empty()
+            || self.emitters.iter().any(|&emitter| {
+                bloom.may_contain_emitter(emitter)
+                    && self.entries.iter().all(|filter| match &filter.key {
+                        KeyFilter::Exact(key) => bloom.may_contain(emitter, key),
+                        KeyFilter::Prefix(_) => true,
+                    })
+            })
+    }
+}
+
+/// Returns up to `limit` events matching the filter, from the blocks with the given epochs and
+/// event sources, starting after the cursor (if any).
+///
+/// The blocks must be given in execution order (by ascending epoch), and the same blocks must be
+/// given when resuming a query from a cursor. Fails if the cursor's block isn't among them, or if
+/// the limit is zero.
+pub fn query_events<BS, I>(
+    blockstore: &BS,
+    blocks: I,
+    filter: &EventFilter,
+    after: Option<&EventCursor>,
+    limit: usize,
+) -> anyhow::Result<EventPage>
+where
+    BS: Blockstore,
+    I: IntoIterator<Item = (ChainEpoch, EventSource)>,
+{
+    if limit == 0 {
+        return Err(anyhow!("event query limit must be positive"));
+    }
+
+    let mut events = Vec::new();
+    let mut more = false;
+    // Whether we've reached the cursor's block (or there is no cursor).
+    let mut resumed = after.is_none();
+
+    for (epoch, source) in blocks {
+        if filter.to_epoch.map_or(false, |to| epoch > to) {
+            break;
+        }
+        let block = source.cid();
+
+        // Skip to the cursor's block, and to the events after the cursor within it.
+        let start = match after {
+            Some(cursor) if !resumed => {
+                if epoch > cursor.epoch {
+                    return Err(anyhow!("cursor block {} not found", cursor.block));
+                }
+                if block != cursor.block {
+                    continue;
+                }
+                resumed = true;
+                Some((cursor.message, cursor.index))
+            }
+            _ => None,
+        };
+
+        if !filter.matches_epoch(epoch) {
+            continue;
+        }
+
+        // Collects the matching events of a message, given its events root.
+        let mut visit = |message: u64, root: &Cid| -> anyhow::Result<bool> {
+            if start.map_or(false, |(m, _)| message < m) {
+                return Ok(true);
+            }
+            let msg_events: Amt<StampedEvent, _> = Amt::load(root, blockstore)?;
+            msg_events.for_each_while(|index, event| {
+                if start.map_or(false, |start| (message, index) <= start) {
+                    return Ok(true);
+                }
+                if !filter.matches(event) {
+                    return Ok(true);
+                }
+                if events.len() == limit {
+                    more = true;
+                    return Ok(false);
+                }
+                events.push(MatchedEvent {
+                    epoch,
+                    block,
+                    event: BlockEvent {
+                        message,
+                        index,
+                        event: event.clone(),
+                    },
+                });
+                Ok(true)
+            })?;
+            Ok(!more)
+        };
+
+        match source {
+            EventSource::Receipts(root) => {
+                let receipts: Amt<Receipt, _> =
+                    Amt::load(&root, blockstore).context("failed to load receipts AMT")?;
+                receipts
+                    .for_each_while(|message, receipt| match &receipt.events_root {
+                        Some(root) => visit(message, root),
+                        None => Ok(true),
+                    })
+                    .context("failed to load receipt events")?;
+            }
+            EventSource::BlockEvents(cid) => {
+                let block_events = BlockEvents::load(blockstore, &cid)?;
+                if !filter.may_match(&block_events.bloom) {
+                    continue;
+                }
+                let roots: Amt<Cid, _> = Amt::load(&block_events.roots, blockstore)
+                    .context("failed to load events roots AMT")?;
+                roots
+                    .for_each_while(|message, root| visit(message, root))
+                    .context("failed to load block events")?;
+            }
+        }
+        if more {
+            break;
+        }
+    }
+
+    if !resumed {
+        let cursor = after.expect("unresumed query has a cursor");
+        return Err(anyhow!("cursor block {} not found", cursor.block));
+    }
+
+    let next = if more {
+        events.last().map(MatchedEvent::cursor)
+    } else {
+        None
+    };
+    Ok(EventPage { events, next })
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::event::{ActorEvent, Entry, Flags};
+
+    use super::*;
+    use crate::executor::EventAccumulator;
+    use crate::machine::EVENTS_AMT_BITWIDTH;
+
+    fn event(emitter: ActorID, key: &str, value: &[u8]) -> StampedEvent {
+        StampedEvent::new(
+            emitter,
+            ActorEvent::from(vec![Entry {
+                flags: Flags::FLAG_INDEXED_ALL,
+                key: key.into(),
+                value: RawBytes::new(value.to_vec()),
+            }]),
+        )
+    }
+
+    /// Commits a message's events, returning their root, as in the message's receipt.
+    fn events_root(bs: &MemoryBlockstore, events: &[StampedEvent]) -> Option<Cid> {
+        if events.is_empty() {
+            return None;
+        }
+        let mut amt = Amt::new_with_bit_width(bs, EVENTS_AMT_BITWIDTH);
+        amt.batch_set(events.iter().cloned()).unwrap();
+        Some(amt.flush().unwrap())
+    }
+
+    /// Commits a block of messages' events with an [`EventAccumulator`].
+    fn block(bs: &MemoryBlockstore, messages: &[Vec<StampedEvent>]) -> EventSource {
+        let mut acc = EventAccumulator::new();
+        for events in messages {
+            acc.push_events(events_root(bs, events), events);
+        }
+        EventSource::BlockEvents(acc.flush(bs).unwrap())
+    }
+
+    /// Commits a block of messages' receipts.
+    fn receipts(bs: &MemoryBlockstore, messages: &[Vec<StampedEvent>]) -> EventSource {
+        let mut amt = Amt::new(bs);
+        amt.batch_set(messages.iter().map(|events| Receipt {
+            exit_code: ExitCode::OK,
+            return_data: RawBytes::default(),
+            gas_used: 0,
+            events_root: events_root(bs, events),
+        }))
+        .unwrap();
+        EventSource::Receipts(amt.flush().unwrap())
+    }
+
+    fn positions(page: &EventPage) -> Vec<(ChainEpoch, u64, u64)> {
+        page.events
+            .iter()
+            .map(|e| (e.epoch, e.event.message, e.event.index))
+            .collect()
+    }
+
+    #[test]
+    fn query() {
+        check_query(block);
+    }
+
+    #[test]
+    fn query_receipts() {
+        check_query(receipts);
+    }
+
+    fn check_query(block: fn(&MemoryBlockstore, &[Vec<StampedEvent>]) -> EventSource) {
+        let bs = MemoryBlockstore::new();
+        let blocks = vec![
+            (
+                10,
+                block(
+                    &bs,
+                    &[
+                        vec![event(100, "transfer", b"\x01"), event(101, "mint", b"")],
+                        vec![event(100, "approval", b"")],
+                    ],
+                ),
+            ),
+            (11, block(&bs, &[vec![event(102, "transfer", b"\x01")]])),
+            (
+                12,
+                block(
+                    &bs,
+                    &[vec![
+                        event(100, "transfer", b"\x02"),
+                        event(100, "transfer", b"\x01\x02"),
+                    ]],
+                ),
+            ),
+        ];
+
+        let transfers = EventFilter {
+            entries: vec![EntryFilter {
+                key: KeyFilter::Exact("transfer".into()),
+                value_prefix: b"\x01".to_vec(),
+            }],
+            ..Default::default()
+        };
+        let page = query_events(&bs, blocks.clone(), &transfers, None, 10).unwrap();
+        assert_eq!(positions(&page), vec![(10, 0, 0), (11, 0, 0), (12, 0, 1)]);
+        assert_eq!(page.next, None);
+
+        // Filter by emitter and epoch.
+        let filter = EventFilter {
+            emitters: vec![100],
+            from_epoch: Some(11),
+            ..transfers.clone()
+        };
+        let page = query_events(&bs, blocks.clone(), &filter, None, 10).unwrap();
+        assert_eq!(positions(&page), vec![(12, 0, 1)]);
+
+        // Key prefixes.
+        let filter = EventFilter {
+            emitters: vec![100, 101],
+            entries: vec![EntryFilter {
+                key: KeyFilter::Prefix("a".into()),
+                value_prefix: vec![],
+            }],
+            to_epoch: Some(11),
+            ..Default::default()
+        };
+        let page = query_events(&bs, blocks.clone(), &filter, None, 10).unwrap();
+        assert_eq!(positions(&page), vec![(10, 1, 0)]);
+
+        // Paginate.
+        let mut after = None;
+        let mut found = Vec::new();
+        loop {
+            let page = query_events(&bs, blocks.clone(), &transfers, after.as_ref(), 1).unwrap();
+            found.extend(positions(&page));
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(found, vec![(10, 0, 0), (11, 0, 0), (12, 0, 1)]);
+
+        // Cursors must point into the given blocks.
+        let cursor = EventCursor {
+            epoch: 10,
+            block: blocks[1].1.cid(),
+            message: 0,
+            index: 0,
+        };
+        assert!(query_events(&bs, blocks.clone(), &transfers, Some(&cursor), 1).is_err());
+
+        // Pages can't be empty.
+        assert!(query_events(&bs, blocks, &transfers, None, 0).is_err());
+    }
+
+    #[test]
+    fn skip() {
+        let bs = MemoryBlockstore::new();
+        let source = block(&bs, &[vec![event(100, "transfer", b"")]]);
+        let cid = source.cid();
+
+        // Blocks ruled out by the bloom filter aren't loaded, beyond their bloom filter.
+        let empty = MemoryBlockstore::new();
+        empty
+            .put_keyed(&cid, &bs.get(&cid).unwrap().unwrap())
+            .unwrap();
+        let filter = EventFilter {
+            emitters: vec![200],
+            ..Default::default()
+        };
+        let page = query_events(&empty, [(1, source)], &filter, None, 10).unwrap();
+        assert!(page.events.is_empty());
+        let filter = EventFilter {
+            emitters: vec![100],
+            ..Default::default()
+        };
+        assert!(query_events(&empty, [(1, source)], &filter, None, 10).is_err());
+    }
+}
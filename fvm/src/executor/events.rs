@@ -0,0 +1,272 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Per-block event commitments.
+//!
+//! Each message receipt commits to the message's events with an AMT root. To find the events
+//! matching a filter, a node would have to load the events of every message in every block it
+//! scans. An [`EventAccumulator`] instead collects the events roots of all the messages in a block
+//! along with an [`EventBloom`] over their emitters and keys, and commits both as a single
+//! [`BlockEvents`] object. Log filters can then check the bloom filter, and skip blocks that
+//! definitely don't contain any matching events without loading them.
+//!
+//! The [`DefaultExecutor`](super::DefaultExecutor) accumulates the events of the messages it
+//! executes; take them at the end of each block with
+//! [`take_block_events`](super::DefaultExecutor::take_block_events).
+
+use anyhow::Context as _;
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_amt::Amt;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::CborStore;
+use fvm_shared::event::{EventBloom, Flags, StampedEvent};
+use fvm_shared::ActorID;
+
+use super::ApplyRet;
+use crate::machine::EVENTS_AMT_BITWIDTH;
+
+/// The events of a block, as committed by an [`EventAccumulator`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct BlockEvents {
+    /// The bloom filter over all the events in the block.
+    pub bloom: EventBloom,
+    /// The root of an AMT mapping the index of each message that emitted events (in the order
+    /// the messages were applied) to the root of the message's events AMT (as in its receipt).
+    pub roots: Cid,
+}
+
+/// An event in a block, as found by [`BlockEvents::find`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockEvent {
+    /// The index of the message that emitted the event, within the block.
+    pub message: u64,
+    /// The index of the event within the message's events.
+    pub index: u64,
+    pub event: StampedEvent,
+}
+
+/// Accumulates the events of the messages in a block. See the [module documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct EventAccumulator {
+    messages: u64,
+    roots: Vec<(u64, Cid)>,
+    bloom: EventBloom,
+}
+
+impl EventAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the events of the next message in the block, as returned by the executor.
+    pub fn push(&mut self, ret: &ApplyRet) {
+        self.push_events(ret.msg_receipt.events_root, &ret.events)
+    }
+
+    /// Records the events of the next message in the block, given the message's events root and
+    /// the events under it.
+    pub fn push_events(&mut self, events_root: Option<Cid>, events: &[StampedEvent]) {
+        if let Some(root) = events_root {
+            self.roots.push((self.messages, root));
+            for event in events {
+                self.bloom.add_event(event);
+            }
+        }
+        self.messages += 1;
+    }
+
+    /// Returns the number of messages recorded so far.
+    pub fn len(&self) -> u64 {
+        self.messages
+    }
+
+    /// Returns true if no messages have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.messages == 0
+    }
+
+    /// Returns the bloom filter over the events recorded so far.
+    pub fn bloom(&self) -> &EventBloom {
+        &self.bloom
+    }
+
+    /// Writes the accumulated [`BlockEvents`] to the blockstore, returning their CID.
+    pub fn flush<BS: Blockstore>(&self, blockstore: &BS) -> anyhow::Result<Cid> {
+        let mut amt = Amt::new_with_bit_width(blockstore, EVENTS_AMT_BITWIDTH);
+        for (i, root) in &self.roots {
+            amt.set(*i, *root)
+                .context("failed to add events root to AMT")?;
+        }
+        let roots = amt.flush().context("failed to flush events roots AMT")?;
+        blockstore
+            .put_cbor(
+                &BlockEvents {
+                    bloom: self.bloom.clone(),
+                    roots,
+                },
+                Code::Blake2b256,
+            )
+            .context("failed to write block events")
+    }
+}
+
+impl BlockEvents {
+    /// Loads the block events with the given CID.
+    pub fn load<BS: Blockstore>(blockstore: &BS, cid: &Cid) -> anyhow::Result<Self> {
+        blockstore
+            .get_cbor(cid)?
+            .with_context(|| format!("block events {cid} not found"))
+    }
+
+    /// Returns false if the block definitely contains no event emitted by `emitter` with an indexed
+    /// entry keyed `key` (or with any entries, if `key` is `None`).
+    pub fn may_contain(&self, emitter: ActorID, key: Option<&str>) -> bool {
+        match key {
+            Some(key) => self.bloom.may_contain(emitter, key),
+            None => self.bloom.may_contain_emitter(emitter),
+        }
+    }
+
+    /// Returns the events in the block emitted by `emitter` with an indexed entry keyed `key` (or
+    /// all the events emitted by `emitter`, if `key` is `None`). Entries whose keys aren't indexed
+    /// ([`Flags::FLAG_INDEXED_KEY`]) never match. The events are only loaded if the bloom filter
+    /// doesn't rule them out.
+    pub fn find<BS: Blockstore>(
+        &self,
+        blockstore: &BS,
+        emitter: ActorID,
+        key: Option<&str>,
+    ) -> anyhow::Result<Vec<BlockEvent>> {
+        let mut found = Vec::new();
+        if !self.may_contain(emitter, key) {
+            return Ok(found);
+        }
+
+        let roots: Amt<Cid, _> =
+            Amt::load(&self.roots, blockstore).context("failed to load events roots AMT")?;
+        roots
+            .for_each(|message, root| {
+                let events: Amt<StampedEvent, _> = Amt::load(root, blockstore)?;
+                events.for_each(|index, event| {
+                    let matches =
+                        event.emitter() == emitter
+                            && key.map_or(true, |key| {
+                                event.event().entries.iter().any(|e| {
+                                    e.key == key && e.flags.contains(Flags::FLAG_INDEXED_KEY)
+                                })
+                            });
+                    if matches {
+                        found.push(BlockEvent {
+                            message,
+                            index,
+                            event: event.clone(),
+                        });
+                    }
+                    Ok(())
+                })?;
+                Ok(())
+            })
+            .context("failed to load block events")?;
+        Ok(found)
+    }
+
+    /// Returns true if the block contains an event emitted by `emitter` with an indexed entry keyed
+    /// `key` (or with any entries, if `key` is `None`). Unlike [`BlockEvents::may_contain`], this is
+    /// exact: bloom filter matches are verified against the events themselves.
+    pub fn contains<BS: Blockstore>(
+        &self,
+        blockstore: &BS,
+        emitter: ActorID,
+        key: Option<&str>,
+    ) -> anyhow::Result<bool> {
+        Ok(!self.find(blockstore, emitter, key)?.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::event::{ActorEvent, Entry};
+
+    use super::*;
+
+    fn event(emitter: ActorID, key: &str) -> StampedEvent {
+        event_with_flags(emitter, key, Flags::FLAG_INDEXED_ALL)
+    }
+
+    fn event_with_flags(emitter: ActorID, key: &str, flags: Flags) -> StampedEvent {
+        StampedEvent::new(
+            emitter,
+            ActorEvent::from(vec![Entry {
+                flags,
+                key: key.into(),
+                value: RawBytes::default(),
+            }]),
+        )
+    }
+
+    /// Writes the events AMT of a message, like the machine does.
+    fn commit(bs: &MemoryBlockstore, events: &[StampedEvent]) -> Option<Cid> {
+        if events.is_empty() {
+            return None;
+        }
+        let mut amt = Amt::new_with_bit_width(bs, EVENTS_AMT_BITWIDTH);
+        amt.batch_set(events.iter().cloned()).unwrap();
+        Some(amt.flush().unwrap())
+    }
+
+    #[test]
+    fn accumulate() {
+        let bs = MemoryBlockstore::new();
+        let messages = [
+            vec![event(100, "transfer"), event(101, "mint")],
+            vec![],
+            vec![
+                event(100, "transfer"),
+                // Entries with unindexed keys don't match.
+                event_with_flags(100, "transfer", Flags::FLAG_INDEXED_VALUE),
+            ],
+        ];
+
+        let mut acc = EventAccumulator::new();
+        for events in &messages {
+            acc.push_events(commit(&bs, events), events);
+        }
+        assert_eq!(acc.len(), 3);
+
+        let cid = acc.flush(&bs).unwrap();
+        let block = BlockEvents::load(&bs, &cid).unwrap();
+        assert_eq!(&block.bloom, acc.bloom());
+
+        let found = block.find(&bs, 100, Some("transfer")).unwrap();
+        assert_eq!(
+            found
+                .iter()
+                .map(|e| (e.message, e.index))
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (2, 0)]
+        );
+        assert_eq!(found[1].event, messages[2][0]);
+
+        assert!(block.contains(&bs, 101, None).unwrap());
+        assert!(block.contains(&bs, 101, Some("mint")).unwrap());
+        assert!(!block.contains(&bs, 101, Some("transfer")).unwrap());
+        assert!(!block.may_contain(102, None));
+    }
+
+    #[test]
+    fn skip() {
+        let bs = MemoryBlockstore::new();
+        let events = [event(100, "transfer")];
+        let mut acc = EventAccumulator::new();
+        acc.push_events(commit(&bs, &events), &events);
+        let block = BlockEvents::load(&bs, &acc.flush(&bs).unwrap()).unwrap();
+
+        // Blocks ruled out by the bloom filter aren't loaded, so the events can be missing.
+        let empty = MemoryBlockstore::new();
+        assert!(block.find(&empty, 200, None).unwrap().is_empty());
+        assert!(block.find(&empty, 100, None).is_err());
+    }
+}
@@ -1,12 +1,22 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+mod cache;
+mod cron;
 mod default;
+pub mod event_query;
+pub mod events;
+mod pool;
+pub mod supply;
 mod threaded;
 
 use std::fmt::Display;
 
+pub use cache::ResultCache;
 use cid::Cid;
+pub use cron::{CronActorCost, CronDryRun};
 pub use default::DefaultExecutor;
+pub use event_query::{query_events, EventFilter, EventSource};
+pub use events::{BlockEvents, EventAccumulator};
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
@@ -14,10 +24,12 @@ use fvm_shared::event::StampedEvent;
 use fvm_shared::message::Message;
 use fvm_shared::receipt::Receipt;
 use num_traits::Zero;
+pub use pool::{MachinePool, PooledExecutor};
 pub use threaded::ThreadedExecutor;
 
 use crate::call_manager::Backtrace;
-use crate::trace::ExecutionTrace;
+use crate::gas::OutOfGasInfo;
+use crate::trace::{ExecutionTrace, TraceCommitment};
 use crate::Kernel;
 
 /// An executor executes messages on the underlying machine/kernel. It's responsible for:
@@ -42,8 +54,34 @@ pub trait Executor {
         raw_length: usize,
     ) -> anyhow::Result<ApplyRet>;
 
+    /// Executes a batch of messages from the same sender atomically: if any message fails, the
+    /// remaining messages aren't executed and the state changes made by the entire batch are
+    /// reverted. Only the senders' nonces and the gas payment persist.
+    ///
+    /// The batch pays for gas once, as if it were a single message with the sum of the messages'
+    /// gas limits. Each message is still limited to its own gas limit, and fails with
+    /// `SYS_OUT_OF_GAS` (failing the batch) if it exceeds it. All messages must have consecutive sequence numbers, and the same gas fee cap
+    /// and premium.
+    ///
+    /// Batching must be enabled with
+    /// [`NetworkConfig::message_batching`](crate::machine::NetworkConfig::message_batching).
+    ///
+    /// NOTE: The "raw length" is the combined length of the messages as they appear on-chain.
+    fn execute_batch(
+        &mut self,
+        msgs: Vec<Message>,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<BatchApplyRet>;
+
     /// Flushes the state-tree, returning the new root CID.
     fn flush(&mut self) -> anyhow::Result<Cid>;
+
+    /// Waits for the writes of previous flushes to complete, if the machine writes them in the
+    /// background (see [`Machine::join_flushes`](crate::machine::Machine::join_flushes)).
+    fn join_flushes(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 /// A description of some failure encountered when applying a message.
@@ -70,6 +108,15 @@ impl Display for ApplyFailure {
     }
 }
 
+/// The progress of an executor, reported when message execution is cancelled. See
+/// [`crate::cancel`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExecutionProgress {
+    /// The number of messages applied since the executor was created (or reset), excluding the
+    /// cancelled message. Messages in a batch are counted individually.
+    pub messages: u64,
+}
+
 /// Apply message return data.
 #[derive(Clone, Debug)]
 pub struct ApplyRet {
@@ -86,11 +133,20 @@ pub struct ApplyRet {
     pub refund: TokenAmount,
     pub gas_refund: i64,
     pub gas_burned: i64,
+    /// The part of the gas cost covered by a gas subsidy (paid by the subsidy's account, or
+    /// waived), if the message was subsidized. See [`crate::gas::subsidy`].
+    pub subsidy: TokenAmount,
 
     /// Additional failure information for debugging, if any.
     pub failure_info: Option<ApplyFailure>,
+    /// Details about where the message ran out of gas, if it exited with
+    /// [`ExitCode::SYS_OUT_OF_GAS`].
+    pub out_of_gas_info: Option<OutOfGasInfo>,
     /// Execution trace information, for debugging.
     pub exec_trace: ExecutionTrace,
+    /// A commitment over the execution trace, if enabled with
+    /// [`MachineContext::enable_trace_commitment`][crate::machine::MachineContext::enable_trace_commitment].
+    pub trace_commitment: Option<TraceCommitment>,
     /// Events generated while applying the message.
     pub events: Vec<StampedEvent>,
 }
@@ -116,21 +172,39 @@ impl ApplyRet {
             refund: TokenAmount::zero(),
             gas_refund: 0,
             gas_burned: 0,
+            subsidy: TokenAmount::zero(),
             failure_info: Some(ApplyFailure::PreValidation(message.into())),
+            out_of_gas_info: None,
             exec_trace: vec![],
+            trace_commitment: None,
             events: vec![],
         }
     }
 }
 
+/// Apply message batch return data.
+#[derive(Clone, Debug)]
+pub struct BatchApplyRet {
+    /// The result of the batch as a whole. The receipt's exit code and return value are those of
+    /// the first failed message (if any) or the last message, and its gas used is the total used
+    /// by the batch.
+    pub apply_ret: ApplyRet,
+    /// The receipts of the messages that were executed, in order. These don't have an events root:
+    /// events are committed for the batch as a whole.
+    pub receipts: Vec<Receipt>,
+}
+
 /// The kind of message being applied:
 ///
 /// 1. Explicit messages may only come from account actors and charge the sending account for gas
 /// consumed.
 /// 2. Implicit messages may come from any actor, ignore the nonce, and charge no gas (but still
 /// account for it).
-#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+/// 3. Unlimited messages are implicit messages (e.g., state migrations) that additionally ignore
+/// the gas limit. Gas used is still accounted for, without saturating.
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash)]
 pub enum ApplyKind {
     Explicit,
     Implicit,
+    Unlimited,
 }
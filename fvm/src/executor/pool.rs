@@ -0,0 +1,198 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+
+use anyhow::{anyhow, Result};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::BLOCK_GAS_LIMIT;
+use num_traits::Zero;
+
+use super::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
+use crate::Kernel;
+
+type Factory<K> = Box<dyn Fn() -> Result<DefaultExecutor<K>> + Send + Sync>;
+
+/// A pool of pre-warmed machines over the same read-only state, for serving concurrent state
+/// queries (e.g., a node's `call_readonly` and gas estimation RPCs) without constructing a machine
+/// per request.
+///
+/// Each request checks out a machine (with its executor) for its exclusive use, and may execute
+/// messages on it. The machine's writes stay in its own write buffer, and its gas and subsidy
+/// accounting is its own, so requests are isolated from one another. When the request returns the
+/// machine, the pool resets it to its initial state root (see [`DefaultExecutor::reset`]),
+/// discarding all of the request's changes.
+///
+/// The machines are built by a factory, typically over clones of a handle to a shared blockstore.
+/// The factory must build all machines with the same configuration and initial state root. If a
+/// machine can't be reset (e.g., because it was poisoned), it's dropped, and a new machine is built
+/// by the next checkout.
+///
+/// Like the [`DefaultExecutor`], pooled machines need at least 64MiB of stack space to execute
+/// messages, so requests should be served on threads with large enough stacks.
+pub struct MachinePool<K: Kernel> {
+    factory: Factory<K>,
+    size: usize,
+    state: Mutex<PoolState<K>>,
+    available: Condvar,
+}
+
+struct PoolState<K: Kernel> {
+    /// The machines ready to be checked out.
+    idle: Vec<DefaultExecutor<K>>,
+    /// The number of machines that were dropped, and must be rebuilt.
+    missing: usize,
+}
+
+/// A machine checked out of a [`MachinePool`], returned to the pool when dropped.
+pub struct PooledExecutor<'a, K: Kernel> {
+    pool: &'a MachinePool<K>,
+    executor: Option<DefaultExecutor<K>>,
+}
+
+impl<K: Kernel> MachinePool<K> {
+    /// Creates a pool of `size` machines built with `factory`, building them all up front.
+    pub fn new<F>(size: usize, factory: F) -> Result<Self>
+    where
+        F: Fn() -> Result<DefaultExecutor<K>> + Send + Sync + 'static,
+    {
+        if size == 0 {
+            return Err(anyhow!("a machine pool needs at least one machine"));
+        }
+        let idle = (0..size).map(|_| factory()).collect::<Result<_>>()?;
+        Ok(MachinePool {
+            factory: Box::new(factory),
+            size,
+            state: Mutex::new(PoolState { idle, missing: 0 }),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Returns the number of machines in the pool, whether checked out or not.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of machines ready to be checked out.
+    pub fn idle(&self) -> usize {
+        self.state.lock().unwrap().idle.len()
+    }
+
+    /// Checks out a machine, waiting for one to be returned if they're all checked out. Fails if a
+    /// machine had to be rebuilt, and the factory failed.
+    pub fn checkout(&self) -> Result<PooledExecutor<'_, K>> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(executor) = state.idle.pop() {
+                return Ok(self.wrap(executor));
+            }
+            if state.missing > 0 {
+                state.missing -= 1;
+                drop(state);
+                return self.rebuild();
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    /// Like [`MachinePool::checkout`], but returns `None` instead of waiting if all machines are
+    /// checked out.
+    pub fn try_checkout(&self) -> Result<Option<PooledExecutor<'_, K>>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(executor) = state.idle.pop() {
+            return Ok(Some(self.wrap(executor)));
+        }
+        if state.missing > 0 {
+            state.missing -= 1;
+            drop(state);
+            return self.rebuild().map(Some);
+        }
+        Ok(None)
+    }
+
+    /// Executes a message on a pooled machine as an implicit message (i.e., without checking its
+    /// nonce or charging its sender for gas), returning the result. The message's state changes are
+    /// discarded.
+    pub fn call_readonly(&self, msg: Message, raw_length: usize) -> Result<ApplyRet> {
+        self.checkout()?
+            .execute_message(msg, ApplyKind::Implicit, raw_length)
+    }
+
+    /// Estimates the gas used by a message, by executing it on a pooled machine with the block gas
+    /// limit, and a zero gas fee cap and premium (so its sender needn't be able to pay for gas).
+    /// The message's nonce must be its sender's next nonce. The estimate is the returned receipt's
+    /// `gas_used`. The message's state changes are discarded.
+    pub fn estimate_gas(&self, msg: Message, raw_length: usize) -> Result<ApplyRet> {
+        let msg = Message {
+            gas_limit: BLOCK_GAS_LIMIT,
+            gas_fee_cap: TokenAmount::zero(),
+            gas_premium: TokenAmount::zero(),
+            ..msg
+        };
+        self.checkout()?
+            .execute_message(msg, ApplyKind::Explicit, raw_length)
+    }
+
+    fn wrap(&self, executor: DefaultExecutor<K>) -> PooledExecutor<'_, K> {
+        PooledExecutor {
+            pool: self,
+            executor: Some(executor),
+        }
+    }
+
+    fn rebuild(&self) -> Result<PooledExecutor<'_, K>> {
+        match (self.factory)() {
+            Ok(executor) => Ok(self.wrap(executor)),
+            Err(e) => {
+                self.release(None);
+                Err(e.context("failed to rebuild a pooled machine"))
+            }
+        }
+    }
+
+    /// Returns a machine to the pool, or records that it was dropped.
+    fn release(&self, executor: Option<DefaultExecutor<K>>) {
+        let mut state = self.state.lock().unwrap();
+        match executor {
+            Some(executor) => state.idle.push(executor),
+            None => state.missing += 1,
+        }
+        self.available.notify_one();
+    }
+}
+
+impl<'a, K: Kernel> Deref for PooledExecutor<'a, K> {
+    type Target = DefaultExecutor<K>;
+
+    fn deref(&self) -> &Self::Target {
+        self.executor
+            .as_ref()
+            .expect("pooled executor already returned")
+    }
+}
+
+impl<'a, K: Kernel> DerefMut for PooledExecutor<'a, K> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.executor
+            .as_mut()
+            .expect("pooled executor already returned")
+    }
+}
+
+impl<'a, K: Kernel> Drop for PooledExecutor<'a, K> {
+    fn drop(&mut self) {
+        let mut executor = match self.executor.take() {
+            Some(executor) => executor,
+            None => return,
+        };
+        let executor = match executor.reset() {
+            Ok(()) => Some(executor),
+            Err(e) => {
+                log::warn!("dropping a pooled machine that couldn't be reset: {:#}", e);
+                None
+            }
+        };
+        self.pool.release(executor);
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Token supply conservation checks, for catching economic bugs in development networks.
+//!
+//! Messages only ever move tokens between actors: fees are paid to the reward actor, burnt funds
+//! are sent to the burnt funds actor, and block rewards are paid from the reward actor's balance.
+//! So, the sum of all actor balances (including burnt funds) never changes, unless tokens are
+//! minted or destroyed outside of message execution (e.g., by a devnet faucet writing balances
+//! directly into the state tree).
+//!
+//! When [`MachineContext::supply_check`](crate::machine::MachineContext::supply_check) is enabled,
+//! the [`DefaultExecutor`](super::DefaultExecutor) checks this on every flush (i.e., at the end of
+//! each block), and fails the flush with a [`SupplyViolation`] listing every balance that changed.
+//! Tokens locked by actors (e.g., miner pledges) are part of those actors' balances, so they're
+//! covered too. Checking reads every actor in the state tree, so it's slow on large states.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anyhow::anyhow;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+
+use crate::machine::BURNT_FUNDS_ACTOR_ID;
+use crate::state_tree::StateTree;
+
+/// The balances of all actors in a state tree.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Balances {
+    balances: BTreeMap<ActorID, TokenAmount>,
+    total: TokenAmount,
+}
+
+impl Balances {
+    /// Reads the balances of all actors in the state tree.
+    pub fn load<B: Blockstore>(state_tree: &StateTree<B>) -> anyhow::Result<Self> {
+        let mut balances = Balances::default();
+        state_tree.for_each(|addr, actor| {
+            let id = addr
+                .id()
+                .map_err(|_| anyhow!("state tree is keyed by non-ID address {}", addr))?;
+            balances.insert(id, actor.balance.clone());
+            Ok(())
+        })?;
+        Ok(balances)
+    }
+
+    fn insert(&mut self, id: ActorID, balance: TokenAmount) {
+        self.total += &balance;
+        if let Some(previous) = self.balances.insert(id, balance) {
+            self.total -= previous;
+        }
+    }
+
+    /// Returns the balance of the given actor (zero if it doesn't exist).
+    pub fn get(&self, id: ActorID) -> TokenAmount {
+        self.balances.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Returns the sum of all balances.
+    pub fn total(&self) -> &TokenAmount {
+        &self.total
+    }
+
+    /// Checks that the total balance changed by exactly `expected_change` since `before`.
+    pub fn check_since(
+        &self,
+        before: &Balances,
+        expected_change: &TokenAmount,
+    ) -> Result<(), SupplyViolation> {
+        let expected = before.total() + expected_change;
+        if self.total == expected {
+            return Ok(());
+        }
+        Err(SupplyViolation {
+            expected,
+            actual: self.total.clone(),
+            burnt: self.get(BURNT_FUNDS_ACTOR_ID) - before.get(BURNT_FUNDS_ACTOR_ID),
+            deltas: self.deltas_since(before),
+        })
+    }
+
+    /// Returns the balances that changed since `before`, in actor ID order.
+    fn deltas_since(&self, before: &Balances) -> Vec<BalanceDelta> {
+        let ids: BTreeSet<_> = before
+            .balances
+            .keys()
+            .chain(self.balances.keys())
+            .copied()
+            .collect();
+        ids.into_iter()
+            .filter_map(|actor| {
+                let (before, after) = (before.get(actor), self.get(actor));
+                (before != after).then_some(BalanceDelta {
+                    actor,
+                    before,
+                    after,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A change to an actor's balance. Actors created or deleted have a balance of zero before or
+/// after the change, respectively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceDelta {
+    pub actor: ActorID,
+    pub before: TokenAmount,
+    pub after: TokenAmount,
+}
+
+impl BalanceDelta {
+    /// The change in the actor's balance.
+    pub fn change(&self) -> TokenAmount {
+        &self.after - &self.before
+    }
+}
+
+/// The total supply of tokens didn't change as expected. See [the module
+/// documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct SupplyViolation {
+    /// The expected sum of all balances.
+    pub expected: TokenAmount,
+    /// The actual sum of all balances.
+    pub actual: TokenAmount,
+    /// The change in the burnt funds actor's balance.
+    pub burnt: TokenAmount,
+    /// Every balance that changed, in actor ID order.
+    pub deltas: Vec<BalanceDelta>,
+}
+
+impl fmt::Display for SupplyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "token supply changed by {} (expected {}, found {}; {} burnt)",
+            &self.actual - &self.expected,
+            self.expected,
+            self.actual,
+            self.burnt
+        )?;
+        for delta in &self.deltas {
+            write!(
+                f,
+                "\n  actor {}: {} -> {} ({})",
+                delta.actor,
+                delta.before,
+                delta.after,
+                delta.change()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_traits::Zero;
+
+    use super::*;
+
+    fn balances(entries: &[(ActorID, u64)]) -> Balances {
+        let mut balances = Balances::default();
+        for &(id, balance) in entries {
+            balances.insert(id, TokenAmount::from_atto(balance));
+        }
+        balances
+    }
+
+    #[test]
+    fn conserved() {
+        let before = balances(&[(BURNT_FUNDS_ACTOR_ID, 0), (100, 10), (101, 5)]);
+        // Transfers, burns, and new actors conserve the supply.
+        let after = balances(&[(BURNT_FUNDS_ACTOR_ID, 2), (100, 3), (101, 6), (102, 4)]);
+        after.check_since(&before, &TokenAmount::zero()).unwrap();
+
+        // Minting is allowed if expected.
+        let minted = balances(&[(BURNT_FUNDS_ACTOR_ID, 0), (100, 17), (101, 5)]);
+        minted
+            .check_since(&before, &TokenAmount::from_atto(7))
+            .unwrap();
+    }
+
+    #[test]
+    fn violated() {
+        let before = balances(&[(BURNT_FUNDS_ACTOR_ID, 0), (100, 10), (101, 5)]);
+        // Actor 101 was deleted with a balance, while 100 burnt 1.
+        let after = balances(&[(BURNT_FUNDS_ACTOR_ID, 1), (100, 9)]);
+        let violation = after
+            .check_since(&before, &TokenAmount::zero())
+            .unwrap_err();
+        assert_eq!(violation.expected, TokenAmount::from_atto(15));
+        assert_eq!(violation.actual, TokenAmount::from_atto(10));
+        assert_eq!(violation.burnt, TokenAmount::from_atto(1));
+        assert_eq!(
+            violation.deltas,
+            vec![
+                BalanceDelta {
+                    actor: BURNT_FUNDS_ACTOR_ID,
+                    before: TokenAmount::zero(),
+                    after: TokenAmount::from_atto(1),
+                },
+                BalanceDelta {
+                    actor: 100,
+                    before: TokenAmount::from_atto(10),
+                    after: TokenAmount::from_atto(9),
+                },
+                BalanceDelta {
+                    actor: 101,
+                    before: TokenAmount::from_atto(5),
+                    after: TokenAmount::zero(),
+                },
+            ]
+        );
+        assert_eq!(violation.deltas[2].change(), TokenAmount::from_atto(-5));
+    }
+}
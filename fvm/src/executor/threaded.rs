@@ -5,7 +5,7 @@ use cid::Cid;
 use fvm_shared::message::Message;
 use lazy_static::lazy_static;
 
-use super::{ApplyKind, ApplyRet, Executor};
+use super::{ApplyKind, ApplyRet, BatchApplyRet, Executor};
 
 lazy_static! {
     static ref EXEC_POOL: yastl::Pool = yastl::Pool::with_config(
@@ -47,7 +47,26 @@ where
         ret
     }
 
+    fn execute_batch(
+        &mut self,
+        msgs: Vec<Message>,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<BatchApplyRet> {
+        let mut ret = Err(anyhow!("failed to execute"));
+
+        EXEC_POOL.scoped(|scope| {
+            scope.execute(|| ret = self.0.execute_batch(msgs, apply_kind, raw_length));
+        });
+
+        ret
+    }
+
     fn flush(&mut self) -> anyhow::Result<Cid> {
         self.0.flush()
     }
+
+    fn join_flushes(&mut self) -> anyhow::Result<()> {
+        self.0.join_flushes()
+    }
 }
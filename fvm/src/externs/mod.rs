@@ -5,8 +5,13 @@
 use cid::Cid;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::state::StateQuery;
 
-pub trait Externs: Rand + Consensus + Chain {}
+mod pinned;
+
+pub use pinned::PinnedExterns;
+
+pub trait Externs: Rand + Consensus + Chain + StateReader {}
 
 /// Consensus related methods.
 pub trait Consensus {
@@ -45,3 +50,15 @@ pub trait Chain {
     /// Gets the CID for a given tipset.
     fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid>;
 }
+
+/// Domain state provider, for state the node tracks outside of the state tree.
+pub trait StateReader {
+    /// Looks up the given domain state, returning its DAG-CBOR encoded value, or `None` if the
+    /// state doesn't exist or the node doesn't support the query.
+    ///
+    /// Results must be deterministic for a given chain epoch.
+    fn read_state(&self, query: &StateQuery) -> anyhow::Result<Option<Vec<u8>>> {
+        let _ = query;
+        Ok(None)
+    }
+}
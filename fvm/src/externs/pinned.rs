@@ -0,0 +1,150 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::state::StateQuery;
+
+use super::{Chain, Consensus, Externs, Rand, StateReader};
+
+/// Externs pinned at a historical epoch, for machines executing "as of" a past tipset (see
+/// [`MachineBuilder::at_tipset`](crate::machine::MachineBuilder::at_tipset)).
+///
+/// The wrapped externs must resolve lookups on the chain ending at the pinned tipset, as they
+/// would have when the tipset was the head. This wrapper refuses any lookup of randomness or
+/// tipsets past the pinned epoch, so that a historical query can never observe (or fail
+/// differently depending on) chain state that didn't exist at the time.
+#[derive(Debug, Clone)]
+pub struct PinnedExterns<E> {
+    inner: E,
+    epoch: ChainEpoch,
+}
+
+impl<E> PinnedExterns<E> {
+    /// Pins the given externs at `epoch`.
+    pub fn new(inner: E, epoch: ChainEpoch) -> Self {
+        Self { inner, epoch }
+    }
+
+    /// Returns the epoch the externs are pinned at.
+    pub fn epoch(&self) -> ChainEpoch {
+        self.epoch
+    }
+
+    pub fn into_inner(self) -> E {
+        self.inner
+    }
+
+    fn check_round(&self, round: ChainEpoch) -> anyhow::Result<()> {
+        if round > self.epoch {
+            return Err(anyhow!(
+                "randomness for epoch {} isn't available at epoch {}",
+                round,
+                self.epoch
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<E: Externs> Externs for PinnedExterns<E> {}
+
+impl<E: Rand> Rand for PinnedExterns<E> {
+    fn get_chain_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.check_round(round)?;
+        self.inner.get_chain_randomness(pers, round, entropy)
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.check_round(round)?;
+        self.inner.get_beacon_randomness(pers, round, entropy)
+    }
+}
+
+impl<E: Consensus> Consensus for PinnedExterns<E> {
+    fn verify_consensus_fault(
+        &self,
+        h1: &[u8],
+        h2: &[u8],
+        extra: &[u8],
+    ) -> anyhow::Result<(Option<ConsensusFault>, i64)> {
+        self.inner.verify_consensus_fault(h1, h2, extra)
+    }
+}
+
+impl<E: Chain> Chain for PinnedExterns<E> {
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid> {
+        // The pinned tipset itself is still being "executed", so its CID isn't known either.
+        if epoch >= self.epoch {
+            return Err(anyhow!(
+                "tipset at epoch {} isn't available at epoch {}",
+                epoch,
+                self.epoch
+            ));
+        }
+        self.inner.get_tipset_cid(epoch)
+    }
+}
+
+impl<E: StateReader> StateReader for PinnedExterns<E> {
+    fn read_state(&self, query: &StateQuery) -> anyhow::Result<Option<Vec<u8>>> {
+        self.inner.read_state(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EpochExterns;
+
+    impl Rand for EpochExterns {
+        fn get_chain_randomness(
+            &self,
+            _pers: i64,
+            round: ChainEpoch,
+            _entropy: &[u8],
+        ) -> anyhow::Result<[u8; 32]> {
+            Ok([round as u8; 32])
+        }
+
+        fn get_beacon_randomness(
+            &self,
+            _pers: i64,
+            round: ChainEpoch,
+            _entropy: &[u8],
+        ) -> anyhow::Result<[u8; 32]> {
+            Ok([!(round as u8); 32])
+        }
+    }
+
+    impl Chain for EpochExterns {
+        fn get_tipset_cid(&self, _epoch: ChainEpoch) -> anyhow::Result<Cid> {
+            Ok(Cid::default())
+        }
+    }
+
+    #[test]
+    fn pinned_lookups() {
+        let externs = PinnedExterns::new(EpochExterns, 10);
+
+        assert_eq!(externs.get_chain_randomness(0, 10, b"").unwrap(), [10; 32]);
+        assert_eq!(externs.get_beacon_randomness(0, 3, b"").unwrap(), [!3; 32]);
+        assert!(externs.get_chain_randomness(0, 11, b"").is_err());
+        assert!(externs.get_beacon_randomness(0, 11, b"").is_err());
+
+        assert!(externs.get_tipset_cid(9).is_ok());
+        assert!(externs.get_tipset_cid(10).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Deterministic fault injection, for testing how actors and the FVM handle errors.
+//!
+//! When a [`FaultInjector`] is installed in the [`MachineContext`](crate::machine::MachineContext)
+//! (see [`MachineContext::inject_faults`](crate::machine::MachineContext::inject_faults)), the
+//! FVM will:
+//!
+//! - Fail syscalls with [`FaultConfig::syscall_error`] before they're executed.
+//! - Fail calls to the [`Externs`](crate::externs::Externs).
+//! - Run out of gas on the Nth gas charge of each message.
+//!
+//! Whether a given syscall or extern call fails is decided by hashing the seed with the number of
+//! syscalls (or extern calls) made so far on the machine, so the same seed always injects the same
+//! faults when applying the same messages to the same machine.
+//!
+//! This module is only available with the `fault-injection` feature, which must never be enabled
+//! in production.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::anyhow;
+use fvm_shared::error::ErrorNumber;
+
+use crate::kernel::SyscallError;
+
+/// One million, the denominator of the `*_ppm` rates in [`FaultConfig`].
+const PPM: u64 = 1_000_000;
+
+/// Configures which faults to inject.
+#[derive(Debug, Clone)]
+pub struct FaultConfig {
+    /// The seed from which all fault decisions are derived.
+    pub seed: u64,
+    /// The probability, in parts per million, that any given syscall fails.
+    pub syscall_error_ppm: u32,
+    /// The error returned by failed syscalls.
+    pub syscall_error: ErrorNumber,
+    /// The probability, in parts per million, that any given extern call fails.
+    pub extern_error_ppm: u32,
+    /// If set, the Nth (counting from 1) gas charge of every message fails with out-of-gas.
+    pub out_of_gas_at: Option<u64>,
+}
+
+impl FaultConfig {
+    /// Creates a config with the given seed that injects no faults.
+    pub fn new(seed: u64) -> Self {
+        FaultConfig {
+            seed,
+            syscall_error_ppm: 0,
+            syscall_error: ErrorNumber::IllegalOperation,
+            extern_error_ppm: 0,
+            out_of_gas_at: None,
+        }
+    }
+}
+
+/// Injects faults according to a [`FaultConfig`].
+#[derive(Debug)]
+pub struct FaultInjector {
+    config: FaultConfig,
+    syscalls: AtomicU64,
+    extern_calls: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        FaultInjector {
+            config,
+            syscalls: AtomicU64::new(0),
+            extern_calls: AtomicU64::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &FaultConfig {
+        &self.config
+    }
+
+    /// Returns the error to fail the given syscall with, if any.
+    pub fn syscall_error(&self, module: &str, name: &str) -> Option<SyscallError> {
+        let n = self.syscalls.fetch_add(1, Ordering::Relaxed);
+        self.roll(0, n, self.config.syscall_error_ppm).then(|| {
+            SyscallError::new(
+                self.config.syscall_error,
+                format_args!("injected fault in syscall {}::{} (#{})", module, name, n),
+            )
+        })
+    }
+
+    /// Returns an error if the given extern call should fail.
+    pub fn extern_error(&self, name: &str) -> anyhow::Result<()> {
+        let n = self.extern_calls.fetch_add(1, Ordering::Relaxed);
+        if self.roll(1, n, self.config.extern_error_ppm) {
+            Err(anyhow!("injected fault in extern {} (#{})", name, n))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Decides whether the `n`th event in the given stream fails, with probability `ppm`.
+    fn roll(&self, stream: u64, n: u64, ppm: u32) -> bool {
+        ppm > 0 && splitmix64(self.config.seed ^ splitmix64(stream) ^ n) % PPM < ppm as u64
+    }
+}
+
+/// The splitmix64 finalizer: a cheap, well-distributed, and portable 64-bit hash.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::error::ErrorNumber;
+
+    use super::{FaultConfig, FaultInjector};
+
+    fn syscall_failures(config: &FaultConfig) -> Vec<bool> {
+        let injector = FaultInjector::new(config.clone());
+        (0..1000)
+            .map(|_| injector.syscall_error("vm", "exit").is_some())
+            .collect()
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut config = FaultConfig::new(1);
+        config.syscall_error_ppm = 100_000;
+
+        let a = syscall_failures(&config);
+        assert_eq!(a, syscall_failures(&config));
+
+        // Roughly 10% of calls fail.
+        let failures = a.iter().filter(|f| **f).count();
+        assert!((50..150).contains(&failures), "{failures} failures");
+
+        // A different seed injects different faults.
+        config.seed = 2;
+        assert_ne!(a, syscall_failures(&config));
+    }
+
+    #[test]
+    fn rates() {
+        let mut config = FaultConfig::new(1);
+        config.syscall_error = ErrorNumber::NotFound;
+        assert!(syscall_failures(&config).iter().all(|f| !f));
+
+        config.syscall_error_ppm = 1_000_000;
+        let injector = FaultInjector::new(config);
+        let err = injector.syscall_error("vm", "exit").unwrap();
+        assert_eq!(err.1, ErrorNumber::NotFound);
+
+        // Externs are controlled separately.
+        assert!(injector.extern_error("get_tipset_cid").is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+
+use super::Gas;
+
+/// A gas cost as a piecewise function of some size `x` (e.g., a number of bytes or sectors), for
+/// costs that don't scale linearly.
+///
+/// Each [`CostSegment`] applies from its `start` up to the start of the next segment, and charges
+/// `flat + linear * d + quadratic * d² / quadratic_divisor`, where `d = x - start`. Sizes before
+/// the first segment cost nothing.
+///
+/// Curves (de)serialize as a list of segments, with gas amounts in milligas, so they can be
+/// published for review along with the rest of the price list (see
+/// [`PriceList::cost_curves`](super::PriceList::cost_curves)).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CostCurve(Vec<CostSegment>);
+
+/// A segment of a [`CostCurve`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CostSegment {
+    /// The size from which the segment applies.
+    pub start: u64,
+    /// The cost at `start`.
+    pub flat: Gas,
+    /// The cost per unit past `start`.
+    pub linear: Gas,
+    /// The cost per squared unit past `start`, divided by `quadratic_divisor`.
+    pub quadratic: Gas,
+    /// The divisor of the quadratic term, allowing quadratic costs of less than a milligas.
+    pub quadratic_divisor: u64,
+}
+
+impl CostSegment {
+    fn apply(&self, x: u64) -> Gas {
+        let d = x - self.start;
+        let quadratic = (self.quadratic.as_milligas().max(0) as u128)
+            .checked_mul(d as u128 * d as u128)
+            .map_or(u128::MAX, |q| q / self.quadratic_divisor.max(1) as u128);
+        self.flat + self.linear * d + Gas::from_milligas(quadratic.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+impl CostCurve {
+    /// A curve charging `flat + linear * x`.
+    pub fn linear(flat: Gas, linear: Gas) -> Self {
+        Self::quadratic(flat, linear, Gas::zero(), 1)
+    }
+
+    /// A curve charging `flat + linear * x + quadratic * x² / quadratic_divisor`.
+    pub fn quadratic(flat: Gas, linear: Gas, quadratic: Gas, quadratic_divisor: u64) -> Self {
+        Self(vec![CostSegment {
+            start: 0,
+            flat,
+            linear,
+            quadratic,
+            quadratic_divisor,
+        }])
+    }
+
+    /// A curve charging a constant cost from each size, for sizes in increasing order.
+    pub fn steps(steps: impl IntoIterator<Item = (u64, Gas)>) -> Self {
+        Self(
+            steps
+                .into_iter()
+                .map(|(start, flat)| CostSegment {
+                    start,
+                    flat,
+                    linear: Gas::zero(),
+                    quadratic: Gas::zero(),
+                    quadratic_divisor: 1,
+                })
+                .collect(),
+        )
+    }
+
+    /// Extends the curve with a segment starting at `start`, continuing from the cost of the
+    /// previous segment at that point.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start` doesn't come after the start of the previous segment.
+    pub fn then(mut self, start: u64, linear: Gas, quadratic: Gas, quadratic_divisor: u64) -> Self {
+        let flat = match self.0.last() {
+            Some(prev) => {
+                assert!(start > prev.start, "cost curve segments must be increasing");
+                prev.apply(start)
+            }
+            None => Gas::zero(),
+        };
+        self.0.push(CostSegment {
+            start,
+            flat,
+            linear,
+            quadratic,
+            quadratic_divisor,
+        });
+        self
+    }
+
+    /// Returns the curve's segments.
+    pub fn segments(&self) -> &[CostSegment] {
+        &self.0
+    }
+
+    /// Computes the cost for the given size, or saturates.
+    pub fn apply<V: TryInto<u64>>(&self, value: V) -> Gas {
+        let x = value.try_into().unwrap_or(u64::MAX);
+        match self.0.iter().rev().find(|s| s.start <= x) {
+            Some(segment) => segment.apply(x),
+            None => Gas::zero(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gas::price_list::ScalingCost;
+
+    #[test]
+    fn linear() {
+        let curve = CostCurve::linear(Gas::new(10), Gas::new(2));
+        assert_eq!(curve.apply(0u32), Gas::new(10));
+        assert_eq!(curve.apply(5usize), Gas::new(20));
+        assert_eq!(
+            curve,
+            ScalingCost {
+                flat: Gas::new(10),
+                scale: Gas::new(2)
+            }
+            .curve()
+        );
+    }
+
+    #[test]
+    fn piecewise() {
+        // Linear up to 100, then quadratic at 1/4 gas per squared unit.
+        let curve =
+            CostCurve::linear(Gas::new(10), Gas::new(1)).then(100, Gas::new(1), Gas::new(1), 4);
+        assert_eq!(curve.apply(100u64), Gas::new(110));
+        assert_eq!(curve.apply(110u64), Gas::new(110 + 10 + 25));
+        assert_eq!(curve.apply(101u64), Gas::from_milligas(111_250));
+
+        // Saturates rather than overflowing.
+        assert!(curve.apply(u64::MAX).is_saturated());
+        assert!(
+            CostCurve::quadratic(Gas::zero(), Gas::zero(), Gas::new(1), 1)
+                .apply(u32::MAX)
+                .is_saturated()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn decreasing_segments() {
+        let _ = CostCurve::linear(Gas::zero(), Gas::new(1))
+            .then(100, Gas::new(2), Gas::zero(), 1)
+            .then(50, Gas::new(3), Gas::zero(), 1);
+    }
+
+    #[test]
+    fn serialization() {
+        let curve = CostCurve::linear(Gas::new(1), Gas::from_milligas(500)).then(
+            10,
+            Gas::new(1),
+            Gas::new(3),
+            2,
+        );
+        let json = serde_json::to_value(&curve).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"start": 0, "flat": 1000, "linear": 500, "quadratic": 0, "quadratic_divisor": 1},
+                {"start": 10, "flat": 6000, "linear": 1000, "quadratic": 3000, "quadratic_divisor": 2},
+            ])
+        );
+        assert_eq!(serde_json::from_value::<CostCurve>(json).unwrap(), curve);
+    }
+}
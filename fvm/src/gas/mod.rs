@@ -6,18 +6,27 @@ use std::cell::{Cell, RefCell};
 use std::fmt::{Debug, Display};
 use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
 
+use anyhow::anyhow;
 use num_traits::Zero;
+use serde::{Deserialize, Serialize};
 
 pub use self::charge::GasCharge;
+pub use self::curve::{CostCurve, CostSegment};
 pub(crate) use self::outputs::GasOutputs;
 pub use self::price_list::{price_list_by_network_version, PriceList, WasmGasPrices};
-pub use self::timer::{GasInstant, GasTimer};
+pub(crate) use self::subsidy::SubsidyQuotas;
+pub use self::subsidy::{GasSubsidy, SubsidyPayer};
+pub use self::timer::{GasInstant, GasTimer, InstructionClock};
+pub use self::usage::{ActorGas, ActorGasUsage};
 use crate::kernel::{ExecutionError, Result};
 
 mod charge;
+mod curve;
 mod outputs;
 mod price_list;
+pub mod subsidy;
 mod timer;
+mod usage;
 
 pub const MILLIGAS_PRECISION: i64 = 1000;
 
@@ -27,7 +36,10 @@ pub const MILLIGAS_PRECISION: i64 = 1000;
 /// - Enforces correct units by making it impossible to, e.g., get gas squared (by multiplying gas
 ///   by gas).
 /// - Makes it harder to confuse gas and milligas.
-#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Default)]
+///
+/// Serialized as an integer number of milligas.
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Gas(i64 /* milligas */);
 
 impl Debug for Gas {
@@ -181,10 +193,39 @@ impl Mul<usize> for Gas {
     }
 }
 
+/// Details about the point at which a message ran out of gas, for debugging.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutOfGasInfo {
+    /// The last syscall (`module::name`) that completed successfully before running out of gas.
+    pub last_syscall: Option<String>,
+    /// The name of the gas charge that tripped the limit.
+    pub charge_name: String,
+    /// The amount of gas the tripping charge attempted to use.
+    pub charge_gas: Gas,
+    /// The call stack depth at which gas ran out, where 1 is the top-level call. This is `None`
+    /// if gas ran out outside of any call frame (e.g., when charging for message inclusion).
+    pub call_depth: Option<u32>,
+}
+
 pub struct GasTracker {
     gas_limit: Gas,
     gas_used: Cell<Gas>,
+    /// Limits saved by [`GasTracker::push_limit`], restored by [`GasTracker::pop_limit`].
+    limit_stack: Vec<Gas>,
+    /// The last syscall to complete successfully.
+    last_syscall: Cell<Option<(&'static str, &'static str)>>,
+    /// Details about the last charge to exceed the gas limit, if any.
+    out_of_gas: RefCell<Option<OutOfGasInfo>>,
+    /// Total milligas used, counted without saturating. Only set in unlimited mode.
+    total_milligas: Option<Cell<i128>>,
     trace: Option<RefCell<Vec<GasCharge>>>,
+    /// The clock traced charges are timed with instead of the wall clock, if any. See
+    /// [`GasTracker::enable_instruction_clock`].
+    instruction_clock: Option<InstructionClock>,
+    /// The charge number at which to inject an out-of-gas failure, and the number of charges
+    /// applied so far. See [`GasTracker::inject_out_of_gas_at`].
+    #[cfg(feature = "fault-injection")]
+    out_of_gas_at: Option<(u64, Cell<u64>)>,
 }
 
 impl GasTracker {
@@ -194,16 +235,85 @@ impl GasTracker {
         Self {
             gas_limit,
             gas_used: Cell::new(gas_used),
+            limit_stack: Vec::new(),
+            last_syscall: Cell::new(None),
+            out_of_gas: RefCell::new(None),
+            total_milligas: None,
             trace: enable_tracing.then_some(Default::default()),
+            instruction_clock: None,
+            #[cfg(feature = "fault-injection")]
+            out_of_gas_at: None,
+        }
+    }
+
+    /// Fails the `n`th (counting from 1) charge with out-of-gas, regardless of the gas
+    /// available. Has no effect on unlimited trackers.
+    #[cfg(feature = "fault-injection")]
+    pub fn inject_out_of_gas_at(&mut self, n: u64) {
+        self.out_of_gas_at = Some((n, Cell::new(0)));
+    }
+
+    #[cfg(feature = "fault-injection")]
+    fn injected_out_of_gas(&self) -> bool {
+        self.out_of_gas_at.as_ref().map_or(false, |(n, count)| {
+            count.set(count.get() + 1);
+            count.get() == *n
+        })
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn injected_out_of_gas(&self) -> bool {
+        false
+    }
+
+    /// Creates a gas tracker that never runs out of gas, for system-level messages such as state
+    /// migrations.
+    ///
+    /// Gas used is still accounted for (see [`GasTracker::total_gas_used`]), using a widened
+    /// integer so the total stays accurate beyond `i64::MAX` milligas. Gas limit windows (see
+    /// [`GasTracker::push_limit`]) are not enforced in this mode.
+    pub fn new_unlimited(enable_tracing: bool) -> Self {
+        Self {
+            total_milligas: Some(Cell::new(0)),
+            ..Self::new(Gas::from_milligas(i64::MAX), Gas::zero(), enable_tracing)
         }
     }
 
-    fn charge_gas_inner(&self, to_use: Gas) -> Result<()> {
+    /// Times traced charges by the number of Wasm instructions executed instead of the wall clock,
+    /// so the timings are identical on every machine. The clock must be advanced as Wasm executes.
+    pub fn enable_instruction_clock(&mut self) {
+        self.instruction_clock = Some(InstructionClock::default());
+    }
+
+    /// Returns the clock traced charges are timed with, if instruction timing is enabled.
+    pub fn instruction_clock(&self) -> Option<&InstructionClock> {
+        self.instruction_clock.as_ref()
+    }
+
+    /// Returns true if this tracker was created with [`GasTracker::new_unlimited`].
+    pub fn is_unlimited(&self) -> bool {
+        self.total_milligas.is_some()
+    }
+
+    fn charge_gas_inner(&self, name: &str, to_use: Gas) -> Result<()> {
         // The gas type uses saturating math.
         let gas_used = self.gas_used.get() + to_use;
-        if gas_used > self.gas_limit {
+        if let Some(total) = &self.total_milligas {
+            total.set(total.get() + i128::from(to_use.as_milligas()));
+            self.gas_used.set(gas_used);
+            Ok(())
+        } else if self.injected_out_of_gas() || gas_used > self.gas_limit {
             log::trace!("gas limit reached");
             self.gas_used.set(self.gas_limit);
+            self.out_of_gas.replace(Some(OutOfGasInfo {
+                last_syscall: self
+                    .last_syscall
+                    .get()
+                    .map(|(module, syscall)| format!("{module}::{syscall}")),
+                charge_name: name.to_owned(),
+                charge_gas: to_use,
+                call_depth: None,
+            }));
             Err(ExecutionError::OutOfGas)
         } else {
             self.gas_used.set(gas_used);
@@ -215,10 +325,10 @@ impl GasTracker {
     /// enough gas remaining for charge.
     pub fn charge_gas(&self, name: &str, to_use: Gas) -> Result<GasTimer> {
         log::trace!("charging gas: {} {}", name, to_use);
-        let res = self.charge_gas_inner(to_use);
+        let res = self.charge_gas_inner(name, to_use);
         if let Some(trace) = &self.trace {
             let mut charge = GasCharge::new(name.to_owned(), to_use, Gas::zero());
-            let timer = GasTimer::new(&mut charge.elapsed);
+            let timer = GasTimer::new_with_clock(&mut charge.elapsed, self.instruction_clock());
             trace.borrow_mut().push(charge);
             res.map(|_| timer)
         } else {
@@ -230,9 +340,9 @@ impl GasTracker {
     pub fn apply_charge(&self, mut charge: GasCharge) -> Result<GasTimer> {
         let to_use = charge.total();
         log::trace!("charging gas: {} {}", &charge.name, to_use);
-        let res = self.charge_gas_inner(to_use);
+        let res = self.charge_gas_inner(&charge.name, to_use);
         if let Some(trace) = &self.trace {
-            let timer = GasTimer::new(&mut charge.elapsed);
+            let timer = GasTimer::new_with_clock(&mut charge.elapsed, self.instruction_clock());
             trace.borrow_mut().push(charge);
             res.map(|_| timer)
         } else {
@@ -240,20 +350,61 @@ impl GasTracker {
         }
     }
 
-    /// Absorbs another GasTracker (usually a nested one) into this one, charging for gas
-    /// used and appending all traces.
-    pub fn absorb(&self, other: &GasTracker) -> Result<()> {
-        if let Some(trace) = &self.trace {
-            trace.borrow_mut().extend(other.drain_trace());
+    /// Constrains all subsequent charges to at most `new_limit` gas until the matching
+    /// [`GasTracker::pop_limit`], if and only if the new limit is less than the available gas.
+    ///
+    /// Gas charged inside the window is billed to this tracker directly, so nothing needs to be
+    /// merged when the window is popped.
+    ///
+    /// Returns true if the limit was lowered. Every call must be matched by a call to `pop_limit`,
+    /// whether or not the limit was lowered.
+    pub fn push_limit(&mut self, new_limit: Gas) -> bool {
+        self.limit_stack.push(self.gas_limit);
+        let lowered = !self.is_unlimited() && self.gas_available() > new_limit;
+        if lowered {
+            self.gas_limit = self.gas_used() + new_limit;
+        }
+        lowered
+    }
+
+    /// Restores the gas limit in effect before the last call to [`GasTracker::push_limit`].
+    ///
+    /// Returns a fatal error if there is no matching `push_limit`.
+    pub fn pop_limit(&mut self) -> Result<()> {
+        match self.limit_stack.pop() {
+            Some(limit) => {
+                self.gas_limit = limit;
+                Ok(())
+            }
+            None => Err(ExecutionError::Fatal(anyhow!(
+                "popped a gas limit without a matching push"
+            ))),
+        }
+    }
+
+    /// Returns the number of nested gas limit windows currently active.
+    pub fn limit_depth(&self) -> usize {
+        self.limit_stack.len()
+    }
+
+    /// Records the last syscall to complete successfully, for out-of-gas reporting.
+    pub fn record_syscall(&self, module: &'static str, name: &'static str) {
+        self.last_syscall.set(Some((module, name)));
+    }
+
+    /// Records the call stack depth at which gas ran out, unless it has already been recorded.
+    ///
+    /// The call manager calls this as an out-of-gas error unwinds each call frame, so the
+    /// innermost (first) frame wins.
+    pub fn record_out_of_gas_depth(&self, depth: u32) {
+        if let Some(info) = self.out_of_gas.borrow_mut().as_mut() {
+            info.call_depth.get_or_insert(depth);
         }
-        self.charge_gas_inner(other.gas_used())
     }
 
-    /// Make a "child" gas-tracker with a new limit, if and only if the new limit is less than the
-    /// available gas.
-    pub fn new_child(&self, new_limit: Gas) -> Option<GasTracker> {
-        (self.gas_available() > new_limit)
-            .then(|| GasTracker::new(new_limit, Gas::zero(), self.trace.is_some()))
+    /// Returns details about the last charge to exceed the gas limit, if any.
+    pub fn out_of_gas_info(&self) -> Option<OutOfGasInfo> {
+        self.out_of_gas.borrow().clone()
     }
 
     /// Getter for the maximum gas usable by this message.
@@ -266,6 +417,22 @@ impl GasTracker {
         self.gas_used.get()
     }
 
+    /// Returns the total gas used in whole gas units (rounding up), clamped to be non-negative.
+    ///
+    /// Unlike [`GasTracker::gas_used`], this doesn't saturate at `i64::MAX` milligas in unlimited
+    /// mode (it only saturates at `i64::MAX` gas).
+    pub fn total_gas_used(&self) -> i64 {
+        let milligas = match &self.total_milligas {
+            Some(total) => total.get(),
+            None => i128::from(self.gas_used().as_milligas()),
+        }
+        .max(0);
+        let precision = i128::from(MILLIGAS_PRECISION);
+        ((milligas + precision - 1) / precision)
+            .try_into()
+            .unwrap_or(i64::MAX)
+    }
+
     /// Getter for gas available.
     pub fn gas_available(&self) -> Gas {
         self.gas_limit - self.gas_used.get()
@@ -312,6 +479,101 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn gas_limit_windows() -> Result<()> {
+        let mut t = GasTracker::new(Gas::new(20), Gas::zero(), false);
+        assert!(t.push_limit(Gas::new(5)));
+        assert_eq!(t.limit_depth(), 1);
+        assert_eq!(t.gas_available(), Gas::new(5));
+        let _ = t.charge_gas("", Gas::new(3))?;
+        assert!(t.charge_gas("", Gas::new(3)).is_err());
+        // Running out of gas in the window bills the parent for the whole window.
+        assert_eq!(t.gas_used(), Gas::new(5));
+        t.pop_limit()?;
+        assert_eq!(t.limit_depth(), 0);
+        assert_eq!(t.gas_limit(), Gas::new(20));
+        assert_eq!(t.gas_available(), Gas::new(15));
+        Ok(())
+    }
+
+    #[test]
+    fn gas_limit_windows_nested() -> Result<()> {
+        let mut t = GasTracker::new(Gas::new(20), Gas::new(2), false);
+        assert!(t.push_limit(Gas::new(10)));
+        let _ = t.charge_gas("", Gas::new(1))?;
+        // A window larger than the available gas doesn't raise the limit.
+        assert!(!t.push_limit(Gas::new(100)));
+        assert_eq!(t.gas_limit(), Gas::new(12));
+        assert!(t.push_limit(Gas::new(4)));
+        assert_eq!(t.gas_limit(), Gas::new(7));
+        t.pop_limit()?;
+        assert_eq!(t.gas_limit(), Gas::new(12));
+        t.pop_limit()?;
+        t.pop_limit()?;
+        assert_eq!(t.gas_limit(), Gas::new(20));
+        assert_eq!(t.gas_used(), Gas::new(3));
+        Ok(())
+    }
+
+    #[test]
+    fn gas_limit_mismatched_pop() {
+        let mut t = GasTracker::new(Gas::new(20), Gas::zero(), false);
+        assert!(matches!(t.pop_limit(), Err(ExecutionError::Fatal(_))));
+        t.push_limit(Gas::new(5));
+        t.pop_limit().unwrap();
+        assert!(matches!(t.pop_limit(), Err(ExecutionError::Fatal(_))));
+        // A failed pop leaves the limit untouched.
+        assert_eq!(t.gas_limit(), Gas::new(20));
+    }
+
+    #[test]
+    fn out_of_gas_info() {
+        let t = GasTracker::new(Gas::new(10), Gas::zero(), false);
+        assert_eq!(t.out_of_gas_info(), None);
+        t.record_syscall("ipld", "block_open");
+        let _ = t.charge_gas("OnBlockOpen", Gas::new(5)).unwrap();
+        assert!(t.charge_gas("OnBlockRead", Gas::new(6)).is_err());
+        t.record_out_of_gas_depth(3);
+        t.record_out_of_gas_depth(2);
+        assert_eq!(
+            t.out_of_gas_info(),
+            Some(OutOfGasInfo {
+                last_syscall: Some("ipld::block_open".into()),
+                charge_name: "OnBlockRead".into(),
+                charge_gas: Gas::new(6),
+                call_depth: Some(3),
+            })
+        );
+    }
+
+    #[test]
+    fn unlimited_gas_tracker() -> Result<()> {
+        let mut t = GasTracker::new_unlimited(false);
+        assert!(t.is_unlimited());
+        for _ in 0..4 {
+            let _ = t.charge_gas("", Gas::from_milligas(i64::MAX / 2))?;
+        }
+        assert!(t.gas_used().is_saturated());
+        assert_eq!(
+            i128::from(t.total_gas_used()),
+            (i128::from(i64::MAX / 2) * 4 + 999) / 1000
+        );
+        // Limit windows aren't enforced.
+        assert!(!t.push_limit(Gas::new(1)));
+        let _ = t.charge_gas("", Gas::new(10))?;
+        t.pop_limit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn total_gas_used_rounding() -> Result<()> {
+        let t = GasTracker::new(Gas::new(20), Gas::from_milligas(-10), false);
+        assert_eq!(t.total_gas_used(), 0);
+        let _ = t.charge_gas("", Gas::from_milligas(1011))?;
+        assert_eq!(t.total_gas_used(), 2);
+        Ok(())
+    }
+
     #[test]
     fn milligas_to_gas_round() {
         assert_eq!(milligas_to_gas(100, false), 0);
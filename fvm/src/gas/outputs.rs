@@ -4,6 +4,9 @@ use std::convert::TryFrom;
 
 use fvm_shared::bigint::BigInt;
 use fvm_shared::econ::TokenAmount;
+use num_traits::Zero;
+
+use super::SubsidyPayer;
 
 #[derive(Clone, Default)]
 pub(crate) struct GasOutputs {
@@ -12,6 +15,8 @@ pub(crate) struct GasOutputs {
     pub miner_penalty: TokenAmount,
     pub miner_tip: TokenAmount,
     pub refund: TokenAmount,
+    /// The part of the gas cost covered by a gas subsidy.
+    pub subsidy: TokenAmount,
 
     // In whole gas units.
     pub gas_refund: i64,
@@ -59,6 +64,19 @@ impl GasOutputs {
 
         out
     }
+
+    /// Accounts for a subsidized message: the gas cost is paid by the subsidy's account (refunded
+    /// like a sender would be), or waived, in which case nothing is burnt, paid to the miner, or
+    /// refunded. The miner penalty is kept either way.
+    pub fn subsidize(&mut self, payer: SubsidyPayer) {
+        self.subsidy = &self.base_fee_burn + &self.over_estimation_burn + &self.miner_tip;
+        if payer == SubsidyPayer::Waived {
+            self.base_fee_burn = TokenAmount::zero();
+            self.over_estimation_burn = TokenAmount::zero();
+            self.miner_tip = TokenAmount::zero();
+            self.refund = TokenAmount::zero();
+        }
+    }
 }
 
 fn compute_gas_overestimation_burn(gas_used: i64, gas_limit: i64) -> (i64, i64) {
@@ -85,3 +103,27 @@ fn compute_gas_overestimation_burn(gas_used: i64, gas_limit: i64) -> (i64, i64)
     let gas_to_burn = i64::try_from(gas_to_burn).unwrap();
     (gas_limit - gas_used - gas_to_burn, gas_to_burn)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waived_subsidy_keeps_miner_penalty() {
+        let mut outputs = GasOutputs::compute(
+            100,
+            110,
+            &TokenAmount::from_atto(20),
+            &TokenAmount::from_atto(10),
+            &TokenAmount::from_atto(1),
+        );
+        let penalty = outputs.miner_penalty.clone();
+        assert!(penalty.is_positive());
+
+        outputs.subsidize(SubsidyPayer::Waived);
+        assert!(outputs.subsidy.is_positive());
+        assert!(outputs.base_fee_burn.is_zero());
+        assert!(outputs.miner_tip.is_zero());
+        assert_eq!(outputs.miner_penalty, penalty);
+    }
+}
@@ -2,11 +2,12 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Mul;
 
 use anyhow::Context;
 use fvm_shared::crypto::signature::SignatureType;
+use fvm_shared::crypto::snark::SnarkCurve;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::event::{ActorEvent, Flags};
 use fvm_shared::piece::PieceInfo;
@@ -14,13 +15,14 @@ use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredPoStProof, RegisteredSealProof, ReplicaUpdateInfo,
     SealVerifyInfo, WindowPoStVerifyInfo,
 };
+use fvm_shared::sys::out::gas::GasPrices;
 use fvm_shared::version::NetworkVersion;
 use fvm_shared::{MethodNum, METHOD_SEND};
 use fvm_wasm_instrument::gas_metering::{InstructionCost, Operator, Rules};
 use lazy_static::lazy_static;
 use num_traits::Zero;
 
-use super::GasCharge;
+use super::{CostCurve, GasCharge};
 use crate::gas::Gas;
 use crate::kernel::SupportedHashes;
 
@@ -125,6 +127,7 @@ lazy_static! {
                 }
             }
         },
+        hasher_init: Gas::new(2000),
         compute_unsealed_sector_cid_base: Gas::new(98647),
         verify_seal_base: Gas::new(2000), // TODO revisit potential removal of this
 
@@ -176,31 +179,29 @@ lazy_static! {
         verify_consensus_fault: Gas::new(516422),
 
         verify_replica_update: Gas::new(36316136),
+        verify_merkle_proof: ScalingCost {
+            flat: Gas::new(10000),
+            scale: Gas::new(2500),
+        },
+        // Groth16 verification is only available from network version 19.
+        verify_groth16_lookup: HashMap::new(),
+        // PLONK verification is only available from network version 19.
+        verify_plonk_lookup: HashMap::new(),
         verify_post_lookup: [
             (
                 RegisteredPoStProof::StackedDRGWindow512MiBV1,
-                ScalingCost {
-                    flat: Gas::new(117680921),
-                    scale: Gas::new(43780),
-                },
+                CostCurve::linear(Gas::new(117680921), Gas::new(43780)),
             ),
             (
                 RegisteredPoStProof::StackedDRGWindow32GiBV1,
-                ScalingCost {
-                    flat: Gas::new(117680921),
-                    scale: Gas::new(43780),
-                },
+                CostCurve::linear(Gas::new(117680921), Gas::new(43780)),
             ),
             (
                 RegisteredPoStProof::StackedDRGWindow64GiBV1,
-                ScalingCost {
-                    flat: Gas::new(117680921),
-                    scale: Gas::new(43780),
-                },
+                CostCurve::linear(Gas::new(117680921), Gas::new(43780)),
             ),
         ]
-        .iter()
-        .copied()
+        .into_iter()
         .collect(),
 
         // TODO(#1277): Implement this first before benchmarking.
@@ -212,6 +213,9 @@ lazy_static! {
             scale: Gas::new(2),
         },
 
+        // Decoding syscall parameters is only charged from network version 19.
+        cbor_decode: ScalingCost::zero(),
+
         block_memcpy: ScalingCost {
             flat: Gas::zero(),
             scale: Gas::from_milligas(400),
@@ -222,6 +226,16 @@ lazy_static! {
             scale: Gas::new(10),
         },
 
+        block_oversize: ScalingCost {
+            flat: Gas::zero(),
+            scale: Gas::new(1000),
+        },
+
+        return_oversize: ScalingCost {
+            flat: Gas::zero(),
+            scale: Gas::new(100),
+        },
+
         block_open: ScalingCost {
             // This was benchmarked (#1264) at 187440 gas/read.
             flat: Gas::new(187440),
@@ -241,8 +255,18 @@ lazy_static! {
 
         block_persist_compute: Gas::new(172000),
 
+        // Blocks can only be prefetched from network version 19.
+        block_prefetch: Zero::zero(),
+
+        // Block handles can only be closed from network version 19.
+        block_close: Zero::zero(),
+
         syscall_cost: Gas::new(14000),
 
+        // Host call transitions and trap unwinding are only charged from network version 19.
+        host_call_transition: Zero::zero(),
+        trap_unwind: Zero::zero(),
+
         // TODO(#1279)
         state_read_base: Zero::zero(),
         // TODO(#1279)
@@ -254,8 +278,19 @@ lazy_static! {
         // TODO(#1279)
         message_context: Zero::zero(),
 
+        read_state_base: Gas::new(21000),
+
         install_wasm_per_byte_cost: Zero::zero(),
 
+        // Actor code can only be inspected from network version 19.
+        code_info: ScalingCost::zero(),
+
+        // Flags can only be set from network version 19.
+        sync_flag: Zero::zero(),
+
+        // Token transfer hooks are only available from network version 19.
+        token_transfer_hook: Zero::zero(),
+
         wasm_rules: WasmGasPrices{
             // Use the default instruction cost of 4 everywhere.
             instruction_default: Gas::new(4),
@@ -287,6 +322,85 @@ lazy_static! {
         // TODO(#1279)
         event_per_byte_cost: Zero::zero(),
     };
+
+    static ref LIGHTNING_PRICES: PriceList = PriceList {
+        // Closing a handle drops the block's registry slot (and, if it's the last reference, frees
+        // its buffer), which costs about as much as a small allocation.
+        block_close: Gas::new(1000),
+
+        // Queueing a hint is cheap. The block is loaded off the execution thread, and charged for
+        // when it's opened.
+        block_prefetch: Gas::new(2000),
+
+        // Syncing the gas counter and resolving the actor's memory export takes about 200ns per
+        // host call on top of the syscall itself (the slope of the `on_host_call` scenario, less
+        // `OnSyscall`), at 10 gas/ns.
+        host_call_transition: Gas::new(2000),
+        // Unwinding a trapped actor takes about 5us (the difference between the `abort` and
+        // `return` slopes of the `on_trap` scenario), dominated by the trap handler and capturing
+        // the backtrace rather than by the stack depth, at 10 gas/ns.
+        trap_unwind: Gas::new(50000),
+
+        // Looking the code up in the manifest is cheap, but we then load it (charged like opening a
+        // block) and parse it. Parsing only walks the section headers and the export section, so
+        // it's priced like hashing.
+        code_info: ScalingCost {
+            flat: Gas::new(1000),
+            scale: Gas::new(2),
+        },
+
+        cbor_decode: ScalingCost {
+            flat: Gas::zero(),
+            // The densest parameters (nested arrays of small integers) decode at about 2ns/byte in
+            // the `on_decode_params` scenario, at 10 gas/ns. Piece infos decode at under 1ns/byte.
+            scale: Gas::new(20),
+        },
+
+        // Setting or clearing a flag is a map update plus an entry in the invocation's rollback
+        // journal.
+        sync_flag: Gas::new(500),
+
+        // Checking a token transfer decodes it, looks up the caller, and encodes the receiver
+        // hook's parameters. Address resolution, block creation, and the hook's invocation are
+        // charged separately, so this only covers the (de)serialization: ~1.5µs at 10 gas/ns.
+        token_transfer_hook: Gas::new(15000),
+
+        // A Groth16 verifier never sees the circuit's private constraints, so its work only scales
+        // with the input constraints (one per public input, plus one for the constant input): it
+        // decodes and subgroup-checks a key point and multiplies it by the input for each. That's
+        // about 140µs per input constraint on BLS12-381 (dominated by the subgroup check), on top
+        // of ~2ms to decode the rest of the key and the proof, and for the three pairings, at
+        // 10 gas/ns.
+        verify_groth16_lookup: [(
+            SnarkCurve::Bls12_381,
+            ScalingCost {
+                flat: Gas::new(20000000),
+                scale: Gas::new(1400000),
+            },
+        )]
+        .into_iter()
+        .collect(),
+
+        // Unlike Groth16, a PLONK verifier's key is (nearly) the same size for every circuit:
+        // decoding it and the proof, and checking the pairings, takes ~22ms on BLS12-381. On top of
+        // that, it evaluates the public inputs over the whole evaluation domain (~0.34µs per
+        // element, most of which are zero), and computes a power and an inversion per public input
+        // (~15µs), at 10 gas/ns.
+        verify_plonk_lookup: [(
+            SnarkCurve::Bls12_381,
+            PlonkCost {
+                domain: ScalingCost {
+                    flat: Gas::new(220000000),
+                    scale: Gas::new(3400),
+                },
+                public_input: Gas::new(150000),
+            },
+        )]
+        .into_iter()
+        .collect(),
+
+        ..HYGGE_PRICES.clone()
+    };
 }
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
@@ -312,6 +426,11 @@ impl ScalingCost {
         }
     }
 
+    /// Returns the equivalent (linear) [`CostCurve`].
+    pub fn curve(&self) -> CostCurve {
+        CostCurve::linear(self.flat, self.scale)
+    }
+
     /// Create a "zero" scaling cost.
     pub fn zero() -> Self {
         Self {
@@ -321,6 +440,14 @@ impl ScalingCost {
     }
 }
 
+/// The cost of verifying a PLONK proof, which scales with both the size of the circuit's
+/// evaluation domain and its number of public inputs.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub(crate) struct PlonkCost {
+    pub domain: ScalingCost,
+    pub public_input: Gas,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) struct StepCost(Vec<Step>);
 
@@ -345,6 +472,15 @@ impl StepCost {
         }
         self.0[i as usize].cost
     }
+
+    /// Returns the equivalent (piecewise constant) [`CostCurve`].
+    pub fn curve(&self) -> CostCurve {
+        CostCurve::steps(
+            self.0
+                .iter()
+                .map(|step| (step.start.try_into().unwrap_or(0), step.cost)),
+        )
+    }
 }
 
 /// Provides prices for operations in the VM.
@@ -404,20 +540,37 @@ pub struct PriceList {
     pub(crate) secp256k1_recover_cost: Gas,
 
     pub(crate) hashing_cost: HashMap<SupportedHashes, ScalingCost>,
+    /// Gas cost of allocating an incremental hasher. The data fed to it is charged as per
+    /// `hashing_cost`.
+    pub(crate) hasher_init: Gas,
 
     pub(crate) compute_unsealed_sector_cid_base: Gas,
     pub(crate) verify_seal_base: Gas,
     pub(crate) verify_aggregate_seal_per: HashMap<RegisteredSealProof, Gas>,
     pub(crate) verify_aggregate_seal_steps: HashMap<RegisteredSealProof, StepCost>,
 
-    pub(crate) verify_post_lookup: HashMap<RegisteredPoStProof, ScalingCost>,
+    /// Gas cost of verifying a window PoSt, by the number of challenged sectors.
+    pub(crate) verify_post_lookup: HashMap<RegisteredPoStProof, CostCurve>,
     pub(crate) verify_consensus_fault: Gas,
     pub(crate) verify_replica_update: Gas,
+    /// Gas cost of verifying a Merkle proof, per node (decoding and comparing), excluding the cost
+    /// of hashing the nodes.
+    pub(crate) verify_merkle_proof: ScalingCost,
+    /// Gas cost of verifying a Groth16 proof, by the number of input constraints of the circuit,
+    /// excluding the cost of opening the verifying key. Curves that aren't priced aren't supported.
+    pub(crate) verify_groth16_lookup: HashMap<SnarkCurve, ScalingCost>,
+    /// Gas cost of verifying a PLONK proof, by the size of the circuit's evaluation domain and its
+    /// number of public inputs, excluding the cost of opening the verifying key. Curves that aren't
+    /// priced aren't supported.
+    pub(crate) verify_plonk_lookup: HashMap<SnarkCurve, PlonkCost>,
 
     /// Gas cost for fetching a randomness seed for an epoch. We charge separately for extracting
     /// randomness (hashing).
     pub(crate) get_randomness_seed: Gas,
 
+    /// Gas cost of decoding CBOR syscall parameters, charged before decoding begins.
+    pub(crate) cbor_decode: ScalingCost,
+
     /// Gas cost per byte copied.
     pub(crate) block_memcpy: ScalingCost,
 
@@ -431,6 +584,13 @@ pub struct PriceList {
     /// `min(block_memory_retention.scale, compute_costs)`.
     pub(crate) block_memory_retention_minimum: ScalingCost,
 
+    /// Gas cost of creating a block larger than the network's soft block size limit, scaled by the
+    /// square of the excess size (in KiB, rounded up) so that it grows superlinearly.
+    pub(crate) block_oversize: ScalingCost,
+
+    /// Gas cost, per byte, of returning more data than the network's soft return size limit.
+    pub(crate) return_oversize: ScalingCost,
+
     /// Gas cost for opening a block.
     pub(crate) block_open: ScalingCost,
 
@@ -440,9 +600,24 @@ pub struct PriceList {
     /// Gas cost to cover the cost of flushing a block.
     pub(crate) block_persist_compute: Gas,
 
+    /// Gas cost of hinting that a block should be prefetched.
+    pub(crate) block_prefetch: Gas,
+
+    /// Gas cost of closing a block handle.
+    pub(crate) block_close: Gas,
+
     /// General gas cost for performing a syscall, accounting for the overhead thereof.
     pub(crate) syscall_cost: Gas,
 
+    /// Gas cost of the transition from Wasm into the host and back, charged on every host call
+    /// (including calls into the WASI shim) on top of `syscall_cost`. This covers syncing the gas
+    /// counter and resolving the actor's memory, which don't depend on the syscall.
+    pub(crate) host_call_transition: Gas,
+
+    /// Gas cost of unwinding the actor's Wasm stack when it traps. Explicit exits (including aborts)
+    /// aren't charged, as they're already charged as syscalls.
+    pub(crate) trap_unwind: Gas,
+
     /// Rules for execution gas.
     pub(crate) wasm_rules: WasmGasPrices,
 
@@ -472,8 +647,22 @@ pub struct PriceList {
     /// Gas cost of accessing the message context.
     pub(crate) message_context: Gas,
 
+    /// Gas cost of looking up domain state through the node's `StateReader`, in addition to the
+    /// cost of copying the value.
+    pub(crate) read_state_base: Gas,
+
     /// Gas cost of compiling a Wasm module during install.
     pub(crate) install_wasm_per_byte_cost: Gas,
+
+    /// Gas cost of looking up actor code and scanning it for its metadata, in addition to the cost
+    /// of loading it.
+    pub(crate) code_info: ScalingCost,
+
+    /// Gas cost of setting or clearing a per-message flag.
+    pub(crate) sync_flag: Gas,
+
+    /// Gas cost of checking a token transfer, on top of its address resolutions and sends.
+    pub(crate) token_transfer_hook: Gas,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -543,6 +732,21 @@ impl PriceList {
         GasCharge::new("OnSyscall", self.syscall_cost, Zero::zero())
     }
 
+    /// Returns the gas cost of decoding CBOR syscall parameters of the given size.
+    pub fn on_decode_params(&self, len: usize) -> GasCharge {
+        GasCharge::new("OnDecodeParams", self.cbor_decode.apply(len), Zero::zero())
+    }
+
+    /// Returns the gas cost of transitioning into the host and back on a host call.
+    pub fn on_host_call(&self) -> GasCharge {
+        GasCharge::new("OnHostCall", self.host_call_transition, Zero::zero())
+    }
+
+    /// Returns the gas cost of unwinding the actor's stack when it traps.
+    pub fn on_trap(&self) -> GasCharge {
+        GasCharge::new("OnTrap", self.trap_unwind, Zero::zero())
+    }
+
     /// Returns the gas required for creating an actor.
     #[inline]
     pub fn on_create_actor(&self, is_new: bool) -> GasCharge {
@@ -586,6 +790,12 @@ impl PriceList {
         GasCharge::new("OnHashing", gas, Zero::zero())
     }
 
+    /// Returns gas required for opening an incremental hasher.
+    #[inline]
+    pub fn on_hasher_init(&self) -> GasCharge {
+        GasCharge::new("OnHasherInit", self.hasher_init, Zero::zero())
+    }
+
     /// Returns gas required for computing unsealed sector Cid.
     #[inline]
     pub fn on_compute_unsealed_sector_cid(
@@ -651,6 +861,51 @@ impl PriceList {
         )
     }
 
+    /// Returns gas required for verifying a Merkle proof of `nodes` nodes, hashing `bytes` bytes.
+    #[inline]
+    pub fn on_verify_merkle_proof(
+        &self,
+        hasher: SupportedHashes,
+        nodes: usize,
+        bytes: usize,
+    ) -> GasCharge {
+        GasCharge::new(
+            "OnVerifyMerkleProof",
+            self.verify_merkle_proof.apply(nodes) + self.hashing_cost[&hasher].apply(bytes),
+            Zero::zero(),
+        )
+    }
+
+    /// Returns gas required for verifying a Groth16 proof of a circuit with `constraints` input
+    /// constraints, or `None` if proofs over the curve aren't supported.
+    #[inline]
+    pub fn on_verify_groth16(&self, curve: SnarkCurve, constraints: usize) -> Option<GasCharge> {
+        let cost = self.verify_groth16_lookup.get(&curve)?;
+        Some(GasCharge::new(
+            "OnVerifyGroth16",
+            cost.apply(constraints),
+            Zero::zero(),
+        ))
+    }
+
+    /// Returns gas required for verifying a PLONK proof of a circuit with an evaluation domain of
+    /// `size` elements and `inputs` public inputs, or `None` if proofs over the curve aren't
+    /// supported.
+    #[inline]
+    pub fn on_verify_plonk(
+        &self,
+        curve: SnarkCurve,
+        size: usize,
+        inputs: usize,
+    ) -> Option<GasCharge> {
+        let cost = self.verify_plonk_lookup.get(&curve)?;
+        Some(GasCharge::new(
+            "OnVerifyPlonk",
+            cost.domain.apply(size) + cost.public_input * inputs,
+            Zero::zero(),
+        ))
+    }
+
     /// Returns gas required for PoSt verification.
     #[inline]
     pub fn on_verify_post(&self, info: &WindowPoStVerifyInfo) -> GasCharge {
@@ -753,6 +1008,30 @@ impl PriceList {
         GasCharge::new("OnBlockCreate", compute, retention_surcharge)
     }
 
+    /// Returns the surcharge for creating a block `excess_size` bytes larger than the network's
+    /// soft block size limit.
+    #[inline]
+    pub fn on_block_oversize(&self, excess_size: usize) -> GasCharge {
+        let excess_kib = (excess_size as u64 + 1023) / 1024;
+        GasCharge::new(
+            "OnBlockOversize",
+            Zero::zero(),
+            self.block_oversize
+                .apply(excess_kib.saturating_mul(excess_kib)),
+        )
+    }
+
+    /// Returns the surcharge for returning `excess_size` bytes more than the network's soft
+    /// return size limit.
+    #[inline]
+    pub fn on_return_oversize(&self, excess_size: usize) -> GasCharge {
+        GasCharge::new(
+            "OnReturnOversize",
+            Zero::zero(),
+            self.return_oversize.apply(excess_size),
+        )
+    }
+
     /// Returns the gas required for committing an object to the state blockstore.
     #[inline]
     pub fn on_block_link(&self, hash_code: SupportedHashes, data_size: usize) -> GasCharge {
@@ -781,12 +1060,48 @@ impl PriceList {
         GasCharge::new("OnBlockStat", Zero::zero(), Zero::zero())
     }
 
+    /// Returns the gas required for closing a block handle.
+    #[inline]
+    pub fn on_block_close(&self) -> GasCharge {
+        GasCharge::new("OnBlockClose", self.block_close, Zero::zero())
+    }
+
+    /// Returns the gas required for loading and scanning a block while walking an IPLD graph, in
+    /// addition to [`on_block_open_base`](Self::on_block_open_base). The block is never handed to
+    /// the actor, so there's no memory retention charge.
+    #[inline]
+    pub fn on_block_graph_stat(&self, data_size: usize) -> GasCharge {
+        GasCharge::new(
+            "OnBlockGraphStat",
+            self.block_memcpy.apply(data_size),
+            self.block_open.scale * data_size,
+        )
+    }
+
+    /// Returns the gas required for hinting that a block should be prefetched.
+    #[inline]
+    pub fn on_block_prefetch(&self) -> GasCharge {
+        GasCharge::new("OnBlockPrefetch", self.block_prefetch, Zero::zero())
+    }
+
     /// Returns the gas required for accessing the actor state root.
     #[inline]
     pub fn on_root(&self) -> GasCharge {
         GasCharge::new("OnRoot", self.state_read_base, Zero::zero())
     }
 
+    /// Returns the base gas required for computing the statistics of the actor's state graph, in
+    /// addition to the cost of walking the blocks that aren't tracked yet (see
+    /// [`on_block_graph_stat`](Self::on_block_graph_stat)).
+    #[inline]
+    pub fn on_self_state_stat(&self) -> GasCharge {
+        GasCharge::new(
+            "OnSelfStateStat",
+            self.state_read_base,
+            self.block_open.flat,
+        )
+    }
+
     /// Returns the gas required for modifying the actor state root.
     #[inline]
     pub fn on_set_root(&self) -> GasCharge {
@@ -811,6 +1126,30 @@ impl PriceList {
         GasCharge::new("OnBalanceOf", self.state_read_base, Zero::zero())
     }
 
+    /// Returns the gas required for accessing the nonce of an actor.
+    #[inline]
+    pub fn on_nonce_of(&self) -> GasCharge {
+        GasCharge::new("OnNonceOf", self.state_read_base, Zero::zero())
+    }
+
+    /// Returns the gas required for delegating a call to other code, on top of the method
+    /// invocation. Covers looking up the delegating actor.
+    #[inline]
+    pub fn on_delegate_call(&self) -> GasCharge {
+        GasCharge::new("OnDelegateCall", self.state_read_base, Zero::zero())
+    }
+
+    /// Returns the gas required for checking a token transfer before invoking the recipient's
+    /// receiver hook, on top of the address resolutions, block creation, and send it entails.
+    #[inline]
+    pub fn on_token_transfer_hook(&self) -> GasCharge {
+        GasCharge::new(
+            "OnTokenTransferHook",
+            self.token_transfer_hook,
+            Zero::zero(),
+        )
+    }
+
     /// Returns the gas required for resolving an actor address.
     ///
     /// Might require lookup in the state tree as well as loading the state of the init actor.
@@ -853,6 +1192,32 @@ impl PriceList {
         )
     }
 
+    /// Returns the gas required for looking up actor code, before loading it.
+    #[inline]
+    pub fn on_get_code_info_base(&self) -> GasCharge {
+        GasCharge::new(
+            "OnGetCodeInfoBase",
+            self.code_info.flat,
+            self.block_open.flat,
+        )
+    }
+
+    /// Returns the gas required for loading actor code of the given size and reading its metadata.
+    #[inline]
+    pub fn on_get_code_info(&self, wasm_size: usize) -> GasCharge {
+        GasCharge::new(
+            "OnGetCodeInfo",
+            self.code_info.scale * wasm_size,
+            self.block_open.scale * wasm_size,
+        )
+    }
+
+    /// Returns the gas required for setting or clearing a per-message flag.
+    #[inline]
+    pub fn on_sync_flag(&self) -> GasCharge {
+        GasCharge::new("OnSyncFlag", self.sync_flag, Zero::zero())
+    }
+
     /// Returns the gas required for accessing the network context.
     #[inline]
     pub fn on_network_context(&self) -> GasCharge {
@@ -865,6 +1230,22 @@ impl PriceList {
         GasCharge::new("OnMessageContext", self.message_context, Zero::zero())
     }
 
+    /// Returns the gas required for looking up domain state, independent of the value's size.
+    #[inline]
+    pub fn on_read_state(&self) -> GasCharge {
+        GasCharge::new("OnReadState", self.read_state_base, Zero::zero())
+    }
+
+    /// Returns the gas required for copying a domain state value into the FVM.
+    #[inline]
+    pub fn on_read_state_value(&self, value_size: usize) -> GasCharge {
+        GasCharge::new(
+            "OnReadStateValue",
+            self.block_allocate.apply(value_size) + self.block_memcpy.apply(value_size),
+            Zero::zero(),
+        )
+    }
+
     /// Returns the gas required for installing an actor.
     #[cfg(feature = "m2-native")]
     pub fn on_install_actor(&self, wasm_size: usize) -> GasCharge {
@@ -893,6 +1274,27 @@ impl PriceList {
             + self.wasm_rules.memory_fill_per_byte_cost * min_table_elements * TABLE_ELEMENT_SIZE
     }
 
+    /// Returns a selection of gas prices, in milligas, for introspection by actors.
+    pub fn gas_prices(&self) -> GasPrices {
+        GasPrices {
+            send_base: self.send_base.as_milligas(),
+            send_transfer_funds: self.send_transfer_funds.as_milligas(),
+            send_transfer_only_premium: self.send_transfer_only_premium.as_milligas(),
+            send_invoke_method: self.send_invoke_method.as_milligas(),
+            syscall_base: (self.syscall_cost + self.host_call_transition).as_milligas(),
+            block_persist_base: (self.block_persist_storage.flat + self.block_persist_compute)
+                .as_milligas(),
+            storage_per_byte: self.block_persist_storage.scale.as_milligas(),
+            on_chain_message_base: (self.on_chain_message_compute.flat
+                + self.on_chain_message_storage.flat)
+                .as_milligas(),
+            on_chain_message_per_byte: (self.on_chain_message_compute.scale
+                + self.on_chain_message_storage.scale)
+                .as_milligas(),
+            create_actor: (self.create_actor_compute + self.create_actor_storage).as_milligas(),
+        }
+    }
+
     #[inline]
     pub fn on_actor_event(&self, evt: &ActorEvent) -> GasCharge {
         let (mut indexed_entries, mut total_bytes) = (0, 0);
@@ -912,12 +1314,227 @@ impl PriceList {
                 + (self.event_per_byte_cost * total_bytes),
         )
     }
+
+    /// Returns every cost in the price list as a function of the size it scales with (e.g., a
+    /// constant for flat costs), by name, e.g., to publish the price list (as JSON) for review.
+    pub fn cost_curves(&self) -> BTreeMap<String, CostCurve> {
+        // Destructure exhaustively, so that new prices can't be left out.
+        let PriceList {
+            on_chain_message_compute,
+            on_chain_message_storage,
+            on_chain_return_compute,
+            on_chain_return_storage,
+            send_base,
+            send_transfer_funds,
+            send_transfer_only_premium,
+            send_invoke_method,
+            create_actor_compute,
+            create_actor_storage,
+            delete_actor,
+            sig_cost,
+            secp256k1_recover_cost,
+            hashing_cost,
+            hasher_init,
+            compute_unsealed_sector_cid_base,
+            verify_seal_base,
+            verify_aggregate_seal_per,
+            verify_aggregate_seal_steps,
+            verify_post_lookup,
+            verify_consensus_fault,
+            verify_replica_update,
+            verify_merkle_proof,
+            verify_groth16_lookup,
+            verify_plonk_lookup,
+            get_randomness_seed,
+            cbor_decode,
+            block_memcpy,
+            block_allocate,
+            block_memory_retention_minimum,
+            block_oversize,
+            return_oversize,
+            block_open,
+            block_persist_storage,
+            block_persist_compute,
+            block_prefetch,
+            block_close,
+            syscall_cost,
+            host_call_transition,
+            trap_unwind,
+            wasm_rules,
+            event_emit_base_cost,
+            event_per_entry_cost,
+            event_entry_index_cost,
+            event_per_byte_cost,
+            state_read_base,
+            state_write_base,
+            builtin_actor_manifest_lookup,
+            network_context,
+            message_context,
+            read_state_base,
+            install_wasm_per_byte_cost,
+            code_info,
+            sync_flag,
+            token_transfer_hook,
+        } = self;
+        let WasmGasPrices {
+            instruction_default,
+            math_default,
+            jump_unconditional,
+            jump_conditional,
+            jump_indirect,
+            call,
+            memory_fill_base_cost,
+            memory_fill_per_byte_cost,
+            memory_access_cost,
+            memory_copy_per_byte_cost,
+        } = wasm_rules;
+
+        let constant = |gas: &Gas| CostCurve::linear(*gas, Gas::zero());
+        let per_unit = |gas: &Gas| CostCurve::linear(Gas::zero(), *gas);
+        let mut curves: BTreeMap<String, CostCurve> = [
+            ("on_chain_message_compute", on_chain_message_compute.curve()),
+            ("on_chain_message_storage", on_chain_message_storage.curve()),
+            ("on_chain_return_compute", on_chain_return_compute.curve()),
+            ("on_chain_return_storage", on_chain_return_storage.curve()),
+            ("verify_merkle_proof", verify_merkle_proof.curve()),
+            ("cbor_decode", cbor_decode.curve()),
+            ("block_memcpy", block_memcpy.curve()),
+            ("block_allocate", block_allocate.curve()),
+            (
+                "block_memory_retention_minimum",
+                block_memory_retention_minimum.curve(),
+            ),
+            ("block_oversize", block_oversize.curve()),
+            ("return_oversize", return_oversize.curve()),
+            ("block_open", block_open.curve()),
+            ("block_persist_storage", block_persist_storage.curve()),
+            ("code_info", code_info.curve()),
+            ("send_base", constant(send_base)),
+            ("send_transfer_funds", constant(send_transfer_funds)),
+            (
+                "send_transfer_only_premium",
+                constant(send_transfer_only_premium),
+            ),
+            ("send_invoke_method", constant(send_invoke_method)),
+            ("create_actor_compute", constant(create_actor_compute)),
+            ("create_actor_storage", constant(create_actor_storage)),
+            ("delete_actor", constant(delete_actor)),
+            ("secp256k1_recover_cost", constant(secp256k1_recover_cost)),
+            ("hasher_init", constant(hasher_init)),
+            (
+                "compute_unsealed_sector_cid_base",
+                constant(compute_unsealed_sector_cid_base),
+            ),
+            ("verify_seal_base", constant(verify_seal_base)),
+            ("verify_consensus_fault", constant(verify_consensus_fault)),
+            ("verify_replica_update", constant(verify_replica_update)),
+            ("get_randomness_seed", constant(get_randomness_seed)),
+            ("block_persist_compute", constant(block_persist_compute)),
+            ("block_prefetch", constant(block_prefetch)),
+            ("block_close", constant(block_close)),
+            ("syscall_cost", constant(syscall_cost)),
+            ("host_call_transition", constant(host_call_transition)),
+            ("trap_unwind", constant(trap_unwind)),
+            ("event_emit_base_cost", constant(event_emit_base_cost)),
+            ("event_per_entry_cost", per_unit(event_per_entry_cost)),
+            ("event_entry_index_cost", per_unit(event_entry_index_cost)),
+            ("event_per_byte_cost", per_unit(event_per_byte_cost)),
+            ("state_read_base", constant(state_read_base)),
+            ("state_write_base", constant(state_write_base)),
+            (
+                "builtin_actor_manifest_lookup",
+                constant(builtin_actor_manifest_lookup),
+            ),
+            ("network_context", constant(network_context)),
+            ("message_context", constant(message_context)),
+            ("read_state_base", constant(read_state_base)),
+            (
+                "install_wasm_per_byte_cost",
+                per_unit(install_wasm_per_byte_cost),
+            ),
+            ("sync_flag", constant(sync_flag)),
+            ("token_transfer_hook", constant(token_transfer_hook)),
+            (
+                "wasm_rules.instruction_default",
+                constant(instruction_default),
+            ),
+            ("wasm_rules.math_default", constant(math_default)),
+            (
+                "wasm_rules.jump_unconditional",
+                constant(jump_unconditional),
+            ),
+            ("wasm_rules.jump_conditional", constant(jump_conditional)),
+            ("wasm_rules.jump_indirect", constant(jump_indirect)),
+            ("wasm_rules.call", constant(call)),
+            (
+                "wasm_rules.memory_fill_base_cost",
+                constant(memory_fill_base_cost),
+            ),
+            (
+                "wasm_rules.memory_fill_per_byte_cost",
+                per_unit(memory_fill_per_byte_cost),
+            ),
+            (
+                "wasm_rules.memory_access_cost",
+                constant(memory_access_cost),
+            ),
+            (
+                "wasm_rules.memory_copy_per_byte_cost",
+                per_unit(memory_copy_per_byte_cost),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, curve)| (name.to_owned(), curve))
+        .collect();
+        curves.extend(
+            sig_cost
+                .iter()
+                .map(|(k, cost)| (format!("sig_cost.{:?}", k), cost.curve())),
+        );
+        curves.extend(
+            hashing_cost
+                .iter()
+                .map(|(k, cost)| (format!("hashing_cost.{:?}", k), cost.curve())),
+        );
+        curves.extend(
+            verify_aggregate_seal_per
+                .iter()
+                .map(|(k, cost)| (format!("verify_aggregate_seal_per.{:?}", k), per_unit(cost))),
+        );
+        curves.extend(
+            verify_aggregate_seal_steps
+                .iter()
+                .map(|(k, cost)| (format!("verify_aggregate_seal_steps.{:?}", k), cost.curve())),
+        );
+        curves.extend(
+            verify_post_lookup
+                .iter()
+                .map(|(k, curve)| (format!("verify_post_lookup.{:?}", k), curve.clone())),
+        );
+        curves.extend(
+            verify_groth16_lookup
+                .iter()
+                .map(|(k, cost)| (format!("verify_groth16_lookup.{:?}", k), cost.curve())),
+        );
+        for (k, cost) in verify_plonk_lookup {
+            curves.insert(
+                format!("verify_plonk_lookup.{:?}.domain", k),
+                cost.domain.curve(),
+            );
+            curves.insert(
+                format!("verify_plonk_lookup.{:?}.public_input", k),
+                per_unit(&cost.public_input),
+            );
+        }
+        curves
+    }
 }
 
 /// Returns gas price list by NetworkVersion for gas consumption.
 pub fn price_list_by_network_version(network_version: NetworkVersion) -> &'static PriceList {
     match network_version {
         NetworkVersion::V18 => &HYGGE_PRICES,
+        NetworkVersion::V19 => &LIGHTNING_PRICES,
         _ => panic!("network version {nv} not supported", nv = network_version),
     }
 }
@@ -1240,3 +1857,59 @@ fn test_read_write() {
     );
     assert_eq!(HYGGE_PRICES.on_block_create(10).total(), Gas::new(100));
 }
+
+#[test]
+fn test_block_oversize() {
+    // Rounded up to the next KiB, then squared.
+    let cost = |excess| HYGGE_PRICES.on_block_oversize(excess).total();
+    assert_eq!(cost(0), Gas::zero());
+    assert_eq!(cost(1), Gas::new(1000));
+    assert_eq!(cost(1024), Gas::new(1000));
+    assert_eq!(cost(2048), Gas::new(4000));
+    assert_eq!(cost(1 << 20), Gas::new(1000 << 20));
+}
+
+#[test]
+fn test_return_oversize() {
+    let cost = |excess| HYGGE_PRICES.on_return_oversize(excess).total();
+    assert_eq!(cost(0), Gas::zero());
+    assert_eq!(cost(1), Gas::new(100));
+    assert_eq!(cost(1000), Gas::new(100_000));
+}
+
+#[test]
+fn test_cost_curves() {
+    let curves = HYGGE_PRICES.cost_curves();
+    assert_eq!(
+        curves["verify_post_lookup.StackedDRGWindow32GiBV1"],
+        HYGGE_PRICES.verify_post_lookup[&RegisteredPoStProof::StackedDRGWindow32GiBV1]
+    );
+    assert_eq!(
+        curves["hashing_cost.Blake2b256"].apply(100usize),
+        HYGGE_PRICES.hashing_cost[&SupportedHashes::Blake2b256].apply(100usize)
+    );
+
+    // Flat costs are included as constant curves, per-unit costs as linear ones, and step costs as
+    // piecewise constant ones.
+    assert_eq!(curves["send_base"].apply(1000u64), HYGGE_PRICES.send_base);
+    assert_eq!(
+        curves["event_per_entry_cost"].apply(3u64),
+        HYGGE_PRICES.event_per_entry_cost * 3u64
+    );
+    assert_eq!(
+        curves["wasm_rules.call"].apply(0u64),
+        HYGGE_PRICES.wasm_rules.call
+    );
+    let steps =
+        &HYGGE_PRICES.verify_aggregate_seal_steps[&RegisteredSealProof::StackedDRG32GiBV1P1];
+    let curve = &curves["verify_aggregate_seal_steps.StackedDRG32GiBV1P1"];
+    for x in [0, 4, 5, 100, 1000, 8192] {
+        assert_eq!(curve.apply(x as u64), steps.lookup(x));
+    }
+
+    let json = serde_json::to_string(&curves).unwrap();
+    assert_eq!(
+        serde_json::from_str::<BTreeMap<String, CostCurve>>(&json).unwrap(),
+        curves
+    );
+}
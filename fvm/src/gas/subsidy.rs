@@ -0,0 +1,128 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Gas subsidies: actor methods whose messages don't pay for their own gas.
+//!
+//! Networks built on the FVM (e.g., appchains) may want some system methods to be free to call. A
+//! [`GasSubsidy`] designates an actor method whose explicit messages have their gas paid for by a
+//! network account, or waived entirely, up to a quota of gas per executor (i.e., per epoch when
+//! the client creates an executor per tipset). Subsidies are configured per network with
+//! [`NetworkConfig::gas_subsidies`](crate::machine::NetworkConfig::gas_subsidies).
+//!
+//! A message is subsidized if its recipient and method match a subsidy, and its gas limit fits in
+//! the subsidy's remaining quota. Otherwise, its sender pays for gas as usual. The gas used by
+//! subsidized messages is deducted from the quota once they're applied. Message batches are
+//! never subsidized.
+//!
+//! The subsidized part of a message's gas cost is reported in
+//! [`ApplyRet::subsidy`](crate::executor::ApplyRet::subsidy).
+
+use fvm_shared::{ActorID, MethodNum};
+
+use crate::determinism::Map;
+
+/// A subsidy for the gas of the messages calling an actor method.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GasSubsidy {
+    /// The (recipient) actor whose method is subsidized.
+    pub actor: ActorID,
+    /// The subsidized method.
+    pub method: MethodNum,
+    /// Who pays for the gas of subsidized messages.
+    pub payer: SubsidyPayer,
+    /// The total gas the messages subsidized by this subsidy may use per executor.
+    pub quota: i64,
+}
+
+/// Who pays for the gas of subsidized messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubsidyPayer {
+    /// The given (network) account pays for gas instead of the sender, as a sponsor would. The
+    /// account must hold enough funds to cover the message's gas limit, or the message is invalid.
+    Account(ActorID),
+    /// Nobody pays for gas: nothing is burnt, and the miner receives no tip. The miner is still
+    /// penalized if the message's fee cap is below the base fee, as for any other message.
+    Waived,
+}
+
+/// The gas used by the messages subsidized by each subsidy, over the lifetime of an executor.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SubsidyQuotas {
+    used: Map<(ActorID, MethodNum), i64>,
+    /// The quota used since the last checkpoint, if one is open.
+    pending: Option<Map<(ActorID, MethodNum), i64>>,
+}
+
+impl SubsidyQuotas {
+    /// Returns the subsidy for a message calling `method` on `actor` with the given gas limit, if
+    /// any matches with enough quota left.
+    pub fn find<'a>(
+        &self,
+        subsidies: &'a [GasSubsidy],
+        actor: ActorID,
+        method: MethodNum,
+        gas_limit: i64,
+    ) -> Option<&'a GasSubsidy> {
+        subsidies.iter().find(|s| {
+            let used = self.used.get(&(s.actor, s.method)).copied().unwrap_or(0);
+            s.actor == actor && s.method == method && gas_limit <= s.quota.saturating_sub(used)
+        })
+    }
+
+    /// Deducts the gas used by a subsidized message from the subsidy's quota.
+    pub fn record(&mut self, subsidy: &GasSubsidy, gas_used: i64) {
+        let key = (subsidy.actor, subsidy.method);
+        *self.used.entry(key).or_default() += gas_used;
+        if let Some(pending) = &mut self.pending {
+            *pending.entry(key).or_default() += gas_used;
+        }
+    }
+
+    /// Starts tracking the quota used from now on, so that it can be given back with
+    /// [`rollback`](Self::rollback).
+    pub fn checkpoint(&mut self) {
+        self.pending = Some(Map::default());
+    }
+
+    /// Keeps the quota used since the last checkpoint.
+    pub fn commit(&mut self) {
+        self.pending = None;
+    }
+
+    /// Gives back the quota used since the last checkpoint.
+    pub fn rollback(&mut self) {
+        for (key, gas_used) in self.pending.take().unwrap_or_default() {
+            *self.used.entry(key).or_default() -= gas_used;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotas() {
+        let subsidies = [GasSubsidy {
+            actor: 100,
+            method: 2,
+            payer: SubsidyPayer::Waived,
+            quota: 1000,
+        }];
+        let mut quotas = SubsidyQuotas::default();
+
+        assert!(quotas.find(&subsidies, 100, 3, 10).is_none());
+        assert!(quotas.find(&subsidies, 101, 2, 10).is_none());
+        assert!(quotas.find(&subsidies, 100, 2, 1001).is_none());
+        let subsidy = quotas.find(&subsidies, 100, 2, 1000).unwrap();
+
+        quotas.record(subsidy, 600);
+        assert!(quotas.find(&subsidies, 100, 2, 500).is_none());
+        assert!(quotas.find(&subsidies, 100, 2, 400).is_some());
+
+        quotas.checkpoint();
+        quotas.record(subsidy, 400);
+        assert!(quotas.find(&subsidies, 100, 2, 1).is_none());
+        quotas.rollback();
+        assert!(quotas.find(&subsidies, 100, 2, 400).is_some());
+    }
+}
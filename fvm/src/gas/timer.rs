@@ -1,5 +1,6 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -25,6 +26,32 @@ impl GasDuration {
 /// Type alias so that we can disable this with a compiler flag.
 pub type GasInstant = Instant;
 
+/// Counts the Wasm instructions (strictly, wasmtime fuel units) executed by a message, so timers
+/// can measure them instead of wall-clock time.
+///
+/// Instruction counts are identical on every machine, so traces timed with this clock are
+/// bit-identical across machines, while still reflecting the relative cost of Wasm execution.
+/// Instructions are reported as one nanosecond each. Work done on the host side (e.g., in
+/// syscalls) executes no instructions, so it's measured as zero.
+#[derive(Default, Debug, Clone)]
+pub struct InstructionClock(Arc<AtomicU64>);
+
+impl InstructionClock {
+    /// Returns the number of instructions executed so far.
+    pub fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Advances the clock by the given number of instructions.
+    pub fn advance(&self, instructions: u64) {
+        let _ = self
+            .0
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                Some(count.saturating_add(instructions))
+            });
+    }
+}
+
 /// A handle returned by `charge_gas` which must be used to mark the end of
 /// the execution associated with that gas.
 #[must_use]
@@ -33,17 +60,24 @@ pub struct GasTimer(Option<GasTimerInner>);
 
 #[derive(Debug)]
 struct GasTimerInner {
-    start: GasInstant,
+    start: TimerStart,
     elapsed: DurationCell,
 }
 
+/// The reading of the clock a timer measures with, taken when it was created.
+#[derive(Debug)]
+enum TimerStart {
+    Wall(GasInstant),
+    Instructions(InstructionClock, u64),
+}
+
 impl GasTimer {
     /// Convenience method to start measuring time before the charge is made.
     ///
     /// Use the return value with [GasTimer::finish_with] to override the internal
     /// instant that the timer was started with.
     pub fn start() -> GasInstant {
-        GasInstant::now()
+        Instant::now()
     }
 
     /// Create a timer that doesn't measure anything.
@@ -55,6 +89,12 @@ impl GasTimer {
     ///
     /// As a side effect it will establish the cell in the `GasDuration`, if it has been empty so far.
     pub fn new(duration: &mut GasDuration) -> Self {
+        Self::new_with_clock(duration, None)
+    }
+
+    /// Create a new timer like [`GasTimer::new`], which measures instructions executed on the
+    /// given clock instead of wall-clock time, if any.
+    pub fn new_with_clock(duration: &mut GasDuration, clock: Option<&InstructionClock>) -> Self {
         assert!(duration.get().is_none(), "GasCharge::elapsed already set!");
 
         let cell = match &duration.0 {
@@ -66,8 +106,13 @@ impl GasTimer {
             }
         };
 
+        let start = match clock {
+            Some(clock) => TimerStart::Instructions(clock.clone(), clock.now()),
+            None => TimerStart::Wall(Self::start()),
+        };
+
         Self(Some(GasTimerInner {
-            start: Self::start(),
+            start,
             elapsed: cell,
         }))
     }
@@ -75,20 +120,34 @@ impl GasTimer {
     /// Record the elapsed time since the charge was made.
     pub fn stop(self) {
         if let Some(timer) = self.0 {
-            Self::set_elapsed(timer.elapsed, timer.start)
+            let elapsed = match timer.start {
+                TimerStart::Wall(start) => start.elapsed(),
+                TimerStart::Instructions(clock, start) => Self::instructions_since(&clock, start),
+            };
+            Self::set_elapsed(timer.elapsed, elapsed)
         }
     }
 
     /// Record the elapsed time based on an instant taken before the charge was made.
+    ///
+    /// Timers measuring instructions ignore the instant, and measure from the charge instead.
     pub fn stop_with(self, start: GasInstant) {
         if let Some(timer) = self.0 {
-            Self::set_elapsed(timer.elapsed, start)
+            let elapsed = match timer.start {
+                TimerStart::Wall(_) => start.elapsed(),
+                TimerStart::Instructions(clock, start) => Self::instructions_since(&clock, start),
+            };
+            Self::set_elapsed(timer.elapsed, elapsed)
         }
     }
 
-    fn set_elapsed(elapsed: Arc<OnceCell<Duration>>, start: GasInstant) {
+    fn instructions_since(clock: &InstructionClock, start: u64) -> Duration {
+        Duration::from_nanos(clock.now().saturating_sub(start))
+    }
+
+    fn set_elapsed(elapsed: Arc<OnceCell<Duration>>, duration: Duration) {
         elapsed
-            .set(start.elapsed())
+            .set(duration)
             .expect("GasCharge::elapsed already set!")
     }
 
@@ -104,3 +163,27 @@ impl GasTimer {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_clock() {
+        let clock = InstructionClock::default();
+        clock.advance(10);
+
+        let mut duration = GasDuration::default();
+        let timer = GasTimer::new_with_clock(&mut duration, Some(&clock));
+        clock.advance(100);
+        clock.clone().advance(20);
+        timer.stop_with(GasTimer::start());
+        assert_eq!(duration.get(), Some(&Duration::from_nanos(120)));
+        assert_eq!(clock.now(), 130);
+
+        let mut duration = GasDuration::default();
+        let timer = GasTimer::new_with_clock(&mut duration, Some(&clock));
+        timer.stop();
+        assert_eq!(duration.get(), Some(&Duration::ZERO));
+    }
+}
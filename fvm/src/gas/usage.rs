@@ -0,0 +1,127 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::BTreeMap;
+
+use cid::Cid;
+use fvm_shared::MethodNum;
+
+use super::Gas;
+
+/// The gas attributed to an actor code and method.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ActorGas {
+    /// The gas charged while executing the method, excluding gas charged by the calls it made.
+    pub gas: Gas,
+    /// The number of times the method was invoked.
+    pub calls: u64,
+}
+
+/// Gas usage by actor code and method, accumulated over the execution of one or more messages
+/// when [`MachineContext::actor_gas_accounting`] is enabled.
+///
+/// Each call is attributed the gas charged from the moment the callee is resolved until it
+/// returns, minus the gas attributed to the calls it makes in turn. That includes the method
+/// invocation and value transfer charges, so plain sends are attributed to the recipient's code
+/// with method 0. Gas charged outside of any call (e.g., message inclusion gas) isn't attributed.
+///
+/// [`MachineContext::actor_gas_accounting`]: crate::machine::MachineContext::actor_gas_accounting
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ActorGasUsage {
+    entries: BTreeMap<(Cid, MethodNum), ActorGas>,
+}
+
+impl ActorGasUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attributes the gas charged by a single call to `method` on an actor with the given code.
+    pub fn record(&mut self, code: Cid, method: MethodNum, gas: Gas) {
+        let entry = self.entries.entry((code, method)).or_default();
+        entry.gas += gas;
+        entry.calls += 1;
+    }
+
+    /// Adds all the usage recorded in `other` to this usage.
+    pub fn merge(&mut self, other: &ActorGasUsage) {
+        for (key, usage) in &other.entries {
+            let entry = self.entries.entry(*key).or_default();
+            entry.gas += usage.gas;
+            entry.calls += usage.calls;
+        }
+    }
+
+    /// Returns the usage attributed to `method` on actors with the given code, if any.
+    pub fn get(&self, code: &Cid, method: MethodNum) -> Option<&ActorGas> {
+        self.entries.get(&(*code, method))
+    }
+
+    /// Iterates over the usage by code and method, in that order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Cid, MethodNum, &ActorGas)> {
+        self.entries
+            .iter()
+            .map(|((code, method), usage)| (code, *method, usage))
+    }
+
+    /// Returns the usage by code and method, from the most to the least gas.
+    pub fn by_gas(&self) -> Vec<(&Cid, MethodNum, &ActorGas)> {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by(|a, b| b.2.gas.cmp(&a.2.gas));
+        entries
+    }
+
+    /// Returns the total gas attributed to all actors.
+    pub fn total(&self) -> Gas {
+        self.entries
+            .values()
+            .fold(Gas::default(), |total, usage| total + usage.gas)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_shared::IPLD_RAW;
+
+    use super::*;
+
+    fn code(name: &[u8]) -> Cid {
+        Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(name))
+    }
+
+    #[test]
+    fn accumulate() {
+        let (a, b) = (code(b"a"), code(b"b"));
+        let mut usage = ActorGasUsage::new();
+        usage.record(a, 2, Gas::new(10));
+        usage.record(a, 2, Gas::new(5));
+        usage.record(a, 3, Gas::new(1));
+
+        let mut other = ActorGasUsage::new();
+        other.record(b, 2, Gas::new(100));
+        other.record(a, 3, Gas::new(1));
+        usage.merge(&other);
+
+        assert_eq!(
+            usage.get(&a, 2),
+            Some(&ActorGas {
+                gas: Gas::new(15),
+                calls: 2
+            })
+        );
+        assert_eq!(usage.get(&a, 3).unwrap().calls, 2);
+        assert_eq!(usage.get(&b, 3), None);
+        assert_eq!(usage.total(), Gas::new(117));
+
+        let ranked: Vec<_> = usage
+            .by_gas()
+            .into_iter()
+            .map(|(c, m, _)| (*c, m))
+            .collect();
+        assert_eq!(ranked, vec![(b, 2), (a, 2), (a, 3)]);
+    }
+}
@@ -1,7 +1,7 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use std::convert::TryInto;
-use std::rc::Rc;
+use std::sync::Arc;
 
 use fvm_ipld_encoding::DAG_CBOR;
 use fvm_shared::IPLD_RAW;
@@ -10,9 +10,23 @@ use thiserror::Error;
 use super::{ExecutionError, SyscallError};
 use crate::syscall_error;
 
-#[derive(Default)]
+/// The blocks opened or created by a single call frame. The registry is owned by the frame's
+/// kernel, so all of its blocks are released when the frame exits.
+///
+/// Handles are assigned in order and never reused, so a closed handle stays invalid for the rest
+/// of the frame.
 pub struct BlockRegistry {
-    blocks: Vec<Block>,
+    blocks: Vec<Option<Block>>,
+    /// The number of open blocks.
+    open: u32,
+    /// The maximum number of blocks that may be open at once.
+    max_open: u32,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new(MAX_BLOCKS)
+    }
 }
 
 /// Blocks in the block registry are addressed by an ordinal, starting from 1 (`FIRST_ID`).
@@ -21,7 +35,8 @@ pub struct BlockRegistry {
 pub type BlockId = u32;
 
 const FIRST_ID: BlockId = 1;
-const MAX_BLOCKS: u32 = i32::MAX as u32; // TODO(M2): Limit
+/// The maximum number of blocks a frame may hold open, regardless of the configured limit.
+pub const MAX_BLOCKS: u32 = i32::MAX as u32;
 
 /// Codecs allowed by the IPLD subsytem.
 const ALLOWED_CODECS: &[u64; 2] = &[DAG_CBOR, IPLD_RAW];
@@ -32,13 +47,29 @@ pub struct BlockStat {
     pub size: u32,
 }
 
+/// The approximate size of an IPLD graph, as computed by
+/// [`IpldBlockOps::block_graph_stat`](super::IpldBlockOps::block_graph_stat).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct GraphStat {
+    /// The total size of the blocks visited, in bytes.
+    pub size: u64,
+    /// The number of blocks visited.
+    pub blocks: u64,
+    /// The depth of the deepest block visited (0 for the root alone).
+    pub depth: u32,
+    /// False if the traversal stopped at the block budget before visiting the whole graph, or
+    /// skipped links to missing blocks.
+    pub complete: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Block {
     codec: u64,
-    // Unfortunately, we usually start with a vector/boxed buffer. If we used Rc<[u8]>, we'd have to
-    // copy the bytes. So we accept some indirection for reliable performance.
+    // Unfortunately, we usually start with a vector/boxed buffer. If we used Arc<[u8]>, we'd have to
+    // copy the bytes. So we accept some indirection for reliable performance. The buffer is shared
+    // atomically so that blocks can be cached by the machine (see `HeadCache`).
     #[allow(clippy::redundant_allocation)]
-    data: Rc<Box<[u8]>>,
+    data: Arc<Box<[u8]>>,
 }
 
 impl Block {
@@ -47,7 +78,7 @@ impl Block {
         // The extra allocation is basically nothing.
         Self {
             codec,
-            data: Rc::new(data.into()),
+            data: Arc::new(data.into()),
         }
     }
 
@@ -115,15 +146,21 @@ impl From<InvalidHandleError> for ExecutionError {
 }
 
 impl BlockRegistry {
-    pub(crate) fn new() -> Self {
-        Self { blocks: Vec::new() }
+    /// Creates an empty registry allowing at most `max_open` blocks to be open at once (capped
+    /// at [`MAX_BLOCKS`]).
+    pub fn new(max_open: u32) -> Self {
+        Self {
+            blocks: Vec::new(),
+            open: 0,
+            max_open: max_open.min(MAX_BLOCKS),
+        }
     }
 }
 
 impl BlockRegistry {
     /// Adds a new block to the registry, and returns a handle to refer to it.
     pub fn put(&mut self, block: Block) -> Result<BlockId, BlockPutError> {
-        if self.is_full() {
+        if self.is_full() || self.blocks.len() as u32 >= MAX_BLOCKS {
             return Err(BlockPutError::TooManyBlocks);
         }
 
@@ -132,7 +169,8 @@ impl BlockRegistry {
         }
 
         let id = FIRST_ID + self.blocks.len() as u32;
-        self.blocks.push(block);
+        self.blocks.push(Some(block));
+        self.open += 1;
         Ok(id)
     }
 
@@ -144,25 +182,86 @@ impl BlockRegistry {
         id.try_into()
             .ok()
             .and_then(|idx: usize| self.blocks.get(idx - FIRST_ID as usize))
+            .and_then(Option::as_ref)
             .ok_or(InvalidHandleError(id))
     }
 
     /// Returns the size & codec of the specified block.
     pub fn stat(&self, id: BlockId) -> Result<BlockStat, InvalidHandleError> {
+        self.get(id).map(Block::stat)
+    }
+
+    /// Closes a block handle, releasing the block.
+    pub fn close(&mut self, id: BlockId) -> Result<(), InvalidHandleError> {
         if id < FIRST_ID {
             return Err(InvalidHandleError(id));
         }
-        id.try_into()
-            .ok()
-            .and_then(|idx: usize| self.blocks.get(idx - FIRST_ID as usize))
-            .ok_or(InvalidHandleError(id))
-            .map(|b| BlockStat {
-                codec: b.codec(),
-                size: b.size(),
-            })
+        match self.blocks.get_mut((id - FIRST_ID) as usize) {
+            Some(slot @ Some(_)) => {
+                *slot = None;
+                self.open -= 1;
+                Ok(())
+            }
+            _ => Err(InvalidHandleError(id)),
+        }
+    }
+
+    /// Returns the number of open blocks.
+    pub fn len(&self) -> u32 {
+        self.open
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the open blocks.
+    pub fn iter(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.iter().flatten()
     }
 
     pub fn is_full(&self) -> bool {
-        self.blocks.len() as u32 == MAX_BLOCKS
+        self.len() >= self.max_open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(data: &[u8]) -> Block {
+        Block::new(DAG_CBOR, data)
+    }
+
+    #[test]
+    fn limit() {
+        let mut reg = BlockRegistry::new(2);
+        let a = reg.put(block(b"a")).unwrap();
+        let b = reg.put(block(b"b")).unwrap();
+        assert!(reg.is_full());
+        assert!(matches!(
+            reg.put(block(b"c")),
+            Err(BlockPutError::TooManyBlocks)
+        ));
+
+        // Closing a block frees up a slot, but the handle isn't reused.
+        reg.close(a).unwrap();
+        assert!(reg.get(a).is_err());
+        assert!(reg.close(a).is_err());
+        assert_eq!(reg.len(), 1);
+        let c = reg.put(block(b"c")).unwrap();
+        assert_eq!(c, b + 1);
+        assert!(reg.get(a).is_err());
+        assert_eq!(reg.get(c).unwrap().data(), b"c");
+        assert_eq!(reg.get(b).unwrap().data(), b"b");
+    }
+
+    #[test]
+    fn invalid_handles() {
+        let mut reg = BlockRegistry::default();
+        assert!(reg.close(0).is_err());
+        assert!(reg.close(FIRST_ID).is_err());
+        assert!(reg.get(FIRST_ID).is_err());
+        assert!(reg.is_empty());
     }
 }
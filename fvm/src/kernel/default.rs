@@ -1,6 +1,6 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::convert::{TryFrom, TryInto};
 use std::panic::{self, UnwindSafe};
 use std::path::PathBuf;
@@ -9,17 +9,23 @@ use anyhow::{anyhow, Context as _};
 use cid::Cid;
 use filecoin_proofs_api::{self as proofs, ProverId, PublicReplicaInfo, SectorId};
 use fvm_ipld_blockstore::Blockstore;
-use fvm_ipld_encoding::bytes_32;
+use fvm_ipld_encoding::{bytes_32, from_slice, to_vec, RawBytes, DAG_CBOR};
 use fvm_shared::address::Payload;
 use fvm_shared::bigint::Zero;
 use fvm_shared::consensus::ConsensusFault;
 use fvm_shared::crypto::signature;
+use fvm_shared::crypto::snark::SnarkVerifyingKey;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ErrorNumber;
 use fvm_shared::piece::{zero_piece_commitment, PaddedPieceSize};
 use fvm_shared::sector::SectorInfo;
+use fvm_shared::state::StateQuery;
 use fvm_shared::sys::out::vm::ContextFlags;
-use fvm_shared::{commcid, ActorID};
+use fvm_shared::token::{
+    FRC46TokenReceived, UniversalReceiverParams, FRC46_TOKEN_TYPE, MAX_RECEIVER_HOOK_GAS,
+    RECEIVER_HOOK_METHOD_NUM,
+};
+use fvm_shared::{commcid, ActorID, IDENTITY_HASH, METHOD_SEND};
 use lazy_static::lazy_static;
 use multihash::MultihashDigest;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
@@ -28,13 +34,17 @@ use rayon::prelude::ParallelDrainRange;
 use super::blocks::{Block, BlockRegistry};
 use super::error::Result;
 use super::hash::SupportedHashes;
-use super::*;
+use super::links::for_each_link;
+use super::{groth16, merkle, plonk, *};
 use crate::call_manager::{CallManager, InvocationResult, NO_DATA_BLOCK_ID};
-use crate::externs::{Chain, Consensus, Rand};
-use crate::gas::GasTimer;
-use crate::machine::{MachineContext, NetworkConfig};
+use crate::cron_actor::{self, CRON_ACTOR_ID};
+use crate::engine::code_info::{CodeInfo, MAX_CODE_SIZE};
+use crate::externs::{Chain, Consensus, Rand, StateReader};
+use crate::gas::{GasTimer, InstructionClock};
+use crate::machine::{HeadLookup, MachineContext, NetworkConfig};
 use crate::state_tree::ActorState;
 use crate::syscall_error;
+use crate::trace::ExecutionEvent;
 
 lazy_static! {
     static ref NUM_CPUS: usize = num_cpus::get();
@@ -44,7 +54,6 @@ lazy_static! {
 const BLAKE2B_256: u64 = 0xb220;
 const ENV_ARTIFACT_DIR: &str = "FVM_STORE_ARTIFACT_DIR";
 const MAX_ARTIFACT_NAME_LEN: usize = 256;
-const FINALITY: i64 = 900;
 
 /// The "default" [`Kernel`] implementation.
 pub struct DefaultKernel<C> {
@@ -63,6 +72,8 @@ pub struct DefaultKernel<C> {
     ///
     /// This does not yet reason about reachability.
     blocks: BlockRegistry,
+    /// Payloads returned by syscalls, waiting to be popped by the actor.
+    returns: VecDeque<Vec<u8>>,
 }
 
 // Even though all children traits are implemented, Rust needs to know that the
@@ -95,6 +106,7 @@ where
             actor_id,
             method,
             value_received,
+            returns: VecDeque::new(),
         }
     }
 
@@ -107,6 +119,38 @@ impl<C> DefaultKernel<C>
 where
     C: CallManager,
 {
+    /// Returns the externs for the named extern call, failing if the machine's fault injector
+    /// says so. See [`crate::fault`].
+    fn externs(&self, _name: &str) -> anyhow::Result<&<C::Machine as Machine>::Externs> {
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &self.call_manager.context().faults {
+            faults.extern_error(_name)?;
+        }
+        Ok(self.call_manager.externs())
+    }
+
+    /// Returns true if the block is reachable by the actor: it's the actor's state root, or it's
+    /// linked by one of the actor's open blocks. Charges for scanning the open blocks for links.
+    fn is_reachable(&self, cid: &Cid) -> Result<bool> {
+        if matches!(self.get_self()?, Some(state) if state.state == *cid) {
+            return Ok(true);
+        }
+        for block in self.blocks.iter() {
+            let t = self.call_manager.charge_gas(
+                self.call_manager
+                    .price_list()
+                    .on_block_graph_stat(block.size() as usize),
+            )?;
+            let mut linked = false;
+            for_each_link(block.codec(), block.data(), |link| linked |= link == *cid);
+            t.stop();
+            if linked {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
     /// Returns `Some(actor_state)` or `None` if this actor has been deleted.
     fn get_self(&self) -> Result<Option<ActorState>> {
         self.call_manager
@@ -133,6 +177,27 @@ where
                 }
             })
     }
+
+    /// Decodes the verifying key of a succinct proof from an open block, charging for decoding
+    /// _before_ decoding begins, as for syscall parameters.
+    fn read_verifying_key(&self, vk: BlockId) -> Result<SnarkVerifyingKey> {
+        let block = self.blocks.get(vk)?;
+        if block.codec() != DAG_CBOR {
+            return Err(syscall_error!(IllegalArgument;
+                "verifying key must be DAG-CBOR encoded, got codec {}", block.codec())
+            .into());
+        }
+        let t = self.call_manager.charge_gas(
+            self.call_manager
+                .price_list()
+                .on_decode_params(block.size() as usize),
+        )?;
+        t.record(
+            from_slice(block.data()).map_err(|e| {
+                syscall_error!(IllegalArgument; "malformed verifying key: {}", e).into()
+            }),
+        )
+    }
 }
 
 impl<C> SelfOps for DefaultKernel<C>
@@ -151,6 +216,11 @@ where
             .or_error(ErrorNumber::IllegalOperation)?
             .state;
 
+        // The actor will likely open its head next, so cache it when it does.
+        if let Some(cache) = self.call_manager.machine().head_cache() {
+            cache.mark(cid);
+        }
+
         Ok(cid)
     }
 
@@ -206,6 +276,81 @@ where
                 .delete_actor(self.actor_id),
         )
     }
+
+    fn self_state_stat(&mut self) -> Result<GraphStat> {
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_self_state_stat())?;
+
+        let root = t
+            .record(self.get_self())?
+            .context("state statistics requested after actor deletion")
+            .or_error(ErrorNumber::IllegalOperation)?
+            .state;
+
+        // Walk the graph depth-first, computing the statistics of each block once those of its
+        // children are known. Tracked blocks aren't walked (or charged for) again.
+        let mut stack: Vec<(Cid, GraphStat, Vec<Cid>)> = Vec::new();
+        let mut visit = Some(root);
+        loop {
+            let finished = match visit.take() {
+                Some(cid) => match self.call_manager.link_tracker().get(&cid) {
+                    Some(stat) => Some(stat),
+                    None => {
+                        let _ = self
+                            .call_manager
+                            .charge_gas(self.call_manager.price_list().on_block_open_base())?;
+                        match self.call_manager.blockstore().get(&cid).or_fatal()? {
+                            Some(data) => {
+                                let t = self.call_manager.charge_gas(
+                                    self.call_manager
+                                        .price_list()
+                                        .on_block_graph_stat(data.len()),
+                                )?;
+                                let mut links = Vec::new();
+                                for_each_link(cid.codec(), &data, |link| links.push(link));
+                                stack.push((cid, GraphStat::leaf(data.len()), links));
+                                t.stop();
+                                continue;
+                            }
+                            None if stack.is_empty() => {
+                                return Err(
+                                    syscall_error!(NotFound; "state root {} not found", cid).into(),
+                                )
+                            }
+                            // Links to missing blocks are skipped, and make the statistics
+                            // incomplete.
+                            None => {
+                                let (_, parent, _) = stack.last_mut().expect("walk stack empty");
+                                parent.complete = false;
+                                None
+                            }
+                        }
+                    }
+                },
+                None => {
+                    let (_, _, links) = stack.last_mut().expect("walk stack empty");
+                    if let Some(link) = links.pop() {
+                        visit = Some(link);
+                        continue;
+                    }
+                    let (cid, stat, _) = stack.pop().expect("walk stack empty");
+                    if stat.complete {
+                        self.call_manager.link_tracker_mut().insert(cid, stat);
+                    }
+                    Some(stat)
+                }
+            };
+            match stack.last_mut() {
+                Some((_, parent, _)) => {
+                    if let Some(child) = finished {
+                        *parent = parent.with_child(&child);
+                    }
+                }
+                None => return Ok(finished.expect("state root not walked")),
+            }
+        }
+    }
 }
 
 impl<C> IpldBlockOps for DefaultKernel<C>
@@ -221,20 +366,52 @@ where
 
         let start = GasTimer::start();
 
-        let data = self
-            .call_manager
-            .blockstore()
-            .get(cid)
-            // TODO: This is really "super fatal". It means we failed to store state, and should
-            // probably abort the entire block.
-            .or_fatal()?
-            .ok_or_else(|| anyhow!("missing state: {}", cid))
-            // Missing state is a fatal error because it means we have a bug. Once we do
-            // reachability checking (for user actors) we won't get here unless the block is known
-            // to be in the state-tree.
-            .or_fatal()?;
+        // Actors usually start by loading their head, so check the machine's head cache first.
+        let cached = match self.call_manager.machine().head_cache() {
+            Some(cache) => cache.get(cid),
+            None => HeadLookup::Miss,
+        };
+        let block = match cached {
+            HeadLookup::Hit(block) => block,
+            lookup => {
+                // Blocks are content-addressed, so a prefetched block is the block we'd read.
+                let prefetched = match self.call_manager.machine().prefetcher() {
+                    Some(prefetcher) => prefetcher.take(cid),
+                    None => None,
+                };
+                let data = match prefetched {
+                    Some(data) => data,
+                    None => self
+                        .call_manager
+                        .blockstore()
+                        .get(cid)
+                        // TODO: This is really "super fatal". It means we failed to store state,
+                        // and should probably abort the entire block.
+                        .or_fatal()?
+                        .ok_or_else(|| anyhow!("missing state: {}", cid))
+                        // Missing state is a fatal error because it means we have a bug. Once we
+                        // do reachability checking (for user actors) we won't get here unless the
+                        // block is known to be in the state-tree.
+                        .or_fatal()?,
+                };
+
+                let block = Block::new(cid.codec(), data);
+                if let (HeadLookup::Head, Some(cache)) =
+                    (lookup, self.call_manager.machine().head_cache())
+                {
+                    cache.put(cid, &block);
+                }
+                block
+            }
+        };
 
-        let block = Block::new(cid.codec(), data);
+        if !self
+            .call_manager
+            .limiter_mut()
+            .read_block(block.size() as usize)
+        {
+            return Err(syscall_error!(LimitExceeded; "state read limit exceeded").into());
+        }
 
         let t = self.call_manager.charge_gas(
             self.call_manager
@@ -249,10 +426,35 @@ where
     }
 
     fn block_create(&mut self, codec: u64, data: &[u8]) -> Result<BlockId> {
+        let (soft_limit, hard_limit, strict_cbor) = {
+            let network = &self.call_manager.context().network;
+            (
+                network.block_size_soft_limit,
+                network.max_block_size,
+                network.strict_cbor,
+            )
+        };
+        if data.len() > hard_limit as usize {
+            return Err(syscall_error!(LimitExceeded; "block size {} exceeds the maximum block size {}", data.len(), hard_limit).into());
+        }
+        if data.len() > soft_limit as usize {
+            let _ = self.call_manager.charge_gas(
+                self.call_manager
+                    .price_list()
+                    .on_block_oversize(data.len() - soft_limit as usize),
+            )?;
+        }
+
         let t = self
             .call_manager
             .charge_gas(self.call_manager.price_list().on_block_create(data.len()))?;
 
+        if strict_cbor && codec == DAG_CBOR {
+            fvm_ipld_encoding::check_canonical(data).map_err(
+                |e| syscall_error!(Serialization; "non-canonical DAG-CBOR block: {}", e.description),
+            )?;
+        }
+
         t.record(Ok(self.blocks.put(Block::new(codec, data))?))
     }
 
@@ -283,7 +485,15 @@ where
             // TODO: This is really "super fatal". It means we failed to store state, and should
             // probably abort the entire block.
             .or_fatal()?;
+        self.call_manager
+            .link_tracker_mut()
+            .on_link(k, block.data());
         t.stop_with(start);
+        if self.call_manager.context().tracing {
+            let size = block.size() as u64;
+            self.call_manager
+                .trace(ExecutionEvent::BlockLink { cid: k, size });
+        }
         Ok(k)
     }
 
@@ -329,6 +539,84 @@ where
 
         t.record(Ok(self.blocks.stat(id)?))
     }
+
+    fn block_close(&mut self, id: BlockId) -> Result<()> {
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_block_close())?;
+
+        t.record(Ok(self.blocks.close(id)?))
+    }
+
+    fn block_graph_stat(&mut self, root: &Cid, max_blocks: u32) -> Result<GraphStat> {
+        if max_blocks == 0 {
+            return Err(syscall_error!(IllegalArgument; "block budget must be non-zero").into());
+        }
+
+        if !self.is_reachable(root)? {
+            return Err(syscall_error!(NotFound; "block {} isn't reachable", root).into());
+        }
+
+        let mut stat = GraphStat {
+            complete: true,
+            ..Default::default()
+        };
+        let mut seen = HashSet::from([*root]);
+        let mut queue = VecDeque::from([(*root, 0u32)]);
+        while let Some((cid, depth)) = queue.pop_front() {
+            if stat.blocks == max_blocks as u64 {
+                stat.complete = false;
+                break;
+            }
+
+            let _ = self
+                .call_manager
+                .charge_gas(self.call_manager.price_list().on_block_open_base())?;
+            let data = match self.call_manager.blockstore().get(&cid).or_fatal()? {
+                Some(data) => data,
+                None if cid == *root => {
+                    return Err(syscall_error!(NotFound; "block {} not found", cid).into())
+                }
+                // The rest of the graph can still be visited, but the statistics are partial.
+                None => {
+                    stat.complete = false;
+                    continue;
+                }
+            };
+            let t = self.call_manager.charge_gas(
+                self.call_manager
+                    .price_list()
+                    .on_block_graph_stat(data.len()),
+            )?;
+
+            stat.blocks += 1;
+            stat.size += data.len() as u64;
+            stat.depth = stat.depth.max(depth);
+
+            for_each_link(cid.codec(), &data, |link| {
+                if seen.insert(link) {
+                    queue.push_back((link, depth + 1));
+                }
+            });
+            t.stop();
+        }
+        Ok(stat)
+    }
+
+    fn block_prefetch(&self, cid: &Cid) -> Result<()> {
+        let _ = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_block_prefetch())?;
+
+        // Identity blocks are inlined in their CIDs, so there's nothing to load.
+        if cid.hash().code() == IDENTITY_HASH {
+            return Ok(());
+        }
+        if let Some(prefetcher) = self.call_manager.machine().prefetcher() {
+            prefetcher.prefetch(*cid);
+        }
+        Ok(())
+    }
 }
 
 impl<C> MessageOps for DefaultKernel<C>
@@ -401,7 +689,77 @@ where
                 cm.send::<Self>(from, *recipient, method, params, value, gas_limit)
             })?;
 
-        // Store result and return.
+        self.store_send_result(result)
+    }
+
+    fn delegate_call(
+        &mut self,
+        code: &Cid,
+        method: MethodNum,
+        params_id: BlockId,
+        gas_limit: Option<Gas>,
+        flags: SendFlags,
+    ) -> Result<SendResult> {
+        let network = &self.call_manager.context().network;
+        if !network.delegate_call {
+            return Err(syscall_error!(Forbidden; "delegate calls are not enabled").into());
+        }
+        if !network.delegate_call_code.contains(code) {
+            return Err(syscall_error!(Forbidden; "cannot delegate calls to code {}", code).into());
+        }
+        if method == METHOD_SEND {
+            return Err(syscall_error!(IllegalArgument; "cannot delegate a plain send").into());
+        }
+
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_delegate_call())?;
+        let self_code = t
+            .record(self.get_self())?
+            .context("delegate call requested after actor deletion")
+            .or_error(ErrorNumber::IllegalOperation)?
+            .code;
+        if self
+            .call_manager
+            .machine()
+            .builtin_actors()
+            .id_by_code(&self_code)
+            != 0
+        {
+            return Err(
+                syscall_error!(Forbidden; "builtin actor {} may not delegate calls", self.actor_id)
+                    .into(),
+            );
+        }
+
+        let params = if params_id == NO_DATA_BLOCK_ID {
+            None
+        } else {
+            Some(self.blocks.get(params_id)?.clone())
+        };
+
+        if self.blocks.is_full() {
+            return Err(syscall_error!(LimitExceeded; "cannot store return block").into());
+        }
+
+        let (caller, actor_id, code) = (self.caller, self.actor_id, *code);
+        let value = self.value_received.clone();
+        let result = self
+            .call_manager
+            .with_transaction(flags.read_only(), |cm| {
+                cm.delegate_call::<Self>(caller, actor_id, code, method, params, &value, gas_limit)
+            })?;
+
+        self.store_send_result(result)
+    }
+}
+
+impl<C> DefaultKernel<C>
+where
+    C: CallManager,
+{
+    /// Stores the return value of a send (if any) in the block registry.
+    fn store_send_result(&mut self, result: InvocationResult) -> Result<SendResult> {
         Ok(match result {
             InvocationResult {
                 exit_code,
@@ -496,13 +854,7 @@ where
     }
 
     fn hash(&self, code: u64, data: &[u8]) -> Result<MultihashGeneric<64>> {
-        let hasher = SupportedHashes::try_from(code).map_err(|e| {
-            if let multihash::Error::UnsupportedCode(code) = e {
-                syscall_error!(IllegalArgument; "unsupported hash code {}", code)
-            } else {
-                syscall_error!(AssertionFailed; "hash expected unsupported code, got {}", e)
-            }
-        })?;
+        let hasher = supported_hash(code)?;
 
         let t = self.call_manager.charge_gas(
             self.call_manager
@@ -510,7 +862,32 @@ where
                 .on_hashing(hasher, data.len()),
         )?;
 
-        t.record(Ok(hasher.digest(data)))
+        t.record(
+            self.call_manager
+                .syscall_memo()
+                .hash(code, data, || Ok(hasher.digest(data))),
+        )
+    }
+
+    fn hash_init(&self, code: u64) -> Result<HasherId> {
+        let hasher = supported_hash(code)?;
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_hasher_init())?;
+        t.record(self.call_manager.hashers().open(self.actor_id, hasher))
+    }
+
+    fn hash_update(&self, hasher: HasherId, data: &[u8]) -> Result<()> {
+        let hashers = self.call_manager.hashers();
+        let code = hashers.code(self.actor_id, hasher)?;
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_hashing(code, data.len()))?;
+        t.record(hashers.update(self.actor_id, hasher, data))
+    }
+
+    fn hash_finalize(&self, hasher: HasherId) -> Result<MultihashGeneric<64>> {
+        self.call_manager.hashers().finalize(self.actor_id, hasher)
     }
 
     fn compute_unsealed_sector_cid(
@@ -524,9 +901,15 @@ where
                 .on_compute_unsealed_sector_cid(proof_type, pieces),
         )?;
 
-        t.record(catch_and_log_panic("computing unsealed sector CID", || {
-            compute_unsealed_sector_cid(proof_type, pieces)
-        }))
+        t.record(
+            self.call_manager
+                .syscall_memo()
+                .unsealed_sector_cid(proof_type, pieces, || {
+                    catch_and_log_panic("computing unsealed sector CID", || {
+                        compute_unsealed_sector_cid(proof_type, pieces)
+                    })
+                }),
+        )
     }
 
     /// Verify seal proof for sectors. This proof verifies that a sector was sealed by the miner.
@@ -568,9 +951,8 @@ where
         // This syscall cannot be resolved inside the FVM, so we need to traverse
         // the node boundary through an extern.
         let (fault, _) = t.record(
-            self.call_manager
-                .externs()
-                .verify_consensus_fault(h1, h2, extra)
+            self.externs("verify_consensus_fault")
+                .and_then(|e| e.verify_consensus_fault(h1, h2, extra))
                 .or_illegal_argument(),
         )?;
 
@@ -649,6 +1031,53 @@ where
             verify_replica_update(replica)
         }))
     }
+
+    fn verify_merkle_proof(&self, proof: &MerkleProof) -> Result<bool> {
+        let cost = merkle::proof_cost(proof)?;
+        let t =
+            self.call_manager
+                .charge_gas(self.call_manager.price_list().on_verify_merkle_proof(
+                    cost.hasher,
+                    cost.nodes,
+                    cost.bytes,
+                ))?;
+        t.record(Ok(merkle::verify(proof)))
+    }
+
+    fn verify_groth16(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool> {
+        let vk = self.read_verifying_key(vk)?;
+        let charge = self
+            .call_manager
+            .price_list()
+            .on_verify_groth16(vk.curve, groth16::input_constraints(info))
+            .ok_or_else(|| {
+                syscall_error!(IllegalArgument; "Groth16 proofs over {:?} aren't supported", vk.curve)
+            })?;
+        groth16::check(&vk, info)?;
+
+        let t = self.call_manager.charge_gas(charge)?;
+        t.record(catch_and_log_panic("verifying groth16 proof", || {
+            groth16::verify(&vk, info)
+        }))
+    }
+
+    fn verify_plonk(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool> {
+        let vk = self.read_verifying_key(vk)?;
+        let size = plonk::check(&vk, info)?;
+        let charge = self
+            .call_manager
+            .price_list()
+            .on_verify_plonk(vk.curve, size, info.public_inputs.len())
+            .ok_or_else(|| {
+                syscall_error!(IllegalArgument; "PLONK proofs over {:?} aren't supported", vk.curve)
+            })?;
+
+        // Charge before verifying: the verifier allocates an evaluation per element of the domain.
+        let t = self.call_manager.charge_gas(charge)?;
+        t.record(catch_and_log_panic("verifying plonk proof", || {
+            plonk::verify(&vk, info)
+        }))
+    }
 }
 
 impl<C> GasOps for DefaultKernel<C>
@@ -670,6 +1099,14 @@ where
     fn price_list(&self) -> &PriceList {
         self.call_manager.price_list()
     }
+
+    fn record_syscall(&self, module: &'static str, name: &'static str) {
+        self.call_manager.gas_tracker().record_syscall(module, name)
+    }
+
+    fn instruction_clock(&self) -> Option<&InstructionClock> {
+        self.call_manager.gas_tracker().instruction_clock()
+    }
 }
 
 impl<C> NetworkOps for DefaultKernel<C>
@@ -717,11 +1154,44 @@ where
         let offset = self.call_manager.context().epoch - epoch;
         if offset < 0 {
             Err(syscall_error!(IllegalArgument; "epoch {} is in the future", epoch).into())
-        } else if offset >= FINALITY {
+        } else if offset >= self.call_manager.context().chain_policy.lookback_window {
             Err(syscall_error!(IllegalArgument; "epoch {} is too far in the past", epoch).into())
         } else {
-            self.call_manager.externs().get_tipset_cid(epoch).or_fatal()
+            self.externs("get_tipset_cid")
+                .and_then(|e| e.get_tipset_cid(epoch))
+                .or_fatal()
+        }
+    }
+
+    fn read_state(&self, query: &StateQuery) -> Result<Option<Vec<u8>>> {
+        if !self
+            .call_manager
+            .context()
+            .state_readers
+            .contains(&self.actor_id)
+        {
+            return Err(
+                syscall_error!(Forbidden; "actor {} may not read domain state", self.actor_id)
+                    .into(),
+            );
+        }
+
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_read_state())?;
+        let value = t.record(
+            self.externs("read_state")
+                .and_then(|e| e.read_state(query))
+                .or_fatal(),
+        )?;
+        if let Some(value) = &value {
+            let _ = self.call_manager.charge_gas(
+                self.call_manager
+                    .price_list()
+                    .on_read_state_value(value.len()),
+            )?;
         }
+        Ok(value)
     }
 }
 
@@ -743,12 +1213,17 @@ where
 
         // TODO(M2): Check error code
         // Specifically, lookback length?
-        t.record(
-            self.call_manager
-                .externs()
-                .get_chain_randomness(personalization, rand_epoch, entropy)
-                .or_illegal_argument(),
-        )
+        t.record(self.call_manager.randomness_cache().get_or_fetch(
+            RandomnessSource::Tickets,
+            personalization,
+            rand_epoch,
+            entropy,
+            || {
+                self.externs("get_chain_randomness")
+                    .and_then(|e| e.get_chain_randomness(personalization, rand_epoch, entropy))
+                    .or_illegal_argument()
+            },
+        ))
     }
 
     fn get_randomness_from_beacon(
@@ -765,12 +1240,37 @@ where
 
         // TODO(M2): Check error code
         // Specifically, lookback length?
-        t.record(
+        t.record(self.call_manager.randomness_cache().get_or_fetch(
+            RandomnessSource::Beacon,
+            personalization,
+            rand_epoch,
+            entropy,
+            || {
+                self.externs("get_beacon_randomness")
+                    .and_then(|e| e.get_beacon_randomness(personalization, rand_epoch, entropy))
+                    .or_illegal_argument()
+            },
+        ))
+    }
+
+    fn get_dev_randomness(
+        &self,
+        personalization: i64,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        let rand = self
+            .call_manager
+            .dev_randomness()
+            .ok_or_else(|| syscall_error!(Forbidden; "dev randomness is not enabled"))?;
+
+        let t = self.call_manager.charge_gas(
             self.call_manager
-                .externs()
-                .get_beacon_randomness(personalization, rand_epoch, entropy)
-                .or_illegal_argument(),
-        )
+                .price_list()
+                .on_get_randomness(entropy.len()),
+        )?;
+
+        let epoch = self.call_manager.context().epoch;
+        t.record(Ok(rand.draw(personalization, epoch, entropy)))
     }
 }
 
@@ -850,6 +1350,48 @@ where
         )
     }
 
+    fn get_code_info(&self, code_cid: &Cid) -> Result<CodeInfo> {
+        // Charge for the lookup before loading anything.
+        let _ = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_get_code_info_base())?;
+
+        // Only the builtin actors' code can be inspected, so actors can't use this to load
+        // arbitrary blocks.
+        if self
+            .call_manager
+            .machine()
+            .builtin_actors()
+            .id_by_code(code_cid)
+            == 0
+        {
+            return Err(
+                syscall_error!(NotFound; "{} isn't a builtin actor's code", code_cid).into(),
+            );
+        }
+
+        let start = GasTimer::start();
+        let wasm = self
+            .call_manager
+            .blockstore()
+            .get(code_cid)
+            .or_fatal()?
+            .context("missing builtin actor code")
+            .or_fatal()?;
+        if wasm.len() > MAX_CODE_SIZE {
+            return Err(syscall_error!(LimitExceeded; "actor code size {} exceeds the maximum {}", wasm.len(), MAX_CODE_SIZE).into());
+        }
+
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_get_code_info(wasm.len()))?;
+        t.stop_with(start);
+
+        CodeInfo::from_wasm(&wasm)
+            .with_context(|| format!("invalid actor code {}", code_cid))
+            .or_fatal()
+    }
+
     #[cfg(feature = "m2-native")]
     fn install_actor(&mut self, code_id: Cid) -> Result<()> {
         let start = GasTimer::start();
@@ -882,6 +1424,21 @@ where
         )
     }
 
+    fn nonce_of(&self, actor_id: ActorID) -> Result<u64> {
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_nonce_of())?;
+
+        t.record(
+            self.call_manager
+                .state_tree()
+                .get_actor(actor_id)?
+                .context("actor not found")
+                .or_error(ErrorNumber::NotFound)
+                .map(|a| a.sequence),
+        )
+    }
+
     fn lookup_delegated_address(&self, actor_id: ActorID) -> Result<Option<Address>> {
         let t = self
             .call_manager
@@ -975,6 +1532,12 @@ where
     C: CallManager,
 {
     fn emit_event(&mut self, evt: ActorEvent) -> Result<()> {
+        let limit = self.call_manager.context().network.max_message_event_bytes;
+        let total = self.call_manager.event_bytes() as u64 + evt.payload_size() as u64;
+        if total > limit {
+            return Err(syscall_error!(LimitExceeded; "message events total {} bytes, exceeding the limit of {} bytes", total, limit).into());
+        }
+
         let t = self
             .call_manager
             .charge_gas(self.call_manager.price_list().on_actor_event(&evt))?;
@@ -989,6 +1552,218 @@ where
     }
 }
 
+impl<C> ReturnOps for DefaultKernel<C>
+where
+    C: CallManager,
+{
+    fn return_push(&mut self, data: Vec<u8>) -> Result<()> {
+        if self.returns.len() >= MAX_RETURN_QUEUE_LEN {
+            return Err(syscall_error!(LimitExceeded; "return queue is full ({} payloads)", MAX_RETURN_QUEUE_LEN).into());
+        }
+        self.returns.push_back(data);
+        Ok(())
+    }
+
+    fn return_pop(&mut self) -> Result<Vec<u8>> {
+        self.returns
+            .pop_front()
+            .ok_or_else(|| syscall_error!(NotFound; "return queue is empty").into())
+    }
+
+    fn return_len(&self) -> Option<u32> {
+        self.returns.front().map(|data| data.len() as u32)
+    }
+}
+
+impl<C> SyncOps for DefaultKernel<C>
+where
+    C: CallManager,
+{
+    fn set_flag(&mut self, key: u64, options: FlagOptions) -> Result<bool> {
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_sync_flag())?;
+        t.record(Ok(self.call_manager.flags().set(
+            self.actor_id,
+            key,
+            options.message_scope(),
+        )))
+    }
+
+    fn clear_flag(&mut self, key: u64) -> Result<bool> {
+        let t = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_sync_flag())?;
+        t.record(Ok(self.call_manager.flags().clear(self.actor_id, key)))
+    }
+}
+
+impl<C> CronOps for DefaultKernel<C>
+where
+    C: CallManager,
+{
+    fn cron_register(&mut self, method: MethodNum) -> Result<()> {
+        self.update_cron_entries(|state, actor| state.register(actor, method))
+    }
+
+    fn cron_unregister(&mut self, method: MethodNum) -> Result<()> {
+        self.update_cron_entries(|state, actor| state.unregister(actor, method))
+    }
+}
+
+impl<C> DefaultKernel<C>
+where
+    C: CallManager,
+{
+    /// Updates the cron actor's entries on behalf of this (builtin) actor, charging for reading and
+    /// writing the cron actor's state.
+    fn update_cron_entries<F>(&mut self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut cron_actor::State, ActorID) -> Result<()>,
+    {
+        if self.call_manager.state_tree().is_read_only() {
+            return Err(
+                syscall_error!(ReadOnly; "cannot update cron entries while in read-only mode")
+                    .into(),
+            );
+        }
+        let builtin = match self.get_self()? {
+            Some(state) => {
+                self.call_manager
+                    .machine()
+                    .builtin_actors()
+                    .id_by_code(&state.code)
+                    != 0
+            }
+            None => false,
+        };
+        if !builtin {
+            return Err(
+                syscall_error!(Forbidden; "only builtin actors can register cron entries").into(),
+            );
+        }
+
+        let _ = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_block_open_base())?;
+        let cron = self
+            .call_manager
+            .state_tree()
+            .get_actor(CRON_ACTOR_ID)?
+            .context("cron actor not found")
+            .or_fatal()?;
+        let data = self
+            .call_manager
+            .blockstore()
+            .get(&cron.state)
+            .or_fatal()?
+            .context("cron actor state not found")
+            .or_fatal()?;
+        let _ = self.call_manager.charge_gas(
+            self.call_manager
+                .price_list()
+                .on_block_open_per_byte(data.len()),
+        )?;
+
+        let mut state: cron_actor::State = from_slice(&data).or_fatal()?;
+        update(&mut state, self.actor_id)?;
+
+        let data = to_vec(&state).or_fatal()?;
+        let t = self.call_manager.charge_gas(
+            self.call_manager
+                .price_list()
+                .on_block_link(SupportedHashes::Blake2b256, data.len()),
+        )?;
+        let cid = Cid::new_v1(DAG_CBOR, multihash::Code::Blake2b256.digest(&data));
+        self.call_manager
+            .blockstore()
+            .put_keyed(&cid, &data)
+            .or_fatal()?;
+        t.stop();
+
+        self.call_manager
+            .state_tree_mut()
+            .mutate_actor(CRON_ACTOR_ID, |actor| {
+                actor.state = cid;
+                Ok(())
+            })
+    }
+}
+
+impl<C> TokenOps for DefaultKernel<C>
+where
+    C: CallManager,
+{
+    fn token_transfer_hook(
+        &mut self,
+        transfer: &TokenTransfer,
+        gas_limit: Option<Gas>,
+    ) -> Result<TokenTransferResult> {
+        let _ = self
+            .call_manager
+            .charge_gas(self.call_manager.price_list().on_token_transfer_hook())?;
+
+        if transfer.amount.is_negative() {
+            return Err(syscall_error!(IllegalArgument; "negative transfer amount").into());
+        }
+
+        // The operator is authenticated by the kernel: it's whoever called the token actor.
+        let operator = self.caller;
+        let from = self.resolve_address(&transfer.from)?;
+        let to = match self.resolve_address(&transfer.to) {
+            Ok(id) => id,
+            Err(ExecutionError::Syscall(SyscallError(_, ErrorNumber::NotFound))) => {
+                // Create the recipient, like a plain value transfer would.
+                let created = self.send(
+                    &transfer.to,
+                    METHOD_SEND,
+                    NO_DATA_BLOCK_ID,
+                    &TokenAmount::zero(),
+                    None,
+                    SendFlags::empty(),
+                )?;
+                if !created.exit_code.is_success() {
+                    return Err(syscall_error!(NotFound;
+                        "failed to create token recipient {}: {}",
+                        transfer.to, created.exit_code)
+                    .into());
+                }
+                self.resolve_address(&transfer.to)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        let payload = to_vec(&FRC46TokenReceived {
+            from,
+            to,
+            operator,
+            amount: transfer.amount.clone(),
+            operator_data: transfer.operator_data.clone(),
+            token_data: transfer.token_data.clone(),
+        })
+        .or_fatal()?;
+        let params = to_vec(&UniversalReceiverParams {
+            type_: FRC46_TOKEN_TYPE,
+            payload: RawBytes::new(payload),
+        })
+        .or_fatal()?;
+        let params_id = self.block_create(DAG_CBOR, &params)?;
+
+        let max_gas = Gas::new(MAX_RECEIVER_HOOK_GAS as i64);
+        let gas_limit = Some(gas_limit.map_or(max_gas, |limit| limit.min(max_gas)));
+        let hook = self.send(
+            &Address::new_id(to),
+            RECEIVER_HOOK_METHOD_NUM,
+            params_id,
+            &TokenAmount::zero(),
+            gas_limit,
+            SendFlags::empty(),
+        )?;
+
+        Ok(TokenTransferResult { from, to, hook })
+    }
+}
+
 fn catch_and_log_panic<F: FnOnce() -> Result<R> + UnwindSafe, R>(context: &str, f: F) -> Result<R> {
     match panic::catch_unwind(f) {
         Ok(v) => v,
@@ -1247,3 +2022,14 @@ fn compute_unsealed_sector_cid(
 
     commcid::data_commitment_v1_to_cid(&comm_d).or_illegal_argument()
 }
+
+/// Returns the hash function with the given multicodec code, if supported.
+fn supported_hash(code: u64) -> Result<SupportedHashes> {
+    SupportedHashes::try_from(code).map_err(|e| {
+        if let multihash::Error::UnsupportedCode(code) = e {
+            syscall_error!(IllegalArgument; "unsupported hash code {}", code).into()
+        } else {
+            syscall_error!(AssertionFailed; "hash expected unsupported code, got {}", e).into()
+        }
+    })
+}
@@ -3,7 +3,7 @@
 use std::fmt::Display;
 
 use derive_more::Display;
-use fvm_shared::error::ErrorNumber;
+use fvm_shared::error::{ErrorNumber, ExitCode};
 
 /// Execution result.
 pub type Result<T> = std::result::Result<T, ExecutionError>;
@@ -64,6 +64,18 @@ impl ExecutionError {
             Syscall(_) => true,
         }
     }
+
+    /// Returns the exit code of a message that fails with this error outside of any actor code
+    /// (e.g., when the message can't be dispatched to the receiving actor). This mapping is
+    /// consensus-critical: any change must be gated by network version.
+    pub fn exit_code(&self) -> ExitCode {
+        use ExecutionError::*;
+        match self {
+            OutOfGas => ExitCode::SYS_OUT_OF_GAS,
+            Syscall(e) => e.exit_code(),
+            Fatal(_) => ExitCode::SYS_ASSERTION_FAILED,
+        }
+    }
 }
 
 // NOTE: this is the _only_ from impl we provide. Otherwise, we expect the user to explicitly
@@ -185,4 +197,14 @@ impl SyscallError {
     pub fn new<D: Display>(c: ErrorNumber, d: D) -> Self {
         SyscallError(d.to_string(), c)
     }
+
+    /// Returns the exit code of a message that can't be dispatched to the receiving actor because
+    /// of this error. See [`ExecutionError::exit_code`].
+    pub fn exit_code(&self) -> ExitCode {
+        match self.1 {
+            ErrorNumber::InsufficientFunds => ExitCode::SYS_INSUFFICIENT_FUNDS,
+            ErrorNumber::NotFound => ExitCode::SYS_INVALID_RECEIVER,
+            _ => ExitCode::SYS_ASSERTION_FAILED,
+        }
+    }
 }
@@ -0,0 +1,185 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+
+use fvm_shared::ActorID;
+
+use crate::determinism::Map;
+
+type FlagKey = (ActorID, u64);
+
+/// The flags set by actors during a message execution (see [`SyncOps`](super::SyncOps)), e.g., to
+/// implement reentrancy locks.
+///
+/// Each actor has its own namespace of flags, keyed by `u64`. A flag is cleared when the invocation
+/// that set it returns (or aborts), unless it was set with message scope, in which case it's kept
+/// until the end of the message. Like state changes, the flags set or cleared by an invocation
+/// (and the invocations it made) are rolled back if it aborts or exits with an error. The call
+/// manager reports invocations to the registry with [`FlagRegistry::enter_call`] and
+/// [`FlagRegistry::exit_call`].
+#[derive(Default)]
+pub struct FlagRegistry {
+    /// The flags currently set, with the depth of the invocation they're scoped to, if any.
+    flags: RefCell<Map<FlagKey, Option<usize>>>,
+    /// The open invocations, innermost last.
+    calls: RefCell<Vec<CallFlags>>,
+}
+
+#[derive(Default)]
+struct CallFlags {
+    /// The flags set by the invocation, to be cleared when it returns.
+    scoped: Vec<FlagKey>,
+    /// The previous value of each flag changed by the invocation, to roll it back.
+    journal: Vec<(FlagKey, Option<Option<usize>>)>,
+}
+
+impl FlagRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets one of `owner`'s flags, scoped to the current invocation unless `message_scope` is
+    /// set. Returns false (and leaves the flag untouched) if it's already set.
+    pub fn set(&self, owner: ActorID, key: u64, message_scope: bool) -> bool {
+        let mut calls = self.calls.borrow_mut();
+        let depth = calls.len().checked_sub(1).filter(|_| !message_scope);
+        match self.flags.borrow_mut().entry((owner, key)) {
+            Entry::Occupied(_) => return false,
+            Entry::Vacant(e) => e.insert(depth),
+        };
+        if let Some(call) = calls.last_mut() {
+            call.journal.push(((owner, key), None));
+            if depth.is_some() {
+                call.scoped.push((owner, key));
+            }
+        }
+        true
+    }
+
+    /// Clears one of `owner`'s flags. Returns false if it wasn't set.
+    pub fn clear(&self, owner: ActorID, key: u64) -> bool {
+        let prev = match self.flags.borrow_mut().remove(&(owner, key)) {
+            Some(prev) => prev,
+            None => return false,
+        };
+        if let Some(call) = self.calls.borrow_mut().last_mut() {
+            call.journal.push(((owner, key), Some(prev)));
+        }
+        true
+    }
+
+    /// Returns true if one of `owner`'s flags is set.
+    pub fn is_set(&self, owner: ActorID, key: u64) -> bool {
+        self.flags.borrow().contains_key(&(owner, key))
+    }
+
+    /// Records the start of an invocation.
+    pub fn enter_call(&self) {
+        self.calls.borrow_mut().push(CallFlags::default());
+    }
+
+    /// Records the end of the current invocation. If `revert`, the flags are restored to their
+    /// state at the start of the invocation. Otherwise, the flags scoped to it are cleared.
+    pub fn exit_call(&self, revert: bool) {
+        let mut calls = self.calls.borrow_mut();
+        let call = match calls.pop() {
+            Some(call) => call,
+            None => return,
+        };
+        let depth = calls.len();
+        let mut flags = self.flags.borrow_mut();
+        if revert {
+            for (key, prev) in call.journal.into_iter().rev() {
+                match prev {
+                    Some(prev) => flags.insert(key, prev),
+                    None => flags.remove(&key),
+                };
+            }
+            return;
+        }
+
+        let mut journal = call.journal;
+        for key in call.scoped {
+            // The flag may have been cleared and set again by another invocation since.
+            if flags.get(&key) == Some(&Some(depth)) {
+                flags.remove(&key);
+                journal.push((key, Some(Some(depth))));
+            }
+        }
+        // The caller's rollback covers the changes made by this invocation.
+        if let Some(caller) = calls.last_mut() {
+            caller.journal.extend(journal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        let flags = FlagRegistry::new();
+        flags.enter_call();
+        assert!(flags.set(100, 1, false));
+        assert!(!flags.set(100, 1, true));
+        // Namespaced by actor.
+        assert!(flags.set(101, 1, false));
+
+        flags.enter_call();
+        assert!(flags.set(100, 2, false));
+        assert!(flags.set(100, 3, true));
+        assert!(flags.clear(101, 1));
+        assert!(flags.set(101, 1, false));
+        flags.exit_call(false);
+
+        // Only the flags scoped to the inner call are cleared.
+        assert!(flags.is_set(100, 1));
+        assert!(!flags.is_set(100, 2));
+        assert!(flags.is_set(100, 3));
+        assert!(!flags.is_set(101, 1));
+
+        assert!(flags.clear(100, 1));
+        assert!(!flags.clear(100, 1));
+        flags.exit_call(false);
+        assert!(flags.is_set(100, 3));
+    }
+
+    #[test]
+    fn reverts() {
+        let flags = FlagRegistry::new();
+        flags.enter_call();
+        assert!(flags.set(100, 1, false));
+        assert!(flags.set(100, 2, true));
+
+        // A reverted invocation's changes are rolled back.
+        flags.enter_call();
+        assert!(flags.clear(100, 1));
+        assert!(flags.set(100, 3, true));
+        assert!(flags.set(100, 4, false));
+        flags.exit_call(true);
+        assert!(flags.is_set(100, 1));
+        assert!(!flags.is_set(100, 3));
+        assert!(!flags.is_set(100, 4));
+
+        // A successful invocation's changes are rolled back with its caller's.
+        flags.enter_call();
+        flags.enter_call();
+        assert!(flags.clear(100, 2));
+        assert!(flags.set(100, 3, true));
+        assert!(flags.set(100, 4, false));
+        flags.exit_call(false);
+        assert!(!flags.is_set(100, 4));
+        flags.exit_call(true);
+        assert!(flags.is_set(100, 1));
+        assert!(flags.is_set(100, 2));
+        assert!(!flags.is_set(100, 3));
+        assert!(!flags.is_set(100, 4));
+
+        // The outermost flags are scoped to the first invocation.
+        flags.exit_call(false);
+        assert!(!flags.is_set(100, 1));
+        assert!(flags.is_set(100, 2));
+    }
+}
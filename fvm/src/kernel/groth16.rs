@@ -0,0 +1,196 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Verification of Groth16 proofs. See
+//! [`CryptoOps::verify_groth16`](super::CryptoOps::verify_groth16).
+//!
+//! Only BLS12-381 (the curve of Filecoin's own proofs) is supported for now: other curves are
+//! rejected until they're implemented and priced.
+
+use bellperson::groth16::{self, Proof, VerifyingKey};
+use blstrs::{Bls12, Scalar};
+use fvm_shared::crypto::snark::{
+    SnarkCurve, SnarkVerifyInfo, SnarkVerifyingKey, SNARK_PUBLIC_INPUT_LEN,
+};
+
+use super::Result;
+use crate::syscall_error;
+
+/// The length of a BLS12-381 verifying key without its input constraint points: three G1 and three
+/// G2 points (uncompressed), and the number of input constraint points.
+const BLS12_381_KEY_BASE_LEN: usize = 3 * 96 + 3 * 192 + 4;
+
+/// The length of an uncompressed G1 point of a BLS12-381 verifying key, one per input constraint.
+const BLS12_381_KEY_POINT_LEN: usize = 96;
+
+/// Returns the number of input constraints of a circuit with the given number of public inputs:
+/// one per public input, plus one for the constant "one" input.
+pub(crate) fn input_constraints(info: &SnarkVerifyInfo) -> usize {
+    info.public_inputs.len() + 1
+}
+
+/// Checks that a proof is well-formed, without decoding it: the key must have been generated for
+/// the supplied number of public inputs, so that it's priced correctly.
+pub(crate) fn check(vk: &SnarkVerifyingKey, info: &SnarkVerifyInfo) -> Result<()> {
+    if let Some(input) = info
+        .public_inputs
+        .iter()
+        .find(|input| input.0.len() != SNARK_PUBLIC_INPUT_LEN)
+    {
+        return Err(syscall_error!(IllegalArgument;
+            "public inputs must be {} bytes long, got {}", SNARK_PUBLIC_INPUT_LEN, input.0.len())
+        .into());
+    }
+    match vk.curve {
+        SnarkCurve::Bls12_381 => {
+            let expected =
+                BLS12_381_KEY_BASE_LEN + BLS12_381_KEY_POINT_LEN * input_constraints(info);
+            if vk.key.len() != expected {
+                return Err(syscall_error!(IllegalArgument;
+                    "verifying key must be {} bytes long for {} public inputs, got {}",
+                    expected, info.public_inputs.len(), vk.key.len())
+                .into());
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Verifies a well-formed proof (see [`check`]). Returns an `IllegalArgument` error if the key,
+/// proof, or public inputs can't be decoded (e.g., points aren't on the curve).
+pub(crate) fn verify(vk: &SnarkVerifyingKey, info: &SnarkVerifyInfo) -> Result<bool> {
+    match vk.curve {
+        SnarkCurve::Bls12_381 => verify_bls12_381(vk, info),
+    }
+}
+
+fn verify_bls12_381(vk: &SnarkVerifyingKey, info: &SnarkVerifyInfo) -> Result<bool> {
+    let mut reader = &vk.key[..];
+    let key = match VerifyingKey::<Bls12>::read(&mut reader) {
+        Ok(parsed) if reader.is_empty() && parsed.ic.len() == input_constraints(info) => parsed,
+        Ok(_) => {
+            return Err(syscall_error!(IllegalArgument;
+                "verifying key doesn't match the public inputs")
+            .into())
+        }
+        Err(e) => {
+            return Err(syscall_error!(IllegalArgument; "malformed verifying key: {}", e).into())
+        }
+    };
+
+    let mut reader = &info.proof[..];
+    let proof = match Proof::<Bls12>::read(&mut reader) {
+        Ok(parsed) if reader.is_empty() => parsed,
+        Ok(_) => return Err(syscall_error!(IllegalArgument; "trailing bytes after proof").into()),
+        Err(e) => return Err(syscall_error!(IllegalArgument; "malformed proof: {}", e).into()),
+    };
+
+    let inputs = info
+        .public_inputs
+        .iter()
+        .map(|input| {
+            let bytes: &[u8; SNARK_PUBLIC_INPUT_LEN] = input.0[..].try_into().map_err(|_| {
+                syscall_error!(IllegalArgument; "public inputs must be {} bytes long",
+                    SNARK_PUBLIC_INPUT_LEN)
+            })?;
+            Option::from(Scalar::from_bytes_le(bytes)).ok_or_else(|| {
+                syscall_error!(IllegalArgument; "public input isn't a canonical field element")
+                    .into()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The only error is a mismatch between the key and the number of inputs, checked above.
+    let pvk = groth16::prepare_verifying_key(&key);
+    Ok(groth16::verify_proof(&pvk, &proof, &inputs).unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use bellperson::{Circuit, ConstraintSystem, SynthesisError};
+    use fvm_ipld_encoding::BytesDe;
+    use fvm_shared::error::ErrorNumber;
+
+    use super::*;
+    use crate::kernel::{ExecutionError, SyscallError};
+
+    /// Proves knowledge of a square root of the public input.
+    struct SquareRoot(Option<Scalar>);
+
+    impl Circuit<Scalar> for SquareRoot {
+        fn synthesize<CS: ConstraintSystem<Scalar>>(
+            self,
+            cs: &mut CS,
+        ) -> std::result::Result<(), SynthesisError> {
+            let root = cs.alloc(
+                || "root",
+                || self.0.ok_or(SynthesisError::AssignmentMissing),
+            )?;
+            let square = cs.alloc_input(
+                || "square",
+                || {
+                    self.0
+                        .map(|r| r * r)
+                        .ok_or(SynthesisError::AssignmentMissing)
+                },
+            )?;
+            cs.enforce(
+                || "root * root = square",
+                |lc| lc + root,
+                |lc| lc + root,
+                |lc| lc + square,
+            );
+            Ok(())
+        }
+    }
+
+    fn error_number(result: Result<impl std::fmt::Debug>) -> ErrorNumber {
+        match result {
+            Err(ExecutionError::Syscall(SyscallError(_, e))) => e,
+            other => panic!("expected a syscall error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bls12_381() {
+        let mut rng = rand::thread_rng();
+        let params =
+            groth16::generate_random_parameters::<Bls12, _, _>(SquareRoot(None), &mut rng).unwrap();
+        let root = Scalar::from(7u64);
+        let proof =
+            groth16::create_random_proof(SquareRoot(Some(root)), &params, &mut rng).unwrap();
+
+        let mut key = Vec::new();
+        params.vk.write(&mut key).unwrap();
+        let vk = SnarkVerifyingKey {
+            curve: SnarkCurve::Bls12_381,
+            key,
+        };
+        let mut encoded = Vec::new();
+        proof.write(&mut encoded).unwrap();
+        let mut info = SnarkVerifyInfo {
+            proof: encoded,
+            public_inputs: vec![BytesDe((root * root).to_bytes_le().to_vec())],
+        };
+        check(&vk, &info).unwrap();
+        assert!(verify(&vk, &info).unwrap());
+
+        // 7 isn't the square of 7.
+        info.public_inputs = vec![BytesDe(root.to_bytes_le().to_vec())];
+        assert!(!verify(&vk, &info).unwrap());
+
+        // The key is for a single public input.
+        info.public_inputs.push(info.public_inputs[0].clone());
+        assert_eq!(
+            error_number(check(&vk, &info)),
+            ErrorNumber::IllegalArgument
+        );
+        info.public_inputs.truncate(1);
+
+        // Public inputs must be canonical field elements.
+        info.public_inputs = vec![BytesDe(vec![0xff; SNARK_PUBLIC_INPUT_LEN])];
+        assert_eq!(
+            error_number(verify(&vk, &info)),
+            ErrorNumber::IllegalArgument
+        );
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::cell::{Cell, RefCell};
+
+use fvm_shared::ActorID;
+use multihash::{
+    Blake2b256, Blake2b512, Hasher, Keccak256, MultihashDigest, MultihashGeneric, Ripemd160,
+    Sha2_256,
+};
+
+use super::{Result, SupportedHashes};
+use crate::syscall_error;
+
+/// Hashers are addressed by an ordinal, starting from 1. Like block IDs, 0 is never a valid handle.
+pub type HasherId = u32;
+
+struct OpenHasher {
+    owner: ActorID,
+    code: SupportedHashes,
+    state: Box<dyn Hasher>,
+}
+
+/// The incremental hashers opened during a message execution (see
+/// [`CryptoOps::hash_init`](super::CryptoOps::hash_init)).
+///
+/// Each hasher may only be used by the actor that opened it, and is released when finalized. At
+/// most a fixed number of hashers may be opened per message, whether or not they're finalized, and
+/// handles are never reused.
+pub struct HasherRegistry {
+    hashers: RefCell<Vec<Option<OpenHasher>>>,
+    remaining: Cell<u32>,
+}
+
+impl Default for HasherRegistry {
+    fn default() -> Self {
+        Self::new(u32::MAX)
+    }
+}
+
+impl HasherRegistry {
+    /// Creates a registry allowing up to `max_hashers` hashers to be opened.
+    pub fn new(max_hashers: u32) -> Self {
+        Self {
+            hashers: Default::default(),
+            remaining: Cell::new(max_hashers),
+        }
+    }
+
+    /// Opens a hasher on behalf of `owner`.
+    pub fn open(&self, owner: ActorID, code: SupportedHashes) -> Result<HasherId> {
+        let remaining = self.remaining.get();
+        if remaining == 0 {
+            return Err(
+                syscall_error!(LimitExceeded; "too many hashers opened by this message").into(),
+            );
+        }
+        self.remaining.set(remaining - 1);
+
+        let state: Box<dyn Hasher> = match code {
+            SupportedHashes::Sha2_256 => Box::<Sha2_256>::default(),
+            SupportedHashes::Blake2b256 => Box::<Blake2b256>::default(),
+            SupportedHashes::Blake2b512 => Box::<Blake2b512>::default(),
+            SupportedHashes::Keccak256 => Box::<Keccak256>::default(),
+            SupportedHashes::Ripemd160 => Box::<Ripemd160>::default(),
+        };
+        let mut hashers = self.hashers.borrow_mut();
+        hashers.push(Some(OpenHasher { owner, code, state }));
+        Ok(hashers.len() as HasherId)
+    }
+
+    /// Returns the hash function of one of `owner`'s hashers.
+    pub fn code(&self, owner: ActorID, id: HasherId) -> Result<SupportedHashes> {
+        self.with_hasher(owner, id, |h| h.code)
+    }
+
+    /// Feeds data to one of `owner`'s hashers.
+    pub fn update(&self, owner: ActorID, id: HasherId, data: &[u8]) -> Result<()> {
+        self.with_hasher(owner, id, |h| h.state.update(data))
+    }
+
+    /// Finalizes and releases one of `owner`'s hashers, returning the digest.
+    pub fn finalize(&self, owner: ActorID, id: HasherId) -> Result<MultihashGeneric<64>> {
+        self.with_hasher(owner, id, |_| ())?;
+        let mut hasher = self.hashers.borrow_mut()[id as usize - 1]
+            .take()
+            .expect("hasher checked above");
+        hasher
+            .code
+            .wrap(hasher.state.finalize())
+            .map_err(|e| syscall_error!(AssertionFailed; "failed to wrap digest: {}", e).into())
+    }
+
+    fn with_hasher<T>(
+        &self,
+        owner: ActorID,
+        id: HasherId,
+        f: impl FnOnce(&mut OpenHasher) -> T,
+    ) -> Result<T> {
+        let mut hashers = self.hashers.borrow_mut();
+        match (id as usize)
+            .checked_sub(1)
+            .and_then(|idx| hashers.get_mut(idx))
+        {
+            Some(Some(hasher)) if hasher.owner == owner => Ok(f(hasher)),
+            _ => Err(invalid_handle(id)),
+        }
+    }
+}
+
+fn invalid_handle(id: HasherId) -> super::ExecutionError {
+    syscall_error!(InvalidHandle; "hasher {} does not exist, or is illegal", id).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::error::ErrorNumber;
+
+    use super::*;
+    use crate::kernel::ExecutionError;
+
+    fn errno<T>(r: Result<T>) -> ErrorNumber {
+        match r {
+            Err(ExecutionError::Syscall(e)) => e.1,
+            _ => panic!("expected a syscall error"),
+        }
+    }
+
+    #[test]
+    fn hashes_incrementally() {
+        let registry = HasherRegistry::new(2);
+        let data = b"the quick brown fox jumps over the lazy dog";
+        for code in [SupportedHashes::Sha2_256, SupportedHashes::Keccak256] {
+            let id = registry.open(100, code).unwrap();
+            assert_eq!(registry.code(100, id).unwrap(), code);
+            for chunk in data.chunks(7) {
+                registry.update(100, id, chunk).unwrap();
+            }
+            assert_eq!(registry.finalize(100, id).unwrap(), code.digest(data));
+            // Finalized hashers are released.
+            assert_eq!(
+                errno(registry.update(100, id, data)),
+                ErrorNumber::InvalidHandle
+            );
+        }
+
+        // The budget is spent, even though no hashers are open.
+        assert_eq!(
+            errno(registry.open(100, SupportedHashes::Blake2b256)),
+            ErrorNumber::LimitExceeded
+        );
+    }
+
+    #[test]
+    fn checks_handles() {
+        let registry = HasherRegistry::default();
+        let id = registry.open(100, SupportedHashes::Blake2b256).unwrap();
+        for (owner, id) in [(101, id), (100, 0), (100, id + 1)] {
+            assert_eq!(
+                errno(registry.update(owner, id, b"data")),
+                ErrorNumber::InvalidHandle
+            );
+            assert_eq!(
+                errno(registry.finalize(owner, id)),
+                ErrorNumber::InvalidHandle
+            );
+        }
+        registry.finalize(100, id).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::io::Cursor;
+
+use cid::Cid;
+use fvm_ipld_encoding::DAG_CBOR;
+use fvm_shared::{IDENTITY_HASH, IPLD_RAW};
+
+use super::blocks::GraphStat;
+use crate::blockstore::scan_for_links;
+use crate::determinism::Map;
+
+/// Tracks the statistics of the state graphs linked during a message, so an actor can query the
+/// size of its own state (see [`SelfOps::self_state_stat`]) without walking the parts it has just
+/// written.
+///
+/// Statistics are recorded per CID, for the graph of DAG-CBOR and raw blocks rooted at that CID,
+/// counted as a tree: a block reachable through several links is counted once per link. A block
+/// is recorded when it's linked, if the statistics of all of the blocks it links to are already
+/// known (as is the case when a state tree is written bottom-up), and when the kernel computes its
+/// statistics by walking the graph. Blocks never change once linked, so records are never
+/// invalidated. Incomplete statistics (of graphs with missing blocks) aren't recorded, as the
+/// missing blocks may be written later.
+///
+/// [`SelfOps::self_state_stat`]: super::SelfOps::self_state_stat
+#[derive(Debug, Default)]
+pub struct LinkTracker {
+    stats: Map<Cid, GraphStat>,
+}
+
+impl LinkTracker {
+    /// Returns the recorded statistics of the graph rooted at `cid`, if any.
+    pub fn get(&self, cid: &Cid) -> Option<GraphStat> {
+        self.stats.get(cid).copied()
+    }
+
+    /// Records the statistics of the graph rooted at `cid`.
+    pub fn insert(&mut self, cid: Cid, stat: GraphStat) {
+        self.stats.insert(cid, stat);
+    }
+
+    /// Returns the number of CIDs with recorded statistics.
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    /// Returns true if no statistics have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records the statistics of a newly linked block, if the statistics of its children are
+    /// known.
+    pub fn on_link(&mut self, cid: Cid, data: &[u8]) {
+        let mut children = Vec::new();
+        for_each_link(cid.codec(), data, |link| children.push(link));
+        let stat = children
+            .iter()
+            .map(|link| self.get(link))
+            .try_fold(GraphStat::leaf(data.len()), |stat, child| {
+                Some(stat.with_child(&child?))
+            });
+        if let Some(stat) = stat {
+            self.insert(cid, stat);
+        }
+    }
+}
+
+impl GraphStat {
+    /// The statistics of a block with no children.
+    pub(crate) fn leaf(size: usize) -> Self {
+        GraphStat {
+            size: size as u64,
+            blocks: 1,
+            depth: 0,
+            complete: true,
+        }
+    }
+
+    /// Adds the statistics of a child's graph to those of its parent.
+    pub(crate) fn with_child(self, child: &GraphStat) -> Self {
+        GraphStat {
+            size: self.size.saturating_add(child.size),
+            blocks: self.blocks.saturating_add(child.blocks),
+            depth: self.depth.max(child.depth.saturating_add(1)),
+            complete: self.complete && child.complete,
+        }
+    }
+}
+
+/// Calls `f` on each link of a block that's followed when computing graph statistics: links to
+/// non-inlined DAG-CBOR and raw blocks. Links of malformed blocks aren't followed.
+pub(crate) fn for_each_link(codec: u64, data: &[u8], mut f: impl FnMut(Cid)) {
+    if codec != DAG_CBOR {
+        return;
+    }
+    let mut links = Vec::new();
+    let ok = scan_for_links(&mut Cursor::new(data), |link| {
+        if link.hash().code() != IDENTITY_HASH && matches!(link.codec(), DAG_CBOR | IPLD_RAW) {
+            links.push(link);
+        }
+        Ok(())
+    })
+    .is_ok();
+    if ok {
+        links.into_iter().for_each(&mut f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::Cid;
+    use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+    use fvm_shared::IPLD_RAW;
+    use multihash::{Code, MultihashDigest};
+
+    use super::LinkTracker;
+    use crate::kernel::GraphStat;
+
+    fn cid(codec: u64, data: &[u8]) -> Cid {
+        Cid::new_v1(codec, Code::Blake2b256.digest(data))
+    }
+
+    #[test]
+    fn tracks_linked_graphs() {
+        let mut tracker = LinkTracker::default();
+        let leaf = b"leaf".to_vec();
+        let leaf_cid = cid(IPLD_RAW, &leaf);
+        let parent = to_vec(&vec![leaf_cid, leaf_cid]).unwrap();
+        let parent_cid = cid(DAG_CBOR, &parent);
+
+        // The parent's child isn't known yet.
+        tracker.on_link(parent_cid, &parent);
+        assert!(tracker.is_empty());
+
+        tracker.on_link(leaf_cid, &leaf);
+        tracker.on_link(parent_cid, &parent);
+        assert_eq!(tracker.get(&leaf_cid), Some(GraphStat::leaf(4)));
+        assert_eq!(
+            tracker.get(&parent_cid),
+            Some(GraphStat {
+                size: parent.len() as u64 + 8,
+                blocks: 3,
+                depth: 1,
+                complete: true,
+            })
+        );
+
+        // Malformed blocks are leaves.
+        let bad = vec![0x82, 0xd8];
+        let bad_cid = cid(DAG_CBOR, &bad);
+        tracker.on_link(bad_cid, &bad);
+        assert_eq!(tracker.get(&bad_cid), Some(GraphStat::leaf(2)));
+    }
+}
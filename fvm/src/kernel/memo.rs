@@ -0,0 +1,189 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::cell::{Cell, RefCell};
+use std::hash::Hash;
+
+use cid::Cid;
+use fvm_shared::piece::PieceInfo;
+use fvm_shared::sector::RegisteredSealProof;
+use multihash::MultihashGeneric;
+
+use super::Result;
+use crate::determinism::Map;
+
+type UnsealedCidKey = (RegisteredSealProof, Vec<(u64, Cid)>);
+
+/// Memoizes the results of pure syscalls (hashing and unsealed sector CID computation) for the
+/// duration of a message, so actors that repeat the same computation don't repeat the host work.
+///
+/// Only host CPU is saved: the kernel charges gas before consulting the memo, so gas usage is the
+/// same whether or not a result is memoized. Memoization is bounded by the total size of the
+/// memoized inputs (see [`NetworkConfig::syscall_memo_bytes`]), and disabled if the bound is zero.
+/// Failed computations are not memoized.
+///
+/// [`NetworkConfig::syscall_memo_bytes`]: crate::machine::NetworkConfig::syscall_memo_bytes
+#[derive(Debug, Default)]
+pub struct SyscallMemo {
+    max_bytes: usize,
+    used_bytes: Cell<usize>,
+    hits: Cell<u64>,
+    hashes: RefCell<Map<(u64, Vec<u8>), MultihashGeneric<64>>>,
+    unsealed_cids: RefCell<Map<UnsealedCidKey, Cid>>,
+}
+
+impl SyscallMemo {
+    /// Creates a memo holding inputs of up to `max_bytes` bytes in total.
+    pub fn new(max_bytes: usize) -> Self {
+        SyscallMemo {
+            max_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if results are memoized at all.
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    /// Returns the number of syscalls served from the memo so far.
+    pub fn hits(&self) -> u64 {
+        self.hits.get()
+    }
+
+    /// Returns the memoized digest of `data` with the hash function `code`, or calls `compute` and
+    /// memoizes its result.
+    pub fn hash<F>(&self, code: u64, data: &[u8], compute: F) -> Result<MultihashGeneric<64>>
+    where
+        F: FnOnce() -> Result<MultihashGeneric<64>>,
+    {
+        if !self.is_enabled() {
+            return compute();
+        }
+        self.memoize(&self.hashes, (code, data.to_vec()), data.len(), compute)
+    }
+
+    /// Returns the memoized unsealed sector CID of the pieces, or calls `compute` and memoizes its
+    /// result.
+    pub fn unsealed_sector_cid<F>(
+        &self,
+        proof_type: RegisteredSealProof,
+        pieces: &[PieceInfo],
+        compute: F,
+    ) -> Result<Cid>
+    where
+        F: FnOnce() -> Result<Cid>,
+    {
+        if !self.is_enabled() {
+            return compute();
+        }
+        let key: Vec<_> = pieces.iter().map(|p| (p.size.0, p.cid)).collect();
+        let size = key.len() * std::mem::size_of::<(u64, Cid)>();
+        self.memoize(&self.unsealed_cids, (proof_type, key), size, compute)
+    }
+
+    fn memoize<K, V, F>(
+        &self,
+        table: &RefCell<Map<K, V>>,
+        key: K,
+        size: usize,
+        compute: F,
+    ) -> Result<V>
+    where
+        K: Eq + Hash,
+        V: Clone,
+        F: FnOnce() -> Result<V>,
+    {
+        if let Some(value) = table.borrow().get(&key) {
+            self.hits.set(self.hits.get() + 1);
+            return Ok(value.clone());
+        }
+        let value = compute()?;
+        let used = self.used_bytes.get().saturating_add(size);
+        if used <= self.max_bytes {
+            self.used_bytes.set(used);
+            table.borrow_mut().insert(key, value.clone());
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use cid::Cid;
+    use fvm_shared::piece::{PaddedPieceSize, PieceInfo};
+    use fvm_shared::sector::RegisteredSealProof;
+    use multihash::{Code, MultihashDigest};
+
+    use super::SyscallMemo;
+    use crate::syscall_error;
+
+    #[test]
+    fn memoizes_hashes() {
+        let memo = SyscallMemo::new(8);
+        let computes = Cell::new(0);
+        let hash = |code, data: &[u8]| {
+            memo.hash(code, data, || {
+                computes.set(computes.get() + 1);
+                Ok(Code::Blake2b256.digest(data))
+            })
+            .unwrap()
+        };
+
+        assert_eq!(hash(0xb220, b"abc"), Code::Blake2b256.digest(b"abc"));
+        assert_eq!(hash(0xb220, b"abc"), Code::Blake2b256.digest(b"abc"));
+        assert_eq!((computes.get(), memo.hits()), (1, 1));
+
+        // The hash code is part of the key.
+        hash(0x1b, b"abc");
+        assert_eq!(computes.get(), 2);
+
+        // The memo is full (6 of 8 bytes used), so larger inputs aren't memoized.
+        hash(0xb220, b"abcdef");
+        hash(0xb220, b"abcdef");
+        assert_eq!((computes.get(), memo.hits()), (4, 1));
+    }
+
+    #[test]
+    fn memoizes_unsealed_cids() {
+        let memo = SyscallMemo::new(1 << 20);
+        let piece = |size| PieceInfo {
+            size: PaddedPieceSize(size),
+            cid: Cid::default(),
+        };
+        let computes = Cell::new(0);
+        let compute = |pieces: &[PieceInfo]| {
+            memo.unsealed_sector_cid(RegisteredSealProof::StackedDRG2KiBV1P1, pieces, || {
+                computes.set(computes.get() + 1);
+                Ok(Cid::default())
+            })
+            .unwrap()
+        };
+
+        compute(&[piece(1024), piece(1024)]);
+        compute(&[piece(1024), piece(1024)]);
+        compute(&[piece(2048)]);
+        assert_eq!((computes.get(), memo.hits()), (2, 1));
+    }
+
+    #[test]
+    fn disabled_and_errors() {
+        let memo = SyscallMemo::default();
+        assert!(!memo.is_enabled());
+        memo.hash(0xb220, b"a", || Ok(Code::Blake2b256.digest(b"a")))
+            .unwrap();
+        memo.hash(0xb220, b"a", || Ok(Code::Blake2b256.digest(b"a")))
+            .unwrap();
+        assert_eq!(memo.hits(), 0);
+
+        let memo = SyscallMemo::new(1024);
+        let res = memo.hash(0xb220, b"a", || {
+            Err(syscall_error!(IllegalArgument; "failed").into())
+        });
+        assert!(res.is_err());
+        let res = memo.hash(0xb220, b"a", || Ok(Code::Blake2b256.digest(b"a")));
+        assert_eq!(res.unwrap(), Code::Blake2b256.digest(b"a"));
+        assert_eq!(memo.hits(), 0);
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Verification of Merkle proofs over data from other chains. See
+//! [`CryptoOps::verify_merkle_proof`](super::CryptoOps::verify_merkle_proof).
+
+use fvm_shared::crypto::merkle::{BinaryMerkleProof, MerkleProof, PatriciaMerkleProof};
+use multihash::MultihashDigest;
+
+use super::hash::SupportedHashes;
+use super::Result;
+use crate::syscall_error;
+
+/// The work involved in verifying a proof, used to price it.
+#[derive(Debug)]
+pub(crate) struct ProofCost {
+    /// The hash function used.
+    pub hasher: SupportedHashes,
+    /// The number of nodes hashed.
+    pub nodes: usize,
+    /// The number of bytes hashed.
+    pub bytes: usize,
+}
+
+/// Checks that a proof is well-formed, and returns the cost of verifying it.
+pub(crate) fn proof_cost(proof: &MerkleProof) -> Result<ProofCost> {
+    match proof {
+        MerkleProof::Binary(proof) => {
+            let hasher = SupportedHashes::try_from(proof.hash).map_err(
+                |_| syscall_error!(IllegalArgument; "unsupported hash code {}", proof.hash),
+            )?;
+            let size = hasher.digest(&[]).size() as usize;
+            if proof.root.len() != size
+                || proof.leaf.len() != size
+                || proof.siblings.iter().any(|s| s.0.len() != size)
+            {
+                return Err(syscall_error!(IllegalArgument;
+                    "merkle proof digests must be {} bytes long", size)
+                .into());
+            }
+            let depth = proof.siblings.len();
+            if depth < 64 && proof.index >> depth != 0 {
+                return Err(syscall_error!(IllegalArgument;
+                    "leaf index {} out of range for a tree of depth {}", proof.index, depth)
+                .into());
+            }
+            Ok(ProofCost {
+                hasher,
+                nodes: depth,
+                bytes: depth * 2 * size,
+            })
+        }
+        MerkleProof::Patricia(proof) => {
+            if proof.value.is_empty() {
+                return Err(
+                    syscall_error!(IllegalArgument; "exclusion proofs aren't supported").into(),
+                );
+            }
+            Ok(ProofCost {
+                hasher: SupportedHashes::Keccak256,
+                nodes: proof.nodes.len(),
+                bytes: proof.nodes.iter().map(|n| n.0.len()).sum(),
+            })
+        }
+    }
+}
+
+/// Verifies a well-formed proof (see [`proof_cost`]).
+pub(crate) fn verify(proof: &MerkleProof) -> bool {
+    match proof {
+        MerkleProof::Binary(proof) => verify_binary(proof),
+        MerkleProof::Patricia(proof) => verify_patricia(proof).unwrap_or(false),
+    }
+}
+
+fn verify_binary(proof: &BinaryMerkleProof) -> bool {
+    let hasher = match SupportedHashes::try_from(proof.hash) {
+        Ok(hasher) => hasher,
+        Err(_) => return false,
+    };
+    let mut node = proof.leaf.clone();
+    let mut buf = Vec::with_capacity(2 * node.len());
+    for (height, sibling) in proof.siblings.iter().enumerate() {
+        buf.clear();
+        if height < 64 && (proof.index >> height) & 1 == 1 {
+            buf.extend_from_slice(&sibling.0);
+            buf.extend_from_slice(&node);
+        } else {
+            buf.extend_from_slice(&node);
+            buf.extend_from_slice(&sibling.0);
+        }
+        node = hasher.digest(&buf).digest().to_vec();
+    }
+    node == proof.root
+}
+
+/// A reference to a trie node: either its hash, or the node itself (its RLP list payload) when
+/// embedded in its parent.
+enum NodeRef<'a> {
+    Hash(&'a [u8]),
+    Inline(&'a [u8]),
+}
+
+/// Walks the trie from the root along the key's nibbles, returning `None` if a node is malformed.
+fn verify_patricia(proof: &PatriciaMerkleProof) -> Option<bool> {
+    let path: Vec<u8> = proof.key.iter().flat_map(|b| [b >> 4, b & 0xf]).collect();
+    let mut path = &path[..];
+    let mut nodes = proof.nodes.iter();
+    let mut next = NodeRef::Hash(&proof.root);
+    loop {
+        let node = match next {
+            NodeRef::Hash(hash) => {
+                let node = match nodes.next() {
+                    Some(node) => &node.0,
+                    None => return Some(false),
+                };
+                if SupportedHashes::Keccak256.digest(node).digest() != hash {
+                    return Some(false);
+                }
+                match decode_rlp(node)? {
+                    (Rlp::List(payload), []) => payload,
+                    _ => return None,
+                }
+            }
+            NodeRef::Inline(payload) => payload,
+        };
+
+        let items = decode_list(node)?;
+        let child = match items[..] {
+            // Branch node.
+            [ref children @ .., value] if children.len() == 16 => match path.split_first() {
+                Some((&nibble, rest)) => {
+                    path = rest;
+                    children[nibble as usize]
+                }
+                None => {
+                    return Some(nodes.next().is_none() && value == Rlp::String(&proof.value));
+                }
+            },
+            // Leaf or extension node.
+            [Rlp::String(encoded_path), child] => {
+                let (leaf, node_path) = decode_hex_prefix(encoded_path)?;
+                if leaf {
+                    return Some(
+                        nodes.next().is_none()
+                            && path == node_path
+                            && child == Rlp::String(&proof.value),
+                    );
+                }
+                if !path.starts_with(&node_path) {
+                    return Some(false);
+                }
+                path = &path[node_path.len()..];
+                child
+            }
+            _ => return None,
+        };
+
+        next = match child {
+            Rlp::String(hash) if hash.len() == 32 => NodeRef::Hash(hash),
+            Rlp::List(payload) => NodeRef::Inline(payload),
+            // An empty slot: the key isn't in the trie.
+            Rlp::String([]) => return Some(false),
+            Rlp::String(_) => return None,
+        };
+    }
+}
+
+/// Decodes a hex-prefix encoded path, returning whether it's a leaf's path, and its nibbles.
+fn decode_hex_prefix(encoded: &[u8]) -> Option<(bool, Vec<u8>)> {
+    let (&first, rest) = encoded.split_first()?;
+    let (leaf, odd) = match first >> 4 {
+        0 => (false, false),
+        1 => (false, true),
+        2 => (true, false),
+        3 => (true, true),
+        _ => return None,
+    };
+    let mut nibbles = Vec::with_capacity(2 * encoded.len());
+    if odd {
+        nibbles.push(first & 0xf);
+    }
+    nibbles.extend(rest.iter().flat_map(|b| [b >> 4, b & 0xf]));
+    Some((leaf, nibbles))
+}
+
+/// An RLP item: a byte string, or the (still encoded) payload of a list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rlp<'a> {
+    String(&'a [u8]),
+    List(&'a [u8]),
+}
+
+/// Decodes the RLP item at the start of `data`, returning it and the remaining bytes.
+fn decode_rlp(data: &[u8]) -> Option<(Rlp<'_>, &[u8])> {
+    let (&prefix, rest) = data.split_first()?;
+    let (list, len, rest) = match prefix {
+        0x00..=0x7f => return Some((Rlp::String(&data[..1]), rest)),
+        0x80..=0xb7 => (false, (prefix - 0x80) as usize, rest),
+        0xb8..=0xbf => {
+            let (len, rest) = decode_length(rest, prefix - 0xb7)?;
+            (false, len, rest)
+        }
+        0xc0..=0xf7 => (true, (prefix - 0xc0) as usize, rest),
+        0xf8..=0xff => {
+            let (len, rest) = decode_length(rest, prefix - 0xf7)?;
+            (true, len, rest)
+        }
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (payload, rest) = rest.split_at(len);
+    let item = if list {
+        Rlp::List(payload)
+    } else {
+        Rlp::String(payload)
+    };
+    Some((item, rest))
+}
+
+/// Decodes a big-endian length of `len_of_len` bytes.
+fn decode_length(data: &[u8], len_of_len: u8) -> Option<(usize, &[u8])> {
+    let len_of_len = len_of_len as usize;
+    if data.len() < len_of_len {
+        return None;
+    }
+    let (be, rest) = data.split_at(len_of_len);
+    let len = be.iter().fold(0u64, |len, &b| len << 8 | b as u64);
+    Some((usize::try_from(len).ok()?, rest))
+}
+
+/// Decodes the items of an RLP list payload.
+fn decode_list(mut payload: &[u8]) -> Option<Vec<Rlp<'_>>> {
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, rest) = decode_rlp(payload)?;
+        items.push(item);
+        payload = rest;
+    }
+    Some(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::BytesDe;
+
+    use super::*;
+
+    fn keccak(data: &[u8]) -> Vec<u8> {
+        SupportedHashes::Keccak256.digest(data).digest().to_vec()
+    }
+
+    fn rlp_string(bytes: &[u8]) -> Vec<u8> {
+        match bytes {
+            [b] if *b < 0x80 => vec![*b],
+            _ => rlp_with_header(0x80, bytes),
+        }
+    }
+
+    fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+        rlp_with_header(0xc0, &items.concat())
+    }
+
+    fn rlp_with_header(offset: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = if payload.len() < 56 {
+            vec![offset + payload.len() as u8]
+        } else {
+            let len = (payload.len() as u64).to_be_bytes();
+            let len = &len[len.iter().position(|&b| b != 0).unwrap()..];
+            let mut out = vec![offset + 55 + len.len() as u8];
+            out.extend_from_slice(len);
+            out
+        };
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// A binary tree of the 8 leaves `hash([i])`, returning the leaves and every level up to the
+    /// root.
+    fn binary_tree() -> Vec<Vec<Vec<u8>>> {
+        let mut levels = vec![(0u8..8).map(|i| keccak(&[i])).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| keccak(&pair.concat()))
+                .collect();
+            levels.push(level);
+        }
+        levels
+    }
+
+    fn binary_proof(levels: &[Vec<Vec<u8>>], index: u64) -> BinaryMerkleProof {
+        BinaryMerkleProof {
+            hash: SupportedHashes::Keccak256.into(),
+            root: levels.last().unwrap()[0].clone(),
+            leaf: levels[0][index as usize].clone(),
+            index,
+            siblings: levels[..levels.len() - 1]
+                .iter()
+                .enumerate()
+                .map(|(height, level)| BytesDe(level[(index >> height ^ 1) as usize].clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn binary() {
+        let levels = binary_tree();
+        for index in 0..8 {
+            let proof = MerkleProof::Binary(binary_proof(&levels, index));
+            let cost = proof_cost(&proof).unwrap();
+            assert_eq!((cost.nodes, cost.bytes), (3, 3 * 64));
+            assert!(verify(&proof));
+        }
+
+        // Wrong index, wrong leaf.
+        let mut proof = binary_proof(&levels, 3);
+        proof.index = 2;
+        assert!(!verify(&MerkleProof::Binary(proof)));
+        let mut proof = binary_proof(&levels, 3);
+        proof.leaf = keccak(b"nope");
+        assert!(!verify(&MerkleProof::Binary(proof)));
+
+        // Malformed proofs.
+        let mut proof = binary_proof(&levels, 3);
+        proof.index = 8;
+        proof_cost(&MerkleProof::Binary(proof)).unwrap_err();
+        let mut proof = binary_proof(&levels, 3);
+        proof.siblings[1].0.pop();
+        proof_cost(&MerkleProof::Binary(proof)).unwrap_err();
+        let mut proof = binary_proof(&levels, 3);
+        proof.hash = 0x1234;
+        proof_cost(&MerkleProof::Binary(proof)).unwrap_err();
+    }
+
+    #[test]
+    fn patricia() {
+        // A trie holding 0x1234 => 40 * "a" and 0x1256 => "b": an extension node with the path
+        // "12", pointing at a branch with a leaf at 3 (stored by hash) and at 5 (embedded).
+        let value_a = vec![b'a'; 40];
+        let leaf_a = rlp_list(&[rlp_string(&[0x34]), rlp_string(&value_a)]);
+        let leaf_b = rlp_list(&[rlp_string(&[0x36]), rlp_string(b"b")]);
+        assert!(leaf_a.len() >= 32 && leaf_b.len() < 32);
+
+        let mut children = vec![rlp_string(&[]); 17];
+        children[3] = rlp_string(&keccak(&leaf_a));
+        children[5] = leaf_b;
+        let branch = rlp_list(&children);
+        let extension = rlp_list(&[rlp_string(&[0x00, 0x12]), rlp_string(&keccak(&branch))]);
+        let root: [u8; 32] = keccak(&extension).try_into().unwrap();
+
+        let proof = |key: &[u8], value: &[u8], nodes: &[&Vec<u8>]| {
+            MerkleProof::Patricia(PatriciaMerkleProof {
+                root,
+                key: key.to_vec(),
+                value: value.to_vec(),
+                nodes: nodes.iter().map(|n| BytesDe(n.to_vec())).collect(),
+            })
+        };
+
+        let valid = proof(&[0x12, 0x34], &value_a, &[&extension, &branch, &leaf_a]);
+        let cost = proof_cost(&valid).unwrap();
+        assert_eq!(cost.nodes, 3);
+        assert_eq!(cost.bytes, extension.len() + branch.len() + leaf_a.len());
+        assert!(verify(&valid));
+        assert!(verify(&proof(&[0x12, 0x56], b"b", &[&extension, &branch])));
+
+        // Wrong value, missing key, missing and extra nodes.
+        assert!(!verify(&proof(&[0x12, 0x56], b"c", &[&extension, &branch])));
+        assert!(!verify(&proof(&[0x12, 0x57], b"b", &[&extension, &branch])));
+        assert!(!verify(&proof(&[0x12, 0x44], b"b", &[&extension, &branch])));
+        assert!(!verify(&proof(
+            &[0x12, 0x34],
+            &value_a,
+            &[&extension, &branch]
+        )));
+        assert!(!verify(&proof(
+            &[0x12, 0x56],
+            b"b",
+            &[&extension, &branch, &leaf_a]
+        )));
+        assert!(!verify(&proof(
+            &[0x12, 0x34],
+            &value_a,
+            &[&branch, &leaf_a]
+        )));
+
+        // Exclusion proofs aren't supported.
+        proof_cost(&proof(&[0x12, 0x57], b"", &[&extension, &branch])).unwrap_err();
+    }
+}
@@ -1,13 +1,15 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-pub use blocks::{Block, BlockId, BlockRegistry, BlockStat};
+pub use blocks::{Block, BlockId, BlockRegistry, BlockStat, GraphStat};
 use cid::Cid;
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::crypto::merkle::MerkleProof;
 use fvm_shared::crypto::signature::{
     SignatureType, SECP_PUB_LEN, SECP_SIG_LEN, SECP_SIG_MESSAGE_HASH_SIZE,
 };
+use fvm_shared::crypto::snark::SnarkVerifyInfo;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::piece::PieceInfo;
@@ -16,34 +18,64 @@ use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
     WindowPoStVerifyInfo,
 };
+use fvm_shared::state::StateQuery;
 use fvm_shared::sys::out::network::NetworkContext;
 use fvm_shared::sys::out::vm::MessageContext;
-use fvm_shared::sys::SendFlags;
+use fvm_shared::sys::{FlagOptions, SendFlags};
+use fvm_shared::token::TokenTransfer;
 use fvm_shared::{ActorID, MethodNum};
 
 mod hash;
 
 mod blocks;
 pub mod default;
+mod flags;
+mod groth16;
+mod hasher;
+mod links;
+mod memo;
+mod merkle;
+mod plonk;
+mod randomness;
+pub mod read_only;
+pub mod replay;
 
 pub(crate) mod error;
 
 pub use error::{ClassifyResult, Context, ExecutionError, Result, SyscallError};
+pub use flags::FlagRegistry;
 use fvm_shared::event::{ActorEvent, StampedEvent};
 pub use hash::SupportedHashes;
+pub use hasher::{HasherId, HasherRegistry};
+pub use links::LinkTracker;
+pub use memo::SyscallMemo;
 use multihash::MultihashGeneric;
+pub use randomness::{DevRandomness, RandomnessCache, RandomnessSource};
 
 use crate::call_manager::CallManager;
-use crate::gas::{Gas, GasTimer, PriceList};
+use crate::engine::CodeInfo;
+use crate::gas::{Gas, GasTimer, InstructionClock, PriceList};
 use crate::machine::limiter::MemoryLimiter;
 use crate::machine::Machine;
 
+#[derive(Debug)]
 pub struct SendResult {
     pub block_id: BlockId,
     pub block_stat: BlockStat,
     pub exit_code: ExitCode,
 }
 
+/// The result of a token transfer's receiver hook.
+#[derive(Debug)]
+pub struct TokenTransferResult {
+    /// The ID of the owner of the tokens.
+    pub from: ActorID,
+    /// The ID of the recipient of the tokens.
+    pub to: ActorID,
+    /// The result of the recipient's receiver hook.
+    pub hook: SendResult,
+}
+
 /// The "kernel" implements the FVM interface as presented to the actors. It:
 ///
 /// - Manages the Actor's state.
@@ -62,9 +94,13 @@ pub trait Kernel:
     + MessageOps
     + NetworkOps
     + RandomnessOps
+    + ReturnOps
     + SelfOps
     + SendOps
     + LimiterOps
+    + SyncOps
+    + CronOps
+    + TokenOps
     + 'static
 {
     /// The [`Kernel`]'s [`CallManager`] is
@@ -105,6 +141,13 @@ pub trait NetworkOps {
 
     /// The CID of the tipset at the specified epoch.
     fn tipset_cid(&self, epoch: ChainEpoch) -> Result<Cid>;
+
+    /// Looks up domain state through the node's
+    /// [`StateReader`](crate::externs::StateReader), returning the DAG-CBOR encoded value if
+    /// found. Only the actors listed in [`NetworkConfig::state_readers`] may call this.
+    ///
+    /// [`NetworkConfig::state_readers`]: crate::machine::NetworkConfig::state_readers
+    fn read_state(&self, query: &StateQuery) -> Result<Option<Vec<u8>>>;
 }
 
 /// Accessors to query attributes of the incoming message.
@@ -122,9 +165,10 @@ pub trait IpldBlockOps {
 
     /// Create a new block.
     ///
-    /// This method will fail if the block is too large (SPEC_AUDIT), the codec is not allowed
-    /// (SPEC_AUDIT), the block references unreachable blocks, or the block contains too many links
-    /// (SPEC_AUDIT).
+    /// This method will fail if the block exceeds the network's maximum block size, the codec is
+    /// not allowed (SPEC_AUDIT), the block references unreachable blocks, or the block contains too
+    /// many links (SPEC_AUDIT). Blocks larger than the network's soft block size limit incur a
+    /// superlinear gas surcharge.
     fn block_create(&mut self, codec: u64, data: &[u8]) -> Result<BlockId>;
 
     /// Computes a CID for a block.
@@ -143,6 +187,27 @@ pub trait IpldBlockOps {
     ///
     /// This method will fail if the block handle is invalid.
     fn block_stat(&self, id: BlockId) -> Result<BlockStat>;
+
+    /// Closes a block handle, releasing the block. The handle may be reused by a later block.
+    ///
+    /// This method will fail if the block handle is invalid.
+    fn block_close(&mut self, id: BlockId) -> Result<()>;
+
+    /// Estimates the size of the graph of blocks reachable from `root` (including `root`) by
+    /// walking it breadth-first, visiting at most `max_blocks` distinct blocks. Only DAG-CBOR and
+    /// raw blocks are visited. Links to blocks missing from the blockstore are skipped, and the
+    /// statistics are marked incomplete.
+    ///
+    /// The root must be reachable by the actor: it must be the actor's state root, or be linked by
+    /// one of the actor's open blocks.
+    ///
+    /// This method will fail if `max_blocks` is zero, or if the root block isn't reachable or
+    /// doesn't exist.
+    fn block_graph_stat(&mut self, root: &Cid, max_blocks: u32) -> Result<GraphStat>;
+
+    /// Hints that the block will likely be opened soon, so that it can be loaded in the background.
+    /// The hint is purely advisory: it doesn't affect execution, and may be ignored.
+    fn block_prefetch(&self, cid: &Cid) -> Result<()>;
 }
 
 /// Actor state access and manipulation.
@@ -163,6 +228,11 @@ pub trait SelfOps: IpldBlockOps {
     /// Aborts if the beneficiary does not exist.
     /// May only be called by the actor itself.
     fn self_destruct(&mut self, beneficiary: &Address) -> Result<()>;
+
+    /// Computes the statistics of the graph of blocks rooted at the actor's state root, counted as
+    /// a tree. Parts of the graph recorded by the [`LinkTracker`] aren't walked again. Links to
+    /// blocks missing from the blockstore are skipped, and the statistics are marked incomplete.
+    fn self_state_stat(&mut self) -> Result<GraphStat>;
 }
 
 /// Actors operations whose scope of action is actors other than the calling
@@ -204,8 +274,16 @@ pub trait ActorOps {
     /// Returns the CodeCID for the supplied built-in actor type.
     fn get_code_cid_for_type(&self, typ: u32) -> Result<Cid>;
 
+    /// Returns the size and ABI version of the given builtin actor code. Fails with `NotFound` if
+    /// the code isn't listed in the builtin actors' manifest, and with `LimitExceeded` if it's
+    /// larger than [`MAX_CODE_SIZE`](crate::engine::code_info::MAX_CODE_SIZE).
+    fn get_code_info(&self, code_cid: &Cid) -> Result<CodeInfo>;
+
     /// Returns the balance associated with an actor id
     fn balance_of(&self, actor_id: ActorID) -> Result<TokenAmount>;
+
+    /// Returns the nonce (sequence number) associated with an actor id
+    fn nonce_of(&self, actor_id: ActorID) -> Result<u64>;
 }
 
 /// Operations to send messages to other actors.
@@ -219,6 +297,22 @@ pub trait SendOps {
         gas_limit: Option<Gas>,
         flags: SendFlags,
     ) -> Result<SendResult>;
+
+    /// Invokes a method of the given code in the context of the calling actor, like a send to
+    /// itself with its code swapped out. The delegated code runs as the same actor (same ID,
+    /// state root, and balance), and sees the same caller and value received, but no value is
+    /// transferred.
+    ///
+    /// Only user (non-builtin) actors may delegate calls, and only to the code allowed by
+    /// [`NetworkConfig::delegate_call_code`](crate::machine::NetworkConfig::delegate_call_code).
+    fn delegate_call(
+        &mut self,
+        code: &Cid,
+        method: u64,
+        params: BlockId,
+        gas_limit: Option<Gas>,
+        flags: SendFlags,
+    ) -> Result<SendResult>;
 }
 
 /// Operations to query the circulating supply.
@@ -248,6 +342,13 @@ pub trait GasOps {
 
     /// Returns the currently active gas price list.
     fn price_list(&self) -> &PriceList;
+
+    /// Records the last syscall to complete successfully, for out-of-gas reporting.
+    fn record_syscall(&self, module: &'static str, name: &'static str);
+
+    /// Returns the clock gas charges are timed with, if they're timed by instructions executed.
+    /// See [`GasTracker::enable_instruction_clock`](crate::gas::GasTracker::enable_instruction_clock).
+    fn instruction_clock(&self) -> Option<&InstructionClock>;
 }
 
 /// Cryptographic primitives provided by the kernel.
@@ -274,6 +375,18 @@ pub trait CryptoOps {
     /// will not be overwritten.
     fn hash(&self, code: u64, data: &[u8]) -> Result<MultihashGeneric<64>>;
 
+    /// Opens an incremental hasher using the specified hash function, for hashing data in multiple
+    /// calls with [`CryptoOps::hash_update`]. Fails with `LimitExceeded` if the message has already
+    /// opened [`NetworkConfig::max_hashers`](crate::machine::NetworkConfig::max_hashers) hashers.
+    fn hash_init(&self, code: u64) -> Result<HasherId>;
+
+    /// Feeds data to one of the actor's incremental hashers.
+    fn hash_update(&self, hasher: HasherId, data: &[u8]) -> Result<()>;
+
+    /// Finalizes one of the actor's incremental hashers, returning the digest. The hasher is
+    /// released.
+    fn hash_finalize(&self, hasher: HasherId) -> Result<MultihashGeneric<64>>;
+
     /// Computes an unsealed sector CID (CommD) from its constituent piece CIDs (CommPs) and sizes.
     fn compute_unsealed_sector_cid(
         &self,
@@ -317,6 +430,34 @@ pub trait CryptoOps {
     /// Verify replica update verifies a snap deal: an upgrade from a CC sector to a sector with
     /// deals.
     fn verify_replica_update(&self, replica: &ReplicaUpdateInfo) -> Result<bool>;
+
+    /// Verifies a Merkle inclusion proof over data from another chain (e.g., an Ethereum storage
+    /// proof), against the root supplied in the proof. Gas is charged by the number and total size
+    /// of the nodes hashed.
+    ///
+    /// Returns an `IllegalArgument` error if the proof is malformed (e.g., its digests don't match
+    /// its hash function), and `false` if it's well-formed but doesn't verify.
+    fn verify_merkle_proof(&self, proof: &MerkleProof) -> Result<bool>;
+
+    /// Verifies a Groth16 proof against the verifying key in the given open block (a DAG-CBOR
+    /// [`SnarkVerifyingKey`](fvm_shared::crypto::snark::SnarkVerifyingKey)). Gas is charged for
+    /// decoding the key, then by the number of input constraints of the circuit; the actor has
+    /// already paid to open the key.
+    ///
+    /// Returns an `InvalidHandle` error if the block isn't open, an `IllegalArgument` error if the
+    /// curve isn't supported, or the key, proof or public inputs are malformed, and `false` if the
+    /// proof doesn't verify.
+    fn verify_groth16(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool>;
+
+    /// Verifies a PLONK proof against the verifying key in the given open block (a DAG-CBOR
+    /// [`SnarkVerifyingKey`](fvm_shared::crypto::snark::SnarkVerifyingKey)). Gas is charged for
+    /// decoding the key, then by the size of the circuit's evaluation domain and its number of
+    /// public inputs; the actor has already paid to open the key.
+    ///
+    /// Returns an `InvalidHandle` error if the block isn't open, an `IllegalArgument` error if the
+    /// curve isn't supported, or the key, proof or public inputs are malformed, and `false` if the
+    /// proof doesn't verify.
+    fn verify_plonk(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool>;
 }
 
 /// Randomness queries.
@@ -340,6 +481,16 @@ pub trait RandomnessOps {
         rand_epoch: ChainEpoch,
         entropy: &[u8],
     ) -> Result<[u8; RANDOMNESS_LENGTH]>;
+
+    /// Returns deterministic pseudo-randomness derived from the current epoch, the message CID,
+    /// and a counter incremented on every draw within the message, incorporating requisite
+    /// entropy. This randomness is entirely predictable, and is only available on development
+    /// networks (see [`NetworkConfig::dev_randomness`](crate::machine::NetworkConfig::dev_randomness)).
+    fn get_dev_randomness(
+        &self,
+        personalization: i64,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]>;
 }
 
 /// Debugging APIs.
@@ -366,8 +517,83 @@ pub trait LimiterOps {
     fn limiter_mut(&mut self) -> &mut Self::Limiter;
 }
 
+/// The maximum number of payloads that may be queued on the return-data queue at once.
+pub const MAX_RETURN_QUEUE_LEN: usize = 32;
+
+/// Return-data queue APIs.
+///
+/// Syscalls that produce optional or variable-sized payloads in addition to their primary result
+/// push those payloads onto a bounded, per-invocation FIFO queue. The actor retrieves them, in
+/// order, with the `vm::return_len` and `vm::return_pop` syscalls. Any payloads left on the queue
+/// are discarded when the invocation returns.
+pub trait ReturnOps {
+    /// Pushes a payload onto the back of the return-data queue.
+    ///
+    /// Fails with `LimitExceeded` if the queue already holds [`MAX_RETURN_QUEUE_LEN`] payloads.
+    fn return_push(&mut self, data: Vec<u8>) -> Result<()>;
+
+    /// Removes and returns the payload at the front of the return-data queue.
+    ///
+    /// Fails with `NotFound` if the queue is empty.
+    fn return_pop(&mut self) -> Result<Vec<u8>>;
+
+    /// Returns the length (in bytes) of the payload at the front of the return-data queue, or
+    /// `None` if the queue is empty.
+    fn return_len(&self) -> Option<u32>;
+}
+
 /// Eventing APIs.
 pub trait EventOps {
     /// Records an event emitted throughout execution.
     fn emit_event(&mut self, evt: ActorEvent) -> Result<()>;
 }
+
+/// Per-message flags, namespaced by actor. These let actors implement cheap reentrancy locks
+/// without a round-trip through their state. Flag changes are rolled back with the invocation's
+/// state changes. See [`FlagRegistry`].
+pub trait SyncOps {
+    /// Sets one of the actor's flags. Unless [`FlagOptions::MESSAGE_SCOPE`] is specified, the flag
+    /// is cleared when the current invocation returns. Returns false, leaving the flag untouched,
+    /// if it's already set.
+    fn set_flag(&mut self, key: u64, options: FlagOptions) -> Result<bool>;
+
+    /// Clears one of the actor's flags. Returns false if it wasn't set.
+    fn clear_flag(&mut self, key: u64) -> Result<bool>;
+}
+
+/// Cron entries: actor methods invoked by the cron actor at the end of every epoch, with no
+/// parameters and no value.
+pub trait CronOps {
+    /// Registers one of the actor's methods with the cron actor.
+    ///
+    /// Only builtin actors may register cron entries (cron invocations aren't paid for by any
+    /// message), and the method can't be a plain send or a constructor. Each method can only be
+    /// registered once.
+    fn cron_register(&mut self, method: MethodNum) -> Result<()>;
+
+    /// Removes one of the actor's methods from the cron actor's entries.
+    fn cron_unregister(&mut self, method: MethodNum) -> Result<()>;
+}
+
+/// Operations to help token actors implement FRC-46 transfers.
+///
+/// The token actor remains responsible for its balances and allowances: it debits the owner (and
+/// the operator's allowance, if the operator isn't the owner), credits the recipient, and then
+/// calls [`token_transfer_hook`](TokenOps::token_transfer_hook) to notify the recipient. The
+/// `token::transfer_hook` syscall aborts the token actor, reverting the transfer, if the recipient
+/// rejects it.
+pub trait TokenOps {
+    /// Invokes the receiver hook of the recipient of tokens transferred by the calling actor on
+    /// behalf of its caller (the operator), with at most `gas_limit` gas (capped at
+    /// [`MAX_RECEIVER_HOOK_GAS`](fvm_shared::token::MAX_RECEIVER_HOOK_GAS)). The recipient is
+    /// created if it doesn't exist.
+    ///
+    /// Fails with `IllegalArgument` if the amount is negative, and with `NotFound` if the owner
+    /// doesn't exist, or the recipient can't be created. Whether the recipient accepted the
+    /// transfer is reported by the hook's exit code.
+    fn token_transfer_hook(
+        &mut self,
+        transfer: &TokenTransfer,
+        gas_limit: Option<Gas>,
+    ) -> Result<TokenTransferResult>;
+}
@@ -0,0 +1,226 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Verification of PLONK proofs. See [`CryptoOps::verify_plonk`](super::CryptoOps::verify_plonk).
+//!
+//! Keys and proofs are in dusk-plonk's encoding, the only PLONK implementation over BLS12-381
+//! that we can verify with. Other curves are rejected until they're implemented and priced.
+
+use dusk_bytes::{DeserializableSlice, Serializable};
+use dusk_plonk::prelude::{BlsScalar, Error as PlonkError, Proof, Verifier};
+use fvm_shared::crypto::snark::{
+    SnarkCurve, SnarkVerifyInfo, SnarkVerifyingKey, SNARK_PUBLIC_INPUT_LEN,
+};
+
+use super::Result;
+use crate::syscall_error;
+
+/// The length of the header of an encoded verifier: the lengths of its label, key, opening key and
+/// public input indexes, followed by the size of its evaluation domain and number of constraints,
+/// as big-endian u64s.
+const VERIFIER_HEADER_LEN: usize = 6 * 8;
+
+/// The length of the verifier key (the commitments to the circuit's polynomials), starting with
+/// the number of constraints as a little-endian u64.
+const VERIFIER_KEY_LEN: usize = 20 * 48 + 8;
+
+/// The length of the KZG opening key: one G1 and two G2 points (compressed).
+const OPENING_KEY_LEN: usize = 48 + 2 * 96;
+
+/// The length of the index of a public input in the evaluation domain.
+const PUBLIC_INPUT_INDEX_LEN: usize = 8;
+
+/// Checks that a proof is well-formed, without decoding the key's points: its header must be
+/// consistent and describe a circuit with the supplied number of public inputs. Returns the size of
+/// the circuit's evaluation domain, by which the verification is priced.
+pub(crate) fn check(vk: &SnarkVerifyingKey, info: &SnarkVerifyInfo) -> Result<usize> {
+    if let Some(input) = info
+        .public_inputs
+        .iter()
+        .find(|input| input.0.len() != SNARK_PUBLIC_INPUT_LEN)
+    {
+        return Err(syscall_error!(IllegalArgument;
+            "public inputs must be {} bytes long, got {}", SNARK_PUBLIC_INPUT_LEN, input.0.len())
+        .into());
+    }
+    match vk.curve {
+        SnarkCurve::Bls12_381 => check_bls12_381(&vk.key, info),
+    }
+}
+
+fn check_bls12_381(key: &[u8], info: &SnarkVerifyInfo) -> Result<usize> {
+    let malformed = || syscall_error!(IllegalArgument; "malformed verifying key");
+    if key.len() < VERIFIER_HEADER_LEN {
+        return Err(malformed().into());
+    }
+    let (header, body) = key.split_at(VERIFIER_HEADER_LEN);
+    let header: Vec<usize> = header
+        .chunks_exact(8)
+        .map(|field| u64::from_be_bytes(field.try_into().expect("checked length")))
+        .map(|field| usize::try_from(field).map_err(|_| malformed()))
+        .collect::<std::result::Result<_, _>>()?;
+    let (label_len, key_len, opening_key_len, inputs, size, constraints) = (
+        header[0], header[1], header[2], header[3], header[4], header[5],
+    );
+
+    if key_len != VERIFIER_KEY_LEN || opening_key_len != OPENING_KEY_LEN {
+        return Err(malformed().into());
+    }
+    let indexes_len = inputs
+        .checked_mul(PUBLIC_INPUT_INDEX_LEN)
+        .ok_or_else(malformed)?;
+    let body_len = label_len
+        .checked_add(VERIFIER_KEY_LEN + OPENING_KEY_LEN)
+        .and_then(|len| len.checked_add(indexes_len))
+        .ok_or_else(malformed)?;
+    if body.len() != body_len {
+        return Err(malformed().into());
+    }
+
+    // The domain must be the smallest that fits the constraints, as the verifier key describes.
+    let key_constraints = &body[label_len..label_len + 8];
+    if constraints == 0
+        || constraints.checked_next_power_of_two() != Some(size)
+        || u64::from_le_bytes(key_constraints.try_into().expect("checked length"))
+            != constraints as u64
+    {
+        return Err(malformed().into());
+    }
+    if body[body_len - indexes_len..]
+        .chunks_exact(PUBLIC_INPUT_INDEX_LEN)
+        .any(|index| u64::from_be_bytes(index.try_into().expect("checked length")) >= size as u64)
+    {
+        return Err(malformed().into());
+    }
+
+    if inputs != info.public_inputs.len() {
+        return Err(syscall_error!(IllegalArgument;
+            "verifying key is for {} public inputs, got {}", inputs, info.public_inputs.len())
+        .into());
+    }
+    Ok(size)
+}
+
+/// Verifies a well-formed proof (see [`check`]). Returns an `IllegalArgument` error if the key,
+/// proof, or public inputs can't be decoded (e.g., points aren't on the curve).
+pub(crate) fn verify(vk: &SnarkVerifyingKey, info: &SnarkVerifyInfo) -> Result<bool> {
+    match vk.curve {
+        SnarkCurve::Bls12_381 => verify_bls12_381(vk, info),
+    }
+}
+
+fn verify_bls12_381(vk: &SnarkVerifyingKey, info: &SnarkVerifyInfo) -> Result<bool> {
+    // The verifier doesn't depend on the circuit's type, only on its (encoded) description.
+    let verifier = Verifier::<()>::try_from_bytes(&vk.key)
+        .map_err(|e| syscall_error!(IllegalArgument; "malformed verifying key: {:?}", e))?;
+
+    if info.proof.len() != Proof::SIZE {
+        return Err(syscall_error!(IllegalArgument;
+            "proof must be {} bytes long, got {}", Proof::SIZE, info.proof.len())
+        .into());
+    }
+    let proof = Proof::from_slice(&info.proof)
+        .map_err(|e| syscall_error!(IllegalArgument; "malformed proof: {:?}", e))?;
+
+    let inputs = info
+        .public_inputs
+        .iter()
+        .map(|input| {
+            let bytes: &[u8; SNARK_PUBLIC_INPUT_LEN] = input.0[..].try_into().map_err(|_| {
+                syscall_error!(IllegalArgument; "public inputs must be {} bytes long",
+                    SNARK_PUBLIC_INPUT_LEN)
+            })?;
+            BlsScalar::from_bytes(bytes).map_err(|_| {
+                syscall_error!(IllegalArgument; "public input isn't a canonical field element")
+                    .into()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    match verifier.verify(&proof, &inputs) {
+        Ok(()) => Ok(true),
+        Err(PlonkError::ProofVerificationError | PlonkError::PairingCheckFailure) => Ok(false),
+        Err(e) => Err(syscall_error!(IllegalArgument; "invalid proof: {:?}", e).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dusk_plonk::prelude::{Circuit, Compiler, Composer, Constraint, PublicParameters};
+    use fvm_ipld_encoding::BytesDe;
+    use fvm_shared::error::ErrorNumber;
+
+    use super::*;
+    use crate::kernel::{ExecutionError, SyscallError};
+
+    /// Proves knowledge of a square root of the public input.
+    #[derive(Default)]
+    struct SquareRoot(BlsScalar);
+
+    impl Circuit for SquareRoot {
+        fn circuit<C: Composer>(&self, composer: &mut C) -> std::result::Result<(), PlonkError> {
+            let root = composer.append_witness(self.0);
+            let square = composer.gate_mul(Constraint::new().mult(1).a(root).b(root));
+            let public = composer.append_public(self.0.square());
+            composer.assert_equal(square, public);
+            Ok(())
+        }
+    }
+
+    fn error_number(result: Result<impl std::fmt::Debug>) -> ErrorNumber {
+        match result {
+            Err(ExecutionError::Syscall(SyscallError(_, e))) => e,
+            other => panic!("expected a syscall error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bls12_381() {
+        let mut rng = rand::thread_rng();
+        let root = BlsScalar::from(7u64);
+        let params = PublicParameters::setup(1 << 8, &mut rng).unwrap();
+        let (prover, verifier) =
+            Compiler::compile_with_circuit(&params, b"square root", &SquareRoot(root)).unwrap();
+        // The prover returns the public inputs as the verifier expects them (negated).
+        let (proof, public_inputs) = prover.prove(&mut rng, &SquareRoot(root)).unwrap();
+        assert_eq!(public_inputs, vec![-root.square()]);
+
+        let vk = SnarkVerifyingKey {
+            curve: SnarkCurve::Bls12_381,
+            key: verifier.to_bytes(),
+        };
+        let mut info = SnarkVerifyInfo {
+            proof: proof.to_bytes().to_vec(),
+            public_inputs: vec![BytesDe(public_inputs[0].to_bytes().to_vec())],
+        };
+        let size = check(&vk, &info).unwrap();
+        assert!(size.is_power_of_two());
+        assert!(verify(&vk, &info).unwrap());
+
+        // 7 isn't the square of 7.
+        info.public_inputs = vec![BytesDe((-root).to_bytes().to_vec())];
+        assert!(!verify(&vk, &info).unwrap());
+
+        // The key is for a single public input.
+        info.public_inputs.push(info.public_inputs[0].clone());
+        assert_eq!(
+            error_number(check(&vk, &info)),
+            ErrorNumber::IllegalArgument
+        );
+        info.public_inputs.truncate(1);
+
+        // The domain size must match the number of constraints.
+        let mut oversized = vk.clone();
+        oversized.key[32..40].copy_from_slice(&(size as u64 * 2).to_be_bytes());
+        assert_eq!(
+            error_number(check(&oversized, &info)),
+            ErrorNumber::IllegalArgument
+        );
+
+        // Public inputs must be canonical field elements.
+        info.public_inputs = vec![BytesDe(vec![0xff; SNARK_PUBLIC_INPUT_LEN])];
+        assert_eq!(
+            error_number(verify(&vk, &info)),
+            ErrorNumber::IllegalArgument
+        );
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::cell::{Cell, RefCell};
+
+use cid::Cid;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+
+use super::Result;
+use crate::determinism::Map;
+
+/// The source of some randomness.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RandomnessSource {
+    /// Randomness drawn from the ticket chain.
+    Tickets,
+    /// Randomness drawn from the beacon.
+    Beacon,
+}
+
+type CacheKey = (RandomnessSource, i64, ChainEpoch, Vec<u8>);
+
+/// The maximum number of entries held by a [`RandomnessCache`].
+const MAX_CACHE_ENTRIES: usize = 1024;
+
+/// The maximum total size (in bytes) of the entropy held by a [`RandomnessCache`].
+const MAX_CACHE_ENTROPY_BYTES: usize = 1 << 20;
+
+/// Caches randomness fetched from the externs for the duration of a message, keyed by source,
+/// personalization, epoch, and entropy.
+///
+/// Randomness is deterministic for a given key, so repeated lookups within a message can be served
+/// without going back to the client. Failed lookups are not cached. The cache is bounded both in
+/// entries and in the total size of the cached entropy (which is chosen by actors): once full,
+/// further lookups are forwarded to the client without being cached.
+#[derive(Debug, Default)]
+pub struct RandomnessCache {
+    entries: RefCell<Map<CacheKey, [u8; RANDOMNESS_LENGTH]>>,
+    entropy_bytes: Cell<usize>,
+}
+
+impl RandomnessCache {
+    /// Returns the cached randomness for the given key, or calls `fetch` and caches the result if
+    /// it succeeds.
+    pub fn get_or_fetch<F>(
+        &self,
+        source: RandomnessSource,
+        personalization: i64,
+        rand_epoch: ChainEpoch,
+        entropy: &[u8],
+        fetch: F,
+    ) -> Result<[u8; RANDOMNESS_LENGTH]>
+    where
+        F: FnOnce() -> Result<[u8; RANDOMNESS_LENGTH]>,
+    {
+        let key = (source, personalization, rand_epoch, entropy.to_vec());
+        if let Some(randomness) = self.entries.borrow().get(&key) {
+            return Ok(*randomness);
+        }
+        let randomness = fetch()?;
+        let mut entries = self.entries.borrow_mut();
+        let entropy_bytes = self.entropy_bytes.get() + entropy.len();
+        if entries.len() < MAX_CACHE_ENTRIES && entropy_bytes <= MAX_CACHE_ENTROPY_BYTES {
+            entries.insert(key, randomness);
+            self.entropy_bytes.set(entropy_bytes);
+        }
+        Ok(randomness)
+    }
+}
+
+/// The BLAKE2b personalization tag for dev randomness, separating it from any other randomness
+/// (in particular, randomness drawn from the beacon).
+const DEV_RANDOMNESS_TAG: &[u8] = b"fvm-dev-rand";
+
+/// Deterministic pseudo-randomness for test networks without a randomness beacon. See
+/// [`NetworkConfig::dev_randomness`](crate::machine::NetworkConfig::dev_randomness).
+///
+/// Each draw hashes the current epoch, the CID of the message being executed, and a counter
+/// incremented on every draw within the message, along with the caller's personalization and
+/// entropy. Draws are therefore reproducible, but entirely predictable: this must never be used on
+/// a network where anyone could profit from predicting the randomness.
+#[derive(Debug)]
+pub struct DevRandomness {
+    message: Cid,
+    counter: Cell<u64>,
+}
+
+impl DevRandomness {
+    /// Creates a new source of dev randomness for the message with the given CID.
+    pub fn new(message: Cid) -> Self {
+        Self {
+            message,
+            counter: Cell::new(0),
+        }
+    }
+
+    /// Draws the next 32 bytes of randomness.
+    pub fn draw(
+        &self,
+        personalization: i64,
+        epoch: ChainEpoch,
+        entropy: &[u8],
+    ) -> [u8; RANDOMNESS_LENGTH] {
+        let counter = self.counter.get();
+        self.counter.set(counter + 1);
+
+        let mut state = blake2b_simd::Params::new()
+            .hash_length(RANDOMNESS_LENGTH)
+            .personal(DEV_RANDOMNESS_TAG)
+            .to_state();
+        state.update(&personalization.to_be_bytes());
+        state.update(&epoch.to_be_bytes());
+        state.update(&self.message.to_bytes());
+        state.update(&counter.to_be_bytes());
+        state.update(entropy);
+
+        let mut ret = [0u8; RANDOMNESS_LENGTH];
+        ret.copy_from_slice(state.finalize().as_bytes());
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use cid::Cid;
+    use fvm_shared::error::ErrorNumber;
+
+    use super::{
+        DevRandomness, RandomnessCache, RandomnessSource, MAX_CACHE_ENTRIES,
+        MAX_CACHE_ENTROPY_BYTES,
+    };
+    use crate::syscall_error;
+
+    #[test]
+    fn caches_by_key() {
+        use RandomnessSource::*;
+
+        let cache = RandomnessCache::default();
+        let fetches = Cell::new(0);
+        let get = |source, pers, epoch, entropy: &[u8], value| {
+            cache
+                .get_or_fetch(source, pers, epoch, entropy, || {
+                    fetches.set(fetches.get() + 1);
+                    Ok([value; 32])
+                })
+                .unwrap()
+        };
+
+        assert_eq!(get(Tickets, 1, 10, b"a", 1), [1; 32]);
+        assert_eq!(get(Tickets, 1, 10, b"a", 2), [1; 32]);
+        assert_eq!(fetches.get(), 1);
+
+        // Every component of the key matters.
+        assert_eq!(get(Beacon, 1, 10, b"a", 3), [3; 32]);
+        assert_eq!(get(Tickets, 2, 10, b"a", 4), [4; 32]);
+        assert_eq!(get(Tickets, 1, 11, b"a", 5), [5; 32]);
+        assert_eq!(get(Tickets, 1, 10, b"b", 6), [6; 32]);
+        assert_eq!(fetches.get(), 5);
+    }
+
+    #[test]
+    fn bounded() {
+        let cache = RandomnessCache::default();
+        let fetches = Cell::new(0);
+        let get = |epoch, entropy: &[u8]| {
+            cache
+                .get_or_fetch(RandomnessSource::Tickets, 1, epoch, entropy, || {
+                    fetches.set(fetches.get() + 1);
+                    Ok([0; 32])
+                })
+                .unwrap()
+        };
+
+        // Entropy beyond the limit isn't cached.
+        let big = vec![0; MAX_CACHE_ENTROPY_BYTES + 1];
+        get(0, &big);
+        get(0, &big);
+        assert_eq!(fetches.get(), 2);
+
+        // Nor are entries beyond the limit.
+        for epoch in 0..=MAX_CACHE_ENTRIES as i64 {
+            get(epoch, &[]);
+        }
+        fetches.set(0);
+        get(0, &[]);
+        get(MAX_CACHE_ENTRIES as i64, &[]);
+        assert_eq!(fetches.get(), 1);
+    }
+
+    #[test]
+    fn errors_not_cached() {
+        let cache = RandomnessCache::default();
+        let source = RandomnessSource::Beacon;
+
+        let res = cache.get_or_fetch(source, 1, 10, &[], || {
+            Err(syscall_error!(IllegalArgument; "no randomness").into())
+        });
+        assert!(matches!(
+            res,
+            Err(crate::kernel::ExecutionError::Syscall(e)) if e.1 == ErrorNumber::IllegalArgument
+        ));
+
+        let res = cache.get_or_fetch(source, 1, 10, &[], || Ok([7; 32]));
+        assert_eq!(res.unwrap(), [7; 32]);
+    }
+
+    #[test]
+    fn dev_randomness_is_deterministic() {
+        let msg = Cid::default();
+        let (a, b) = (DevRandomness::new(msg), DevRandomness::new(msg));
+
+        let first = a.draw(1, 10, b"a");
+        assert_eq!(first, b.draw(1, 10, b"a"));
+
+        // Every draw advances the counter.
+        let second = a.draw(1, 10, b"a");
+        assert_ne!(first, second);
+        assert_eq!(second, b.draw(1, 10, b"a"));
+
+        // Every input matters.
+        let draw = |pers, epoch, entropy: &[u8]| DevRandomness::new(msg).draw(pers, epoch, entropy);
+        assert_ne!(first, draw(2, 10, b"a"));
+        assert_ne!(first, draw(1, 11, b"a"));
+        assert_ne!(first, draw(1, 10, b"b"));
+        let other = Cid::new_v1(0x55, cid::multihash::Multihash::wrap(0, b"x").unwrap());
+        assert_ne!(first, DevRandomness::new(other).draw(1, 10, b"a"));
+    }
+}
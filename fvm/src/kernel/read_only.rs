@@ -0,0 +1,500 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Gas-free, read-only execution for tooling.
+//!
+//! The [`ReadOnlyKernel`] wraps another kernel (usually the
+//! [`DefaultKernel`](super::default::DefaultKernel)) to run actor "view" methods against historical
+//! state, e.g., from a block explorer:
+//!
+//! - IPLD reads, state, and other query syscalls are forwarded to the inner kernel.
+//! - Block creation and linking, sends, state root updates, actor creation/deletion fail with
+//!   `ReadOnly` (before anything is charged or written), and events are discarded. Actors can detect this through the `READ_ONLY` message context flag.
+//! - Gas isn't charged to the actor: execution and syscall charges are dropped, and the actor
+//!   always sees unlimited gas available.
+//!
+//! Operations forwarded to the inner kernel still account their gas with the call manager, so
+//! messages must be applied with [`ApplyKind::Unlimited`](crate::executor::ApplyKind::Unlimited)
+//! to avoid running out of gas. The machine must never be flushed.
+
+use fvm_shared::sys::out::vm::ContextFlags;
+
+use super::*;
+use crate::syscall_error;
+
+/// A kernel that forwards all queries to the inner kernel, but forbids state changes and doesn't
+/// charge gas. See the [module documentation](self).
+pub struct ReadOnlyKernel<K>(pub K);
+
+impl<K> ReadOnlyKernel<K> {
+    fn read_only<T>(&self, op: &str) -> Result<T> {
+        Err(syscall_error!(ReadOnly; "cannot {} while inspecting state", op).into())
+    }
+}
+
+impl<K> Kernel for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    type CallManager = K::CallManager;
+
+    fn into_inner(self) -> (Self::CallManager, BlockRegistry)
+    where
+        Self: Sized,
+    {
+        self.0.into_inner()
+    }
+
+    fn new(
+        mgr: Self::CallManager,
+        blocks: BlockRegistry,
+        caller: ActorID,
+        actor_id: ActorID,
+        method: MethodNum,
+        value_received: TokenAmount,
+    ) -> Self
+    where
+        Self: Sized,
+    {
+        ReadOnlyKernel(K::new(
+            mgr,
+            blocks,
+            caller,
+            actor_id,
+            method,
+            value_received,
+        ))
+    }
+
+    fn machine(&self) -> &<Self::CallManager as CallManager>::Machine {
+        self.0.machine()
+    }
+}
+
+impl<K> ActorOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn resolve_address(&self, address: &Address) -> Result<ActorID> {
+        self.0.resolve_address(address)
+    }
+
+    fn lookup_delegated_address(&self, actor_id: ActorID) -> Result<Option<Address>> {
+        self.0.lookup_delegated_address(actor_id)
+    }
+
+    fn get_actor_code_cid(&self, id: ActorID) -> Result<Cid> {
+        self.0.get_actor_code_cid(id)
+    }
+
+    fn next_actor_address(&self) -> Result<Address> {
+        self.0.next_actor_address()
+    }
+
+    fn create_actor(
+        &mut self,
+        _code_cid: Cid,
+        _actor_id: ActorID,
+        _delegated_address: Option<Address>,
+    ) -> Result<()> {
+        self.read_only("create actors")
+    }
+
+    #[cfg(feature = "m2-native")]
+    fn install_actor(&mut self, _code_cid: Cid) -> Result<()> {
+        self.read_only("install actors")
+    }
+
+    fn get_builtin_actor_type(&self, code_cid: &Cid) -> Result<u32> {
+        self.0.get_builtin_actor_type(code_cid)
+    }
+
+    fn get_code_cid_for_type(&self, typ: u32) -> Result<Cid> {
+        self.0.get_code_cid_for_type(typ)
+    }
+
+    fn get_code_info(&self, code_cid: &Cid) -> Result<CodeInfo> {
+        self.0.get_code_info(code_cid)
+    }
+
+    fn balance_of(&self, actor_id: ActorID) -> Result<TokenAmount> {
+        self.0.balance_of(actor_id)
+    }
+
+    fn nonce_of(&self, actor_id: ActorID) -> Result<u64> {
+        self.0.nonce_of(actor_id)
+    }
+}
+
+impl<K> IpldBlockOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn block_open(&mut self, cid: &Cid) -> Result<(BlockId, BlockStat)> {
+        self.0.block_open(cid)
+    }
+
+    fn block_create(&mut self, _codec: u64, _data: &[u8]) -> Result<BlockId> {
+        self.read_only("create blocks")
+    }
+
+    fn block_link(&mut self, _id: BlockId, _hash_fun: u64, _hash_len: u32) -> Result<Cid> {
+        self.read_only("link blocks")
+    }
+
+    fn block_read(&self, id: BlockId, offset: u32, buf: &mut [u8]) -> Result<i32> {
+        self.0.block_read(id, offset, buf)
+    }
+
+    fn block_stat(&self, id: BlockId) -> Result<BlockStat> {
+        self.0.block_stat(id)
+    }
+
+    fn block_close(&mut self, id: BlockId) -> Result<()> {
+        self.0.block_close(id)
+    }
+
+    fn block_graph_stat(&mut self, root: &Cid, max_blocks: u32) -> Result<GraphStat> {
+        self.0.block_graph_stat(root, max_blocks)
+    }
+
+    fn block_prefetch(&self, cid: &Cid) -> Result<()> {
+        self.0.block_prefetch(cid)
+    }
+}
+
+impl<K> CircSupplyOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn total_fil_circ_supply(&self) -> Result<TokenAmount> {
+        self.0.total_fil_circ_supply()
+    }
+}
+
+impl<K> CryptoOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn verify_signature(
+        &self,
+        sig_type: SignatureType,
+        signature: &[u8],
+        signer: &Address,
+        plaintext: &[u8],
+    ) -> Result<bool> {
+        self.0
+            .verify_signature(sig_type, signature, signer, plaintext)
+    }
+
+    fn recover_secp_public_key(
+        &self,
+        hash: &[u8; SECP_SIG_MESSAGE_HASH_SIZE],
+        signature: &[u8; SECP_SIG_LEN],
+    ) -> Result<[u8; SECP_PUB_LEN]> {
+        self.0.recover_secp_public_key(hash, signature)
+    }
+
+    fn hash(&self, code: u64, data: &[u8]) -> Result<MultihashGeneric<64>> {
+        self.0.hash(code, data)
+    }
+
+    fn hash_init(&self, code: u64) -> Result<HasherId> {
+        self.0.hash_init(code)
+    }
+
+    fn hash_update(&self, hasher: HasherId, data: &[u8]) -> Result<()> {
+        self.0.hash_update(hasher, data)
+    }
+
+    fn hash_finalize(&self, hasher: HasherId) -> Result<MultihashGeneric<64>> {
+        self.0.hash_finalize(hasher)
+    }
+
+    fn compute_unsealed_sector_cid(
+        &self,
+        proof_type: RegisteredSealProof,
+        pieces: &[PieceInfo],
+    ) -> Result<Cid> {
+        self.0.compute_unsealed_sector_cid(proof_type, pieces)
+    }
+
+    fn verify_seal(&self, vi: &SealVerifyInfo) -> Result<bool> {
+        self.0.verify_seal(vi)
+    }
+
+    fn verify_post(&self, verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
+        self.0.verify_post(verify_info)
+    }
+
+    fn verify_consensus_fault(
+        &self,
+        h1: &[u8],
+        h2: &[u8],
+        extra: &[u8],
+    ) -> Result<Option<ConsensusFault>> {
+        self.0.verify_consensus_fault(h1, h2, extra)
+    }
+
+    fn batch_verify_seals(&self, vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
+        self.0.batch_verify_seals(vis)
+    }
+
+    fn verify_aggregate_seals(&self, aggregate: &AggregateSealVerifyProofAndInfos) -> Result<bool> {
+        self.0.verify_aggregate_seals(aggregate)
+    }
+
+    fn verify_replica_update(&self, replica: &ReplicaUpdateInfo) -> Result<bool> {
+        self.0.verify_replica_update(replica)
+    }
+
+    fn verify_merkle_proof(&self, proof: &MerkleProof) -> Result<bool> {
+        self.0.verify_merkle_proof(proof)
+    }
+
+    fn verify_groth16(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool> {
+        self.0.verify_groth16(vk, info)
+    }
+
+    fn verify_plonk(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool> {
+        self.0.verify_plonk(vk, info)
+    }
+}
+
+impl<K> DebugOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn log(&self, msg: String) {
+        self.0.log(msg)
+    }
+
+    fn debug_enabled(&self) -> bool {
+        self.0.debug_enabled()
+    }
+
+    fn store_artifact(&self, name: &str, data: &[u8]) -> Result<()> {
+        self.0.store_artifact(name, data)
+    }
+}
+
+impl<K> EventOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn emit_event(&mut self, _evt: ActorEvent) -> Result<()> {
+        // Discarded, as in read-only sends.
+        Ok(())
+    }
+}
+
+impl<K> GasOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn gas_used(&self) -> Gas {
+        self.0.gas_used()
+    }
+
+    fn gas_available(&self) -> Gas {
+        Gas::from_milligas(i64::MAX)
+    }
+
+    fn charge_gas(&self, _name: &str, _compute: Gas) -> Result<GasTimer> {
+        Ok(GasTimer::empty())
+    }
+
+    fn price_list(&self) -> &PriceList {
+        self.0.price_list()
+    }
+
+    fn record_syscall(&self, module: &'static str, name: &'static str) {
+        self.0.record_syscall(module, name)
+    }
+
+    fn instruction_clock(&self) -> Option<&InstructionClock> {
+        self.0.instruction_clock()
+    }
+}
+
+impl<K> MessageOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn msg_context(&self) -> Result<MessageContext> {
+        let mut ctx = self.0.msg_context()?;
+        // The context is packed, so we can't take a reference to its flags to update them in place.
+        let flags = ctx.flags | ContextFlags::READ_ONLY;
+        ctx.flags = flags;
+        Ok(ctx)
+    }
+}
+
+impl<K> NetworkOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn network_context(&self) -> Result<NetworkContext> {
+        self.0.network_context()
+    }
+
+    fn tipset_cid(&self, epoch: ChainEpoch) -> Result<Cid> {
+        self.0.tipset_cid(epoch)
+    }
+
+    fn read_state(&self, query: &StateQuery) -> Result<Option<Vec<u8>>> {
+        self.0.read_state(query)
+    }
+}
+
+impl<K> RandomnessOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn get_randomness_from_tickets(
+        &self,
+        personalization: i64,
+        rand_epoch: ChainEpoch,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.0
+            .get_randomness_from_tickets(personalization, rand_epoch, entropy)
+    }
+
+    fn get_randomness_from_beacon(
+        &self,
+        personalization: i64,
+        rand_epoch: ChainEpoch,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.0
+            .get_randomness_from_beacon(personalization, rand_epoch, entropy)
+    }
+
+    fn get_dev_randomness(
+        &self,
+        personalization: i64,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.0.get_dev_randomness(personalization, entropy)
+    }
+}
+
+impl<K> ReturnOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn return_push(&mut self, data: Vec<u8>) -> Result<()> {
+        self.0.return_push(data)
+    }
+
+    fn return_pop(&mut self) -> Result<Vec<u8>> {
+        self.0.return_pop()
+    }
+
+    fn return_len(&self) -> Option<u32> {
+        self.0.return_len()
+    }
+}
+
+impl<K> SyncOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn set_flag(&mut self, key: u64, options: FlagOptions) -> Result<bool> {
+        self.0.set_flag(key, options)
+    }
+
+    fn clear_flag(&mut self, key: u64) -> Result<bool> {
+        self.0.clear_flag(key)
+    }
+}
+
+impl<K> CronOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn cron_register(&mut self, _method: MethodNum) -> Result<()> {
+        self.read_only("register cron entries")
+    }
+
+    fn cron_unregister(&mut self, _method: MethodNum) -> Result<()> {
+        self.read_only("unregister cron entries")
+    }
+}
+
+impl<K> TokenOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn token_transfer_hook(
+        &mut self,
+        _transfer: &TokenTransfer,
+        _gas_limit: Option<Gas>,
+    ) -> Result<TokenTransferResult> {
+        self.read_only("transfer tokens")
+    }
+}
+
+impl<K> SelfOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn root(&self) -> Result<Cid> {
+        self.0.root()
+    }
+
+    fn set_root(&mut self, _root: Cid) -> Result<()> {
+        self.read_only("update the state root")
+    }
+
+    fn current_balance(&self) -> Result<TokenAmount> {
+        self.0.current_balance()
+    }
+
+    fn self_destruct(&mut self, _beneficiary: &Address) -> Result<()> {
+        self.read_only("delete actors")
+    }
+
+    fn self_state_stat(&mut self) -> Result<GraphStat> {
+        self.0.self_state_stat()
+    }
+}
+
+impl<K> SendOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    fn send(
+        &mut self,
+        _recipient: &Address,
+        _method: u64,
+        _params: BlockId,
+        _value: &TokenAmount,
+        _gas_limit: Option<Gas>,
+        _flags: SendFlags,
+    ) -> Result<SendResult> {
+        self.read_only("send messages")
+    }
+
+    fn delegate_call(
+        &mut self,
+        _code: &Cid,
+        _method: u64,
+        _params: BlockId,
+        _gas_limit: Option<Gas>,
+        _flags: SendFlags,
+    ) -> Result<SendResult> {
+        self.read_only("delegate calls")
+    }
+}
+
+impl<K> LimiterOps for ReadOnlyKernel<K>
+where
+    K: Kernel,
+{
+    type Limiter = K::Limiter;
+
+    fn limiter_mut(&mut self) -> &mut Self::Limiter {
+        self.0.limiter_mut()
+    }
+}
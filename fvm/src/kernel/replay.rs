@@ -0,0 +1,742 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Actor-only re-execution from a recorded syscall tape.
+//!
+//! An [`AuditLog`](crate::audit::AuditLog) that records outcomes captures everything an actor
+//! observed from the host: each syscall's return value, error, the bytes it wrote into the actor's
+//! memory, and the gas it charged. A [`SyscallTape`] replays the syscalls made by a single call
+//! frame, and the [`ReplayKernel`] serves every syscall from the tape instead of from real
+//! state or externs. The actor's own code runs as usual, so changes to it can be bisected without
+//! replaying the chain.
+//!
+//! To replay a frame, install the tape with
+//! [`MachineContext::replay_syscalls`](crate::machine::MachineContext::replay_syscalls) and invoke
+//! the same actor code (with the same parameters) using the [`ReplayKernel`]. The state tree
+//! need only contain the invoked actor (and the sender, when applying a message). Sends are served
+//! from the tape too, so nested frames aren't re-executed.
+//!
+//! Replay stops with a fatal error as soon as the actor diverges from the tape, i.e., when it
+//! makes a syscall with different arguments than recorded, or a syscall that isn't on the tape.
+//! Syscalls that abort the invocation (e.g., `vm::exit`) aren't recorded and run as usual.
+//!
+//! Blocks created during replay hold the data the actor passed to `ipld::block_create`, so they're
+//! identical to the recorded blocks. Blocks opened or received from sends are placeholders with the
+//! recorded codec and size, but no data (reads of them are served from the tape).
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _};
+use fvm_shared::sys::out::ipld::IpldOpen;
+use fvm_shared::sys::out::send;
+use fvm_shared::sys::SyscallSafe;
+
+use super::*;
+use crate::audit::{self, AuditEntry, SyscallOutcome};
+use crate::call_manager::NO_DATA_BLOCK_ID;
+
+/// The syscalls made by a single call frame, as recorded in an audit log.
+#[derive(Debug, Clone)]
+pub struct SyscallTape {
+    entries: Arc<[AuditEntry]>,
+    next: usize,
+}
+
+impl SyscallTape {
+    /// Creates a tape replaying the syscalls made by the given call frame of the given message,
+    /// from the entries of an audit log that records outcomes.
+    pub fn new(log: &[AuditEntry], message: u64, frame: u64) -> anyhow::Result<Self> {
+        let entries: Vec<_> = log
+            .iter()
+            .filter(|e| e.message == message && e.frame == frame)
+            .cloned()
+            .collect();
+        if let Some(e) = entries.iter().find(|e| e.outcome.is_none()) {
+            return Err(anyhow!(
+                "audit entry {} doesn't record the syscall's outcome",
+                e.seq
+            ));
+        }
+        Ok(SyscallTape {
+            entries: entries.into(),
+            next: 0,
+        })
+    }
+
+    /// Returns the number of syscalls left on the tape.
+    pub fn remaining(&self) -> usize {
+        self.entries.len() - self.next
+    }
+
+    /// Returns the recorded gas and outcome of the next syscall on the tape, if it's a call to the
+    /// given syscall. Fails if it is, but was recorded with different arguments.
+    pub fn next(
+        &mut self,
+        module: &str,
+        name: &str,
+        args: &[u8],
+    ) -> anyhow::Result<Option<(Gas, SyscallOutcome)>> {
+        let entry = match self.entries.get(self.next) {
+            Some(e) if e.module == module && e.name == name => e,
+            _ => return Ok(None),
+        };
+        if entry.args_digest != audit::digest(args) {
+            return Err(anyhow!(
+                "syscall {}::{} diverged from the tape (audit entry {}): arguments differ",
+                module,
+                name,
+                entry.seq
+            ));
+        }
+        self.next += 1;
+        let outcome = entry
+            .outcome
+            .clone()
+            .context("syscall outcome not recorded")?;
+        Ok(Some((Gas::from_milligas(entry.gas), outcome)))
+    }
+}
+
+/// Reads a recorded syscall return value.
+fn read_value<T: SyscallSafe>(value: &[u8]) -> Option<T> {
+    (value.len() == std::mem::size_of::<T>())
+        // SAFETY: syscall-safe types can be initialized from any bytes of the right length.
+        .then(|| unsafe { std::ptr::read_unaligned(value.as_ptr() as *const T) })
+}
+
+/// Reads the `N` bytes of a recorded syscall argument at the given offset into the (native-endian)
+/// arguments.
+fn read_arg<const N: usize>(args: &[u8], offset: usize) -> anyhow::Result<[u8; N]> {
+    args.get(offset..offset + N)
+        .and_then(|arg| arg.try_into().ok())
+        .context("malformed syscall arguments")
+}
+
+/// A kernel that serves all syscalls from the [`SyscallTape`] installed in the machine context.
+/// Any kernel operation reached during replay means the actor diverged from the tape, so all
+/// operations (other than gas accounting and debug logging) fail with a fatal error.
+pub struct ReplayKernel<C> {
+    call_manager: C,
+    blocks: BlockRegistry,
+    tape: SyscallTape,
+}
+
+impl<C> ReplayKernel<C> {
+    /// Returns the recorded gas and outcome of the given syscall, if it's next on the tape (see
+    /// [`SyscallTape::next`]), given its (native-endian) arguments and the actor's memory.
+    ///
+    /// Blocks created, opened, received, or closed by a successful syscall are registered with (or
+    /// closed in) this kernel's block registry, so that they keep their recorded IDs.
+    pub fn replay(
+        &mut self,
+        module: &str,
+        name: &str,
+        args: &[u8],
+        memory: &[u8],
+    ) -> anyhow::Result<Option<(Gas, SyscallOutcome)>> {
+        let (gas, outcome) = match self.tape.next(module, name, args)? {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+        if outcome.error != 0 {
+            return Ok(Some((gas, outcome)));
+        }
+        match (module, name) {
+            ("ipld", "block_create") => {
+                let codec = u64::from_ne_bytes(read_arg(args, 0)?);
+                let offset = u32::from_ne_bytes(read_arg(args, 8)?) as usize;
+                let len = u32::from_ne_bytes(read_arg(args, 12)?) as usize;
+                let data = memory
+                    .get(offset..offset + len)
+                    .context("replayed block data is out of bounds")?;
+                let id = read_value::<BlockId>(&outcome.value)
+                    .context("malformed replayed block_create return value")?;
+                self.register(id, Block::new(codec, data))?;
+            }
+            ("ipld", "block_open") => {
+                let open = read_value::<IpldOpen>(&outcome.value)
+                    .context("malformed replayed block_open return value")?;
+                let placeholder = Block::new(open.codec, vec![0; open.size as usize]);
+                self.register(open.id, placeholder)?;
+            }
+            ("send", "send") | ("send", "delegate_call") => {
+                let send = read_value::<send::Send>(&outcome.value)
+                    .context("malformed replayed send return value")?;
+                if send.return_id != NO_DATA_BLOCK_ID {
+                    let placeholder =
+                        Block::new(send.return_codec, vec![0; send.return_size as usize]);
+                    self.register(send.return_id, placeholder)?;
+                }
+            }
+            ("ipld", "block_close") => {
+                self.blocks.close(u32::from_ne_bytes(read_arg(args, 0)?))?;
+            }
+            _ => {}
+        }
+        Ok(Some((gas, outcome)))
+    }
+
+    /// Registers a replayed block, checking that it gets the recorded ID.
+    fn register(&mut self, recorded: BlockId, block: Block) -> anyhow::Result<()> {
+        let id = self.blocks.put(block)?;
+        if id != recorded {
+            return Err(anyhow!(
+                "replayed block got ID {}, but was recorded with ID {}",
+                id,
+                recorded
+            ));
+        }
+        Ok(())
+    }
+
+    fn diverged<T>(&self, op: &str) -> Result<T> {
+        Err(ExecutionError::Fatal(anyhow!(
+            "actor diverged from the syscall tape: unexpected {} ({} syscalls left)",
+            op,
+            self.tape.remaining()
+        )))
+    }
+}
+
+impl<C> Kernel for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    type CallManager = C;
+
+    fn into_inner(self) -> (Self::CallManager, BlockRegistry)
+    where
+        Self: Sized,
+    {
+        (self.call_manager, self.blocks)
+    }
+
+    fn new(
+        mgr: Self::CallManager,
+        blocks: BlockRegistry,
+        _caller: ActorID,
+        _actor_id: ActorID,
+        _method: MethodNum,
+        _value_received: TokenAmount,
+    ) -> Self
+    where
+        Self: Sized,
+    {
+        let tape = mgr
+            .context()
+            .syscall_tape
+            .clone()
+            .unwrap_or_else(|| SyscallTape {
+                entries: Arc::new([]),
+                next: 0,
+            });
+
+        ReplayKernel {
+            call_manager: mgr,
+            blocks,
+            tape,
+        }
+    }
+
+    fn machine(&self) -> &<Self::CallManager as CallManager>::Machine {
+        self.call_manager.machine()
+    }
+}
+
+impl<C> ActorOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn resolve_address(&self, _address: &Address) -> Result<ActorID> {
+        self.diverged("address resolution")
+    }
+
+    fn lookup_delegated_address(&self, _actor_id: ActorID) -> Result<Option<Address>> {
+        self.diverged("delegated address lookup")
+    }
+
+    fn get_actor_code_cid(&self, _id: ActorID) -> Result<Cid> {
+        self.diverged("actor code lookup")
+    }
+
+    fn next_actor_address(&self) -> Result<Address> {
+        self.diverged("actor address generation")
+    }
+
+    fn create_actor(
+        &mut self,
+        _code_cid: Cid,
+        _actor_id: ActorID,
+        _delegated_address: Option<Address>,
+    ) -> Result<()> {
+        self.diverged("actor creation")
+    }
+
+    #[cfg(feature = "m2-native")]
+    fn install_actor(&mut self, _code_cid: Cid) -> Result<()> {
+        self.diverged("actor installation")
+    }
+
+    fn get_builtin_actor_type(&self, _code_cid: &Cid) -> Result<u32> {
+        self.diverged("builtin actor type lookup")
+    }
+
+    fn get_code_cid_for_type(&self, _typ: u32) -> Result<Cid> {
+        self.diverged("builtin actor code lookup")
+    }
+
+    fn get_code_info(&self, _code_cid: &Cid) -> Result<CodeInfo> {
+        self.diverged("code info lookup")
+    }
+
+    fn balance_of(&self, _actor_id: ActorID) -> Result<TokenAmount> {
+        self.diverged("balance lookup")
+    }
+
+    fn nonce_of(&self, _actor_id: ActorID) -> Result<u64> {
+        self.diverged("nonce lookup")
+    }
+}
+
+impl<C> IpldBlockOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn block_open(&mut self, _cid: &Cid) -> Result<(BlockId, BlockStat)> {
+        self.diverged("block open")
+    }
+
+    fn block_create(&mut self, _codec: u64, _data: &[u8]) -> Result<BlockId> {
+        self.diverged("block creation")
+    }
+
+    fn block_link(&mut self, _id: BlockId, _hash_fun: u64, _hash_len: u32) -> Result<Cid> {
+        self.diverged("block link")
+    }
+
+    fn block_read(&self, _id: BlockId, _offset: u32, _buf: &mut [u8]) -> Result<i32> {
+        self.diverged("block read")
+    }
+
+    fn block_stat(&self, _id: BlockId) -> Result<BlockStat> {
+        self.diverged("block stat")
+    }
+
+    fn block_close(&mut self, _id: BlockId) -> Result<()> {
+        self.diverged("block close")
+    }
+
+    fn block_graph_stat(&mut self, _root: &Cid, _max_blocks: u32) -> Result<GraphStat> {
+        self.diverged("block graph stat")
+    }
+
+    fn block_prefetch(&self, _cid: &Cid) -> Result<()> {
+        self.diverged("block prefetch")
+    }
+}
+
+impl<C> CircSupplyOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn total_fil_circ_supply(&self) -> Result<TokenAmount> {
+        self.diverged("circulating supply lookup")
+    }
+}
+
+impl<C> CryptoOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn verify_signature(
+        &self,
+        _sig_type: SignatureType,
+        _signature: &[u8],
+        _signer: &Address,
+        _plaintext: &[u8],
+    ) -> Result<bool> {
+        self.diverged("signature verification")
+    }
+
+    fn recover_secp_public_key(
+        &self,
+        _hash: &[u8; SECP_SIG_MESSAGE_HASH_SIZE],
+        _signature: &[u8; SECP_SIG_LEN],
+    ) -> Result<[u8; SECP_PUB_LEN]> {
+        self.diverged("public key recovery")
+    }
+
+    fn hash(&self, _code: u64, _data: &[u8]) -> Result<MultihashGeneric<64>> {
+        self.diverged("hash")
+    }
+
+    fn hash_init(&self, _code: u64) -> Result<HasherId> {
+        self.diverged("hasher init")
+    }
+
+    fn hash_update(&self, _hasher: HasherId, _data: &[u8]) -> Result<()> {
+        self.diverged("hasher update")
+    }
+
+    fn hash_finalize(&self, _hasher: HasherId) -> Result<MultihashGeneric<64>> {
+        self.diverged("hasher finalize")
+    }
+
+    fn compute_unsealed_sector_cid(
+        &self,
+        _proof_type: RegisteredSealProof,
+        _pieces: &[PieceInfo],
+    ) -> Result<Cid> {
+        self.diverged("unsealed sector CID computation")
+    }
+
+    fn verify_seal(&self, _vi: &SealVerifyInfo) -> Result<bool> {
+        self.diverged("seal verification")
+    }
+
+    fn verify_post(&self, _verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
+        self.diverged("PoSt verification")
+    }
+
+    fn verify_consensus_fault(
+        &self,
+        _h1: &[u8],
+        _h2: &[u8],
+        _extra: &[u8],
+    ) -> Result<Option<ConsensusFault>> {
+        self.diverged("consensus fault verification")
+    }
+
+    fn batch_verify_seals(&self, _vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
+        self.diverged("batch seal verification")
+    }
+
+    fn verify_aggregate_seals(
+        &self,
+        _aggregate: &AggregateSealVerifyProofAndInfos,
+    ) -> Result<bool> {
+        self.diverged("aggregate seal verification")
+    }
+
+    fn verify_replica_update(&self, _replica: &ReplicaUpdateInfo) -> Result<bool> {
+        self.diverged("replica update verification")
+    }
+
+    fn verify_merkle_proof(&self, _proof: &MerkleProof) -> Result<bool> {
+        self.diverged("merkle proof verification")
+    }
+
+    fn verify_groth16(&self, _vk: BlockId, _info: &SnarkVerifyInfo) -> Result<bool> {
+        self.diverged("groth16 verification")
+    }
+
+    fn verify_plonk(&self, _vk: BlockId, _info: &SnarkVerifyInfo) -> Result<bool> {
+        self.diverged("plonk verification")
+    }
+}
+
+impl<C> DebugOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn log(&self, msg: String) {
+        log::info!("{}", msg)
+    }
+
+    fn debug_enabled(&self) -> bool {
+        self.call_manager.context().actor_debugging
+    }
+
+    fn store_artifact(&self, _name: &str, _data: &[u8]) -> Result<()> {
+        self.diverged("artifact storage")
+    }
+}
+
+impl<C> EventOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn emit_event(&mut self, _evt: ActorEvent) -> Result<()> {
+        self.diverged("event emission")
+    }
+}
+
+impl<C> GasOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn gas_used(&self) -> Gas {
+        self.call_manager.gas_tracker().gas_used()
+    }
+
+    fn gas_available(&self) -> Gas {
+        self.call_manager.gas_tracker().gas_available()
+    }
+
+    fn charge_gas(&self, name: &str, compute: Gas) -> Result<GasTimer> {
+        self.call_manager.gas_tracker().charge_gas(name, compute)
+    }
+
+    fn price_list(&self) -> &PriceList {
+        self.call_manager.price_list()
+    }
+
+    fn record_syscall(&self, module: &'static str, name: &'static str) {
+        self.call_manager.gas_tracker().record_syscall(module, name)
+    }
+
+    fn instruction_clock(&self) -> Option<&InstructionClock> {
+        self.call_manager.gas_tracker().instruction_clock()
+    }
+}
+
+impl<C> MessageOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn msg_context(&self) -> Result<MessageContext> {
+        self.diverged("message context lookup")
+    }
+}
+
+impl<C> NetworkOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn network_context(&self) -> Result<NetworkContext> {
+        self.diverged("network context lookup")
+    }
+
+    fn tipset_cid(&self, _epoch: ChainEpoch) -> Result<Cid> {
+        self.diverged("tipset CID lookup")
+    }
+
+    fn read_state(&self, _query: &StateQuery) -> Result<Option<Vec<u8>>> {
+        self.diverged("domain state lookup")
+    }
+}
+
+impl<C> RandomnessOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn get_randomness_from_tickets(
+        &self,
+        _personalization: i64,
+        _rand_epoch: ChainEpoch,
+        _entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.diverged("randomness lookup")
+    }
+
+    fn get_randomness_from_beacon(
+        &self,
+        _personalization: i64,
+        _rand_epoch: ChainEpoch,
+        _entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.diverged("randomness lookup")
+    }
+
+    fn get_dev_randomness(
+        &self,
+        _personalization: i64,
+        _entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.diverged("randomness lookup")
+    }
+}
+
+impl<C> ReturnOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn return_push(&mut self, _data: Vec<u8>) -> Result<()> {
+        self.diverged("return queue push")
+    }
+
+    fn return_pop(&mut self) -> Result<Vec<u8>> {
+        self.diverged("return queue pop")
+    }
+
+    fn return_len(&self) -> Option<u32> {
+        None
+    }
+}
+
+impl<C> SyncOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn set_flag(&mut self, _key: u64, _options: FlagOptions) -> Result<bool> {
+        self.diverged("flag set")
+    }
+
+    fn clear_flag(&mut self, _key: u64) -> Result<bool> {
+        self.diverged("flag clear")
+    }
+}
+
+impl<C> CronOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn cron_register(&mut self, _method: MethodNum) -> Result<()> {
+        self.diverged("cron registration")
+    }
+
+    fn cron_unregister(&mut self, _method: MethodNum) -> Result<()> {
+        self.diverged("cron unregistration")
+    }
+}
+
+impl<C> TokenOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn token_transfer_hook(
+        &mut self,
+        _transfer: &TokenTransfer,
+        _gas_limit: Option<Gas>,
+    ) -> Result<TokenTransferResult> {
+        self.diverged("token transfer hook")
+    }
+}
+
+impl<C> SelfOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn root(&self) -> Result<Cid> {
+        self.diverged("state root lookup")
+    }
+
+    fn set_root(&mut self, _root: Cid) -> Result<()> {
+        self.diverged("state root update")
+    }
+
+    fn current_balance(&self) -> Result<TokenAmount> {
+        self.diverged("balance lookup")
+    }
+
+    fn self_destruct(&mut self, _beneficiary: &Address) -> Result<()> {
+        self.diverged("self destruct")
+    }
+
+    fn self_state_stat(&mut self) -> Result<GraphStat> {
+        self.diverged("state statistics")
+    }
+}
+
+impl<C> SendOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    fn send(
+        &mut self,
+        _recipient: &Address,
+        _method: u64,
+        _params: BlockId,
+        _value: &TokenAmount,
+        _gas_limit: Option<Gas>,
+        _flags: SendFlags,
+    ) -> Result<SendResult> {
+        self.diverged("send")
+    }
+
+    fn delegate_call(
+        &mut self,
+        _code: &Cid,
+        _method: u64,
+        _params: BlockId,
+        _gas_limit: Option<Gas>,
+        _flags: SendFlags,
+    ) -> Result<SendResult> {
+        self.diverged("delegate call")
+    }
+}
+
+impl<C> LimiterOps for ReplayKernel<C>
+where
+    C: CallManager,
+{
+    type Limiter = <<C as CallManager>::Machine as Machine>::Limiter;
+
+    fn limiter_mut(&mut self) -> &mut Self::Limiter {
+        self.call_manager.limiter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use num_traits::Zero;
+
+    use super::SyscallTape;
+    use crate::audit::{read_entries, AuditLog, SyscallOutcome};
+    use crate::gas::Gas;
+
+    #[derive(Clone, Default)]
+    struct Buf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tape() {
+        let buf = Buf::default();
+        let log = AuditLog::new(buf.clone()).record_outcomes();
+        log.begin_message();
+        log.enter_frame(1, 100);
+        log.append(
+            "vm",
+            "message_context",
+            &[],
+            Gas::new(10),
+            SyscallOutcome::new(0, &[1, 2], &[], &[]),
+        )
+        .unwrap();
+        log.enter_frame(2, 101);
+        log.append("ipld", "block_open", &[1], Gas::zero(), Default::default())
+            .unwrap();
+        log.exit_frame();
+        log.append("ipld", "block_open", &[2], Gas::new(5), Default::default())
+            .unwrap();
+        log.exit_frame();
+        let entries = read_entries(&buf.0.lock().unwrap()[..]).unwrap();
+
+        let mut tape = SyscallTape::new(&entries, 0, 1).unwrap();
+        assert_eq!(tape.remaining(), 2);
+
+        // Syscalls that aren't next on the tape aren't served.
+        assert!(tape.next("ipld", "block_open", &[2]).unwrap().is_none());
+
+        let (gas, outcome) = tape.next("vm", "message_context", &[]).unwrap().unwrap();
+        assert_eq!(gas, Gas::new(10));
+        assert_eq!(outcome.value, [1, 2]);
+
+        // The nested frame's syscalls aren't on the tape, and arguments must match.
+        assert!(tape.next("ipld", "block_open", &[1]).is_err());
+        let (gas, _) = tape.next("ipld", "block_open", &[2]).unwrap().unwrap();
+        assert_eq!(gas, Gas::new(5));
+        assert_eq!(tape.remaining(), 0);
+
+        // Tapes need recorded outcomes.
+        let buf = Buf::default();
+        let log = AuditLog::new(buf.clone());
+        log.begin_message();
+        log.enter_frame(1, 100);
+        log.append("vm", "exit", &[], Gas::zero(), Default::default())
+            .unwrap();
+        let entries = read_entries(&buf.0.lock().unwrap()[..]).unwrap();
+        assert!(SyscallTape::new(&entries, 0, 1).is_err());
+    }
+}
@@ -20,6 +20,9 @@ pub mod machine;
 pub mod syscalls;
 
 pub mod gas;
+pub mod migration;
+pub mod state_check;
+pub mod state_proof;
 pub mod state_tree;
 
 mod blockstore;
@@ -27,6 +30,8 @@ mod blockstore;
 #[cfg(not(feature = "testing"))]
 mod account_actor;
 #[cfg(not(feature = "testing"))]
+mod cron_actor;
+#[cfg(not(feature = "testing"))]
 mod init_actor;
 #[cfg(not(feature = "testing"))]
 mod system_actor;
@@ -34,13 +39,24 @@ mod system_actor;
 #[cfg(feature = "testing")]
 pub mod account_actor;
 #[cfg(feature = "testing")]
+pub mod cron_actor;
+#[cfg(feature = "testing")]
 pub mod init_actor;
 #[cfg(feature = "testing")]
 pub mod system_actor;
 
 mod eam_actor;
+pub mod native;
 pub mod trace;
 
+pub mod audit;
+pub mod block_packer;
+pub mod cancel;
+pub mod determinism;
+
+#[cfg(feature = "fault-injection")]
+pub mod fault;
+
 use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
 use fvm_ipld_encoding::{to_vec, DAG_CBOR};
@@ -59,13 +75,16 @@ mod test {
     use fvm_ipld_blockstore::MemoryBlockstore;
     use fvm_ipld_encoding::{CborStore, DAG_CBOR};
     use fvm_shared::state::StateTreeVersion;
+    use fvm_shared::version::NetworkVersion;
     use fvm_shared::IDENTITY_HASH;
     use multihash::{Code, Multihash};
 
     use crate::call_manager::DefaultCallManager;
     use crate::engine::EnginePool;
-    use crate::externs::{Chain, Consensus, Externs, Rand};
-    use crate::machine::{DefaultMachine, Manifest, NetworkConfig};
+    use crate::externs::{Chain, Consensus, Externs, Rand, StateReader};
+    use crate::machine::{
+        DefaultMachine, Machine, MachineBuildError, MachineBuilder, Manifest, NetworkConfig,
+    };
     use crate::state_tree::StateTree;
     use crate::{executor, DefaultKernel};
 
@@ -117,6 +136,8 @@ mod test {
         }
     }
 
+    impl StateReader for DummyExterns {}
+
     #[test]
     fn test_constructor() {
         let mut bs = MemoryBlockstore::default();
@@ -143,4 +164,38 @@ mod test {
             Box::new(machine),
         );
     }
+
+    #[test]
+    fn test_builder() {
+        let mut bs = MemoryBlockstore::default();
+        let mut st = StateTree::new(bs, StateTreeVersion::V5).unwrap();
+        let root = st.flush().unwrap();
+        bs = st.into_store();
+
+        let builder = MachineBuilder::new(NetworkVersion::V18, Cid::default()).unwrap();
+        let err = builder.build(bs.clone(), DummyExterns).err().unwrap();
+        assert!(matches!(err, MachineBuildError::MissingStateRoot(_)));
+
+        // The state tree has no system actor, so an actor bundle must be supplied.
+        let mut builder = MachineBuilder::new(NetworkVersion::V18, root).unwrap();
+        let err = builder.build(bs.clone(), DummyExterns).err().unwrap();
+        assert!(matches!(err, MachineBuildError::InvalidActorBundle(_)));
+
+        // An unsupported manifest version.
+        let manifest_cid = bs
+            .put_cbor(&Manifest::DUMMY_CODES, Code::Blake2b256)
+            .unwrap();
+        let actors_cid = bs.put_cbor(&(2, manifest_cid), Code::Blake2b256).unwrap();
+        builder.override_actors(actors_cid);
+        let err = builder.build(bs.clone(), DummyExterns).err().unwrap();
+        assert!(matches!(err, MachineBuildError::InvalidActorBundle(_)));
+
+        let actors_cid = bs.put_cbor(&(1, manifest_cid), Code::Blake2b256).unwrap();
+        let machine = builder
+            .override_actors(actors_cid)
+            .epoch(10)
+            .build(bs, DummyExterns)
+            .unwrap();
+        assert_eq!(machine.context().epoch, 10);
+    }
 }
@@ -6,7 +6,7 @@ use fvm_shared::econ::TokenAmount;
 use fvm_shared::event::StampedEvent;
 use fvm_shared::ActorID;
 
-use super::{Machine, MachineContext, Manifest};
+use super::{HeadCache, Machine, MachineContext, Manifest, Prefetcher};
 use crate::kernel::Result;
 use crate::state_tree::{ActorState, StateTree};
 
@@ -27,6 +27,11 @@ impl<M: Machine> Machine for Box<M> {
         (**self).context()
     }
 
+    #[inline(always)]
+    fn context_mut(&mut self) -> &mut Type {
+        (**self).context_mut()
+    }
+
     #[inline(always)]
     fn externs(&self) -> &Self::Externs {
         (**self).externs()
@@ -62,11 +67,21 @@ impl<M: Machine> Machine for Box<M> {
         (**self).flush()
     }
 
+    #[inline(always)]
+    fn join_flushes(&mut self) -> Result<()> {
+        (**self).join_flushes()
+    }
+
     #[inline(always)]
     fn into_store(self) -> Self::Blockstore {
         (*self).into_store()
     }
 
+    #[inline(always)]
+    fn reset(&mut self) -> Result<()> {
+        (**self).reset()
+    }
+
     #[inline(always)]
     fn machine_id(&self) -> &str {
         (**self).machine_id()
@@ -81,4 +96,14 @@ impl<M: Machine> Machine for Box<M> {
     fn commit_events(&self, events: &[StampedEvent]) -> Result<Option<Cid>> {
         (**self).commit_events(events)
     }
+
+    #[inline(always)]
+    fn head_cache(&self) -> Option<&HeadCache> {
+        (**self).head_cache()
+    }
+
+    #[inline(always)]
+    fn prefetcher(&self) -> Option<&Prefetcher> {
+        (**self).prefetcher()
+    }
 }
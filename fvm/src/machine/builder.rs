@@ -0,0 +1,333 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::chainid::ChainID;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::version::NetworkVersion;
+use thiserror::Error;
+
+use super::default::{load_manifest, load_state_tree, SUPPORTED_VERSIONS};
+use super::{DefaultMachine, MachineContext, NetworkConfig};
+use crate::externs::{Externs, PinnedExterns};
+use crate::gas::price_list_by_network_version;
+
+/// The size of a Wasm page, in bytes.
+const WASM_PAGE_SIZE: u64 = 64 << 10;
+
+/// Builds a [`DefaultMachine`], validating the configuration before loading any state.
+///
+/// The builder starts from the defaults for the network version (see [`NetworkConfig::new`] and
+/// [`NetworkConfig::for_epoch`]), which can be adjusted with the builder's methods or directly
+/// through [`MachineBuilder::configure_network`] and [`MachineBuilder::configure_context`].
+///
+/// ```ignore
+/// let machine = MachineBuilder::new(NetworkVersion::V18, state_root)?
+///     .epoch(epoch)
+///     .base_fee(base_fee)
+///     .build(blockstore, externs)?;
+/// ```
+#[derive(Clone)]
+pub struct MachineBuilder {
+    context: MachineContext,
+}
+
+/// The context of a historical tipset, to pin a machine at with [`MachineBuilder::at_tipset`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TipsetContext {
+    /// The tipset's epoch.
+    pub epoch: ChainEpoch,
+    /// The state root the tipset's messages were executed on (its parent state root).
+    pub state_root: Cid,
+    /// The base fee in effect in the tipset.
+    pub base_fee: TokenAmount,
+    /// The tipset's UNIX timestamp, in seconds.
+    pub timestamp: u64,
+}
+
+/// An error encountered while building a machine.
+#[derive(Error, Debug)]
+pub enum MachineBuildError {
+    #[error("unsupported network version {0}")]
+    UnsupportedNetworkVersion(NetworkVersion),
+    #[error("the price list doesn't match network version {0}")]
+    PriceListMismatch(NetworkVersion),
+    #[error("invalid machine configuration: {0}")]
+    InvalidConfig(&'static str),
+    #[error("blockstore doesn't have the initial state-root {0}")]
+    MissingStateRoot(Cid),
+    #[error("failed to load the state tree: {0:#}")]
+    InvalidStateTree(anyhow::Error),
+    #[error("invalid built-in actor bundle: {0:#}")]
+    InvalidActorBundle(anyhow::Error),
+}
+
+impl MachineBuilder {
+    /// Starts building a machine for the given network version, on top of the given state root.
+    pub fn new(
+        network_version: NetworkVersion,
+        state_root: Cid,
+    ) -> Result<Self, MachineBuildError> {
+        // Check this first: the network config defaults are only defined for supported versions.
+        if !SUPPORTED_VERSIONS.contains(&network_version) {
+            return Err(MachineBuildError::UnsupportedNetworkVersion(
+                network_version,
+            ));
+        }
+        Ok(MachineBuilder {
+            context: NetworkConfig::new(network_version).for_epoch(0, 0, state_root),
+        })
+    }
+
+    /// Starts building a machine pinned at a historical tipset, e.g., to execute a view method "at
+    /// height H" on behalf of a node RPC. The network version must be the one in effect at the
+    /// tipset's epoch.
+    ///
+    /// Build the machine with [`MachineBuilder::build_pinned`] to keep actors from observing any
+    /// chain state past the tipset. The machine's writes are buffered, and only reach the
+    /// blockstore if it's explicitly flushed, so the live chain state is never touched.
+    pub fn at_tipset(
+        network_version: NetworkVersion,
+        tipset: &TipsetContext,
+    ) -> Result<Self, MachineBuildError> {
+        let mut builder = Self::new(network_version, tipset.state_root)?;
+        builder
+            .epoch(tipset.epoch)
+            .timestamp(tipset.timestamp)
+            .base_fee(tipset.base_fee.clone());
+        Ok(builder)
+    }
+
+    /// Set the epoch. [`MachineContext::epoch`].
+    pub fn epoch(&mut self, epoch: ChainEpoch) -> &mut Self {
+        self.context.epoch = epoch;
+        self
+    }
+
+    /// Set the timestamp. [`MachineContext::timestamp`].
+    pub fn timestamp(&mut self, timestamp: u64) -> &mut Self {
+        self.context.timestamp = timestamp;
+        self
+    }
+
+    /// Set the base fee. [`MachineContext::base_fee`].
+    pub fn base_fee(&mut self, amt: TokenAmount) -> &mut Self {
+        self.context.set_base_fee(amt);
+        self
+    }
+
+    /// Set the circulating supply. [`MachineContext::circ_supply`].
+    pub fn circulating_supply(&mut self, amt: TokenAmount) -> &mut Self {
+        self.context.set_circulating_supply(amt);
+        self
+    }
+
+    /// Set the chain ID. [`NetworkConfig::chain_id`].
+    pub fn chain_id(&mut self, id: ChainID) -> &mut Self {
+        self.context.network.chain_id(id);
+        self
+    }
+
+    /// Use the given built-in actors manifest. [`NetworkConfig::builtin_actors_override`].
+    pub fn override_actors(&mut self, manifest: Cid) -> &mut Self {
+        self.context.network.override_actors(manifest);
+        self
+    }
+
+    /// Adjust the network config.
+    pub fn configure_network(&mut self, f: impl FnOnce(&mut NetworkConfig)) -> &mut Self {
+        f(&mut self.context.network);
+        self
+    }
+
+    /// Adjust the machine context.
+    pub fn configure_context(&mut self, f: impl FnOnce(&mut MachineContext)) -> &mut Self {
+        f(&mut self.context);
+        self
+    }
+
+    /// Returns the machine context the machine will be built with.
+    pub fn context(&self) -> &MachineContext {
+        &self.context
+    }
+
+    /// Checks that the configuration is consistent, without loading anything.
+    pub fn validate(&self) -> Result<(), MachineBuildError> {
+        use MachineBuildError::*;
+
+        let nc = &self.context.network;
+        let nv = nc.network_version;
+        if !SUPPORTED_VERSIONS.contains(&nv) {
+            return Err(UnsupportedNetworkVersion(nv));
+        }
+        if !std::ptr::eq(nc.price_list, price_list_by_network_version(nv)) {
+            return Err(PriceListMismatch(nv));
+        }
+        if nc.max_call_depth == 0 {
+            return Err(InvalidConfig("the maximum call depth must be positive"));
+        }
+        if nc.max_inst_memory_bytes % WASM_PAGE_SIZE != 0 {
+            return Err(InvalidConfig(
+                "the instance memory limit must be a multiple of the Wasm page size",
+            ));
+        }
+        if nc.max_inst_memory_bytes > nc.max_memory_bytes {
+            return Err(InvalidConfig(
+                "the instance memory limit exceeds the total memory limit",
+            ));
+        }
+        if nc.block_size_soft_limit > nc.max_block_size {
+            return Err(InvalidConfig(
+                "the soft block size limit exceeds the maximum block size",
+            ));
+        }
+        if nc.return_size_soft_limit > nc.max_return_size {
+            return Err(InvalidConfig(
+                "the soft return size limit exceeds the maximum return size",
+            ));
+        }
+        if nc.max_block_handles == 0 {
+            return Err(InvalidConfig("the block handle limit must be positive"));
+        }
+        if nc.account_abstraction && nc.validation_gas_limit <= 0 {
+            return Err(InvalidConfig("the validation gas limit must be positive"));
+        }
+        if self.context.trace_commitment && !self.context.tracing {
+            return Err(InvalidConfig("trace commitments require tracing"));
+        }
+        Ok(())
+    }
+
+    /// Validates the configuration, then loads the machine's state tree and actor bundle.
+    pub fn build<B, E>(
+        &self,
+        blockstore: B,
+        externs: E,
+    ) -> Result<DefaultMachine<B, E>, MachineBuildError>
+    where
+        B: Blockstore + 'static,
+        E: Externs + 'static,
+    {
+        self.validate()?;
+
+        let root = self.context.initial_state_root;
+        match blockstore.has(&root) {
+            Ok(true) => {}
+            Ok(false) => return Err(MachineBuildError::MissingStateRoot(root)),
+            Err(e) => return Err(MachineBuildError::InvalidStateTree(e)),
+        }
+        let state_tree = load_state_tree(&self.context, blockstore)
+            .map_err(MachineBuildError::InvalidStateTree)?;
+        let builtin_actors = load_manifest(&self.context, &state_tree)
+            .map_err(MachineBuildError::InvalidActorBundle)?;
+
+        Ok(DefaultMachine::from_parts(
+            &self.context,
+            externs,
+            state_tree,
+            builtin_actors,
+        ))
+    }
+
+    /// Like [`MachineBuilder::build`], but pins the externs at the machine's epoch (see
+    /// [`PinnedExterns`]). `externs` must resolve lookups on the chain ending at that epoch.
+    pub fn build_pinned<B, E>(
+        &self,
+        blockstore: B,
+        externs: E,
+    ) -> Result<DefaultMachine<B, PinnedExterns<E>>, MachineBuildError>
+    where
+        B: Blockstore + 'static,
+        E: Externs + 'static,
+    {
+        self.build(blockstore, PinnedExterns::new(externs, self.context.epoch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> MachineBuilder {
+        MachineBuilder::new(NetworkVersion::V18, Cid::default()).unwrap()
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(matches!(
+            MachineBuilder::new(NetworkVersion::V17, Cid::default()),
+            Err(MachineBuildError::UnsupportedNetworkVersion(_))
+        ));
+    }
+
+    #[test]
+    fn at_tipset() {
+        let tipset = TipsetContext {
+            epoch: 1234,
+            state_root: Cid::default(),
+            base_fee: TokenAmount::from_atto(100),
+            timestamp: 5678,
+        };
+        let b = MachineBuilder::at_tipset(NetworkVersion::V18, &tipset).unwrap();
+        let mc = b.context();
+        assert_eq!(mc.epoch, tipset.epoch);
+        assert_eq!(mc.initial_state_root, tipset.state_root);
+        assert_eq!(mc.base_fee, tipset.base_fee);
+        assert_eq!(mc.timestamp, tipset.timestamp);
+        b.validate().unwrap();
+    }
+
+    #[test]
+    fn validate() {
+        builder().validate().unwrap();
+
+        let mut b = builder();
+        b.configure_network(|nc| nc.network_version = NetworkVersion::V17);
+        assert!(matches!(
+            b.validate(),
+            Err(MachineBuildError::UnsupportedNetworkVersion(_))
+        ));
+
+        let mut b = builder();
+        b.configure_network(|nc| nc.max_inst_memory_bytes = 1000);
+        assert!(matches!(
+            b.validate(),
+            Err(MachineBuildError::InvalidConfig(_))
+        ));
+
+        let mut b = builder();
+        b.configure_network(|nc| {
+            nc.block_size_limits(2, 1);
+        });
+        assert!(matches!(
+            b.validate(),
+            Err(MachineBuildError::InvalidConfig(_))
+        ));
+
+        let mut b = builder();
+        b.configure_network(|nc| {
+            nc.return_size_limits(2, 1);
+        });
+        assert!(matches!(
+            b.validate(),
+            Err(MachineBuildError::InvalidConfig(_))
+        ));
+
+        let mut b = builder();
+        b.configure_network(|nc| {
+            nc.enable_account_abstraction(0);
+        });
+        assert!(matches!(
+            b.validate(),
+            Err(MachineBuildError::InvalidConfig(_))
+        ));
+
+        let mut b = builder();
+        b.configure_context(|mc| mc.trace_commitment = true);
+        assert!(matches!(
+            b.validate(),
+            Err(MachineBuildError::InvalidConfig(_))
+        ));
+    }
+}
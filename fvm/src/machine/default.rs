@@ -15,8 +15,8 @@ use fvm_shared::version::NetworkVersion;
 use fvm_shared::ActorID;
 use log::debug;
 
-use super::{Machine, MachineContext};
-use crate::blockstore::BufferedBlockstore;
+use super::{HeadCache, Machine, MachineContext};
+use crate::blockstore::{BackgroundWriter, BufferedBlockstore, Prefetcher};
 use crate::externs::Externs;
 #[cfg(feature = "m2-native")]
 use crate::init_actor::State as InitActorState;
@@ -27,6 +27,7 @@ use crate::state_tree::{ActorState, StateTree};
 use crate::syscall_error;
 use crate::system_actor::State as SystemActorState;
 
+/// The bit width of the AMTs committing to the events emitted by each message.
 pub const EVENTS_AMT_BITWIDTH: u32 = 5;
 
 pub struct DefaultMachine<B, E> {
@@ -45,6 +46,12 @@ pub struct DefaultMachine<B, E> {
     /// Somewhat unique ID of the machine consisting of (epoch, randomness)
     /// randomness is generated with `initial_state_root`
     id: String,
+    /// Actor head blocks, cached across messages.
+    head_cache: HeadCache,
+    /// Writes flushed blocks in the background, if flushes are deferred.
+    background_writer: Option<BackgroundWriter>,
+    /// Loads blocks in the background at the request of actors, if prefetching is enabled.
+    prefetcher: Option<Prefetcher>,
 }
 
 impl<B, E> DefaultMachine<B, E>
@@ -60,10 +67,10 @@ where
     ///    version, etc.).
     /// * `blockstore`: The underlying [blockstore][`Blockstore`] for reading/writing state.
     /// * `externs`: Client-provided ["external"][`Externs`] methods for accessing chain state.
+    ///
+    /// See [`MachineBuilder`](super::MachineBuilder) for a constructor that validates the
+    /// configuration first.
     pub fn new(context: &MachineContext, blockstore: B, externs: E) -> anyhow::Result<Self> {
-        const SUPPORTED_VERSIONS: RangeInclusive<NetworkVersion> =
-            NetworkVersion::V18..=NetworkVersion::V18;
-
         debug!(
             "initializing a new machine, epoch={}, base_fee={}, nv={:?}, root={}",
             context.epoch, &context.base_fee, context.network_version, context.initial_state_root
@@ -87,33 +94,27 @@ where
             ));
         }
 
-        // Create a new state tree from the supplied root.
-        let state_tree = {
-            let bstore = BufferedBlockstore::new(blockstore);
-            StateTree::new_from_root(bstore, &context.initial_state_root)?
-        };
-
-        // Load the built-in actors manifest.
-        let (builtin_actors_cid, manifest_version) = match context.builtin_actors_override {
-            Some(manifest_cid) => {
-                let (version, cid): (u32, Cid) = state_tree
-                    .store()
-                    .get_cbor(&manifest_cid)?
-                    .context("failed to load actor manifest")?;
-                (cid, version)
-            }
-            None => {
-                let (state, _) = SystemActorState::load(&state_tree)?;
-                (state.builtin_actors, 1)
-            }
-        };
-        let builtin_actors =
-            Manifest::load(state_tree.store(), &builtin_actors_cid, manifest_version)?;
+        let state_tree = load_state_tree(context, blockstore)?;
+        let builtin_actors = load_manifest(context, &state_tree)?;
+        Ok(Self::from_parts(
+            context,
+            externs,
+            state_tree,
+            builtin_actors,
+        ))
+    }
 
+    /// Assembles a machine from its loaded state tree and actor manifest.
+    pub(super) fn from_parts(
+        context: &MachineContext,
+        externs: E,
+        state_tree: StateTree<BufferedBlockstore<B>>,
+        builtin_actors: Manifest,
+    ) -> Self {
         // 16 bytes is random _enough_
         let randomness: [u8; 16] = rand::random();
 
-        Ok(DefaultMachine {
+        DefaultMachine {
             context: context.clone(),
             externs,
             state_tree,
@@ -123,10 +124,83 @@ where
                 context.epoch,
                 cid::multibase::encode(cid::multibase::Base::Base32Lower, randomness)
             ),
-        })
+            head_cache: HeadCache::default(),
+            background_writer: None,
+            prefetcher: None,
+        }
+    }
+
+    /// Defers the writes of [`Machine::flush`]: instead of writing the flushed blocks to the
+    /// underlying blockstore before returning, hand them to a [`BackgroundWriter`] writing to
+    /// `store` (usually another handle to the same store), so execution can continue while they're
+    /// written. At most `capacity` flushes may be pending at once.
+    ///
+    /// Call [`Machine::join_flushes`] to wait for the pending writes (and learn whether they
+    /// succeeded) before relying on the flushed state, e.g., at the end of a block.
+    pub fn defer_flushes<BS>(&mut self, store: BS, capacity: usize) -> anyhow::Result<()>
+    where
+        BS: Blockstore + Send + 'static,
+    {
+        if let Some(writer) = self.background_writer.take() {
+            writer.join()?;
+        }
+        self.background_writer = Some(BackgroundWriter::new(store, capacity)?);
+        Ok(())
+    }
+
+    /// Honors the block prefetching hints of actors (the `ipld::block_prefetch` syscall) by loading
+    /// the hinted blocks from `store` (usually another handle to the underlying blockstore) on a
+    /// background thread, so that IO can overlap with execution. At most `capacity` blocks may be
+    /// pending at once. See [`Prefetcher`].
+    ///
+    /// Without prefetching, hints are ignored. Either way, execution is unaffected.
+    pub fn enable_prefetching<BS>(&mut self, store: BS, capacity: usize) -> anyhow::Result<()>
+    where
+        BS: Blockstore + Send + 'static,
+    {
+        self.prefetcher = Some(Prefetcher::new(store, capacity)?);
+        Ok(())
     }
 }
 
+/// The network versions supported by the [`DefaultMachine`].
+pub(super) const SUPPORTED_VERSIONS: RangeInclusive<NetworkVersion> =
+    NetworkVersion::V18..=NetworkVersion::V19;
+
+/// Creates a new state tree from the context's initial state root.
+pub(super) fn load_state_tree<B: Blockstore>(
+    context: &MachineContext,
+    blockstore: B,
+) -> anyhow::Result<StateTree<BufferedBlockstore<B>>> {
+    let bstore = BufferedBlockstore::new(blockstore);
+    Ok(StateTree::new_from_root(
+        bstore,
+        &context.initial_state_root,
+    )?)
+}
+
+/// Loads the built-in actors manifest, either from the override in the context's network config
+/// or from the system actor's state.
+pub(super) fn load_manifest<B: Blockstore>(
+    context: &MachineContext,
+    state_tree: &StateTree<B>,
+) -> anyhow::Result<Manifest> {
+    let (builtin_actors_cid, manifest_version) = match context.builtin_actors_override {
+        Some(manifest_cid) => {
+            let (version, cid): (u32, Cid) = state_tree
+                .store()
+                .get_cbor(&manifest_cid)?
+                .context("failed to load actor manifest")?;
+            (cid, version)
+        }
+        None => {
+            let (state, _) = SystemActorState::load(state_tree)?;
+            (state.builtin_actors, 1)
+        }
+    };
+    Manifest::load(state_tree.store(), &builtin_actors_cid, manifest_version)
+}
+
 impl<B, E> Machine for DefaultMachine<B, E>
 where
     B: Blockstore + 'static,
@@ -144,6 +218,10 @@ where
         &self.context
     }
 
+    fn context_mut(&mut self) -> &mut MachineContext {
+        &mut self.context
+    }
+
     fn externs(&self) -> &Self::Externs {
         &self.externs
     }
@@ -160,17 +238,45 @@ where
         &mut self.state_tree
     }
 
+    fn head_cache(&self) -> Option<&HeadCache> {
+        Some(&self.head_cache)
+    }
+
+    fn prefetcher(&self) -> Option<&Prefetcher> {
+        self.prefetcher.as_ref()
+    }
+
     /// Flushes the state-tree and returns the new root CID.
     ///
     /// This method also flushes all new blocks (reachable from this new root CID) from the write
     /// buffer into the underlying blockstore (the blockstore with which the machine was
-    /// constructed).
+    /// constructed), or hands them to the background writer if flushes are deferred (see
+    /// [`DefaultMachine::defer_flushes`]).
     fn flush(&mut self) -> Result<Cid> {
         let root = self.state_tree_mut().flush()?;
-        self.blockstore().flush(&root).or_fatal()?;
+        match &self.background_writer {
+            Some(writer) => self.blockstore().flush_to(&root, writer).or_fatal()?,
+            None => self.blockstore().flush(&root).or_fatal()?,
+        }
         Ok(root)
     }
 
+    fn join_flushes(&mut self) -> Result<()> {
+        match &self.background_writer {
+            Some(writer) => writer.join().or_fatal(),
+            None => Ok(()),
+        }
+    }
+
+    /// Discards all state changes and unflushed blocks. Blocks flushed to the underlying
+    /// blockstore stay there, but the machine returns to its initial state root.
+    fn reset(&mut self) -> Result<()> {
+        // Flushed blocks may still be read from the write buffer until they're written.
+        self.join_flushes()?;
+        self.state_tree.store().discard();
+        self.state_tree.reset(&self.context.initial_state_root)
+    }
+
     /// Creates an uninitialized actor.
     fn create_actor(&mut self, addr: &Address, act: ActorState) -> Result<ActorID> {
         let state_tree = self.state_tree_mut();
@@ -236,7 +342,7 @@ where
             amt.batch_set(events.iter().cloned())
                 .context("failed to add events to AMT")
                 .or_fatal()?;
-            amt.flush()
+            amt.flush_batched()
                 .context("failed to flush events AMT")
                 .or_fatal()?
         };
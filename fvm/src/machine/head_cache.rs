@@ -0,0 +1,158 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+use cid::Cid;
+
+use crate::kernel::Block;
+
+/// The maximum number of CIDs tracked by a [`HeadCache`].
+pub const MAX_HEAD_CACHE_ENTRIES: usize = 1024;
+
+/// The maximum total size of the blocks held by a [`HeadCache`].
+pub const MAX_HEAD_CACHE_BYTES: usize = 32 << 20;
+
+/// Caches actor state root ("head") blocks across the messages executed by a machine. Consecutive
+/// messages frequently call the same hot actors (e.g., market and power), each of which starts by
+/// loading its head, so the cache saves a blockstore lookup (and a copy) per call.
+///
+/// Entries are keyed by the head's CID. Blocks are content-addressed, so entries never go stale,
+/// and needn't be invalidated when an actor updates its state. The kernel marks a CID as a head
+/// when an actor reads its state root, and the block is cached when the actor opens it. The
+/// oldest entries are evicted once the cache exceeds [`MAX_HEAD_CACHE_ENTRIES`] or
+/// [`MAX_HEAD_CACHE_BYTES`]. Gas is charged as if the block were read from the store.
+#[derive(Default)]
+pub struct HeadCache {
+    inner: RefCell<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Head CIDs, with their blocks once opened.
+    heads: HashMap<Cid, Option<Block>>,
+    /// Head CIDs, oldest first.
+    order: VecDeque<Cid>,
+    /// The total size of the cached blocks.
+    bytes: usize,
+}
+
+/// The result of a [`HeadCache`] lookup.
+#[derive(Debug)]
+pub enum HeadLookup {
+    /// The block is a cached head.
+    Hit(Block),
+    /// The block is a head, but hasn't been cached yet.
+    Head,
+    /// The block isn't known to be a head.
+    Miss,
+}
+
+impl HeadCache {
+    /// Looks up the block with the given CID.
+    pub fn get(&self, cid: &Cid) -> HeadLookup {
+        match self.inner.borrow().heads.get(cid) {
+            Some(Some(block)) => HeadLookup::Hit(block.clone()),
+            Some(None) => HeadLookup::Head,
+            None => HeadLookup::Miss,
+        }
+    }
+
+    /// Marks the CID as an actor's head, so that its block is cached once it's opened.
+    pub fn mark(&self, cid: Cid) {
+        let entries = &mut *self.inner.borrow_mut();
+        if let Entry::Vacant(entry) = entries.heads.entry(cid) {
+            entry.insert(None);
+            entries.order.push_back(cid);
+            entries.evict();
+        }
+    }
+
+    /// Caches the block of a head marked with [`HeadCache::mark`]. Other blocks are ignored.
+    pub fn put(&self, cid: &Cid, block: &Block) {
+        let mut entries = self.inner.borrow_mut();
+        match entries.heads.get_mut(cid) {
+            Some(slot @ None) => *slot = Some(block.clone()),
+            _ => return,
+        }
+        entries.bytes += block.size() as usize;
+        entries.evict();
+    }
+
+    /// Returns the number of tracked heads.
+    pub fn len(&self) -> usize {
+        self.inner.borrow().heads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Entries {
+    fn evict(&mut self) {
+        while self.heads.len() > MAX_HEAD_CACHE_ENTRIES || self.bytes > MAX_HEAD_CACHE_BYTES {
+            let oldest = match self.order.pop_front() {
+                Some(cid) => cid,
+                None => break,
+            };
+            if let Some(Some(block)) = self.heads.remove(&oldest) {
+                self.bytes -= block.size() as usize;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_ipld_encoding::DAG_CBOR;
+
+    use super::*;
+
+    fn cid(data: &[u8]) -> Cid {
+        Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(data))
+    }
+
+    #[test]
+    fn lookup() {
+        let cache = HeadCache::default();
+        let block = Block::new(DAG_CBOR, &b"a"[..]);
+        assert!(matches!(cache.get(&cid(b"a")), HeadLookup::Miss));
+
+        // Only heads are cached.
+        cache.put(&cid(b"a"), &block);
+        assert!(matches!(cache.get(&cid(b"a")), HeadLookup::Miss));
+
+        cache.mark(cid(b"a"));
+        assert!(matches!(cache.get(&cid(b"a")), HeadLookup::Head));
+        cache.put(&cid(b"a"), &block);
+        match cache.get(&cid(b"a")) {
+            HeadLookup::Hit(hit) => assert_eq!(hit.data(), b"a"),
+            other => panic!("expected a hit, got {:?}", other),
+        }
+        assert!(matches!(cache.get(&cid(b"b")), HeadLookup::Miss));
+    }
+
+    #[test]
+    fn bounded() {
+        let cache = HeadCache::default();
+        for i in 0..=MAX_HEAD_CACHE_ENTRIES as u64 {
+            cache.mark(cid(&i.to_be_bytes()));
+        }
+        assert_eq!(cache.len(), MAX_HEAD_CACHE_ENTRIES);
+        assert!(matches!(
+            cache.get(&cid(&0u64.to_be_bytes())),
+            HeadLookup::Miss
+        ));
+
+        // Large blocks evict older heads.
+        let big = Block::new(DAG_CBOR, vec![0; MAX_HEAD_CACHE_BYTES / 2 + 1]);
+        let (a, b) = (cid(&1u64.to_be_bytes()), cid(&2u64.to_be_bytes()));
+        cache.put(&a, &big);
+        cache.put(&b, &big);
+        assert!(matches!(cache.get(&a), HeadLookup::Miss));
+        assert!(matches!(cache.get(&b), HeadLookup::Hit(_)));
+    }
+}
@@ -37,6 +37,20 @@ pub trait MemoryLimiter: Sized {
         // we charge 8 bytes per table element
         self.grow_memory(to.saturating_sub(from).saturating_mul(8) as usize)
     }
+
+    /// Returns `true` if reading a block of `size` bytes from the state is allowed. Implement this
+    /// along with [`MemoryLimiter::limit_state_reads`] to limit state reads; by default, they
+    /// aren't limited.
+    fn read_block(&mut self, size: usize) -> bool {
+        let _ = size;
+        true
+    }
+
+    /// Limits the state read from now on to `max_blocks` blocks, totalling at most `max_bytes`
+    /// bytes, e.g., while validating a message.
+    fn limit_state_reads(&mut self, max_blocks: usize, max_bytes: usize) {
+        let _ = (max_blocks, max_bytes);
+    }
 }
 
 /// Limit resources throughout the whole message execution,
@@ -44,6 +58,16 @@ pub trait MemoryLimiter: Sized {
 pub struct DefaultMemoryLimiter {
     max_memory_bytes: usize,
     curr_memory_bytes: usize,
+    /// The maximum total growth; unlike the current memory, this isn't restored when a stack frame
+    /// is popped.
+    max_growth_bytes: usize,
+    total_growth_bytes: usize,
+    /// The maximum number of blocks and bytes read from the state, and the blocks and bytes read so
+    /// far (since the limit was set).
+    max_read_blocks: usize,
+    max_read_bytes: usize,
+    read_blocks: usize,
+    read_bytes: usize,
 }
 
 impl DefaultMemoryLimiter {
@@ -51,11 +75,35 @@ impl DefaultMemoryLimiter {
         Self {
             max_memory_bytes,
             curr_memory_bytes: 0,
+            max_growth_bytes: usize::MAX,
+            total_growth_bytes: 0,
+            max_read_blocks: usize::MAX,
+            max_read_bytes: usize::MAX,
+            read_blocks: 0,
+            read_bytes: 0,
         }
     }
 
+    /// Limits the total growth over the lifetime of the limiter to `max_growth_bytes`, including
+    /// memory since released by popped stack frames.
+    pub fn with_max_growth(mut self, max_growth_bytes: usize) -> Self {
+        self.max_growth_bytes = max_growth_bytes;
+        self
+    }
+
     pub fn for_network(config: &NetworkConfig) -> Self {
-        Self::new(config.max_memory_bytes as usize)
+        Self::new(config.max_memory_bytes as usize).with_max_growth(
+            config
+                .max_memory_growth_bytes
+                .try_into()
+                .unwrap_or(usize::MAX),
+        )
+    }
+
+    /// Returns the total number of bytes grown so far, including memory since released by popped
+    /// stack frames.
+    pub fn total_growth(&self) -> usize {
+        self.total_growth_bytes
     }
 }
 
@@ -66,12 +114,14 @@ impl MemoryLimiter for DefaultMemoryLimiter {
 
     fn grow_memory(&mut self, bytes: usize) -> bool {
         let total_desired = self.curr_memory_bytes.saturating_add(bytes);
+        let total_growth = self.total_growth_bytes.saturating_add(bytes);
 
-        if total_desired > self.max_memory_bytes {
+        if total_desired > self.max_memory_bytes || total_growth > self.max_growth_bytes {
             return false;
         }
 
         self.curr_memory_bytes = total_desired;
+        self.total_growth_bytes = total_growth;
         true
     }
 
@@ -87,6 +137,26 @@ impl MemoryLimiter for DefaultMemoryLimiter {
         g(t).curr_memory_bytes = memory_bytes;
         ret
     }
+
+    fn read_block(&mut self, size: usize) -> bool {
+        let read_blocks = self.read_blocks.saturating_add(1);
+        let read_bytes = self.read_bytes.saturating_add(size);
+
+        if read_blocks > self.max_read_blocks || read_bytes > self.max_read_bytes {
+            return false;
+        }
+
+        self.read_blocks = read_blocks;
+        self.read_bytes = read_bytes;
+        true
+    }
+
+    fn limit_state_reads(&mut self, max_blocks: usize, max_bytes: usize) {
+        self.max_read_blocks = max_blocks;
+        self.max_read_bytes = max_bytes;
+        self.read_blocks = 0;
+        self.read_bytes = 0;
+    }
 }
 
 #[cfg(test)]
@@ -130,4 +200,37 @@ mod tests {
         assert!(limits.grow_memory(2)); // 2 bytes
         assert!(!limits.grow_memory(1));
     }
+
+    #[test]
+    fn growth() {
+        let mut limits = DefaultMemoryLimiter::new(10).with_max_growth(12);
+        assert!(limits.grow_memory(4));
+        DefaultMemoryLimiter::with_stack_frame(
+            &mut limits,
+            |x| x,
+            |limits| {
+                assert!(limits.grow_memory(6)); // Ok, just at the memory limit.
+            },
+        );
+        assert_eq!(limits.memory_used(), 4);
+        assert_eq!(limits.total_growth(), 10);
+
+        // Memory released by the popped frame isn't credited back.
+        assert!(!limits.grow_memory(3)); // Fail, 10+3 would be over the growth limit of 12.
+        assert!(limits.grow_memory(2)); // Ok, just at the growth limit.
+        assert_eq!(limits.memory_used(), 6);
+    }
+
+    #[test]
+    fn state_reads() {
+        let mut limits = DefaultMemoryLimiter::new(10);
+        assert!(limits.read_block(1 << 20)); // Unlimited by default.
+
+        limits.limit_state_reads(3, 10);
+        assert!(limits.read_block(4));
+        assert!(!limits.read_block(7)); // Fail, 4+7 would be over the byte limit of 10.
+        assert!(limits.read_block(6)); // Ok, just at the byte limit.
+        assert!(limits.read_block(0));
+        assert!(!limits.read_block(0)); // Fail, over the block limit of 3.
+    }
 }
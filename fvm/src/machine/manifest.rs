@@ -13,6 +13,7 @@ const SYSTEM_ACTOR_NAME: &str = "system";
 const PLACEHOLDER_ACTOR_NAME: &str = "placeholder";
 const EAM_ACTOR_NAME: &str = "eam";
 const ETHACCOUNT_ACTOR_NAME: &str = "ethaccount";
+const CRON_ACTOR_NAME: &str = "cron";
 
 /// A mapping of builtin actor CIDs to their respective types.
 pub struct Manifest {
@@ -22,6 +23,7 @@ pub struct Manifest {
     init_code: Cid,
     eam_code: Cid,
     ethaccount_code: Cid,
+    cron_code: Cid,
 
     by_id: HashMap<u32, Cid>,
     by_code: HashMap<Cid, u32>,
@@ -124,6 +126,10 @@ impl Manifest {
             .get(ETHACCOUNT_ACTOR_NAME)
             .context("manifest missing ethaccount actor")?;
 
+        let cron_code = *by_name
+            .get(CRON_ACTOR_NAME)
+            .context("manifest missing cron actor")?;
+
         Ok(Self {
             account_code,
             system_code,
@@ -131,6 +137,7 @@ impl Manifest {
             placeholder_code,
             eam_code,
             ethaccount_code,
+            cron_code,
             by_id,
             by_code,
         })
@@ -194,4 +201,9 @@ impl Manifest {
     pub fn get_ethaccount_code(&self) -> &Cid {
         &self.ethaccount_code
     }
+
+    /// Returns the code CID for the cron actor.
+    pub fn get_cron_code(&self) -> &Cid {
+        &self.cron_code
+    }
 }
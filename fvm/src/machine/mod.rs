@@ -1,5 +1,8 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::sync::Arc;
+
+use anyhow::anyhow;
 use cid::Cid;
 use derive_more::{Deref, DerefMut};
 use fvm_ipld_blockstore::Blockstore;
@@ -7,18 +10,34 @@ use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::version::NetworkVersion;
-use fvm_shared::ActorID;
+use fvm_shared::{ActorID, BLOCK_GAS_LIMIT};
 use num_traits::Zero;
 
+use crate::audit::AuditLog;
+use crate::call_manager::TransferHook;
+use crate::cancel::CancellationToken;
+use crate::eam_actor::EAM_ACTOR_ID;
 use crate::externs::Externs;
-use crate::gas::{price_list_by_network_version, PriceList};
-use crate::kernel::Result;
+#[cfg(feature = "fault-injection")]
+use crate::fault::{FaultConfig, FaultInjector};
+use crate::gas::{price_list_by_network_version, GasSubsidy, PriceList};
+use crate::kernel::replay::SyscallTape;
+use crate::kernel::{ExecutionError, Result};
 use crate::state_tree::{ActorState, StateTree};
 
+mod builder;
 mod default;
+mod head_cache;
 
-pub use default::DefaultMachine;
+pub use builder::{MachineBuildError, MachineBuilder, TipsetContext};
+pub use default::{DefaultMachine, EVENTS_AMT_BITWIDTH};
 use fvm_shared::chainid::ChainID;
+pub use head_cache::{HeadCache, HeadLookup};
+
+pub use crate::blockstore::{
+    AccessRecorder, AccessTrace, Prefetcher, PrefetchingBlockstore, RecordingBlockstore,
+    TracePrefetcher,
+};
 
 pub mod limiter;
 mod manifest;
@@ -32,6 +51,8 @@ mod boxed;
 
 pub const REWARD_ACTOR_ID: ActorID = 2;
 
+pub use fvm_shared::CRON_ACTOR_ID;
+
 /// Distinguished Account actor that is the destination of all burnt funds.
 pub const BURNT_FUNDS_ACTOR_ID: ActorID = 99;
 
@@ -58,6 +79,10 @@ pub trait Machine: 'static {
     /// context.
     fn context(&self) -> &MachineContext;
 
+    /// Returns a mutable reference to the machine context, to update the per-message information
+    /// (see [`MachineContext::message_cid`]).
+    fn context_mut(&mut self) -> &mut MachineContext;
+
     /// Returns a reference to all "node" supplied APIs.
     fn externs(&self) -> &Self::Externs;
 
@@ -84,9 +109,25 @@ pub trait Machine: 'static {
         self.state_tree_mut().flush()
     }
 
+    /// Waits for the blocks of previous flushes to be written, if the machine writes them in the
+    /// background, failing if any of the writes failed.
+    fn join_flushes(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     /// Consumes the machine and returns the owned blockstore.
     fn into_store(self) -> Self::Blockstore;
 
+    /// Discards all state changes and unflushed blocks, returning the machine to its initial state
+    /// root, e.g., to reuse it for another read-only call (see
+    /// [`MachinePool`](crate::executor::MachinePool)). Fails if the machine doesn't support resets.
+    fn reset(&mut self) -> Result<()> {
+        Err(ExecutionError::Fatal(anyhow!(
+            "machine {} doesn't support resets",
+            self.machine_id()
+        )))
+    }
+
     /// Returns a generated ID of a machine
     fn machine_id(&self) -> &str;
 
@@ -96,6 +137,64 @@ pub trait Machine: 'static {
     /// Commits the events to the machine by building the events AMT, and making sure that events
     /// are written to the store.
     fn commit_events(&self, events: &[StampedEvent]) -> Result<Option<Cid>>;
+
+    /// Returns the cache of actor head blocks shared by the messages executed on this machine, if
+    /// the machine keeps one.
+    fn head_cache(&self) -> Option<&HeadCache> {
+        None
+    }
+
+    /// Returns the prefetcher loading blocks ahead of time at the request of actors, if the
+    /// machine has one.
+    fn prefetcher(&self) -> Option<&Prefetcher> {
+        None
+    }
+}
+
+/// How the FVM handles sends to deleted actors. A deleted actor (a "tombstone") is an actor ID that
+/// was allocated by the init actor, but no longer has an actor in the state tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TombstonePolicy {
+    /// Reject all sends with [`ErrorNumber::NotFound`][fvm_shared::error::ErrorNumber::NotFound]
+    /// (`SYS_INVALID_RECEIVER` for top-level messages). Any value stays with the sender.
+    Reject,
+    /// Accept plain value transfers (method 0), burning the value by transferring it to the burnt
+    /// funds actor. Method invocations are still rejected.
+    Burn,
+}
+
+impl TombstonePolicy {
+    /// Returns the policy in effect at the given network version.
+    pub fn for_network_version(nv: NetworkVersion) -> Self {
+        match nv {
+            NetworkVersion::V18 | NetworkVersion::V19 => TombstonePolicy::Reject,
+            _ => panic!("network version {nv} not supported"),
+        }
+    }
+}
+
+/// Returns whether atomic message batches are enabled at the given network version.
+fn message_batching_enabled(nv: NetworkVersion) -> bool {
+    match nv {
+        NetworkVersion::V18 | NetworkVersion::V19 => false,
+        _ => panic!("network version {nv} not supported"),
+    }
+}
+
+/// Returns whether native actor implementations are used at the given network version.
+fn native_actors_enabled(nv: NetworkVersion) -> bool {
+    match nv {
+        NetworkVersion::V18 | NetworkVersion::V19 => false,
+        _ => panic!("network version {nv} not supported"),
+    }
+}
+
+/// Returns whether actors may delegate calls to other code at the given network version.
+fn delegate_call_enabled(nv: NetworkVersion) -> bool {
+    match nv {
+        NetworkVersion::V18 | NetworkVersion::V19 => false,
+        _ => panic!("network version {nv} not supported"),
+    }
 }
 
 /// Network-level settings. Except when testing locally, changing any of these likely requires a
@@ -117,6 +216,10 @@ pub struct NetworkConfig {
 
     /// The maximum number of elements on wasm stack
     /// DEFAULT: 64Ki (512KiB of u64 elements)
+    ///
+    /// Every function activation counts as at least two elements (on top of its locals and
+    /// operands), so this also bounds the number of nested calls within a single Wasm instance,
+    /// even through functions that use no locals or operands.
     pub max_wasm_stack: u32,
 
     /// Maximum size of memory of any Wasm instance, ie. each level of the recursion, in bytes.
@@ -131,13 +234,23 @@ pub struct NetworkConfig {
     /// DEFAULT: 2GiB
     pub max_memory_bytes: u64,
 
+    /// Maximum total number of bytes Wasm memories and tables may grow by over the course of a
+    /// message execution. Unlike [`NetworkConfig::max_memory_bytes`], memory released when a call
+    /// returns isn't credited back, so this bounds the total allocation work a message can cause
+    /// (each byte of which is charged gas, see [`PriceList::grow_memory_gas`]). Growth beyond this
+    /// limit fails (`memory.grow` returns -1, and instantiation fails).
+    ///
+    /// DEFAULT: `u64::MAX` (only bounded by gas)
+    pub max_memory_growth_bytes: u64,
+
     /// An override for builtin-actors. If specified, this should be the CID of a builtin-actors
     /// "manifest".
     ///
     /// DEFAULT: `None`
     pub builtin_actors_override: Option<Cid>,
 
-    /// Enable actor debugging.
+    /// Enable actor debugging. This also links a minimal WASI shim into actors (see
+    /// [`crate::syscalls::wasi`]), so actors built for `wasm32-wasi` can be run.
     ///
     /// DEFAULT: `false`
     pub actor_debugging: bool,
@@ -149,6 +262,218 @@ pub struct NetworkConfig {
 
     /// Actor redirects for debug execution
     pub actor_redirect: Vec<(Cid, Cid)>,
+
+    /// Chain-specific consensus parameters.
+    ///
+    /// DEFAULT: The Filecoin mainnet parameters.
+    pub chain_policy: ChainPolicy,
+
+    /// The delegated (f4) address namespaces in which sending to a non-existent address creates a
+    /// placeholder actor. The placeholder holds any funds sent to it until the namespace's manager
+    /// actor deploys real code at that address. Sending to a non-existent delegated address in any
+    /// other namespace fails.
+    ///
+    /// DEFAULT: The Ethereum Address Manager's namespace.
+    pub placeholder_namespaces: Vec<ActorID>,
+
+    /// How to handle sends to deleted actors.
+    ///
+    /// DEFAULT: The policy for the current network version.
+    pub tombstone_policy: TombstonePolicy,
+
+    /// Start each actor invocation from a cached copy-on-write image of the actor's
+    /// post-initialization linear memory, instead of re-applying the module's data segments on
+    /// every invocation. This speeds up calls to frequently invoked actors at the cost of larger
+    /// compiled modules (the image covers the entire span of the data segments).
+    ///
+    /// This is not consensus-critical: memory contents and gas charges are unaffected.
+    ///
+    /// DEFAULT: `false`
+    pub memory_snapshots: bool,
+
+    /// Count the Wasm instructions executed by each actor invocation with wasmtime's fuel
+    /// mechanism, and record the counts in execution traces (see
+    /// [`ExecutionEvent::InstructionCount`](crate::trace::ExecutionEvent::InstructionCount)) when
+    /// tracing is enabled. Unlike gas, instruction counts aren't priced, so this is useful for
+    /// analyzing how well gas tracks the work actually performed.
+    ///
+    /// This is not consensus-critical, but slows down execution and requires actor code to be
+    /// compiled separately.
+    ///
+    /// DEFAULT: `false`
+    pub instruction_counting: bool,
+
+    /// Time gas charges by the number of Wasm instructions executed instead of the wall clock, so
+    /// the timings in execution traces are identical on every machine (see
+    /// [`InstructionClock`](crate::gas::InstructionClock)). Requires
+    /// [`instruction_counting`](NetworkConfig::instruction_counting).
+    ///
+    /// This is not consensus-critical.
+    ///
+    /// DEFAULT: `false`
+    pub deterministic_timing: bool,
+
+    /// Emit a jitdump file describing the compiled actor code, so that actors can be profiled on
+    /// Linux with `perf record -k mono` and `perf inject --jit`. Functions are named after the
+    /// actors' name sections, if any.
+    ///
+    /// This is not consensus-critical, but requires actor code to be compiled separately.
+    ///
+    /// DEFAULT: `false`
+    pub profiling: bool,
+
+    /// The (builtin) actors allowed to look up domain state through the node's
+    /// [`StateReader`](crate::externs::StateReader) extern. Lookups from any other actor fail with
+    /// `Forbidden`.
+    ///
+    /// DEFAULT: Empty (domain state lookups are disabled)
+    pub state_readers: Vec<ActorID>,
+
+    /// The size (in bytes) beyond which actors pay a superlinear gas surcharge to create a block,
+    /// discouraging giant state objects. Should not exceed [`NetworkConfig::max_block_size`].
+    ///
+    /// DEFAULT: No soft limit
+    pub block_size_soft_limit: u32,
+
+    /// The maximum size (in bytes) of a block created by an actor. Creating a larger block fails
+    /// with `LimitExceeded`.
+    ///
+    /// DEFAULT: No limit
+    pub max_block_size: u32,
+
+    /// The size (in bytes) beyond which actors pay a gas surcharge, per excess byte, for the data
+    /// they return (or abort with). Should not exceed [`NetworkConfig::max_return_size`].
+    ///
+    /// DEFAULT: No soft limit
+    pub return_size_soft_limit: u32,
+
+    /// The maximum size (in bytes) of the data returned by an actor. Returning more data aborts
+    /// the call with [`ExitCode::SYS_RETURN_TOO_LARGE`](fvm_shared::error::ExitCode).
+    ///
+    /// DEFAULT: No limit
+    pub max_return_size: u32,
+
+    /// The maximum size (in bytes) of the parameters of an explicit message. Larger messages fail
+    /// validation with [`ExitCode::SYS_PARAMS_TOO_LARGE`](fvm_shared::error::ExitCode), and the
+    /// miner including them is penalized. This is a consensus-critical option.
+    ///
+    /// DEFAULT: No limit
+    pub max_message_params_size: u32,
+
+    /// The maximum total payload size (in bytes, see [`ActorEvent::payload_size`]) of the events
+    /// emitted by a single message. Emitting an event beyond this limit fails with
+    /// `LimitExceeded`.
+    ///
+    /// [`ActorEvent::payload_size`]: fvm_shared::event::ActorEvent::payload_size
+    ///
+    /// DEFAULT: No limit
+    pub max_message_event_bytes: u64,
+
+    /// The maximum number of block handles a single call frame may hold open at once. Opening or
+    /// creating a block beyond this limit fails with `LimitExceeded`, until the actor closes some
+    /// of its blocks.
+    ///
+    /// DEFAULT: No limit
+    pub max_block_handles: u32,
+
+    /// The maximum number of incremental hashers a single message may open (see
+    /// [`CryptoOps::hash_init`](crate::kernel::CryptoOps::hash_init)), including hashers that have
+    /// since been finalized. Opening a hasher beyond this limit fails with `LimitExceeded`.
+    ///
+    /// DEFAULT: 1024
+    pub max_hashers: u32,
+
+    /// Reject DAG-CBOR blocks created by actors unless they're in canonical form (see
+    /// [`fvm_ipld_encoding::check_canonical`]). Creating a non-canonical block fails with
+    /// `Serialization`. This is a consensus-critical option.
+    ///
+    /// DEFAULT: `false`
+    pub strict_cbor: bool,
+
+    /// Whether messages may be applied in atomic batches with
+    /// [`Executor::execute_batch`](crate::executor::Executor::execute_batch).
+    ///
+    /// DEFAULT: Disabled at all supported network versions.
+    pub message_batching: bool,
+
+    /// Whether to invoke the native implementations of actors registered with
+    /// [`EnginePool::register_native_actor`](crate::engine::EnginePool::register_native_actor)
+    /// instead of their Wasm code. This is a consensus-critical option, as Wasm execution gas isn't
+    /// charged for native actors. See [`crate::native`].
+    ///
+    /// DEFAULT: Disabled at all supported network versions.
+    pub native_actors: bool,
+
+    /// Actors to invoke before or after sends matching a filter, in order. This is a
+    /// consensus-critical option. See [`crate::call_manager::hooks`].
+    ///
+    /// DEFAULT: No hooks
+    pub transfer_hooks: Vec<TransferHook>,
+
+    /// Actor methods whose messages have their gas paid for by a network account, or waived, up
+    /// to a quota. This is a consensus-critical option. See [`crate::gas::subsidy`].
+    ///
+    /// DEFAULT: No subsidies
+    pub gas_subsidies: Vec<GasSubsidy>,
+
+    /// The maximum total size (in bytes) of the inputs of pure syscalls (hashing and unsealed
+    /// sector CID computation) whose results are memoized over the course of a message execution,
+    /// so repeated computations are served without redoing the host work. Gas is charged as
+    /// usual, so this is not consensus-critical. See [`SyscallMemo`](crate::kernel::SyscallMemo).
+    ///
+    /// DEFAULT: 0 (memoization is disabled)
+    pub syscall_memo_bytes: usize,
+
+    /// Whether user actors may invoke the code in [`NetworkConfig::delegate_call_code`] in the
+    /// context of their own state with
+    /// [`SendOps::delegate_call`](crate::kernel::SendOps::delegate_call). This is a
+    /// consensus-critical option.
+    ///
+    /// DEFAULT: Disabled at all supported network versions.
+    pub delegate_call: bool,
+
+    /// The code CIDs actors may delegate calls to, when [`NetworkConfig::delegate_call`] is
+    /// enabled. The code must be loaded by the engine (e.g., built-in actor code).
+    ///
+    /// DEFAULT: No code
+    pub delegate_call_code: Vec<Cid>,
+
+    /// Whether actors may draw deterministic pseudo-randomness derived from the epoch, the message
+    /// CID, and a per-message counter with
+    /// [`RandomnessOps::get_dev_randomness`](crate::kernel::RandomnessOps::get_dev_randomness).
+    /// This randomness is entirely predictable, so this is only intended for local development
+    /// networks without a randomness beacon. This is a consensus-critical option.
+    ///
+    /// DEFAULT: false
+    pub dev_randomness: bool,
+
+    /// Whether actors other than accounts may send messages by validating them with
+    /// [`VALIDATE_MESSAGE_METHOD`](fvm_shared::message::validation::VALIDATE_MESSAGE_METHOD). See
+    /// [`fvm_shared::message::validation`]. This is a consensus-critical option.
+    ///
+    /// DEFAULT: false
+    pub account_abstraction: bool,
+
+    /// The maximum gas the validation of a message sent by an abstract account may use, when
+    /// [`NetworkConfig::account_abstraction`] is enabled. This includes validating the sponsor, if
+    /// any.
+    ///
+    /// DEFAULT: 10M
+    pub validation_gas_limit: i64,
+
+    /// The maximum number of blocks the validation of a message sent by an abstract account may
+    /// read from the state (see [`NetworkConfig::account_abstraction`]). This includes validating
+    /// the sponsor, if any. Further reads fail with
+    /// [`ErrorNumber::LimitExceeded`](fvm_shared::error::ErrorNumber::LimitExceeded).
+    ///
+    /// DEFAULT: 64
+    pub max_validation_reads: u32,
+
+    /// The maximum total size of the blocks the validation of a message sent by an abstract
+    /// account may read from the state, like [`NetworkConfig::max_validation_reads`].
+    ///
+    /// DEFAULT: 1MiB
+    pub max_validation_read_bytes: u64,
 }
 
 impl NetworkConfig {
@@ -161,13 +486,215 @@ impl NetworkConfig {
             max_wasm_stack: 2048,
             max_inst_memory_bytes: 512 * (1 << 20),
             max_memory_bytes: 2 * (1 << 30),
+            max_memory_growth_bytes: u64::MAX,
             actor_debugging: false,
             builtin_actors_override: None,
             price_list: price_list_by_network_version(network_version),
             actor_redirect: vec![],
+            chain_policy: ChainPolicy::default(),
+            placeholder_namespaces: vec![EAM_ACTOR_ID],
+            tombstone_policy: TombstonePolicy::for_network_version(network_version),
+            memory_snapshots: false,
+            instruction_counting: false,
+            deterministic_timing: false,
+            profiling: false,
+            state_readers: vec![],
+            block_size_soft_limit: u32::MAX,
+            max_block_size: u32::MAX,
+            max_block_handles: u32::MAX,
+            max_hashers: 1024,
+            return_size_soft_limit: u32::MAX,
+            max_return_size: u32::MAX,
+            max_message_params_size: u32::MAX,
+            max_message_event_bytes: u64::MAX,
+            strict_cbor: false,
+            message_batching: message_batching_enabled(network_version),
+            native_actors: native_actors_enabled(network_version),
+            transfer_hooks: vec![],
+            gas_subsidies: vec![],
+            syscall_memo_bytes: 0,
+            delegate_call: delegate_call_enabled(network_version),
+            delegate_call_code: vec![],
+            dev_randomness: false,
+            account_abstraction: false,
+            validation_gas_limit: 10_000_000,
+            max_validation_reads: 64,
+            max_validation_read_bytes: 1 << 20,
         }
     }
 
+    /// Override the chain-specific consensus parameters. This is primarily useful for networks
+    /// other than Filecoin.
+    pub fn chain_policy(&mut self, policy: ChainPolicy) -> &mut Self {
+        self.chain_policy = policy;
+        self
+    }
+
+    /// Set the delegated address namespaces in which sends to non-existent addresses create
+    /// placeholder actors. [`NetworkConfig::placeholder_namespaces`].
+    pub fn placeholder_namespaces(
+        &mut self,
+        namespaces: impl IntoIterator<Item = ActorID>,
+    ) -> &mut Self {
+        self.placeholder_namespaces = namespaces.into_iter().collect();
+        self
+    }
+
+    /// Override how sends to deleted actors are handled. [`NetworkConfig::tombstone_policy`].
+    pub fn tombstone_policy(&mut self, policy: TombstonePolicy) -> &mut Self {
+        self.tombstone_policy = policy;
+        self
+    }
+
+    /// Set the actors allowed to look up domain state. [`NetworkConfig::state_readers`].
+    pub fn state_readers(&mut self, actors: impl IntoIterator<Item = ActorID>) -> &mut Self {
+        self.state_readers = actors.into_iter().collect();
+        self
+    }
+
+    /// Set the soft and hard limits on the size of blocks created by actors.
+    /// [`NetworkConfig::block_size_soft_limit`] and [`NetworkConfig::max_block_size`].
+    pub fn block_size_limits(&mut self, soft: u32, hard: u32) -> &mut Self {
+        self.block_size_soft_limit = soft;
+        self.max_block_size = hard;
+        self
+    }
+
+    /// Set the soft and hard limits on the size of the data returned by actors.
+    /// [`NetworkConfig::return_size_soft_limit`] and [`NetworkConfig::max_return_size`].
+    pub fn return_size_limits(&mut self, soft: u32, hard: u32) -> &mut Self {
+        self.return_size_soft_limit = soft;
+        self.max_return_size = hard;
+        self
+    }
+
+    /// Limit the size of the parameters of explicit messages.
+    /// [`NetworkConfig::max_message_params_size`].
+    pub fn max_message_params_size(&mut self, limit: u32) -> &mut Self {
+        self.max_message_params_size = limit;
+        self
+    }
+
+    /// Limit the total size of the events emitted by each message.
+    /// [`NetworkConfig::max_message_event_bytes`].
+    pub fn max_message_event_bytes(&mut self, limit: u64) -> &mut Self {
+        self.max_message_event_bytes = limit;
+        self
+    }
+
+    /// Limit the number of blocks each call frame may hold open.
+    /// [`NetworkConfig::max_block_handles`].
+    pub fn max_block_handles(&mut self, limit: u32) -> &mut Self {
+        self.max_block_handles = limit;
+        self
+    }
+
+    /// Limit the number of incremental hashers each message may open.
+    /// [`NetworkConfig::max_hashers`].
+    pub fn max_hashers(&mut self, limit: u32) -> &mut Self {
+        self.max_hashers = limit;
+        self
+    }
+
+    /// Reject non-canonical DAG-CBOR blocks. [`NetworkConfig::strict_cbor`].
+    pub fn enable_strict_cbor(&mut self) -> &mut Self {
+        self.strict_cbor = true;
+        self
+    }
+
+    /// Allow atomic message batches. [`NetworkConfig::message_batching`].
+    pub fn enable_message_batching(&mut self) -> &mut Self {
+        self.message_batching = true;
+        self
+    }
+
+    /// Use registered native actor implementations. [`NetworkConfig::native_actors`].
+    pub fn enable_native_actors(&mut self) -> &mut Self {
+        self.native_actors = true;
+        self
+    }
+
+    /// Set the hooks to invoke around matching sends. [`NetworkConfig::transfer_hooks`].
+    pub fn transfer_hooks(&mut self, hooks: impl IntoIterator<Item = TransferHook>) -> &mut Self {
+        self.transfer_hooks = hooks.into_iter().collect();
+        self
+    }
+
+    /// Set the subsidized actor methods. [`NetworkConfig::gas_subsidies`].
+    pub fn gas_subsidies(&mut self, subsidies: impl IntoIterator<Item = GasSubsidy>) -> &mut Self {
+        self.gas_subsidies = subsidies.into_iter().collect();
+        self
+    }
+
+    /// Memoize pure syscall results within each message. [`NetworkConfig::syscall_memo_bytes`].
+    pub fn syscall_memo_bytes(&mut self, bytes: usize) -> &mut Self {
+        self.syscall_memo_bytes = bytes;
+        self
+    }
+
+    /// Allow actors to delegate calls to the given code. [`NetworkConfig::delegate_call`] and
+    /// [`NetworkConfig::delegate_call_code`].
+    pub fn enable_delegate_call(&mut self, code: impl IntoIterator<Item = Cid>) -> &mut Self {
+        self.delegate_call = true;
+        self.delegate_call_code = code.into_iter().collect();
+        self
+    }
+
+    /// Allow actors to draw deterministic pseudo-randomness, for local development networks
+    /// without a randomness beacon. [`NetworkConfig::dev_randomness`].
+    pub fn enable_dev_randomness(&mut self) -> &mut Self {
+        self.dev_randomness = true;
+        self
+    }
+
+    /// Allow actors to send messages by validating them, using at most the given gas.
+    /// [`NetworkConfig::account_abstraction`] and [`NetworkConfig::validation_gas_limit`].
+    pub fn enable_account_abstraction(&mut self, validation_gas_limit: i64) -> &mut Self {
+        self.account_abstraction = true;
+        self.validation_gas_limit = validation_gas_limit;
+        self
+    }
+
+    /// Limit the state read by the validation of messages sent by abstract accounts.
+    /// [`NetworkConfig::max_validation_reads`] and [`NetworkConfig::max_validation_read_bytes`].
+    pub fn validation_read_limits(&mut self, max_blocks: u32, max_bytes: u64) -> &mut Self {
+        self.max_validation_reads = max_blocks;
+        self.max_validation_read_bytes = max_bytes;
+        self
+    }
+
+    /// Limit the total memory growth per message. [`NetworkConfig::max_memory_growth_bytes`].
+    pub fn max_memory_growth_bytes(&mut self, bytes: u64) -> &mut Self {
+        self.max_memory_growth_bytes = bytes;
+        self
+    }
+
+    /// Initialize actor memories from cached snapshots. [`NetworkConfig::memory_snapshots`].
+    pub fn enable_memory_snapshots(&mut self) -> &mut Self {
+        self.memory_snapshots = true;
+        self
+    }
+
+    /// Count the instructions executed by each invocation. [`NetworkConfig::instruction_counting`].
+    pub fn enable_instruction_counting(&mut self) -> &mut Self {
+        self.instruction_counting = true;
+        self
+    }
+
+    /// Time gas charges by instruction counts, enabling instruction counting.
+    /// [`NetworkConfig::deterministic_timing`].
+    pub fn enable_deterministic_timing(&mut self) -> &mut Self {
+        self.instruction_counting = true;
+        self.deterministic_timing = true;
+        self
+    }
+
+    /// Enable profiling of actor code. [`NetworkConfig::profiling`].
+    pub fn enable_profiling(&mut self) -> &mut Self {
+        self.profiling = true;
+        self
+    }
+
     /// Enable actor debugging. This is a consensus-critical option (affects gas usage) so it should
     /// only be enabled for local testing or as a network-wide parameter.
     pub fn enable_actor_debugging(&mut self) -> &mut Self {
@@ -203,6 +730,15 @@ impl NetworkConfig {
             initial_state_root: initial_state,
             circ_supply: fvm_shared::TOTAL_FILECOIN.clone(),
             tracing: false,
+            trace_commitment: false,
+            actor_gas_accounting: false,
+            supply_check: false,
+            audit_log: None,
+            syscall_tape: None,
+            cancellation: None,
+            message_cid: None,
+            #[cfg(feature = "fault-injection")]
+            faults: None,
         }
     }
 
@@ -213,6 +749,42 @@ impl NetworkConfig {
     }
 }
 
+/// Chain-specific consensus parameters. Changing any of these requires a network upgrade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainPolicy {
+    /// The total gas limit allowed per block.
+    ///
+    /// The FVM doesn't enforce this itself; it's provided so clients building blocks on top of the
+    /// FVM have a single source of truth.
+    ///
+    /// DEFAULT: [`BLOCK_GAS_LIMIT`]
+    pub block_gas_limit: i64,
+
+    /// The minimum base fee.
+    ///
+    /// The FVM doesn't enforce this itself (the base fee is provided by the client in the
+    /// [`MachineContext`]); it's provided so clients have a single source of truth.
+    ///
+    /// DEFAULT: 100 attoFIL
+    pub minimum_base_fee: TokenAmount,
+
+    /// The number of epochs into the past for which actors may look up tipset CIDs (i.e., the
+    /// chain's finality).
+    ///
+    /// DEFAULT: 900
+    pub lookback_window: ChainEpoch,
+}
+
+impl Default for ChainPolicy {
+    fn default() -> Self {
+        ChainPolicy {
+            block_gas_limit: BLOCK_GAS_LIMIT,
+            minimum_base_fee: TokenAmount::from_atto(100),
+            lookback_window: 900,
+        }
+    }
+}
+
 /// Per-epoch machine context.
 #[derive(Clone, Debug, Deref, DerefMut)]
 pub struct MachineContext {
@@ -249,6 +821,39 @@ pub struct MachineContext {
     /// Whether or not to produce execution traces in the returned result.
     /// Not consensus-critical, but has a performance impact.
     pub tracing: bool,
+
+    /// Whether or not to compute a commitment over each message's execution trace (see
+    /// [`ApplyRet::trace_commitment`][crate::executor::ApplyRet::trace_commitment]). Implies
+    /// [`MachineContext::tracing`].
+    pub trace_commitment: bool,
+
+    /// Whether or not to attribute the gas used by each call to the callee's code and method (see
+    /// [`ActorGasUsage`](crate::gas::ActorGasUsage)). Not consensus-critical.
+    pub actor_gas_accounting: bool,
+
+    /// Whether or not to check that the total token supply is conserved whenever the executor is
+    /// flushed (see [`crate::executor::supply`]). Not consensus-critical, but has a significant
+    /// performance impact.
+    pub supply_check: bool,
+
+    /// The log to append every syscall to, if any. Not consensus-critical, but has a significant
+    /// performance impact. See [`crate::audit`].
+    pub audit_log: Option<Arc<AuditLog>>,
+
+    /// The syscall tape replayed by [`ReplayKernel`](crate::kernel::replay::ReplayKernel)s.
+    /// Ignored by other kernels. See [`crate::kernel::replay`].
+    pub syscall_tape: Option<SyscallTape>,
+
+    /// The token cancelling message execution, if any. See [`crate::cancel`].
+    pub cancellation: Option<CancellationToken>,
+
+    /// The CID of the message being executed. Only set by the executor (before each message) if
+    /// [`NetworkConfig::dev_randomness`] is enabled, as dev randomness is derived from it.
+    pub message_cid: Option<Cid>,
+
+    /// Faults to inject during execution. See [`crate::fault`].
+    #[cfg(feature = "fault-injection")]
+    pub faults: Option<Arc<FaultInjector>>,
 }
 
 impl MachineContext {
@@ -269,4 +874,49 @@ impl MachineContext {
         self.tracing = true;
         self
     }
+
+    /// Enable execution trace commitments. [`MachineContext::trace_commitment`].
+    pub fn enable_trace_commitment(&mut self) -> &mut Self {
+        self.tracing = true;
+        self.trace_commitment = true;
+        self
+    }
+
+    /// Attribute gas usage to actor code and methods. [`MachineContext::actor_gas_accounting`].
+    pub fn enable_actor_gas_accounting(&mut self) -> &mut Self {
+        self.actor_gas_accounting = true;
+        self
+    }
+
+    /// Check token supply conservation. [`MachineContext::supply_check`].
+    pub fn enable_supply_check(&mut self) -> &mut Self {
+        self.supply_check = true;
+        self
+    }
+
+    /// Log all syscalls to the given audit log. [`MachineContext::audit_log`].
+    pub fn enable_audit_log(&mut self, log: AuditLog) -> &mut Self {
+        self.audit_log = Some(Arc::new(log));
+        self
+    }
+
+    /// Replay syscalls from the given tape. [`MachineContext::syscall_tape`].
+    pub fn replay_syscalls(&mut self, tape: SyscallTape) -> &mut Self {
+        self.syscall_tape = Some(tape);
+        self
+    }
+
+    /// Cancel message execution when the given token is cancelled.
+    /// [`MachineContext::cancellation`].
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Inject faults during execution. [`MachineContext::faults`].
+    #[cfg(feature = "fault-injection")]
+    pub fn inject_faults(&mut self, config: FaultConfig) -> &mut Self {
+        self.faults = Some(Arc::new(FaultInjector::new(config)));
+        self
+    }
 }
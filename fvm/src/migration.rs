@@ -0,0 +1,337 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! A resumable runner for state migrations that rewrite every actor in the state tree.
+//!
+//! Migrating a mainnet-sized state tree can take hours. Instead of migrating the whole tree in one
+//! go, [`run_migration`] walks the actors HAMT in batches, and reports a [`MigrationCheckpoint`]
+//! after each one. Checkpoints are CBOR-serializable so the caller can persist them (along with
+//! the blockstore), and a migration interrupted at any point can be resumed from the last
+//! checkpoint.
+//!
+//! The output doesn't depend on the batch size or on where (or how often) the migration was
+//! interrupted: the output actors HAMT is built from scratch, and HAMTs with the same contents
+//! always have the same root.
+
+use std::ops::ControlFlow;
+
+use anyhow::{anyhow, Context as _};
+use cid::multihash::Code::Blake2b256;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::CborStore;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_shared::address::Address;
+use fvm_shared::state::{StateRoot, StateTreeVersion};
+use fvm_shared::{ActorID, HAMT_BIT_WIDTH};
+
+use crate::cancel::{CancellationToken, Cancelled};
+use crate::state_tree::ActorState;
+
+/// The progress of a [`run_migration`], sufficient to resume it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct MigrationCheckpoint {
+    /// The state root being migrated.
+    pub input: Cid,
+    /// The root of the output actors HAMT, holding the actors migrated so far.
+    pub output: Cid,
+    /// The key (address bytes) of the next actor to migrate, just after the last actor processed,
+    /// or `None` once every actor has been migrated.
+    pub next_key: Option<BytesKey>,
+    /// The number of actors processed so far.
+    pub processed: u64,
+}
+
+impl MigrationCheckpoint {
+    /// Returns true if every actor has been migrated.
+    pub fn is_done(&self) -> bool {
+        self.next_key.is_none()
+    }
+}
+
+/// Migrates every actor in the state tree rooted at `input`, returning the new state root.
+///
+/// Actors are migrated in HAMT iteration order, `batch_size` at a time. `migrate` is called with
+/// each actor, and returns its new state (or `None` to drop it). It must be deterministic, and
+/// must write any new state it references to `store`.
+///
+/// After each batch, the partial output is flushed to `store` and `checkpoint` is called with the
+/// progress so far; returning [`ControlFlow::Break`] interrupts the migration with an error. The
+/// migration is also interrupted if `cancel` is cancelled, failing with a
+/// [`Cancelled<MigrationCheckpoint>`](Cancelled) error holding the last checkpoint (the actors
+/// migrated since are discarded). To resume an interrupted migration, pass the last checkpoint as
+/// `resume`.
+pub fn run_migration<BS, F, C>(
+    store: &BS,
+    input: &Cid,
+    resume: Option<MigrationCheckpoint>,
+    batch_size: usize,
+    cancel: &CancellationToken,
+    mut migrate: F,
+    mut checkpoint: C,
+) -> anyhow::Result<Cid>
+where
+    BS: Blockstore,
+    F: FnMut(ActorID, ActorState) -> anyhow::Result<Option<ActorState>>,
+    C: FnMut(&MigrationCheckpoint) -> ControlFlow<()>,
+{
+    if batch_size == 0 {
+        return Err(anyhow!("migration batch size must be non-zero"));
+    }
+
+    let root: StateRoot = store
+        .get_cbor(input)?
+        .ok_or_else(|| anyhow!("failed to find state tree {}", input))?;
+    if root.version != StateTreeVersion::V5 {
+        return Err(anyhow!(
+            "unsupported state tree version: {:?}",
+            root.version
+        ));
+    }
+    let actors: Hamt<_, ActorState> =
+        Hamt::load_with_bit_width(&root.actors, store, HAMT_BIT_WIDTH)
+            .context("failed to load the actors")?;
+
+    let (mut output, mut progress): (Hamt<_, ActorState>, _) = match resume {
+        Some(progress) => {
+            if progress.input != *input {
+                return Err(anyhow!(
+                    "checkpoint is for state {}, not {}",
+                    progress.input,
+                    input
+                ));
+            }
+            let output = Hamt::load_with_bit_width(&progress.output, store, HAMT_BIT_WIDTH)
+                .context("failed to load the partial output")?;
+            (output, progress)
+        }
+        None => {
+            let mut output = Hamt::new_with_bit_width(store, HAMT_BIT_WIDTH);
+            // Visiting zero actors yields the first key, if any.
+            let (_, first) = actors.for_each_ranged(None::<&BytesKey>, Some(0), |_, _| Ok(()))?;
+            let progress = MigrationCheckpoint {
+                input: *input,
+                output: output.flush()?,
+                next_key: first,
+                processed: 0,
+            };
+            (output, progress)
+        }
+    };
+
+    while let Some(start) = progress.next_key.clone() {
+        let res = actors.for_each_ranged(Some(&start), Some(batch_size), |key, actor| {
+            cancel.check(|| ())?;
+            let id = Address::from_bytes(&key.0)?
+                .id()
+                .map_err(|_| anyhow!("non-ID address {:?} in the actors HAMT", key))?;
+            if let Some(actor) =
+                migrate(id, actor.clone()).with_context(|| format!("migrating actor {id}"))?
+            {
+                output.set(key.clone(), actor)?;
+            }
+            Ok(())
+        });
+        if cancel.is_cancelled() {
+            return Err(Cancelled { progress }.into());
+        }
+        let (processed, next_key) = res?;
+
+        progress = MigrationCheckpoint {
+            input: *input,
+            output: output.flush()?,
+            next_key,
+            processed: progress.processed + processed as u64,
+        };
+        if checkpoint(&progress).is_break() {
+            return Err(anyhow!(
+                "migration interrupted after {} actors",
+                progress.processed
+            ));
+        }
+    }
+
+    let root = StateRoot {
+        actors: progress.output,
+        ..root
+    };
+    store.put_cbor(&root, Blake2b256)
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::{from_slice, to_vec};
+    use fvm_shared::econ::TokenAmount;
+
+    use super::*;
+    use crate::state_tree::StateTree;
+    use crate::EMPTY_ARR_CID;
+
+    fn state(store: &MemoryBlockstore) -> Cid {
+        let mut tree = StateTree::new(store, StateTreeVersion::V5).unwrap();
+        for id in 100..200 {
+            let actor = ActorState::new(
+                *EMPTY_ARR_CID,
+                *EMPTY_ARR_CID,
+                TokenAmount::from_atto(id),
+                0,
+                None,
+            );
+            tree.set_actor(id, actor).unwrap();
+        }
+        tree.flush().unwrap()
+    }
+
+    /// Bumps every nonce, and drops actors with an odd ID.
+    fn migrate(id: ActorID, mut actor: ActorState) -> anyhow::Result<Option<ActorState>> {
+        actor.sequence += 1;
+        Ok((id % 2 == 0).then_some(actor))
+    }
+
+    #[test]
+    fn migrate_all() {
+        let store = MemoryBlockstore::default();
+        let input = state(&store);
+
+        let mut checkpoints = 0;
+        let output = run_migration(
+            &store,
+            &input,
+            None,
+            30,
+            &CancellationToken::new(),
+            migrate,
+            |progress| {
+                checkpoints += 1;
+                assert_eq!(progress.is_done(), progress.processed == 100);
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap();
+        assert_eq!(checkpoints, 4);
+
+        let tree = StateTree::new_from_root(&store, &output).unwrap();
+        assert_eq!(tree.get_actor(100).unwrap().unwrap().sequence, 1);
+        assert_eq!(tree.get_actor(101).unwrap(), None);
+    }
+
+    #[test]
+    fn resume() {
+        let store = MemoryBlockstore::default();
+        let input = state(&store);
+        let expected = run_migration(
+            &store,
+            &input,
+            None,
+            1000,
+            &CancellationToken::new(),
+            migrate,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+
+        // Interrupt after every other batch, persisting the checkpoint, then resume.
+        let mut saved: Option<Vec<u8>> = None;
+        let output = loop {
+            let resume = saved.as_deref().map(|s| from_slice(s).unwrap());
+            let mut batches = 0;
+            let res = run_migration(
+                &store,
+                &input,
+                resume,
+                7,
+                &CancellationToken::new(),
+                migrate,
+                |progress| {
+                    saved = Some(to_vec(progress).unwrap());
+                    batches += 1;
+                    if batches == 2 {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                },
+            );
+            match res {
+                Ok(output) => break output,
+                Err(_) => continue,
+            }
+        };
+        assert_eq!(output, expected);
+
+        // Checkpoints can only be used to resume the same migration.
+        let progress: MigrationCheckpoint = from_slice(&saved.unwrap()).unwrap();
+        assert!(progress.is_done());
+        run_migration(
+            &store,
+            &expected,
+            Some(progress),
+            7,
+            &CancellationToken::new(),
+            migrate,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn cancel() {
+        let store = MemoryBlockstore::default();
+        let input = state(&store);
+
+        // Cancel in the middle of the third batch.
+        let token = CancellationToken::new();
+        let mut migrated = 0;
+        let mut last = None;
+        let err = run_migration(
+            &store,
+            &input,
+            None,
+            10,
+            &token,
+            |id, actor| {
+                migrated += 1;
+                if migrated == 25 {
+                    token.cancel();
+                }
+                migrate(id, actor)
+            },
+            |progress| {
+                last = Some(progress.clone());
+                ControlFlow::Continue(())
+            },
+        )
+        .unwrap_err();
+        assert_eq!(migrated, 25);
+
+        // The last checkpoint is reported, and can be resumed from.
+        let progress = err
+            .downcast::<Cancelled<MigrationCheckpoint>>()
+            .unwrap()
+            .progress;
+        assert_eq!(Some(&progress), last.as_ref());
+        assert_eq!(progress.processed, 20);
+        let output = run_migration(
+            &store,
+            &input,
+            Some(progress),
+            10,
+            &CancellationToken::new(),
+            migrate,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+        let expected = run_migration(
+            &store,
+            &input,
+            None,
+            10,
+            &CancellationToken::new(),
+            migrate,
+            |_| ControlFlow::Continue(()),
+        )
+        .unwrap();
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Native (Rust) actor implementations.
+//!
+//! Embedders can register native implementations of performance-critical actors (e.g., built-in
+//! actors) by code CID with
+//! [`EnginePool::register_native_actor`](crate::engine::EnginePool::register_native_actor). When
+//! [`NetworkConfig::native_actors`](crate::machine::NetworkConfig::native_actors) is enabled, calls
+//! to actors with a registered code CID invoke the native implementation instead of the actor's
+//! Wasm code.
+//!
+//! Native actors are invoked through the same kernel as Wasm actors, so every syscall they make
+//! is validated and charged exactly as it would be from Wasm. However, no gas is charged for the
+//! execution of the native code itself, so enabling native actors changes gas usage and must be
+//! coordinated with a network upgrade.
+
+use fvm_shared::error::ExitCode;
+
+use crate::call_manager::NO_DATA_BLOCK_ID;
+use crate::kernel::{BlockId, ExecutionError};
+use crate::syscalls::error::Abort;
+
+/// A native implementation of an actor.
+pub trait NativeActor<K>: Send + Sync + 'static {
+    /// Invokes the actor with the given kernel. `params` is the ID of the parameters block in the
+    /// kernel's block registry (or [`NO_DATA_BLOCK_ID`] if there are none).
+    ///
+    /// Returns the ID of the return value block (or [`NO_DATA_BLOCK_ID`]) on success.
+    fn invoke(&self, kernel: &mut K, params: BlockId) -> Result<BlockId, NativeAbort>;
+}
+
+impl<K, F> NativeActor<K> for F
+where
+    F: Fn(&mut K, BlockId) -> Result<BlockId, NativeAbort> + Send + Sync + 'static,
+{
+    fn invoke(&self, kernel: &mut K, params: BlockId) -> Result<BlockId, NativeAbort> {
+        self(kernel, params)
+    }
+}
+
+/// Why a native actor invocation didn't return normally.
+#[derive(Debug)]
+pub enum NativeAbort {
+    /// The actor exited with the given exit code, message, and return value block, like the
+    /// `vm::exit` syscall. System exit codes are replaced with
+    /// [`ExitCode::SYS_ILLEGAL_EXIT_CODE`].
+    Exit(ExitCode, String, BlockId),
+    /// A kernel call failed, and the actor didn't handle the error. Syscall errors abort the actor
+    /// with [`ExitCode::USR_ASSERTION_FAILED`], like an unhandled error in a Wasm actor.
+    Error(ExecutionError),
+}
+
+impl From<ExecutionError> for NativeAbort {
+    fn from(e: ExecutionError) -> Self {
+        NativeAbort::Error(e)
+    }
+}
+
+impl From<NativeAbort> for Abort {
+    fn from(a: NativeAbort) -> Self {
+        match a {
+            NativeAbort::Exit(code, _, _) if !code.is_success() && code.is_system_error() => {
+                Abort::Exit(
+                    ExitCode::SYS_ILLEGAL_EXIT_CODE,
+                    format!("actor aborted with reserved exit code {}", code),
+                    NO_DATA_BLOCK_ID,
+                )
+            }
+            NativeAbort::Exit(code, message, blk) => Abort::Exit(code, message, blk),
+            NativeAbort::Error(e) => Abort::from_error(ExitCode::USR_ASSERTION_FAILED, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::SyscallError;
+
+    #[test]
+    fn aborts() {
+        let abort = Abort::from(NativeAbort::Exit(ExitCode::SYS_OUT_OF_GAS, "".into(), 1));
+        assert!(matches!(
+            abort,
+            Abort::Exit(ExitCode::SYS_ILLEGAL_EXIT_CODE, _, NO_DATA_BLOCK_ID)
+        ));
+
+        let abort = Abort::from(NativeAbort::Exit(ExitCode::USR_FORBIDDEN, "no".into(), 1));
+        assert!(matches!(abort, Abort::Exit(ExitCode::USR_FORBIDDEN, _, 1)));
+
+        let err = SyscallError::new(fvm_shared::error::ErrorNumber::NotFound, "missing");
+        let abort = Abort::from(NativeAbort::from(ExecutionError::Syscall(err)));
+        assert!(matches!(
+            abort,
+            Abort::Exit(ExitCode::USR_ASSERTION_FAILED, _, _)
+        ));
+    }
+}
@@ -0,0 +1,401 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Integrity checks over an entire state tree.
+//!
+//! [`check_state`] walks every block reachable from a state root, counting the references to each
+//! block and recording any block missing from the blockstore. It then checks every actor against
+//! the rest of the state:
+//!
+//! - Actors are keyed by ID address, and their IDs have been allocated by the init actor.
+//! - Their code is a builtin actor listed in the system actor's manifest.
+//! - Their balances are non-negative.
+//! - Their delegated addresses (if any) are `f4` addresses mapped to their IDs by the init actor,
+//!   and every delegated address mapped by the init actor belongs to the actor it's mapped to.
+//!
+//! This is a (slow) debugging aid, e.g., for validating the output of a state migration. It's
+//! also run by the conformance tests when `CHECK_STATE` is set.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::DAG_CBOR;
+use fvm_ipld_hamt::Hamt;
+use fvm_shared::address::{Address, Payload};
+use fvm_shared::commcid::{FIL_COMMITMENT_SEALED, FIL_COMMITMENT_UNSEALED};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::{ActorID, HAMT_BIT_WIDTH, IDENTITY_HASH};
+use thiserror::Error;
+
+use crate::blockstore::scan_for_links;
+use crate::init_actor::State as InitActorState;
+use crate::machine::Manifest;
+use crate::state_tree::StateTree;
+use crate::system_actor::State as SystemActorState;
+
+/// An inconsistency found by [`check_state`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StateProblem {
+    #[error("state tree key {0} isn't an ID address")]
+    NonIdKey(Address),
+    #[error("actor {0} has an ID that hasn't been allocated by the init actor")]
+    UnallocatedId(ActorID),
+    #[error("actor {id} has unknown code {code}")]
+    UnknownCode { id: ActorID, code: Cid },
+    #[error("actor {id} has negative balance {balance}")]
+    NegativeBalance { id: ActorID, balance: TokenAmount },
+    #[error("actor {id} has non-delegated delegated address {address}")]
+    InvalidDelegatedAddress { id: ActorID, address: Address },
+    #[error("actor {id}'s delegated address {address} isn't mapped to it by the init actor")]
+    UnmappedDelegatedAddress { id: ActorID, address: Address },
+    #[error("init actor maps address {address} to actor {id}, which doesn't own it")]
+    AddressMismatch { address: Address, id: ActorID },
+    #[error("init actor maps address {address} to unallocated ID {id}")]
+    DanglingAddress { address: Address, id: ActorID },
+    #[error("invalid init actor address map key {0:?}")]
+    InvalidAddressKey(Vec<u8>),
+    #[error("failed to read {what}: {error}")]
+    Unreadable { what: &'static str, error: String },
+}
+
+/// The result of [`check_state`].
+#[derive(Debug, Clone, Default)]
+pub struct StateReport {
+    /// The number of actors in the state tree.
+    pub actors: u64,
+    /// The number of distinct blocks reachable from the state root, including the root.
+    pub blocks: u64,
+    /// The total size of those blocks, in bytes.
+    pub bytes: u64,
+    /// The number of links to each reachable block from other reachable blocks (0 for the root).
+    /// Identity-hashed CIDs and sector commitments aren't blocks, so they aren't counted.
+    pub references: HashMap<Cid, u64>,
+    /// Reachable blocks missing from the blockstore.
+    pub missing: Vec<Cid>,
+    /// The inconsistencies found in the state.
+    pub problems: Vec<StateProblem>,
+}
+
+impl StateReport {
+    /// Returns true if all reachable blocks are present and no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.problems.is_empty()
+    }
+
+    /// Returns the number of blocks linked more than once.
+    pub fn shared_blocks(&self) -> usize {
+        self.references.values().filter(|&&refs| refs > 1).count()
+    }
+}
+
+/// Checks the integrity of the state tree rooted at `root`. See the [module documentation](self).
+///
+/// Inconsistencies are reported in the returned [`StateReport`]. An error is only returned if the
+/// state root itself can't be loaded.
+pub fn check_state<BS: Blockstore>(store: &BS, root: &Cid) -> anyhow::Result<StateReport> {
+    let tree = StateTree::new_from_root(store, root)
+        .map_err(|e| anyhow!("failed to load state tree {}: {:?}", root, e))?;
+
+    let mut report = StateReport::default();
+    walk_links(store, root, &mut report)?;
+    check_actors(&tree, &mut report);
+    Ok(report)
+}
+
+/// Walks the state graph, counting references to each block.
+fn walk_links<BS: Blockstore>(
+    store: &BS,
+    root: &Cid,
+    report: &mut StateReport,
+) -> anyhow::Result<()> {
+    report.references.insert(*root, 0);
+    let mut stack = vec![*root];
+    while let Some(cid) = stack.pop() {
+        let block = match store.get(&cid)? {
+            Some(block) => block,
+            None => {
+                report.missing.push(cid);
+                continue;
+            }
+        };
+        report.blocks += 1;
+        report.bytes += block.len() as u64;
+
+        if cid.codec() != DAG_CBOR {
+            continue;
+        }
+        let res = scan_for_links(&mut Cursor::new(&block), |link| {
+            if link.hash().code() == IDENTITY_HASH
+                || matches!(
+                    link.codec(),
+                    FIL_COMMITMENT_SEALED | FIL_COMMITMENT_UNSEALED
+                )
+            {
+                return Ok(());
+            }
+            match report.references.entry(link) {
+                Entry::Occupied(mut e) => *e.get_mut() += 1,
+                Entry::Vacant(e) => {
+                    e.insert(1);
+                    stack.push(link);
+                }
+            }
+            Ok(())
+        });
+        if let Err(e) = res {
+            report.problems.push(StateProblem::Unreadable {
+                what: "block",
+                error: format!("{}: {}", cid, e),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_actors<BS: Blockstore>(tree: &StateTree<&BS>, report: &mut StateReport) {
+    fn unreadable(what: &'static str, e: impl std::fmt::Display) -> StateProblem {
+        StateProblem::Unreadable {
+            what,
+            error: e.to_string(),
+        }
+    }
+
+    let mut problems = Vec::new();
+    let manifest = match SystemActorState::load(tree) {
+        Ok((state, _)) => Manifest::load(tree.store(), &state.builtin_actors, 1)
+            .map_err(|e| problems.push(unreadable("builtin actor manifest", format!("{:#}", e))))
+            .ok(),
+        Err(e) => {
+            problems.push(unreadable("system actor state", e));
+            None
+        }
+    };
+    let init = InitActorState::load(tree)
+        .map(|(state, _)| state)
+        .map_err(|e| problems.push(unreadable("init actor state", e)))
+        .ok();
+
+    // The delegated addresses of all actors, and whether the init actor maps them.
+    let mut delegated: HashMap<ActorID, (Address, bool)> = HashMap::new();
+    let res = tree.for_each(|key, actor| {
+        report.actors += 1;
+        let id = match key.payload() {
+            Payload::ID(id) => *id,
+            _ => {
+                problems.push(StateProblem::NonIdKey(key));
+                return Ok(());
+            }
+        };
+        if matches!(&init, Some(init) if id >= init.next_id) {
+            problems.push(StateProblem::UnallocatedId(id));
+        }
+        if matches!(&manifest, Some(manifest) if manifest.id_by_code(&actor.code) == 0) {
+            problems.push(StateProblem::UnknownCode {
+                id,
+                code: actor.code,
+            });
+        }
+        if actor.balance.is_negative() {
+            problems.push(StateProblem::NegativeBalance {
+                id,
+                balance: actor.balance.clone(),
+            });
+        }
+        match actor.delegated_address {
+            Some(address) if matches!(address.payload(), Payload::Delegated(_)) => {
+                delegated.insert(id, (address, false));
+            }
+            Some(address) => problems.push(StateProblem::InvalidDelegatedAddress { id, address }),
+            None => {}
+        }
+        Ok(())
+    });
+    if let Err(e) = res {
+        problems.push(unreadable("state tree", format!("{:#}", e)));
+    }
+
+    if let Some(init) = init {
+        let res = Hamt::<_, ActorID>::load_with_bit_width(
+            &init.address_map,
+            tree.store(),
+            HAMT_BIT_WIDTH,
+        )
+        .and_then(|map| {
+            map.for_each(|key, &id| {
+                let address = match Address::from_bytes(&key.0) {
+                    Ok(address) if !matches!(address.payload(), Payload::ID(_)) => address,
+                    _ => {
+                        problems.push(StateProblem::InvalidAddressKey(key.0.clone()));
+                        return Ok(());
+                    }
+                };
+                if id >= init.next_id {
+                    problems.push(StateProblem::DanglingAddress { address, id });
+                } else if let Payload::Delegated(_) = address.payload() {
+                    // Deleted actors keep their mappings, so only check actors that exist.
+                    match delegated.get_mut(&id) {
+                        Some((owned, mapped)) if *owned == address => *mapped = true,
+                        _ => {
+                            if tree.get_actor(id).ok().flatten().is_some() {
+                                problems.push(StateProblem::AddressMismatch { address, id });
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            })
+        });
+        if let Err(e) = res {
+            problems.push(unreadable("init actor address map", e));
+        }
+        let mut unmapped: Vec<_> = delegated
+            .into_iter()
+            .filter(|(_, (_, mapped))| !mapped)
+            .collect();
+        unmapped.sort_by_key(|(id, _)| *id);
+        problems.extend(
+            unmapped
+                .into_iter()
+                .map(|(id, (address, _))| StateProblem::UnmappedDelegatedAddress { id, address }),
+        );
+    }
+
+    report.problems.extend(problems);
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Code::Blake2b256;
+    use cid::multihash::MultihashDigest;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::CborStore;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+    use crate::init_actor::INIT_ACTOR_ID;
+    use crate::state_tree::ActorState;
+    use crate::system_actor::SYSTEM_ACTOR_ID;
+    use crate::EMPTY_ARR_CID;
+
+    /// Builds a state tree with the system and init actors, and an Ethereum account with a
+    /// delegated address.
+    fn state(store: &MemoryBlockstore) -> (StateTree<&MemoryBlockstore>, Manifest) {
+        let codes: Vec<_> = Manifest::DUMMY_CODES
+            .iter()
+            .map(|(name, code)| (name.to_string(), *code))
+            .collect();
+        let manifest = Manifest::new(codes.clone()).unwrap();
+        let builtin_actors = store.put_cbor(&codes, Blake2b256).unwrap();
+
+        let mut tree = StateTree::new(store, StateTreeVersion::V5).unwrap();
+        let system_state = store
+            .put_cbor(&SystemActorState { builtin_actors }, Blake2b256)
+            .unwrap();
+        let system = ActorState::new(
+            *manifest.get_system_code(),
+            system_state,
+            TokenAmount::default(),
+            0,
+            None,
+        );
+        tree.set_actor(SYSTEM_ACTOR_ID, system).unwrap();
+
+        let mut init_state = InitActorState::new_test(store);
+        let delegated = Address::new_delegated(10, &[1; 20]).unwrap();
+        let id = init_state.map_address_to_new_id(store, &delegated).unwrap();
+        let init_state = store.put_cbor(&init_state, Blake2b256).unwrap();
+        let init = ActorState::new(
+            *manifest.get_init_code(),
+            init_state,
+            TokenAmount::default(),
+            0,
+            None,
+        );
+        tree.set_actor(INIT_ACTOR_ID, init).unwrap();
+
+        let account = ActorState::new_empty(*manifest.get_ethaccount_code(), Some(delegated));
+        tree.set_actor(id, account).unwrap();
+        (tree, manifest)
+    }
+
+    #[test]
+    fn consistent() {
+        let store = MemoryBlockstore::default();
+        let (mut tree, _) = state(&store);
+        let root = tree.flush().unwrap();
+
+        let report = check_state(&store, &root).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.actors, 3);
+        assert_eq!(report.references[&root], 0);
+        // The (empty) state tree info and the account's empty state are the same block.
+        assert_eq!(report.references[&EMPTY_ARR_CID], 2);
+        assert_eq!(report.shared_blocks(), 1);
+    }
+
+    #[test]
+    fn problems() {
+        let store = MemoryBlockstore::default();
+        let (mut tree, manifest) = state(&store);
+        let account_code = *manifest.get_account_code();
+
+        let mut unallocated = ActorState::new_empty(account_code, None);
+        unallocated.balance = TokenAmount::from_atto(-1);
+        tree.set_actor(101, unallocated).unwrap();
+        let unknown = Cid::new_v1(DAG_CBOR, Blake2b256.digest(b"unknown"));
+        tree.set_actor(50, ActorState::new_empty(unknown, None))
+            .unwrap();
+        let unmapped = Address::new_delegated(10, &[2; 20]).unwrap();
+        tree.set_actor(51, ActorState::new_empty(account_code, Some(unmapped)))
+            .unwrap();
+        let root = tree.flush().unwrap();
+
+        let report = check_state(&store, &root).unwrap();
+        let expected = [
+            StateProblem::UnallocatedId(101),
+            StateProblem::NegativeBalance {
+                id: 101,
+                balance: TokenAmount::from_atto(-1),
+            },
+            StateProblem::UnknownCode {
+                id: 50,
+                code: unknown,
+            },
+            StateProblem::UnmappedDelegatedAddress {
+                id: 51,
+                address: unmapped,
+            },
+        ];
+        assert_eq!(report.problems.len(), expected.len(), "{:?}", report);
+        for problem in &expected {
+            assert!(report.problems.contains(problem), "missing {}", problem);
+        }
+        assert_eq!(report.references[&EMPTY_ARR_CID], 5);
+    }
+
+    #[test]
+    fn missing_blocks() {
+        let store = MemoryBlockstore::default();
+        let (mut tree, manifest) = state(&store);
+        let head = Cid::new_v1(DAG_CBOR, Blake2b256.digest(b"missing"));
+        let actor = ActorState::new(
+            *manifest.get_account_code(),
+            head,
+            TokenAmount::default(),
+            0,
+            None,
+        );
+        tree.set_actor(50, actor).unwrap();
+        let root = tree.flush().unwrap();
+
+        let report = check_state(&store, &root).unwrap();
+        assert!(report.problems.is_empty(), "{:?}", report);
+        assert_eq!(report.missing, vec![head]);
+        assert!(!report.is_ok());
+
+        assert!(check_state(&store, &head).is_err());
+    }
+}
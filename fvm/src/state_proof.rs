@@ -0,0 +1,216 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Inclusion proofs for state under a state root.
+//!
+//! A [`StateProof`] is the set of IPLD blocks (state root, HAMT/AMT nodes, actor state objects,
+//! etc.) visited while looking up some value under a state root. Anyone holding the proof can
+//! re-run the same lookup against _only_ those blocks, after checking that every block hashes to
+//! its CID, and thereby convince themselves of the value without access to the full state.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Context as _};
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+use fvm_shared::ActorID;
+
+use crate::state_tree::{ActorState, StateTree};
+
+/// An inclusion proof for some value under a state root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateProof {
+    /// The state root the proof is anchored to.
+    pub root: Cid,
+    /// The blocks visited while looking up the proven value, in the order they were visited.
+    pub blocks: Vec<(Cid, Vec<u8>)>,
+}
+
+impl StateProof {
+    /// Generates a proof by running the lookup `f` against `store` starting from `root`. Every
+    /// block `f` reads is recorded into the proof.
+    ///
+    /// Returns the lookup's result along with the proof.
+    pub fn generate<BS, F, T>(store: &BS, root: Cid, f: F) -> anyhow::Result<(T, StateProof)>
+    where
+        BS: Blockstore,
+        F: FnOnce(&RecordingBlockstore<&BS>, &Cid) -> anyhow::Result<T>,
+    {
+        let recorder = RecordingBlockstore::new(store);
+        let value = f(&recorder, &root)?;
+        Ok((
+            value,
+            StateProof {
+                root,
+                blocks: recorder.into_blocks(),
+            },
+        ))
+    }
+
+    /// Generates a proof of an actor's state (code, head, nonce, balance, and delegated address)
+    /// under the state tree rooted at `root`. If the actor doesn't exist, the proof attests to its
+    /// absence.
+    pub fn generate_for_actor<BS>(
+        store: &BS,
+        root: Cid,
+        id: ActorID,
+    ) -> anyhow::Result<(Option<ActorState>, StateProof)>
+    where
+        BS: Blockstore,
+    {
+        Self::generate(store, root, |bs, root| lookup_actor(bs, root, id))
+    }
+
+    /// Verifies the proof by checking that every block matches its CID, then re-running the
+    /// lookup `f` against _only_ the blocks in the proof.
+    ///
+    /// The lookup fails if it needs any block not included in the proof.
+    pub fn verify<F, T>(&self, f: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&MemoryBlockstore, &Cid) -> anyhow::Result<T>,
+    {
+        let store = MemoryBlockstore::new();
+        for (cid, data) in &self.blocks {
+            let code = Code::try_from(cid.hash().code())
+                .with_context(|| format!("unsupported hash function in proof block {cid}"))?;
+            if code.digest(data) != *cid.hash() {
+                return Err(anyhow!("proof block {cid} doesn't match its CID"));
+            }
+            store.put_keyed(cid, data)?;
+        }
+        f(&store, &self.root)
+    }
+
+    /// Verifies a proof generated by [`StateProof::generate_for_actor`], returning the proven
+    /// actor state (or `None` if the proof attests to the actor's absence).
+    pub fn verify_actor(&self, id: ActorID) -> anyhow::Result<Option<ActorState>> {
+        self.verify(|bs, root| lookup_actor(bs, root, id))
+    }
+}
+
+fn lookup_actor<BS: Blockstore>(
+    store: BS,
+    root: &Cid,
+    id: ActorID,
+) -> anyhow::Result<Option<ActorState>> {
+    StateTree::new_from_root(store, root)
+        .and_then(|tree| tree.get_actor(id))
+        .map_err(|e| anyhow!("failed to look up actor {id}: {e}"))
+}
+
+/// A blockstore that records every block read through it. Writes are passed through to the
+/// underlying store and are not recorded.
+#[derive(Debug)]
+pub struct RecordingBlockstore<BS> {
+    base: BS,
+    seen: RefCell<HashSet<Cid>>,
+    blocks: RefCell<Vec<(Cid, Vec<u8>)>>,
+}
+
+impl<BS> RecordingBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    pub fn new(base: BS) -> Self {
+        Self {
+            base,
+            seen: Default::default(),
+            blocks: Default::default(),
+        }
+    }
+
+    /// Returns the recorded blocks, in the order they were first read.
+    pub fn into_blocks(self) -> Vec<(Cid, Vec<u8>)> {
+        self.blocks.into_inner()
+    }
+}
+
+impl<BS> Blockstore for RecordingBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        let block = self.base.get(k)?;
+        if let Some(data) = &block {
+            if self.seen.borrow_mut().insert(*k) {
+                self.blocks.borrow_mut().push((*k, data.clone()));
+            }
+        }
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.base.put_keyed(k, block)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> anyhow::Result<()>
+    where
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.base.put_many_keyed(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Multihash;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::state::StateTreeVersion;
+    use fvm_shared::{IDENTITY_HASH, IPLD_RAW};
+
+    use super::StateProof;
+    use crate::state_tree::{ActorState, StateTree};
+
+    fn code_cid() -> cid::Cid {
+        cid::Cid::new_v1(
+            IPLD_RAW,
+            Multihash::wrap(IDENTITY_HASH, b"fil/test/dummy").unwrap(),
+        )
+    }
+
+    fn make_tree(store: &MemoryBlockstore) -> cid::Cid {
+        let mut tree = StateTree::new(store, StateTreeVersion::V5).unwrap();
+        for id in 0..200 {
+            let act = ActorState::new(code_cid(), code_cid(), Default::default(), id, None);
+            tree.set_actor(id, act).unwrap();
+        }
+        tree.flush().unwrap()
+    }
+
+    #[test]
+    fn actor_proof_roundtrip() {
+        let store = MemoryBlockstore::default();
+        let root = make_tree(&store);
+
+        let (actor, proof) = StateProof::generate_for_actor(&store, root, 42).unwrap();
+        let actor = actor.expect("actor should exist");
+        assert_eq!(actor.sequence, 42);
+        assert_eq!(proof.root, root);
+        assert_eq!(proof.verify_actor(42).unwrap(), Some(actor));
+
+        // The proof doesn't cover unrelated actors.
+        assert!(proof.verify_actor(199).is_err());
+    }
+
+    #[test]
+    fn actor_absence_proof() {
+        let store = MemoryBlockstore::default();
+        let root = make_tree(&store);
+
+        let (actor, proof) = StateProof::generate_for_actor(&store, root, 1000).unwrap();
+        assert_eq!(actor, None);
+        assert_eq!(proof.verify_actor(1000).unwrap(), None);
+    }
+
+    #[test]
+    fn tampered_proof() {
+        let store = MemoryBlockstore::default();
+        let root = make_tree(&store);
+
+        let (_, mut proof) = StateProof::generate_for_actor(&store, root, 42).unwrap();
+        proof.blocks.last_mut().unwrap().1.push(0);
+        assert!(proof.verify_actor(42).is_err());
+    }
+}
@@ -5,27 +5,47 @@
 use std::borrow::Borrow;
 use std::cell::RefCell;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::hash::Hash;
+use std::io::{Cursor, Write};
+use std::ops::ControlFlow;
 
 use anyhow::{anyhow, Context as _};
 use cid::{multihash, Cid};
+use futures::io::AllowStdIo;
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_car::CarHeader;
 use fvm_ipld_encoding::tuple::*;
-use fvm_ipld_encoding::CborStore;
+use fvm_ipld_encoding::{to_vec, CborStore, DAG_CBOR};
 use fvm_ipld_hamt::Hamt;
-use fvm_shared::address::{Address, Payload};
+use fvm_shared::address::{Address, Payload, FIRST_NON_SINGLETON_ADDR};
+use fvm_shared::commcid::{FIL_COMMITMENT_SEALED, FIL_COMMITMENT_UNSEALED};
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::state::{StateInfo0, StateRoot, StateTreeVersion};
-use fvm_shared::{ActorID, HAMT_BIT_WIDTH};
+use fvm_shared::{ActorID, HAMT_BIT_WIDTH, IDENTITY_HASH};
 use num_traits::Zero;
 #[cfg(feature = "arb")]
 use quickcheck::Arbitrary;
 
+use crate::blockstore::scan_for_links;
+use crate::cancel::CancellationToken;
+use crate::determinism::Map;
 use crate::init_actor::State as InitActorState;
 use crate::kernel::{ClassifyResult, ExecutionError, Result};
 use crate::{syscall_error, EMPTY_ARR_CID};
 
+/// The number of blocks written between progress callbacks in [`StateTree::export_car`].
+pub const EXPORT_PROGRESS_INTERVAL: u64 = 1000;
+
+/// Progress of a [`StateTree::export_car`] operation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportProgress {
+    /// The number of blocks written so far.
+    pub blocks: u64,
+    /// The number of bytes written so far, including the CAR header and framing.
+    pub bytes: u64,
+}
+
 /// State tree implementation using hamt. This structure is not threadsafe and should only be used
 /// in sync contexts.
 pub struct StateTree<S> {
@@ -52,7 +72,7 @@ pub struct StateTree<S> {
 /// 1. The user can call `history_len` to record the current history length.
 /// 2. The user can _later_ call `rollback(previous_length)` to rollback to the state in step 1.
 struct HistoryMap<K, V> {
-    map: HashMap<K, V>,
+    map: Map<K, V>,
     history: Vec<(K, Option<V>)>,
 }
 
@@ -122,6 +142,11 @@ where
         self.history.clear();
     }
 
+    /// Iterate over the current map.
+    fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map.iter()
+    }
+
     /// Iterate mutably over the current map.
     fn iter_mut(&mut self) -> impl Iterator<Item = (&K, &mut V)> {
         self.map.iter_mut()
@@ -234,6 +259,52 @@ where
         }
     }
 
+    /// Discards all changes made since the last flush, and reloads the state tree from `root`
+    /// (which must be a state tree of the same version, in the store). Fails if called inside a
+    /// transaction.
+    pub fn reset(&mut self, root: &Cid) -> Result<()> {
+        if self.in_transaction() {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "cannot reset while inside of a transaction",
+            )));
+        }
+        let StateRoot {
+            version,
+            info,
+            actors,
+        } = match self.store().get_cbor(root) {
+            Ok(Some(state_root)) => state_root,
+            Ok(None) => {
+                return Err(ExecutionError::Fatal(anyhow!(
+                    "failed to find state tree {}",
+                    root
+                )))
+            }
+            Err(e) => {
+                return Err(ExecutionError::Fatal(anyhow!(
+                    "failed to load state tree {}: {}",
+                    root,
+                    e
+                )))
+            }
+        };
+        if version != self.version {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "cannot reset a state tree of version {:?} to version {:?}",
+                self.version,
+                version
+            )));
+        }
+        self.hamt
+            .set_root(&actors)
+            .context("failed to load state tree")
+            .or_fatal()?;
+        self.info = Some(info);
+        self.actor_cache = Default::default();
+        self.resolve_cache = Default::default();
+        Ok(())
+    }
+
     /// Retrieve store reference to modify db.
     pub fn store(&self) -> &S {
         self.hamt.store()
@@ -305,6 +376,16 @@ where
         Ok(Some(a))
     }
 
+    /// Returns true if the actor with the given ID has been deleted, i.e., the ID was allocated by
+    /// the init actor but no longer has an actor. Singleton IDs are never considered deleted.
+    pub fn is_tombstone(&self, id: ActorID) -> Result<bool> {
+        if id < FIRST_NON_SINGLETON_ADDR || self.get_actor(id)?.is_some() {
+            return Ok(false);
+        }
+        let (state, _) = InitActorState::load(self)?;
+        Ok(id < state.next_id)
+    }
+
     /// Delete actor identified by the supplied ID. Returns no error if the actor doesn't exist.
     pub fn delete_actor(&mut self, id: ActorID) -> Result<()> {
         self.assert_writable()?;
@@ -440,7 +521,7 @@ where
             }
         }
 
-        let root = self.hamt.flush().or_fatal()?;
+        let root = self.hamt.flush_batched().or_fatal()?;
 
         match self.version {
             StateTreeVersion::V0 => Ok(root),
@@ -462,6 +543,20 @@ where
         }
     }
 
+    /// Returns the actors changed since the last flush, in ID order, with their new state (or
+    /// `None` if they were deleted).
+    pub fn dirty_actors(&self) -> Vec<(ActorID, Option<ActorState>)> {
+        let mut dirty: Vec<_> = self
+            .actor_cache
+            .borrow()
+            .iter()
+            .filter(|(_, entry)| entry.dirty)
+            .map(|(&id, entry)| (id, entry.actor.clone()))
+            .collect();
+        dirty.sort_unstable_by_key(|(id, _)| *id);
+        dirty
+    }
+
     /// Consumes this StateTree and returns the Blockstore it owns via the HAMT.
     pub fn into_store(self) -> S {
         self.hamt.into_store()
@@ -482,6 +577,110 @@ where
         self.read_only_layers > 0
     }
 
+    /// Flushes the state tree, then streams the entire state DAG reachable from the new root into
+    /// `writer` as a CARv1 file. Returns the exported root.
+    ///
+    /// Every block is written exactly once, parents before children. `progress` is called every
+    /// [`EXPORT_PROGRESS_INTERVAL`] blocks and once more when the export completes; returning
+    /// [`ControlFlow::Break`] cancels the export with an error.
+    ///
+    /// Identity-hashed CIDs (e.g., builtin actor code CIDs) are inlined and sector commitments
+    /// aren't stored in the blockstore, so neither is exported.
+    pub fn export_car<W, F>(&mut self, writer: W, progress: F) -> Result<Cid>
+    where
+        W: Write + Send,
+        F: FnMut(ExportProgress) -> ControlFlow<()>,
+    {
+        self.export_car_cancellable(writer, &CancellationToken::new(), progress)
+    }
+
+    /// Like [`export_car`](Self::export_car), but the export is also cancelled if `cancel` is,
+    /// failing with a [`Cancelled<ExportProgress>`](crate::cancel::Cancelled) error holding the progress so far.
+    pub fn export_car_cancellable<W, F>(
+        &mut self,
+        writer: W,
+        cancel: &CancellationToken,
+        mut progress: F,
+    ) -> Result<Cid>
+    where
+        W: Write + Send,
+        F: FnMut(ExportProgress) -> ControlFlow<()>,
+    {
+        let root = self.flush()?;
+        let header = CarHeader::from(vec![root]);
+        let mut stats = ExportProgress {
+            blocks: 0,
+            bytes: car_frame_len(to_vec(&header).or_fatal()?.len()),
+        };
+
+        let store = self.store();
+        let mut seen = HashSet::from([root]);
+        let mut stack = vec![root];
+        // Whether to report progress before reading the next block, i.e., once the previous block
+        // has been written.
+        let mut report = false;
+        let mut next_block = || -> Result<Option<(Cid, Vec<u8>)>> {
+            if std::mem::take(&mut report) && progress(stats).is_break() {
+                return Err(ExecutionError::Fatal(anyhow!("state export cancelled")));
+            }
+            while let Some(cid) = stack.pop() {
+                cancel
+                    .check(|| stats)
+                    .map_err(|e| ExecutionError::Fatal(e.into()))?;
+                if cid.hash().code() == IDENTITY_HASH
+                    || matches!(cid.codec(), FIL_COMMITMENT_SEALED | FIL_COMMITMENT_UNSEALED)
+                {
+                    continue;
+                }
+                let block = store
+                    .get(&cid)
+                    .or_fatal()?
+                    .ok_or_else(|| anyhow!("missing block {cid} while exporting state"))
+                    .or_fatal()?;
+
+                if cid.codec() == DAG_CBOR {
+                    scan_for_links(&mut Cursor::new(&block), |link| {
+                        if seen.insert(link) {
+                            stack.push(link);
+                        }
+                        Ok(())
+                    })
+                    .or_fatal()?;
+                }
+
+                stats.bytes += car_frame_len(cid.to_bytes().len() + block.len());
+                stats.blocks += 1;
+                report = stats.blocks % EXPORT_PROGRESS_INTERVAL == 0;
+                return Ok(Some((cid, block)));
+            }
+            Ok(None)
+        };
+
+        // The CAR writer takes an infallible stream, so we stop it on the first error, and return
+        // that error once it's done.
+        let mut error = None;
+        let mut blocks = futures::stream::iter(std::iter::from_fn(|| match next_block() {
+            Ok(block) => block,
+            Err(e) => {
+                error = Some(e);
+                None
+            }
+        }));
+        let written = futures::executor::block_on(
+            header.write_stream_async(&mut AllowStdIo::new(writer), &mut blocks),
+        );
+        drop(blocks);
+        if let Some(e) = error {
+            return Err(e);
+        }
+        written.or_fatal()?;
+
+        if progress(stats).is_break() {
+            return Err(ExecutionError::Fatal(anyhow!("state export cancelled")));
+        }
+        Ok(root)
+    }
+
     fn assert_writable(&self) -> Result<()> {
         if self.is_read_only() {
             Err(syscall_error!(ReadOnly; "cannot mutate state while in read-only mode").into())
@@ -491,6 +690,12 @@ where
     }
 }
 
+/// Returns the length of a varint length-prefixed CAR frame holding `len` bytes.
+fn car_frame_len(len: usize) -> u64 {
+    let bits = usize::BITS - len.leading_zeros();
+    (bits.max(1) as u64 + 6) / 7 + len as u64
+}
+
 /// State of all actor implementations.
 #[derive(PartialEq, Eq, Clone, Debug, Serialize_tuple, Deserialize_tuple)]
 pub struct ActorState {
@@ -652,20 +857,30 @@ pub mod json {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+    use std::ops::ControlFlow;
+
     use cid::multihash::Code::Blake2b256;
     use cid::multihash::Multihash;
     use cid::Cid;
-    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_car::CarReader;
     use fvm_ipld_encoding::{CborStore, DAG_CBOR};
     use fvm_shared::address::{Address, SECP_PUB_LEN};
     use fvm_shared::econ::TokenAmount;
     use fvm_shared::state::StateTreeVersion;
     use fvm_shared::{ActorID, IDENTITY_HASH, IPLD_RAW};
     use lazy_static::lazy_static;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
 
-    use super::HistoryMap;
+    use super::{ExportProgress, HistoryMap};
+    use crate::cancel::{CancellationToken, Cancelled};
+    use crate::determinism::set_hasher_seed;
     use crate::init_actor;
     use crate::init_actor::INIT_ACTOR_ID;
+    use crate::kernel::ExecutionError;
     use crate::state_tree::{ActorState, StateTree};
 
     lazy_static! {
@@ -703,6 +918,98 @@ mod tests {
         assert_eq!(tree.get_actor(actor_id).unwrap().unwrap(), act_a);
     }
 
+    #[test]
+    fn flush_is_order_independent() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        for id in 0..100 {
+            let act = ActorState::new(empty_cid(), empty_cid(), Default::default(), id, None);
+            tree.set_actor(id, act).unwrap();
+        }
+        let base = tree.flush().unwrap();
+
+        // Delete some actors, and update or create the others.
+        let ops: Vec<(ActorID, bool)> = (0..200).map(|id| (id, id < 100 && id % 3 == 0)).collect();
+        let apply = |seed: u64| {
+            // The tree's caches iterate in a different order under every seed, and so do the
+            // operations.
+            set_hasher_seed(seed);
+            let mut ops = ops.clone();
+            ops.shuffle(&mut StdRng::seed_from_u64(seed));
+            let mut tree = StateTree::new_from_root(&store, &base).unwrap();
+            for (id, delete) in ops {
+                if delete {
+                    tree.delete_actor(id).unwrap();
+                } else {
+                    let act =
+                        ActorState::new(empty_cid(), empty_cid(), Default::default(), id + 1, None);
+                    tree.set_actor(id, act).unwrap();
+                }
+            }
+            let dirty = tree.dirty_actors();
+            (tree.flush().unwrap(), dirty)
+        };
+
+        let expected = apply(0);
+        for seed in 1..16 {
+            assert_eq!(apply(seed), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn export_car() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        for id in 0..100 {
+            let act = ActorState::new(empty_cid(), empty_cid(), Default::default(), id, None);
+            tree.set_actor(id, act).unwrap();
+        }
+
+        let mut car = Vec::new();
+        let mut last = ExportProgress::default();
+        let root = tree
+            .export_car(&mut car, |p| {
+                last = p;
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        assert_eq!(last.bytes, car.len() as u64);
+
+        let mut reader = futures::executor::block_on(CarReader::new(&car[..])).unwrap();
+        assert_eq!(reader.header.roots, vec![root]);
+        assert_eq!(reader.header.version, 1);
+
+        let mut exported = HashSet::new();
+        while let Some(block) = futures::executor::block_on(reader.next_block()).unwrap() {
+            assert_eq!(store.get(&block.cid).unwrap(), Some(block.data));
+            assert!(
+                exported.insert(block.cid),
+                "block {} exported twice",
+                block.cid
+            );
+        }
+        assert!(exported.contains(&root));
+        assert_eq!(exported.len() as u64, last.blocks);
+    }
+
+    #[test]
+    fn export_car_cancel() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        let res = tree.export_car(Vec::new(), |_| ControlFlow::Break(()));
+        assert!(matches!(res, Err(ExecutionError::Fatal(_))));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        match tree.export_car_cancellable(Vec::new(), &token, |_| ControlFlow::Continue(())) {
+            Err(ExecutionError::Fatal(e)) => {
+                let cancelled = e.downcast_ref::<Cancelled<ExportProgress>>().unwrap();
+                assert_eq!(cancelled.progress.blocks, 0);
+            }
+            _ => panic!("expected the export to be cancelled"),
+        }
+    }
+
     #[test]
     fn delete_actor() {
         let store = MemoryBlockstore::default();
@@ -716,6 +1023,46 @@ mod tests {
         assert_eq!(tree.get_actor(actor_id).unwrap(), None);
     }
 
+    #[test]
+    fn dirty_actors() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        let act = |seq| ActorState::new(empty_cid(), empty_cid(), Default::default(), seq, None);
+        tree.set_actor(2, act(0)).unwrap();
+        tree.set_actor(1, act(0)).unwrap();
+        tree.flush().unwrap();
+        assert!(tree.dirty_actors().is_empty());
+
+        // Reads don't dirty actors.
+        tree.get_actor(2).unwrap();
+        tree.set_actor(3, act(1)).unwrap();
+        tree.delete_actor(1).unwrap();
+        assert_eq!(tree.dirty_actors(), vec![(1, None), (3, Some(act(1)))]);
+    }
+
+    #[test]
+    fn reset() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        let act = |seq| ActorState::new(empty_cid(), empty_cid(), Default::default(), seq, None);
+        tree.set_actor(1, act(0)).unwrap();
+        let root = tree.flush().unwrap();
+
+        tree.set_actor(1, act(1)).unwrap();
+        tree.set_actor(2, act(0)).unwrap();
+        tree.flush().unwrap();
+        tree.set_actor(3, act(0)).unwrap();
+
+        tree.reset(&root).unwrap();
+        assert_eq!(tree.get_actor(1).unwrap(), Some(act(0)));
+        assert_eq!(tree.get_actor(2).unwrap(), None);
+        assert_eq!(tree.get_actor(3).unwrap(), None);
+        assert_eq!(tree.flush().unwrap(), root);
+
+        tree.begin_transaction(false);
+        assert!(tree.reset(&root).is_err());
+    }
+
     #[test]
     fn get_set_non_id() {
         let store = MemoryBlockstore::default();
@@ -764,6 +1111,41 @@ mod tests {
         assert_eq!(assigned_addr, 100);
     }
 
+    #[test]
+    fn tombstones() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V5).unwrap();
+        let init_state = init_actor::State::new_test(&store);
+        let state_cid = tree.store().put_cbor(&init_state, Blake2b256).unwrap();
+        let act_s = ActorState::new(
+            *DUMMY_INIT_ACTOR_CODE_ID,
+            state_cid,
+            Default::default(),
+            1,
+            None,
+        );
+        tree.set_actor(INIT_ACTOR_ID, act_s).unwrap();
+
+        let addr = Address::new_secp256k1(&[2; SECP_PUB_LEN]).unwrap();
+        let id = tree.register_new_address(&addr).unwrap();
+        let act_s = ActorState::new(
+            *DUMMY_ACCOUNT_ACTOR_CODE_ID,
+            empty_cid(),
+            Default::default(),
+            0,
+            None,
+        );
+        tree.set_actor(id, act_s).unwrap();
+
+        // Live actors, unassigned IDs, and singletons aren't tombstones.
+        assert!(!tree.is_tombstone(id).unwrap());
+        assert!(!tree.is_tombstone(id + 1).unwrap());
+        assert!(!tree.is_tombstone(50).unwrap());
+
+        tree.delete_actor(id).unwrap();
+        assert!(tree.is_tombstone(id).unwrap());
+    }
+
     #[test]
     fn test_transactions() {
         let store = MemoryBlockstore::default();
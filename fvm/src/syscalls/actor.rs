@@ -129,6 +129,19 @@ pub fn get_code_cid_for_type(
     context.memory.write_cid(&k, obuf_off, obuf_len)
 }
 
+/// Looks up the metadata of the given builtin actor code.
+pub fn get_code_info(
+    context: Context<'_, impl Kernel>,
+    code_cid_off: u32, // Cid
+) -> Result<sys::out::actor::CodeInfo> {
+    let cid = context.memory.read_cid(code_cid_off)?;
+    let info = context.kernel.get_code_info(&cid)?;
+    Ok(sys::out::actor::CodeInfo {
+        size: info.size as u64,
+        abi_version: info.abi_version,
+    })
+}
+
 #[cfg(feature = "m2-native")]
 pub fn install_actor(
     context: Context<'_, impl Kernel>,
@@ -145,3 +158,7 @@ pub fn balance_of(context: Context<'_, impl Kernel>, actor_id: u64) -> Result<sy
         .context("balance exceeds u128 limit")
         .or_fatal()
 }
+
+pub fn nonce_of(context: Context<'_, impl Kernel>, actor_id: u64) -> Result<u64> {
+    context.kernel.nonce_of(actor_id)
+}
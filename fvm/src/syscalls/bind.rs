@@ -1,23 +1,31 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::any::Any;
 use std::mem;
+use std::sync::Arc;
 
+use anyhow::anyhow;
 use fvm_shared::error::ErrorNumber;
 use fvm_shared::sys::SyscallSafe;
+use num_traits::FromPrimitive;
 use wasmtime::{Caller, Linker, WasmTy};
 
 use super::context::Memory;
 use super::error::Abort;
-use super::{charge_for_exec, update_gas_available, Context, InvocationData};
+use super::{charge_for_exec, update_gas_available, Context, Determinism, InvocationData};
+use crate::audit::{AuditLog, SyscallOutcome};
 use crate::call_manager::backtrace;
+use crate::gas::Gas;
+use crate::kernel::replay::ReplayKernel;
 use crate::kernel::{self, ExecutionError, Kernel, SyscallError};
+use crate::machine::Machine;
 
 /// Binds syscalls to a linker, converting the returned error according to the syscall convention:
 ///
 /// 1. If the error is a syscall error, it's returned as the first return value.
 /// 2. If the error is a fatal error, a Trap is returned.
 pub(super) trait BindSyscall<Args, Ret, Func> {
-    /// Bind a syscall to the linker.
+    /// Bind a syscall to the linker, in the given [`Determinism`] domain.
     ///
     /// 1. The return type will be automatically adjusted to return `Result<u32, Trap>` where
     /// `u32` is the error code.
@@ -39,12 +47,13 @@ pub(super) trait BindSyscall<Args, Ret, Func> {
     /// }
     /// let engine = wasmtime::Engine::default();
     /// let mut linker = wasmtime::Linker::new(&engine);
-    /// linker.bind("my_module", "zero", my_module::zero);
+    /// linker.bind("my_module", "zero", Determinism::Pure, my_module::zero);
     /// ```
     fn bind(
         &mut self,
         module: &'static str,
         name: &'static str,
+        determinism: Determinism,
         syscall: Func,
     ) -> anyhow::Result<&mut Self>;
 }
@@ -86,7 +95,7 @@ where
     }
 }
 
-fn memory_and_data<'a, K: Kernel>(
+pub(super) fn memory_and_data<'a, K: Kernel>(
     caller: &'a mut Caller<'_, InvocationData<K>>,
 ) -> (&'a mut Memory, &'a mut InvocationData<K>) {
     let memory_handle = caller.data().memory;
@@ -94,8 +103,124 @@ fn memory_and_data<'a, K: Kernel>(
     (Memory::new(mem), data)
 }
 
+/// Returns the in-memory representation of a syscall-safe value.
+fn as_bytes<T: SyscallSafe>(v: &T) -> &[u8] {
+    // SAFETY: syscall-safe types are packed, so every byte is initialized.
+    unsafe { std::slice::from_raw_parts(v as *const T as *const u8, mem::size_of::<T>()) }
+}
+
+/// Records a syscall in the machine's audit log, if enabled. See [`crate::audit`].
+struct AuditScope {
+    log: Arc<AuditLog>,
+    args: Vec<u8>,
+    gas_used: Gas,
+}
+
+impl AuditScope {
+    /// Starts auditing a syscall, capturing its arguments, and tracking the ranges of the actor's
+    /// memory it writes to.
+    fn begin<K: Kernel>(
+        kernel: &K,
+        memory: &Memory,
+        args: impl FnOnce() -> Vec<u8>,
+    ) -> Option<Self> {
+        let log = kernel.machine().context().audit_log.as_ref()?;
+        memory.track_writes();
+        Some(AuditScope {
+            log: log.clone(),
+            args: args(),
+            gas_used: kernel.gas_used(),
+        })
+    }
+
+    /// Appends the syscall to the audit log, unless it aborted the invocation.
+    fn finish<K: Kernel, T: SyscallSafe>(
+        self,
+        module: &str,
+        name: &str,
+        kernel: &K,
+        memory: &Memory,
+        out: &Result<Result<T, SyscallError>, Abort>,
+    ) -> Result<(), Abort> {
+        let ranges = Memory::tracked_writes();
+        let outcome = match out {
+            Ok(Ok(value)) => SyscallOutcome::from_ranges(0, as_bytes(value), &ranges, memory),
+            Ok(Err(err)) => SyscallOutcome::from_ranges(err.1 as u32, &[], &ranges, memory),
+            Err(_) => return Ok(()),
+        };
+        self.log
+            .append(
+                module,
+                name,
+                &self.args,
+                kernel.gas_used() - self.gas_used,
+                outcome,
+            )
+            .map_err(Abort::Fatal)
+    }
+}
+
+/// Serves a syscall from the syscall tape, if the kernel is a [`ReplayKernel`] and the syscall is
+/// next on the tape, writing the recorded return value (if any) to `ret`. Returns the recorded
+/// error number. See [`crate::kernel::replay`].
+fn replay_syscall<K: Kernel>(
+    data: &mut InvocationData<K>,
+    memory: &mut Memory,
+    module: &'static str,
+    name: &'static str,
+    args: impl FnOnce() -> Vec<u8>,
+    ret: Option<(u32, usize)>,
+) -> Result<Option<u32>, Abort> {
+    let kernel =
+        match (&mut data.kernel as &mut dyn Any).downcast_mut::<ReplayKernel<K::CallManager>>() {
+            Some(kernel) => kernel,
+            None => return Ok(None),
+        };
+    let replayed = kernel.replay(module, name, &args(), memory);
+    let (gas, outcome) = match replayed.map_err(Abort::Fatal)? {
+        Some(next) => next,
+        None => return Ok(None),
+    };
+    let _ = data
+        .kernel
+        .charge_gas("OnReplayedSyscall", gas)
+        .map_err(Abort::from_error_as_fatal)?;
+
+    let mut write = |offset: u32, bytes: &[u8]| -> Result<(), Abort> {
+        memory
+            .try_slice_mut(offset, bytes.len() as u32)
+            .map_err(|_| Abort::Fatal(anyhow!("replayed write to {} is out of bounds", offset)))?
+            .copy_from_slice(bytes);
+        Ok(())
+    };
+    for w in &outcome.writes {
+        write(w.offset, &w.data)?;
+    }
+    if outcome.error != 0 {
+        let code = ErrorNumber::from_u32(outcome.error)
+            .ok_or_else(|| Abort::Fatal(anyhow!("invalid replayed error {}", outcome.error)))?;
+        let err = SyscallError::new(code, "replayed syscall error");
+        data.last_error = Some(backtrace::Cause::from_syscall(module, name, err));
+        return Ok(Some(outcome.error));
+    }
+    if let Some((ret, size)) = ret {
+        if outcome.value.len() != size {
+            return Err(Abort::Fatal(anyhow!(
+                "replayed return value of {}::{} has the wrong size",
+                module,
+                name
+            )));
+        }
+        write(ret, &outcome.value)?;
+    }
+    data.kernel.record_syscall(module, name);
+    data.last_error = None;
+    Ok(Some(0))
+}
+
 macro_rules! charge_syscall_gas {
     ($kernel:expr) => {
+        charge_host_call(&$kernel)?;
         let charge = $kernel.price_list().on_syscall();
         let _ = $kernel
             .charge_gas(&charge.name, charge.compute_gas)
@@ -103,6 +228,65 @@ macro_rules! charge_syscall_gas {
     };
 }
 
+/// Charges for the transition into the host and back, on every host call. Host calls are also
+/// where message execution is cancelled (see [`crate::cancel`]).
+pub(super) fn charge_host_call<K: Kernel>(kernel: &K) -> Result<(), Abort> {
+    if let Some(token) = &kernel.machine().context().cancellation {
+        token.check(|| ()).map_err(|e| Abort::Fatal(e.into()))?;
+    }
+    let charge = kernel.price_list().on_host_call();
+    let _ = kernel
+        .charge_gas(&charge.name, charge.compute_gas)
+        .map_err(Abort::from_error_as_fatal)?;
+    Ok(())
+}
+
+/// Fails the syscall with an injected error, if the machine's fault injector says so. See
+/// [`crate::fault`].
+macro_rules! inject_syscall_fault {
+    ($caller:ident, $data:ident, $module:ident, $name:ident) => {
+        #[cfg(feature = "fault-injection")]
+        if let Some(err) = crate::machine::Machine::context($data.kernel.machine())
+            .faults
+            .as_ref()
+            .and_then(|faults| faults.syscall_error($module, $name))
+        {
+            let code = err.1;
+            log::trace!(
+                "syscall {}::{}: injected fail ({})",
+                $module,
+                $name,
+                code as u32
+            );
+            $data.last_error = Some(backtrace::Cause::from_syscall($module, $name, err));
+            update_gas_available(&mut $caller)?;
+            return Ok(code as u32);
+        }
+    };
+}
+
+/// Fails extern-dependent syscalls in read-only contexts. See [`Determinism`].
+macro_rules! check_determinism {
+    ($caller:ident, $data:ident, $determinism:ident, $module:ident, $name:ident) => {
+        if $determinism == Determinism::Extern && $data.refuse_extern {
+            let code = ErrorNumber::ReadOnly;
+            log::trace!(
+                "syscall {}::{}: fail ({}, extern-dependent)",
+                $module,
+                $name,
+                code as u32
+            );
+            let err = SyscallError::new(
+                code,
+                "extern-dependent syscalls can't be called in a read-only context",
+            );
+            $data.last_error = Some(backtrace::Cause::from_syscall($module, $name, err));
+            update_gas_available(&mut $caller)?;
+            return Ok(code as u32);
+        }
+    };
+}
+
 // Unfortunately, we can't implement this for _all_ functions. So we implement it for functions of up to 6 arguments.
 macro_rules! impl_bind_syscalls {
     ($($t:ident)*) => {
@@ -118,6 +302,7 @@ macro_rules! impl_bind_syscalls {
                 &mut self,
                 module: &'static str,
                 name: &'static str,
+                determinism: Determinism,
                 syscall: Func,
             ) -> anyhow::Result<&mut Self> {
                 if mem::size_of::<Ret::Value>() == 0 {
@@ -127,13 +312,26 @@ macro_rules! impl_bind_syscalls {
 
                         let (mut memory, mut data) = memory_and_data(&mut caller);
                         charge_syscall_gas!(data.kernel);
+                        check_determinism!(caller, data, determinism, module, name);
+                        inject_syscall_fault!(caller, data, module, name);
+
+                        let args = || <[&[u8]]>::concat(&[$(as_bytes(&$t)),*]);
+                        if let Some(code) = replay_syscall(data, memory, module, name, args, None)? {
+                            update_gas_available(&mut caller)?;
+                            return Ok(code);
+                        }
 
+                        let audit = AuditScope::begin(&data.kernel, memory, args);
                         let ctx = Context{kernel: &mut data.kernel, memory: &mut memory};
                         let out = syscall(ctx $(, $t)*).into();
+                        if let Some(audit) = audit {
+                            audit.finish(module, name, &data.kernel, memory, &out)?;
+                        }
 
                         let result = match out {
                             Ok(Ok(_)) => {
                                 log::trace!("syscall {}::{}: ok", module, name);
+                                data.kernel.record_syscall(module, name);
                                 data.last_error = None;
                                 Ok(0)
                             },
@@ -165,12 +363,27 @@ macro_rules! impl_bind_syscalls {
                             data.last_error = Some(backtrace::Cause::from_syscall(module, name, SyscallError(format!("no space for return value"), code)));
                             return Ok(code as u32);
                         }
+                        check_determinism!(caller, data, determinism, module, name);
+                        inject_syscall_fault!(caller, data, module, name);
 
+                        let args = || <[&[u8]]>::concat(&[$(as_bytes(&$t)),*]);
+                        let ret_size = Some((ret, mem::size_of::<Ret::Value>()));
+                        if let Some(code) = replay_syscall(data, memory, module, name, args, ret_size)? {
+                            update_gas_available(&mut caller)?;
+                            return Ok(code);
+                        }
+
+                        let audit = AuditScope::begin(&data.kernel, memory, args);
                         let ctx = Context{kernel: &mut data.kernel, memory: &mut memory};
-                        let result = match syscall(ctx $(, $t)*).into() {
+                        let out = syscall(ctx $(, $t)*).into();
+                        if let Some(audit) = audit {
+                            audit.finish(module, name, &data.kernel, memory, &out)?;
+                        }
+                        let result = match out {
                             Ok(Ok(value)) => {
                                 log::trace!("syscall {}::{}: ok", module, name);
                                 unsafe { *(memory.as_mut_ptr().offset(ret as isize) as *mut Ret::Value) = value };
+                                data.kernel.record_syscall(module, name);
                                 data.last_error = None;
                                 Ok(0)
                             },
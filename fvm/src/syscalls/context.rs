@@ -1,24 +1,49 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::cell::RefCell;
 use std::io::Cursor;
 use std::ops::{Deref, DerefMut};
 use std::panic;
 
 use cid::Cid;
 use fvm_ipld_encoding::from_slice;
-use fvm_shared::address::Address;
+use fvm_shared::address::{Address, MAX_ADDRESS_LEN};
 use fvm_shared::error::ErrorNumber;
 use fvm_shared::MAX_CID_LEN;
 use serde::de::DeserializeOwned;
 
 use crate::kernel::{ClassifyResult, Context as _, Result};
-use crate::syscall_error;
+use crate::{syscall_error, Kernel};
 
 pub struct Context<'a, K> {
     pub kernel: &'a mut K,
     pub memory: &'a mut Memory,
 }
 
+impl<'a, K: Kernel> Context<'a, K> {
+    /// Decodes CBOR parameters from the actor's memory, charging for decoding _before_ decoding
+    /// begins so that large or deeply nested parameters can't be decoded for free.
+    pub fn read_cbor<T: DeserializeOwned>(&self, offset: u32, len: u32) -> Result<T> {
+        self.memory.check_bounds(offset, len)?;
+        let charge = self.kernel.price_list().on_decode_params(len as usize);
+        let t = self.kernel.charge_gas(&charge.name, charge.compute_gas)?;
+        t.record(self.memory.read_cbor(offset, len))
+    }
+}
+
+thread_local! {
+    /// The writes tracked for the syscalls being audited on this thread, innermost syscall last.
+    /// See [`Memory::track_writes`].
+    static TRACKED_WRITES: RefCell<Vec<TrackedWrites>> = RefCell::new(Vec::new());
+}
+
+/// The ranges of a memory borrowed mutably during a syscall, each with its offset and its contents
+/// when borrowed.
+struct TrackedWrites {
+    memory: *const u8,
+    ranges: Vec<(u32, Vec<u8>)>,
+}
+
 #[repr(transparent)]
 pub struct Memory([u8]);
 
@@ -62,10 +87,38 @@ impl Memory {
             .or_error(ErrorNumber::IllegalArgument)
     }
     pub fn try_slice_mut(&mut self, offset: u32, len: u32) -> Result<&mut [u8]> {
-        self.get_mut(offset as usize..)
+        let base = self.0.as_ptr();
+        let data = self
+            .get_mut(offset as usize..)
             .and_then(|data| data.get_mut(..len as usize))
             .ok_or_else(|| format!("buffer {} (length {}) out of bounds", offset, len))
-            .or_error(ErrorNumber::IllegalArgument)
+            .or_error(ErrorNumber::IllegalArgument)?;
+        TRACKED_WRITES.with(|tracked| match tracked.borrow_mut().last_mut() {
+            Some(writes) if writes.memory == base => writes.ranges.push((offset, data.to_vec())),
+            _ => {}
+        });
+        Ok(data)
+    }
+
+    /// Starts tracking the ranges of this memory borrowed with [`Memory::try_slice_mut`] on this
+    /// thread, until [`Memory::tracked_writes`] is called. Tracking nests, so a syscall can be
+    /// tracked while another one (e.g., the `send` invoking the actor) is.
+    pub(crate) fn track_writes(&self) {
+        TRACKED_WRITES.with(|tracked| {
+            tracked.borrow_mut().push(TrackedWrites {
+                memory: self.0.as_ptr(),
+                ranges: Vec::new(),
+            })
+        });
+    }
+
+    /// Stops the innermost tracking started by [`Memory::track_writes`], returning the borrowed
+    /// ranges in order, each with its offset and its contents when borrowed.
+    pub(crate) fn tracked_writes() -> Vec<(u32, Vec<u8>)> {
+        TRACKED_WRITES
+            .with(|tracked| tracked.borrow_mut().pop())
+            .map(|writes| writes.ranges)
+            .unwrap_or_default()
     }
 
     pub fn read_cid(&self, offset: u32) -> Result<Cid> {
@@ -103,8 +156,17 @@ impl Memory {
     }
 
     pub fn read_address(&self, offset: u32, len: u32) -> Result<Address> {
+        // Reject oversized buffers before touching memory. Decoding then checks the length against
+        // the protocol (see `validate_address_bytes`) before looking at the payload.
+        if len as usize > MAX_ADDRESS_LEN {
+            return Err(syscall_error!(IllegalArgument;
+                "address of length {} exceeds the maximum length of {}", len, MAX_ADDRESS_LEN)
+            .into());
+        }
         let bytes = self.try_slice(offset, len)?;
-        Address::from_bytes(bytes).or_error(ErrorNumber::IllegalArgument)
+        Address::from_bytes(bytes)
+            .or_error(ErrorNumber::IllegalArgument)
+            .context("invalid address")
     }
 
     pub fn read_cbor<T: DeserializeOwned>(&self, offset: u32, len: u32) -> Result<T> {
@@ -193,4 +255,38 @@ mod test {
         let mem = Memory::new(&mut []);
         mem.try_slice(0, 0).expect("slice was in bounds");
     }
+
+    #[test]
+    fn test_read_address() {
+        let addr = Address::new_delegated(10, b"foobar").unwrap();
+        let mut bytes = addr.to_bytes();
+        bytes.resize(100, 0);
+        let mem = Memory::new(&mut bytes);
+        let len = addr.to_bytes().len() as u32;
+        assert_eq!(
+            mem.read_address(0, len).expect("failed to read address"),
+            addr
+        );
+
+        // Out of bounds, too long, truncated, and trailing garbage.
+        expect_syscall_err!(IllegalArgument, mem.read_address(90, 20));
+        expect_syscall_err!(IllegalArgument, mem.read_address(0, 66));
+        expect_syscall_err!(IllegalArgument, mem.read_address(0, 1));
+        expect_syscall_err!(IllegalArgument, mem.read_address(0, 0));
+        expect_syscall_err!(IllegalArgument, mem.read_address(0, 60));
+    }
+
+    #[test]
+    fn test_read_address_garbage() {
+        let mut bytes: Vec<u8> = (0..=255).collect();
+        let mem = Memory::new(&mut bytes);
+        for offset in 0..=255 {
+            for len in 0..=(256 - offset).min(70) {
+                if let Ok(addr) = mem.read_address(offset, len) {
+                    // Decoding is deterministic: the address has exactly one encoding.
+                    assert_eq!(addr.to_bytes(), mem.try_slice(offset, len).unwrap());
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,18 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use fvm_shared::MethodNum;
+
+use super::Context;
+use crate::kernel::Result;
+use crate::Kernel;
+
+/// Registers one of the calling (builtin) actor's methods with the cron actor. See
+/// [`CronOps::cron_register`](crate::kernel::CronOps::cron_register).
+pub fn register_entry(context: Context<'_, impl Kernel>, method: MethodNum) -> Result<()> {
+    context.kernel.cron_register(method)
+}
+
+/// Removes one of the calling actor's methods from the cron actor's entries.
+pub fn unregister_entry(context: Context<'_, impl Kernel>, method: MethodNum) -> Result<()> {
+    context.kernel.cron_unregister(method)
+}
@@ -3,9 +3,11 @@
 use std::cmp;
 
 use anyhow::{anyhow, Context as _};
+use fvm_shared::crypto::merkle::{MerkleProof, MerkleProofType};
 use fvm_shared::crypto::signature::{
     SignatureType, SECP_PUB_LEN, SECP_SIG_LEN, SECP_SIG_MESSAGE_HASH_SIZE,
 };
+use fvm_shared::crypto::snark::SnarkVerifyInfo;
 use fvm_shared::piece::PieceInfo;
 use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
@@ -95,6 +97,41 @@ pub fn hash(
     Ok(length as u32)
 }
 
+/// Opens an incremental hasher using the specified hash function, returning its handle.
+pub fn hash_init(context: Context<'_, impl Kernel>, hash_code: u64) -> Result<u32> {
+    context.kernel.hash_init(hash_code)
+}
+
+/// Feeds data to an incremental hasher.
+pub fn hash_update(
+    context: Context<'_, impl Kernel>,
+    hasher: u32,
+    data_off: u32, // input
+    data_len: u32,
+) -> Result<()> {
+    let data = context.memory.try_slice(data_off, data_len)?;
+    context.kernel.hash_update(hasher, data)
+}
+
+/// Finalizes and releases an incremental hasher. The digest is written to the passed digest
+/// buffer and truncated to `digest_len`, and its (possibly truncated) length is returned.
+pub fn hash_finalize(
+    context: Context<'_, impl Kernel>,
+    hasher: u32,
+    digest_off: u32, // output
+    digest_len: u32,
+) -> Result<u32> {
+    // Check the digest bounds first so we don't release the hasher if they're incorrect.
+    context.memory.check_bounds(digest_off, digest_len)?;
+
+    let digest = context.kernel.hash_finalize(hasher)?;
+
+    let digest_out = context.memory.try_slice_mut(digest_off, digest_len)?;
+    let length = cmp::min(digest_out.len(), digest.digest().len());
+    digest_out[..length].copy_from_slice(&digest.digest()[..length]);
+    Ok(length as u32)
+}
+
 /// Computes an unsealed sector CID (CommD) from its constituent piece CIDs
 /// (CommPs) and sizes.
 ///
@@ -112,7 +149,7 @@ pub fn compute_unsealed_sector_cid(
     if let RegisteredSealProof::Invalid(invalid) = typ {
         return Err(syscall_error!(IllegalArgument; "invalid proof type {}", invalid).into());
     }
-    let pieces: Vec<PieceInfo> = context.memory.read_cbor(pieces_off, pieces_len)?;
+    let pieces: Vec<PieceInfo> = context.read_cbor(pieces_off, pieces_len)?;
     context.memory.check_bounds(cid_off, cid_len)?;
 
     // Compute
@@ -134,9 +171,7 @@ pub fn verify_seal(
     info_off: u32, // SealVerifyInfo
     info_len: u32,
 ) -> Result<i32> {
-    let info = context
-        .memory
-        .read_cbor::<SealVerifyInfo>(info_off, info_len)?;
+    let info = context.read_cbor::<SealVerifyInfo>(info_off, info_len)?;
     context
         .kernel
         .verify_seal(&info)
@@ -153,9 +188,7 @@ pub fn verify_post(
     info_off: u32, // WindowPoStVerifyInfo,
     info_len: u32,
 ) -> Result<i32> {
-    let info = context
-        .memory
-        .read_cbor::<WindowPoStVerifyInfo>(info_off, info_len)?;
+    let info = context.read_cbor::<WindowPoStVerifyInfo>(info_off, info_len)?;
     context
         .kernel
         .verify_post(&info)
@@ -215,9 +248,7 @@ pub fn verify_aggregate_seals(
     agg_off: u32, // AggregateSealVerifyProofAndInfos
     agg_len: u32,
 ) -> Result<i32> {
-    let info = context
-        .memory
-        .read_cbor::<AggregateSealVerifyProofAndInfos>(agg_off, agg_len)?;
+    let info = context.read_cbor::<AggregateSealVerifyProofAndInfos>(agg_off, agg_len)?;
     context
         .kernel
         .verify_aggregate_seals(&info)
@@ -232,15 +263,77 @@ pub fn verify_replica_update(
     rep_off: u32, // ReplicaUpdateInfo
     rep_len: u32,
 ) -> Result<i32> {
-    let info = context
-        .memory
-        .read_cbor::<ReplicaUpdateInfo>(rep_off, rep_len)?;
+    let info = context.read_cbor::<ReplicaUpdateInfo>(rep_off, rep_len)?;
     context
         .kernel
         .verify_replica_update(&info)
         .map(|v| if v { 0 } else { -1 })
 }
 
+/// Verifies a Merkle proof of the given type, encoded as CBOR.
+///
+/// The return i32 indicates the status code of the verification:
+///  - 0: verification ok.
+///  - -1: verification failed.
+pub fn verify_merkle_proof(
+    context: Context<'_, impl Kernel>,
+    proof_type: u32,
+    proof_off: u32, // BinaryMerkleProof | PatriciaMerkleProof
+    proof_len: u32,
+) -> Result<i32> {
+    let proof_type = MerkleProofType::from_u32(proof_type)
+        .with_context(|| format!("unknown merkle proof type {}", proof_type))
+        .or_illegal_argument()?;
+    let proof = match proof_type {
+        MerkleProofType::Binary => MerkleProof::Binary(context.read_cbor(proof_off, proof_len)?),
+        MerkleProofType::Patricia => {
+            MerkleProof::Patricia(context.read_cbor(proof_off, proof_len)?)
+        }
+    };
+    context
+        .kernel
+        .verify_merkle_proof(&proof)
+        .map(|v| if v { 0 } else { -1 })
+}
+
+/// Verifies a Groth16 proof encoded as a CBOR `SnarkVerifyInfo`, against the verifying key in the
+/// open block `vk_id`.
+///
+/// The return i32 indicates the status code of the verification:
+///  - 0: verification ok.
+///  - -1: verification failed.
+pub fn verify_groth16(
+    context: Context<'_, impl Kernel>,
+    vk_id: u32,
+    info_off: u32, // SnarkVerifyInfo
+    info_len: u32,
+) -> Result<i32> {
+    let info: SnarkVerifyInfo = context.read_cbor(info_off, info_len)?;
+    context
+        .kernel
+        .verify_groth16(vk_id, &info)
+        .map(|v| if v { 0 } else { -1 })
+}
+
+/// Verifies a PLONK proof encoded as a CBOR `SnarkVerifyInfo`, against the verifying key in the
+/// open block `vk_id`.
+///
+/// The return i32 indicates the status code of the verification:
+///  - 0: verification ok.
+///  - -1: verification failed.
+pub fn verify_plonk(
+    context: Context<'_, impl Kernel>,
+    vk_id: u32,
+    info_off: u32, // SnarkVerifyInfo
+    info_len: u32,
+) -> Result<i32> {
+    let info: SnarkVerifyInfo = context.read_cbor(info_off, info_len)?;
+    context
+        .kernel
+        .verify_plonk(vk_id, &info)
+        .map(|v| if v { 0 } else { -1 })
+}
+
 /// Verify a batch of seals encoded as a CBOR array of `SealVerifyInfo`.
 ///
 /// When successful, this method will write a single byte back into the array at `result_off` for
@@ -252,9 +345,7 @@ pub fn batch_verify_seals(
     result_off: u32,
 ) -> Result<()> {
     // Check and decode params.
-    let batch = context
-        .memory
-        .read_cbor::<Vec<SealVerifyInfo>>(batch_off, batch_len)?;
+    let batch = context.read_cbor::<Vec<SealVerifyInfo>>(batch_off, batch_len)?;
     let output = context
         .memory
         .try_slice_mut(result_off, batch.len() as u32)?;
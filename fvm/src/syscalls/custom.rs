@@ -0,0 +1,237 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Syscall modules provided by embedders.
+//!
+//! Embedders (e.g., appchains built on the FVM) can link additional host functions into actors by
+//! registering a [`SyscallModule`] with
+//! [`EnginePool::register_syscall_module`](crate::engine::EnginePool::register_syscall_module).
+//! The module's syscalls are imported by actors from the module's own namespace (Wasm import
+//! module), and are handed a restricted [`SyscallContext`] through which they can charge gas and
+//! inspect the message and network context, but can't otherwise access the actor's state.
+//!
+//! Custom syscalls share a single ABI: they take their parameters and a return buffer as
+//! `(params_off, params_len, ret_off, ret_len)` and return the length of the result written to
+//! the return buffer. Like all syscalls, they take a pointer to the return value as an additional
+//! first argument, and return an error number (0 on success). For example, an actor written in
+//! Rust would import the syscall `double` of the module `myns` with:
+//!
+//! ```ignore
+//! #[link(wasm_import_module = "myns")]
+//! extern "C" {
+//!     fn double(
+//!         ret: *mut u32,
+//!         params_off: *const u8,
+//!         params_len: u32,
+//!         ret_off: *mut u8,
+//!         ret_len: u32,
+//!     ) -> u32;
+//! }
+//! ```
+//!
+//! If the result doesn't fit in the return buffer, the syscall fails with `BufferTooSmall`.
+//!
+//! Custom syscalls run as part of message execution, so they must be deterministic, and they must
+//! charge gas for the work they do. Every node of a network must register the same modules.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use fvm_shared::sys::out::network::NetworkContext;
+use fvm_shared::sys::out::vm::MessageContext;
+use wasmtime::Linker;
+
+use super::bind::BindSyscall;
+use super::{Context, Determinism, InvocationData};
+use crate::gas::{Gas, GasTimer};
+use crate::kernel::{GasOps, MessageOps, NetworkOps, Result};
+use crate::{syscall_error, Kernel};
+
+/// A module of syscalls provided by the embedder. See the [module documentation](self).
+pub trait SyscallModule: Send + Sync + 'static {
+    /// The namespace (Wasm import module) of the syscalls. It must not be the namespace of any of
+    /// the FVM's own syscalls.
+    fn namespace(&self) -> &'static str;
+
+    /// The names of the syscalls in the module.
+    fn syscalls(&self) -> Vec<&'static str>;
+
+    /// Invokes the syscall `name` with the given parameters, returning its result. Syscall errors
+    /// are returned to the actor, while fatal errors abort the message.
+    fn call(&self, context: &SyscallContext<'_>, name: &str, params: &[u8]) -> Result<Vec<u8>>;
+
+    /// What the module's syscalls depend on. Modules that consult anything outside the message and
+    /// its context (e.g., an oracle run by the client) must return [`Determinism::Extern`], so that
+    /// their syscalls are refused in read-only contexts (from network version 19).
+    fn determinism(&self) -> Determinism {
+        Determinism::State
+    }
+}
+
+/// The restricted view of the kernel available to custom syscalls.
+pub struct SyscallContext<'a> {
+    kernel: &'a dyn RestrictedKernel,
+}
+
+impl<'a> SyscallContext<'a> {
+    /// Charges gas for the syscall's work, failing if the message runs out of gas.
+    pub fn charge_gas(&self, name: &str, compute: Gas) -> Result<GasTimer> {
+        self.kernel.charge_gas(name, compute)
+    }
+
+    /// Returns the gas still available to the message.
+    pub fn gas_available(&self) -> Gas {
+        self.kernel.gas_available()
+    }
+
+    /// Returns the context of the message being executed (caller, receiver, etc.).
+    pub fn msg_context(&self) -> Result<MessageContext> {
+        self.kernel.msg_context()
+    }
+
+    /// Returns the network context (epoch, network version, etc.).
+    pub fn network_context(&self) -> Result<NetworkContext> {
+        self.kernel.network_context()
+    }
+}
+
+/// The kernel operations exposed by [`SyscallContext`].
+trait RestrictedKernel {
+    fn charge_gas(&self, name: &str, compute: Gas) -> Result<GasTimer>;
+    fn gas_available(&self) -> Gas;
+    fn msg_context(&self) -> Result<MessageContext>;
+    fn network_context(&self) -> Result<NetworkContext>;
+}
+
+impl<K: Kernel> RestrictedKernel for K {
+    fn charge_gas(&self, name: &str, compute: Gas) -> Result<GasTimer> {
+        GasOps::charge_gas(self, name, compute)
+    }
+
+    fn gas_available(&self) -> Gas {
+        GasOps::gas_available(self)
+    }
+
+    fn msg_context(&self) -> Result<MessageContext> {
+        MessageOps::msg_context(self)
+    }
+
+    fn network_context(&self) -> Result<NetworkContext> {
+        NetworkOps::network_context(self)
+    }
+}
+
+/// Checks that a syscall module can be registered alongside the FVM's syscalls and the modules
+/// registered so far.
+pub(crate) fn check_module(
+    module: &dyn SyscallModule,
+    registered: &[Arc<dyn SyscallModule>],
+) -> anyhow::Result<()> {
+    let namespace = module.namespace();
+    if namespace == "gas"
+        || namespace == super::wasi::MODULE
+        || super::SYSCALLS.keys().any(|(m, _)| *m == namespace)
+    {
+        return Err(anyhow!("syscall namespace {} is reserved", namespace));
+    }
+    if registered.iter().any(|m| m.namespace() == namespace) {
+        return Err(anyhow!(
+            "syscall namespace {} is already registered",
+            namespace
+        ));
+    }
+    Ok(())
+}
+
+/// Returns true if `module::name` is a syscall of one of the given custom modules.
+pub(crate) fn is_custom_syscall(
+    modules: &[Arc<dyn SyscallModule>],
+    module: &str,
+    name: &str,
+) -> bool {
+    modules
+        .iter()
+        .any(|m| m.namespace() == module && m.syscalls().contains(&name))
+}
+
+/// Binds the syscalls of the given custom modules.
+pub(crate) fn bind_custom_syscalls<K: Kernel>(
+    linker: &mut Linker<InvocationData<K>>,
+    modules: &[Arc<dyn SyscallModule>],
+) -> anyhow::Result<()> {
+    for module in modules {
+        let namespace = module.namespace();
+        for name in module.syscalls() {
+            let module = module.clone();
+            linker.bind(
+                namespace,
+                name,
+                module.determinism(),
+                move |context: Context<'_, K>,
+                      params_off: u32,
+                      params_len: u32,
+                      ret_off: u32,
+                      ret_len: u32|
+                      -> Result<u32> {
+                    let params = context.memory.try_slice(params_off, params_len)?;
+                    // Custom syscalls may decode their parameters, so charge for that up front.
+                    let charge = context.kernel.price_list().on_decode_params(params.len());
+                    let _ = context
+                        .kernel
+                        .charge_gas(&charge.name, charge.compute_gas)?;
+                    let ctx = SyscallContext {
+                        kernel: &*context.kernel,
+                    };
+                    let ret = module.call(&ctx, name, params)?;
+                    if ret.len() > ret_len as usize {
+                        return Err(syscall_error!(BufferTooSmall;
+                            "syscall result is {} bytes, buffer is {} bytes", ret.len(), ret_len)
+                        .into());
+                    }
+                    context
+                        .memory
+                        .try_slice_mut(ret_off, ret.len() as u32)?
+                        .copy_from_slice(&ret);
+                    Ok(ret.len() as u32)
+                },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Module(&'static str);
+
+    impl SyscallModule for Module {
+        fn namespace(&self) -> &'static str {
+            self.0
+        }
+
+        fn syscalls(&self) -> Vec<&'static str> {
+            vec!["double"]
+        }
+
+        fn call(&self, _: &SyscallContext<'_>, _: &str, params: &[u8]) -> Result<Vec<u8>> {
+            Ok(params.repeat(2))
+        }
+    }
+
+    #[test]
+    fn check_namespaces() {
+        check_module(&Module("gas"), &[]).unwrap_err();
+        check_module(&Module("ipld"), &[]).unwrap_err();
+        check_module(&Module("wasi_snapshot_preview1"), &[]).unwrap_err();
+        check_module(&Module("myns"), &[]).unwrap();
+
+        let registered: Vec<Arc<dyn SyscallModule>> = vec![Arc::new(Module("myns"))];
+        check_module(&Module("myns"), &registered).unwrap_err();
+        check_module(&Module("other"), &registered).unwrap();
+
+        assert!(is_custom_syscall(&registered, "myns", "double"));
+        assert!(!is_custom_syscall(&registered, "myns", "triple"));
+        assert!(!is_custom_syscall(&registered, "other", "double"));
+    }
+}
@@ -19,6 +19,6 @@ pub fn emit_event(
     event_off: u32, // ActorEvent
     event_len: u32,
 ) -> Result<()> {
-    let evt: ActorEvent = context.memory.read_cbor(event_off, event_len)?;
+    let evt: ActorEvent = context.read_cbor(event_off, event_len)?;
     context.kernel.emit_event(evt)
 }
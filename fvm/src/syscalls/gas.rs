@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 use std::str;
 
+use fvm_shared::sys::out::gas::GasPrices;
+
 use super::Context;
 use crate::gas::Gas;
 use crate::kernel::{ClassifyResult, Result};
@@ -25,3 +27,7 @@ pub fn charge_gas(
 pub fn available(context: Context<'_, impl Kernel>) -> Result<u64> {
     Ok(context.kernel.gas_available().round_down() as u64)
 }
+
+pub fn prices(context: Context<'_, impl Kernel>) -> Result<GasPrices> {
+    Ok(context.kernel.price_list().gas_prices())
+}
@@ -64,3 +64,27 @@ pub fn block_stat(context: Context<'_, impl Kernel>, id: u32) -> Result<sys::out
             size: stat.size,
         })
 }
+
+pub fn block_close(context: Context<'_, impl Kernel>, id: u32) -> Result<()> {
+    context.kernel.block_close(id)
+}
+
+pub fn block_prefetch(context: Context<'_, impl Kernel>, cid: u32) -> Result<()> {
+    let cid = context.memory.read_cid(cid)?;
+    context.kernel.block_prefetch(&cid)
+}
+
+pub fn block_graph_stat(
+    context: Context<'_, impl Kernel>,
+    cid: u32,
+    max_blocks: u32,
+) -> Result<sys::out::ipld::IpldGraphStat> {
+    let cid = context.memory.read_cid(cid)?;
+    let stat = context.kernel.block_graph_stat(&cid, max_blocks)?;
+    Ok(sys::out::ipld::IpldGraphStat {
+        size: stat.size,
+        blocks: stat.blocks,
+        depth: stat.depth,
+        complete: stat.complete as u32,
+    })
+}
@@ -1,8 +1,11 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::collections::HashMap;
 use std::mem;
+use std::ops::{Bound, RangeBounds};
 
 use anyhow::{anyhow, Context as _};
+use fvm_shared::version::NetworkVersion;
 use num_traits::Zero;
 use wasmtime::{AsContextMut, ExternType, Global, Linker, Memory, Module, Val};
 
@@ -17,7 +20,9 @@ pub(crate) mod error;
 mod actor;
 mod bind;
 mod context;
+mod cron;
 mod crypto;
+pub mod custom;
 mod debug;
 mod event;
 mod gas;
@@ -26,9 +31,13 @@ mod network;
 mod rand;
 mod send;
 mod sself;
+mod sync;
+mod token;
 mod vm;
+pub mod wasi;
 
 pub(self) use context::Context;
+pub use custom::{SyscallContext, SyscallModule};
 
 /// Invocation data attached to a wasm "store" and available to the syscall binding.
 pub struct InvocationData<K> {
@@ -55,8 +64,21 @@ pub struct InvocationData<K> {
     /// Last time we charged for gas; it can be used to correlate gas with time.
     pub last_charge_time: GasInstant,
 
+    /// The fuel consumed by the invocation the last time we charged gas for its execution, if
+    /// instruction counting is enabled. Used to advance the kernel's instruction clock, if any
+    /// (see [`InstructionClock`](crate::gas::InstructionClock)).
+    pub last_fuel_consumed: u64,
+
     /// The invocation's imported "memory".
     pub memory: Memory,
+
+    /// The sizes (in bytes) of the instance's memory before and after each time it grew, including
+    /// the initial allocation, in order. Only recorded when tracing.
+    pub memory_growth: Option<Vec<(usize, usize)>>,
+
+    /// Whether extern-dependent syscalls fail with `ReadOnly`, i.e., whether the invocation runs in
+    /// a read-only context from network version 19. See [`Determinism`].
+    pub refuse_extern: bool,
 }
 
 /// Updates the global available gas in the Wasm module after a syscall, to account for any
@@ -101,6 +123,15 @@ pub fn charge_for_exec<K: Kernel>(
         Gas::from_milligas(last_milligas.saturating_sub(milligas_available))
     };
 
+    // Count the instructions executed since the last charge.
+    let instructions = match ctx.fuel_consumed() {
+        Some(fuel) => {
+            let last_fuel = mem::replace(&mut ctx.data_mut().last_fuel_consumed, fuel);
+            fuel.saturating_sub(last_fuel)
+        }
+        None => 0,
+    };
+
     let data = ctx.data_mut();
 
     // Separate the amount of gas charged for memory; this is only makes a difference in tracing.
@@ -110,10 +141,15 @@ pub fn charge_for_exec<K: Kernel>(
 
     exec_gas = (exec_gas - memory_gas).max(Gas::zero());
 
-    let t = data
-        .kernel
-        .charge_gas("wasm_exec", exec_gas)
-        .map_err(Abort::from_error_as_fatal)?;
+    let res = data.kernel.charge_gas("wasm_exec", exec_gas);
+
+    // Advance the instruction clock (if timing by instructions) after the charge, so its timer
+    // measures the instructions executed. Timers stopped later still count them if it fails.
+    if let Some(clock) = data.kernel.instruction_clock() {
+        clock.advance(instructions);
+    }
+
+    let t = res.map_err(Abort::from_error_as_fatal)?;
 
     // It should be okay to record time associated with Wasm execution because `charge_for_exec` is called
     // before syscalls `impl_bind_syscalls`, so the syscall timings are going to be interleaved, rather than
@@ -210,103 +246,273 @@ fn min_table_elements(module: &Module) -> Option<u32> {
 use self::bind::BindSyscall;
 use self::error::Abort;
 
-// Binds the syscall handlers so they can handle invocations
-// from the actor code.
+/// Invokes the given macro with the list of all syscalls, as
+/// `"module" "name" (versions) determinism => handler;` entries (optionally preceded by
+/// attributes), where `versions` is the range of network versions at which the syscall is
+/// available, and `determinism` its [`Determinism`] domain. This list drives both
+/// [`bind_syscalls`] and [`is_syscall`], so the two can't get out of sync.
+///
+/// To introduce a syscall at a new network version, add it with an open range starting at that
+/// version (e.g., `(V19..)`). To retire one, close its range (e.g., `(V18..=V19)`). Either way,
+/// update the snapshots of the syscall surface tested below.
+macro_rules! with_syscalls {
+    ($m:ident) => {
+        $m! {
+            "vm" "exit" (V18..) Pure => vm::exit;
+            "vm" "message_context" (V18..) State => vm::message_context;
+            "vm" "return_len" (V19..) State => vm::return_len;
+            "vm" "return_pop" (V19..) State => vm::return_pop;
+
+            "network" "total_fil_circ_supply" (V18..) State => network::total_fil_circ_supply;
+            "network" "context" (V18..) State => network::context;
+            "network" "tipset_cid" (V18..) Extern => network::tipset_cid;
+            "network" "lookup_state" (V19..) Extern => network::lookup_state;
+
+            "ipld" "block_open" (V18..) State => ipld::block_open;
+            "ipld" "block_create" (V18..) State => ipld::block_create;
+            "ipld" "block_read" (V18..) State => ipld::block_read;
+            "ipld" "block_stat" (V18..) State => ipld::block_stat;
+            "ipld" "block_link" (V18..) State => ipld::block_link;
+            "ipld" "block_close" (V19..) State => ipld::block_close;
+            "ipld" "block_graph_stat" (V19..) State => ipld::block_graph_stat;
+            "ipld" "block_prefetch" (V19..) State => ipld::block_prefetch;
+
+            "self" "root" (V18..) State => sself::root;
+            "self" "set_root" (V18..) State => sself::set_root;
+            "self" "current_balance" (V18..) State => sself::current_balance;
+            "self" "self_destruct" (V18..) State => sself::self_destruct;
+            "self" "state_stat" (V19..) State => sself::state_stat;
+
+            "actor" "resolve_address" (V18..) State => actor::resolve_address;
+            "actor" "lookup_delegated_address" (V18..) State => actor::lookup_delegated_address;
+            "actor" "get_actor_code_cid" (V18..) State => actor::get_actor_code_cid;
+            "actor" "next_actor_address" (V18..) State => actor::next_actor_address;
+            "actor" "create_actor" (V18..) State => actor::create_actor;
+            "actor" "get_builtin_actor_type" (V18..) State => actor::get_builtin_actor_type;
+            "actor" "get_code_cid_for_type" (V18..) State => actor::get_code_cid_for_type;
+            "actor" "get_code_info" (V19..) State => actor::get_code_info;
+            "actor" "balance_of" (V18..) State => actor::balance_of;
+            "actor" "nonce_of" (V19..) State => actor::nonce_of;
+            // Only wire this syscall when M2 native is enabled.
+            #[cfg(feature = "m2-native")]
+            "actor" "install_actor" (V18..) State => actor::install_actor;
+
+            "crypto" "verify_signature" (V18..) Pure => crypto::verify_signature;
+            "crypto" "recover_secp_public_key" (V18..) Pure => crypto::recover_secp_public_key;
+            "crypto" "hash" (V18..) Pure => crypto::hash;
+            "crypto" "hash_init" (V19..) Pure => crypto::hash_init;
+            "crypto" "hash_update" (V19..) Pure => crypto::hash_update;
+            "crypto" "hash_finalize" (V19..) Pure => crypto::hash_finalize;
+            "crypto" "verify_seal" (V18..) Pure => crypto::verify_seal;
+            "crypto" "verify_post" (V18..) Pure => crypto::verify_post;
+            "crypto" "compute_unsealed_sector_cid" (V18..) Pure => crypto::compute_unsealed_sector_cid;
+            "crypto" "verify_consensus_fault" (V18..) Extern => crypto::verify_consensus_fault;
+            "crypto" "verify_aggregate_seals" (V18..) Pure => crypto::verify_aggregate_seals;
+            "crypto" "verify_replica_update" (V18..) Pure => crypto::verify_replica_update;
+            "crypto" "batch_verify_seals" (V18..) Pure => crypto::batch_verify_seals;
+            "crypto" "verify_merkle_proof" (V19..) Pure => crypto::verify_merkle_proof;
+            "crypto" "verify_groth16" (V19..) State => crypto::verify_groth16;
+            "crypto" "verify_plonk" (V19..) State => crypto::verify_plonk;
+
+            "event" "emit_event" (V18..) State => event::emit_event;
+
+            "rand" "get_chain_randomness" (V18..) Extern => rand::get_chain_randomness;
+            "rand" "get_beacon_randomness" (V18..) Extern => rand::get_beacon_randomness;
+
+            "gas" "charge" (V18..) State => gas::charge_gas;
+            "gas" "available" (V18..) State => gas::available;
+            "gas" "prices" (V19..) State => gas::prices;
+
+            // Ok, this singled-out syscall should probably be in another category.
+            "send" "send" (V18..) State => send::send;
+            "send" "delegate_call" (V19..) State => send::delegate_call;
+
+            "sync" "set_flag" (V19..) State => sync::set_flag;
+            "sync" "clear_flag" (V19..) State => sync::clear_flag;
+
+            "cron" "register_entry" (V19..) State => cron::register_entry;
+            "cron" "unregister_entry" (V19..) State => cron::unregister_entry;
+
+            "token" "transfer_hook" (V19..) State => token::transfer_hook;
+
+            "debug" "log" (V18..) Pure => debug::log;
+            "debug" "enabled" (V18..) State => debug::enabled;
+            "debug" "store_artifact" (V18..) Pure => debug::store_artifact;
+        }
+    };
+}
+
+/// Shorthands for the network versions used in the syscall table.
+const V18: NetworkVersion = NetworkVersion::V18;
+const V19: NetworkVersion = NetworkVersion::V19;
+
+/// What the result of a syscall may depend on, besides its arguments.
+///
+/// From network version 19, extern-dependent syscalls fail with `ReadOnly` in read-only contexts
+/// (read-only sends, and the validation of messages sent by abstract accounts), so that the outcome
+/// of such calls only depends on the state they run against, never on the client's view of the
+/// chain. Before that, read-only sends could call them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Determinism {
+    /// Depends only on the arguments (and the actor's memory).
+    Pure,
+    /// Also depends on the state tree, the message, and the machine's context.
+    State,
+    /// Also depends on the client, through the [externs](crate::externs) (randomness, past
+    /// tipsets, etc.).
+    Extern,
+}
+
+// Binds the syscall handlers available at the given network version so they can handle
+// invocations from the actor code.
 pub fn bind_syscalls(
     linker: &mut Linker<InvocationData<impl Kernel + 'static>>,
+    network_version: NetworkVersion,
 ) -> anyhow::Result<()> {
-    linker.bind("vm", "exit", vm::exit)?;
-    linker.bind("vm", "message_context", vm::message_context)?;
+    macro_rules! bind {
+        ($($(#[$attr:meta])* $module:literal $name:literal $versions:tt $determinism:ident => $handler:path;)*) => {
+            $(
+                $(#[$attr])*
+                if RangeBounds::contains(&$versions, &network_version) {
+                    linker.bind($module, $name, Determinism::$determinism, $handler)?;
+                }
+            )*
+        };
+    }
+    with_syscalls!(bind);
+
+    Ok(())
+}
 
+/// Binds the syscalls only available on development networks: dev randomness (see
+/// [`NetworkConfig::dev_randomness`](crate::machine::NetworkConfig::dev_randomness)). They must
+/// only be bound when enabled, at any network version.
+pub(crate) fn bind_dev_syscalls(
+    linker: &mut Linker<InvocationData<impl Kernel + 'static>>,
+) -> anyhow::Result<()> {
     linker.bind(
-        "network",
-        "total_fil_circ_supply",
-        network::total_fil_circ_supply,
+        "rand",
+        "get_dev_randomness",
+        Determinism::State,
+        rand::get_dev_randomness,
     )?;
-    linker.bind("network", "context", network::context)?;
-    linker.bind("network", "tipset_cid", network::tipset_cid)?;
-
-    linker.bind("ipld", "block_open", ipld::block_open)?;
-    linker.bind("ipld", "block_create", ipld::block_create)?;
-    linker.bind("ipld", "block_read", ipld::block_read)?;
-    linker.bind("ipld", "block_stat", ipld::block_stat)?;
-    linker.bind("ipld", "block_link", ipld::block_link)?;
+    Ok(())
+}
 
-    linker.bind("self", "root", sself::root)?;
-    linker.bind("self", "set_root", sself::set_root)?;
-    linker.bind("self", "current_balance", sself::current_balance)?;
-    linker.bind("self", "self_destruct", sself::self_destruct)?;
+/// Returns true if `module::name` is a syscall bound by [`bind_dev_syscalls`].
+pub(crate) fn is_dev_syscall(module: &str, name: &str) -> bool {
+    (module, name) == ("rand", "get_dev_randomness")
+}
 
-    linker.bind("actor", "resolve_address", actor::resolve_address)?;
-    linker.bind(
-        "actor",
-        "lookup_delegated_address",
-        actor::lookup_delegated_address,
-    )?;
-    linker.bind("actor", "get_actor_code_cid", actor::get_actor_code_cid)?;
-    linker.bind("actor", "next_actor_address", actor::next_actor_address)?;
-    linker.bind("actor", "create_actor", actor::create_actor)?;
-    linker.bind(
-        "actor",
-        "get_builtin_actor_type",
-        actor::get_builtin_actor_type,
-    )?;
-    linker.bind(
-        "actor",
-        "get_code_cid_for_type",
-        actor::get_code_cid_for_type,
-    )?;
-    linker.bind("actor", "balance_of", actor::balance_of)?;
+/// The network versions at which a syscall is available.
+type Versions = (Bound<NetworkVersion>, Bound<NetworkVersion>);
+
+lazy_static::lazy_static! {
+    static ref SYSCALLS: HashMap<(&'static str, &'static str), (Versions, Determinism)> = {
+        let mut syscalls = HashMap::new();
+        macro_rules! collect {
+            ($($(#[$attr:meta])* $module:literal $name:literal $versions:tt $determinism:ident => $handler:path;)*) => {
+                $(
+                    $(#[$attr])*
+                    syscalls.insert(
+                        ($module, $name),
+                        (
+                            ($versions.start_bound().cloned(), $versions.end_bound().cloned()),
+                            Determinism::$determinism,
+                        ),
+                    );
+                )*
+            };
+        }
+        with_syscalls!(collect);
+        syscalls
+    };
+}
 
-    // Only wire this syscall when M2 native is enabled.
-    #[cfg(feature = "m2-native")]
-    linker.bind("actor", "install_actor", actor::install_actor)?;
+/// Returns true if `module::name` is a syscall bound by [`bind_syscalls`] at the given network
+/// version.
+pub fn is_syscall(module: &str, name: &str, network_version: NetworkVersion) -> bool {
+    SYSCALLS
+        .get(&(module, name))
+        .map_or(false, |(versions, _)| versions.contains(&network_version))
+}
 
-    linker.bind("crypto", "verify_signature", crypto::verify_signature)?;
-    linker.bind(
-        "crypto",
-        "recover_secp_public_key",
-        crypto::recover_secp_public_key,
-    )?;
-    linker.bind("crypto", "hash", crypto::hash)?;
-    linker.bind("crypto", "verify_seal", crypto::verify_seal)?;
-    linker.bind("crypto", "verify_post", crypto::verify_post)?;
-    linker.bind(
-        "crypto",
-        "compute_unsealed_sector_cid",
-        crypto::compute_unsealed_sector_cid,
-    )?;
-    linker.bind(
-        "crypto",
-        "verify_consensus_fault",
-        crypto::verify_consensus_fault,
-    )?;
-    linker.bind(
-        "crypto",
-        "verify_aggregate_seals",
-        crypto::verify_aggregate_seals,
-    )?;
-    linker.bind(
-        "crypto",
-        "verify_replica_update",
-        crypto::verify_replica_update,
-    )?;
-    linker.bind("crypto", "batch_verify_seals", crypto::batch_verify_seals)?;
+/// Returns the [`Determinism`] domain of the syscall `module::name`, if it's one of the FVM's
+/// syscalls (at any network version).
+pub fn determinism(module: &str, name: &str) -> Option<Determinism> {
+    SYSCALLS
+        .get(&(module, name))
+        .map(|(_, determinism)| *determinism)
+}
 
-    linker.bind("event", "emit_event", event::emit_event)?;
+/// Returns the syscalls available at the given network version, as sorted `(module, name)` pairs.
+pub fn syscalls(network_version: NetworkVersion) -> Vec<(&'static str, &'static str)> {
+    let mut syscalls: Vec<_> = SYSCALLS
+        .iter()
+        .filter(|(_, (versions, _))| versions.contains(&network_version))
+        .map(|(syscall, _)| *syscall)
+        .collect();
+    syscalls.sort_unstable();
+    syscalls
+}
 
-    linker.bind("rand", "get_chain_randomness", rand::get_chain_randomness)?;
-    linker.bind("rand", "get_beacon_randomness", rand::get_beacon_randomness)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the syscalls available at each network version against the snapshots in
+    /// `snapshots/`, to catch accidental changes to the syscall surface. Run with
+    /// `UPDATE_SNAPSHOTS=1` to update the snapshots after an intended change.
+    #[test]
+    fn syscall_surface() {
+        for (nv, snapshot) in [(V18, "v18.txt"), (V19, "v19.txt")] {
+            let surface: String = syscalls(nv)
+                .into_iter()
+                // Feature-gated syscalls aren't part of the snapshot.
+                .filter(|syscall| *syscall != ("actor", "install_actor"))
+                .map(|(module, name)| format!("{}::{}\n", module, name))
+                .collect();
+
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("src/syscalls/snapshots")
+                .join(snapshot);
+            if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+                std::fs::write(&path, &surface).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(
+                surface, expected,
+                "the syscall surface at network version {} changed",
+                nv
+            );
+        }
+    }
 
-    linker.bind("gas", "charge", gas::charge_gas)?;
-    linker.bind("gas", "available", gas::available)?;
+    #[test]
+    fn versions() {
+        assert!(is_syscall("vm", "exit", V18));
+        assert!(!is_syscall("vm", "exit", NetworkVersion::V17));
+        assert!(!is_syscall("vm", "missing", V18));
+        assert!(syscalls(NetworkVersion::V17).is_empty());
 
-    // Ok, this singled-out syscall should probably be in another category.
-    linker.bind("send", "send", send::send)?;
+        // Introduced at network version 19.
+        assert!(!is_syscall("gas", "prices", V18));
+        assert!(is_syscall("gas", "prices", V19));
 
-    linker.bind("debug", "log", debug::log)?;
-    linker.bind("debug", "enabled", debug::enabled)?;
-    linker.bind("debug", "store_artifact", debug::store_artifact)?;
+        // Only bound on development networks.
+        assert!(!is_syscall("rand", "get_dev_randomness", V19));
+        assert!(is_dev_syscall("rand", "get_dev_randomness"));
+    }
 
-    Ok(())
+    #[test]
+    fn determinism_domains() {
+        assert_eq!(determinism("crypto", "hash"), Some(Determinism::Pure));
+        assert_eq!(determinism("ipld", "block_open"), Some(Determinism::State));
+        assert_eq!(
+            determinism("rand", "get_chain_randomness"),
+            Some(Determinism::Extern)
+        );
+        assert_eq!(determinism("vm", "missing"), None);
+    }
 }
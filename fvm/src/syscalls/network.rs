@@ -1,11 +1,13 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use anyhow::Context as _;
+use fvm_shared::state::StateQuery;
 use fvm_shared::sys;
 use fvm_shared::sys::out::network::NetworkContext;
 
 use super::Context;
 use crate::kernel::{ClassifyResult, Kernel, Result};
+use crate::syscall_error;
 
 /// Returns the network circ supply split as two u64 ordered in little endian.
 pub fn total_fil_circ_supply(context: Context<'_, impl Kernel>) -> Result<sys::TokenAmount> {
@@ -33,3 +35,22 @@ pub fn tipset_cid(
     let cid = context.kernel.tipset_cid(epoch)?;
     context.memory.write_cid(&cid, obuf_off, obuf_len)
 }
+
+/// Looks up domain state, pushing the value onto the return-data queue and returning its length.
+pub fn lookup_state(
+    context: Context<'_, impl Kernel>,
+    kind: u64,
+    key_off: u32,
+    key_len: u32,
+) -> Result<u32> {
+    let key = context.memory.try_slice(key_off, key_len)?;
+    let query = StateQuery::decode(kind, key)
+        .ok_or_else(|| syscall_error!(IllegalArgument; "invalid state query of kind {}", kind))?;
+    let value = context
+        .kernel
+        .read_state(&query)?
+        .ok_or_else(|| syscall_error!(NotFound; "state not found"))?;
+    let len = value.len() as u32;
+    context.kernel.return_push(value)?;
+    Ok(len)
+}
@@ -39,3 +39,18 @@ pub fn get_beacon_randomness(
         .kernel
         .get_randomness_from_beacon(pers, round, entropy)
 }
+
+/// Gets 32 bytes of deterministic pseudo-randomness, derived from the current epoch, the message
+/// CID, and a per-message counter. Only available on networks with dev randomness enabled.
+/// The supplied output buffer must have at least 32 bytes of capacity.
+/// If this syscall succeeds, exactly 32 bytes will be written starting at the
+/// supplied offset.
+pub fn get_dev_randomness(
+    context: Context<'_, impl Kernel>,
+    pers: i64, // DomainSeparationTag
+    entropy_off: u32,
+    entropy_len: u32,
+) -> Result<[u8; RANDOMNESS_LENGTH]> {
+    let entropy = context.memory.try_slice(entropy_off, entropy_len)?;
+    context.kernel.get_dev_randomness(pers, entropy)
+}
@@ -51,3 +51,38 @@ pub fn send(
         return_size: block_stat.size,
     })
 }
+
+/// Invokes a method of the given code in the context of the calling actor. The result is returned
+/// like with [`send`].
+pub fn delegate_call(
+    context: Context<'_, impl Kernel>,
+    code_off: u32,
+    method: u64,
+    params_id: u32,
+    gas_limit: u64,
+    flags: u64,
+) -> Result<sys::out::send::Send> {
+    let code = context.memory.read_cid(code_off)?;
+
+    // If that gas limit exceeds i64, treat it as infinity. u64::MAX is used to indicate "all gas".
+    let gas_limit = gas_limit.try_into().ok().map(Gas::new);
+
+    let flags = SendFlags::from_bits(flags)
+        .with_context(|| format!("invalid send flags: {flags}"))
+        .or_illegal_argument()?;
+
+    let SendResult {
+        block_id,
+        block_stat,
+        exit_code,
+    } = context
+        .kernel
+        .delegate_call(&code, method, params_id, gas_limit, flags)?;
+
+    Ok(sys::out::send::Send {
+        exit_code: exit_code.value(),
+        return_id: block_id,
+        return_codec: block_stat.codec,
+        return_size: block_stat.size,
+    })
+}
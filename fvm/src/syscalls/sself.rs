@@ -42,3 +42,13 @@ pub fn self_destruct(
     context.kernel.self_destruct(&addr)?;
     Ok(())
 }
+
+pub fn state_stat(context: Context<'_, impl Kernel>) -> Result<sys::out::ipld::IpldGraphStat> {
+    let stat = context.kernel.self_state_stat()?;
+    Ok(sys::out::ipld::IpldGraphStat {
+        size: stat.size,
+        blocks: stat.blocks,
+        depth: stat.depth,
+        complete: stat.complete as u32,
+    })
+}
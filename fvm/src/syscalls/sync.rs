@@ -0,0 +1,23 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use anyhow::Context as _;
+use fvm_shared::sys::FlagOptions;
+
+use super::Context;
+use crate::kernel::{ClassifyResult, Result};
+use crate::Kernel;
+
+/// Sets one of the calling actor's per-message flags, returning 1 if it was set, or 0 if it was
+/// already set. See [`SyncOps::set_flag`](crate::kernel::SyncOps::set_flag).
+pub fn set_flag(context: Context<'_, impl Kernel>, key: u64, options: u64) -> Result<u32> {
+    let options = FlagOptions::from_bits(options)
+        .with_context(|| format!("invalid flag options: {options}"))
+        .or_illegal_argument()?;
+    context.kernel.set_flag(key, options).map(|set| set as u32)
+}
+
+/// Clears one of the calling actor's per-message flags, returning 1 if it was cleared, or 0 if it
+/// wasn't set.
+pub fn clear_flag(context: Context<'_, impl Kernel>, key: u64) -> Result<u32> {
+    context.kernel.clear_flag(key).map(|cleared| cleared as u32)
+}
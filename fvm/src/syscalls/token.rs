@@ -0,0 +1,75 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use fvm_shared::error::{ErrorNumber, ExitCode};
+use fvm_shared::sys;
+use fvm_shared::token::TokenTransfer;
+
+use super::error::Abort;
+use super::Context;
+use crate::call_manager::NO_DATA_BLOCK_ID;
+use crate::gas::Gas;
+use crate::kernel::{ExecutionError, SendResult, SyscallError, TokenTransferResult};
+use crate::Kernel;
+
+/// Invokes the receiver hook of the recipient of tokens transferred by the calling actor. See
+/// [`TokenOps::token_transfer_hook`](crate::kernel::TokenOps::token_transfer_hook).
+///
+/// Unlike [`send`](super::send::send), this aborts the calling (token) actor, reverting the
+/// transfer, if the transfer is invalid or the recipient rejects it.
+pub fn transfer_hook(
+    context: Context<'_, impl Kernel>,
+    transfer_off: u32,
+    transfer_len: u32,
+    gas_limit: u64,
+) -> Result<sys::out::token::TransferHook, Abort> {
+    let transfer: TokenTransfer = context
+        .read_cbor(transfer_off, transfer_len)
+        .map_err(|e| Abort::from_error(ExitCode::USR_SERIALIZATION, e))?;
+
+    // If that gas limit exceeds i64, treat it as infinity. u64::MAX is used to indicate "all gas".
+    let gas_limit = gas_limit.try_into().ok().map(Gas::new);
+
+    let TokenTransferResult {
+        from,
+        to,
+        hook:
+            SendResult {
+                block_id,
+                block_stat,
+                exit_code,
+            },
+    } = context
+        .kernel
+        .token_transfer_hook(&transfer, gas_limit)
+        .map_err(|e| {
+            let code = match &e {
+                ExecutionError::Syscall(SyscallError(_, ErrorNumber::NotFound)) => {
+                    ExitCode::USR_NOT_FOUND
+                }
+                ExecutionError::Syscall(SyscallError(_, ErrorNumber::ReadOnly)) => {
+                    ExitCode::USR_READ_ONLY
+                }
+                _ => ExitCode::USR_ILLEGAL_ARGUMENT,
+            };
+            Abort::from_error(code, e)
+        })?;
+
+    if !exit_code.is_success() {
+        return Err(Abort::Exit(
+            ExitCode::USR_FORBIDDEN,
+            format!(
+                "token transfer rejected by {} with exit code {}",
+                to, exit_code
+            ),
+            NO_DATA_BLOCK_ID,
+        ));
+    }
+
+    Ok(sys::out::token::TransferHook {
+        from,
+        to,
+        return_codec: block_stat.codec,
+        return_id: block_id,
+        return_size: block_stat.size,
+    })
+}
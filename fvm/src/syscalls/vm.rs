@@ -3,10 +3,14 @@
 use fvm_shared::error::ExitCode;
 use fvm_shared::sys::out::vm::MessageContext;
 use fvm_shared::sys::SyscallSafe;
+use fvm_shared::version::NetworkVersion;
 
 use super::error::Abort;
 use super::Context;
+use crate::call_manager::NO_DATA_BLOCK_ID;
 use crate::kernel::Kernel;
+use crate::machine::Machine;
+use crate::syscall_error;
 
 /// An uninhabited type. We use this in `abort` to make sure there's no way to return without
 /// returning an error.
@@ -28,10 +32,18 @@ pub fn exit(
     message_len: u32,
 ) -> Result<Never, Abort> {
     let code = ExitCode::new(code);
+    // Actors may not exit with codes reserved for the system. We replace any such code with
+    // SYS_ILLEGAL_EXIT_CODE and, from network version 19, drop the return value, as system exit
+    // codes never carry one.
     if !code.is_success() && code.is_system_error() {
+        let blk = if context.kernel.machine().context().network_version >= NetworkVersion::V19 {
+            NO_DATA_BLOCK_ID
+        } else {
+            blk
+        };
         return Err(Abort::Exit(
             ExitCode::SYS_ILLEGAL_EXIT_CODE,
-            format!("actor aborted with code {}", code),
+            format!("actor aborted with reserved exit code {}", code),
             blk,
         ));
     }
@@ -63,3 +75,35 @@ pub fn exit(
 pub fn message_context(context: Context<'_, impl Kernel>) -> crate::kernel::Result<MessageContext> {
     context.kernel.msg_context()
 }
+
+/// Returns the length (in bytes) of the payload at the front of the return-data queue.
+///
+/// Fails with `NotFound` if the queue is empty.
+pub fn return_len(context: Context<'_, impl Kernel>) -> crate::kernel::Result<u32> {
+    context
+        .kernel
+        .return_len()
+        .ok_or_else(|| syscall_error!(NotFound; "return queue is empty").into())
+}
+
+/// Pops the payload at the front of the return-data queue into the output buffer, returning its
+/// length.
+///
+/// Fails with `NotFound` if the queue is empty, and with `BufferTooSmall` (without popping the
+/// payload) if the payload doesn't fit in the output buffer.
+pub fn return_pop(
+    context: Context<'_, impl Kernel>,
+    obuf_off: u32,
+    obuf_len: u32,
+) -> crate::kernel::Result<u32> {
+    let len = context
+        .kernel
+        .return_len()
+        .ok_or_else(|| syscall_error!(NotFound; "return queue is empty"))?;
+    if len > obuf_len {
+        return Err(syscall_error!(BufferTooSmall; "return payload ({} bytes) exceeds buffer ({} bytes)", len, obuf_len).into());
+    }
+    let obuf = context.memory.try_slice_mut(obuf_off, len)?;
+    obuf.copy_from_slice(&context.kernel.return_pop()?);
+    Ok(len)
+}
@@ -0,0 +1,296 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! A minimal, deterministic subset of WASI preview1, linked into actors only when actor debugging
+//! is enabled.
+//!
+//! This lets actors built for `wasm32-wasi` with standard toolchains print (e.g., with `println!`)
+//! and exit naturally during development. Actors importing WASI functions can't be loaded by
+//! machines without actor debugging, so they can't be deployed to real networks.
+//!
+//! The shim only implements:
+//!
+//! - `fd_write`: writes to stdout and stderr are sent to the debug log. Other descriptors are
+//!   invalid.
+//! - `clock_time_get`: the realtime and monotonic clocks both return the current tipset's
+//!   timestamp.
+//! - `random_get`: returns beacon randomness drawn at the current epoch. The same bytes are
+//!   returned on every call, so they're not suitable for anything but seeding hash maps.
+//! - `environ_*` and `args_*`: the environment and argument lists are empty.
+//! - `proc_exit`: exits the actor with the given exit code, like `vm::exit`.
+
+use fvm_shared::error::{ErrorNumber, ExitCode};
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use wasmtime::{Caller, Linker, Trap};
+
+use super::bind::{charge_host_call, memory_and_data};
+use super::context::Memory;
+use super::error::Abort;
+use super::{charge_for_exec, update_gas_available, Context, InvocationData};
+use crate::call_manager::NO_DATA_BLOCK_ID;
+use crate::kernel::{ExecutionError, Kernel, Result};
+
+/// The Wasm import module of WASI preview1.
+pub const MODULE: &str = "wasi_snapshot_preview1";
+
+/// The WASI functions implemented by the shim.
+const FUNCTIONS: &[&str] = &[
+    "args_get",
+    "args_sizes_get",
+    "clock_time_get",
+    "environ_get",
+    "environ_sizes_get",
+    "fd_write",
+    "proc_exit",
+    "random_get",
+];
+
+/// The personalization of the randomness returned by `random_get`.
+const RANDOMNESS_PERSONALIZATION: i64 = 0x5741_5349; // "WASI"
+
+/// WASI error numbers.
+type Errno = u32;
+const ESUCCESS: Errno = 0;
+const EBADF: Errno = 8;
+const EINVAL: Errno = 28;
+const EIO: Errno = 29;
+
+const CLOCK_REALTIME: u32 = 0;
+const CLOCK_MONOTONIC: u32 = 1;
+
+/// Returns true if `module::name` is a WASI function implemented by the shim.
+pub(crate) fn is_wasi_function(module: &str, name: &str) -> bool {
+    module == MODULE && FUNCTIONS.contains(&name)
+}
+
+/// Binds the WASI shim. It must only be bound when actor debugging is enabled.
+pub(crate) fn bind_wasi<K: Kernel>(linker: &mut Linker<InvocationData<K>>) -> anyhow::Result<()> {
+    linker.func_wrap(
+        MODULE,
+        "fd_write",
+        |mut caller: Caller<'_, InvocationData<K>>,
+         fd: u32,
+         iovs: u32,
+         iovs_len: u32,
+         nwritten: u32| {
+            call(&mut caller, |ctx| {
+                fd_write(ctx, fd, iovs, iovs_len, nwritten)
+            })
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "clock_time_get",
+        |mut caller: Caller<'_, InvocationData<K>>, id: u32, _precision: u64, time: u32| {
+            call(&mut caller, |ctx| clock_time_get(ctx, id, time))
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "random_get",
+        |mut caller: Caller<'_, InvocationData<K>>, buf: u32, buf_len: u32| {
+            call(&mut caller, |ctx| random_get(ctx, buf, buf_len))
+        },
+    )?;
+    for (sizes_get, get) in [
+        ("environ_sizes_get", "environ_get"),
+        ("args_sizes_get", "args_get"),
+    ] {
+        linker.func_wrap(
+            MODULE,
+            sizes_get,
+            |mut caller: Caller<'_, InvocationData<K>>, count: u32, buf_size: u32| {
+                call(&mut caller, |ctx| empty_list_sizes(ctx, count, buf_size))
+            },
+        )?;
+        linker.func_wrap(
+            MODULE,
+            get,
+            |mut caller: Caller<'_, InvocationData<K>>, _list: u32, _buf: u32| {
+                call(&mut caller, |_| Ok(ESUCCESS))
+            },
+        )?;
+    }
+    linker.func_wrap(
+        MODULE,
+        "proc_exit",
+        |mut caller: Caller<'_, InvocationData<K>>, code: u32| -> std::result::Result<(), Trap> {
+            charge_for_exec(&mut caller)?;
+            charge_host_call(&caller.data().kernel)?;
+            Err(proc_exit(code).into())
+        },
+    )?;
+    Ok(())
+}
+
+/// Invokes a WASI function, charging for it like a syscall. Syscall errors are returned to the actor
+/// as WASI error numbers, while other errors abort the actor.
+fn call<K: Kernel>(
+    caller: &mut Caller<'_, InvocationData<K>>,
+    f: impl FnOnce(Context<'_, K>) -> Result<Errno>,
+) -> std::result::Result<Errno, Trap> {
+    charge_for_exec(caller)?;
+
+    let (memory, data) = memory_and_data(caller);
+    charge_host_call(&data.kernel)?;
+    let charge = data.kernel.price_list().on_syscall();
+    let _ = data
+        .kernel
+        .charge_gas(&charge.name, charge.compute_gas)
+        .map_err(Abort::from_error_as_fatal)?;
+
+    let errno = match f(Context {
+        kernel: &mut data.kernel,
+        memory,
+    }) {
+        Ok(errno) => errno,
+        Err(ExecutionError::Syscall(e)) => {
+            log::trace!("wasi call failed: {}", e);
+            match e.1 {
+                ErrorNumber::IllegalArgument => EINVAL,
+                _ => EIO,
+            }
+        }
+        Err(ExecutionError::OutOfGas) => return Err(Abort::OutOfGas.into()),
+        Err(ExecutionError::Fatal(e)) => return Err(Abort::Fatal(e).into()),
+    };
+
+    update_gas_available(caller)?;
+    Ok(errno)
+}
+
+fn write_u32(memory: &mut Memory, offset: u32, value: u32) -> Result<()> {
+    memory
+        .try_slice_mut(offset, 4)?
+        .copy_from_slice(&value.to_le_bytes());
+    Ok(())
+}
+
+/// Concatenates the buffers described by an array of WASI `ciovec`s.
+fn gather(memory: &Memory, iovs: u32, iovs_len: u32) -> Result<Vec<u8>> {
+    let iovs = memory.try_slice(iovs, iovs_len.saturating_mul(8))?;
+    let mut out = Vec::new();
+    for iov in iovs.chunks_exact(8) {
+        let buf = u32::from_le_bytes(iov[..4].try_into().unwrap());
+        let buf_len = u32::from_le_bytes(iov[4..].try_into().unwrap());
+        out.extend_from_slice(memory.try_slice(buf, buf_len)?);
+    }
+    Ok(out)
+}
+
+fn fd_write(
+    context: Context<'_, impl Kernel>,
+    fd: u32,
+    iovs: u32,
+    iovs_len: u32,
+    nwritten: u32,
+) -> Result<Errno> {
+    if fd != 1 && fd != 2 {
+        return Ok(EBADF);
+    }
+    let data = gather(context.memory, iovs, iovs_len)?;
+    write_u32(context.memory, nwritten, data.len() as u32)?;
+
+    // Writes are usually whole lines, which the log terminates itself.
+    let msg = String::from_utf8_lossy(&data);
+    let msg = msg.strip_suffix('\n').unwrap_or(&msg);
+    if context.kernel.debug_enabled() && !msg.is_empty() {
+        context.kernel.log(msg.to_owned());
+    }
+    Ok(ESUCCESS)
+}
+
+fn clock_time_get(context: Context<'_, impl Kernel>, id: u32, time: u32) -> Result<Errno> {
+    if id != CLOCK_REALTIME && id != CLOCK_MONOTONIC {
+        return Ok(EINVAL);
+    }
+    let nanos = context
+        .kernel
+        .network_context()?
+        .timestamp
+        .saturating_mul(1_000_000_000);
+    context
+        .memory
+        .try_slice_mut(time, 8)?
+        .copy_from_slice(&nanos.to_le_bytes());
+    Ok(ESUCCESS)
+}
+
+fn random_get(context: Context<'_, impl Kernel>, buf: u32, buf_len: u32) -> Result<Errno> {
+    let epoch = context.kernel.network_context()?.epoch;
+    let buf = context.memory.try_slice_mut(buf, buf_len)?;
+    for (i, chunk) in buf.chunks_mut(RANDOMNESS_LENGTH).enumerate() {
+        let randomness = context.kernel.get_randomness_from_beacon(
+            RANDOMNESS_PERSONALIZATION,
+            epoch,
+            &(i as u64).to_be_bytes(),
+        )?;
+        chunk.copy_from_slice(&randomness[..chunk.len()]);
+    }
+    Ok(ESUCCESS)
+}
+
+fn empty_list_sizes(context: Context<'_, impl Kernel>, count: u32, buf_size: u32) -> Result<Errno> {
+    write_u32(context.memory, count, 0)?;
+    write_u32(context.memory, buf_size, 0)?;
+    Ok(ESUCCESS)
+}
+
+/// Exits the actor like `vm::exit`, without a return value.
+fn proc_exit(code: u32) -> Abort {
+    let code = ExitCode::new(code);
+    if !code.is_success() && code.is_system_error() {
+        return Abort::Exit(
+            ExitCode::SYS_ILLEGAL_EXIT_CODE,
+            format!("actor exited with reserved exit code {}", code),
+            NO_DATA_BLOCK_ID,
+        );
+    }
+    Abort::Exit(
+        code,
+        format!("actor exited with code {}", code),
+        NO_DATA_BLOCK_ID,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gathers_iovecs() {
+        let mut bytes = vec![0u8; 32];
+        bytes[16..21].copy_from_slice(b"hello");
+        bytes[24..30].copy_from_slice(b" world");
+        for (i, (buf, len)) in [(16u32, 5u32), (24, 6)].iter().enumerate() {
+            bytes[i * 8..i * 8 + 4].copy_from_slice(&buf.to_le_bytes());
+            bytes[i * 8 + 4..i * 8 + 8].copy_from_slice(&len.to_le_bytes());
+        }
+        let memory = Memory::new(&mut bytes);
+
+        assert_eq!(gather(memory, 0, 2).unwrap(), b"hello world");
+        assert_eq!(gather(memory, 8, 1).unwrap(), b" world");
+        assert!(gather(memory, 0, 0).unwrap().is_empty());
+        // Out of bounds.
+        gather(memory, 0, 5).unwrap_err();
+        gather(memory, 16, 1).unwrap_err();
+    }
+
+    #[test]
+    fn exits() {
+        match proc_exit(17) {
+            Abort::Exit(code, _, _) => assert_eq!(code, ExitCode::new(17)),
+            _ => panic!("expected exit"),
+        }
+        match proc_exit(1) {
+            Abort::Exit(code, _, _) => assert_eq!(code, ExitCode::SYS_ILLEGAL_EXIT_CODE),
+            _ => panic!("expected exit"),
+        }
+    }
+
+    #[test]
+    fn functions() {
+        assert!(is_wasi_function(MODULE, "fd_write"));
+        assert!(!is_wasi_function(MODULE, "fd_read"));
+        assert!(!is_wasi_function("debug", "fd_write"));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Converters from FVM [`ExecutionTrace`]s to the trace formats used by Ethereum tooling:
+//!
+//! - [`call_frames`] builds geth `callTracer`-style call trees.
+//! - [`struct_logs`] builds a "lite" version of geth's `structLogs`, with one entry per gas
+//!   charge instead of one per EVM opcode.
+//!
+//! Both serialize to the JSON shapes expected by Ethereum tooling. Addresses are rendered as
+//! Ethereum addresses where possible (ID addresses as "masked" `0xff..` addresses, and `f410`
+//! addresses as the embedded Ethereum address). Params and return values are passed through as-is.
+
+use std::fmt::Write;
+
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+pub use fvm_shared::message::eth::eth_address;
+use fvm_shared::MethodNum;
+use serde::{Serialize, Serializer};
+
+use super::{ExecutionEvent, ExecutionTrace};
+use crate::gas::Gas;
+
+/// A call in a geth `callTracer`-style call tree.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+    /// Always `CALL`: the FVM doesn't distinguish between kinds of calls.
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    #[serde(serialize_with = "ser_address")]
+    pub from: Address,
+    #[serde(serialize_with = "ser_address")]
+    pub to: Address,
+    #[serde(skip)]
+    pub method: MethodNum,
+    #[serde(serialize_with = "ser_token_amount")]
+    pub value: TokenAmount,
+    /// The gas used by this call, including its sub-calls.
+    #[serde(serialize_with = "ser_gas")]
+    pub gas_used: Gas,
+    #[serde(serialize_with = "ser_bytes")]
+    pub input: Vec<u8>,
+    #[serde(serialize_with = "ser_bytes")]
+    pub output: Vec<u8>,
+    /// The exit code, if the call returned.
+    #[serde(skip)]
+    pub exit_code: Option<ExitCode>,
+    /// Why the call failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<CallFrame>,
+}
+
+/// A gas charge, in the shape of a geth `structLogs` entry.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructLog {
+    /// The name of the gas charge (in place of the opcode).
+    pub op: String,
+    /// The gas charged, rounded up to whole gas units.
+    pub gas_cost: u64,
+    /// The total gas charged so far, including this charge.
+    pub gas_used: u64,
+    /// The call depth of the charge, starting from 1 for the message's call. Charges made outside
+    /// of any call (e.g., for message inclusion) have depth 0.
+    pub depth: u32,
+}
+
+/// Converts an execution trace to a call tree, returning the top-level calls (usually just the
+/// message's call).
+pub fn call_frames(trace: &ExecutionTrace) -> Vec<CallFrame> {
+    let mut roots = Vec::new();
+    let mut stack: Vec<CallFrame> = Vec::new();
+
+    let mut close = |stack: &mut Vec<CallFrame>, finish: &dyn Fn(&mut CallFrame)| {
+        if let Some(mut frame) = stack.pop() {
+            finish(&mut frame);
+            match stack.last_mut() {
+                Some(parent) => {
+                    parent.gas_used += frame.gas_used;
+                    parent.calls.push(frame);
+                }
+                None => roots.push(frame),
+            }
+        }
+    };
+
+    for event in trace {
+        match event {
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                params,
+                value,
+            } => stack.push(CallFrame {
+                typ: "CALL",
+                from: Address::new_id(*from),
+                to: *to,
+                method: *method,
+                value: value.clone(),
+                gas_used: Gas::default(),
+                input: params.to_vec(),
+                output: Vec::new(),
+                exit_code: None,
+                error: None,
+                calls: Vec::new(),
+            }),
+            ExecutionEvent::GasCharge(charge) => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.gas_used += charge.total();
+                }
+            }
+            ExecutionEvent::CallReturn(code, ret) => close(&mut stack, &|frame| {
+                frame.exit_code = Some(*code);
+                frame.output = ret.to_vec();
+                if !code.is_success() {
+                    frame.error = Some(format!("exit code {}", code));
+                }
+            }),
+            ExecutionEvent::CallError(err) => close(&mut stack, &|frame| {
+                frame.error = Some(err.to_string());
+            }),
+            _ => {}
+        }
+    }
+
+    // Close any calls left open by a truncated trace.
+    while !stack.is_empty() {
+        close(&mut stack, &|frame| {
+            frame.error.get_or_insert_with(|| "incomplete trace".into());
+        });
+    }
+    roots
+}
+
+/// Converts the gas charges in an execution trace to `structLogs` entries.
+pub fn struct_logs(trace: &ExecutionTrace) -> Vec<StructLog> {
+    let mut logs = Vec::new();
+    let mut depth = 0u32;
+    let mut gas_used = Gas::default();
+    for event in trace {
+        match event {
+            ExecutionEvent::Call { .. } => depth += 1,
+            ExecutionEvent::CallReturn(..) | ExecutionEvent::CallError(_) => {
+                depth = depth.saturating_sub(1)
+            }
+            ExecutionEvent::GasCharge(charge) => {
+                let cost = charge.total();
+                gas_used += cost;
+                logs.push(StructLog {
+                    op: charge.name.to_string(),
+                    gas_cost: cost.round_up() as u64,
+                    gas_used: gas_used.round_up() as u64,
+                    depth,
+                });
+            }
+            _ => {}
+        }
+    }
+    logs
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(2 + bytes.len() * 2);
+    out.push_str("0x");
+    for b in bytes {
+        let _ = write!(out, "{:02x}", b);
+    }
+    out
+}
+
+/// Addresses without an Ethereum equivalent (e.g., unresolved `f1` addresses) serialize to null.
+fn ser_address<S: Serializer>(addr: &Address, s: S) -> Result<S::Ok, S::Error> {
+    match eth_address(addr) {
+        Some(eth) => s.serialize_str(&hex(&eth)),
+        None => s.serialize_none(),
+    }
+}
+
+fn ser_token_amount<S: Serializer>(amt: &TokenAmount, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("0x{:x}", amt.atto()))
+}
+
+/// Gas serializes to hex, like other quantities. Negative gas (e.g., a frame whose refunds exceed
+/// its charges) has no hex quantity, so it serializes to a signed decimal string instead.
+fn ser_gas<S: Serializer>(gas: &Gas, s: S) -> Result<S::Ok, S::Error> {
+    match gas.round_up() {
+        gas if gas < 0 => s.serialize_str(&gas.to_string()),
+        gas => s.serialize_str(&format!("0x{:x}", gas)),
+    }
+}
+
+fn ser_bytes<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&hex(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::error::ErrorNumber;
+    use fvm_shared::ActorID;
+
+    use super::*;
+    use crate::eam_actor::EAM_ACTOR_ID;
+    use crate::gas::GasCharge;
+    use crate::kernel::SyscallError;
+
+    fn charge(gas: i64) -> ExecutionEvent {
+        ExecutionEvent::GasCharge(GasCharge::new("OnTest", Gas::new(gas), Gas::default()))
+    }
+
+    fn call(from: ActorID, to: ActorID) -> ExecutionEvent {
+        ExecutionEvent::Call {
+            from,
+            to: Address::new_id(to),
+            method: 2,
+            params: RawBytes::new(vec![1, 2]),
+            value: TokenAmount::from_atto(255),
+        }
+    }
+
+    fn sample_trace() -> ExecutionTrace {
+        vec![
+            charge(1),
+            call(100, 101),
+            charge(10),
+            call(101, 102),
+            charge(20),
+            ExecutionEvent::CallReturn(ExitCode::USR_ASSERTION_FAILED, RawBytes::default()),
+            call(101, 103),
+            ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "no actor")),
+            charge(5),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::new(vec![3])),
+        ]
+    }
+
+    #[test]
+    fn frames() {
+        let frames = call_frames(&sample_trace());
+        assert_eq!(frames.len(), 1);
+        let root = &frames[0];
+        assert_eq!(root.to, Address::new_id(101));
+        assert_eq!(root.gas_used, Gas::new(35));
+        assert_eq!(root.output, vec![3]);
+        assert_eq!(root.exit_code, Some(ExitCode::OK));
+        assert_eq!(root.error, None);
+
+        assert_eq!(root.calls.len(), 2);
+        assert_eq!(root.calls[0].gas_used, Gas::new(20));
+        assert!(root.calls[0].error.is_some());
+        assert_eq!(root.calls[1].exit_code, None);
+        assert!(root.calls[1].error.as_ref().unwrap().contains("no actor"));
+    }
+
+    #[test]
+    fn logs() {
+        let logs = struct_logs(&sample_trace());
+        let depths: Vec<_> = logs.iter().map(|l| l.depth).collect();
+        assert_eq!(depths, vec![0, 1, 2, 1]);
+        assert_eq!(logs[3].gas_cost, 5);
+        assert_eq!(logs[3].gas_used, 36);
+    }
+
+    #[test]
+    fn addresses() {
+        let masked = eth_address(&Address::new_id(0x0102)).unwrap();
+        assert_eq!(hex(&masked), "0xff00000000000000000000000000000000000102");
+
+        let eth = [0x11; 20];
+        let delegated = Address::new_delegated(EAM_ACTOR_ID, &eth).unwrap();
+        assert_eq!(eth_address(&delegated), Some(eth));
+        let other = Address::new_delegated(EAM_ACTOR_ID + 1, &eth).unwrap();
+        assert_eq!(eth_address(&other), None);
+    }
+
+    #[test]
+    fn json() {
+        let frames = call_frames(&sample_trace());
+        let json = serde_json::to_value(&frames[0]).unwrap();
+        assert_eq!(json["type"], "CALL");
+        assert_eq!(json["from"], "0xff00000000000000000000000000000000000064");
+        assert_eq!(json["value"], "0xff");
+        assert_eq!(json["gasUsed"], "0x23");
+        assert_eq!(json["input"], "0x0102");
+        assert_eq!(json["output"], "0x03");
+        assert!(json.get("error").is_none());
+        assert_eq!(json["calls"].as_array().unwrap().len(), 2);
+        assert!(json["calls"][0].get("calls").is_none());
+    }
+
+    #[test]
+    fn negative_gas() {
+        let gas = |g: i64| {
+            let mut json = serde_json::Serializer::new(Vec::new());
+            ser_gas(&Gas::new(g), &mut json).unwrap();
+            String::from_utf8(json.into_inner()).unwrap()
+        };
+        assert_eq!(gas(35), r#""0x23""#);
+        assert_eq!(gas(0), r#""0x0""#);
+        assert_eq!(gas(-35), r#""-35""#);
+    }
+}
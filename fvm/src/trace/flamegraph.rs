@@ -0,0 +1,114 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Converts [execution traces](super::ExecutionTrace) to the "collapsed stack" format read by
+//! flame graph tools such as `inferno` and speedscope, to visualize where gas goes across a call
+//! graph.
+//!
+//! Each line is a semicolon-separated stack of call frames (`<to>::<method>`) ending with the name
+//! of a gas charge, followed by the total milligas charged under that stack. Charges made outside
+//! of any call (e.g., message inclusion) have only the charge name as their stack.
+//!
+//! ```text
+//! f0101::2;f0102::3;OnBlockRead 4400000
+//! f0101::2;wasm_exec 1234000
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::ExecutionEvent;
+
+/// Aggregates the gas charges in the trace by call stack and charge name, returning the milligas
+/// charged under each stack. Stacks are sorted, and stacks with no gas charged are omitted.
+pub fn collapsed_stacks(trace: &[ExecutionEvent]) -> BTreeMap<String, i64> {
+    let mut stacks = BTreeMap::new();
+    let mut frames: Vec<String> = Vec::new();
+    for event in trace {
+        match event {
+            ExecutionEvent::Call { to, method, .. } => {
+                frames.push(format!("{}::{}", to, method));
+            }
+            ExecutionEvent::CallReturn(..) | ExecutionEvent::CallError(_) => {
+                frames.pop();
+            }
+            ExecutionEvent::GasCharge(charge) => {
+                let milligas = charge.total().as_milligas();
+                if milligas == 0 {
+                    continue;
+                }
+                let mut stack = frames.join(";");
+                if !stack.is_empty() {
+                    stack.push(';');
+                }
+                stack.push_str(&charge.name);
+                *stacks.entry(stack).or_insert(0) += milligas;
+            }
+            _ => {}
+        }
+    }
+    stacks
+}
+
+/// Renders the trace in collapsed stack format (see the [module documentation](self)), one stack
+/// per line.
+pub fn to_collapsed(trace: &[ExecutionEvent]) -> String {
+    let mut out = String::new();
+    for (stack, milligas) in collapsed_stacks(trace) {
+        let _ = writeln!(out, "{} {}", stack, milligas);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::{ErrorNumber, ExitCode};
+
+    use super::*;
+    use crate::gas::{Gas, GasCharge};
+    use crate::kernel::SyscallError;
+
+    fn charge(name: &'static str, gas: i64) -> ExecutionEvent {
+        ExecutionEvent::GasCharge(GasCharge::new(name, Gas::new(gas), Gas::default()))
+    }
+
+    fn call(to: u64, method: u64) -> ExecutionEvent {
+        ExecutionEvent::Call {
+            from: 100,
+            to: Address::new_id(to),
+            method,
+            params: RawBytes::default(),
+            value: TokenAmount::default(),
+        }
+    }
+
+    #[test]
+    fn collapse() {
+        let trace = vec![
+            charge("OnChainMessage", 1),
+            call(101, 2),
+            charge("wasm_exec", 10),
+            call(102, 3),
+            charge("OnBlockRead", 20),
+            charge("OnBlockRead", 5),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+            call(103, 4),
+            charge("wasm_exec", 0),
+            ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "no actor")),
+            call(102, 3),
+            charge("OnBlockRead", 1),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+            charge("wasm_exec", 7),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+        ];
+
+        assert_eq!(
+            to_collapsed(&trace),
+            "OnChainMessage 1000\n\
+             f0101::2;f0102::3;OnBlockRead 26000\n\
+             f0101::2;wasm_exec 17000\n"
+        );
+    }
+}
@@ -1,14 +1,18 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use cid::Cid;
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared::address::Address;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::{ActorID, MethodNum};
 
-use crate::gas::GasCharge;
+use crate::gas::{Gas, GasCharge};
 use crate::kernel::SyscallError;
 
+pub mod eth;
+pub mod flamegraph;
+
 /// Execution Trace, only for informational and debugging purposes.
 pub type ExecutionTrace = Vec<ExecutionEvent>;
 
@@ -28,4 +32,307 @@ pub enum ExecutionEvent {
     },
     CallReturn(ExitCode, RawBytes),
     CallError(SyscallError),
+    TransferFailed(TransferFailure),
+    /// The number of Wasm instructions (strictly, wasmtime fuel units) executed by the current
+    /// call's actor code, excluding sub-calls and syscalls. Recorded just before the call's
+    /// [`ExecutionEvent::CallReturn`] if
+    /// [`NetworkConfig::instruction_counting`](crate::machine::NetworkConfig::instruction_counting)
+    /// is enabled.
+    InstructionCount(u64),
+    /// The current call's Wasm memory grew from `from` to `to` bytes, including its initial
+    /// allocation when the actor was instantiated. Recorded in order, just before the call's
+    /// [`ExecutionEvent::InstructionCount`] (if any) and [`ExecutionEvent::CallReturn`].
+    MemoryGrowth {
+        from: u64,
+        to: u64,
+    },
+    /// The current call linked a block of `size` bytes, writing it to the state blockstore.
+    /// Recorded just after the corresponding gas charge.
+    BlockLink {
+        cid: Cid,
+        size: u64,
+    },
+}
+
+/// Details of a value transfer that failed because the sender couldn't cover it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransferFailure {
+    /// The actor attempting to send funds.
+    pub from: ActorID,
+    /// The intended recipient.
+    pub to: ActorID,
+    /// The sender's balance at the time of the transfer (zero if the sender doesn't exist).
+    pub balance: TokenAmount,
+    /// The amount the sender attempted to transfer.
+    pub amount: TokenAmount,
+    /// The call stack depth of the send that attempted the transfer (1 for the top-level message).
+    pub depth: u32,
+}
+
+impl std::fmt::Display for TransferFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "insufficient funds at call depth {}: actor {} attempted to transfer {} to actor {} with balance {}",
+            self.depth, self.from, self.amount, self.to, self.balance
+        )
+    }
+}
+
+/// The cost of a single call frame, excluding its sub-calls.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameCost {
+    /// The called actor.
+    pub to: Address,
+    /// The called method.
+    pub method: MethodNum,
+    /// The call stack depth of the frame (1 for the top-level message).
+    pub depth: u32,
+    /// The gas charged while executing the frame.
+    pub gas: Gas,
+    /// The number of Wasm instructions executed by the frame, if instruction counting is enabled
+    /// and the frame ran actor code.
+    pub instructions: Option<u64>,
+}
+
+/// Computes the gas and instructions used by each call frame in an execution trace, in call order.
+/// Aggregating these (e.g., by the actor's code and method) over a chain replay shows how well gas
+/// prices track the actual work performed.
+pub fn frame_costs(trace: &[ExecutionEvent]) -> Vec<FrameCost> {
+    let mut frames = Vec::new();
+    // The indices of the open frames.
+    let mut stack: Vec<usize> = Vec::new();
+    for event in trace {
+        match event {
+            ExecutionEvent::Call { to, method, .. } => {
+                stack.push(frames.len());
+                frames.push(FrameCost {
+                    to: *to,
+                    method: *method,
+                    depth: stack.len() as u32,
+                    gas: Gas::default(),
+                    instructions: None,
+                });
+            }
+            ExecutionEvent::GasCharge(charge) => {
+                if let Some(&idx) = stack.last() {
+                    frames[idx].gas += charge.total();
+                }
+            }
+            ExecutionEvent::InstructionCount(count) => {
+                if let Some(&idx) = stack.last() {
+                    frames[idx].instructions = Some(*count);
+                }
+            }
+            ExecutionEvent::CallReturn(..) | ExecutionEvent::CallError(_) => {
+                stack.pop();
+            }
+            ExecutionEvent::TransferFailed(_)
+            | ExecutionEvent::MemoryGrowth { .. }
+            | ExecutionEvent::BlockLink { .. } => {}
+        }
+    }
+    frames
+}
+
+/// A blake2b-256 commitment over an execution trace.
+pub type TraceCommitment = [u8; 32];
+
+/// Computes a deterministic commitment over the ordered gas charges and call events of an
+/// execution trace. Two nodes that executed a message identically will compute the same
+/// commitment, so comparing commitments is a cheap way to localize divergence without exchanging
+/// full traces.
+///
+/// Only consensus-relevant data is committed to: gas charge timings, syscall error messages (only
+/// the error number is included), and instruction counts are excluded.
+pub fn commitment(trace: &[ExecutionEvent]) -> TraceCommitment {
+    let mut hasher = CommitmentHasher(blake2b_simd::Params::new().hash_length(32).to_state());
+    for event in trace {
+        match event {
+            ExecutionEvent::GasCharge(charge) => {
+                hasher.tag(0);
+                hasher.bytes(charge.name.as_bytes());
+                hasher.u64(charge.compute_gas.as_milligas() as u64);
+                hasher.u64(charge.other_gas.as_milligas() as u64);
+            }
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                params,
+                value,
+            } => {
+                hasher.tag(1);
+                hasher.u64(*from);
+                hasher.bytes(&to.to_bytes());
+                hasher.u64(*method);
+                hasher.bytes(params);
+                hasher.token_amount(value);
+            }
+            ExecutionEvent::CallReturn(exit_code, ret) => {
+                hasher.tag(2);
+                hasher.u64(exit_code.value() as u64);
+                hasher.bytes(ret);
+            }
+            ExecutionEvent::CallError(SyscallError(_, number)) => {
+                hasher.tag(3);
+                hasher.u64(*number as u64);
+            }
+            ExecutionEvent::TransferFailed(failure) => {
+                hasher.tag(4);
+                hasher.u64(failure.from);
+                hasher.u64(failure.to);
+                hasher.token_amount(&failure.balance);
+                hasher.token_amount(&failure.amount);
+                hasher.u64(failure.depth as u64);
+            }
+            ExecutionEvent::MemoryGrowth { from, to } => {
+                hasher.tag(5);
+                hasher.u64(*from);
+                hasher.u64(*to);
+            }
+            ExecutionEvent::BlockLink { cid, size } => {
+                hasher.tag(6);
+                hasher.bytes(&cid.to_bytes());
+                hasher.u64(*size);
+            }
+            // Instruction counts aren't consensus-critical.
+            ExecutionEvent::InstructionCount(_) => {}
+        }
+    }
+    let mut out = TraceCommitment::default();
+    out.copy_from_slice(hasher.0.finalize().as_bytes());
+    out
+}
+
+/// Feeds trace fields into the hash with unambiguous framing (fixed-width integers and
+/// length-prefixed byte strings).
+struct CommitmentHasher(blake2b_simd::State);
+
+impl CommitmentHasher {
+    fn tag(&mut self, tag: u8) {
+        self.0.update(&[tag]);
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.update(&v.to_be_bytes());
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.u64(b.len() as u64);
+        self.0.update(b);
+    }
+
+    fn token_amount(&mut self, amt: &TokenAmount) {
+        self.bytes(&amt.atto().to_signed_bytes_be());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::error::ErrorNumber;
+    use num_traits::Zero;
+
+    use super::*;
+
+    fn sample_trace() -> ExecutionTrace {
+        vec![
+            ExecutionEvent::Call {
+                from: 100,
+                to: Address::new_id(101),
+                method: 2,
+                params: RawBytes::new(vec![1, 2, 3]),
+                value: TokenAmount::from_atto(10),
+            },
+            ExecutionEvent::GasCharge(GasCharge::new(
+                "OnMethodInvocation",
+                Gas::new(10),
+                Gas::zero(),
+            )),
+            ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "not found")),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+        ]
+    }
+
+    #[test]
+    fn commitment_is_deterministic() {
+        let trace = sample_trace();
+        assert_eq!(commitment(&trace), commitment(&sample_trace()));
+
+        // Non-consensus data doesn't affect the commitment.
+        let mut other = sample_trace();
+        other[2] = ExecutionEvent::CallError(SyscallError::new(ErrorNumber::NotFound, "missing"));
+        assert_eq!(commitment(&trace), commitment(&other));
+    }
+
+    #[test]
+    fn commitment_detects_divergence() {
+        let trace = sample_trace();
+
+        let mut reordered = sample_trace();
+        reordered.swap(1, 2);
+        assert_ne!(commitment(&trace), commitment(&reordered));
+
+        let mut charged = sample_trace();
+        charged[1] = ExecutionEvent::GasCharge(GasCharge::new(
+            "OnMethodInvocation",
+            Gas::new(11),
+            Gas::zero(),
+        ));
+        assert_ne!(commitment(&trace), commitment(&charged));
+
+        assert_ne!(commitment(&trace), commitment(&trace[..3]));
+    }
+
+    #[test]
+    fn costs() {
+        let call = |to| ExecutionEvent::Call {
+            from: 100,
+            to: Address::new_id(to),
+            method: 2,
+            params: RawBytes::default(),
+            value: TokenAmount::zero(),
+        };
+        let charge =
+            |gas| ExecutionEvent::GasCharge(GasCharge::new("OnTest", Gas::new(gas), Gas::zero()));
+        let trace = vec![
+            charge(1),
+            call(101),
+            charge(10),
+            call(102),
+            charge(20),
+            ExecutionEvent::InstructionCount(200),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+            charge(5),
+            ExecutionEvent::InstructionCount(150),
+            ExecutionEvent::CallReturn(ExitCode::OK, RawBytes::default()),
+        ];
+        assert_eq!(
+            frame_costs(&trace),
+            vec![
+                FrameCost {
+                    to: Address::new_id(101),
+                    method: 2,
+                    depth: 1,
+                    gas: Gas::new(15),
+                    instructions: Some(150),
+                },
+                FrameCost {
+                    to: Address::new_id(102),
+                    method: 2,
+                    depth: 2,
+                    gas: Gas::new(20),
+                    instructions: Some(200),
+                },
+            ]
+        );
+
+        // Instruction counts don't affect the commitment.
+        let without: Vec<_> = trace
+            .iter()
+            .filter(|e| !matches!(e, ExecutionEvent::InstructionCount(_)))
+            .cloned()
+            .collect();
+        assert_eq!(commitment(&trace), commitment(&without));
+    }
 }
@@ -5,7 +5,7 @@ use super::*;
 mod ipld {
 
     use cid::Cid;
-    use fvm::kernel::{IpldBlockOps, SupportedHashes};
+    use fvm::kernel::{GasOps, IpldBlockOps, SupportedHashes};
     use fvm::machine::Machine;
     use fvm_ipld_blockstore::Blockstore;
     use fvm_ipld_encoding::DAG_CBOR;
@@ -130,6 +130,69 @@ mod ipld {
         Ok(())
     }
 
+    #[test]
+    fn create_limits() -> anyhow::Result<()> {
+        let (mut call_manager, _) = dummy::DummyCallManager::new_stub();
+        call_manager.machine.ctx.block_size_limits(2048, 4096);
+        let mut kern = TestingKernel::new(
+            call_manager,
+            BlockRegistry::default(),
+            0,
+            0,
+            0,
+            Zero::zero(),
+        );
+
+        // Up to the soft limit, only the usual creation cost is charged.
+        let before = kern.gas_used();
+        kern.block_create(DAG_CBOR, &[0; 2048])?;
+        let price_list = kern.price_list();
+        assert_eq!(
+            kern.gas_used() - before,
+            price_list.on_block_create(2048).total()
+        );
+
+        // Beyond the soft limit, a surcharge is charged on the excess.
+        let before = kern.gas_used();
+        kern.block_create(DAG_CBOR, &[0; 4096])?;
+        let price_list = kern.price_list();
+        assert_eq!(
+            kern.gas_used() - before,
+            price_list.on_block_create(4096).total() + price_list.on_block_oversize(2048).total()
+        );
+
+        // Beyond the hard limit, creation fails without charging for the block.
+        let before = kern.gas_used();
+        expect_syscall_err!(LimitExceeded, kern.block_create(DAG_CBOR, &[0; 4097]));
+        assert_eq!(kern.gas_used(), before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn create_strict_cbor() -> anyhow::Result<()> {
+        let (mut call_manager, _) = dummy::DummyCallManager::new_stub();
+        call_manager.machine.ctx.enable_strict_cbor();
+        let mut kern = TestingKernel::new(
+            call_manager,
+            BlockRegistry::default(),
+            0,
+            0,
+            0,
+            Zero::zero(),
+        );
+
+        // Canonical DAG-CBOR (and raw blocks) are accepted.
+        kern.block_create(DAG_CBOR, &fvm_ipld_encoding::to_vec(&(1u8, "foo"))?)?;
+        kern.block_create(fvm_shared::IPLD_RAW, &[0x18, 0x01])?;
+
+        // Non-minimal integers and indefinite lengths are rejected.
+        expect_syscall_err!(Serialization, kern.block_create(DAG_CBOR, &[0x18, 0x01]));
+        expect_syscall_err!(Serialization, kern.block_create(DAG_CBOR, &[0x9f, 0xff]));
+
+        Ok(())
+    }
+
     #[test]
     fn link() -> anyhow::Result<()> {
         let (mut kern, _) = build_inspecting_test()?;
@@ -432,6 +495,33 @@ mod ipld {
 
         Ok(())
     }
+
+    #[test]
+    fn close() -> anyhow::Result<()> {
+        let (call_manager, _) = dummy::DummyCallManager::new_stub();
+        let mut kern =
+            TestingKernel::new(call_manager, BlockRegistry::new(2), 0, 0, 0, Zero::zero());
+
+        let a = kern.block_create(DAG_CBOR, b"foo")?;
+        let b = kern.block_create(DAG_CBOR, b"bar")?;
+
+        // The frame is out of handles.
+        expect_syscall_err!(LimitExceeded, kern.block_create(DAG_CBOR, b"baz"));
+
+        // Closing a block makes room for another, under a new handle.
+        kern.block_close(a)?;
+        expect_syscall_err!(InvalidHandle, kern.block_stat(a));
+        expect_syscall_err!(InvalidHandle, kern.block_close(a));
+        let c = kern.block_create(DAG_CBOR, b"baz")?;
+        assert_eq!(c, b + 1);
+        expect_syscall_err!(InvalidHandle, kern.block_stat(a));
+        assert_eq!(kern.block_stat(b)?.size, 3);
+
+        expect_syscall_err!(InvalidHandle, kern.block_close(0));
+        expect_syscall_err!(InvalidHandle, kern.block_close(0xFF));
+
+        Ok(())
+    }
 }
 
 mod gas {
@@ -547,3 +637,208 @@ mod gas {
         Ok(())
     }
 }
+
+mod returns {
+    use fvm::kernel::{ReturnOps, MAX_RETURN_QUEUE_LEN};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn push_pop() -> anyhow::Result<()> {
+        let (mut kern, _) = build_inspecting_test()?;
+
+        assert_eq!(kern.return_len(), None);
+        expect_syscall_err!(NotFound, kern.return_pop());
+
+        kern.return_push(vec![1, 2, 3])?;
+        kern.return_push(vec![])?;
+
+        // payloads come out in the order they went in
+        assert_eq!(kern.return_len(), Some(3));
+        assert_eq!(kern.return_pop()?, vec![1, 2, 3]);
+        assert_eq!(kern.return_len(), Some(0));
+        assert_eq!(kern.return_pop()?, Vec::<u8>::new());
+        assert_eq!(kern.return_len(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn bounded() -> anyhow::Result<()> {
+        let (mut kern, _) = build_inspecting_test()?;
+
+        for i in 0..MAX_RETURN_QUEUE_LEN {
+            kern.return_push(vec![i as u8])?;
+        }
+        expect_syscall_err!(LimitExceeded, kern.return_push(vec![]));
+
+        // popping makes room again
+        assert_eq!(kern.return_pop()?, vec![0]);
+        kern.return_push(vec![])?;
+
+        Ok(())
+    }
+}
+
+mod read_only {
+    use cid::Cid;
+    use fvm::gas::Gas;
+    use fvm::kernel::read_only::ReadOnlyKernel;
+    use fvm::kernel::{
+        CronOps, EventOps, GasOps, IpldBlockOps, MessageOps, SelfOps, SendOps, TokenOps,
+    };
+    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_shared::address::Address;
+    use fvm_shared::event::ActorEvent;
+    use fvm_shared::sys::SendFlags;
+    use fvm_shared::token::TokenTransfer;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn inspect() -> anyhow::Result<()> {
+        let (kern, _) = build_inspecting_test()?;
+        let mut kern = ReadOnlyKernel(kern);
+
+        // Queries work, and the message context is flagged read-only.
+        assert!(kern.msg_context()?.flags.read_only());
+
+        // State changes are forbidden, and aren't charged for.
+        let used = kern.0.gas_used();
+        expect_syscall_err!(ReadOnly, kern.block_create(DAG_CBOR, b"foo"));
+        expect_syscall_err!(ReadOnly, kern.block_link(1, 0xb220, 32));
+        assert_eq!(kern.0.gas_used(), used);
+        expect_syscall_err!(ReadOnly, kern.set_root(Cid::default()));
+        expect_syscall_err!(ReadOnly, kern.self_destruct(&Address::new_id(1)));
+        expect_syscall_err!(ReadOnly, kern.cron_register(2));
+        expect_syscall_err!(
+            ReadOnly,
+            kern.send(
+                &Address::new_id(1),
+                0,
+                0,
+                &Zero::zero(),
+                None,
+                SendFlags::empty()
+            )
+        );
+        let transfer = TokenTransfer {
+            from: Address::new_id(1),
+            to: Address::new_id(2),
+            amount: Zero::zero(),
+            operator_data: Default::default(),
+            token_data: Default::default(),
+        };
+        expect_syscall_err!(ReadOnly, kern.token_transfer_hook(&transfer, None));
+        kern.emit_event(ActorEvent { entries: vec![] })?;
+
+        // Gas isn't charged to the actor.
+        let used = kern.gas_used();
+        let _ = kern.charge_gas("test", Gas::new(1000))?;
+        assert_eq!(kern.gas_used(), used);
+        assert_eq!(kern.gas_available(), Gas::from_milligas(i64::MAX));
+
+        Ok(())
+    }
+}
+
+mod cron {
+    use cid::Cid;
+    use fvm::cron_actor::{self, CRON_ACTOR_ID};
+    use fvm::kernel::CronOps;
+    use fvm::machine::Manifest;
+    use fvm::state_tree::ActorState;
+    use fvm_ipld_encoding::CborStore;
+    use fvm_shared::address::Address;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Builds a kernel for actor 100, with the given code, and deploys the cron actor.
+    fn build_cron_test(code: Cid) -> anyhow::Result<TestingKernel> {
+        let (mut call_manager, _) = DummyCallManager::new_stub();
+        let tree = &mut call_manager.machine.state_tree;
+        let state = tree
+            .store()
+            .put_cbor(&cron_actor::State::default(), Code::Blake2b256)?;
+        let cron_code = *Manifest::dummy().get_cron_code();
+        tree.set_actor(
+            CRON_ACTOR_ID,
+            ActorState::new(cron_code, state, Zero::zero(), 0, None),
+        )?;
+        tree.set_actor(100, ActorState::new(code, state, Zero::zero(), 0, None))?;
+        Ok(TestingKernel::new(
+            call_manager,
+            BlockRegistry::default(),
+            0,
+            100,
+            0,
+            Zero::zero(),
+        ))
+    }
+
+    fn entries(kern: TestingKernel) -> anyhow::Result<Vec<cron_actor::Entry>> {
+        let (call_manager, _) = kern.into_inner();
+        let (state, _) = cron_actor::State::load(&call_manager.machine.state_tree)?;
+        Ok(state.entries)
+    }
+
+    #[test]
+    fn register() -> anyhow::Result<()> {
+        let code = *Manifest::dummy().get_system_code();
+        let mut kern = build_cron_test(code)?;
+
+        kern.cron_register(2)?;
+        kern.cron_register(3)?;
+        kern.cron_unregister(2)?;
+
+        // Sends, constructors, and duplicates are rejected.
+        expect_syscall_err!(IllegalArgument, kern.cron_register(0));
+        expect_syscall_err!(IllegalArgument, kern.cron_register(1));
+        expect_syscall_err!(IllegalArgument, kern.cron_register(3));
+        expect_syscall_err!(NotFound, kern.cron_unregister(2));
+
+        assert_eq!(
+            entries(kern)?,
+            vec![cron_actor::Entry {
+                receiver: Address::new_id(100),
+                method_num: 3,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn user_actors_forbidden() -> anyhow::Result<()> {
+        let mut kern = build_cron_test(Cid::default())?;
+        expect_syscall_err!(Forbidden, kern.cron_register(2));
+        expect_syscall_err!(Forbidden, kern.cron_unregister(2));
+        assert_eq!(entries(kern)?, vec![]);
+        Ok(())
+    }
+}
+
+mod token {
+    use fvm::kernel::TokenOps;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::token::TokenTransfer;
+
+    use super::*;
+
+    #[test]
+    fn negative_amount() -> anyhow::Result<()> {
+        let (mut kern, _) = build_inspecting_test()?;
+        let transfer = TokenTransfer {
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            amount: TokenAmount::from_atto(-1),
+            operator_data: Default::default(),
+            token_data: Default::default(),
+        };
+        expect_syscall_err!(IllegalArgument, kern.token_transfer_hook(&transfer, None));
+        Ok(())
+    }
+}
@@ -8,11 +8,15 @@ use anyhow::Context;
 use cid::Cid;
 use fvm::call_manager::{Backtrace, CallManager, FinishRet, InvocationResult};
 use fvm::engine::Engine;
-use fvm::externs::{Chain, Consensus, Externs, Rand};
+use fvm::externs::{Chain, Consensus, Externs, Rand, StateReader};
 use fvm::gas::{Gas, GasCharge, GasTimer, GasTracker};
+use fvm::kernel::{
+    DevRandomness, FlagRegistry, HasherRegistry, LinkTracker, RandomnessCache, SyscallMemo,
+};
 use fvm::machine::limiter::MemoryLimiter;
 use fvm::machine::{Machine, MachineContext, Manifest, NetworkConfig};
 use fvm::state_tree::{ActorState, StateTree};
+use fvm::trace::ExecutionEvent;
 use fvm::{kernel, Kernel};
 use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
 use fvm_ipld_encoding::{CborStore, DAG_CBOR};
@@ -73,6 +77,8 @@ impl Chain for DummyExterns {
     }
 }
 
+impl StateReader for DummyExterns {}
+
 #[derive(Default)]
 pub struct DummyLimiter {
     curr_exec_memory_bytes: usize,
@@ -158,6 +164,10 @@ impl Machine for DummyMachine {
         &self.ctx
     }
 
+    fn context_mut(&mut self) -> &mut fvm::machine::MachineContext {
+        &mut self.ctx
+    }
+
     fn externs(&self) -> &Self::Externs {
         &DummyExterns
     }
@@ -218,6 +228,11 @@ pub struct DummyCallManager {
     pub nonce: u64,
     pub test_data: Rc<RefCell<TestData>>,
     limits: DummyLimiter,
+    randomness_cache: RandomnessCache,
+    syscall_memo: SyscallMemo,
+    link_tracker: LinkTracker,
+    hashers: HasherRegistry,
+    flags: FlagRegistry,
 }
 
 /// Information to be read by external tests
@@ -241,6 +256,11 @@ impl DummyCallManager {
                 limits: DummyLimiter::default(),
                 origin_address: Address::new_id(0),
                 gas_premium: TokenAmount::zero(),
+                randomness_cache: Default::default(),
+                syscall_memo: Default::default(),
+                link_tracker: Default::default(),
+                hashers: Default::default(),
+                flags: Default::default(),
             },
             cell_ref,
         )
@@ -261,6 +281,11 @@ impl DummyCallManager {
                 limits: DummyLimiter::default(),
                 origin_address: Address::new_id(0),
                 gas_premium: TokenAmount::zero(),
+                randomness_cache: Default::default(),
+                syscall_memo: Default::default(),
+                link_tracker: Default::default(),
+                hashers: Default::default(),
+                flags: Default::default(),
             },
             cell_ref,
         )
@@ -273,7 +298,7 @@ impl CallManager for DummyCallManager {
     fn new(
         machine: Self::Machine,
         _engine: Engine,
-        _gas_limit: i64,
+        _gas_limit: Option<i64>,
         origin: ActorID,
         origin_address: Address,
         nonce: u64,
@@ -292,6 +317,11 @@ impl CallManager for DummyCallManager {
             nonce,
             test_data: rc,
             limits,
+            randomness_cache: Default::default(),
+            syscall_memo: Default::default(),
+            link_tracker: Default::default(),
+            hashers: Default::default(),
+            flags: Default::default(),
         }
     }
 
@@ -308,6 +338,19 @@ impl CallManager for DummyCallManager {
         todo!()
     }
 
+    fn delegate_call<K: Kernel<CallManager = Self>>(
+        &mut self,
+        _from: fvm_shared::ActorID,
+        _to: fvm_shared::ActorID,
+        _code: Cid,
+        _method: fvm_shared::MethodNum,
+        _params: Option<kernel::Block>,
+        _value: &fvm_shared::econ::TokenAmount,
+        _gas_limit: Option<Gas>,
+    ) -> kernel::Result<InvocationResult> {
+        todo!()
+    }
+
     fn with_transaction(
         &mut self,
         _read_only: bool,
@@ -327,6 +370,8 @@ impl CallManager for DummyCallManager {
                 },
                 exec_trace: Vec::new(),
                 events: Vec::new(),
+                out_of_gas: None,
+                actor_gas: Default::default(),
             },
             self.machine,
         )
@@ -389,4 +434,40 @@ impl CallManager for DummyCallManager {
     fn append_event(&mut self, _evt: StampedEvent) {
         todo!()
     }
+
+    fn trace(&mut self, _event: ExecutionEvent) {
+        todo!()
+    }
+
+    fn event_bytes(&self) -> usize {
+        todo!()
+    }
+
+    fn randomness_cache(&self) -> &RandomnessCache {
+        &self.randomness_cache
+    }
+
+    fn dev_randomness(&self) -> Option<&DevRandomness> {
+        None
+    }
+
+    fn syscall_memo(&self) -> &SyscallMemo {
+        &self.syscall_memo
+    }
+
+    fn link_tracker(&self) -> &LinkTracker {
+        &self.link_tracker
+    }
+
+    fn link_tracker_mut(&mut self) -> &mut LinkTracker {
+        &mut self.link_tracker
+    }
+
+    fn hashers(&self) -> &HasherRegistry {
+        &self.hashers
+    }
+
+    fn flags(&self) -> &FlagRegistry {
+        &self.flags
+    }
 }
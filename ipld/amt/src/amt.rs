@@ -5,11 +5,12 @@
 use anyhow::anyhow;
 use cid::multihash::Code;
 use cid::Cid;
-use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_blockstore::cache::{CacheTracker, CountingBlockstore};
+use fvm_ipld_blockstore::{Block, Blockstore};
 use fvm_ipld_encoding::de::DeserializeOwned;
 use fvm_ipld_encoding::ser::Serialize;
 use fvm_ipld_encoding::serde::Deserialize;
-use fvm_ipld_encoding::CborStore;
+use fvm_ipld_encoding::{to_vec, CborStore, DAG_CBOR};
 use itertools::sorted;
 
 use super::ValueMut;
@@ -17,7 +18,8 @@ use crate::node::{CollapsedNode, Link};
 use crate::root::version::{Version as AmtVersion, V0, V3};
 use crate::root::RootImpl;
 use crate::{
-    init_sized_vec, nodes_for_height, Error, Node, DEFAULT_BIT_WIDTH, MAX_HEIGHT, MAX_INDEX,
+    init_sized_vec, nodes_for_height, CacheConfig, Error, FlushPolicy, Node, DEFAULT_BIT_WIDTH,
+    MAX_HEIGHT, MAX_INDEX,
 };
 
 #[derive(Debug)]
@@ -27,6 +29,8 @@ pub struct AmtImpl<V, BS, Ver> {
     block_store: BS,
     /// Remember the last flushed CID until it changes.
     flushed_cid: Option<Cid>,
+    cache_config: CacheConfig,
+    cache: CacheTracker,
 }
 
 /// Array Mapped Trie allows for the insertion and persistence of data, serializable to a CID.
@@ -75,6 +79,8 @@ where
             root: RootImpl::new_with_bit_width(bit_width),
             block_store,
             flushed_cid: None,
+            cache_config: Default::default(),
+            cache: Default::default(),
         }
     }
 
@@ -160,9 +166,43 @@ where
             root,
             block_store,
             flushed_cid: Some(*cid),
+            cache_config: Default::default(),
+            cache: Default::default(),
         })
     }
 
+    /// Sets the budget for the AMT's node cache. See [`CacheConfig`].
+    pub fn set_cache_config(&mut self, conf: CacheConfig) {
+        self.cache_config = conf;
+    }
+
+    /// Returns the budget for the AMT's node cache.
+    pub fn cache_config(&self) -> &CacheConfig {
+        &self.cache_config
+    }
+
+    /// Evicts the least recently used cached nodes down to half of the cache budget, after flushing
+    /// the AMT if the [`FlushPolicy`] says so.
+    ///
+    /// This is called automatically by mutating methods when the cache exceeds its budget.
+    pub fn trim_cache(&mut self) -> Result<(), Error> {
+        if self.cache_config.flush_policy == FlushPolicy::OnEviction {
+            self.flush()?;
+        }
+        self.cache.trim(&self.cache_config, &mut self.root.node);
+        Ok(())
+    }
+
+    /// Records a modification, then trims the cache if it exceeds its budget.
+    fn modified(&mut self) -> Result<(), Error> {
+        self.flushed_cid = None;
+        self.cache.add(1);
+        if self.cache.needs_trim(&self.cache_config) {
+            self.trim_cache()?;
+        }
+        Ok(())
+    }
+
     /// Get value at index of AMT
     pub fn get(&self, i: u64) -> Result<Option<&V>, Error> {
         if i > MAX_INDEX {
@@ -173,9 +213,12 @@ where
             return Ok(None);
         }
 
-        self.root
-            .node
-            .get(&self.block_store, self.height(), self.bit_width(), i)
+        self.root.node.get(
+            &CountingBlockstore::new(&self.block_store, &self.cache),
+            self.height(),
+            self.bit_width(),
+            i,
+        )
     }
 
     /// Set value at index
@@ -210,14 +253,20 @@ where
         if self
             .root
             .node
-            .set(&self.block_store, self.height(), self.bit_width(), i, val)?
+            .set(
+                &CountingBlockstore::new(&self.block_store, &self.cache),
+                self.height(),
+                self.bit_width(),
+                i,
+                val,
+            )?
             .is_none()
         {
             self.root.count += 1;
         }
 
         // There's no equality constraint on `V` so we could check if the content changed.
-        self.flushed_cid = None;
+        self.modified()?;
 
         Ok(())
     }
@@ -244,16 +293,17 @@ where
         }
 
         // Delete node from AMT
-        let deleted =
-            self.root
-                .node
-                .delete(&self.block_store, self.height(), self.bit_width(), i)?;
+        let deleted = self.root.node.delete(
+            &CountingBlockstore::new(&self.block_store, &self.cache),
+            self.height(),
+            self.bit_width(),
+            i,
+        )?;
 
         if deleted.is_none() {
             return Ok(None);
         }
 
-        self.flushed_cid = None;
         self.root.count -= 1;
 
         if self.root.node.is_empty() {
@@ -293,6 +343,8 @@ where
             }
         }
 
+        self.modified()?;
+
         Ok(deleted)
     }
 
@@ -331,6 +383,41 @@ where
         Ok(cid)
     }
 
+    /// Like [`Amt::flush`](AmtImpl::flush), but collects all dirty nodes (and the root) and writes
+    /// them to the blockstore with a single [`Blockstore::put_many_keyed`] call, instead of one put
+    /// per node.
+    ///
+    /// ```
+    /// use fvm_ipld_amt::Amt;
+    ///
+    /// let store = fvm_ipld_blockstore::MemoryBlockstore::default();
+    ///
+    /// let mut amt: Amt<String, _> = Amt::new(&store);
+    /// for i in 0..100 {
+    ///     amt.set(i, i.to_string()).unwrap();
+    /// }
+    /// let cid = amt.flush_batched().unwrap();
+    ///
+    /// let amt: Amt<String, _> = Amt::load(&cid, &store).unwrap();
+    /// assert_eq!(amt.get(42).unwrap(), Some(&"42".to_string()));
+    /// ```
+    pub fn flush_batched(&mut self) -> Result<Cid, Error> {
+        if let Some(cid) = self.flushed_cid {
+            return Ok(cid);
+        }
+        let mut blocks = Vec::new();
+        let mut put = |data: Vec<u8>| {
+            let cid = Block::new(DAG_CBOR, &data).cid(Code::Blake2b256);
+            blocks.push((cid, data));
+            cid
+        };
+        self.root.node.flush_with(&mut |n| Ok(put(to_vec(n)?)))?;
+        let cid = put(to_vec(&self.root)?);
+        self.block_store.put_many_keyed(blocks)?;
+        self.flushed_cid = Some(cid);
+        Ok(cid)
+    }
+
     /// Iterates over each value in the Amt and runs a function on the values.
     ///
     /// The index in the amt is a `u64` and the value is the generic parameter `V` as defined
@@ -374,7 +461,7 @@ where
         self.root
             .node
             .for_each_while(
-                &self.block_store,
+                &CountingBlockstore::new(&self.block_store, &self.cache),
                 self.height(),
                 self.bit_width(),
                 0,
@@ -408,7 +495,7 @@ where
         #[cfg(not(feature = "go-interop"))]
         {
             let (_, did_mutate) = self.root.node.for_each_while_mut(
-                &self.block_store,
+                &CountingBlockstore::new(&self.block_store, &self.cache),
                 self.height(),
                 self.bit_width(),
                 0,
@@ -416,7 +503,7 @@ where
             )?;
 
             if did_mutate {
-                self.flushed_cid = None;
+                self.modified()?;
             }
 
             Ok(())
@@ -432,7 +519,7 @@ where
             let mut mutated = Vec::new();
 
             self.root.node.for_each_while_mut(
-                &self.block_store,
+                &CountingBlockstore::new(&self.block_store, &self.cache),
                 self.height(),
                 self.bit_width(),
                 0,
@@ -13,6 +13,8 @@ mod node;
 mod root;
 mod value_mut;
 
+pub use fvm_ipld_blockstore::cache::{CacheConfig, FlushPolicy};
+
 pub use self::amt::{Amt, Amtv0};
 pub use self::error::Error;
 pub(crate) use self::node::Node;
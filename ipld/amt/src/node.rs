@@ -7,9 +7,9 @@ use std::convert::{TryFrom, TryInto};
 use anyhow::anyhow;
 use cid::multihash::Code;
 use cid::Cid;
+use fvm_ipld_blockstore::cache::{CachedNode, Child, NodeCache};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::{strict_bytes, BytesSer, CborStore};
-use once_cell::unsync::OnceCell;
 use serde::de::{self, DeserializeOwned};
 use serde::{ser, Deserialize, Serialize};
 
@@ -22,7 +22,7 @@ pub(super) enum Link<V> {
     /// Unchanged link to data with an atomic cache.
     Cid {
         cid: Cid,
-        cache: OnceCell<Box<Node<V>>>,
+        cache: NodeCache<Box<Node<V>>>,
     },
     /// Modifications have been made to the link, requires flush to clear
     Dirty(Box<Node<V>>),
@@ -168,6 +168,19 @@ impl<V> CollapsedNode<V> {
     }
 }
 
+impl<V> CachedNode for Node<V> {
+    fn for_each_child(&mut self, f: &mut dyn FnMut(Child<'_, Self>)) {
+        if let Node::Link { links } = self {
+            for link in links.iter_mut().flatten() {
+                match link {
+                    Link::Cid { cache, .. } => f(Child::Cached(cache)),
+                    Link::Dirty(node) => f(Child::Dirty(node)),
+                }
+            }
+        }
+    }
+}
+
 impl<V> Node<V>
 where
     V: Serialize + DeserializeOwned,
@@ -182,21 +195,30 @@ where
 
     /// Flushes cache for node, replacing any cached values with a Cid variant
     pub(super) fn flush<DB: Blockstore>(&mut self, bs: &DB) -> Result<(), Error> {
+        self.flush_with(&mut |n| Ok(bs.put_cbor(n, Code::Blake2b256)?))
+    }
+
+    /// Flushes all dirty sub nodes, children before their parents, handing each one to `put` and
+    /// replacing it with a link to the returned CID.
+    pub(super) fn flush_with<F>(&mut self, put: &mut F) -> Result<(), Error>
+    where
+        F: FnMut(&Self) -> Result<Cid, Error>,
+    {
         if let Node::Link { links } = self {
             for link in links.iter_mut().flatten() {
                 // links should only be flushed if the bitmap is set.
                 if let Link::Dirty(n) = link {
                     // flush sub node to clear caches
-                    n.flush(bs)?;
+                    n.flush_with(put)?;
 
-                    // Puts node in blockstore and and retrieves it's CID
-                    let cid = bs.put_cbor(n, Code::Blake2b256)?;
+                    // Stores the node and retrieves it's CID
+                    let cid = put(n)?;
 
                     // Replace the data with some arbitrary node to move without requiring clone
                     let existing = std::mem::replace(n, Box::new(Node::empty()));
 
                     // Can keep the flushed node in link cache
-                    let cache = OnceCell::from(existing);
+                    let cache = NodeCache::from(existing);
                     *link = Link::Cid { cid, cache };
                 }
             }
@@ -4,7 +4,7 @@
 
 use std::fmt::Debug;
 
-use fvm_ipld_amt::{Amt, Amtv0, Error, MAX_INDEX};
+use fvm_ipld_amt::{Amt, Amtv0, CacheConfig, Error, FlushPolicy, MAX_INDEX};
 use fvm_ipld_blockstore::tracking::{BSStats, TrackingBlockstore};
 use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
 use fvm_ipld_encoding::de::DeserializeOwned;
@@ -198,6 +198,36 @@ fn flush_read() {
     assert_eq!(*db.stats.borrow(), BSStats {r: 0, w: 16, br: 0, bw: 1930});
 }
 
+#[test]
+fn flush_batched() {
+    let build = |store| {
+        let mut a = Amt::new(store);
+        for i in 0..1000 {
+            a.set(i, tbytes(b"foo foo bar")).unwrap();
+        }
+        a
+    };
+
+    let mem = MemoryBlockstore::default();
+    let db = TrackingBlockstore::new(&mem);
+    let expected = build(&db).flush().unwrap();
+    let expected_stats = *db.stats.borrow();
+
+    // The same blocks are written, just in one batch.
+    let mem = MemoryBlockstore::default();
+    let db = TrackingBlockstore::new(&mem);
+    let mut a = build(&db);
+    assert_eq!(a.flush_batched().unwrap(), expected);
+    assert_eq!(*db.stats.borrow(), expected_stats);
+
+    let mut a = Amt::load(&expected, &mem).unwrap();
+    assert_get(&a, 999, &tbytes(b"foo foo bar"));
+    a.delete(999).unwrap();
+    let cid = a.flush_batched().unwrap();
+    let a: Amt<BytesDe, _> = Amt::load(&cid, &mem).unwrap();
+    assert_eq!(a.count(), 999);
+}
+
 #[test]
 fn delete() {
     let mem = fvm_ipld_blockstore::MemoryBlockstore::default();
@@ -454,6 +484,51 @@ fn delete_bug_test() {
     assert_eq!(*db.stats.borrow(), BSStats {r:0, w:2, br:0, bw:18});
 }
 
+#[test]
+fn cache_budget() {
+    let mem = MemoryBlockstore::default();
+    let mut a = Amt::new(&mem);
+    for i in 0..1000u64 {
+        a.set(i, i).unwrap();
+    }
+    let expected = a.flush().unwrap();
+
+    // Modify the AMT, and reload all of its nodes into the cache.
+    let modify = |conf: CacheConfig| {
+        let db = TrackingBlockstore::new(&mem);
+        let mut a: Amt<u64, _> = Amt::load(&expected, &db).unwrap();
+        a.set_cache_config(conf);
+        a.for_each(|_, _| Ok(())).unwrap();
+        a.delete(0).unwrap();
+        let reads = db.stats.borrow().r;
+        a.for_each(|_, _| Ok(())).unwrap();
+        let cached = reads == db.stats.borrow().r;
+        a.set(0, 0).unwrap();
+        (cached, a.flush().unwrap())
+    };
+
+    // Without a budget, every node stays cached.
+    assert_eq!(modify(CacheConfig::default()), (true, expected));
+
+    // With a budget, nodes are evicted and reloaded without changing the result.
+    let bounded = CacheConfig {
+        max_nodes: Some(8),
+        flush_policy: FlushPolicy::Manual,
+    };
+    assert_eq!(modify(bounded), (false, expected));
+
+    // Flushing on eviction allows the AMT to be built in bounded memory.
+    let mut a = Amt::new(&mem);
+    a.set_cache_config(CacheConfig {
+        max_nodes: Some(8),
+        flush_policy: FlushPolicy::OnEviction,
+    });
+    for i in 0..1000u64 {
+        a.set(i, i).unwrap();
+    }
+    assert_eq!(a.flush().unwrap(), expected);
+}
+
 fn tbytes(bz: &[u8]) -> BytesDe {
     BytesDe(bz.to_vec())
 }
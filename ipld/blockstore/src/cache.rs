@@ -0,0 +1,329 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Bounded node caches for the IPLD collections (the AMT and the HAMT).
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Result;
+use cid::multihash::Code;
+use cid::Cid;
+use once_cell::unsync::OnceCell;
+
+use super::{Block, Blockstore};
+
+/// Bounds the number of nodes a collection keeps cached in memory.
+///
+/// Nodes loaded from the blockstore are cached so that they don't need to be loaded (and decoded)
+/// again. By default, the cache is unbounded. With a budget, the least recently used unmodified
+/// nodes are evicted whenever the (estimated) number of cached nodes exceeds the budget.
+/// Modified nodes can't be evicted until they're flushed; see [`FlushPolicy`].
+///
+/// Eviction is performed by the collection's mutating methods, and by its `trim_cache` method.
+/// Nodes loaded by read-only methods remain cached until then.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// The maximum number of nodes to keep cached, or `None` for no limit.
+    pub max_nodes: Option<usize>,
+    /// What to do with modified nodes when the cache exceeds its budget.
+    pub flush_policy: FlushPolicy,
+}
+
+/// What to do with modified nodes when the cache exceeds its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Keep modified nodes in memory until the collection is explicitly flushed.
+    Manual,
+    /// Flush the collection to the blockstore before evicting nodes, so that modified nodes can be
+    /// evicted too. This writes intermediate nodes that may never be referenced by a final root.
+    OnEviction,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy::Manual
+    }
+}
+
+/// Orders node accesses. Only the relative order of accesses to nodes of the same collection
+/// matters, so a single process-wide counter is shared by all collections.
+static CLOCK: AtomicU64 = AtomicU64::new(1);
+
+fn now() -> u64 {
+    CLOCK.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A lazily loaded node, along with the time it was last used.
+#[derive(Debug)]
+pub struct NodeCache<T> {
+    node: OnceCell<T>,
+    last_used: Cell<u64>,
+}
+
+impl<T> Default for NodeCache<T> {
+    fn default() -> Self {
+        Self {
+            node: OnceCell::new(),
+            last_used: Cell::new(0),
+        }
+    }
+}
+
+impl<T> From<T> for NodeCache<T> {
+    fn from(node: T) -> Self {
+        Self {
+            node: OnceCell::from(node),
+            last_used: Cell::new(now()),
+        }
+    }
+}
+
+impl<T> NodeCache<T> {
+    pub fn get(&self) -> Option<&T> {
+        self.last_used.set(now());
+        self.node.get()
+    }
+
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        self.last_used.set(now());
+        self.node.get_or_init(f)
+    }
+
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        self.last_used.set(now());
+        self.node.get_or_try_init(f)
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.last_used.set(now());
+        self.node.get_mut()
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.node.into_inner()
+    }
+}
+
+/// A child of a [`CachedNode`].
+pub enum Child<'a, N> {
+    /// An unmodified child, which may be evicted.
+    Cached(&'a mut NodeCache<Box<N>>),
+    /// A modified child, which stays in memory until it's flushed.
+    Dirty(&'a mut N),
+}
+
+/// A collection node whose children are cached in [`NodeCache`]s.
+pub trait CachedNode: Sized {
+    /// Calls `f` on each of the node's children.
+    fn for_each_child(&mut self, f: &mut dyn FnMut(Child<'_, Self>));
+}
+
+/// Evicts the least recently used unmodified nodes below `root`, until about `max_nodes` nodes
+/// (if possible) are held in memory. Returns the number of nodes still held in memory.
+///
+/// A node is considered used whenever one of its descendants is, so that a node is never evicted
+/// before its descendants (which would evict them too).
+pub fn evict_lru<N: CachedNode>(root: &mut N, max_nodes: usize) -> usize {
+    let mut stamps = Vec::new();
+    let mut dirty = 0;
+    propagate_last_used(root, &mut stamps, &mut dirty);
+
+    let keep = max_nodes.saturating_sub(dirty);
+    if stamps.len() <= keep {
+        return stamps.len() + dirty;
+    }
+    let cutoff = if keep == 0 {
+        u64::MAX
+    } else {
+        let idx = stamps.len() - keep;
+        *stamps.select_nth_unstable(idx).1
+    };
+    evict_before(root, cutoff)
+}
+
+/// Sets the last use of every cached node below `node` to the latest use of it and its
+/// descendants, collecting them in `stamps`. Returns the latest use below `node`.
+fn propagate_last_used<N: CachedNode>(
+    node: &mut N,
+    stamps: &mut Vec<u64>,
+    dirty: &mut usize,
+) -> u64 {
+    let mut latest = 0;
+    node.for_each_child(&mut |child| {
+        let last_used = match child {
+            Child::Cached(cache) => match cache.node.get_mut() {
+                Some(child) => {
+                    let last_used =
+                        propagate_last_used(&mut **child, stamps, dirty).max(cache.last_used.get());
+                    cache.last_used.set(last_used);
+                    stamps.push(last_used);
+                    last_used
+                }
+                None => 0,
+            },
+            Child::Dirty(child) => {
+                *dirty += 1;
+                propagate_last_used(child, stamps, dirty)
+            }
+        };
+        latest = latest.max(last_used);
+    });
+    latest
+}
+
+/// Evicts the cached nodes below `node` last used before `cutoff`. Returns the number of nodes
+/// still held in memory.
+fn evict_before<N: CachedNode>(node: &mut N, cutoff: u64) -> usize {
+    let mut nodes = 0;
+    node.for_each_child(&mut |child| {
+        let child = match child {
+            Child::Cached(cache) => {
+                if cache.last_used.get() < cutoff {
+                    cache.node.take();
+                }
+                cache.node.get_mut().map(|child| &mut **child)
+            }
+            Child::Dirty(child) => Some(child),
+        };
+        if let Some(child) = child {
+            nodes += 1 + evict_before(child, cutoff);
+        }
+    });
+    nodes
+}
+
+/// Decides when to run an eviction pass, based on an estimate of the number of cached nodes.
+#[derive(Debug, Default)]
+pub struct CacheTracker {
+    /// The estimated number of cached nodes.
+    nodes: Cell<usize>,
+    /// Run an eviction pass when the estimate exceeds this.
+    threshold: usize,
+}
+
+impl CacheTracker {
+    /// Records that a node was loaded or modified.
+    pub fn add(&self, n: usize) {
+        self.nodes.set(self.nodes.get().saturating_add(n));
+    }
+
+    pub fn needs_trim(&self, conf: &CacheConfig) -> bool {
+        matches!(conf.max_nodes, Some(max) if self.nodes.get() > self.threshold.max(max))
+    }
+
+    /// Evicts the least recently used nodes below `root` down to half of the budget, so that
+    /// eviction passes are amortized over many operations.
+    pub fn trim<N: CachedNode>(&mut self, conf: &CacheConfig, root: &mut N) {
+        let nodes = evict_lru(root, conf.max_nodes.map_or(usize::MAX, |max| max / 2));
+        self.nodes.set(nodes);
+        // Nodes that couldn't be evicted (modified nodes) may exceed the budget. Don't try again
+        // until the cache has doubled, to avoid a full eviction pass on every operation.
+        self.threshold = nodes.saturating_mul(2);
+    }
+}
+
+/// A blockstore wrapper that counts the nodes loaded by a collection operation.
+pub struct CountingBlockstore<'a, BS> {
+    store: &'a BS,
+    tracker: &'a CacheTracker,
+}
+
+impl<'a, BS> CountingBlockstore<'a, BS> {
+    pub fn new(store: &'a BS, tracker: &'a CacheTracker) -> Self {
+        Self { store, tracker }
+    }
+}
+
+impl<BS: Blockstore> Blockstore for CountingBlockstore<'_, BS> {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.tracker.add(1);
+        self.store.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.store.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.store.has(k)
+    }
+
+    fn put<D>(&self, mh_code: Code, block: &Block<D>) -> Result<Cid>
+    where
+        D: AsRef<[u8]>,
+    {
+        self.store.put(mh_code, block)
+    }
+
+    fn put_many<D, I>(&self, blocks: I) -> Result<()>
+    where
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Code, Block<D>)>,
+    {
+        self.store.put_many(blocks)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.store.put_many_keyed(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tree of nodes, all cached.
+    #[derive(Default)]
+    struct Node(Vec<NodeCache<Box<Node>>>);
+
+    impl CachedNode for Node {
+        fn for_each_child(&mut self, f: &mut dyn FnMut(Child<'_, Self>)) {
+            for cache in &mut self.0 {
+                f(Child::Cached(cache))
+            }
+        }
+    }
+
+    fn leaves(n: usize) -> Node {
+        Node((0..n).map(|_| NodeCache::from(Box::default())).collect())
+    }
+
+    fn is_cached(node: &mut Node, path: &[usize]) -> bool {
+        match path.split_first() {
+            None => true,
+            Some((&i, rest)) => match node.0[i].node.get_mut() {
+                Some(child) => is_cached(child, rest),
+                None => false,
+            },
+        }
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let mut root = Node(vec![
+            NodeCache::from(Box::new(leaves(2))),
+            NodeCache::from(Box::new(leaves(2))),
+        ]);
+        assert_eq!(evict_lru(&mut root, 6), 6);
+
+        // Using a leaf keeps its parent too.
+        root.0[0].get().unwrap().0[1].get();
+        root.0[1].get();
+        assert_eq!(evict_lru(&mut root, 3), 3);
+        assert!(is_cached(&mut root, &[0, 1]));
+        assert!(is_cached(&mut root, &[1]));
+        assert!(!is_cached(&mut root, &[0, 0]));
+        assert!(!is_cached(&mut root, &[1, 0]));
+        assert!(!is_cached(&mut root, &[1, 1]));
+
+        assert_eq!(evict_lru(&mut root, 0), 0);
+        assert!(!is_cached(&mut root, &[0]));
+    }
+}
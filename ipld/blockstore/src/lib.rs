@@ -5,6 +5,7 @@ use std::rc::Rc;
 use anyhow::Result;
 use cid::{multihash, Cid};
 
+pub mod cache;
 pub mod tracking;
 
 mod memory;
@@ -0,0 +1,352 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::ops::ControlFlow;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use cid::Cid;
+use futures::executor::block_on;
+use futures::AsyncRead;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{scan_for_links, DAG_CBOR};
+
+use crate::{Block, CarReader, Error};
+
+/// The number of blocks read between progress callbacks in [`import_car`].
+pub const IMPORT_PROGRESS_INTERVAL: u64 = 1000;
+
+const FIL_COMMITMENT_UNSEALED: u64 = 0xf101;
+const FIL_COMMITMENT_SEALED: u64 = 0xf102;
+
+/// Options for [`import_car`].
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// The number of threads verifying and inserting blocks.
+    ///
+    /// DEFAULT: The available parallelism.
+    pub workers: usize,
+    /// The number of blocks inserted into the blockstore at once.
+    ///
+    /// DEFAULT: 1000
+    pub batch_size: usize,
+    /// Whether to check that each block's CID matches its data.
+    ///
+    /// DEFAULT: true
+    pub verify_cids: bool,
+    /// Whether to check that every block reachable from the CAR's roots was imported (or was
+    /// already in the blockstore).
+    ///
+    /// DEFAULT: true
+    pub audit_roots: bool,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            batch_size: 1000,
+            verify_cids: true,
+            audit_roots: true,
+        }
+    }
+}
+
+/// Progress of an [`import_car`] operation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportProgress {
+    /// The number of blocks read so far.
+    pub blocks: u64,
+    /// The number of bytes of block data read so far.
+    pub bytes: u64,
+}
+
+/// The result of an [`import_car`] operation.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportStats {
+    /// The roots claimed by the CAR header.
+    pub roots: Vec<Cid>,
+    /// The number of blocks imported.
+    pub blocks: u64,
+    /// The number of bytes of block data imported.
+    pub bytes: u64,
+    /// The number of distinct blocks reachable from the roots, if audited. Identity-hashed CIDs
+    /// and sector commitments aren't stored, so they aren't counted.
+    pub reachable: Option<u64>,
+}
+
+/// Imports a CAR (e.g., a chain state snapshot) into a blockstore, returning statistics about the
+/// import.
+///
+/// Blocks are verified and inserted in batches by `options.workers` threads while the CAR is
+/// read, so the blockstore must be thread-safe. `progress` is called every
+/// [`IMPORT_PROGRESS_INTERVAL`] blocks and once more when all blocks have been read; returning
+/// [`ControlFlow::Break`] cancels the import with an error (blocks already inserted are left in
+/// the blockstore).
+///
+/// Once all blocks are inserted, the DAG-CBOR DAGs under the CAR's roots are walked to check that
+/// no blocks are missing, unless disabled in the options.
+///
+/// This drives the reader on the current thread, blocking it until the import completes.
+pub fn import_car<R, B, F>(
+    store: &B,
+    reader: R,
+    options: &ImportOptions,
+    mut progress: F,
+) -> Result<ImportStats, Error>
+where
+    R: AsyncRead + Send + Unpin,
+    B: Blockstore + Sync,
+    F: FnMut(ImportProgress) -> ControlFlow<()>,
+{
+    let workers = options.workers.max(1);
+    let batch_size = options.batch_size.max(1);
+    let (tx, rx) = sync_channel::<Vec<Block>>(workers);
+    // Shared by the workers only, so sends fail once they've all exited.
+    let rx = Arc::new(Mutex::new(rx));
+
+    let mut stats = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                let rx = rx.clone();
+                scope.spawn(move || insert_batches(store, &rx, options.verify_cids))
+            })
+            .collect();
+        drop(rx);
+
+        let read = block_on(async {
+            let mut car_reader = CarReader::new_unchecked(reader).await?;
+            let mut stats = ImportStats {
+                roots: std::mem::take(&mut car_reader.header.roots),
+                ..Default::default()
+            };
+            let mut batch = Vec::with_capacity(batch_size);
+            while let Some(block) = car_reader.next_block().await? {
+                stats.blocks += 1;
+                stats.bytes += block.data.len() as u64;
+                batch.push(block);
+                if batch.len() >= batch_size {
+                    let batch = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+                    // Only fails if all workers have failed, in which case we report their error.
+                    if tx.send(batch).is_err() {
+                        break;
+                    }
+                }
+                if stats.blocks % IMPORT_PROGRESS_INTERVAL == 0
+                    && progress(ImportProgress {
+                        blocks: stats.blocks,
+                        bytes: stats.bytes,
+                    })
+                    .is_break()
+                {
+                    return Err(Error::Other("CAR import cancelled".into()));
+                }
+            }
+            let _ = tx.send(batch);
+            if progress(ImportProgress {
+                blocks: stats.blocks,
+                bytes: stats.bytes,
+            })
+            .is_break()
+            {
+                return Err(Error::Other("CAR import cancelled".into()));
+            }
+            Ok(stats)
+        });
+        drop(tx);
+
+        // Prefer worker errors, as they cause the reader to stop early.
+        for handle in handles {
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(Error::Other("CAR import worker panicked".into())))?;
+        }
+        read
+    })?;
+
+    if options.audit_roots {
+        stats.reachable = Some(audit_roots(store, &stats.roots)?);
+    }
+    Ok(stats)
+}
+
+/// Verifies (if requested) and inserts batches of blocks until the channel is closed.
+fn insert_batches<B: Blockstore>(
+    store: &B,
+    rx: &Mutex<Receiver<Vec<Block>>>,
+    verify_cids: bool,
+) -> Result<(), Error> {
+    loop {
+        let batch = match rx.lock().unwrap().recv() {
+            Ok(batch) => batch,
+            Err(_) => return Ok(()),
+        };
+        if verify_cids {
+            batch.iter().try_for_each(Block::validate)?;
+        }
+        store
+            .put_many_keyed(batch.iter().map(|b| (b.cid, &b.data)))
+            .map_err(|e| Error::Other(e.to_string()))?;
+    }
+}
+
+/// Walks the DAGs under the given roots, returning the number of distinct blocks reachable from
+/// them, and failing if any are missing from the store.
+fn audit_roots<B: Blockstore>(store: &B, roots: &[Cid]) -> Result<u64, Error> {
+    let mut reachable = 0;
+    let mut seen: HashSet<Cid> = roots.iter().copied().collect();
+    let mut stack: Vec<Cid> = seen.iter().copied().collect();
+    while let Some(cid) = stack.pop() {
+        if matches!(cid.codec(), FIL_COMMITMENT_SEALED | FIL_COMMITMENT_UNSEALED) {
+            continue;
+        }
+        let block = if cid.hash().code() == 0x0 {
+            cid.hash().digest().to_vec()
+        } else {
+            reachable += 1;
+            store
+                .get(&cid)
+                .map_err(|e| Error::Other(e.to_string()))?
+                .ok_or_else(|| {
+                    Error::InvalidFile(format!("block {} reachable from the roots is missing", cid))
+                })?
+        };
+        if cid.codec() == DAG_CBOR {
+            scan_for_links(&mut Cursor::new(&block), |link| {
+                if seen.insert(link) {
+                    stack.push(link);
+                }
+                Ok(())
+            })
+            .map_err(|e| Error::ParsingError(e.to_string()))?;
+        }
+    }
+    Ok(reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use cid::multihash::Code::Blake2b256;
+    use cid::multihash::MultihashDigest;
+    use cid::Cid;
+    use futures::stream;
+    use fvm_ipld_blockstore::Blockstore;
+    use fvm_ipld_encoding::{to_vec, DAG_CBOR, IPLD_RAW};
+
+    use super::{import_car, ImportOptions, ImportProgress};
+    use crate::{CarHeader, Error};
+
+    /// A blockstore that's safe to share between the import workers.
+    #[derive(Default)]
+    struct SyncBlockstore(std::sync::Mutex<fvm_ipld_blockstore::MemoryBlockstore>);
+
+    impl Blockstore for SyncBlockstore {
+        fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+            self.0.lock().unwrap().get(k)
+        }
+
+        fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+            self.0.lock().unwrap().put_keyed(k, block)
+        }
+    }
+
+    fn cid(codec: u64, data: &[u8]) -> Cid {
+        Cid::new_v1(codec, Blake2b256.digest(data))
+    }
+
+    fn car(roots: Vec<Cid>, blocks: Vec<(Cid, Vec<u8>)>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        futures::executor::block_on(
+            CarHeader::new(roots, 1).write_stream_async(&mut buf, &mut stream::iter(blocks)),
+        )
+        .unwrap();
+        buf
+    }
+
+    /// A root linking to `n` raw leaves (and a sector commitment, which isn't stored).
+    fn dag(n: u8) -> (Cid, Vec<(Cid, Vec<u8>)>) {
+        let mut blocks: Vec<_> = (0..n).map(|i| (cid(IPLD_RAW, &[i]), vec![i])).collect();
+        let commd = Cid::new_v1(0xf101, Blake2b256.digest(b"commd"));
+        let links: Vec<_> = blocks.iter().map(|(c, _)| *c).chain([commd]).collect();
+        let root = to_vec(&links).unwrap();
+        let root_cid = cid(DAG_CBOR, &root);
+        blocks.insert(0, (root_cid, root));
+        (root_cid, blocks)
+    }
+
+    #[test]
+    fn import() {
+        let (root, blocks) = dag(5);
+        let car = car(vec![root], blocks.clone());
+        let options = ImportOptions {
+            workers: 3,
+            batch_size: 2,
+            ..Default::default()
+        };
+
+        let bs = SyncBlockstore::default();
+        let mut reports = Vec::new();
+        let stats = import_car(&bs, &car[..], &options, |p| {
+            reports.push(p);
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(stats.roots, [root]);
+        assert_eq!(stats.blocks, 6);
+        assert_eq!(stats.reachable, Some(6));
+        let bytes = blocks.iter().map(|(_, d)| d.len() as u64).sum();
+        assert_eq!(stats.bytes, bytes);
+        assert_eq!(reports, [ImportProgress { blocks: 6, bytes }]);
+        for (cid, data) in blocks {
+            assert_eq!(bs.get(&cid).unwrap(), Some(data));
+        }
+
+        // Cancelling.
+        let err = import_car(&bs, &car[..], &options, |_| ControlFlow::Break(())).unwrap_err();
+        assert!(matches!(err, Error::Other(_)), "{err}");
+    }
+
+    #[test]
+    fn invalid() {
+        let options = ImportOptions::default();
+        let (root, mut blocks) = dag(3);
+
+        // A block is missing.
+        let missing = blocks.pop().unwrap();
+        let car1 = car(vec![root], blocks.clone());
+        let err = import_car(&SyncBlockstore::default(), &car1[..], &options, |_| {
+            ControlFlow::Continue(())
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains(&missing.0.to_string()), "{err}");
+
+        // Unless it's already in the store, or the roots aren't audited.
+        let bs = SyncBlockstore::default();
+        bs.put_keyed(&missing.0, &missing.1).unwrap();
+        let stats = import_car(&bs, &car1[..], &options, |_| ControlFlow::Continue(())).unwrap();
+        assert_eq!((stats.blocks, stats.reachable), (3, Some(4)));
+        let no_audit = ImportOptions {
+            audit_roots: false,
+            ..Default::default()
+        };
+        let stats = import_car(&SyncBlockstore::default(), &car1[..], &no_audit, |_| {
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(stats.reachable, None);
+
+        // A block doesn't match its CID.
+        blocks[1].1 = b"bad".to_vec();
+        let car2 = car(vec![root], blocks);
+        let err = import_car(&SyncBlockstore::default(), &car2[..], &no_audit, |_| {
+            ControlFlow::Continue(())
+        })
+        .unwrap_err();
+        assert!(matches!(err, Error::InvalidFile(_)), "{err}");
+    }
+}
@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 mod error;
+mod import;
 mod util;
 
 use std::convert::TryFrom;
@@ -12,6 +13,7 @@ pub use error::*;
 use futures::{AsyncRead, AsyncWrite, Stream, StreamExt};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::{from_slice, to_vec};
+pub use import::*;
 use serde::{Deserialize, Serialize};
 use util::{ld_read, ld_write, read_node};
 
@@ -96,32 +98,13 @@ where
 
     /// Returns the next IPLD Block in the buffer
     pub async fn next_block(&mut self) -> Result<Option<Block>, Error> {
-        use cid::multihash::{self, MultihashDigest};
         // Read node -> cid, bytes
         if let Some((cid, data)) = read_node(&mut self.reader).await? {
+            let block = Block { cid, data };
             if self.validate {
-                match cid.hash().code() {
-                    0x0 => {
-                        if cid.hash().digest() != data {
-                            return Err(Error::InvalidFile(
-                                "CAR has an identity CID that doesn't match the corresponding data"
-                                    .into(),
-                            ));
-                        }
-                    }
-                    code => {
-                        let code = multihash::Code::try_from(code)?;
-                        let actual = Cid::new_v1(cid.codec(), code.digest(&data));
-                        if actual != cid {
-                            return Err(Error::InvalidFile(format!(
-                                "CAR has an incorrect CID: expected {}, found {}",
-                                cid, actual,
-                            )));
-                        }
-                    }
-                }
+                block.validate()?;
             }
-            Ok(Some(Block { cid, data }))
+            Ok(Some(block))
         } else {
             Ok(None)
         }
@@ -135,6 +118,33 @@ pub struct Block {
     pub data: Vec<u8>,
 }
 
+impl Block {
+    /// Checks that the block's CID matches its data.
+    pub fn validate(&self) -> Result<(), Error> {
+        use cid::multihash::{self, MultihashDigest};
+        match self.cid.hash().code() {
+            0x0 => {
+                if self.cid.hash().digest() != self.data {
+                    return Err(Error::InvalidFile(
+                        "CAR has an identity CID that doesn't match the corresponding data".into(),
+                    ));
+                }
+            }
+            code => {
+                let code = multihash::Code::try_from(code)?;
+                let actual = Cid::new_v1(self.cid.codec(), code.digest(&self.data));
+                if actual != self.cid {
+                    return Err(Error::InvalidFile(format!(
+                        "CAR has an incorrect CID: expected {}, found {}",
+                        self.cid, actual,
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Loads a CAR buffer into a Blockstore
 pub async fn load_car<R, B>(s: &B, reader: R) -> Result<Vec<Cid>, Error>
 where
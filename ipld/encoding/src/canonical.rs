@@ -0,0 +1,236 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::cmp::Ordering;
+
+use crate::{CodecProtocol, Error};
+
+/// The CBOR tag for CIDs.
+const CID_TAG: u64 = 42;
+
+/// Checks that `data` is a single DAG-CBOR object in the canonical form required by the DAG-CBOR
+/// spec, so that each value has exactly one valid encoding:
+///
+/// - Integers, lengths, and tags use the shortest possible encoding.
+/// - All strings, arrays, and maps have a definite length.
+/// - Map keys are unique strings, sorted by length and then bytewise.
+/// - Floats are 64-bit, and the only simple values are `true`, `false`, and `null`.
+/// - The only tag is the CID tag, applied to a byte string with a leading zero.
+/// - Text strings are valid UTF-8, and there's no data after the object.
+///
+/// Two different encodings of the same value hash to different CIDs, so rejecting non-canonical
+/// blocks prevents CID malleability.
+pub fn check_canonical(mut data: &[u8]) -> Result<(), Error> {
+    // Nested items are tracked with an explicit stack (rather than recursion) so that deeply
+    // nested objects can't overflow the stack.
+    let mut stack = vec![Container {
+        remaining: 1,
+        keys: None,
+    }];
+    while let Some(top) = stack.last_mut() {
+        if top.remaining == 0 {
+            stack.pop();
+            continue;
+        }
+        top.remaining -= 1;
+        if let Some(last_key) = &mut top.keys {
+            check_key(&mut data, last_key)?;
+        }
+
+        let (maj, extra) = read_header(&mut data)?;
+        let nested = match maj {
+            // Byte strings.
+            2 => {
+                take(&mut data, extra)?;
+                None
+            }
+            // Text strings.
+            3 => {
+                check_utf8(take(&mut data, extra)?)?;
+                None
+            }
+            4 => Some(Container {
+                remaining: extra,
+                keys: None,
+            }),
+            5 => Some(Container {
+                remaining: extra,
+                keys: Some(None),
+            }),
+            6 if extra == CID_TAG => {
+                let (maj, len) = read_header(&mut data)?;
+                if maj != 2 {
+                    return Err(non_canonical("CID must be a byte string"));
+                }
+                if take(&mut data, len)?.first() != Some(&0) {
+                    return Err(non_canonical("CID must have a 0 prefix"));
+                }
+                None
+            }
+            6 => return Err(non_canonical(&format!("unsupported CBOR tag {}", extra))),
+            // Integers, floats, and simple values. The header has already been checked.
+            _ => None,
+        };
+        if let Some(nested) = nested {
+            stack.push(nested);
+        }
+    }
+    if !data.is_empty() {
+        return Err(non_canonical("trailing data after CBOR object"));
+    }
+    Ok(())
+}
+
+/// An array or map being checked.
+struct Container<'a> {
+    /// The number of items (or map entries) left to check.
+    remaining: u64,
+    /// For maps, the previous key (if any).
+    keys: Option<Option<&'a [u8]>>,
+}
+
+/// Reads a map key, checking that it's a string that sorts after the previous key.
+fn check_key<'a>(data: &mut &'a [u8], last_key: &mut Option<&'a [u8]>) -> Result<(), Error> {
+    let (maj, len) = read_header(data)?;
+    if maj != 3 {
+        return Err(non_canonical("map keys must be strings"));
+    }
+    let key = take(data, len)?;
+    check_utf8(key)?;
+    if let Some(last) = *last_key {
+        match last.len().cmp(&key.len()).then_with(|| last.cmp(key)) {
+            Ordering::Less => {}
+            Ordering::Equal => return Err(non_canonical("duplicate map key")),
+            Ordering::Greater => return Err(non_canonical("map keys are not sorted")),
+        }
+    }
+    *last_key = Some(key);
+    Ok(())
+}
+
+fn check_utf8(s: &[u8]) -> Result<(), Error> {
+    std::str::from_utf8(s)
+        .map(|_| ())
+        .map_err(|_| non_canonical("invalid UTF-8 in text string"))
+}
+
+/// Reads a CBOR major type and its argument, checking that the argument is minimally encoded.
+fn read_header(data: &mut &[u8]) -> Result<(u8, u64), Error> {
+    let first = take(data, 1)?[0];
+    let (maj, info) = (first >> 5, first & 0x1f);
+    if maj == 7 {
+        return match info {
+            // false, true, null
+            20..=22 => Ok((maj, info as u64)),
+            27 => {
+                take(data, 8)?;
+                Ok((maj, 0))
+            }
+            25 | 26 => Err(non_canonical("floats must be 64-bit")),
+            31 => Err(non_canonical("indefinite length items are not allowed")),
+            _ => Err(non_canonical("unsupported CBOR simple value")),
+        };
+    }
+    let extra = match info {
+        n @ 0..=23 => return Ok((maj, n as u64)),
+        n @ 24..=27 => take(data, 1 << (n - 24))?
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as u64),
+        31 => return Err(non_canonical("indefinite length items are not allowed")),
+        _ => return Err(non_canonical("invalid CBOR header")),
+    };
+    let min = match info {
+        24 => 24,
+        25 => 1 << 8,
+        26 => 1 << 16,
+        _ => 1 << 32,
+    };
+    if extra < min {
+        return Err(non_canonical("integer or length is not minimally encoded"));
+    }
+    Ok((maj, extra))
+}
+
+fn take<'a>(data: &mut &'a [u8], len: u64) -> Result<&'a [u8], Error> {
+    if len > data.len() as u64 {
+        return Err(non_canonical("unexpected end of CBOR object"));
+    }
+    let (head, tail) = data.split_at(len as usize);
+    *data = tail;
+    Ok(head)
+}
+
+fn non_canonical(description: &str) -> Error {
+    Error {
+        description: description.into(),
+        protocol: CodecProtocol::Cbor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use serde_tuple::{Deserialize_tuple, Serialize_tuple};
+
+    use super::*;
+    use crate::{to_vec, BytesSer, DAG_CBOR};
+
+    #[derive(Serialize_tuple, Deserialize_tuple)]
+    struct Object {
+        number: i64,
+        big: u64,
+        float: f64,
+        flag: Option<bool>,
+        name: String,
+        list: Vec<i32>,
+        map: BTreeMap<String, Vec<u8>>,
+        link: Cid,
+    }
+
+    #[test]
+    fn canonical() {
+        let obj = Object {
+            number: -1000,
+            big: u64::MAX,
+            float: 1.5,
+            flag: None,
+            name: "foo".into(),
+            list: vec![1, -24, 25, 300],
+            map: [("b".into(), vec![1]), ("aa".into(), vec![2, 3])].into(),
+            link: Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"foo")),
+        };
+        check_canonical(&to_vec(&obj).unwrap()).unwrap();
+        check_canonical(&to_vec(&BytesSer(&[0; 300])).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn rejects() {
+        for (data, reason) in [
+            (&[0x18, 0x17][..], "non-minimal int"),
+            (&[0x19, 0x00, 0xff], "non-minimal int"),
+            (&[0x5f, 0x41, 0x00, 0xff], "indefinite length"),
+            (&[0x9f, 0xff], "indefinite length"),
+            (&[0xa2, 0x61, 0x62, 0x00, 0x61, 0x61, 0x00], "unsorted keys"),
+            (
+                &[0xa2, 0x62, 0x61, 0x61, 0x00, 0x61, 0x62, 0x00],
+                "unsorted keys",
+            ),
+            (
+                &[0xa2, 0x61, 0x61, 0x00, 0x61, 0x61, 0x00],
+                "duplicate keys",
+            ),
+            (&[0xa1, 0x01, 0x00], "integer key"),
+            (&[0xf9, 0x3c, 0x00], "16-bit float"),
+            (&[0xf7], "undefined"),
+            (&[0xc1, 0x00], "tag"),
+            (&[0x61, 0xff], "invalid utf-8"),
+            (&[0x00, 0x00], "trailing data"),
+            (&[0x82, 0x00], "truncated"),
+        ] {
+            assert!(check_canonical(data).is_err(), "accepted {}", reason);
+        }
+    }
+}
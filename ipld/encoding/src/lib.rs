@@ -3,10 +3,12 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 mod bytes;
+mod canonical;
 mod cbor;
 mod cbor_store;
 mod errors;
 pub mod ipld_block;
+mod links;
 mod raw;
 mod vec;
 use std::io;
@@ -14,9 +16,11 @@ use std::io;
 pub use serde::{self, de, ser};
 
 pub use self::bytes::*;
+pub use self::canonical::check_canonical;
 pub use self::cbor::*;
 pub use self::cbor_store::CborStore;
 pub use self::errors::*;
+pub use self::links::scan_for_links;
 pub use self::vec::*;
 
 pub const DAG_CBOR: u64 = 0x71;
@@ -0,0 +1,117 @@
+// Copyright 2021-2023 Protocol Labs
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::io::{Read, Seek};
+
+use anyhow::{anyhow, Result};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use cid::Cid;
+
+/// Given a CBOR encoded Buffer, returns a tuple of:
+/// the type of the CBOR object along with extra
+/// elements we expect to read. More info on this can be found in
+/// Appendix C. of RFC 7049 which defines the CBOR specification.
+/// This was implemented because the CBOR library we use does not expose low
+/// methods like this, requiring us to deserialize the whole CBOR payload, which
+/// is unnecessary and quite inefficient for our usecase here.
+fn cbor_read_header_buf<B: Read>(br: &mut B, scratch: &mut [u8]) -> anyhow::Result<(u8, usize)> {
+    let first = br.read_u8()?;
+    let maj = (first & 0xe0) >> 5;
+    let low = first & 0x1f;
+
+    if low < 24 {
+        Ok((maj, low as usize))
+    } else if low == 24 {
+        let val = br.read_u8()?;
+        if val < 24 {
+            return Err(anyhow!(
+                "cbor input was not canonical (lval 24 with value < 24)"
+            ));
+        }
+        Ok((maj, val as usize))
+    } else if low == 25 {
+        br.read_exact(&mut scratch[..2])?;
+        let val = BigEndian::read_u16(&scratch[..2]);
+        if val <= u8::MAX as u16 {
+            return Err(anyhow!(
+                "cbor input was not canonical (lval 25 with value <= MaxUint8)"
+            ));
+        }
+        Ok((maj, val as usize))
+    } else if low == 26 {
+        br.read_exact(&mut scratch[..4])?;
+        let val = BigEndian::read_u32(&scratch[..4]);
+        if val <= u16::MAX as u32 {
+            return Err(anyhow!(
+                "cbor input was not canonical (lval 26 with value <= MaxUint16)"
+            ));
+        }
+        Ok((maj, val as usize))
+    } else if low == 27 {
+        br.read_exact(&mut scratch[..8])?;
+        let val = BigEndian::read_u64(&scratch[..8]);
+        if val <= u32::MAX as u64 {
+            return Err(anyhow!(
+                "cbor input was not canonical (lval 27 with value <= MaxUint32)"
+            ));
+        }
+        Ok((maj, val as usize))
+    } else {
+        Err(anyhow!("invalid header cbor_read_header_buf"))
+    }
+}
+
+/// Given a CBOR serialized IPLD buffer, read through all of it and return all the Links.
+/// This function is useful because it is quite a bit more fast than doing this recursively on a
+/// deserialized IPLD object.
+pub fn scan_for_links<B: Read + Seek, F>(buf: &mut B, mut callback: F) -> Result<()>
+where
+    F: FnMut(Cid) -> anyhow::Result<()>,
+{
+    let mut scratch: [u8; 100] = [0; 100];
+    let mut remaining = 1;
+    while remaining > 0 {
+        let (maj, extra) = cbor_read_header_buf(buf, &mut scratch)?;
+        match maj {
+            // MajUnsignedInt, MajNegativeInt, MajOther
+            0 | 1 | 7 => {}
+            // MajByteString, MajTextString
+            2 | 3 => {
+                buf.seek(std::io::SeekFrom::Current(extra as i64))?;
+            }
+            // MajTag
+            6 => {
+                // Check if the tag refers to a CID
+                if extra == 42 {
+                    let (maj, extra) = cbor_read_header_buf(buf, &mut scratch)?;
+                    // The actual CID is expected to be a byte string
+                    if maj != 2 {
+                        return Err(anyhow!("expected cbor type byte string in input"));
+                    }
+                    if extra > 100 {
+                        return Err(anyhow!("string in cbor input too long"));
+                    }
+                    buf.read_exact(&mut scratch[..extra])?;
+                    let c = Cid::try_from(&scratch[1..extra])?;
+                    callback(c)?;
+                } else {
+                    remaining += 1;
+                }
+            }
+            // MajArray
+            4 => {
+                remaining += extra;
+            }
+            // MajMap
+            5 => {
+                remaining += extra * 2;
+            }
+            _ => {
+                return Err(anyhow!("unhandled cbor type: {}", maj));
+            }
+        }
+        remaining -= 1;
+    }
+    Ok(())
+}
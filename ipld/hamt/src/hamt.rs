@@ -4,17 +4,21 @@
 
 use std::borrow::Borrow;
 use std::marker::PhantomData;
+use std::ops::ControlFlow;
 
 use cid::Cid;
 use forest_hash_utils::BytesKey;
-use fvm_ipld_blockstore::Blockstore;
-use fvm_ipld_encoding::CborStore;
+use fvm_ipld_blockstore::cache::{CacheTracker, CountingBlockstore};
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::{to_vec, CborStore, DAG_CBOR};
 use multihash::Code;
 use serde::de::DeserializeOwned;
 use serde::{Serialize, Serializer};
 
+use crate::hash_bits::HashBits;
+use crate::invariants::{check_node, Violation};
 use crate::node::Node;
-use crate::{Config, Error, Hash, HashAlgorithm, Sha256};
+use crate::{CacheConfig, Config, Error, FlushPolicy, Hash, HashAlgorithm, Sha256};
 
 /// Implementation of the HAMT data structure for IPLD.
 ///
@@ -40,6 +44,8 @@ pub struct Hamt<BS, V, K = BytesKey, H = Sha256> {
     hash: PhantomData<H>,
     /// Remember the last flushed CID until it changes.
     flushed_cid: Option<Cid>,
+    cache_config: CacheConfig,
+    cache: CacheTracker,
 }
 
 impl<BS, V, K, H> Serialize for Hamt<BS, V, K, H>
@@ -80,6 +86,8 @@ where
             conf,
             hash: Default::default(),
             flushed_cid: None,
+            cache_config: Default::default(),
+            cache: Default::default(),
         }
     }
 
@@ -108,6 +116,8 @@ where
                 conf,
                 hash: Default::default(),
                 flushed_cid: Some(*cid),
+                cache_config: Default::default(),
+                cache: Default::default(),
             }),
             None => Err(Error::CidNotFound(cid.to_string())),
         }
@@ -130,6 +140,7 @@ where
             Some(root) => {
                 self.root = root;
                 self.flushed_cid = Some(*cid);
+                self.cache = Default::default();
             }
             None => return Err(Error::CidNotFound(cid.to_string())),
         }
@@ -142,6 +153,38 @@ where
         &self.store
     }
 
+    /// Sets the budget for the HAMT's node cache. See [`CacheConfig`].
+    pub fn set_cache_config(&mut self, conf: CacheConfig) {
+        self.cache_config = conf;
+    }
+
+    /// Returns the budget for the HAMT's node cache.
+    pub fn cache_config(&self) -> &CacheConfig {
+        &self.cache_config
+    }
+
+    /// Evicts the least recently used cached nodes down to half of the cache budget, after flushing
+    /// the HAMT if the [`FlushPolicy`] says so.
+    ///
+    /// This is called automatically by mutating methods when the cache exceeds its budget.
+    pub fn trim_cache(&mut self) -> Result<(), Error> {
+        if self.cache_config.flush_policy == FlushPolicy::OnEviction {
+            self.flush()?;
+        }
+        self.cache.trim(&self.cache_config, &mut self.root);
+        Ok(())
+    }
+
+    /// Records a modification, then trims the cache if it exceeds its budget.
+    fn modified(&mut self) -> Result<(), Error> {
+        self.flushed_cid = None;
+        self.cache.add(1);
+        if self.cache.needs_trim(&self.cache_config) {
+            self.trim_cache()?;
+        }
+        Ok(())
+    }
+
     /// Inserts a key-value pair into the HAMT.
     ///
     /// If the HAMT did not have this key present, `None` is returned.
@@ -168,12 +211,16 @@ where
     where
         V: PartialEq,
     {
-        let (old, modified) = self
-            .root
-            .set(key, value, self.store.borrow(), &self.conf, true)?;
+        let (old, modified) = self.root.set(
+            key,
+            value,
+            &CountingBlockstore::new(&self.store, &self.cache),
+            &self.conf,
+            true,
+        )?;
 
         if modified {
-            self.flushed_cid = None;
+            self.modified()?;
         }
 
         Ok(old)
@@ -211,11 +258,17 @@ where
     {
         let set = self
             .root
-            .set(key, value, self.store.borrow(), &self.conf, false)
+            .set(
+                key,
+                value,
+                &CountingBlockstore::new(&self.store, &self.cache),
+                &self.conf,
+                false,
+            )
             .map(|(_, set)| set)?;
 
         if set {
-            self.flushed_cid = None;
+            self.modified()?;
         }
 
         Ok(set)
@@ -247,7 +300,11 @@ where
         Q: Hash + Eq,
         V: DeserializeOwned,
     {
-        match self.root.get(k, self.store.borrow(), &self.conf)? {
+        match self.root.get(
+            k,
+            &CountingBlockstore::new(&self.store, &self.cache),
+            &self.conf,
+        )? {
             Some(v) => Ok(Some(v)),
             None => Ok(None),
         }
@@ -278,7 +335,14 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq,
     {
-        Ok(self.root.get(k, self.store.borrow(), &self.conf)?.is_some())
+        Ok(self
+            .root
+            .get(
+                k,
+                &CountingBlockstore::new(&self.store, &self.cache),
+                &self.conf,
+            )?
+            .is_some())
     }
 
     /// Removes a key from the HAMT, returning the value at the key if the key
@@ -306,10 +370,14 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq,
     {
-        let deleted = self.root.remove_entry(k, self.store.borrow(), &self.conf)?;
+        let deleted = self.root.remove_entry(
+            k,
+            &CountingBlockstore::new(&self.store, &self.cache),
+            &self.conf,
+        )?;
 
         if deleted.is_some() {
-            self.flushed_cid = None;
+            self.modified()?;
         }
 
         Ok(deleted)
@@ -326,6 +394,44 @@ where
         Ok(cid)
     }
 
+    /// Like [`Hamt::flush`], but collects all dirty nodes (and the root) and writes them to the
+    /// blockstore with a single [`Blockstore::put_many_keyed`] call, instead of one put per node.
+    ///
+    /// This is significantly cheaper on blockstores with a high per-write overhead, or ones that
+    /// implement an efficient bulk insert, when many nodes have been modified.
+    ///
+    /// ```
+    /// use fvm_ipld_hamt::Hamt;
+    ///
+    /// let store = fvm_ipld_blockstore::MemoryBlockstore::default();
+    ///
+    /// let mut map: Hamt<_, _, usize> = Hamt::new(&store);
+    /// for i in 0..100usize {
+    ///     map.set(i, i.to_string()).unwrap();
+    /// }
+    /// let cid = map.flush_batched().unwrap();
+    ///
+    /// let map: Hamt<_, String, usize> = Hamt::load(&cid, &store).unwrap();
+    /// assert_eq!(map.get(&42).unwrap(), Some(&"42".to_string()));
+    /// ```
+    pub fn flush_batched(&mut self) -> Result<Cid, Error> {
+        if let Some(cid) = self.flushed_cid {
+            return Ok(cid);
+        }
+        let mut blocks = Vec::new();
+        let mut put = |node: &Node<K, V, H>| -> Result<Cid, Error> {
+            let data = to_vec(node)?;
+            let cid = Block::new(DAG_CBOR, &data).cid(Code::Blake2b256);
+            blocks.push((cid, data));
+            Ok(cid)
+        };
+        self.root.flush_with(&mut put)?;
+        let cid = put(&self.root)?;
+        self.store.put_many_keyed(blocks)?;
+        self.flushed_cid = Some(cid);
+        Ok(cid)
+    }
+
     /// Returns true if the HAMT has no entries
     pub fn is_empty(&self) -> bool {
         self.root.is_empty()
@@ -333,7 +439,12 @@ where
 
     /// Iterates over each KV in the Hamt and runs a function on the values.
     ///
-    /// This function will constrain all values to be of the same type
+    /// This function will constrain all values to be of the same type.
+    ///
+    /// Iteration order is deterministic: key-value pairs are visited in the order of their key
+    /// hashes, and in ascending key order within a bucket. HAMTs with the same contents (and
+    /// configuration) are therefore always iterated in the same order, provided they're canonical
+    /// (see [`Hamt::check_invariants`]).
     ///
     /// # Examples
     ///
@@ -359,7 +470,124 @@ where
         V: DeserializeOwned,
         F: FnMut(&K, &V) -> anyhow::Result<()>,
     {
-        self.root.for_each(self.store.borrow(), &mut f)
+        self.root
+            .for_each(&CountingBlockstore::new(&self.store, &self.cache), &mut f)
+    }
+
+    /// Iterates over the KVs in the Hamt in the same order as [`Hamt::for_each`], starting at
+    /// `starting_key` (inclusive) and visiting at most `max` KVs.
+    ///
+    /// Returns the number of KVs visited and, if the iteration stopped early, the key to resume
+    /// from. This allows iterating over a large HAMT in batches. Fails if `starting_key` isn't in
+    /// the Hamt.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fvm_ipld_hamt::Hamt;
+    ///
+    /// let store = fvm_ipld_blockstore::MemoryBlockstore::default();
+    ///
+    /// let mut map: Hamt<_, _, usize> = Hamt::new(store);
+    /// for i in 0..10 {
+    ///     map.set(i, i as u64).unwrap();
+    /// }
+    ///
+    /// let mut total = 0;
+    /// let mut next = None;
+    /// loop {
+    ///     let (_, resume) = map
+    ///         .for_each_ranged(next.as_ref(), Some(3), |_, v: &u64| {
+    ///             total += v;
+    ///             Ok(())
+    ///         })
+    ///         .unwrap();
+    ///     next = resume;
+    ///     if next.is_none() {
+    ///         break;
+    ///     }
+    /// }
+    /// assert_eq!(total, 45);
+    /// ```
+    pub fn for_each_ranged<Q: ?Sized, F>(
+        &self,
+        starting_key: Option<&Q>,
+        max: Option<usize>,
+        mut f: F,
+    ) -> Result<(usize, Option<K>), Error>
+    where
+        K: Borrow<Q> + Clone,
+        Q: Eq + Hash,
+        V: DeserializeOwned,
+        F: FnMut(&K, &V) -> anyhow::Result<()>,
+    {
+        let hash = starting_key.map(|k| H::hash(k));
+        let start = hash
+            .as_ref()
+            .zip(starting_key)
+            .map(|(hash, key)| (HashBits::new(hash), key));
+
+        let mut visited = 0;
+        let mut next = None;
+        let _ = self.root.for_each_from(
+            &CountingBlockstore::new(&self.store, &self.cache),
+            &self.conf,
+            start,
+            &mut |k, v| {
+                if max == Some(visited) {
+                    next = Some(k.clone());
+                    return Ok(ControlFlow::Break(()));
+                }
+                visited += 1;
+                f(k, v).map(ControlFlow::Continue)
+            },
+        )?;
+        Ok((visited, next))
+    }
+
+    /// Checks the structural invariants of the HAMT, loading every node from the store, and
+    /// returns all violations found. An empty result means the HAMT is in canonical form: the
+    /// same HAMT would have been produced by inserting its key-value pairs into an empty HAMT,
+    /// so it will have the same root CID as any other canonical HAMT with the same contents.
+    ///
+    /// The invariants checked are:
+    ///
+    /// - Each node's bitfield has exactly one bit set per pointer, within the bit width.
+    /// - No node other than the root is empty.
+    /// - Buckets are non-empty, hold at most `max_array_width` key-value pairs, sorted by key,
+    ///   and hold only keys whose hashes select the bucket.
+    /// - No buckets are stored above `min_data_depth`.
+    /// - No child node could be collapsed into a bucket of its parent.
+    ///
+    /// Violations are returned as values, while errors (e.g., a missing node) are returned as
+    /// [`Error`]s.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use fvm_ipld_hamt::Hamt;
+    ///
+    /// let store = fvm_ipld_blockstore::MemoryBlockstore::default();
+    ///
+    /// let mut map: Hamt<_, _, usize> = Hamt::new(store);
+    /// for i in 0..100 {
+    ///     map.set(i, i).unwrap();
+    /// }
+    /// assert!(map.check_invariants().unwrap().is_empty());
+    /// ```
+    pub fn check_invariants(&self) -> Result<Vec<Violation>, Error> {
+        if self.conf.bit_width == 0 || self.conf.bit_width > 8 {
+            return Err(Error::InvalidHashBitLen);
+        }
+        let mut violations = Vec::new();
+        check_node(
+            &self.root,
+            &CountingBlockstore::new(&self.store, &self.cache),
+            &self.conf,
+            &mut Vec::new(),
+            &mut violations,
+        )?;
+        Ok(violations)
     }
 
     /// Consumes this HAMT and returns the Blockstore it owns.
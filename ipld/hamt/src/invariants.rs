@@ -0,0 +1,190 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Structural invariant checks for HAMTs.
+//!
+//! A HAMT built exclusively through [`Hamt`](crate::Hamt) operations is always in canonical form:
+//! the same set of key-value pairs produces the same tree (and therefore the same root CID)
+//! regardless of the order of the operations that built it. HAMTs produced by other
+//! implementations (or by hand) can be structurally valid yet non-canonical, and will then
+//! produce different CIDs once modified. See [`Hamt::check_invariants`](crate::Hamt::check_invariants).
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::hash_bits::HashBits;
+use crate::node::Node;
+use crate::pointer::Pointer;
+use crate::{Config, Error, Hash, HashAlgorithm};
+
+/// A violation of the HAMT's structural invariants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The bucket indices leading from the root to the offending node or bucket.
+    pub path: Vec<u32>,
+    /// The kind of violation.
+    pub kind: ViolationKind,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {:?}: {}", self.path, self.kind)
+    }
+}
+
+/// The kinds of structural invariant violations.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// A non-root node has no pointers.
+    #[error("node has no pointers")]
+    EmptyNode,
+    /// The node's bitfield doesn't match its pointers, or sets bits beyond the bit width.
+    #[error("bitfield sets {bits} bits (highest {highest}) for {pointers} pointers")]
+    BitfieldMismatch {
+        bits: usize,
+        highest: u32,
+        pointers: usize,
+    },
+    /// A bucket has no key-value pairs.
+    #[error("bucket is empty")]
+    EmptyBucket,
+    /// A bucket holds more than `max_array_width` key-value pairs.
+    #[error("bucket holds {0} key-value pairs")]
+    OversizedBucket(usize),
+    /// A bucket's keys aren't in strictly ascending order.
+    #[error("bucket keys are not sorted")]
+    UnsortedBucket,
+    /// A bucket holds a key whose hash doesn't select the bucket.
+    #[error("bucket holds a key that hashes to a different bucket")]
+    MisplacedKey,
+    /// A bucket is stored above `min_data_depth`.
+    #[error("bucket stored above the minimum data depth")]
+    DataAboveMinDepth,
+    /// A child node holds few enough key-value pairs that it should have been collapsed into
+    /// a bucket of its parent.
+    #[error("node should have been collapsed into its parent")]
+    CollapsibleNode,
+    /// A link is deeper than the key hash allows.
+    #[error("link exceeds the maximum depth")]
+    MaxDepth,
+}
+
+/// Checks the node at `path`, and everything below it, for structural invariant violations.
+pub(crate) fn check_node<K, V, H, S>(
+    node: &Node<K, V, H>,
+    store: &S,
+    conf: &Config,
+    path: &mut Vec<u32>,
+    violations: &mut Vec<Violation>,
+) -> Result<(), Error>
+where
+    K: Hash + PartialOrd + DeserializeOwned,
+    V: DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    let depth = path.len() as u32;
+    if depth > 0 && node.pointers.is_empty() {
+        report(violations, path, ViolationKind::EmptyNode);
+    }
+
+    let indices: Vec<u32> = (0..256).filter(|&i| node.bitfield.test_bit(i)).collect();
+    let highest = indices.last().copied().unwrap_or_default();
+    if indices.len() != node.pointers.len() || highest >= 1 << conf.bit_width {
+        report(
+            violations,
+            path,
+            ViolationKind::BitfieldMismatch {
+                bits: indices.len(),
+                highest,
+                pointers: node.pointers.len(),
+            },
+        );
+    }
+
+    for (&idx, pointer) in indices.iter().zip(&node.pointers) {
+        path.push(idx);
+        match pointer {
+            Pointer::Values(kvs) => {
+                if depth < conf.min_data_depth {
+                    report(violations, path, ViolationKind::DataAboveMinDepth);
+                }
+                if kvs.is_empty() {
+                    report(violations, path, ViolationKind::EmptyBucket);
+                }
+                if kvs.len() > conf.max_array_width {
+                    report(violations, path, ViolationKind::OversizedBucket(kvs.len()));
+                }
+                if kvs
+                    .windows(2)
+                    .any(|w| w[0].key().partial_cmp(w[1].key()) != Some(Ordering::Less))
+                {
+                    report(violations, path, ViolationKind::UnsortedBucket);
+                }
+                if !kvs.iter().all(|kv| hashes_to::<H, _>(kv.key(), path, conf)) {
+                    report(violations, path, ViolationKind::MisplacedKey);
+                }
+            }
+            Pointer::Link { .. } | Pointer::Dirty(_) if (depth + 1) * conf.bit_width >= 256 => {
+                report(violations, path, ViolationKind::MaxDepth);
+            }
+            Pointer::Link { cid, cache } => {
+                let child = match cache.get() {
+                    Some(child) => child,
+                    None => {
+                        let child = store
+                            .get_cbor(cid)?
+                            .ok_or_else(|| Error::CidNotFound(cid.to_string()))?;
+                        // Ignore error intentionally, the cache value will always be the same
+                        cache.get_or_init(|| child)
+                    }
+                };
+                if depth >= conf.min_data_depth && is_collapsible(child, conf) {
+                    report(violations, path, ViolationKind::CollapsibleNode);
+                }
+                check_node(child, store, conf, path, violations)?;
+            }
+            Pointer::Dirty(child) => {
+                if depth >= conf.min_data_depth && is_collapsible(child, conf) {
+                    report(violations, path, ViolationKind::CollapsibleNode);
+                }
+                check_node(child, store, conf, path, violations)?;
+            }
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn report(violations: &mut Vec<Violation>, path: &[u32], kind: ViolationKind) {
+    violations.push(Violation {
+        path: path.to_vec(),
+        kind,
+    })
+}
+
+/// Returns true if the key's hash selects the bucket at `path`.
+fn hashes_to<H: HashAlgorithm, K: Hash>(key: &K, path: &[u32], conf: &Config) -> bool {
+    let hash = H::hash(key);
+    let mut bits = HashBits::new(&hash);
+    path.iter()
+        .all(|&idx| bits.next(conf.bit_width).map_or(false, |i| i == idx))
+}
+
+/// Returns true if the node only holds buckets, with no more than `max_array_width` key-value
+/// pairs in total. Such nodes are replaced by a single bucket on delete.
+fn is_collapsible<K, V, H>(node: &Node<K, V, H>, conf: &Config) -> bool {
+    let mut len = 0;
+    for p in &node.pointers {
+        match p {
+            Pointer::Values(kvs) => len += kvs.len(),
+            _ => return false,
+        }
+    }
+    len > 0 && len <= conf.max_array_width
+}
@@ -16,16 +16,19 @@ mod hamt;
 mod hash;
 mod hash_algorithm;
 mod hash_bits;
+mod invariants;
 mod node;
 mod pointer;
 
 pub use forest_hash_utils::{BytesKey, Hash};
+pub use fvm_ipld_blockstore::cache::{CacheConfig, FlushPolicy};
 use serde::{Deserialize, Serialize};
 
 pub use self::error::Error;
 pub use self::hamt::Hamt;
 pub use self::hash::*;
 pub use self::hash_algorithm::*;
+pub use self::invariants::{Violation, ViolationKind};
 
 /// Default bit width for indexing a hash at each depth level
 const DEFAULT_BIT_WIDTH: u32 = 8;
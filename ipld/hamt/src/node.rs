@@ -5,11 +5,13 @@
 use std::borrow::Borrow;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::ops::ControlFlow;
 
+use cid::Cid;
+use fvm_ipld_blockstore::cache::{CachedNode, Child, NodeCache};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::CborStore;
 use multihash::Code;
-use once_cell::unsync::OnceCell;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
@@ -74,6 +76,18 @@ impl<K, V, H> Default for Node<K, V, H> {
     }
 }
 
+impl<K, V, H> CachedNode for Node<K, V, H> {
+    fn for_each_child(&mut self, f: &mut dyn FnMut(Child<'_, Self>)) {
+        for pointer in &mut self.pointers {
+            match pointer {
+                Pointer::Link { cache, .. } => f(Child::Cached(cache)),
+                Pointer::Dirty(node) => f(Child::Dirty(node)),
+                Pointer::Values(_) => {}
+            }
+        }
+    }
+}
+
 impl<K, V, H> Node<K, V, H>
 where
     K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
@@ -174,6 +188,79 @@ where
         Ok(())
     }
 
+    /// Calls `f` on the key-value pairs under this node in iteration order until it breaks,
+    /// starting at the given key (inclusive) if any. The key's hash bits must be positioned at
+    /// this node's depth. Fails if the starting key isn't in the HAMT.
+    pub(crate) fn for_each_from<Q: ?Sized, S, F>(
+        &self,
+        store: &S,
+        conf: &Config,
+        start: Option<(HashBits, &Q)>,
+        f: &mut F,
+    ) -> Result<ControlFlow<()>, Error>
+    where
+        K: Borrow<Q>,
+        Q: Eq,
+        F: FnMut(&K, &V) -> anyhow::Result<ControlFlow<()>>,
+        S: Blockstore,
+    {
+        // Skip the pointers before the starting key, then descend into the pointer holding it.
+        let (first, mut start) = match start {
+            Some((mut hashed_key, key)) => {
+                let idx = hashed_key.next(conf.bit_width)?;
+                if !self.bitfield.test_bit(idx) {
+                    return Err("starting key not found".into());
+                }
+                (self.index_for_bit_pos(idx), Some((hashed_key, key)))
+            }
+            None => (0, None),
+        };
+
+        for p in &self.pointers[first..] {
+            let start = start.take();
+            let node = match p {
+                Pointer::Link { cid, cache } => {
+                    if let Some(cached_node) = cache.get() {
+                        cached_node
+                    } else {
+                        let node = if let Some(node) = store.get_cbor(cid)? {
+                            node
+                        } else {
+                            #[cfg(not(feature = "ignore-dead-links"))]
+                            return Err(Error::CidNotFound(cid.to_string()));
+
+                            #[cfg(feature = "ignore-dead-links")]
+                            continue;
+                        };
+
+                        // Ignore error intentionally, the cache value will always be the same
+                        cache.get_or_init(|| node)
+                    }
+                }
+                Pointer::Dirty(node) => node,
+                Pointer::Values(kvs) => {
+                    let skip = match start {
+                        Some((_, key)) => kvs
+                            .iter()
+                            .position(|kv| kv.key().borrow() == key)
+                            .ok_or("starting key not found")?,
+                        None => 0,
+                    };
+                    for kv in &kvs[skip..] {
+                        if f(kv.key(), kv.value())?.is_break() {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                    }
+                    continue;
+                }
+            };
+            if node.for_each_from(store, conf, start, f)?.is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
     /// Search for a key.
     fn search<Q: ?Sized, S: Blockstore>(
         &self,
@@ -446,16 +533,25 @@ where
     }
 
     pub fn flush<S: Blockstore>(&mut self, store: &S) -> Result<(), Error> {
+        self.flush_with(&mut |node| Ok(store.put_cbor(node, Code::Blake2b256)?))
+    }
+
+    /// Flushes all dirty sub nodes, children before their parents, handing each one to `put` and
+    /// replacing it with a link to the returned Cid.
+    pub(crate) fn flush_with<F>(&mut self, put: &mut F) -> Result<(), Error>
+    where
+        F: FnMut(&Self) -> Result<Cid, Error>,
+    {
         for pointer in &mut self.pointers {
             if let Pointer::Dirty(node) = pointer {
                 // Flush cached sub node to clear it's cache
-                node.flush(store)?;
+                node.flush_with(put)?;
 
-                // Put node in blockstore and retrieve Cid
-                let cid = store.put_cbor(node, Code::Blake2b256)?;
+                // Store the node and retrieve Cid
+                let cid = put(node)?;
 
                 // Can keep the flushed node in link cache
-                let cache = OnceCell::from(std::mem::take(node));
+                let cache = NodeCache::from(std::mem::take(node));
 
                 // Replace cached node with Cid link
                 *pointer = Pointer::Link { cid, cache };
@@ -6,8 +6,8 @@ use std::cmp::Ordering;
 use std::convert::{TryFrom, TryInto};
 
 use cid::Cid;
+use fvm_ipld_blockstore::cache::NodeCache;
 use libipld_core::ipld::Ipld;
-use once_cell::unsync::OnceCell;
 use serde::de::{self, DeserializeOwned};
 use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
 
@@ -21,7 +21,7 @@ pub(crate) enum Pointer<K, V, H> {
     Values(Vec<KeyValuePair<K, V>>),
     Link {
         cid: Cid,
-        cache: OnceCell<Box<Node<K, V, H>>>,
+        cache: NodeCache<Box<Node<K, V, H>>>,
     },
     Dirty(Box<Node<K, V, H>>),
 }
@@ -13,7 +13,10 @@ use fvm_ipld_encoding::strict_bytes::ByteBuf;
 use fvm_ipld_encoding::CborStore;
 #[cfg(feature = "identity")]
 use fvm_ipld_hamt::Identity;
-use fvm_ipld_hamt::{BytesKey, Config, Error, Hamt, Hash};
+use fvm_ipld_hamt::{
+    BytesKey, CacheConfig, Config, Error, FlushPolicy, Hamt, Hash, HashAlgorithm, Sha256,
+    Violation, ViolationKind,
+};
 use multihash::Code;
 use quickcheck::Arbitrary;
 use rand::seq::SliceRandom;
@@ -601,6 +604,57 @@ fn max_array_width_reduces_root_size() {
     test_reduced_root_size(factory1, factory2);
 }
 
+#[test]
+fn for_each_ranged() {
+    let store = MemoryBlockstore::default();
+    let mut hamt: Hamt<_, BytesKey> = Hamt::new_with_bit_width(&store, 5);
+    for i in 0..200 {
+        hamt.set(tstring(i), tstring(i)).unwrap();
+    }
+
+    let mut all = Vec::new();
+    hamt.for_each(|k, _| {
+        all.push(k.clone());
+        Ok(())
+    })
+    .unwrap();
+
+    // Iterate in batches, both before and after flushing, and make sure the concatenated batches
+    // match a full iteration.
+    for flush in [false, true] {
+        if flush {
+            let c = hamt.flush().unwrap();
+            hamt = Hamt::load_with_bit_width(&c, &store, 5).unwrap();
+        }
+        let mut batched = Vec::new();
+        let mut next = None;
+        loop {
+            let (count, resume) = hamt
+                .for_each_ranged(next.as_ref(), Some(7), |k, _| {
+                    batched.push(k.clone());
+                    Ok(())
+                })
+                .unwrap();
+            assert!(count <= 7);
+            next = resume;
+            if next.is_none() {
+                break;
+            }
+        }
+        assert_eq!(batched, all);
+    }
+
+    // Unbounded iteration from the middle visits the rest.
+    let (count, next) = hamt
+        .for_each_ranged(Some(&all[150]), None, |_, _| Ok(()))
+        .unwrap();
+    assert_eq!((count, next), (50, None));
+
+    // Starting keys must exist.
+    hamt.for_each_ranged(Some(&tstring(1000)), None, |_, _| Ok(()))
+        .unwrap_err();
+}
+
 /// List of key value pairs with unique keys.
 ///
 /// Uniqueness is used so insert order doesn't cause overwrites.
@@ -720,10 +774,195 @@ fn prop_cid_ops_reduced<const N: u32>(factory: HamtFactory, ops: LimitedKeyOps<N
             hamt
         });
 
+    let canonical = hamt1.check_invariants().unwrap().is_empty();
+
     let cid1 = hamt1.flush().unwrap();
     let cid2 = hamt2.flush().unwrap();
 
-    cid1 == cid2
+    canonical && cid1 == cid2
+}
+
+#[test]
+fn cache_budget() {
+    let mem = MemoryBlockstore::default();
+    let mut hamt: Hamt<_, BytesKey> = Hamt::new_with_bit_width(&mem, 5);
+    for i in 0..1000 {
+        hamt.set(tstring(i), tstring(i)).unwrap();
+    }
+    let expected = hamt.flush().unwrap();
+
+    // Modify the HAMT, and reload all of its nodes into the cache.
+    let modify = |conf: CacheConfig| {
+        let store = TrackingBlockstore::new(&mem);
+        let mut hamt: Hamt<_, BytesKey> = Hamt::load_with_bit_width(&expected, &store, 5).unwrap();
+        hamt.set_cache_config(conf);
+        hamt.for_each(|_, _| Ok(())).unwrap();
+        hamt.delete(&tstring(0)).unwrap();
+        let reads = store.stats.borrow().r;
+        hamt.for_each(|_, _| Ok(())).unwrap();
+        let cached = reads == store.stats.borrow().r;
+        hamt.set(tstring(0), tstring(0)).unwrap();
+        (cached, hamt.flush().unwrap())
+    };
+
+    // Without a budget, every node stays cached.
+    assert_eq!(modify(CacheConfig::default()), (true, expected));
+
+    // With a budget, nodes are evicted and reloaded without changing the result.
+    let bounded = CacheConfig {
+        max_nodes: Some(8),
+        flush_policy: FlushPolicy::Manual,
+    };
+    assert_eq!(modify(bounded), (false, expected));
+
+    // Flushing on eviction allows the HAMT to be built in bounded memory.
+    let mut hamt: Hamt<_, BytesKey> = Hamt::new_with_bit_width(&mem, 5);
+    hamt.set_cache_config(CacheConfig {
+        max_nodes: Some(8),
+        flush_policy: FlushPolicy::OnEviction,
+    });
+    for i in 0..1000 {
+        hamt.set(tstring(i), tstring(i)).unwrap();
+    }
+    assert_eq!(hamt.flush().unwrap(), expected);
+    assert!(hamt.check_invariants().unwrap().is_empty());
+}
+
+#[test]
+fn flush_batched() {
+    let build = |store| {
+        let mut hamt: Hamt<_, BytesKey> = Hamt::new_with_bit_width(store, 5);
+        for i in 0..1000 {
+            hamt.set(tstring(i), tstring(i)).unwrap();
+        }
+        hamt
+    };
+
+    let mem = MemoryBlockstore::default();
+    let store = TrackingBlockstore::new(&mem);
+    let expected = build(&store).flush().unwrap();
+    let expected_stats = *store.stats.borrow();
+
+    // The same blocks are written, just in one batch.
+    let mem = MemoryBlockstore::default();
+    let store = TrackingBlockstore::new(&mem);
+    let mut hamt = build(&store);
+    assert_eq!(hamt.flush_batched().unwrap(), expected);
+    assert_eq!(*store.stats.borrow(), expected_stats);
+
+    // Only the modified nodes are written by subsequent flushes.
+    hamt.delete(&tstring(0)).unwrap();
+    let cid = hamt.flush_batched().unwrap();
+    assert!(store.stats.borrow().w - expected_stats.w < 5);
+
+    let mut hamt: Hamt<_, BytesKey> = Hamt::load_with_bit_width(&cid, &mem, 5).unwrap();
+    assert_eq!(hamt.get(&tstring(0)).unwrap(), None);
+    hamt.set(tstring(0), tstring(0)).unwrap();
+    assert_eq!(hamt.flush_batched().unwrap(), expected);
+}
+
+/// A pointer in a hand-built HAMT node.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum RawPointer {
+    Values(Vec<(u8, u8)>),
+    Link(Cid),
+}
+
+#[test]
+fn check_invariants() {
+    use RawPointer::*;
+    use ViolationKind::*;
+
+    let store = MemoryBlockstore::default();
+    let conf = Config {
+        bit_width: 1,
+        ..Default::default()
+    };
+    let put = |bits: u8, pointers: Vec<RawPointer>| {
+        let bitfield = ByteBuf(if bits == 0 { vec![] } else { vec![bits] });
+        store
+            .put_cbor(&(bitfield, pointers), Code::Blake2b256)
+            .unwrap()
+    };
+    let check = |root: Cid, conf: &Config| {
+        let hamt: Hamt<_, u8, u8> = Hamt::load_with_config(&root, &store, conf.clone()).unwrap();
+        hamt.check_invariants()
+            .unwrap()
+            .into_iter()
+            .map(|Violation { path, kind }| (path, kind))
+            .collect::<Vec<_>>()
+    };
+
+    // Keys whose hashes start with the given two bits, in ascending order.
+    let keys = |prefix: u8| -> Vec<u8> {
+        (0..=255u8)
+            .filter(|k| Sha256::hash(k)[0] >> 6 == prefix)
+            .collect()
+    };
+    let (k00, k01, k10) = (keys(0b00), keys(0b01), keys(0b10));
+
+    // Canonical.
+    let root = put(
+        0b11,
+        vec![Values(vec![(k00[0], 0)]), Values(vec![(k10[0], 0)])],
+    );
+    assert_eq!(check(root, &conf), vec![]);
+
+    // A key in the wrong bucket.
+    let root = put(0b01, vec![Values(vec![(k10[0], 0)])]);
+    assert_eq!(check(root, &conf), vec![(vec![0], MisplacedKey)]);
+
+    // Unsorted, empty and oversized buckets.
+    let root = put(0b01, vec![Values(vec![(k00[1], 0), (k00[0], 0)])]);
+    assert_eq!(check(root, &conf), vec![(vec![0], UnsortedBucket)]);
+    let root = put(0b01, vec![Values(vec![])]);
+    assert_eq!(check(root, &conf), vec![(vec![0], EmptyBucket)]);
+    let kvs = k00[..4].iter().map(|&k| (k, 0)).collect();
+    let root = put(0b01, vec![Values(kvs)]);
+    assert_eq!(check(root, &conf), vec![(vec![0], OversizedBucket(4))]);
+
+    // Bitfield doesn't match the pointers, or exceeds the bit width.
+    let root = put(0b11, vec![Values(vec![(k00[0], 0)])]);
+    let mismatch = BitfieldMismatch {
+        bits: 2,
+        highest: 1,
+        pointers: 1,
+    };
+    assert_eq!(check(root, &conf), vec![(vec![], mismatch)]);
+    let root = put(0b100, vec![Values(vec![(k00[0], 0)])]);
+    let mismatch = BitfieldMismatch {
+        bits: 1,
+        highest: 2,
+        pointers: 1,
+    };
+    assert_eq!(
+        check(root, &conf),
+        vec![(vec![], mismatch), (vec![2], MisplacedKey)]
+    );
+
+    // Empty and collapsible child nodes.
+    let child = put(0, vec![]);
+    let root = put(0b01, vec![Link(child)]);
+    assert_eq!(check(root, &conf), vec![(vec![0], EmptyNode)]);
+    let child = put(
+        0b11,
+        vec![Values(vec![(k00[0], 0)]), Values(vec![(k01[0], 0)])],
+    );
+    let root = put(0b01, vec![Link(child)]);
+    assert_eq!(check(root, &conf), vec![(vec![0], CollapsibleNode)]);
+
+    // The same child is required above the minimum data depth, but data isn't allowed there.
+    let min_depth_conf = Config {
+        min_data_depth: 1,
+        ..conf
+    };
+    assert_eq!(check(root, &min_depth_conf), vec![]);
+    let root = put(0b01, vec![Values(vec![(k00[0], 0)])]);
+    assert_eq!(
+        check(root, &min_depth_conf),
+        vec![(vec![0], DataAboveMinDepth)]
+    );
 }
 
 fn tstring(v: impl Display) -> BytesKey {
@@ -143,6 +143,26 @@ pub fn get_code_cid_for_type(typ: i32) -> Cid {
     }
 }
 
+/// The metadata of some actor code, as returned by [`get_code_info`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeInfo {
+    /// The size of the code, in bytes.
+    pub size: u64,
+    /// The FVM ABI version the code targets, or 0 if it's not an actor.
+    pub abi_version: u32,
+}
+
+/// Looks up the size and ABI version of some builtin actor code. See
+/// [`sys::actor::get_code_info`].
+pub fn get_code_info(code_cid: &Cid) -> SyscallResult<CodeInfo> {
+    let cid = code_cid.to_bytes();
+    let info = unsafe { sys::actor::get_code_info(cid.as_ptr())? };
+    Ok(CodeInfo {
+        size: info.size,
+        abi_version: info.abi_version,
+    })
+}
+
 /// Retrieves the balance of the specified actor, or None if the actor doesn't exist.
 pub fn balance_of(actor_id: ActorID) -> Option<TokenAmount> {
     unsafe {
@@ -153,3 +173,15 @@ pub fn balance_of(actor_id: ActorID) -> Option<TokenAmount> {
         }
     }
 }
+
+/// Retrieves the nonce (sequence number) of the specified actor, or None if the actor doesn't
+/// exist.
+pub fn nonce_of(actor_id: ActorID) -> Option<u64> {
+    unsafe {
+        match sys::actor::nonce_of(actor_id) {
+            Ok(nonce) => Some(nonce),
+            Err(ErrorNumber::NotFound) => None,
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+}
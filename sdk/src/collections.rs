@@ -0,0 +1,406 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Lazily loading, read-only iterators over on-chain HAMTs (maps) and AMTs (arrays).
+//!
+//! The iterators load nodes with [`ipld::get`](crate::ipld::get) (i.e., the `block_open` syscall)
+//! as iteration reaches them, and drop them once they've been visited. At most one node per level
+//! of the tree is held in memory at any time, so actors can scan large collections without loading
+//! them into memory in their entirety. Iteration order matches the `for_each` methods of the
+//! `fvm_ipld_hamt` and `fvm_ipld_amt` crates.
+//!
+//! [`Map`] and [`Array`] are typed references to a collection's root, and are (de)serialized as
+//! CIDs, so they can be used in place of CIDs in an actor's state:
+//!
+//! ```ignore
+//! #[derive(Serialize_tuple, Deserialize_tuple)]
+//! struct State {
+//!     balances: Map<BytesKey, TokenAmount>,
+//! }
+//!
+//! let state: State = fvm_ipld_encoding::from_slice(&ipld::get(&sself::root()?)?)?;
+//! for entry in state.balances.iter() {
+//!     let (owner, balance) = entry?;
+//!     // ...
+//! }
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use cid::Cid;
+use fvm_ipld_encoding::de::{
+    self, Deserialize, DeserializeOwned, Deserializer, SeqAccess, Visitor,
+};
+use fvm_ipld_encoding::ser::{Serialize, Serializer};
+use fvm_ipld_encoding::strict_bytes::ByteBuf;
+
+use crate::error::CollectionReadError;
+use crate::{ipld, SyscallResult};
+
+/// The default block loader, which loads blocks with [`ipld::get`].
+pub type Loader = fn(&Cid) -> SyscallResult<Vec<u8>>;
+
+/// A typed reference to a HAMT with keys of type `K` and values of type `V`.
+pub struct Map<K, V> {
+    root: Cid,
+    types: PhantomData<fn() -> (K, V)>,
+}
+
+/// A typed reference to an AMT (version 3) with values of type `V`.
+pub struct Array<V> {
+    root: Cid,
+    types: PhantomData<fn() -> V>,
+}
+
+macro_rules! collection_ref {
+    ($name:ident<$($param:ident),*>) => {
+        impl<$($param),*> $name<$($param),*> {
+            /// Creates a reference to the collection with the given root.
+            pub fn new(root: Cid) -> Self {
+                Self {
+                    root,
+                    types: PhantomData,
+                }
+            }
+
+            /// Returns the CID of the collection's root.
+            pub fn root(&self) -> &Cid {
+                &self.root
+            }
+        }
+
+        impl<$($param),*> Clone for $name<$($param),*> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<$($param),*> Copy for $name<$($param),*> {}
+
+        impl<$($param),*> PartialEq for $name<$($param),*> {
+            fn eq(&self, other: &Self) -> bool {
+                self.root == other.root
+            }
+        }
+
+        impl<$($param),*> Eq for $name<$($param),*> {}
+
+        impl<$($param),*> fmt::Debug for $name<$($param),*> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.debug_tuple(stringify!($name)).field(&self.root).finish()
+            }
+        }
+
+        impl<$($param),*> Serialize for $name<$($param),*> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.root.serialize(serializer)
+            }
+        }
+
+        impl<'de, $($param),*> Deserialize<'de> for $name<$($param),*> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                Cid::deserialize(deserializer).map(Self::new)
+            }
+        }
+    };
+}
+
+collection_ref!(Map<K, V>);
+collection_ref!(Array<V>);
+
+impl<K: DeserializeOwned, V: DeserializeOwned> Map<K, V> {
+    /// Iterates over the map's key-value pairs, loading nodes with [`ipld::get`].
+    pub fn iter(&self) -> MapIter<K, V> {
+        self.iter_with(ipld::get)
+    }
+
+    /// Iterates over the map's key-value pairs, loading nodes with the given function.
+    pub fn iter_with<L>(&self, load: L) -> MapIter<K, V, L>
+    where
+        L: FnMut(&Cid) -> SyscallResult<Vec<u8>>,
+    {
+        MapIter {
+            load,
+            pending: Some(self.root),
+            stack: Vec::new(),
+            values: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<V: DeserializeOwned> Array<V> {
+    /// Iterates over the array's index-value pairs, loading nodes with [`ipld::get`].
+    pub fn iter(&self) -> ArrayIter<V> {
+        self.iter_with(ipld::get)
+    }
+
+    /// Iterates over the array's index-value pairs, loading nodes with the given function.
+    pub fn iter_with<L>(&self, load: L) -> ArrayIter<V, L>
+    where
+        L: FnMut(&Cid) -> SyscallResult<Vec<u8>>,
+    {
+        ArrayIter {
+            load,
+            root: Some(self.root),
+            bit_width: 0,
+            pending: None,
+            stack: Vec::new(),
+            values: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// Loads and decodes a block.
+#[allow(clippy::result_large_err)]
+fn load_node<T, L>(load: &mut L, cid: &Cid) -> Result<T, CollectionReadError>
+where
+    T: DeserializeOwned,
+    L: FnMut(&Cid) -> SyscallResult<Vec<u8>>,
+{
+    let block = load(cid).map_err(|e| CollectionReadError::Load(*cid, e))?;
+    fvm_ipld_encoding::from_slice(&block)
+        .map_err(|e| CollectionReadError::Decode(*cid, e.to_string()))
+}
+
+/// A HAMT node: a bitfield, and the pointers for the bits set in the bitfield.
+type HamtNode<K, V> = (ByteBuf, Vec<HamtPointer<K, V>>);
+
+/// A HAMT pointer: a bucket of key-value pairs, or a link to a child node.
+enum HamtPointer<K, V> {
+    Values(Vec<(K, V)>),
+    Link(Cid),
+}
+
+impl<'de, K, V> Deserialize<'de> for HamtPointer<K, V>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PointerVisitor<K, V>(PhantomData<(K, V)>);
+
+        impl<'de, K, V> Visitor<'de> for PointerVisitor<K, V>
+        where
+            K: DeserializeOwned,
+            V: DeserializeOwned,
+        {
+            type Value = HamtPointer<K, V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of key-value pairs or a CID")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut values = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(kv) = seq.next_element()? {
+                    values.push(kv);
+                }
+                Ok(HamtPointer::Values(values))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                // DAG-CBOR presents CIDs as newtype structs wrapping the CID's bytes.
+                deserializer
+                    .deserialize_bytes(CidVisitor)
+                    .map(HamtPointer::Link)
+            }
+        }
+
+        struct CidVisitor;
+
+        impl<'de> Visitor<'de> for CidVisitor {
+            type Value = Cid;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a CID")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Cid::try_from(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(PointerVisitor(PhantomData))
+    }
+}
+
+/// A lazily loading iterator over the key-value pairs of a HAMT.
+///
+/// Yields an error (and then stops) if a node can't be loaded or decoded.
+pub struct MapIter<K, V, L = Loader> {
+    load: L,
+    /// The next node to load.
+    pending: Option<Cid>,
+    /// The remaining pointers of the nodes on the path to the current bucket.
+    stack: Vec<std::vec::IntoIter<HamtPointer<K, V>>>,
+    /// The remaining key-value pairs of the current bucket.
+    values: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V, L> Iterator for MapIter<K, V, L>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+    L: FnMut(&Cid) -> SyscallResult<Vec<u8>>,
+{
+    type Item = Result<(K, V), CollectionReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(kv) = self.values.next() {
+                return Some(Ok(kv));
+            }
+            if let Some(cid) = self.pending.take() {
+                match load_node::<HamtNode<K, V>, _>(&mut self.load, &cid) {
+                    Ok((_, pointers)) => self.stack.push(pointers.into_iter()),
+                    Err(e) => {
+                        self.stack.clear();
+                        return Some(Err(e));
+                    }
+                }
+            }
+            match self.stack.last_mut()?.next() {
+                Some(HamtPointer::Values(values)) => self.values = values.into_iter(),
+                Some(HamtPointer::Link(cid)) => self.pending = Some(cid),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+/// An AMT node: a bitmap, and the links or values for the bits set in the bitmap.
+type AmtNode<V> = (ByteBuf, Vec<Cid>, Vec<V>);
+
+/// An AMT root: the bit width, the height, the number of values, and the root node.
+type AmtRoot<V> = (u32, u32, u64, AmtNode<V>);
+
+/// The largest supported AMT bit width. Wider nodes can't be addressed with a 64 bit index.
+const MAX_AMT_BIT_WIDTH: u32 = 18;
+
+/// The links of an AMT node, along with the node's height.
+struct AmtFrame {
+    height: u32,
+    links: std::vec::IntoIter<(u64, Cid)>,
+}
+
+/// A lazily loading iterator over the index-value pairs of an AMT.
+///
+/// Yields an error (and then stops) if a node can't be loaded or decoded.
+pub struct ArrayIter<V, L = Loader> {
+    load: L,
+    /// The root, until it's loaded.
+    root: Option<Cid>,
+    bit_width: u32,
+    /// The next node to load, with its height and the index of its first value.
+    pending: Option<(u32, u64, Cid)>,
+    /// The remaining links of the nodes on the path to the current leaf.
+    stack: Vec<AmtFrame>,
+    /// The remaining index-value pairs of the current leaf.
+    values: std::vec::IntoIter<(u64, V)>,
+}
+
+impl<V, L> ArrayIter<V, L>
+where
+    V: DeserializeOwned,
+    L: FnMut(&Cid) -> SyscallResult<Vec<u8>>,
+{
+    /// Loads the root, or the pending node.
+    #[allow(clippy::result_large_err)]
+    fn load_next(&mut self) -> Result<(), CollectionReadError> {
+        let (height, offset, cid, node) = if let Some(cid) = self.root.take() {
+            let (bit_width, height, _, node) = load_node::<AmtRoot<V>, _>(&mut self.load, &cid)?;
+            if bit_width == 0 || bit_width > MAX_AMT_BIT_WIDTH {
+                return Err(CollectionReadError::Decode(
+                    cid,
+                    format!("invalid bit width {}", bit_width),
+                ));
+            }
+            self.bit_width = bit_width;
+            (height, 0, cid, node)
+        } else if let Some((height, offset, cid)) = self.pending.take() {
+            let node = load_node::<AmtNode<V>, _>(&mut self.load, &cid)?;
+            (height, offset, cid, node)
+        } else {
+            return Ok(());
+        };
+
+        let (bmap, links, values) = node;
+        let bmap = bmap.into_vec();
+        let slots: Vec<u64> = (0..1u64 << self.bit_width)
+            .filter(|&i| {
+                bmap.get((i / 8) as usize)
+                    .map_or(false, |b| b & (1 << (i % 8)) != 0)
+            })
+            .collect();
+        if height == 0 && slots.len() == values.len() && links.is_empty() {
+            self.values = slots
+                .into_iter()
+                .map(|i| offset + i)
+                .zip(values)
+                .collect::<Vec<_>>()
+                .into_iter();
+        } else if height > 0 && slots.len() == links.len() && values.is_empty() {
+            let step = nodes_for_height(self.bit_width, height);
+            self.stack.push(AmtFrame {
+                height,
+                links: slots
+                    .into_iter()
+                    .map(|i| offset.saturating_add(i.saturating_mul(step)))
+                    .zip(links)
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            });
+        } else {
+            return Err(CollectionReadError::Decode(
+                cid,
+                "node doesn't match its bitmap and height".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The number of values under each link of a node at the given height.
+fn nodes_for_height(bit_width: u32, height: u32) -> u64 {
+    let log = bit_width as u64 * height as u64;
+    if log >= 64 {
+        u64::MAX
+    } else {
+        1 << log
+    }
+}
+
+impl<V, L> Iterator for ArrayIter<V, L>
+where
+    V: DeserializeOwned,
+    L: FnMut(&Cid) -> SyscallResult<Vec<u8>>,
+{
+    type Item = Result<(u64, V), CollectionReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(v) = self.values.next() {
+                return Some(Ok(v));
+            }
+            if self.root.is_some() || self.pending.is_some() {
+                if let Err(e) = self.load_next() {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+                continue;
+            }
+            let frame = self.stack.last_mut()?;
+            match frame.links.next() {
+                Some((offset, cid)) => self.pending = Some((frame.height - 1, offset, cid)),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
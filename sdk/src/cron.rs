@@ -0,0 +1,53 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Helpers for actor methods invoked by the cron actor at the end of every epoch.
+//!
+//! Cron entries are registered at genesis, by network upgrades, or by builtin actors (see
+//! [`register_entry`]). Cron invokes each entry with no parameters and no value, so entry methods
+//! should check that they were called by cron, and may use [`is_due`] to only do their work every
+//! few epochs.
+
+use fvm_shared::clock::ChainEpoch;
+pub use fvm_shared::CRON_ACTOR_ID;
+use fvm_shared::{MethodNum, METHOD_CONSTRUCTOR, METHOD_SEND};
+
+use crate::error::CronError;
+use crate::{message, network, sys, SyscallResult};
+
+/// Returns true if the current method was invoked by the cron actor.
+pub fn called_by_cron() -> bool {
+    message::caller() == CRON_ACTOR_ID
+}
+
+/// Checks that `method` can be registered with cron. Cron can't invoke plain sends or
+/// constructors.
+pub fn validate_entry_method(method: MethodNum) -> Result<(), CronError> {
+    if method == METHOD_SEND || method == METHOD_CONSTRUCTOR {
+        return Err(CronError::InvalidMethod);
+    }
+    Ok(())
+}
+
+/// Registers one of the calling actor's methods to be invoked by cron at the end of every epoch.
+/// Only builtin actors may register cron entries. See [`sys::cron::register_entry`].
+pub fn register_entry(method: MethodNum) -> SyscallResult<()> {
+    unsafe { sys::cron::register_entry(method) }
+}
+
+/// Stops cron from invoking one of the calling actor's methods.
+pub fn unregister_entry(method: MethodNum) -> SyscallResult<()> {
+    unsafe { sys::cron::unregister_entry(method) }
+}
+
+/// Returns true if a task run every `interval` epochs, and last run at `last_run`, is due at the
+/// current epoch.
+pub fn is_due(last_run: ChainEpoch, interval: ChainEpoch) -> Result<bool, CronError> {
+    if interval <= 0 {
+        return Err(CronError::InvalidInterval);
+    }
+    let now = network::curr_epoch();
+    if last_run > now {
+        return Err(CronError::FutureEpoch);
+    }
+    Ok(now - last_run >= interval)
+}
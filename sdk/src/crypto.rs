@@ -5,9 +5,11 @@ use fvm_ipld_encoding::to_vec;
 use fvm_shared::address::Address;
 use fvm_shared::consensus::ConsensusFault;
 use fvm_shared::crypto::hash::SupportedHashes;
+use fvm_shared::crypto::merkle::MerkleProof;
 use fvm_shared::crypto::signature::{
     Signature, SECP_PUB_LEN, SECP_SIG_LEN, SECP_SIG_MESSAGE_HASH_SIZE,
 };
+use fvm_shared::crypto::snark::SnarkVerifyInfo;
 use fvm_shared::piece::PieceInfo;
 use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
@@ -106,6 +108,45 @@ pub fn hash_into(hasher: SupportedHashes, data: &[u8], digest: &mut [u8]) -> usi
     }
 }
 
+/// An incremental hasher, for hashing data without buffering all of it (e.g., data read from
+/// multiple blocks). Each message may only open a limited number of hashers.
+///
+/// Dropping the hasher without finalizing it leaks it until the end of the message.
+pub struct StreamingHasher {
+    hasher: SupportedHashes,
+    handle: u32,
+}
+
+impl StreamingHasher {
+    /// Opens a hasher using one of the supported hash functions.
+    pub fn new(hasher: SupportedHashes) -> SyscallResult<Self> {
+        let handle = unsafe { sys::crypto::hash_init(hasher as u64)? };
+        Ok(Self { hasher, handle })
+    }
+
+    /// Hashes more data.
+    pub fn update(&mut self, data: &[u8]) {
+        unsafe { sys::crypto::hash_update(self.handle, data.as_ptr(), data.len() as u32) }
+            .unwrap_or_else(|_| panic!("failed to hash using {:?}", self.hasher))
+    }
+
+    /// Returns the digest of all data hashed. Digests longer than 64 bytes will be truncated.
+    pub fn finalize(self) -> Vec<u8> {
+        let mut ret = vec![0u8; 64];
+        let written = self.finalize_into(&mut ret);
+        ret.truncate(written);
+        ret
+    }
+
+    /// Writes the digest of all data hashed into a buffer, truncating it to the buffer's length.
+    /// Returns the number of bytes written.
+    pub fn finalize_into(self, digest: &mut [u8]) -> usize {
+        unsafe { sys::crypto::hash_finalize(self.handle, digest.as_mut_ptr(), digest.len() as u32) }
+            .unwrap_or_else(|_| panic!("failed to finalize hash using {:?}", self.hasher))
+            as usize
+    }
+}
+
 /// Computes an unsealed sector CID (CommD) from its constituent piece CIDs (CommPs) and sizes.
 pub fn compute_unsealed_sector_cid(
     proof_type: RegisteredSealProof,
@@ -201,6 +242,64 @@ pub fn verify_replica_update(info: &ReplicaUpdateInfo) -> SyscallResult<bool> {
     }
 }
 
+/// Verifies a Merkle inclusion proof over data from another chain. Returns `Ok(false)` if the
+/// proof is well-formed but doesn't verify.
+pub fn verify_merkle_proof(proof: &MerkleProof) -> SyscallResult<bool> {
+    let encoded = match proof {
+        MerkleProof::Binary(proof) => to_vec(proof),
+        MerkleProof::Patricia(proof) => to_vec(proof),
+    }
+    .expect("failed to marshal merkle proof");
+    unsafe {
+        sys::crypto::verify_merkle_proof(
+            proof.proof_type() as u32,
+            encoded.as_ptr(),
+            encoded.len() as u32,
+        )
+        .map(status_code_to_bool)
+    }
+}
+
+/// Verifies a Groth16 proof against the verifying key with the given CID, which must be reachable
+/// by the actor like any block it gets (e.g., stored in the actor's state). Returns `Ok(false)` if
+/// the proof doesn't verify.
+///
+/// Unlike this function, the syscall takes the key as the handle of an open block rather than a
+/// CID: opening the block charges for loading the key and checks that it's reachable, like any
+/// other block, so the syscall doesn't need to. This function opens the key's block, and closes it
+/// once the proof is verified.
+pub fn verify_groth16(vk: &Cid, info: &SnarkVerifyInfo) -> SyscallResult<bool> {
+    let info = to_vec(info).expect("failed to marshal groth16 verification input");
+    with_verifying_key(vk, |id| unsafe {
+        sys::crypto::verify_groth16(id, info.as_ptr(), info.len() as u32)
+    })
+}
+
+/// Verifies a PLONK proof against the verifying key with the given CID, like [`verify_groth16`].
+/// Returns `Ok(false)` if the proof doesn't verify.
+pub fn verify_plonk(vk: &Cid, info: &SnarkVerifyInfo) -> SyscallResult<bool> {
+    let info = to_vec(info).expect("failed to marshal plonk verification input");
+    with_verifying_key(vk, |id| unsafe {
+        sys::crypto::verify_plonk(id, info.as_ptr(), info.len() as u32)
+    })
+}
+
+/// Opens the block of a succinct proof's verifying key, and runs `verify` with its handle. The
+/// block is closed afterwards, whether or not the proof verifies.
+fn with_verifying_key(
+    vk: &Cid,
+    verify: impl FnOnce(fvm_shared::sys::BlockId) -> SyscallResult<i32>,
+) -> SyscallResult<bool> {
+    let mut cid_buf = [0u8; MAX_CID_LEN];
+    vk.write_bytes(&mut cid_buf[..])
+        .expect("CID encoding should not fail");
+    let fvm_shared::sys::out::ipld::IpldOpen { id, .. } =
+        unsafe { sys::ipld::block_open(cid_buf.as_mut_ptr())? };
+    let res = verify(id);
+    unsafe { sys::ipld::block_close(id)? };
+    res.map(status_code_to_bool)
+}
+
 pub fn batch_verify_seals(batch: &[SealVerifyInfo]) -> SyscallResult<Vec<bool>> {
     let encoded = to_vec(batch).expect("failed to marshal batch seal verification input");
 
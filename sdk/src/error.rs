@@ -1,5 +1,7 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use cid::Cid;
+use fvm_shared::error::ErrorNumber;
 use thiserror::Error;
 
 #[derive(Copy, Clone, Debug, Error)]
@@ -30,4 +32,102 @@ pub enum EpochBoundsError {
     Invalid,
     #[error("the requested epoch exceeds the maximum lookback")]
     ExceedsLookback,
+    #[error("tipsets can't be looked up in read-only mode")]
+    ReadOnly,
+}
+
+#[derive(Copy, Clone, Debug, Error, Eq, PartialEq)]
+pub enum CronError {
+    #[error("cron can't invoke sends or constructors")]
+    InvalidMethod,
+    #[error("the schedule interval must be positive")]
+    InvalidInterval,
+    #[error("the last run is after the current epoch")]
+    FutureEpoch,
+}
+
+#[derive(Clone, Debug, Error, Eq, PartialEq)]
+pub enum CollectionReadError {
+    #[error("failed to load block {0}: {1}")]
+    Load(Cid, ErrorNumber),
+    #[error("failed to decode block {0}: {1}")]
+    Decode(Cid, String),
+}
+
+#[doc(hidden)]
+pub mod __private {
+    pub use fvm_shared::error::ExitCode;
+}
+
+/// Defines an enum of actor-specific exit codes, convertible to and from
+/// [`ExitCode`][fvm_shared::error::ExitCode].
+///
+/// Actors may not exit with codes in the system-reserved range (below
+/// [`FIRST_USER_EXIT_CODE`][fvm_shared::error::ExitCode::FIRST_USER_EXIT_CODE]), so defining such
+/// a code is a compile-time error. Actors should generally start at
+/// [`FIRST_ACTOR_SPECIFIC_EXIT_CODE`][fvm_shared::error::ExitCode::FIRST_ACTOR_SPECIFIC_EXIT_CODE]
+/// to avoid overlapping with the standard calling convention.
+///
+/// ```
+/// use fvm_shared::error::ExitCode;
+///
+/// fvm_sdk::actor_exit_codes! {
+///     pub enum MyExitCode {
+///         /// The widget is broken.
+///         BrokenWidget = 32,
+///         MissingGadget = 33,
+///     }
+/// }
+///
+/// assert_eq!(ExitCode::from(MyExitCode::BrokenWidget), ExitCode::new(32));
+/// assert_eq!(MyExitCode::try_from(ExitCode::new(33)), Ok(MyExitCode::MissingGadget));
+/// assert_eq!(MyExitCode::try_from(ExitCode::new(34)), Err(ExitCode::new(34)));
+/// ```
+///
+/// ```compile_fail
+/// fvm_sdk::actor_exit_codes! {
+///     pub enum MyExitCode {
+///         Reserved = 7,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! actor_exit_codes {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$vmeta:meta])* $variant:ident = $value:literal),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[repr(u32)]
+        $vis enum $name {
+            $($(#[$vmeta])* $variant = $value,)*
+        }
+
+        const _: () = {
+            $(assert!(
+                $value >= $crate::error::__private::ExitCode::FIRST_USER_EXIT_CODE,
+                concat!("exit code ", stringify!($variant), " is in the system-reserved range"),
+            );)*
+        };
+
+        impl ::core::convert::From<$name> for $crate::error::__private::ExitCode {
+            fn from(code: $name) -> Self {
+                Self::new(code as u32)
+            }
+        }
+
+        impl ::core::convert::TryFrom<$crate::error::__private::ExitCode> for $name {
+            type Error = $crate::error::__private::ExitCode;
+
+            fn try_from(code: $crate::error::__private::ExitCode) -> Result<Self, Self::Error> {
+                match code.value() {
+                    $($value => Ok(Self::$variant),)*
+                    _ => Err(code),
+                }
+            }
+        }
+    };
 }
@@ -1,6 +1,7 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use crate::sys;
+use crate::sys::gas::GasPrices;
 
 /// Charge gas for the operation identified by name.
 pub fn charge(name: &str, compute: u64) {
@@ -12,3 +13,8 @@ pub fn charge(name: &str, compute: u64) {
 pub fn available() -> u64 {
     unsafe { sys::gas::available() }.expect("failed to check available gas")
 }
+
+/// Returns a selection of gas prices (in milligas) for the current network version.
+pub fn prices() -> GasPrices {
+    unsafe { sys::gas::prices() }.expect("failed to get gas prices")
+}
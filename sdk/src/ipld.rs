@@ -82,3 +82,29 @@ pub fn put_block(
 ) -> SyscallResult<fvm_shared::sys::BlockId> {
     unsafe { sys::ipld::block_create(codec, data.as_ptr(), data.len() as u32) }
 }
+
+/// Closes the block referenced by BlockId, releasing it. Blocks are released when the invocation
+/// returns, so this is only needed by actors that would otherwise hit the network's limit on open
+/// blocks.
+pub fn close_block(id: fvm_shared::sys::BlockId) -> SyscallResult<()> {
+    unsafe { sys::ipld::block_close(id) }
+}
+
+/// Hints that the block with the given CID will likely be needed soon, so that it can be loaded in
+/// the background. See [`sys::ipld::block_prefetch`].
+pub fn prefetch(cid: &Cid) -> SyscallResult<()> {
+    let mut cid_buf = [0u8; MAX_CID_LEN];
+    cid.write_bytes(&mut cid_buf[..])
+        .expect("CID encoding should not fail");
+    unsafe { sys::ipld::block_prefetch(cid_buf.as_ptr()) }
+}
+
+/// Estimates the size of the graph of blocks reachable from `cid`, visiting at most `max_blocks`
+/// blocks. The root must be the actor's state root, or be linked by one of its open blocks. See
+/// [`sys::ipld::block_graph_stat`].
+pub fn graph_stat(cid: &Cid, max_blocks: u32) -> SyscallResult<sys::ipld::IpldGraphStat> {
+    let mut cid_buf = [0u8; MAX_CID_LEN];
+    cid.write_bytes(&mut cid_buf[..])
+        .expect("CID encoding should not fail");
+    unsafe { sys::ipld::block_graph_stat(cid_buf.as_ptr(), max_blocks) }
+}
@@ -1,6 +1,8 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 pub mod actor;
+pub mod collections;
+pub mod cron;
 pub mod crypto;
 pub mod debug;
 pub mod error;
@@ -12,7 +14,9 @@ pub mod network;
 pub mod rand;
 pub mod send;
 pub mod sself;
+pub mod sync;
 pub mod sys;
+pub mod token;
 pub mod vm;
 
 /// BlockID representing nil parameters or return data.
@@ -7,12 +7,13 @@ use fvm_shared::chainid::ChainID;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ErrorNumber;
+use fvm_shared::state::StateQuery;
 use fvm_shared::sys::out::network::NetworkContext;
 use fvm_shared::version::NetworkVersion;
 use fvm_shared::MAX_CID_LEN;
 
 use crate::error::EpochBoundsError;
-use crate::sys;
+use crate::{sys, vm};
 
 lazy_static::lazy_static! {
     pub(crate) static ref NETWORK_CONTEXT: NetworkContext = {
@@ -49,7 +50,12 @@ pub fn total_fil_circ_supply() -> TokenAmount {
     }
 }
 
-/// Returns the current block time in seconds since the EPOCH.
+/// Returns the timestamp of the current tipset, in seconds since the UNIX epoch. Actors can use it
+/// to implement deadlines in wall-clock terms, instead of deriving them from epochs and a
+/// hard-coded block time.
+///
+/// There's no dedicated syscall for this: the timestamp is part of the [`NetworkContext`], which
+/// the actor looks up (and pays for) once per invocation, so reading it again is free.
 pub fn tipset_timestamp() -> u64 {
     NETWORK_CONTEXT.timestamp
 }
@@ -64,7 +70,21 @@ pub fn tipset_cid(epoch: ChainEpoch) -> Result<Cid, EpochBoundsError> {
             Ok(len) => Ok(Cid::read_bytes(&buf[..len as usize]).expect("invalid cid")),
             Err(ErrorNumber::IllegalArgument) => Err(EpochBoundsError::Invalid),
             Err(ErrorNumber::LimitExceeded) => Err(EpochBoundsError::ExceedsLookback),
+            Err(ErrorNumber::ReadOnly) => Err(EpochBoundsError::ReadOnly),
             Err(other) => panic!("unexpected cid resolution failure: {}", other),
         }
     }
 }
+
+/// Looks up domain state held by the node, returning the DAG-CBOR encoded value, or `None` if the
+/// state doesn't exist. Only actors allowed by the network configuration may call this.
+pub fn read_state(query: &StateQuery) -> Option<Vec<u8>> {
+    let (kind, key) = query.encode().expect("failed to encode state query");
+    unsafe {
+        match sys::network::lookup_state(kind, key.as_ptr(), key.len() as u32) {
+            Ok(_) => Some(vm::return_pop().expect("state value missing from the return queue")),
+            Err(ErrorNumber::NotFound) => None,
+            Err(other) => panic!("unexpected state lookup failure: {}", other),
+        }
+    }
+}
@@ -34,3 +34,12 @@ pub fn get_beacon_randomness(
     };
     Ok(ret)
 }
+
+/// Gets 32 bytes of deterministic pseudo-randomness, for development networks without a randomness
+/// beacon. Successive calls within a message return different randomness, but the randomness is
+/// entirely predictable. Only available on networks with dev randomness enabled.
+pub fn get_dev_randomness(dst: i64, entropy: &[u8]) -> SyscallResult<[u8; RANDOMNESS_LENGTH]> {
+    let ret =
+        unsafe { sys::rand::get_dev_randomness(dst, entropy.as_ptr(), entropy.len() as u32)? };
+    Ok(ret)
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 use std::convert::TryInto;
 
+use cid::Cid;
+use fvm_ipld_encoding::de::DeserializeOwned;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_shared::address::Address;
 use fvm_shared::econ::TokenAmount;
@@ -18,6 +20,18 @@ pub struct Response {
     pub return_data: Option<IpldBlock>,
 }
 
+impl Response {
+    /// Decodes the data the callee attached when aborting (e.g., with
+    /// [`vm::revert`](crate::vm::revert)). Returns `None` if the call succeeded, or if the callee
+    /// didn't attach any data.
+    pub fn revert_data<T: DeserializeOwned>(&self) -> Result<Option<T>, fvm_ipld_encoding::Error> {
+        match &self.return_data {
+            Some(data) if !self.exit_code.is_success() => data.deserialize().map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
 /// Sends a message to another actor.
 pub fn send(
     to: &Address,
@@ -40,12 +54,7 @@ pub fn send(
         };
 
         // Perform the syscall to send the message.
-        let fvm_shared::sys::out::send::Send {
-            exit_code,
-            return_id,
-            return_codec,
-            return_size,
-        } = sys::send::send(
+        let ret = sys::send::send(
             recipient.as_ptr(),
             recipient.len() as u32,
             method,
@@ -56,26 +65,61 @@ pub fn send(
             flags,
         )?;
 
-        // Process the result.
-        let exit_code = ExitCode::new(exit_code);
-        let return_data = if return_id == NO_DATA_BLOCK_ID {
-            None
-        } else {
-            // Allocate a buffer to read the return data.
-            let mut bytes = vec![0; return_size as usize];
+        into_response(ret)
+    }
+}
 
-            // Now read the return data.
-            let unread = sys::ipld::block_read(return_id, 0, bytes.as_mut_ptr(), return_size)?;
-            assert_eq!(0, unread);
-            Some(IpldBlock {
-                codec: return_codec,
-                data: bytes.to_vec(),
-            })
+/// Invokes a method of the given code in the context of the calling actor: the code runs as this
+/// actor, on its state, and sees this call's caller and value received. The code must be allowed
+/// by the network.
+pub fn delegate_call(
+    code: &Cid,
+    method: MethodNum,
+    params: Option<IpldBlock>,
+    gas_limit: Option<u64>,
+    flags: SendFlags,
+) -> SyscallResult<Response> {
+    let code = code.to_bytes();
+    unsafe {
+        let params_id = match params {
+            Some(p) => sys::ipld::block_create(p.codec, p.data.as_ptr(), p.data.len() as u32)?,
+            None => NO_DATA_BLOCK_ID,
         };
 
-        Ok(Response {
-            exit_code,
-            return_data,
-        })
+        let ret = sys::send::delegate_call(
+            code.as_ptr(),
+            method,
+            params_id,
+            gas_limit.unwrap_or(u64::MAX),
+            flags,
+        )?;
+
+        into_response(ret)
     }
 }
+
+/// Reads the return value of a send into a [`Response`].
+fn into_response(ret: sys::send::Send) -> SyscallResult<Response> {
+    let exit_code = ExitCode::new(ret.exit_code);
+    let return_data = if ret.return_id == NO_DATA_BLOCK_ID {
+        None
+    } else {
+        // Allocate a buffer to read the return data.
+        let mut bytes = vec![0; ret.return_size as usize];
+
+        // Now read the return data.
+        let unread = unsafe {
+            sys::ipld::block_read(ret.return_id, 0, bytes.as_mut_ptr(), ret.return_size)?
+        };
+        assert_eq!(0, unread);
+        Some(IpldBlock {
+            codec: ret.return_codec,
+            data: bytes.to_vec(),
+        })
+    };
+
+    Ok(Response {
+        exit_code,
+        return_data,
+    })
+}
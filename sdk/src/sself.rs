@@ -68,3 +68,16 @@ pub fn self_destruct(beneficiary: &Address) -> Result<(), ActorDeleteError> {
         })
     }
 }
+
+/// Computes the statistics (block count, total size, and depth) of the actor's state graph, so the
+/// actor can enforce a budget on its own state. See [`sys::sself::state_stat`].
+///
+/// Fails if the actor has been deleted.
+pub fn state_stat() -> Result<sys::ipld::IpldGraphStat, StateReadError> {
+    unsafe {
+        sys::sself::state_stat().map_err(|e| match e {
+            ErrorNumber::IllegalOperation => StateReadError,
+            e => panic!("unexpected error from `self::state_stat` syscall: {}", e),
+        })
+    }
+}
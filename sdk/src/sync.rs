@@ -0,0 +1,52 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Per-message flags, for synchronizing the invocations of an actor within a message (e.g.,
+//! reentrancy locks) without a round-trip through the actor's state.
+
+use crate::sys;
+pub use crate::sys::sync::FlagOptions;
+
+/// Sets one of the actor's per-message flags, returning false if it was already set. See
+/// [`sys::sync::set_flag`] for the semantics of flags.
+pub fn set_flag(key: u64, options: FlagOptions) -> bool {
+    unsafe { sys::sync::set_flag(key, options.bits()) }.expect("failed to set flag") != 0
+}
+
+/// Clears one of the actor's per-message flags, returning false if it wasn't set.
+pub fn clear_flag(key: u64) -> bool {
+    unsafe { sys::sync::clear_flag(key) }.expect("failed to clear flag") != 0
+}
+
+/// A reentrancy lock, held until dropped. If the invocation holding it aborts, the lock is
+/// released when the invocation returns.
+///
+/// ```ignore
+/// const TRANSFER_LOCK: u64 = 1;
+///
+/// let _guard = CallGuard::acquire(TRANSFER_LOCK).ok_or(ExitCode::USR_FORBIDDEN)?;
+/// // Any reentrant call attempting to acquire the lock fails until the guard is dropped.
+/// ```
+#[must_use = "the lock is released when the guard is dropped"]
+#[derive(Debug)]
+pub struct CallGuard {
+    key: u64,
+}
+
+impl CallGuard {
+    /// Acquires the lock identified by `key`, or returns `None` if it's already held by an
+    /// invocation of this actor in the current message.
+    pub fn acquire(key: u64) -> Option<Self> {
+        set_flag(key, FlagOptions::empty()).then_some(Self { key })
+    }
+
+    /// Returns the key identifying the lock.
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        clear_flag(self.key);
+    }
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 //! Syscalls for creating and resolving actors.
 
+pub use fvm_shared::sys::out::actor::*;
+
 // for documentation links
 #[cfg(doc)]
 use crate::sys::ErrorNumber::*;
@@ -110,6 +112,23 @@ super::fvm_syscalls! {
     /// | [`IllegalArgument`] | if the type is invalid, or the outupt buffer isn't large enough |
     pub fn get_code_cid_for_type(typ: i32, obuf_off: *mut u8, obuf_len: u32) -> Result<u32>;
 
+    /// Returns the size and ABI version of the given builtin actor code, so it can be validated
+    /// before instantiating an actor with it. The ABI version is 1 for code exporting `invoke`,
+    /// and 0 otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// - `cid_off` specifies the code CID, which must be listed in the builtin actors' manifest.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                     |
+    /// |---------------------|--------------------------------------------|
+    /// | [`NotFound`]        | if the code isn't a builtin actor's code   |
+    /// | [`LimitExceeded`]   | if the code is too large to be inspected   |
+    /// | [`IllegalArgument`] | if the CID is invalid                      |
+    pub fn get_code_info(cid_off: *const u8) -> Result<CodeInfo>;
+
     /// Generates a new actor address for an actor deployed by the calling actor.
     ///
     /// **Privileged:** May only be called by the init actor.
@@ -147,4 +166,19 @@ super::fvm_syscalls! {
     pub fn balance_of(
         actor_id: u64
     )  -> Result<super::TokenAmount>;
+
+    /// Gets the nonce (sequence number) of the specified actor.
+    ///
+    /// # Arguments
+    ///
+    /// - `actor_id` is the ID of the target actor.
+    ///
+    /// # Errors
+    ///
+    /// | Error                | Reason                                         |
+    /// |----------------------|------------------------------------------------|
+    /// | [`NotFound`]         | the target actor does not exist                |
+    pub fn nonce_of(
+        actor_id: u64
+    )  -> Result<u64>;
 }
@@ -0,0 +1,45 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Syscalls for registering actor methods with the cron actor.
+
+// for documentation links
+#[cfg(doc)]
+use crate::sys::ErrorNumber::*;
+
+super::fvm_syscalls! {
+    module = "cron";
+
+    /// Registers one of the calling actor's methods with the cron actor, which invokes it at the
+    /// end of every epoch (after the entries registered before it), with no parameters and no
+    /// value.
+    ///
+    /// Only builtin actors may register cron entries.
+    ///
+    /// # Arguments
+    ///
+    /// - `method` is the method to invoke. It can't be a plain send or a constructor.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                                       |
+    /// |---------------------|--------------------------------------------------------------|
+    /// | [`IllegalArgument`] | the method is a send or a constructor, or already registered |
+    /// | [`Forbidden`]       | the calling actor isn't a builtin actor                      |
+    /// | [`ReadOnly`]        | the calling actor is executing in read-only mode             |
+    pub fn register_entry(method: u64) -> Result<()>;
+
+    /// Removes one of the calling actor's methods from the cron actor's entries.
+    ///
+    /// # Arguments
+    ///
+    /// - `method` is the method to remove.
+    ///
+    /// # Errors
+    ///
+    /// | Error         | Reason                                           |
+    /// |---------------|--------------------------------------------------|
+    /// | [`NotFound`]  | the method isn't registered                      |
+    /// | [`Forbidden`] | the calling actor isn't a builtin actor          |
+    /// | [`ReadOnly`]  | the calling actor is executing in read-only mode |
+    pub fn unregister_entry(method: u64) -> Result<()>;
+}
@@ -83,6 +83,54 @@ super::fvm_syscalls! {
         digest_len: u32,
     ) -> Result<u32>;
 
+    /// Opens an incremental hasher using the specified hash function, for hashing data larger than
+    /// the actor wants to buffer in memory. Returns the hasher's handle.
+    ///
+    /// Each message may only open a limited number of hashers (finalized or not). Hashers may
+    /// only be used by the actor that opened them.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                         |
+    /// |---------------------|------------------------------------------------|
+    /// | [`IllegalArgument`] | the hash function isn't supported              |
+    /// | [`LimitExceeded`]   | the message has opened too many hashers        |
+    pub fn hash_init(hash_code: u64) -> Result<u32>;
+
+    /// Feeds data to an incremental hasher. Hashing data in multiple calls costs the same gas per
+    /// byte as hashing it at once.
+    ///
+    /// # Arguments
+    ///
+    /// - `hasher` is the hasher's handle, as returned by [`hash_init`].
+    /// - `data_off` and `data_len` specify location and length of the data to be hashed.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                          |
+    /// |---------------------|-------------------------------------------------|
+    /// | [`InvalidHandle`]   | the hasher isn't known                          |
+    /// | [`IllegalArgument`] | the input buffer does not point to valid memory |
+    pub fn hash_update(hasher: u32, data_off: *const u8, data_len: u32) -> Result<()>;
+
+    /// Finalizes an incremental hasher, releasing it. The digest is written to the passed digest
+    /// buffer and truncated to `digest_len`.
+    ///
+    /// Returns the length of the digest written to the digest buffer.
+    ///
+    /// # Arguments
+    ///
+    /// - `hasher` is the hasher's handle, as returned by [`hash_init`].
+    /// - `digest_off` and `digest_len` specify the location and length of the output digest buffer.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                           |
+    /// |---------------------|--------------------------------------------------|
+    /// | [`InvalidHandle`]   | the hasher isn't known                           |
+    /// | [`IllegalArgument`] | the digest buffer does not point to valid memory |
+    pub fn hash_finalize(hasher: u32, digest_off: *mut u8, digest_len: u32) -> Result<u32>;
+
     /// Computes an unsealed sector CID (CommD) from its constituent piece CIDs
     /// (CommPs) and sizes.
     ///
@@ -162,6 +210,7 @@ super::fvm_syscalls! {
     /// |---------------------|---------------------------------------|
     /// | [`LimitExceeded`]   | exceeded lookback limit finding block |
     /// | [`IllegalArgument`] | an argument is malformed              |
+    /// | [`ReadOnly`]        | called in read-only mode              |
     pub fn verify_consensus_fault(
         h1_off: *const u8,
         h1_len: u32,
@@ -206,6 +255,66 @@ super::fvm_syscalls! {
     /// | [`IllegalArgument`] | an argument is malformed      |
     pub fn verify_replica_update(rep_off: *const u8, rep_len: u32) -> Result<i32>;
 
+    /// Verifies a Merkle inclusion proof against the root supplied in the proof. Gas is charged by
+    /// the number and total size of the proof's nodes.
+    ///
+    /// Returns 0 to indicate that the proof was valid, -1 otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// - `proof_type` is the [`MerkleProofType`][fvm_shared::crypto::merkle::MerkleProofType].
+    /// - `proof_off` and `proof_len` specify the location and length of a cbor-encoded
+    ///   [`BinaryMerkleProof`][fvm_shared::crypto::merkle::BinaryMerkleProof] or
+    ///   [`PatriciaMerkleProof`][fvm_shared::crypto::merkle::PatriciaMerkleProof] (depending on
+    ///   the proof type) in tuple representation.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                                 |
+    /// |---------------------|--------------------------------------------------------|
+    /// | [`IllegalArgument`] | unknown proof type, or the proof is malformed          |
+    pub fn verify_merkle_proof(proof_type: u32, proof_off: *const u8, proof_len: u32) -> Result<i32>;
+
+    /// Verifies a Groth16 proof against a verifying key. Gas is charged by the number of input
+    /// constraints of the circuit (one per public input, plus one).
+    ///
+    /// Returns 0 to indicate that the proof was valid, -1 otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// - `vk_id` is the handle of an open block holding a DAG-CBOR
+    ///   [`SnarkVerifyingKey`][fvm_shared::crypto::snark::SnarkVerifyingKey].
+    /// - `info_off` and `info_len` specify the location and length of a cbor-encoded
+    ///   [`SnarkVerifyInfo`][fvm_shared::crypto::snark::SnarkVerifyInfo] in tuple representation.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                               |
+    /// |---------------------|------------------------------------------------------|
+    /// | [`InvalidHandle`]   | the verifying key's handle isn't known               |
+    /// | [`IllegalArgument`] | unsupported curve, or malformed key, proof or inputs |
+    pub fn verify_groth16(vk_id: u32, info_off: *const u8, info_len: u32) -> Result<i32>;
+
+    /// Verifies a PLONK proof against a verifying key. Gas is charged by the size of the circuit's
+    /// evaluation domain, and by its number of public inputs.
+    ///
+    /// Returns 0 to indicate that the proof was valid, -1 otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// - `vk_id` is the handle of an open block holding a DAG-CBOR
+    ///   [`SnarkVerifyingKey`][fvm_shared::crypto::snark::SnarkVerifyingKey].
+    /// - `info_off` and `info_len` specify the location and length of a cbor-encoded
+    ///   [`SnarkVerifyInfo`][fvm_shared::crypto::snark::SnarkVerifyInfo] in tuple representation.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                               |
+    /// |---------------------|------------------------------------------------------|
+    /// | [`InvalidHandle`]   | the verifying key's handle isn't known               |
+    /// | [`IllegalArgument`] | unsupported curve, or malformed key, proof or inputs |
+    pub fn verify_plonk(vk_id: u32, info_off: *const u8, info_len: u32) -> Result<i32>;
+
     /// Verifies a batch of sector seal proofs.
     ///
     /// # Arguments
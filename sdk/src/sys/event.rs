@@ -18,6 +18,7 @@ super::fvm_syscalls! {
     /// | Error               | Reason                                                              |
     /// |---------------------|---------------------------------------------------------------------|
     /// | [`IllegalArgument`] | entries failed to validate due to improper encoding or invalid data |
+    /// | [`LimitExceeded`]   | the message's events exceed the network's total event size limit   |
     pub fn emit_event(
         evt_off: *const u8,
         evt_len: u32,
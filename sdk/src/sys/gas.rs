@@ -2,6 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 //! Syscalls for working with gas.
 
+#[doc(inline)]
+pub use fvm_shared::sys::out::gas::GasPrices;
+
 // for documentation links
 #[cfg(doc)]
 use crate::sys::ErrorNumber::*;
@@ -26,4 +29,11 @@ super::fvm_syscalls! {
 
     /// Returns the amount of gas remaining.
     pub fn available() -> Result<u64>;
+
+    /// Returns a selection of gas prices (in milligas) for the current network version.
+    ///
+    /// # Errors
+    ///
+    /// None
+    pub fn prices() -> Result<GasPrices>;
 }
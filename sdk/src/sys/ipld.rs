@@ -31,6 +31,7 @@ super::fvm_syscalls! {
     /// | Error               | Reason                                      |
     /// |---------------------|---------------------------------------------|
     /// | [`NotFound`]        | the target block isn't in the reachable set |
+    /// | [`LimitExceeded`]   | too many blocks are open                    |
     /// | [`IllegalArgument`] | there's something wrong with the CID        |
     pub fn block_open(cid: *const u8) -> Result<IpldOpen>;
 
@@ -46,7 +47,7 @@ super::fvm_syscalls! {
     ///
     /// | Error               | Reason                                                  |
     /// |---------------------|---------------------------------------------------------|
-    /// | [`LimitExceeded`]   | the block is too big                                    |
+    /// | [`LimitExceeded`]   | the block is too large, or too many blocks are open     |
     /// | [`NotFound`]        | one of the blocks's children isn't in the reachable set |
     /// | [`IllegalCodec`]    | the passed codec isn't supported                        |
     /// | [`Serialization`]   | the passed block doesn't match the passed codec         |
@@ -121,4 +122,56 @@ super::fvm_syscalls! {
         cid: *mut u8,
         cid_max_len: u32,
     ) -> Result<u32>;
+
+    /// Closes the specified block, releasing it. The handle isn't reused.
+    ///
+    /// Blocks are released automatically when the invocation returns, but actors that open or
+    /// create many blocks in a single invocation should close them to stay under the network's
+    /// limit on open blocks.
+    ///
+    /// # Errors
+    ///
+    /// | Error             | Reason                     |
+    /// |-------------------|----------------------------|
+    /// | [`InvalidHandle`] | if the handle isn't known. |
+    pub fn block_close(id: u32) -> Result<()>;
+
+    /// Estimates the size of the graph of blocks reachable from the specified CID (including the
+    /// block itself), visiting at most `max_blocks` distinct blocks. Only DAG-CBOR and raw blocks
+    /// are visited. Links to missing blocks are skipped, and the result is marked incomplete.
+    ///
+    /// The root must be reachable by the calling actor: it must be the actor's state root, or be
+    /// linked by one of the actor's open blocks.
+    ///
+    /// Gas is charged for every block visited, so the budget also bounds the cost of the call.
+    ///
+    /// # Arguments
+    ///
+    /// - `cid` is the root CID (in wasm memory).
+    /// - `max_blocks` is the maximum number of blocks to visit.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                                     |
+    /// |---------------------|------------------------------------------------------------|
+    /// | [`NotFound`]        | the root block isn't reachable, or isn't in the blockstore. |
+    /// | [`IllegalArgument`] | `max_blocks` is zero, or the CID is invalid/not in memory. |
+    pub fn block_graph_stat(cid: *const u8, max_blocks: u32) -> Result<IpldGraphStat>;
+
+    /// Hints that the specified block will likely be opened soon, so that the node can load it in
+    /// the background while the actor keeps executing (e.g., when traversing a deep structure).
+    ///
+    /// The hint is purely advisory and cheap: it has no effect on execution, the block needn't
+    /// exist or be reachable, and the node may ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// - `cid` the location of the CID (in wasm memory).
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                               |
+    /// |---------------------|--------------------------------------|
+    /// | [`IllegalArgument`] | there's something wrong with the CID |
+    pub fn block_prefetch(cid: *const u8) -> Result<()>;
 }
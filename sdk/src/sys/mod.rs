@@ -57,6 +57,7 @@ pub use fvm_shared::error::ErrorNumber;
 pub use fvm_shared::sys::TokenAmount;
 
 pub mod actor;
+pub mod cron;
 pub mod crypto;
 pub mod debug;
 pub mod event;
@@ -66,6 +67,8 @@ pub mod network;
 pub mod rand;
 pub mod send;
 pub mod sself;
+pub mod sync;
+pub mod token;
 pub mod vm;
 
 /// Generate a set of FVM syscall shims.
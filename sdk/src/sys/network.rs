@@ -37,12 +37,41 @@ super::fvm_syscalls! {
     /// |---------------------|----------------------------------------------|
     /// | [`IllegalArgument`] | specified epoch is negative or in the future |
     /// | [`LimitExceeded`]   | specified epoch exceeds finality             |
+    /// | [`ReadOnly`]        | called in read-only mode                     |
     pub fn tipset_cid(
         epoch: i64,
         ret_off: *mut u8,
         ret_len: u32,
     ) -> Result<u32>;
 
+    /// Looks up domain state held by the node (e.g., sector or deal info). Only actors allowed by
+    /// the network configuration may call this.
+    ///
+    /// # Arguments
+    ///
+    /// - `kind` is the kind of query (see `fvm_shared::state::StateQuery`).
+    /// - `key_off` and `key_len` specify the location and length of the query's key.
+    ///
+    /// # Returns
+    ///
+    /// Pushes the DAG-CBOR encoded value onto the return-data queue (see
+    /// [`return_pop`](crate::sys::vm::return_pop)) and returns its length.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                             |
+    /// |---------------------|----------------------------------------------------|
+    /// | [`Forbidden`]       | the calling actor may not look up domain state     |
+    /// | [`IllegalArgument`] | the query kind is unknown or the key is malformed  |
+    /// | [`NotFound`]        | the state doesn't exist                            |
+    /// | [`LimitExceeded`]   | the return-data queue is full                      |
+    /// | [`ReadOnly`]        | called in read-only mode                           |
+    pub fn lookup_state(
+        kind: u64,
+        key_off: *const u8,
+        key_len: u32,
+    ) -> Result<u32>;
+
     /// Returns the details about the network.
     ///
     /// # Errors
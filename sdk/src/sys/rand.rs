@@ -23,10 +23,11 @@ super::fvm_syscalls! {
     ///
     /// # Errors
     ///
-    /// | Error               | Reason                  |
-    /// |---------------------|-------------------------|
-    /// | [`LimitExceeded`]   | lookback exceeds limit. |
-    /// | [`IllegalArgument`] | invalid buffer, etc.    |
+    /// | Error               | Reason                     |
+    /// |---------------------|----------------------------|
+    /// | [`LimitExceeded`]   | lookback exceeds limit.    |
+    /// | [`IllegalArgument`] | invalid buffer, etc.       |
+    /// | [`ReadOnly`]        | called in read-only mode.  |
     pub fn get_chain_randomness(
         tag: i64,
         epoch: i64,
@@ -46,14 +47,40 @@ super::fvm_syscalls! {
     ///
     /// # Errors
     ///
-    /// | Error               | Reason                  |
-    /// |---------------------|-------------------------|
-    /// | [`LimitExceeded`]   | lookback exceeds limit. |
-    /// | [`IllegalArgument`] | invalid buffer, etc.    |
+    /// | Error               | Reason                     |
+    /// |---------------------|----------------------------|
+    /// | [`LimitExceeded`]   | lookback exceeds limit.    |
+    /// | [`IllegalArgument`] | invalid buffer, etc.       |
+    /// | [`ReadOnly`]        | called in read-only mode.  |
     pub fn get_beacon_randomness(
         tag: i64,
         epoch: i64,
         entropy_off: *const u8,
         entropy_len: u32,
     ) -> Result<[u8; RANDOMNESS_LENGTH]>;
+
+    /// Gets 32 bytes of deterministic pseudo-randomness, for development networks without a
+    /// randomness beacon. The randomness is derived from the current epoch, the message CID, and a
+    /// counter incremented on every call within the message, so it's entirely predictable.
+    ///
+    /// This syscall is only available on networks with dev randomness enabled: elsewhere, actors
+    /// importing it can't be loaded.
+    ///
+    /// # Arguments
+    ///
+    /// - `tag` is the "domain separation tag" for distinguishing between different categories of
+    ///    randomness. Think of it like extra, structured entropy.
+    /// - `entropy_off` and `entropy_len` specify the location and length of the entropy buffer that
+    ///    will be mixed into the randomness.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason               |
+    /// |---------------------|----------------------|
+    /// | [`IllegalArgument`] | invalid buffer, etc. |
+    pub fn get_dev_randomness(
+        tag: i64,
+        entropy_off: *const u8,
+        entropy_len: u32,
+    ) -> Result<[u8; RANDOMNESS_LENGTH]>;
 }
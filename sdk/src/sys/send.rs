@@ -25,7 +25,8 @@ super::fvm_syscalls! {
     /// - `params` is the IPLD block handle of the method parameters.
     /// - `value_hi` are the "high" bits of the token value to send (little-endian) in attoFIL.
     /// - `value_lo` are the "high" bits of the token value to send (little-endian) in attoFIL.
-    /// - `gas_limit` is the gas this send is allowed to use. Zero means "all available gas".
+    /// - `gas_limit` is the gas this send is allowed to use. Values above `i64::MAX` (e.g.,
+    ///   `u64::MAX`) mean "all available gas".
     /// - `send_flags` are additional send flags.
     ///
     /// **NOTE**: This syscall will transfer `(value_hi << 64) | (value_lo)` attoFIL to the
@@ -55,4 +56,39 @@ super::fvm_syscalls! {
         gas_limit: u64,
         flags: SendFlags,
     ) -> Result<Send>;
+
+    /// Invokes a method of the given code in the context of the calling actor, and returns the
+    /// exit code and block ID of the return result. The code runs as the calling actor (with the
+    /// same state root and balance), and sees the same caller and value received. No value is
+    /// transferred.
+    ///
+    /// # Arguments
+    ///
+    /// - `code_off` is the location of the code CID (in wasm memory).
+    /// - `method` is the method number to invoke.
+    /// - `params` is the IPLD block handle of the method parameters.
+    /// - `gas_limit` is the gas this call is allowed to use. Values above `i64::MAX` (e.g.,
+    ///   `u64::MAX`) mean "all available gas".
+    /// - `send_flags` are additional send flags.
+    ///
+    /// # Errors
+    ///
+    /// As with [`send`], failures of the delegated code are reflected in the exit code contained
+    /// in the return value.
+    ///
+    /// | Error                | Reason                                                     |
+    /// |----------------------|------------------------------------------------------------|
+    /// | [`Forbidden`]        | delegate calls aren't enabled, or not allowed to the code. |
+    /// | [`InvalidHandle`]    | parameters block not found.                                |
+    /// | [`LimitExceeded`]    | recursion limit reached.                                   |
+    /// | [`IllegalArgument`]  | invalid code CID buffer, or method 0.                      |
+    /// | [`IllegalOperation`] | the calling actor has been deleted.                        |
+    /// | [`ReadOnly`]         | the call would mutate state in read-only mode.             |
+    pub fn delegate_call(
+        code_off: *const u8,
+        method: u64,
+        params: u32,
+        gas_limit: u64,
+        flags: SendFlags,
+    ) -> Result<Send>;
 }
@@ -64,4 +64,21 @@ super::fvm_syscalls! {
     /// | [`Forbidden`]       | beneficiary is not allowed (usually means beneficiary is self) |
     /// | [`IllegalArgument`] | if the passed address buffer isn't valid, in memory, etc.      |
     pub fn self_destruct(addr_off: *const u8, addr_len: u32) -> Result<()>;
+
+    /// Computes the statistics of the calling actor's state: the number of blocks, their total
+    /// size, and the depth of the graph of DAG-CBOR and raw blocks reachable from its state root.
+    ///
+    /// Blocks reachable through several links are counted once per link. Links to missing blocks
+    /// are skipped, and the statistics are marked incomplete. Gas is charged for every block
+    /// visited, except for the blocks whose statistics the kernel already knows because they were
+    /// linked (or visited) earlier in the same message. Actors that query their statistics after
+    /// updating their state therefore only pay for the parts they didn't rewrite.
+    ///
+    /// # Errors
+    ///
+    /// | Error                | Reason                                             |
+    /// |----------------------|----------------------------------------------------|
+    /// | [`IllegalOperation`] | actor has been deleted                             |
+    /// | [`NotFound`]         | the state root block isn't in the blockstore       |
+    pub fn state_stat() -> Result<super::ipld::IpldGraphStat>;
 }
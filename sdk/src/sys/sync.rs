@@ -0,0 +1,47 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Syscalls for synchronizing the invocations of an actor within a message.
+
+#[doc(inline)]
+pub use fvm_shared::sys::FlagOptions;
+
+// for documentation links
+#[cfg(doc)]
+use crate::sys::ErrorNumber::*;
+
+super::fvm_syscalls! {
+    module = "sync";
+
+    /// Sets one of the calling actor's per-message flags. Returns 1 if the flag was set, or 0 if
+    /// it was already set (in which case it's left untouched).
+    ///
+    /// Each actor has its own namespace of flags. A flag is cleared when the invocation that set
+    /// it returns or aborts, unless it's set with [`FlagOptions::MESSAGE_SCOPE`], in which case it
+    /// stays set until the end of the message. Like state changes, the flags set or cleared by an
+    /// invocation are rolled back if it aborts or exits with an error. Flags are never persisted in
+    /// the state.
+    ///
+    /// # Arguments
+    ///
+    /// - `key` identifies the flag.
+    /// - `options` are the [`FlagOptions`] bits.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                 |
+    /// |---------------------|------------------------|
+    /// | [`IllegalArgument`] | invalid option bits    |
+    pub fn set_flag(key: u64, options: u64) -> Result<u32>;
+
+    /// Clears one of the calling actor's per-message flags. Returns 1 if the flag was cleared, or
+    /// 0 if it wasn't set.
+    ///
+    /// # Arguments
+    ///
+    /// - `key` identifies the flag.
+    ///
+    /// # Errors
+    ///
+    /// None
+    pub fn clear_flag(key: u64) -> Result<u32>;
+}
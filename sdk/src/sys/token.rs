@@ -0,0 +1,40 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Syscalls for FRC-46 token transfers.
+
+#[doc(inline)]
+pub use fvm_shared::sys::out::token::*;
+
+super::fvm_syscalls! {
+    module = "token";
+
+    /// Invokes the FRC-46 receiver hook of the recipient of tokens transferred by the calling
+    /// (token) actor on behalf of its caller (the operator). Returns the IDs of the owner and
+    /// recipient, and the block ID of the hook's return value. The recipient is created if it
+    /// doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// - `transfer_off` and `transfer_len` specify the location and length of the CBOR-encoded
+    ///   [`TokenTransfer`](fvm_shared::token::TokenTransfer) (in wasm memory).
+    /// - `gas_limit` is the gas the receiver hook is allowed to use, capped at
+    ///   [`MAX_RECEIVER_HOOK_GAS`](fvm_shared::token::MAX_RECEIVER_HOOK_GAS).
+    ///
+    /// # Aborts
+    ///
+    /// This syscall never returns an error. Instead, it aborts the calling actor, reverting the
+    /// transfer, with one of the following exit codes.
+    ///
+    /// | Exit code              | Reason                                                       |
+    /// |------------------------|--------------------------------------------------------------|
+    /// | `USR_FORBIDDEN`        | the recipient rejected the transfer (or its hook failed).    |
+    /// | `USR_NOT_FOUND`        | the owner doesn't exist, or the recipient can't be created.  |
+    /// | `USR_SERIALIZATION`    | the transfer can't be decoded.                               |
+    /// | `USR_READ_ONLY`        | the calling actor is in read-only mode.                      |
+    /// | `USR_ILLEGAL_ARGUMENT` | negative amount, or recursion limit reached.                 |
+    pub fn transfer_hook(
+        transfer_off: *const u8,
+        transfer_len: u32,
+        gas_limit: u64,
+    ) -> Result<TransferHook>;
+}
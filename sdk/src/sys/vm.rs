@@ -5,6 +5,10 @@
 #[doc(inline)]
 pub use fvm_shared::sys::out::vm::MessageContext;
 
+// for documentation links
+#[cfg(doc)]
+use crate::sys::ErrorNumber::*;
+
 super::fvm_syscalls! {
     module = "vm";
 
@@ -18,7 +22,8 @@ super::fvm_syscalls! {
     ///   the current execution context.
     ///   If this code is not zero and less than the [minimum "user" exit
     ///   code][fvm_shared::error::ExitCode::FIRST_USER_EXIT_CODE], it will be replaced with
-    ///   [`SYS_ILLEGAL_EXIT_CODE`][fvm_shared::error::ExitCode::SYS_ILLEGAL_EXIT_CODE].
+    ///   [`SYS_ILLEGAL_EXIT_CODE`][fvm_shared::error::ExitCode::SYS_ILLEGAL_EXIT_CODE] and,
+    ///   from network version 19, any return data will be discarded.
     /// - `blk_id` is the optional data block id; it should be 0 if there are no data attached to
     ///   this exit.
     /// - `message_off` and `message_len` specify the offset and length (in wasm memory) of an
@@ -36,4 +41,33 @@ super::fvm_syscalls! {
     ///
     /// None
     pub fn message_context() -> Result<MessageContext>;
+
+    /// Returns the length (in bytes) of the payload at the front of the return-data queue.
+    ///
+    /// Some syscalls return optional or variable-sized payloads by pushing them onto a bounded,
+    /// per-invocation return-data queue, to be popped (in order) with [`return_pop`]. Payloads
+    /// left on the queue are discarded when the current invocation returns.
+    ///
+    /// # Errors
+    ///
+    /// | Error        | Reason                    |
+    /// |--------------|---------------------------|
+    /// | [`NotFound`] | the return queue is empty |
+    pub fn return_len() -> Result<u32>;
+
+    /// Pops the payload at the front of the return-data queue into the output buffer, returning
+    /// the number of bytes written.
+    ///
+    /// # Arguments
+    ///
+    /// - `obuf_off` and `obuf_len` specify the location and length of the output buffer.
+    ///
+    /// # Errors
+    ///
+    /// | Error               | Reason                                                   |
+    /// |---------------------|----------------------------------------------------------|
+    /// | [`NotFound`]        | the return queue is empty                                |
+    /// | [`BufferTooSmall`]  | the payload doesn't fit in the buffer (it is not popped) |
+    /// | [`IllegalArgument`] | the output buffer isn't valid                            |
+    pub fn return_pop(obuf_off: *mut u8, obuf_len: u32) -> Result<u32>;
 }
@@ -0,0 +1,60 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Kernel-assisted [FRC-46](https://github.com/filecoin-project/FIPs/blob/master/FRCs/frc-0046.md)
+//! token transfers.
+//!
+//! A token actor keeps its own balances and allowances. To transfer tokens, it debits the owner
+//! (and the operator's allowance, if the operator isn't the owner), credits the recipient, and then
+//! calls [`transfer_hook`], which invokes the recipient's receiver hook. If the transfer is invalid,
+//! or the recipient rejects it, the FVM aborts the token actor, reverting the transfer.
+
+use fvm_ipld_encoding::ipld_block::IpldBlock;
+use fvm_ipld_encoding::to_vec;
+pub use fvm_shared::token::*;
+use fvm_shared::ActorID;
+
+use crate::{sys, NO_DATA_BLOCK_ID};
+
+/// The outcome of a token transfer accepted by its recipient.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TransferReceipt {
+    /// The ID of the owner of the tokens.
+    pub from: ActorID,
+    /// The ID of the recipient of the tokens.
+    pub to: ActorID,
+    /// The value returned by the recipient's receiver hook.
+    pub return_data: Option<IpldBlock>,
+}
+
+/// Invokes the receiver hook of the recipient of tokens transferred on behalf of the caller (the
+/// operator), with at most `gas_limit` gas (by default, [`MAX_RECEIVER_HOOK_GAS`]).
+///
+/// Only returns if the recipient accepted the transfer: otherwise, the calling actor is aborted
+/// with `USR_FORBIDDEN` (or another exit code if the transfer is invalid).
+pub fn transfer_hook(transfer: &TokenTransfer, gas_limit: Option<u64>) -> TransferReceipt {
+    let transfer = to_vec(transfer).expect("failed to encode token transfer");
+    let ret = unsafe {
+        sys::token::transfer_hook(
+            transfer.as_ptr(),
+            transfer.len() as u32,
+            gas_limit.unwrap_or(u64::MAX),
+        )
+    }
+    .expect("token transfers abort on failure");
+
+    let return_data = if ret.return_id == NO_DATA_BLOCK_ID {
+        None
+    } else {
+        Some(IpldBlock {
+            codec: ret.return_codec,
+            data: crate::ipld::get_block(ret.return_id, Some(ret.return_size))
+                .expect("failed to read the receiver hook's return value"),
+        })
+    };
+
+    TransferReceipt {
+        from: ret.from,
+        to: ret.to,
+        return_data,
+    }
+}
@@ -2,8 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 use std::ptr;
 
+use fvm_ipld_encoding::ser::Serialize;
 use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
-use fvm_shared::error::ExitCode;
+use fvm_shared::error::{ErrorNumber, ExitCode};
 
 use crate::sys;
 
@@ -33,6 +34,29 @@ pub fn abort(code: u32, message: Option<&str>) -> ! {
     }
 }
 
+/// Abort execution with the specified (non-zero) exit code, attaching `data` (encoded as DAG-CBOR)
+/// for the caller to decode with [`Response::revert_data`](crate::send::Response::revert_data).
+/// This lets callers handle failures based on more than just the exit code.
+///
+/// Reverting with a success code is a bug in the actor: it aborts with `USR_ASSERTION_FAILED`
+/// instead, without the data.
+pub fn revert<T: Serialize + ?Sized>(code: ExitCode, data: &T, message: Option<&str>) -> ! {
+    if code.is_success() {
+        abort(
+            ExitCode::USR_ASSERTION_FAILED.value(),
+            Some("cannot revert with a success exit code"),
+        )
+    }
+    let data = match RawBytes::serialize(data) {
+        Ok(data) => data,
+        Err(_) => abort(
+            ExitCode::USR_SERIALIZATION.value(),
+            Some("failed to serialize revert data"),
+        ),
+    };
+    exit(code.value(), data, message)
+}
+
 /// Exit from current message execution, with the specified code and an optional message and data.
 pub fn exit(code: u32, data: RawBytes, message: Option<&str>) -> ! {
     unsafe {
@@ -54,6 +78,22 @@ pub fn exit(code: u32, data: RawBytes, message: Option<&str>) -> ! {
     }
 }
 
+/// Pops the next payload from the return-data queue, or returns `None` if the queue is empty.
+pub fn return_pop() -> Option<Vec<u8>> {
+    unsafe {
+        let len = match sys::vm::return_len() {
+            Ok(len) => len,
+            Err(ErrorNumber::NotFound) => return None,
+            Err(e) => panic!("unexpected error when reading the return queue: {}", e),
+        };
+        let mut buf = Vec::with_capacity(len as usize);
+        let written = sys::vm::return_pop(buf.as_mut_ptr(), len)
+            .expect("failed to pop from the return queue");
+        buf.set_len(written as usize);
+        Some(buf)
+    }
+}
+
 /// Sets a panic handler to turn all panics into aborts with `USR_ASSERTION_FAILED`. This should be
 /// called early in the actor to improve debuggability.
 ///
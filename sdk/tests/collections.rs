@@ -0,0 +1,96 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use cid::Cid;
+use fvm_ipld_amt::Amt;
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_sdk::collections::{Array, Map};
+use fvm_sdk::SyscallResult;
+use fvm_shared::error::ErrorNumber;
+
+fn loader(store: &MemoryBlockstore) -> impl FnMut(&Cid) -> SyscallResult<Vec<u8>> + '_ {
+    |cid| store.get(cid).unwrap().ok_or(ErrorNumber::NotFound)
+}
+
+#[test]
+fn map_matches_hamt() {
+    for (bit_width, len) in [(8, 0), (8, 3), (5, 1000), (1, 100)] {
+        let store = MemoryBlockstore::default();
+        let mut hamt: Hamt<_, String, BytesKey> = Hamt::new_with_bit_width(&store, bit_width);
+        for i in 0..len {
+            hamt.set(
+                format!("key{}", i).into_bytes().into(),
+                format!("value{}", i),
+            )
+            .unwrap();
+        }
+        let root = hamt.flush().unwrap();
+
+        let mut expected = Vec::new();
+        hamt.for_each(|k, v| {
+            expected.push((k.clone(), v.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(expected.len(), len);
+
+        let map = Map::<BytesKey, String>::new(root);
+        let entries: Vec<_> = map.iter_with(loader(&store)).map(Result::unwrap).collect();
+        assert_eq!(entries, expected, "bit width {}", bit_width);
+    }
+}
+
+#[test]
+fn array_matches_amt() {
+    let indices: Vec<u64> = (0..1000).chain([4096, 1 << 20, u64::MAX >> 1]).collect();
+    for bit_width in [3, 1, 8] {
+        let store = MemoryBlockstore::default();
+        let mut amt = Amt::new_with_bit_width(&store, bit_width);
+        for &i in &indices {
+            amt.set(i, format!("value{}", i)).unwrap();
+        }
+        let root = amt.flush().unwrap();
+
+        let mut expected = Vec::new();
+        amt.for_each(|i, v: &String| {
+            expected.push((i, v.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(expected.len(), indices.len());
+
+        let array = Array::<String>::new(root);
+        let entries: Vec<_> = array
+            .iter_with(loader(&store))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries, expected, "bit width {}", bit_width);
+    }
+}
+
+#[test]
+fn missing_node() {
+    let store = MemoryBlockstore::default();
+    let mut hamt: Hamt<_, String, BytesKey> = Hamt::new_with_bit_width(&store, 1);
+    for i in 0..100 {
+        hamt.set(
+            format!("key{}", i).into_bytes().into(),
+            format!("value{}", i),
+        )
+        .unwrap();
+    }
+    let root = hamt.flush().unwrap();
+
+    // Only the root is available: iteration fails once it reaches a child, then stops.
+    let map = Map::<BytesKey, String>::new(root);
+    let mut loads = 0;
+    let mut iter = map.iter_with(|cid| {
+        loads += 1;
+        match loads {
+            1 => loader(&store)(cid),
+            _ => Err(ErrorNumber::NotFound),
+        }
+    });
+    assert!(iter.by_ref().any(|entry| entry.is_err()));
+    assert!(iter.next().is_none());
+}
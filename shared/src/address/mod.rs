@@ -61,6 +61,9 @@ pub const CHECKSUM_HASH_LEN: usize = 4;
 /// The max encoded length of an address.
 pub const MAX_ADDRESS_LEN: usize = 65;
 
+/// The max length of a LEB128-encoded u64, as used in ID and delegated address payloads.
+const MAX_LEB128_U64_LEN: usize = 10;
+
 const MAX_ADDRRESS_TEXT_LEN: usize = 138;
 const MAINNET_PREFIX: &str = "f";
 const TESTNET_PREFIX: &str = "t";
@@ -84,12 +87,9 @@ impl Address {
 
     /// Creates address from encoded bytes.
     pub fn from_bytes(bz: &[u8]) -> Result<Self, Error> {
-        if bz.len() < 2 {
-            Err(Error::InvalidLength)
-        } else {
-            let protocol = Protocol::from_byte(bz[0]).ok_or(Error::UnknownProtocol)?;
-            Self::new(protocol, &bz[1..])
-        }
+        let protocol = validate_address_bytes(bz)?;
+        let (_, payload) = bz.split_first().ok_or(Error::InvalidLength)?;
+        Self::new(protocol, payload)
     }
 
     /// Generates new address using ID protocol.
@@ -366,6 +366,32 @@ impl<'de> Deserialize<'de> for Address {
     }
 }
 
+/// Checks that an encoded address has a known protocol and a payload length allowed by that
+/// protocol, without decoding the payload, and returns the protocol. This is the first step of
+/// [`Address::from_bytes`], and can be used to cheaply reject garbage before decoding it.
+///
+/// Fails with [`Error::InvalidLength`] if the address is shorter than 2 bytes or longer than
+/// [`MAX_ADDRESS_LEN`], [`Error::UnknownProtocol`] if the protocol byte is unknown, and
+/// [`Error::InvalidPayloadLength`] if the payload length isn't valid for the protocol.
+pub fn validate_address_bytes(bz: &[u8]) -> Result<Protocol, Error> {
+    if bz.len() < 2 || bz.len() > MAX_ADDRESS_LEN {
+        return Err(Error::InvalidLength);
+    }
+    let protocol = Protocol::from_byte(bz[0]).ok_or(Error::UnknownProtocol)?;
+    let payload_len = bz.len() - 1;
+    let valid = match protocol {
+        Protocol::ID => payload_len <= MAX_LEB128_U64_LEN,
+        Protocol::Secp256k1 | Protocol::Actor => payload_len == PAYLOAD_HASH_LEN,
+        Protocol::BLS => payload_len == BLS_PUB_LEN,
+        // The namespace, then the subaddress.
+        Protocol::Delegated => payload_len <= MAX_LEB128_U64_LEN + MAX_SUBADDRESS_LEN,
+    };
+    if !valid {
+        return Err(Error::InvalidPayloadLength(payload_len));
+    }
+    Ok(protocol)
+}
+
 pub(crate) fn to_leb_bytes(id: u64) -> Vec<u8> {
     // write id to buffer in leb128 format
     unsigned_varint::encode::u64(id, &mut unsigned_varint::encode::u64_buffer()).into()
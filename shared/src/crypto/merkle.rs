@@ -0,0 +1,74 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Merkle inclusion proofs over data from other chains, verified by the `verify_merkle_proof`
+//! syscall. These let actors (e.g., bridges) check foreign-chain data against a trusted root
+//! without hashing in Wasm.
+
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{strict_bytes, BytesDe};
+use num_derive::FromPrimitive;
+
+/// The kinds of Merkle proofs that can be verified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, FromPrimitive)]
+#[repr(u32)]
+pub enum MerkleProofType {
+    /// See [`BinaryMerkleProof`].
+    Binary = 1,
+    /// See [`PatriciaMerkleProof`].
+    Patricia = 2,
+}
+
+/// A proof that `leaf` is the `index`th leaf of a binary Merkle tree with the given `root`.
+///
+/// Each interior node is the hash of the concatenation of its left and right children. The leaf
+/// is the digest stored in the tree (i.e., leaf data must be hashed by the caller).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct BinaryMerkleProof {
+    /// The multihash code of the hash function used to build the tree.
+    pub hash: u64,
+    #[serde(with = "strict_bytes")]
+    pub root: Vec<u8>,
+    #[serde(with = "strict_bytes")]
+    pub leaf: Vec<u8>,
+    /// The position of the leaf in the tree, from the left. Bit `i` of the index selects whether
+    /// the node at height `i` on the path is a right (1) or left (0) child.
+    pub index: u64,
+    /// The siblings of the nodes on the path from the leaf to the root, starting at the leaf.
+    pub siblings: Vec<BytesDe>,
+}
+
+/// A proof that `key` maps to `value` in an Ethereum Merkle-Patricia trie with the given `root`
+/// (e.g., a state, storage, transaction or receipt trie).
+///
+/// Keys are used as is: for secure tries (state and storage), the caller must pass the Keccak-256
+/// hash of the account address or storage slot.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct PatriciaMerkleProof {
+    #[serde(with = "strict_bytes")]
+    pub root: [u8; 32],
+    #[serde(with = "strict_bytes")]
+    pub key: Vec<u8>,
+    /// The expected value, as stored in the trie (usually RLP-encoded). Must not be empty:
+    /// exclusion proofs aren't supported.
+    #[serde(with = "strict_bytes")]
+    pub value: Vec<u8>,
+    /// The RLP-encoded trie nodes on the path from the root to the value, starting at the root.
+    /// Nodes embedded in their parent (shorter than 32 bytes) must be omitted.
+    pub nodes: Vec<BytesDe>,
+}
+
+/// A Merkle proof of any supported type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MerkleProof {
+    Binary(BinaryMerkleProof),
+    Patricia(PatriciaMerkleProof),
+}
+
+impl MerkleProof {
+    pub fn proof_type(&self) -> MerkleProofType {
+        match self {
+            MerkleProof::Binary(_) => MerkleProofType::Binary,
+            MerkleProof::Patricia(_) => MerkleProofType::Patricia,
+        }
+    }
+}
@@ -1,4 +1,6 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 pub mod hash;
+pub mod merkle;
 pub mod signature;
+pub mod snark;
@@ -0,0 +1,44 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Succinct (zk-SNARK) proofs, verified by the `verify_groth16` and `verify_plonk` syscalls. This
+//! lets actors (e.g., bridges and rollups) check proofs generated off-chain without verifying them
+//! in Wasm.
+
+use fvm_ipld_encoding::repr::*;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{strict_bytes, BytesDe};
+use num_derive::FromPrimitive;
+
+/// The length of an encoded public input: a little-endian element of the curve's scalar field.
+pub const SNARK_PUBLIC_INPUT_LEN: usize = 32;
+
+/// The pairing-friendly curves that succinct proofs may be generated over.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, FromPrimitive, Serialize_repr, Deserialize_repr,
+)]
+#[repr(u32)]
+pub enum SnarkCurve {
+    Bls12_381 = 1,
+}
+
+/// The Groth16 or PLONK verifying key of a circuit, stored as a DAG-CBOR block (e.g., in the
+/// verifying actor's state), and opened by the actor to verify proofs against it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SnarkVerifyingKey {
+    pub curve: SnarkCurve,
+    /// The key, in bellperson's (uncompressed) encoding for Groth16, or dusk-plonk's encoding of
+    /// the circuit's verifier for PLONK.
+    #[serde(with = "strict_bytes")]
+    pub key: Vec<u8>,
+}
+
+/// A Groth16 or PLONK proof to verify against the verifying key of a circuit.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SnarkVerifyInfo {
+    /// The proof, in bellperson's (compressed) encoding for Groth16, or dusk-plonk's for PLONK.
+    #[serde(with = "strict_bytes")]
+    pub proof: Vec<u8>,
+    /// The public inputs of the circuit, each [`SNARK_PUBLIC_INPUT_LEN`] bytes long. For PLONK,
+    /// these are the inputs as dusk-plonk's prover returns them (i.e., negated).
+    pub public_inputs: Vec<BytesDe>,
+}
@@ -7,10 +7,11 @@ use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 use num_bigint::BigInt;
 use num_integer::Integer;
-use num_traits::{Signed, Zero};
+use num_traits::{One, Signed, Zero};
 use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
 
-use crate::bigint::bigint_ser;
+use crate::bigint::{bigint_ser, MAX_BIGINT_SIZE};
 
 /// A quantity of native tokens.
 /// A token amount is an integer, but has a human interpretation as a value with
@@ -335,6 +336,173 @@ impl TokenAmount {
     }
 }
 
+/// An arithmetic operation on token amounts, as reported by [`TokenAmountError`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TokenOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl fmt::Display for TokenOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TokenOp::Add => "+",
+            TokenOp::Sub => "-",
+            TokenOp::Mul => "*",
+            TokenOp::Div => "/",
+        })
+    }
+}
+
+/// A failed checked operation on token amounts, with the operation and its operands. The operands
+/// are in attoFIL (the right-hand side of a multiplication or division is a plain factor).
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TokenAmountError {
+    /// The result is outside of the range of serializable token amounts (see
+    /// [`TokenAmount::max_value`]).
+    #[error("token amount overflow: {lhs} {op} {rhs}")]
+    Overflow {
+        op: TokenOp,
+        lhs: BigInt,
+        rhs: BigInt,
+    },
+    #[error("token amount division by zero: {lhs} / 0")]
+    DivisionByZero { lhs: BigInt },
+}
+
+/// An invalid attoFIL string.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid attoFIL amount {input:?}: {reason}")]
+pub struct ParseTokenAmountError {
+    pub input: String,
+    pub reason: &'static str,
+}
+
+// Checked operations fail, and saturating operations clamp, when the result isn't serializable.
+// Both match the semantics of the corresponding integer operations (and of `Gas` arithmetic in
+// the FVM), with [`TokenAmount::min_value`] and [`TokenAmount::max_value`] as the bounds.
+impl TokenAmount {
+    /// The largest serializable token amount.
+    pub fn max_value() -> TokenAmount {
+        // Serialized amounts are a sign byte followed by the big-endian magnitude.
+        let bits = (MAX_BIGINT_SIZE as u64 - 1) * 8;
+        TokenAmount::from_atto((BigInt::one() << bits) - 1)
+    }
+
+    /// The smallest (most negative) serializable token amount.
+    pub fn min_value() -> TokenAmount {
+        -TokenAmount::max_value()
+    }
+
+    fn in_range(atto: &BigInt) -> bool {
+        atto.bits() <= (MAX_BIGINT_SIZE as u64 - 1) * 8
+    }
+
+    fn check(
+        atto: BigInt,
+        op: TokenOp,
+        lhs: &BigInt,
+        rhs: &BigInt,
+    ) -> Result<TokenAmount, TokenAmountError> {
+        if !Self::in_range(&atto) {
+            return Err(TokenAmountError::Overflow {
+                op,
+                lhs: lhs.clone(),
+                rhs: rhs.clone(),
+            });
+        }
+        Ok(TokenAmount { atto })
+    }
+
+    fn saturate(atto: BigInt) -> TokenAmount {
+        let amount = TokenAmount { atto };
+        amount.clamp(TokenAmount::min_value(), TokenAmount::max_value())
+    }
+
+    pub fn checked_add(&self, other: &TokenAmount) -> Result<TokenAmount, TokenAmountError> {
+        Self::check(
+            &self.atto + &other.atto,
+            TokenOp::Add,
+            &self.atto,
+            &other.atto,
+        )
+    }
+
+    pub fn checked_sub(&self, other: &TokenAmount) -> Result<TokenAmount, TokenAmountError> {
+        Self::check(
+            &self.atto - &other.atto,
+            TokenOp::Sub,
+            &self.atto,
+            &other.atto,
+        )
+    }
+
+    pub fn checked_mul(&self, other: impl Into<BigInt>) -> Result<TokenAmount, TokenAmountError> {
+        let other = other.into();
+        Self::check(&self.atto * &other, TokenOp::Mul, &self.atto, &other)
+    }
+
+    /// Divides, rounding towards negative infinity (like [`TokenAmount::div_floor`]).
+    pub fn checked_div_floor(
+        &self,
+        other: impl Into<BigInt>,
+    ) -> Result<TokenAmount, TokenAmountError> {
+        let other = other.into();
+        if other.is_zero() {
+            return Err(TokenAmountError::DivisionByZero {
+                lhs: self.atto.clone(),
+            });
+        }
+        Self::check(
+            self.atto.div_floor(&other),
+            TokenOp::Div,
+            &self.atto,
+            &other,
+        )
+    }
+
+    pub fn saturating_add(&self, other: &TokenAmount) -> TokenAmount {
+        Self::saturate(&self.atto + &other.atto)
+    }
+
+    pub fn saturating_sub(&self, other: &TokenAmount) -> TokenAmount {
+        Self::saturate(&self.atto - &other.atto)
+    }
+
+    pub fn saturating_mul(&self, other: impl Into<BigInt>) -> TokenAmount {
+        Self::saturate(&self.atto * other.into())
+    }
+
+    /// Parses an integer amount of attoFIL (e.g., `"-1000"`), as used by Lotus' JSON APIs. Only the
+    /// canonical form produced by [`TokenAmount::to_atto_string`] is accepted: an optional minus
+    /// sign followed by decimal digits, with no leading zeros, and within the serializable range.
+    pub fn from_atto_str(s: &str) -> Result<TokenAmount, ParseTokenAmountError> {
+        let err = |reason| ParseTokenAmountError {
+            input: s.to_owned(),
+            reason,
+        };
+        let digits = s.strip_prefix('-').unwrap_or(s);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(err("expected an integer"));
+        }
+        if (digits.len() > 1 && digits.starts_with('0')) || s == "-0" {
+            return Err(err("non-canonical integer"));
+        }
+        let atto: BigInt = s.parse().map_err(|_| err("expected an integer"))?;
+        if !Self::in_range(&atto) {
+            return Err(err("out of range"));
+        }
+        Ok(TokenAmount { atto })
+    }
+
+    /// Formats the amount as an integer amount of attoFIL (e.g., `"-1000"`).
+    pub fn to_atto_string(&self) -> String {
+        self.atto.to_str_radix(10)
+    }
+}
+
 impl Sum for TokenAmount {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
         Self::from_atto(iter.map(|t| t.atto).sum::<BigInt>())
@@ -372,6 +540,7 @@ mod test {
     use num_bigint::BigInt;
     use num_traits::Zero;
 
+    use super::{TokenAmountError, TokenOp};
     use crate::TokenAmount;
 
     fn whole(x: impl Into<BigInt>) -> TokenAmount {
@@ -472,6 +641,70 @@ mod test {
         assert_eq!(a, b);
     }
 
+    #[test]
+    fn checked_ops() {
+        let max = TokenAmount::max_value();
+        assert_eq!(atto(3).checked_add(&atto(4)), Ok(atto(7)));
+        assert_eq!(atto(3).checked_sub(&atto(4)), Ok(atto(-1)));
+        assert_eq!(atto(3).checked_mul(4), Ok(atto(12)));
+        assert_eq!(atto(-7).checked_div_floor(2), Ok(atto(-4)));
+        assert_eq!(max.checked_add(&TokenAmount::zero()), Ok(max.clone()));
+
+        let err = max.checked_add(&atto(1)).unwrap_err();
+        assert_eq!(
+            err,
+            TokenAmountError::Overflow {
+                op: TokenOp::Add,
+                lhs: max.atto().clone(),
+                rhs: 1.into(),
+            }
+        );
+        assert!(err.to_string().ends_with(" + 1"));
+        assert!(TokenAmount::min_value().checked_sub(&atto(1)).is_err());
+        assert!(max.checked_mul(-2).is_err());
+        assert_eq!(
+            atto(1).checked_div_floor(0),
+            Err(TokenAmountError::DivisionByZero { lhs: 1.into() })
+        );
+
+        // The bounds are exactly the serializable range.
+        assert!(fvm_ipld_encoding::to_vec(&max).is_ok());
+        assert!(fvm_ipld_encoding::to_vec(&(&max + atto(1))).is_err());
+    }
+
+    #[test]
+    fn saturating_ops() {
+        let (max, min) = (TokenAmount::max_value(), TokenAmount::min_value());
+        assert_eq!(atto(3).saturating_add(&atto(4)), atto(7));
+        assert_eq!(max.saturating_add(&atto(1)), max);
+        assert_eq!(min.saturating_sub(&atto(1)), min);
+        assert_eq!(max.saturating_mul(-2), min);
+        assert_eq!(atto(3).saturating_sub(&atto(4)), atto(-1));
+    }
+
+    #[test]
+    fn atto_strings() {
+        for amount in [
+            atto(0),
+            atto(1),
+            atto(-1000),
+            whole(2_000_000_000),
+            TokenAmount::max_value(),
+            TokenAmount::min_value(),
+        ] {
+            let s = amount.to_atto_string();
+            assert_eq!(TokenAmount::from_atto_str(&s), Ok(amount));
+        }
+        for bad in ["", "-", "+1", "1.0", " 1", "1_000", "01", "-0", "0x10"] {
+            assert!(TokenAmount::from_atto_str(bad).is_err(), "{:?}", bad);
+        }
+        let too_big = (TokenAmount::max_value() + atto(1)).to_atto_string();
+        assert_eq!(
+            TokenAmount::from_atto_str(&too_big).unwrap_err().reason,
+            "out of range"
+        );
+    }
+
     #[test]
     fn test_sum() {
         assert_eq!(
@@ -33,6 +33,12 @@ impl ExitCode {
     pub fn is_system_error(self) -> bool {
         self.value < (Self::FIRST_USER_EXIT_CODE)
     }
+
+    /// Returns true if the exit code is in the range of exit codes left for individual actors to
+    /// define (i.e., not reserved for the VM or the standard actor calling convention).
+    pub fn is_actor_specific(self) -> bool {
+        self.value >= Self::FIRST_ACTOR_SPECIFIC_EXIT_CODE
+    }
 }
 
 impl From<u32> for ExitCode {
@@ -77,8 +83,10 @@ impl ExitCode {
     pub const SYS_ASSERTION_FAILED: ExitCode = ExitCode::new(10);
     /// The actor returned a block handle that doesn't exist
     pub const SYS_MISSING_RETURN: ExitCode = ExitCode::new(11);
-    // pub const SYS_RESERVED_12: ExitCode = ExitCode::new(12);
-    // pub const SYS_RESERVED_13: ExitCode = ExitCode::new(13);
+    /// The actor returned more data than the network's return size limit allows.
+    pub const SYS_RETURN_TOO_LARGE: ExitCode = ExitCode::new(12);
+    /// The message's parameters exceeded the network's params size limit.
+    pub const SYS_PARAMS_TOO_LARGE: ExitCode = ExitCode::new(13);
     // pub const SYS_RESERVED_14: ExitCode = ExitCode::new(14);
     // pub const SYS_RESERVED_15: ExitCode = ExitCode::new(15);
 
@@ -113,6 +121,11 @@ impl ExitCode {
     // pub const RESERVED_29: ExitCode = ExitCode::new(29);
     // pub const RESERVED_30: ExitCode = ExitCode::new(30);
     // pub const RESERVED_31: ExitCode = ExitCode::new(31);
+
+    /// The lowest exit code available for actor-specific errors. Codes between
+    /// [`FIRST_USER_EXIT_CODE`][Self::FIRST_USER_EXIT_CODE] and this value are reserved for the
+    /// standard calling convention above.
+    pub const FIRST_ACTOR_SPECIFIC_EXIT_CODE: u32 = 32;
 }
 
 /// When a syscall fails, it returns an `ErrorNumber` to indicate why. The syscalls themselves
@@ -155,6 +168,31 @@ pub enum ErrorNumber {
     ReadOnly = 13,
 }
 
+impl ErrorNumber {
+    /// All error numbers, in numerical order. Error numbers are part of the syscall ABI: existing
+    /// numbers must never be changed or reused, and new numbers must be appended.
+    pub const ALL: [ErrorNumber; 13] = [
+        ErrorNumber::IllegalArgument,
+        ErrorNumber::IllegalOperation,
+        ErrorNumber::LimitExceeded,
+        ErrorNumber::AssertionFailed,
+        ErrorNumber::InsufficientFunds,
+        ErrorNumber::NotFound,
+        ErrorNumber::InvalidHandle,
+        ErrorNumber::IllegalCid,
+        ErrorNumber::IllegalCodec,
+        ErrorNumber::Serialization,
+        ErrorNumber::Forbidden,
+        ErrorNumber::BufferTooSmall,
+        ErrorNumber::ReadOnly,
+    ];
+
+    /// Returns the error number's numerical value, as returned by syscalls.
+    pub fn value(self) -> u32 {
+        self as u32
+    }
+}
+
 impl std::fmt::Display for ErrorNumber {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use ErrorNumber::*;
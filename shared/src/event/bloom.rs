@@ -0,0 +1,229 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::borrow::Cow;
+use std::fmt;
+
+use fvm_ipld_encoding::{de, ser, strict_bytes};
+
+use super::{Flags, StampedEvent};
+use crate::ActorID;
+
+/// The size of an [`EventBloom`], in bytes.
+pub const EVENT_BLOOM_BYTES: usize = 256;
+
+/// The number of bits set in an [`EventBloom`] for each item.
+const EVENT_BLOOM_HASHES: usize = 3;
+
+/// A bloom filter over the emitters of a set of events, and the (emitter, key) pairs of their
+/// entries with indexed keys ([`Flags::FLAG_INDEXED_KEY`]). Filters can be checked before loading the events they summarize: a negative answer is
+/// definitive, while a positive answer may be a false positive.
+///
+/// Each item sets 3 of the filter's 2048 bits, chosen by the blake2b-256 hash of the item.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct EventBloom([u8; EVENT_BLOOM_BYTES]);
+
+impl EventBloom {
+    /// Creates an empty filter.
+    pub fn new() -> Self {
+        Self([0; EVENT_BLOOM_BYTES])
+    }
+
+    /// Creates a filter over the given events.
+    pub fn from_events<'a>(events: impl IntoIterator<Item = &'a StampedEvent>) -> Self {
+        let mut bloom = Self::new();
+        for event in events {
+            bloom.add_event(event);
+        }
+        bloom
+    }
+
+    /// Adds the event's emitter, and the emitter paired with each of the event's indexed entry
+    /// keys. Keys that aren't indexed aren't added.
+    pub fn add_event(&mut self, event: &StampedEvent) {
+        self.add(&emitter_item(event.emitter()));
+        for entry in &event.event().entries {
+            if entry.flags.contains(Flags::FLAG_INDEXED_KEY) {
+                self.add(&key_item(event.emitter(), &entry.key));
+            }
+        }
+    }
+
+    /// Returns false if no event in the filter was emitted by `emitter`.
+    pub fn may_contain_emitter(&self, emitter: ActorID) -> bool {
+        self.contains(&emitter_item(emitter))
+    }
+
+    /// Returns false if no event in the filter was emitted by `emitter` with an indexed entry keyed
+    /// `key`.
+    pub fn may_contain(&self, emitter: ActorID, key: &str) -> bool {
+        self.contains(&key_item(emitter, key))
+    }
+
+    /// Adds all the items in the other filter to this filter.
+    pub fn union(&mut self, other: &EventBloom) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a |= b;
+        }
+    }
+
+    /// Returns true if the filter contains no items.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+
+    /// Returns the raw filter bits.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn add(&mut self, item: &[u8]) {
+        for bit in bits(item) {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    fn contains(&self, item: &[u8]) -> bool {
+        bits(item)
+            .into_iter()
+            .all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+}
+
+impl Default for EventBloom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for EventBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let set: u32 = self.0.iter().map(|b| b.count_ones()).sum();
+        write!(f, "EventBloom({} bits set)", set)
+    }
+}
+
+impl ser::Serialize for EventBloom {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        strict_bytes::Serialize::serialize(&self.0[..], serializer)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for EventBloom {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let bytes: Cow<'de, [u8]> = strict_bytes::Deserialize::deserialize(deserializer)?;
+        let bits = bytes.as_ref().try_into().map_err(|_| {
+            de::Error::custom(format!(
+                "invalid event bloom length {}, must be {}",
+                bytes.len(),
+                EVENT_BLOOM_BYTES
+            ))
+        })?;
+        Ok(Self(bits))
+    }
+}
+
+fn emitter_item(emitter: ActorID) -> Vec<u8> {
+    let mut item = vec![0];
+    item.extend_from_slice(&emitter.to_be_bytes());
+    item
+}
+
+fn key_item(emitter: ActorID, key: &str) -> Vec<u8> {
+    let mut item = vec![1];
+    item.extend_from_slice(&emitter.to_be_bytes());
+    item.extend_from_slice(key.as_bytes());
+    item
+}
+
+/// Returns the bits set for the item: each is taken from 11 bits of a pair of hash bytes.
+fn bits(item: &[u8]) -> [usize; EVENT_BLOOM_HASHES] {
+    let hash = blake2b_simd::Params::new().hash_length(32).hash(item);
+    let hash = hash.as_bytes();
+    let mut bits = [0; EVENT_BLOOM_HASHES];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        let v = u16::from_be_bytes([hash[2 * i], hash[2 * i + 1]]) as usize;
+        *bit = v % (EVENT_BLOOM_BYTES * 8);
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::{from_slice, to_vec, RawBytes};
+
+    use super::*;
+    use crate::event::{ActorEvent, Entry};
+
+    fn event(emitter: ActorID, keys: &[&str]) -> StampedEvent {
+        event_with_flags(emitter, keys, Flags::FLAG_INDEXED_ALL)
+    }
+
+    fn event_with_flags(emitter: ActorID, keys: &[&str], flags: Flags) -> StampedEvent {
+        StampedEvent::new(
+            emitter,
+            ActorEvent::from(
+                keys.iter()
+                    .map(|k| Entry {
+                        flags,
+                        key: k.to_string(),
+                        value: RawBytes::default(),
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+        )
+    }
+
+    #[test]
+    fn membership() {
+        let bloom = EventBloom::from_events(&[event(100, &["t1", "t2"]), event(101, &["t1"])]);
+        assert!(!bloom.is_empty());
+        assert!(bloom.may_contain_emitter(100));
+        assert!(bloom.may_contain_emitter(101));
+        assert!(bloom.may_contain(100, "t1"));
+        assert!(bloom.may_contain(100, "t2"));
+        assert!(bloom.may_contain(101, "t1"));
+
+        // Keys are paired with their emitter. These could be false positives, but aren't.
+        assert!(!bloom.may_contain(101, "t2"));
+        assert!(!bloom.may_contain_emitter(102));
+        assert!(!EventBloom::new().may_contain(100, "t1"));
+    }
+
+    #[test]
+    fn unindexed_keys() {
+        let bloom = EventBloom::from_events(&[
+            event_with_flags(100, &["key"], Flags::FLAG_INDEXED_KEY),
+            event_with_flags(101, &["value"], Flags::FLAG_INDEXED_VALUE),
+            event_with_flags(102, &["none"], Flags::empty()),
+        ]);
+        assert!(bloom.may_contain(100, "key"));
+
+        // The emitters are always added, their keys only if indexed.
+        assert!(bloom.may_contain_emitter(101));
+        assert!(bloom.may_contain_emitter(102));
+        assert!(!bloom.may_contain(101, "value"));
+        assert!(!bloom.may_contain(102, "none"));
+    }
+
+    #[test]
+    fn union() {
+        let mut bloom = EventBloom::from_events(&[event(100, &["a"])]);
+        bloom.union(&EventBloom::from_events(&[event(200, &["b"])]));
+        assert!(bloom.may_contain(100, "a"));
+        assert!(bloom.may_contain(200, "b"));
+    }
+
+    #[test]
+    fn serialization() {
+        let bloom = EventBloom::from_events(&[event(100, &["a"])]);
+        let bytes = to_vec(&bloom).unwrap();
+        assert_eq!(from_slice::<EventBloom>(&bytes).unwrap(), bloom);
+        assert!(from_slice::<EventBloom>(&to_vec(&RawBytes::new(vec![0; 8])).unwrap()).is_err());
+    }
+}
@@ -7,6 +7,10 @@ use serde_tuple::*;
 
 use crate::ActorID;
 
+mod bloom;
+
+pub use bloom::{EventBloom, EVENT_BLOOM_BYTES};
+
 /// Event with extra information stamped by the FVM. This is the structure that gets committed
 /// on-chain via the receipt.
 #[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
@@ -21,6 +25,16 @@ impl StampedEvent {
     pub fn new(emitter: ActorID, event: ActorEvent) -> Self {
         Self { emitter, event }
     }
+
+    /// Returns the ID of the actor that emitted the event.
+    pub fn emitter(&self) -> ActorID {
+        self.emitter
+    }
+
+    /// Returns the event as emitted by the actor.
+    pub fn event(&self) -> &ActorEvent {
+        &self.event
+    }
 }
 
 /// An event as originally emitted by the actor.
@@ -30,6 +44,16 @@ pub struct ActorEvent {
     pub entries: Vec<Entry>,
 }
 
+impl ActorEvent {
+    /// Returns the size of the event's payload: the total length of its entries' keys and values.
+    pub fn payload_size(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|e| e.key.len() + e.value.len())
+            .sum()
+    }
+}
+
 impl From<Vec<Entry>> for ActorEvent {
     fn from(entries: Vec<Entry>) -> Self {
         Self { entries }
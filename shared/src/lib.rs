@@ -29,6 +29,7 @@ pub mod sector;
 pub mod smooth;
 pub mod state;
 pub mod sys;
+pub mod token;
 pub mod version;
 
 use econ::TokenAmount;
@@ -55,6 +56,9 @@ pub const MAX_CID_LEN: usize = 100;
 /// Identifier for Actors, includes builtin and initialized actors
 pub type ActorID = u64;
 
+/// The cron actor, invoked by the system actor at the end of every epoch.
+pub const CRON_ACTOR_ID: ActorID = 3;
+
 /// Default bit width for the hamt in the filecoin protocol.
 pub const HAMT_BIT_WIDTH: u32 = 5;
 /// Total gas limit allowed per block. This is shared across networks.
@@ -0,0 +1,260 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Signing rules for messages sent by delegated (`f410`) Ethereum accounts.
+//!
+//! Ethereum wallets can't sign Filecoin messages. Instead, a message sent by an `f410` account is
+//! signed as the equivalent EIP-1559 Ethereum transaction: the sender signs the keccak-256 hash of
+//! [`signing_payload`] with its secp256k1 key, and the signature is checked by recovering the
+//! public key and comparing the Ethereum address it derives to the sender's subaddress.
+//!
+//! Only messages with an Ethereum equivalent can be signed this way: plain value transfers, calls
+//! to `InvokeContract`, and contract creations through the Ethereum address manager's
+//! `CreateExternal` method.
+
+use anyhow::{anyhow, Context};
+use fvm_ipld_encoding::{from_slice, BytesDe};
+
+use super::Message;
+use crate::address::{Address, Payload};
+use crate::chainid::ChainID;
+use crate::econ::TokenAmount;
+use crate::{ActorID, MethodNum, METHOD_SEND};
+
+/// The ID of the Ethereum address manager, which is also the namespace of `f410` addresses.
+pub const EAM_ACTOR_ID: ActorID = 10;
+
+/// The method invoking an EVM contract (the FRC-0042 hash of `InvokeEVM`).
+pub const INVOKE_CONTRACT_METHOD: MethodNum = 3844450837;
+
+/// The Ethereum address manager method creating a contract on behalf of an Ethereum account.
+pub const CREATE_EXTERNAL_METHOD: MethodNum = 4;
+
+/// The EIP-2718 type of EIP-1559 transactions.
+const EIP_1559_TX_TYPE: u8 = 0x02;
+
+/// Returns true if messages from this address are signed by the rules of this module.
+pub fn is_eth_account(addr: &Address) -> bool {
+    matches!(addr.payload(), Payload::Delegated(da) if da.namespace() == EAM_ACTOR_ID)
+}
+
+/// Returns the Ethereum address corresponding to an address, if any: the embedded address of
+/// `f410` addresses, and the "masked" `0xff00..` address (followed by the big-endian ID) of ID
+/// addresses.
+pub fn eth_address(addr: &Address) -> Option<[u8; 20]> {
+    match addr.payload() {
+        Payload::ID(id) => {
+            let mut out = [0u8; 20];
+            out[0] = 0xff;
+            out[12..].copy_from_slice(&id.to_be_bytes());
+            Some(out)
+        }
+        Payload::Delegated(da) if da.namespace() == EAM_ACTOR_ID => da.subaddress().try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Returns the bytes an Ethereum account signs (after hashing them with keccak-256) to send the
+/// message on the chain with the given ID: the EIP-2718 envelope of the equivalent EIP-1559
+/// transaction, with an empty access list.
+pub fn signing_payload(msg: &Message, chain_id: ChainID) -> anyhow::Result<Vec<u8>> {
+    if msg.version != 0 {
+        return Err(anyhow!("unsupported message version {}", msg.version));
+    }
+    if !is_eth_account(&msg.from) {
+        return Err(anyhow!("sender {} isn't an Ethereum account", msg.from));
+    }
+
+    let (to, input) = if msg.to == Address::new_id(EAM_ACTOR_ID) {
+        if msg.method_num != CREATE_EXTERNAL_METHOD {
+            return Err(anyhow!(
+                "method {} of the Ethereum address manager can't be invoked by an Ethereum transaction",
+                msg.method_num
+            ));
+        }
+        (None, input(msg)?)
+    } else {
+        match msg.method_num {
+            INVOKE_CONTRACT_METHOD => {}
+            METHOD_SEND if msg.params.is_empty() => {}
+            method => {
+                return Err(anyhow!(
+                    "method {} can't be invoked by an Ethereum transaction",
+                    method
+                ))
+            }
+        }
+        let to = eth_address(&msg.to)
+            .ok_or_else(|| anyhow!("recipient {} has no Ethereum address", msg.to))?;
+        (Some(to), input(msg)?)
+    };
+
+    let gas_limit = u64::try_from(msg.gas_limit).context("negative gas limit")?;
+
+    let mut fields = Vec::new();
+    rlp_uint(&mut fields, &u64::from(chain_id).to_be_bytes());
+    rlp_uint(&mut fields, &msg.sequence.to_be_bytes());
+    rlp_uint(&mut fields, &token_amount_bytes(&msg.gas_premium)?);
+    rlp_uint(&mut fields, &token_amount_bytes(&msg.gas_fee_cap)?);
+    rlp_uint(&mut fields, &gas_limit.to_be_bytes());
+    rlp_bytes(&mut fields, to.as_ref().map_or(&[][..], |to| &to[..]));
+    rlp_uint(&mut fields, &token_amount_bytes(&msg.value)?);
+    rlp_bytes(&mut fields, &input);
+    // The (empty) access list.
+    rlp_header(&mut fields, 0xc0, 0);
+
+    let mut out = vec![EIP_1559_TX_TYPE];
+    rlp_header(&mut out, 0xc0, fields.len());
+    out.extend_from_slice(&fields);
+    Ok(out)
+}
+
+/// The transaction input: the message params are the CBOR encoding of the input bytes.
+fn input(msg: &Message) -> anyhow::Result<Vec<u8>> {
+    if msg.params.is_empty() {
+        return Ok(Vec::new());
+    }
+    let BytesDe(input) = from_slice(&msg.params).context("params aren't a CBOR byte string")?;
+    Ok(input)
+}
+
+fn token_amount_bytes(amount: &TokenAmount) -> anyhow::Result<Vec<u8>> {
+    if amount.is_negative() {
+        return Err(anyhow!("negative token amount {}", amount));
+    }
+    Ok(amount.atto().to_bytes_be().1)
+}
+
+/// Appends an RLP string or list header for a payload of `len` bytes.
+fn rlp_header(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len < 56 {
+        out.push(offset + len as u8);
+    } else {
+        let len = len.to_be_bytes();
+        let len = strip_zeros(&len);
+        out.push(offset + 55 + len.len() as u8);
+        out.extend_from_slice(len);
+    }
+}
+
+/// Appends an RLP string.
+fn rlp_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if let [b] = bytes {
+        if *b < 0x80 {
+            out.push(*b);
+            return;
+        }
+    }
+    rlp_header(out, 0x80, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+/// Appends a big-endian unsigned integer as an RLP string, without leading zeros.
+fn rlp_uint(out: &mut Vec<u8>, be: &[u8]) {
+    rlp_bytes(out, strip_zeros(be))
+}
+
+fn strip_zeros(be: &[u8]) -> &[u8] {
+    let start = be.iter().position(|&b| b != 0).unwrap_or(be.len());
+    &be[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::{BytesSer, RawBytes};
+
+    use super::*;
+
+    fn eth_message() -> Message {
+        Message {
+            version: 0,
+            from: Address::new_delegated(EAM_ACTOR_ID, &[0x11; 20]).unwrap(),
+            to: Address::new_delegated(EAM_ACTOR_ID, &[0x22; 20]).unwrap(),
+            sequence: 1,
+            value: TokenAmount::from_atto(1000),
+            method_num: INVOKE_CONTRACT_METHOD,
+            params: RawBytes::serialize(BytesSer(&[0xca, 0xfe])).unwrap(),
+            gas_limit: 100000,
+            gas_fee_cap: TokenAmount::from_atto(300),
+            gas_premium: TokenAmount::from_atto(200),
+        }
+    }
+
+    #[test]
+    fn payload() {
+        let payload = signing_payload(&eth_message(), ChainID::from(314)).unwrap();
+
+        let mut expected = vec![0x02, 0xe9];
+        expected.extend([0x82, 0x01, 0x3a]); // chain ID
+        expected.push(0x01); // nonce
+        expected.extend([0x81, 0xc8]); // priority fee
+        expected.extend([0x82, 0x01, 0x2c]); // fee cap
+        expected.extend([0x83, 0x01, 0x86, 0xa0]); // gas limit
+        expected.push(0x94); // to
+        expected.extend([0x22; 20]);
+        expected.extend([0x82, 0x03, 0xe8]); // value
+        expected.extend([0x82, 0xca, 0xfe]); // input
+        expected.push(0xc0); // access list
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    fn creation() {
+        let msg = Message {
+            to: Address::new_id(EAM_ACTOR_ID),
+            method_num: CREATE_EXTERNAL_METHOD,
+            ..eth_message()
+        };
+        let payload = signing_payload(&msg, ChainID::from(314)).unwrap();
+        // The recipient is empty.
+        assert_eq!(payload[1], 0xd5);
+        assert_eq!(payload[15], 0x80);
+
+        let msg = Message {
+            method_num: 2,
+            ..msg
+        };
+        assert!(signing_payload(&msg, ChainID::from(314)).is_err());
+    }
+
+    #[test]
+    fn unsupported() {
+        // Only Ethereum accounts sign Ethereum transactions.
+        let msg = Message {
+            from: Address::new_id(100),
+            ..eth_message()
+        };
+        assert!(signing_payload(&msg, ChainID::from(314)).is_err());
+
+        // Sends need empty params, other methods can't be invoked.
+        for method_num in [METHOD_SEND, 2] {
+            let msg = Message {
+                method_num,
+                ..eth_message()
+            };
+            assert!(signing_payload(&msg, ChainID::from(314)).is_err());
+        }
+
+        // Only ID and f410 recipients have Ethereum addresses.
+        let msg = Message {
+            to: Address::new_actor(b"actor"),
+            ..eth_message()
+        };
+        assert!(signing_payload(&msg, ChainID::from(314)).is_err());
+    }
+
+    #[test]
+    fn eth_addresses() {
+        let mut masked = [0u8; 20];
+        masked[0] = 0xff;
+        masked[19] = 100;
+        assert_eq!(eth_address(&Address::new_id(100)), Some(masked));
+        assert_eq!(
+            eth_address(&Address::new_delegated(EAM_ACTOR_ID, &[1; 20]).unwrap()),
+            Some([1; 20])
+        );
+        assert_eq!(
+            eth_address(&Address::new_delegated(EAM_ACTOR_ID + 1, &[1; 20]).unwrap()),
+            None
+        );
+    }
+}
@@ -3,13 +3,22 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use anyhow::anyhow;
+use cid::Cid;
 use fvm_ipld_encoding::de::{Deserialize, Deserializer};
 use fvm_ipld_encoding::ser::{Serialize, Serializer};
-use fvm_ipld_encoding::RawBytes;
+use fvm_ipld_encoding::{to_vec, RawBytes, DAG_CBOR};
+use multihash::{Code, MultihashDigest};
 
 use crate::address::Address;
+use crate::chainid::ChainID;
 use crate::econ::TokenAmount;
-use crate::MethodNum;
+use crate::{MethodNum, BLOCK_GAS_LIMIT, TOTAL_FILECOIN};
+
+pub mod eth;
+mod signed;
+pub mod validation;
+
+pub use signed::SignedMessage;
 
 /// Default Unsigned VM message type which includes all data needed for a state transition
 #[cfg_attr(feature = "testing", derive(Default))]
@@ -38,6 +47,56 @@ impl Message {
         }
         Ok(())
     }
+
+    /// Checks that the message is valid for inclusion in a block, as nodes do when accepting
+    /// messages into their message pools and validating blocks. This is stricter than
+    /// [`Message::check`], but doesn't check the gas limit against the message's on-chain size
+    /// (which depends on the network's price list).
+    pub fn check_for_inclusion(&self) -> anyhow::Result<()> {
+        if self.version != 0 {
+            return Err(anyhow!("Message version {} is not supported", self.version));
+        }
+        if self.value.is_negative() {
+            return Err(anyhow!("Message value cannot be negative"));
+        }
+        if self.value > *TOTAL_FILECOIN {
+            return Err(anyhow!("Message value cannot exceed the total FIL supply"));
+        }
+        if self.gas_fee_cap.is_negative() {
+            return Err(anyhow!("Message gas fee cap cannot be negative"));
+        }
+        if self.gas_premium.is_negative() {
+            return Err(anyhow!("Message gas premium cannot be negative"));
+        }
+        if self.gas_premium > self.gas_fee_cap {
+            return Err(anyhow!("Message gas premium cannot exceed the gas fee cap"));
+        }
+        if self.gas_limit > BLOCK_GAS_LIMIT {
+            return Err(anyhow!(
+                "Message gas limit cannot exceed the block gas limit"
+            ));
+        }
+        self.check()
+    }
+
+    /// Returns the CID of the message: the blake2b-256 hash of its DAG-CBOR encoding.
+    pub fn cid(&self) -> anyhow::Result<Cid> {
+        Ok(Cid::new_v1(
+            DAG_CBOR,
+            Code::Blake2b256.digest(&to_vec(self)?),
+        ))
+    }
+
+    /// Returns the bytes the sender signs to send the message on the chain with the given ID.
+    /// Ethereum accounts sign the equivalent Ethereum transaction (see [`eth::signing_payload`]),
+    /// other senders sign the bytes of the message's CID.
+    pub fn signing_payload(&self, chain_id: ChainID) -> anyhow::Result<Vec<u8>> {
+        if eth::is_eth_account(&self.from) {
+            eth::signing_payload(self, chain_id)
+        } else {
+            Ok(self.cid()?.to_bytes())
+        }
+    }
 }
 
 impl Serialize for Message {
@@ -0,0 +1,183 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use cid::Cid;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use multihash::{Code, MultihashDigest};
+
+use super::Message;
+use crate::crypto::signature::{Signature, SignatureType};
+
+/// A message and its sender's signature.
+#[derive(PartialEq, Clone, Debug, Hash, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SignedMessage {
+    pub message: Message,
+    pub signature: Signature,
+}
+
+impl SignedMessage {
+    /// Returns true if the message is BLS-signed.
+    pub fn is_bls(&self) -> bool {
+        self.signature.signature_type() == SignatureType::BLS
+    }
+
+    /// Returns the CID under which the message is included on chain. BLS signatures are aggregated
+    /// per block, so BLS-signed messages are included (and identified) by the unsigned message.
+    /// Other messages are identified by the signed message.
+    pub fn cid(&self) -> anyhow::Result<Cid> {
+        if self.is_bls() {
+            self.message.cid()
+        } else {
+            Ok(Cid::new_v1(
+                DAG_CBOR,
+                Code::Blake2b256.digest(&to_vec(self)?),
+            ))
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl SignedMessage {
+    /// Checks that the message was signed by its sender, for the chain with the given ID.
+    ///
+    /// Ethereum accounts sign with secp256k1 keys, over the keccak-256 hash of the message's
+    /// [signing payload](Message::signing_payload). Their signatures are secp256k1 signatures, and
+    /// are checked against the Ethereum address embedded in the sender's `f410` address.
+    pub fn verify(&self, chain_id: crate::chainid::ChainID) -> Result<(), String> {
+        use crate::crypto::signature::ops::recover_secp_public_key;
+        use crate::crypto::signature::SECP_SIG_LEN;
+        use crate::message::eth;
+
+        let payload = self
+            .message
+            .signing_payload(chain_id)
+            .map_err(|e| e.to_string())?;
+
+        let eth_address = match eth::eth_address(&self.message.from) {
+            Some(addr) if eth::is_eth_account(&self.message.from) => addr,
+            _ => return self.signature.verify(&payload, &self.message.from),
+        };
+
+        if self.signature.signature_type() != SignatureType::Secp256k1 {
+            return Err("Ethereum accounts must sign with secp256k1 signatures".to_owned());
+        }
+        let signature: &[u8; SECP_SIG_LEN] = self.signature.bytes().try_into().map_err(|_| {
+            format!(
+                "Invalid Secp256k1 signature length. Was {}, must be 65",
+                self.signature.bytes().len()
+            )
+        })?;
+
+        let hash = Code::Keccak256.digest(&payload);
+        let key = recover_secp_public_key(
+            hash.digest().try_into().expect("fixed array size"),
+            signature,
+        )
+        .map_err(|e| e.to_string())?;
+
+        // The Ethereum address is the last 20 bytes of the hash of the uncompressed key, without
+        // its prefix byte.
+        let key_hash = Code::Keccak256.digest(&key.serialize()[1..]);
+        if key_hash.digest()[12..] == eth_address {
+            Ok(())
+        } else {
+            Err("Ethereum account signature verification failed".to_owned())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use fvm_ipld_encoding::RawBytes;
+    use libsecp256k1::{sign, PublicKey, SecretKey};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::address::Address;
+    use crate::chainid::ChainID;
+    use crate::crypto::signature::SECP_SIG_LEN;
+    use crate::econ::TokenAmount;
+    use crate::message::eth::{EAM_ACTOR_ID, INVOKE_CONTRACT_METHOD};
+
+    fn message(from: Address) -> Message {
+        Message {
+            version: 0,
+            from,
+            to: Address::new_id(1000),
+            sequence: 3,
+            value: TokenAmount::from_atto(10),
+            method_num: INVOKE_CONTRACT_METHOD,
+            params: RawBytes::default(),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::from_atto(100),
+            gas_premium: TokenAmount::from_atto(10),
+        }
+    }
+
+    /// Signs the 32 byte hash with the key, returning a 65 byte recoverable signature.
+    fn sign_hash(key: &SecretKey, hash: &[u8]) -> Signature {
+        let (sig, recovery_id) = sign(&libsecp256k1::Message::parse_slice(hash).unwrap(), key);
+        let mut bytes = vec![0u8; SECP_SIG_LEN];
+        bytes[..64].copy_from_slice(&sig.serialize());
+        bytes[64] = recovery_id.serialize();
+        Signature::new_secp256k1(bytes)
+    }
+
+    #[test]
+    fn secp256k1() {
+        let key = SecretKey::random(&mut ChaCha8Rng::seed_from_u64(1));
+        let from = Address::new_secp256k1(&PublicKey::from_secret_key(&key).serialize()).unwrap();
+        let chain_id = ChainID::from(314);
+
+        let msg = message(from);
+        let payload = msg.signing_payload(chain_id).unwrap();
+        assert_eq!(payload, msg.cid().unwrap().to_bytes());
+
+        let hash = blake2b_simd::Params::new().hash_length(32).hash(&payload);
+        let signed = SignedMessage {
+            message: msg.clone(),
+            signature: sign_hash(&key, hash.as_bytes()),
+        };
+        signed.verify(chain_id).unwrap();
+        assert_ne!(signed.cid().unwrap(), msg.cid().unwrap());
+
+        // Signatures are over the whole message.
+        let tampered = SignedMessage {
+            message: Message { sequence: 4, ..msg },
+            ..signed
+        };
+        assert!(tampered.verify(chain_id).is_err());
+    }
+
+    #[test]
+    fn eth_account() {
+        let key = SecretKey::random(&mut ChaCha8Rng::seed_from_u64(2));
+        let pubkey = PublicKey::from_secret_key(&key).serialize();
+        let key_hash = Code::Keccak256.digest(&pubkey[1..]);
+        let from = Address::new_delegated(EAM_ACTOR_ID, &key_hash.digest()[12..]).unwrap();
+        let chain_id = ChainID::from(314);
+
+        let msg = message(from);
+        let hash = Code::Keccak256.digest(&msg.signing_payload(chain_id).unwrap());
+        let signed = SignedMessage {
+            message: msg,
+            signature: sign_hash(&key, hash.digest()),
+        };
+        signed.verify(chain_id).unwrap();
+
+        // The chain ID is part of the signed transaction.
+        assert!(signed.verify(ChainID::from(315)).is_err());
+    }
+
+    #[test]
+    fn bls_cid() {
+        let msg = message(Address::new_id(100));
+        let signed = SignedMessage {
+            message: msg.clone(),
+            signature: Signature::new_bls(vec![0; 96]),
+        };
+        assert_eq!(signed.cid().unwrap(), msg.cid().unwrap());
+    }
+}
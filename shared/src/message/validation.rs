@@ -0,0 +1,42 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Validation of messages sent by abstract accounts.
+//!
+//! When account abstraction is enabled, any actor exporting [`VALIDATE_MESSAGE_METHOD`] may send
+//! messages. Before executing such a message, the FVM invokes the method on the sender with
+//! [`ValidateParams`], in a read-only context with a limited gas budget, and a limit on the blocks
+//! it may read from the state. The sender authorizes the message by exiting successfully,
+//! typically after checking some proof of authorization carried by the message itself, and may
+//! designate a sponsor paying for the message's gas in its [`ValidateReturn`]. The sponsor must in
+//! turn agree to pay by validating the message with [`ValidateParams::sponsor`] set.
+//!
+//! The gas used by the validation phase is charged to the message.
+
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+
+use super::Message;
+use crate::address::Address;
+use crate::MethodNum;
+
+/// The method an abstract account exports to validate the messages it sends (the FRC-0042 hash of
+/// `ValidateMessage`).
+pub const VALIDATE_MESSAGE_METHOD: MethodNum = 500739230;
+
+/// The parameters of [`VALIDATE_MESSAGE_METHOD`].
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct ValidateParams {
+    /// The message to validate.
+    pub message: Message,
+    /// Whether the actor is asked to pay for the message's gas on behalf of its sender, rather
+    /// than to authorize a message it sent.
+    pub sponsor: bool,
+}
+
+/// The return value of [`VALIDATE_MESSAGE_METHOD`]. An empty return value is equivalent to the
+/// default (the sender pays for gas).
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug, Default)]
+pub struct ValidateReturn {
+    /// The actor paying for the message's gas, if not the sender. Ignored when validating as a
+    /// sponsor.
+    pub sponsor: Option<Address>,
+}
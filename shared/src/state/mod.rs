@@ -7,6 +7,10 @@ use fvm_ipld_encoding::repr::*;
 use fvm_ipld_encoding::tuple::*;
 use serde::{Deserialize, Serialize};
 
+use crate::deal::DealID;
+use crate::sector::SectorNumber;
+use crate::ActorID;
+
 /// Specifies the version of the state tree
 #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Serialize_repr, Deserialize_repr)]
 #[repr(u64)]
@@ -43,3 +47,89 @@ pub struct StateRoot {
 #[derive(Default, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct StateInfo0([(); 0]);
+
+/// A typed lookup of domain state that the node holds outside of the state tree (e.g., sector or
+/// deal info), answered by the node's `StateReader` extern. Queries cross the syscall boundary as
+/// a `(kind, key)` pair, see [`StateQuery::encode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateQuery {
+    /// On-chain info for a storage provider's sector.
+    SectorInfo {
+        miner: ActorID,
+        sector: SectorNumber,
+    },
+    /// The state of a storage deal.
+    DealState { deal: DealID },
+    /// A lookup in an embedder-defined namespace, which must be at least
+    /// [`StateQuery::MIN_CUSTOM_KIND`].
+    Custom { namespace: u64, key: Vec<u8> },
+}
+
+impl StateQuery {
+    /// The query kind of [`StateQuery::SectorInfo`].
+    pub const SECTOR_INFO: u64 = 1;
+    /// The query kind of [`StateQuery::DealState`].
+    pub const DEAL_STATE: u64 = 2;
+    /// The smallest query kind available to embedders. Smaller kinds are reserved.
+    pub const MIN_CUSTOM_KIND: u64 = 1 << 32;
+
+    /// Encodes the query as a `(kind, key)` pair. Keys of typed queries are DAG-CBOR tuples of
+    /// their fields.
+    pub fn encode(&self) -> Result<(u64, Vec<u8>), fvm_ipld_encoding::Error> {
+        Ok(match self {
+            StateQuery::SectorInfo { miner, sector } => (
+                Self::SECTOR_INFO,
+                fvm_ipld_encoding::to_vec(&(miner, sector))?,
+            ),
+            StateQuery::DealState { deal } => {
+                (Self::DEAL_STATE, fvm_ipld_encoding::to_vec(&(deal,))?)
+            }
+            StateQuery::Custom { namespace, key } => (*namespace, key.clone()),
+        })
+    }
+
+    /// Decodes a query from a `(kind, key)` pair. Returns `None` if the kind is reserved but
+    /// unknown, or the key is malformed.
+    pub fn decode(kind: u64, key: &[u8]) -> Option<Self> {
+        match kind {
+            Self::SECTOR_INFO => fvm_ipld_encoding::from_slice(key)
+                .ok()
+                .map(|(miner, sector)| StateQuery::SectorInfo { miner, sector }),
+            Self::DEAL_STATE => fvm_ipld_encoding::from_slice(key)
+                .ok()
+                .map(|(deal,)| StateQuery::DealState { deal }),
+            Self::MIN_CUSTOM_KIND.. => Some(StateQuery::Custom {
+                namespace: kind,
+                key: key.to_vec(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateQuery;
+
+    #[test]
+    fn state_query_roundtrip() {
+        for query in [
+            StateQuery::SectorInfo {
+                miner: 1000,
+                sector: 42,
+            },
+            StateQuery::DealState { deal: 7 },
+            StateQuery::Custom {
+                namespace: StateQuery::MIN_CUSTOM_KIND + 1,
+                key: b"key".to_vec(),
+            },
+        ] {
+            let (kind, key) = query.encode().unwrap();
+            assert_eq!(StateQuery::decode(kind, &key), Some(query));
+        }
+
+        // Reserved kinds and malformed keys are rejected.
+        assert_eq!(StateQuery::decode(3, &[]), None);
+        assert_eq!(StateQuery::decode(StateQuery::DEAL_STATE, &[0xff]), None);
+    }
+}
@@ -67,6 +67,23 @@ impl SendFlags {
     }
 }
 
+bitflags! {
+    /// Options passed to the `sync::set_flag` syscall.
+    #[derive(Default)]
+    #[repr(transparent)]
+    pub struct FlagOptions: u64 {
+        /// Keep the flag set until the end of the message, instead of clearing it when the
+        /// invocation that set it returns.
+        const MESSAGE_SCOPE = 0b00000001;
+    }
+}
+
+impl FlagOptions {
+    pub fn message_scope(self) -> bool {
+        self.intersects(Self::MESSAGE_SCOPE)
+    }
+}
+
 /// An unsafe trait to mark "syscall safe" types. These types must be safe to memcpy to and from
 /// WASM. This means:
 ///
@@ -95,9 +112,13 @@ assert_syscall_safe! {
     TokenAmount,
     out::ipld::IpldOpen,
     out::ipld::IpldStat,
+    out::ipld::IpldGraphStat,
+    out::actor::CodeInfo,
     out::send::Send,
+    out::token::TransferHook,
     out::crypto::VerifyConsensusFault,
     out::network::NetworkContext,
+    out::gas::GasPrices,
     out::vm::MessageContext,
 }
 
@@ -31,6 +31,31 @@ pub mod ipld {
         pub codec: u64,
         pub size: u32,
     }
+
+    #[derive(Debug, Copy, Clone)]
+    #[repr(packed, C)]
+    pub struct IpldGraphStat {
+        /// The total size of the blocks visited, in bytes.
+        pub size: u64,
+        /// The number of blocks visited.
+        pub blocks: u64,
+        /// The depth of the deepest block visited (0 for the root alone).
+        pub depth: u32,
+        /// 1 if the whole graph was visited, 0 if the traversal stopped at the block budget or
+        /// skipped links to missing blocks.
+        pub complete: u32,
+    }
+}
+
+pub mod actor {
+    #[derive(Debug, Copy, Clone)]
+    #[repr(packed, C)]
+    pub struct CodeInfo {
+        /// The size of the code, in bytes.
+        pub size: u64,
+        /// The FVM ABI version the code targets, or 0 if it's not an actor.
+        pub abi_version: u32,
+    }
 }
 
 pub mod send {
@@ -46,6 +71,24 @@ pub mod send {
     }
 }
 
+pub mod token {
+    use crate::sys::BlockId;
+    use crate::ActorID;
+
+    #[derive(Debug, Copy, Clone)]
+    #[repr(packed, C)]
+    pub struct TransferHook {
+        /// The ID of the owner of the tokens.
+        pub from: ActorID,
+        /// The ID of the recipient of the tokens.
+        pub to: ActorID,
+        pub return_codec: u64,
+        /// The block ID of the value returned by the recipient's receiver hook.
+        pub return_id: BlockId,
+        pub return_size: u32,
+    }
+}
+
 pub mod crypto {
     use crate::{ActorID, ChainEpoch};
 
@@ -103,6 +146,34 @@ pub mod vm {
     }
 }
 
+pub mod gas {
+    /// A selection of gas prices for the current network version, in milligas.
+    #[derive(Debug, Copy, Clone)]
+    #[repr(packed, C)]
+    pub struct GasPrices {
+        /// The base cost of any send.
+        pub send_base: i64,
+        /// The additional cost of a send that transfers a non-zero value.
+        pub send_transfer_funds: i64,
+        /// The additional cost of a send that _only_ transfers funds (method 0).
+        pub send_transfer_only_premium: i64,
+        /// The additional cost of a send that invokes a method (may be negative).
+        pub send_invoke_method: i64,
+        /// The base cost of any syscall.
+        pub syscall_base: i64,
+        /// The flat cost of persisting a block (storage and compute).
+        pub block_persist_base: i64,
+        /// The cost of storing each byte of a persisted block.
+        pub storage_per_byte: i64,
+        /// The flat cost of including a message on-chain.
+        pub on_chain_message_base: i64,
+        /// The cost of including each byte of a message on-chain.
+        pub on_chain_message_per_byte: i64,
+        /// The cost of creating a new actor (compute and storage).
+        pub create_actor: i64,
+    }
+}
+
 pub mod network {
     use crate::clock::ChainEpoch;
     use crate::sys::TokenAmount;
@@ -0,0 +1,102 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Types for [FRC-46](https://github.com/filecoin-project/FIPs/blob/master/FRCs/frc-0046.md)
+//! fungible token transfers, and the FRC-46 receiver hooks invoked by the `token::transfer_hook`
+//! syscall.
+
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::RawBytes;
+
+use crate::address::Address;
+use crate::econ::TokenAmount;
+use crate::{ActorID, MethodNum};
+
+/// The method number of the universal receiver hook (the FRC-42 hash of `Receive`).
+pub const RECEIVER_HOOK_METHOD_NUM: MethodNum = 3726118371;
+
+/// The receiver type of FRC-46 token transfers (the FRC-42 hash of `FRC46`), telling receiver
+/// hooks how to interpret their payload.
+pub const FRC46_TOKEN_TYPE: u32 = 2233613279;
+
+/// The gas limit of receiver hooks, unless the token actor specifies a lower one.
+pub const MAX_RECEIVER_HOOK_GAS: u64 = 30_000_000;
+
+/// The parameters of the universal receiver hook.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct UniversalReceiverParams {
+    /// The type of the payload, e.g., [`FRC46_TOKEN_TYPE`].
+    pub type_: u32,
+    /// The payload, e.g., a CBOR-encoded [`FRC46TokenReceived`].
+    pub payload: RawBytes,
+}
+
+/// The payload passed to the receiver hook of the recipient of FRC-46 tokens.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct FRC46TokenReceived {
+    /// The owner of the tokens.
+    pub from: ActorID,
+    /// The recipient of the tokens.
+    pub to: ActorID,
+    /// The actor that initiated the transfer: the owner, or an operator it approved.
+    pub operator: ActorID,
+    /// The amount of tokens transferred.
+    pub amount: TokenAmount,
+    /// Data provided by the operator.
+    pub operator_data: RawBytes,
+    /// Data provided by the token actor.
+    pub token_data: RawBytes,
+}
+
+/// A token transfer made by a token actor, as passed to the `token::transfer_hook` syscall. The
+/// operator is the token actor's caller: the token actor must have checked (and spent) the
+/// operator's allowance if the operator isn't the owner.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct TokenTransfer {
+    /// The owner of the tokens.
+    pub from: Address,
+    /// The recipient of the tokens.
+    pub to: Address,
+    /// The amount of tokens to transfer.
+    pub amount: TokenAmount,
+    /// Data provided by the operator, passed to the receiver hook.
+    pub operator_data: RawBytes,
+    /// Data provided by the token actor, passed to the receiver hook.
+    pub token_data: RawBytes,
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_ipld_encoding::{from_slice, to_vec};
+
+    use super::*;
+
+    /// Computes the FRC-42 hash of a name.
+    fn frc42(name: &str) -> u32 {
+        (1..)
+            .map(|i| {
+                let digest = blake2b_simd::blake2b(format!("{i}|{name}").as_bytes());
+                u32::from_be_bytes(digest.as_bytes()[..4].try_into().unwrap())
+            })
+            .find(|&n| n >= 1 << 24)
+            .unwrap()
+    }
+
+    #[test]
+    fn frc42_constants() {
+        assert_eq!(frc42("Receive") as MethodNum, RECEIVER_HOOK_METHOD_NUM);
+        assert_eq!(frc42("FRC46"), FRC46_TOKEN_TYPE);
+    }
+
+    #[test]
+    fn transfer_roundtrip() {
+        let transfer = TokenTransfer {
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            amount: TokenAmount::from_whole(5),
+            operator_data: RawBytes::new(vec![1, 2, 3]),
+            token_data: RawBytes::default(),
+        };
+        let encoded = to_vec(&transfer).unwrap();
+        assert_eq!(from_slice::<TokenTransfer>(&encoded).unwrap(), transfer);
+    }
+}
@@ -7,7 +7,7 @@ use std::fmt::Display;
 use fvm_ipld_encoding::repr::Serialize_repr;
 
 /// Specifies the network version
-#[derive(Debug, Eq, PartialEq, Clone, Copy, Ord, PartialOrd, Serialize_repr)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Ord, PartialOrd, Serialize_repr)]
 #[repr(u32)]
 #[non_exhaustive]
 pub enum NetworkVersion {
@@ -49,6 +49,8 @@ pub enum NetworkVersion {
     V17,
     /// Hygge (builtin-actors v10)
     V18,
+    /// Lightning (builtin-actors v11)
+    V19,
 }
 
 impl Display for NetworkVersion {
@@ -82,6 +84,7 @@ impl TryFrom<u32> for NetworkVersion {
             16 => Ok(V16),
             17 => Ok(V17),
             18 => Ok(V18),
+            19 => Ok(V19),
             _ => Err(value),
         }
     }
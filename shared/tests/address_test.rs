@@ -8,7 +8,8 @@ use std::str::FromStr;
 use data_encoding::{DecodeError, DecodeKind};
 use fvm_ipld_encoding::{from_slice, to_vec};
 use fvm_shared::address::{
-    Address, Error, Protocol, BLS_PUB_LEN, MAX_SUBADDRESS_LEN, PAYLOAD_HASH_LEN, SECP_PUB_LEN,
+    validate_address_bytes, Address, Error, Protocol, BLS_PUB_LEN, MAX_ADDRESS_LEN,
+    MAX_SUBADDRESS_LEN, PAYLOAD_HASH_LEN, SECP_PUB_LEN,
 };
 
 #[test]
@@ -451,6 +452,48 @@ fn invalid_byte_addresses() {
     }
 }
 
+#[test]
+fn address_bytes_validation() {
+    let longest = Address::new_delegated(u64::MAX, &[0xff; MAX_SUBADDRESS_LEN]).unwrap();
+    assert_eq!(longest.to_bytes().len(), MAX_ADDRESS_LEN);
+    for addr in [
+        Address::new_id(u64::MAX),
+        Address::new_secp256k1(&[0; SECP_PUB_LEN]).unwrap(),
+        Address::new_actor(b"actor"),
+        Address::new_bls(&[0; BLS_PUB_LEN]).unwrap(),
+        longest,
+    ] {
+        assert_eq!(
+            validate_address_bytes(&addr.to_bytes()),
+            Ok(addr.protocol())
+        );
+    }
+
+    let invalid = [
+        (vec![], Error::InvalidLength),
+        (vec![0; MAX_ADDRESS_LEN + 1], Error::InvalidLength),
+        (vec![5, 0], Error::UnknownProtocol),
+        (vec![0xff; MAX_ADDRESS_LEN], Error::UnknownProtocol),
+        (vec![0; 12], Error::InvalidPayloadLength(11)),
+        (vec![1; PAYLOAD_HASH_LEN], Error::InvalidPayloadLength(19)),
+        (vec![3; 2], Error::InvalidPayloadLength(1)),
+    ];
+    for (input, expected) in invalid {
+        assert_eq!(validate_address_bytes(&input).unwrap_err(), expected);
+        assert_eq!(Address::from_bytes(&input).unwrap_err(), expected);
+    }
+
+    // Integers must be minimally encoded, so that each address has a single encoding.
+    assert_eq!(
+        Address::from_bytes(&[0, 0x81, 0x00]),
+        Err(Error::InvalidPayload)
+    );
+    assert_eq!(
+        Address::from_bytes(&[4, 0x8a, 0x00, 1]),
+        Err(Error::InvalidPayload)
+    );
+}
+
 #[test]
 fn cbor_encoding() {
     struct StringAddrVec<'a> {
@@ -0,0 +1,46 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::HashSet;
+
+use fvm_shared::error::ErrorNumber;
+use num_traits::FromPrimitive;
+
+#[test]
+fn error_numbers_are_stable() {
+    // Error numbers are part of the syscall ABI. If this test fails, a change broke the ABI.
+    let expected = [
+        (ErrorNumber::IllegalArgument, 1),
+        (ErrorNumber::IllegalOperation, 2),
+        (ErrorNumber::LimitExceeded, 3),
+        (ErrorNumber::AssertionFailed, 4),
+        (ErrorNumber::InsufficientFunds, 5),
+        (ErrorNumber::NotFound, 6),
+        (ErrorNumber::InvalidHandle, 7),
+        (ErrorNumber::IllegalCid, 8),
+        (ErrorNumber::IllegalCodec, 9),
+        (ErrorNumber::Serialization, 10),
+        (ErrorNumber::Forbidden, 11),
+        (ErrorNumber::BufferTooSmall, 12),
+        (ErrorNumber::ReadOnly, 13),
+    ];
+    assert_eq!(ErrorNumber::ALL.len(), expected.len());
+    for (number, (expected, value)) in ErrorNumber::ALL.into_iter().zip(expected) {
+        assert_eq!(number, expected);
+        assert_eq!(number.value(), value);
+    }
+}
+
+#[test]
+fn error_numbers_round_trip() {
+    let mut messages = HashSet::new();
+    for number in ErrorNumber::ALL {
+        assert_eq!(ErrorNumber::from_u32(number.value()), Some(number));
+        assert!(messages.insert(number.to_string()), "duplicate message");
+    }
+    assert_eq!(ErrorNumber::from_u32(0), None);
+    assert_eq!(
+        ErrorNumber::from_u32(ErrorNumber::ALL.len() as u32 + 1),
+        None
+    );
+}
@@ -2,15 +2,17 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 use anyhow::{anyhow, Result};
 use cid::multihash::Code;
+use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::DAG_CBOR;
 use fvm_sdk::message::params_raw;
 use fvm_sdk::vm::abort;
 use fvm_shared::address::{Address, Protocol};
 use fvm_shared::crypto::hash::SupportedHashes;
 use fvm_shared::crypto::signature::{Signature, SignatureType, SECP_SIG_LEN};
+use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, Zero};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
@@ -30,6 +32,12 @@ pub enum Method {
     OnVerifySignature,
     /// Try (and fail) to recovery a public key from a signature, using random data.
     OnRecoverSecpPublicKey,
+    /// Make many cheap syscalls to measure `OnHostCall`.
+    OnHostCall,
+    /// Repeatedly call `Recurse` on this actor to measure `OnTrap`.
+    OnTrap,
+    /// Recurse to some depth, then trap or return.
+    Recurse,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -71,6 +79,25 @@ pub struct OnRecoverSecpPublicKeyParams {
     pub seed: u64,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct OnHostCallParams {
+    pub iterations: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OnTrapParams {
+    pub iterations: usize,
+    pub depth: usize,
+    /// Whether the calls should trap, or return normally (as a baseline).
+    pub abort: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecurseParams {
+    pub depth: usize,
+    pub abort: bool,
+}
+
 impl OnHashingParams {
     pub fn hasher(&self) -> Option<SupportedHashes> {
         match self.hasher {
@@ -112,6 +139,9 @@ fn dispatch(method: Method, params_ptr: u32) -> Result<()> {
         Method::OnBlock => dispatch_to(on_block, params_ptr),
         Method::OnVerifySignature => dispatch_to(on_verify_signature, params_ptr),
         Method::OnRecoverSecpPublicKey => dispatch_to(on_recover_secp_public_key, params_ptr),
+        Method::OnHostCall => dispatch_to(on_host_call, params_ptr),
+        Method::OnTrap => dispatch_to(on_trap, params_ptr),
+        Method::Recurse => dispatch_to(recurse, params_ptr),
     }
 }
 
@@ -196,6 +226,57 @@ fn on_recover_secp_public_key(p: OnRecoverSecpPublicKeyParams) -> Result<()> {
     Ok(())
 }
 
+fn on_host_call(p: OnHostCallParams) -> Result<()> {
+    // About the cheapest syscall there is, so the time is dominated by the transition.
+    for _ in 0..p.iterations {
+        fvm_sdk::gas::available();
+    }
+    Ok(())
+}
+
+fn on_trap(p: OnTrapParams) -> Result<()> {
+    let this = Address::new_id(fvm_sdk::message::receiver());
+    let params = IpldBlock::serialize_cbor(&RecurseParams {
+        depth: p.depth,
+        abort: p.abort,
+    })?;
+
+    for _ in 0..p.iterations {
+        let ret = fvm_sdk::send::send(
+            &this,
+            Method::Recurse as u64,
+            params.clone(),
+            TokenAmount::zero(),
+            None,
+            Default::default(),
+        )?;
+        if ret.exit_code.is_success() == p.abort {
+            return Err(anyhow!("unexpected exit code: {}", ret.exit_code));
+        }
+    }
+
+    Ok(())
+}
+
+fn recurse(p: RecurseParams) -> Result<()> {
+    /// Recurses without being turned into a loop, so that there are frames to unwind.
+    #[inline(never)]
+    fn go(depth: usize, abort: bool) -> u64 {
+        if depth == 0 {
+            if abort {
+                // Trap (`unreachable`) rather than exiting, as only traps are charged `OnTrap`.
+                std::process::abort()
+            }
+            return 0;
+        }
+        go(depth - 1, abort)
+            .wrapping_mul(31)
+            .wrapping_add(depth as u64)
+    }
+    go(p.depth, p.abort);
+    Ok(())
+}
+
 fn random_bytes(size: usize, seed: u64) -> Vec<u8> {
     lcg8(seed).take(size).collect()
 }
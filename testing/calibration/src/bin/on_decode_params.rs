@@ -0,0 +1,75 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Syscall parameters are decoded on the host, so we time decoding directly, for the densest
+//! parameters the syscalls accept (piece infos, and nested arrays of small integers). The slope of
+//! the regression is the time per byte, which should be covered by `OnDecodeParams`.
+#![feature(slice_group_by)]
+
+use std::time::Instant;
+
+use cid::Cid;
+use fvm::gas::price_list_by_network_version;
+use fvm_gas_calibration::*;
+use fvm_ipld_encoding::{from_slice, to_vec};
+use fvm_shared::piece::{PaddedPieceSize, PieceInfo};
+use fvm_shared::version::NetworkVersion;
+use serde::de::DeserializeOwned;
+
+const CHARGE_NAME: &str = "OnDecodeParams";
+
+/// Times decoding `params`, returning the observation.
+fn observe<T: DeserializeOwned>(label: &str, count: usize, params: Vec<u8>) -> Obs {
+    let price_list = price_list_by_network_version(NetworkVersion::V19);
+
+    let start = Instant::now();
+    let _: T = from_slice(&params).unwrap();
+    let elapsed = start.elapsed();
+
+    Obs {
+        label: label.into(),
+        elapsed_nanos: elapsed.as_nanos(),
+        variables: vec![params.len(), count],
+        compute_gas: price_list
+            .on_decode_params(params.len())
+            .compute_gas
+            .as_milligas(),
+    }
+}
+
+fn main() {
+    let rounds = 10;
+    let counts = (0..=10).map(|i| i * 1000).collect::<Vec<_>>();
+    let cid = Cid::default();
+
+    let mut obs = Vec::new();
+    for label in ["pieces", "arrays"] {
+        let mut label_obs = Vec::new();
+        for _ in 0..rounds {
+            for count in counts.iter().copied() {
+                label_obs.push(match label {
+                    "pieces" => {
+                        let pieces = (0..count)
+                            .map(|i| PieceInfo {
+                                size: PaddedPieceSize(i as u64),
+                                cid,
+                            })
+                            .collect::<Vec<_>>();
+                        observe::<Vec<PieceInfo>>(label, count, to_vec(&pieces).unwrap())
+                    }
+                    _ => {
+                        let arrays = (0..count).map(|i| vec![i as u8]).collect::<Vec<_>>();
+                        observe::<Vec<Vec<u8>>>(label, count, to_vec(&arrays).unwrap())
+                    }
+                });
+            }
+        }
+        obs.extend(eliminate_outliers(label_obs, 0.02, Eliminate::Top));
+    }
+
+    let regs = obs
+        .group_by(|a, b| a.label == b.label)
+        .map(|g| least_squares(g[0].label.to_owned(), g, 0))
+        .collect::<Vec<_>>();
+
+    export(CHARGE_NAME, &obs, &regs).unwrap();
+}
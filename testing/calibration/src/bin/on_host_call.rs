@@ -0,0 +1,44 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Host call transitions aren't timed individually, so we time whole messages making increasing
+//! numbers of cheap syscalls instead. The slope of the regression is the time per syscall, which
+//! should be covered by `OnHostCall` and `OnSyscall` together.
+use std::time::Instant;
+
+use fil_gas_calibration_actor::{Method, OnHostCallParams};
+use fvm_gas_calibration::*;
+
+const CHARGE_NAME: &str = "OnHostCall";
+const METHOD: Method = Method::OnHostCall;
+
+fn main() {
+    let rounds = 10;
+    let counts = (0..=10).map(|i| i * 1000).collect::<Vec<_>>();
+
+    let mut te = instantiate_tester();
+    let mut obs = Vec::new();
+
+    for _ in 0..rounds {
+        for iterations in counts.iter() {
+            let params = OnHostCallParams {
+                iterations: *iterations,
+            };
+
+            let start = Instant::now();
+            let ret = te.execute_or_die(METHOD as u64, &params);
+            let elapsed = start.elapsed();
+
+            obs.push(Obs {
+                label: "gas::available".into(),
+                elapsed_nanos: elapsed.as_nanos(),
+                variables: vec![*iterations],
+                compute_gas: charged_gas(&ret, CHARGE_NAME),
+            });
+        }
+    }
+
+    let obs = eliminate_outliers(obs, 0.02, Eliminate::Top);
+    let regs = vec![least_squares("gas::available".into(), &obs, 0)];
+
+    export(CHARGE_NAME, &obs, &regs).unwrap();
+}
@@ -0,0 +1,59 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Traps can't be timed from the inside, so we time whole messages calling into the actor
+//! repeatedly, once with calls that trap at the bottom of a recursion and once with calls that
+//! return normally. The difference between the slopes of the two regressions is the time it takes
+//! to trap and unwind, which should be covered by `OnTrap`.
+#![feature(slice_group_by)]
+
+use std::time::Instant;
+
+use fil_gas_calibration_actor::{Method, OnTrapParams};
+use fvm_gas_calibration::*;
+
+const CHARGE_NAME: &str = "OnTrap";
+const METHOD: Method = Method::OnTrap;
+
+fn main() {
+    let rounds = 10;
+    let depths = [1, 10, 100, 1000];
+    let counts = (0..=10).map(|i| i * 10).collect::<Vec<_>>();
+
+    let mut te = instantiate_tester();
+    let mut obs = Vec::new();
+
+    for abort in [true, false] {
+        for depth in depths {
+            let label = format!("{}-{depth}", if abort { "trap" } else { "return" });
+            let mut label_obs = Vec::new();
+            for _ in 0..rounds {
+                for iterations in counts.iter() {
+                    let params = OnTrapParams {
+                        iterations: *iterations,
+                        depth,
+                        abort,
+                    };
+
+                    let start = Instant::now();
+                    let ret = te.execute_or_die(METHOD as u64, &params);
+                    let elapsed = start.elapsed();
+
+                    label_obs.push(Obs {
+                        label: label.clone(),
+                        elapsed_nanos: elapsed.as_nanos(),
+                        variables: vec![*iterations],
+                        compute_gas: charged_gas(&ret, CHARGE_NAME),
+                    });
+                }
+            }
+            obs.extend(eliminate_outliers(label_obs, 0.02, Eliminate::Top));
+        }
+    }
+
+    let regs = obs
+        .group_by(|a, b| a.label == b.label)
+        .map(|g| least_squares(g[0].label.to_owned(), g, 0))
+        .collect::<Vec<_>>();
+
+    export(CHARGE_NAME, &obs, &regs).unwrap();
+}
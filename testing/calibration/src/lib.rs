@@ -241,6 +241,19 @@ pub fn collect_obs(ret: ApplyRet, name: &str, label: &str, size: usize) -> Vec<O
         .collect()
 }
 
+/// Sums the compute gas (in milligas) of the charges with the given name in a message's trace.
+pub fn charged_gas(ret: &ApplyRet, name: &str) -> i64 {
+    ret.exec_trace
+        .iter()
+        .filter_map(|t| match t {
+            ExecutionEvent::GasCharge(charge) if charge.name == name => {
+                Some(charge.compute_gas.as_milligas())
+            }
+            _ => None,
+        })
+        .sum()
+}
+
 /// Drop a certain fraction of the observations with the highest time as outliers.
 pub fn eliminate_outliers(mut obs: Vec<Obs>, drop: f32, eliminate: Eliminate) -> Vec<Obs> {
     obs.sort_by_key(|obs| obs.elapsed_nanos);
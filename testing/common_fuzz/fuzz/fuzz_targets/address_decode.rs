@@ -0,0 +1,21 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+#![no_main]
+use fvm_shared::address::{validate_address_bytes, Address};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let addr = Address::from_bytes(data);
+    if validate_address_bytes(data).is_err() {
+        assert!(addr.is_err(), "decoded an address that failed validation");
+        return;
+    }
+    let addr = match addr {
+        Ok(addr) => addr,
+        Err(_) => return,
+    };
+
+    // Every address has exactly one encoding.
+    assert_eq!(addr.to_bytes(), data);
+    assert_eq!(Address::from_bytes(&addr.to_bytes()).unwrap(), addr);
+});
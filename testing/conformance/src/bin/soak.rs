@@ -0,0 +1,65 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+use anyhow::{anyhow, Context};
+use fvm_conformance_tests::soak::{read_car, replay, MessageRange, SoakConfig};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        println!("Usage: {} <state.car[.gz]> <messages.json>", args[0]);
+        process::exit(1)
+    }
+
+    match run(Path::new(&args[1]), Path::new(&args[2])) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            println!("Error: {err:#}");
+            process::exit(1)
+        }
+    }
+}
+
+/// Runs the soak test, returning whether all state roots matched.
+fn run(car_path: &Path, messages_path: &Path) -> anyhow::Result<bool> {
+    let config = config_from_env()?;
+
+    let car = read_car(car_path).with_context(|| format!("failed to read {:?}", car_path))?;
+    let range = MessageRange::from_file(messages_path)
+        .with_context(|| format!("failed to read {:?}", messages_path))?;
+
+    let report = replay(&config, &car, &range);
+    println!("{report}");
+
+    if let Ok(path) = env::var("SOAK_REPORT") {
+        let file = File::create(&path).with_context(|| format!("failed to create {path}"))?;
+        serde_json::to_writer_pretty(file, &report)?;
+    }
+
+    Ok(report.is_success())
+}
+
+/// Reads the soak test settings from the environment.
+fn config_from_env() -> anyhow::Result<SoakConfig> {
+    fn var<T: std::str::FromStr>(name: &str) -> anyhow::Result<Option<T>> {
+        env::var(name)
+            .ok()
+            .map(|v| v.parse().map_err(|_| anyhow!("{name} should be a number")))
+            .transpose()
+    }
+
+    let mut config = SoakConfig::default();
+    if let Some(concurrency) = var("SOAK_CONCURRENCY")? {
+        config.concurrency = concurrency;
+    }
+    if let Some(iterations) = var("SOAK_ITERATIONS")? {
+        config.iterations = iterations;
+    }
+    config.trace_dir = env::var_os("TRACE_DIR").map(PathBuf::from);
+    config.bundled_actors = env::var_os("SOAK_BUNDLED_ACTORS").is_some();
+    Ok(config)
+}
@@ -1,6 +1,7 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use std::fmt;
+use std::str::FromStr;
 use std::time::Instant;
 
 use anyhow::{anyhow, Result};
@@ -10,21 +11,23 @@ use fvm::engine::MultiEngine;
 use fvm::executor::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
 use fvm::kernel::Context;
 use fvm::machine::Machine;
+use fvm::state_check::{check_state, StateProblem};
 use fvm::state_tree::{ActorState, StateTree};
+use fvm::trace::ExecutionEvent;
 use fvm_ipld_blockstore::MemoryBlockstore;
 use fvm_ipld_encoding::{from_slice, CborStore};
 use fvm_shared::address::Protocol;
 use fvm_shared::crypto::signature::SECP_SIG_LEN;
 use fvm_shared::message::Message;
-use fvm_shared::receipt::Receipt;
 use fvm_shared::version::NetworkVersion;
 use lazy_static::lazy_static;
 use libipld_core::ipld::Ipld;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use walkdir::DirEntry;
 
 use crate::tracing::{CaptureApplyRetFun, TestTraceFun};
-use crate::vector::{MessageVector, Variant};
+use crate::vector::{gas_charges, gas_trace_digest, GasTraceVector, MessageVector, Variant};
 use crate::vm::{TestKernel, TestMachine, TestStatsRef};
 
 lazy_static! {
@@ -48,6 +51,28 @@ lazy_static! {
         });
 }
 
+lazy_static! {
+    /// Check the integrity of the final state tree of every vector (see [`check_state`]).
+    static ref CHECK_STATE: bool = std::env::var("CHECK_STATE").map_or(false, |v| v != "0");
+}
+
+#[cfg(feature = "fault-injection")]
+lazy_static! {
+    /// Inject faults derived from `FAULT_SEED`, at the rates given by `FAULT_SYSCALL_PPM` and
+    /// `FAULT_EXTERN_PPM`, and run out of gas at the `FAULT_OUT_OF_GAS_AT`th charge.
+    pub(crate) static ref FAULT_CONFIG: Option<fvm::fault::FaultConfig> = std::env::var("FAULT_SEED").ok()
+        .map(|seed| {
+            fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+                std::env::var(name).ok().map(|v| v.parse().unwrap_or_else(|_| panic!("{name} should be a number")))
+            }
+            let mut config = fvm::fault::FaultConfig::new(seed.parse().expect("FAULT_SEED should be a number"));
+            config.syscall_error_ppm = var("FAULT_SYSCALL_PPM").unwrap_or_default();
+            config.extern_error_ppm = var("FAULT_EXTERN_PPM").unwrap_or_default();
+            config.out_of_gas_at = var("FAULT_OUT_OF_GAS_AT");
+            config
+        });
+}
+
 /// Checks if the file is a runnable vector.
 pub fn is_runnable(entry: &DirEntry) -> bool {
     let file_name = match entry.path().to_str() {
@@ -65,13 +90,13 @@ pub fn is_runnable(entry: &DirEntry) -> bool {
     file_name.ends_with(".json")
 }
 
-/// Compares the result of running a message with the expected result.
+/// Compares the result of running the `label`th message of the vector with the expected result.
 fn check_msg_result(
-    expected_rec: &Receipt,
+    v: &MessageVector,
+    label: usize,
     ret: &ApplyRet,
-    label: impl Display,
-    skip_compare_gas_used: bool,
-) -> Result<()> {
+) -> std::result::Result<(), (FailureClass, anyhow::Error)> {
+    let expected_rec = &v.postconditions.receipts[label];
     let error = ret
         .failure_info
         .as_ref()
@@ -80,33 +105,63 @@ fn check_msg_result(
     let actual_rec = &ret.msg_receipt;
     let (expected, actual) = (expected_rec.exit_code, actual_rec.exit_code);
     if expected != actual {
-        return Err(anyhow!(
-            "exit code of msg {} did not match; expected: {:?}, got {:?}. Error: {}",
-            label,
-            expected,
-            actual,
-            error
+        return Err((
+            FailureClass::ExitCode,
+            anyhow!(
+                "exit code of msg {} did not match; expected: {:?}, got {:?}. Error: {}",
+                label,
+                expected,
+                actual,
+                error
+            ),
         ));
     }
 
     let (expected, actual) = (&expected_rec.return_data, &actual_rec.return_data);
     if expected != actual {
-        return Err(anyhow!(
-            "return data of msg {} did not match; expected: {:?}, got {:?}",
-            label,
-            expected.as_slice(),
-            actual.as_slice()
+        return Err((
+            FailureClass::ReturnData,
+            anyhow!(
+                "return data of msg {} did not match; expected: {:?}, got {:?}",
+                label,
+                expected.as_slice(),
+                actual.as_slice()
+            ),
         ));
     }
 
-    if !skip_compare_gas_used {
+    if !v.skip_compare_gas_used {
+        // Compare the gas traces first, to report where they diverge.
+        if let Some(Some(expected)) = v.postconditions.gas_traces.get(label) {
+            check_gas_trace(expected, &ret.exec_trace, label)
+                .map_err(|e| (FailureClass::GasTrace, e))?;
+        }
+
         let (expected, actual) = (expected_rec.gas_used, actual_rec.gas_used);
         if expected != actual {
-            return Err(anyhow!(
-                "gas used of msg {} did not match; expected: {}, got {}",
-                label,
-                expected,
-                actual
+            return Err((
+                FailureClass::GasUsed,
+                anyhow!(
+                    "gas used of msg {} did not match; expected: {}, got {}",
+                    label,
+                    expected,
+                    actual
+                ),
+            ));
+        }
+    }
+
+    if v.checks_events_roots() {
+        let (expected, actual) = (expected_rec.events_root, actual_rec.events_root);
+        if expected != actual {
+            return Err((
+                FailureClass::Events,
+                anyhow!(
+                    "events root of msg {} did not match; expected: {:?}, got {:?}",
+                    label,
+                    expected,
+                    actual
+                ),
             ));
         }
     }
@@ -114,6 +169,68 @@ fn check_msg_result(
     Ok(())
 }
 
+/// Compares the gas trace of a message with the expected trace, reporting the first diverging gas
+/// charge if the vector includes the expected charges.
+fn check_gas_trace(
+    expected: &GasTraceVector,
+    trace: &[ExecutionEvent],
+    label: impl Display,
+) -> Result<()> {
+    let digest = gas_trace_digest(trace);
+    if expected.digest == digest {
+        return Ok(());
+    }
+    let expected_charges = match &expected.charges {
+        Some(charges) => charges,
+        None => {
+            return Err(anyhow!(
+                "gas trace of msg {} did not match; expected digest: {}, got {}",
+                label,
+                base64::encode(&expected.digest),
+                base64::encode(digest)
+            ))
+        }
+    };
+
+    let actual_charges: Vec<_> = gas_charges(trace).collect();
+    let divergence = expected_charges
+        .iter()
+        .zip(&actual_charges)
+        .position(|(e, a)| {
+            e.name != a.name
+                || e.compute_gas != a.compute_gas.as_milligas()
+                || e.other_gas != a.other_gas.as_milligas()
+        });
+    match divergence {
+        Some(i) => {
+            let (e, a) = (&expected_charges[i], actual_charges[i]);
+            Err(anyhow!(
+                "gas trace of msg {} diverges at charge {}; expected: {} (compute: {}, other: {}), \
+                 got {} (compute: {}, other: {}) (in milligas)",
+                label,
+                i,
+                e.name,
+                e.compute_gas,
+                e.other_gas,
+                a.name,
+                a.compute_gas.as_milligas(),
+                a.other_gas.as_milligas()
+            ))
+        }
+        None if expected_charges.len() != actual_charges.len() => Err(anyhow!(
+            "gas trace of msg {} diverges at charge {}; expected {} charges, got {}",
+            label,
+            expected_charges.len().min(actual_charges.len()),
+            expected_charges.len(),
+            actual_charges.len()
+        )),
+        None => Err(anyhow!(
+            "gas trace of msg {} matches the expected charges, but not the expected digest",
+            label
+        )),
+    }
+}
+
 fn compare_actors(
     bs: &MemoryBlockstore,
     identifier: impl Display,
@@ -241,6 +358,81 @@ fn compare_state_roots(bs: &MemoryBlockstore, root: &Cid, vector: &MessageVector
     }
 }
 
+/// The kind of check a variant failed, used to select the failures to re-run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureClass {
+    /// The vector couldn't be loaded, or the runner panicked while running it.
+    Harness,
+    /// A message couldn't be applied, or the machine couldn't be flushed.
+    Execution,
+    /// A message exited with an unexpected exit code.
+    ExitCode,
+    /// A message returned unexpected data.
+    ReturnData,
+    /// The gas charges of a message don't match its expected gas trace.
+    GasTrace,
+    /// A message used an unexpected amount of gas.
+    GasUsed,
+    /// A message emitted unexpected events.
+    Events,
+    /// The final state doesn't match the postconditions.
+    StateRoot,
+    /// The final state failed the integrity check (`CHECK_STATE`).
+    StateCheck,
+}
+
+impl FailureClass {
+    pub const ALL: [FailureClass; 9] = [
+        FailureClass::Harness,
+        FailureClass::Execution,
+        FailureClass::ExitCode,
+        FailureClass::ReturnData,
+        FailureClass::GasTrace,
+        FailureClass::GasUsed,
+        FailureClass::Events,
+        FailureClass::StateRoot,
+        FailureClass::StateCheck,
+    ];
+
+    /// Returns the name of the class, as used in the JSON results and filters.
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureClass::Harness => "harness",
+            FailureClass::Execution => "execution",
+            FailureClass::ExitCode => "exit-code",
+            FailureClass::ReturnData => "return-data",
+            FailureClass::GasTrace => "gas-trace",
+            FailureClass::GasUsed => "gas-used",
+            FailureClass::Events => "events",
+            FailureClass::StateRoot => "state-root",
+            FailureClass::StateCheck => "state-check",
+        }
+    }
+}
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FailureClass {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        FailureClass::ALL
+            .into_iter()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "must be one of {}",
+                    FailureClass::ALL.map(FailureClass::name).join("|")
+                )
+            })
+    }
+}
+
 /// Represents the result from running a vector.
 pub enum VariantResult {
     /// The vector succeeded.
@@ -248,7 +440,11 @@ pub enum VariantResult {
     /// A variant was skipped, due to the specified reason.
     Skipped { reason: String, id: String },
     /// A variant failed, due to the specified error.
-    Failed { reason: anyhow::Error, id: String },
+    Failed {
+        reason: anyhow::Error,
+        class: FailureClass,
+        id: String,
+    },
 }
 
 pub fn run_variant(
@@ -268,13 +464,20 @@ pub fn run_variant(
         check_correctness = false;
     }
 
+    // Nor can we expect anything to match if we inject faults.
+    #[cfg(feature = "fault-injection")]
+    if FAULT_CONFIG.is_some() {
+        check_correctness = false;
+    }
+
     // Construct the Machine.
     let machine = TestMachine::new_for_vector(
         v,
         variant,
         bs,
         stats,
-        trace.is_some(),
+        // The gas traces of vectors recording them are checked, so they must be collected.
+        trace.is_some() || (check_correctness && v.has_gas_traces()),
         *PRICE_NETWORK_VERSION,
     )?;
     let engine = engines
@@ -316,16 +519,25 @@ pub fn run_variant(
         let start = Instant::now();
         let ret = match exec.execute_message(msg, ApplyKind::Explicit, raw_length) {
             Ok(ret) => ret,
-            Err(e) => return Ok(VariantResult::Failed { id, reason: e }),
+            Err(e) => {
+                return Ok(VariantResult::Failed {
+                    id,
+                    reason: e,
+                    class: FailureClass::Execution,
+                })
+            }
         };
 
         capture_apply_ret_fn((i as i32, ret.clone()))?;
 
         if check_correctness {
             // Compare the actual receipt with the expected receipt.
-            let expected_receipt = &v.postconditions.receipts[i];
-            if let Err(err) = check_msg_result(expected_receipt, &ret, i, v.skip_compare_gas_used) {
-                return Ok(VariantResult::Failed { id, reason: err });
+            if let Err((class, err)) = check_msg_result(v, i, &ret) {
+                return Ok(VariantResult::Failed {
+                    id,
+                    reason: err,
+                    class,
+                });
             }
         }
 
@@ -340,6 +552,7 @@ pub fn run_variant(
             return Ok(VariantResult::Failed {
                 id,
                 reason: err.context("flushing executor failed"),
+                class: FailureClass::Execution,
             });
         }
     };
@@ -350,16 +563,44 @@ pub fn run_variant(
             return Ok(VariantResult::Failed {
                 id,
                 reason: anyhow!("machine poisoned"),
+                class: FailureClass::Execution,
             })
         }
     };
+    let bs = machine.into_store().into_inner();
     if check_correctness {
-        let bs = machine.into_store().into_inner();
-
         if let Err(err) = compare_state_roots(&bs, &final_root, v) {
             return Ok(VariantResult::Failed {
                 id,
                 reason: err.context("comparing state roots failed"),
+                class: FailureClass::StateRoot,
+            });
+        }
+    }
+
+    // Vectors only include the state they touch, so missing (and therefore unreadable) state is
+    // expected and ignored.
+    if *CHECK_STATE {
+        let problems = match check_state(&bs, &final_root) {
+            Ok(report) => report.problems,
+            Err(err) => {
+                return Ok(VariantResult::Failed {
+                    id,
+                    reason: err.context("checking the final state failed"),
+                    class: FailureClass::StateCheck,
+                })
+            }
+        };
+        let problems: Vec<_> = problems
+            .iter()
+            .filter(|p| !matches!(p, StateProblem::Unreadable { .. }))
+            .map(|p| p.to_string())
+            .collect();
+        if !problems.is_empty() {
+            return Ok(VariantResult::Failed {
+                id,
+                reason: anyhow!("inconsistent final state: {}", problems.join("; ")),
+                class: FailureClass::StateCheck,
             });
         }
     }
@@ -373,3 +614,76 @@ pub fn run_variant(
 
     Ok(VariantResult::Ok { id })
 }
+
+#[cfg(test)]
+mod tests {
+    use fvm::gas::{Gas, GasCharge};
+
+    use super::*;
+
+    fn trace(charges: &[(&'static str, i64)]) -> Vec<ExecutionEvent> {
+        charges
+            .iter()
+            .map(|&(name, gas)| {
+                ExecutionEvent::GasCharge(GasCharge::new(name, Gas::new(gas), Gas::zero()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gas_trace_divergence() {
+        let actual = trace(&[
+            ("OnChainMessage", 10),
+            ("OnBlockOpen", 5),
+            ("OnBlockRead", 1),
+        ]);
+        let check = |expected: &[(&'static str, i64)], with_charges: bool| {
+            let expected = GasTraceVector::from_trace(&trace(expected), with_charges);
+            check_gas_trace(&expected, &actual, 0).map_err(|e| e.to_string())
+        };
+
+        check(
+            &[
+                ("OnChainMessage", 10),
+                ("OnBlockOpen", 5),
+                ("OnBlockRead", 1),
+            ],
+            false,
+        )
+        .unwrap();
+
+        // Without the charges, only the digests are compared.
+        let err = check(&[("OnChainMessage", 10)], false).unwrap_err();
+        assert!(err.contains("expected digest"), "{}", err);
+
+        // The first diverging charge is reported.
+        let err = check(
+            &[
+                ("OnChainMessage", 10),
+                ("OnBlockOpen", 6),
+                ("OnBlockRead", 2),
+            ],
+            true,
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("diverges at charge 1; expected: OnBlockOpen (compute: 6000"),
+            "{}",
+            err
+        );
+        let err = check(&[("OnChainMessage", 10), ("OnBlockStat", 5)], true).unwrap_err();
+        assert!(
+            err.contains("diverges at charge 1; expected: OnBlockStat"),
+            "{}",
+            err
+        );
+
+        // Missing or extra charges are reported after the common prefix.
+        let err = check(&[("OnChainMessage", 10), ("OnBlockOpen", 5)], true).unwrap_err();
+        assert!(
+            err.contains("diverges at charge 2; expected 2 charges, got 3"),
+            "{}",
+            err
+        );
+    }
+}
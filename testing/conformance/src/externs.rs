@@ -1,7 +1,7 @@
 use anyhow::anyhow;
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-use fvm::externs::{Chain, Consensus, Externs, Rand};
+use fvm::externs::{Chain, Consensus, Externs, Rand, StateReader};
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
 
@@ -68,3 +68,5 @@ impl Chain for TestExterns {
         Err(anyhow!("cannot find tipset cid, epoch {}", _epoch))
     }
 }
+
+impl StateReader for TestExterns {}
@@ -6,6 +6,8 @@ pub mod cidjson;
 pub mod driver;
 pub mod externs;
 pub mod rand;
+pub mod runner;
+pub mod soak;
 pub mod tracing;
 pub mod vector;
 pub mod vm;
@@ -0,0 +1,707 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! A parallel test vector runner.
+//!
+//! Vectors are run on a rayon thread pool, each in isolation: every variant runs on its own copy
+//! of the vector's blockstore, and a panic while running a variant is recorded as a failure of
+//! that variant instead of bringing down the whole run.
+//!
+//! The vectors to run can be narrowed down with a regex filter, split into shards (to spread a
+//! run over several CI jobs), or restricted to the failures recorded in the results of a previous
+//! run, optionally only those of some [`FailureClass`]es. Re-run failures are run with execution
+//! tracing enabled, and the traces of the variants still failing are written out for inspection.
+//!
+//! The results ([`RunReport`]) can be written out as JSON, which is also the input of re-runs.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs::{create_dir_all, File};
+use std::io::BufReader;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, Context};
+use colored::*;
+use futures::executor::block_on;
+use fvm::engine::MultiEngine;
+use fvm::executor::ApplyRet;
+use itertools::Itertools;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::driver::{is_runnable, run_variant, FailureClass, VariantResult};
+use crate::report;
+use crate::tracing::{CaptureApplyRetFun, TestTraceExporter, TestTraceFun};
+use crate::vector::MessageVector;
+use crate::vm::{TestStatsGlobal, TestStatsRef};
+
+/// What to do when a vector doesn't include its postcondition state root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    Error,
+    Warn,
+    Ignore,
+}
+
+impl FromStr for ErrorAction {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err("must be one of error|warn|ignore".into()),
+        }
+    }
+}
+
+/// A shard of the vectors: the `index`th (zero-based) of `count` equal shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+impl Shard {
+    /// Returns true if the `i`th vector (in path order) belongs to this shard.
+    pub fn contains(&self, i: usize) -> bool {
+        i % self.count == self.index
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+    /// Parses a shard of the form `index/count`, e.g., `0/4`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| "must be of the form index/count".to_owned())?;
+        let index = index
+            .trim()
+            .parse()
+            .map_err(|_| "the shard index must be an integer".to_owned())?;
+        let count = count
+            .trim()
+            .parse()
+            .map_err(|_| "the shard count must be an integer".to_owned())?;
+        if index >= count {
+            return Err("the shard index must be less than the shard count".into());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+/// Test vector runner settings.
+#[derive(Debug, Clone)]
+pub struct RunnerConfig {
+    /// The number of vectors to run at once.
+    pub parallelism: usize,
+    /// Only run the vectors whose path matches this regex.
+    pub filter: Option<Regex>,
+    /// Only run this shard of the vectors.
+    pub shard: Option<Shard>,
+    /// Only re-run the failures recorded in these results.
+    pub rerun: Option<RunReport>,
+    /// Only re-run failures of these classes (all failures if empty).
+    pub rerun_classes: Vec<FailureClass>,
+    /// Export the gas traces of passing variants to the given directory.
+    pub trace_dir: Option<PathBuf>,
+    /// Write the execution traces of failing variants to the given directory.
+    pub failure_trace_dir: Option<PathBuf>,
+    /// What to do when a vector doesn't include its postcondition state root (there's a
+    /// correctness check against the postconditions anyway).
+    pub postcondition_missing: ErrorAction,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        RunnerConfig {
+            parallelism: num_cpus::get(),
+            filter: None,
+            shard: None,
+            rerun: None,
+            rerun_classes: Vec::new(),
+            trace_dir: None,
+            failure_trace_dir: None,
+            postcondition_missing: ErrorAction::Warn,
+        }
+    }
+}
+
+impl RunnerConfig {
+    /// Reads the runner settings from the environment:
+    ///
+    /// - `TEST_VECTOR_PARALLELISM`: the number of vectors to run at once.
+    /// - `TEST_VECTOR_FILTER`: a regex matching the paths of the vectors to run.
+    /// - `TEST_VECTOR_SHARD`: the shard of the vectors to run, as `index/count`.
+    /// - `TEST_VECTOR_RERUN`: the JSON results of a previous run, to re-run its failures.
+    /// - `TEST_VECTOR_RERUN_CLASSES`: a comma-separated list of the failure classes to re-run.
+    /// - `TRACE_DIR`: the directory to export gas traces (and re-run failure traces) to.
+    /// - `TEST_VECTOR_POSTCONDITION_MISSING_ACTION`: one of `error`, `warn` (the default) or
+    ///   `ignore`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        fn var<T: FromStr<Err = String>>(name: &str) -> anyhow::Result<Option<T>> {
+            std::env::var(name)
+                .ok()
+                .map(|v| v.parse().map_err(|e| anyhow!("invalid {name}: {e}")))
+                .transpose()
+        }
+
+        let mut config = RunnerConfig::default();
+        if let Ok(parallelism) = std::env::var("TEST_VECTOR_PARALLELISM") {
+            config.parallelism = parallelism
+                .parse()
+                .context("TEST_VECTOR_PARALLELISM should be a number")?;
+        }
+        if let Ok(filter) = std::env::var("TEST_VECTOR_FILTER") {
+            config.filter = Some(Regex::new(&filter).context("invalid TEST_VECTOR_FILTER")?);
+        }
+        config.shard = var("TEST_VECTOR_SHARD")?;
+        config.trace_dir = std::env::var_os("TRACE_DIR").map(PathBuf::from);
+        if let Some(path) = std::env::var_os("TEST_VECTOR_RERUN") {
+            config.rerun = Some(RunReport::from_file(Path::new(&path))?);
+            config.failure_trace_dir = Some(
+                config
+                    .trace_dir
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from("rerun-traces")),
+            );
+        }
+        if let Ok(classes) = std::env::var("TEST_VECTOR_RERUN_CLASSES") {
+            config.rerun_classes = classes
+                .split(',')
+                .map(|c| c.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|e| anyhow!("invalid TEST_VECTOR_RERUN_CLASSES: {e}"))?;
+        }
+        if let Some(action) = var("TEST_VECTOR_POSTCONDITION_MISSING_ACTION")? {
+            config.postcondition_missing = action;
+        }
+        Ok(config)
+    }
+}
+
+/// The status of a variant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// The result of running a single variant of a vector.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantOutcome {
+    /// The path of the vector.
+    pub path: PathBuf,
+    /// The ID of the variant, or `None` if the vector couldn't be loaded.
+    pub variant: Option<String>,
+    pub status: Status,
+    /// The kind of check the variant failed.
+    #[serde(default)]
+    pub class: Option<FailureClass>,
+    /// Why the variant failed or was skipped.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// The time taken to run the variant.
+    pub elapsed_nanos: u128,
+    /// The execution trace of the failing variant, if re-run with tracing.
+    #[serde(default)]
+    pub trace_path: Option<PathBuf>,
+}
+
+/// The results of a test vector run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunReport {
+    /// The results of each variant, in vector path order.
+    pub results: Vec<VariantOutcome>,
+    /// The total time taken by the run.
+    pub elapsed_nanos: u128,
+}
+
+impl RunReport {
+    /// Loads the results of a previous run from a JSON file.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse the results in {:?}", path))
+    }
+
+    /// Writes the results to a JSON file.
+    pub fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path).with_context(|| format!("failed to create {:?}", path))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Returns the number of variants with the given status.
+    pub fn count(&self, status: Status) -> usize {
+        self.results.iter().filter(|r| r.status == status).count()
+    }
+
+    /// Returns true if no variant failed.
+    pub fn is_success(&self) -> bool {
+        self.count(Status::Failed) == 0
+    }
+
+    /// Returns the failures of the given classes (all failures if `classes` is empty).
+    pub fn failures<'a>(
+        &'a self,
+        classes: &'a [FailureClass],
+    ) -> impl Iterator<Item = &'a VariantOutcome> + 'a {
+        self.results.iter().filter(move |r| {
+            r.status == Status::Failed
+                && (classes.is_empty() || r.class.map_or(false, |c| classes.contains(&c)))
+        })
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (succeeded, failed) = (self.count(Status::Ok), self.count(Status::Failed));
+        write!(
+            f,
+            "conformance tests result: {}/{} tests passed ({} skipped)",
+            succeeded,
+            succeeded + failed,
+            self.count(Status::Skipped),
+        )?;
+        let classes = self.failures(&[]).filter_map(|r| r.class).counts();
+        for class in FailureClass::ALL {
+            if let Some(n) = classes.get(&class) {
+                write!(f, "\n  {}: {} failed", class, n)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The vectors to run, each with the IDs of the variants to run (all variants if `None`).
+pub type VectorSelection = Vec<(PathBuf, Option<HashSet<String>>)>;
+
+/// Selects the vectors to run from `path` (a vector file, or a directory of vectors), according to
+/// the config. Vectors are sorted by path, so that shards are stable.
+pub fn select_vectors(path: &Path, config: &RunnerConfig) -> anyhow::Result<VectorSelection> {
+    let mut paths = Vec::new();
+    if path.is_file() {
+        paths.push(path.to_owned());
+    } else {
+        for entry in WalkDir::new(path) {
+            let entry = entry?;
+            if is_runnable(&entry) {
+                paths.push(entry.into_path());
+            }
+        }
+    }
+    paths.sort();
+    if let Some(filter) = &config.filter {
+        paths.retain(|p| filter.is_match(&p.to_string_lossy()));
+    }
+    if let Some(shard) = config.shard {
+        paths = paths
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| shard.contains(*i))
+            .map(|(_, p)| p)
+            .collect();
+    }
+
+    let previous = match &config.rerun {
+        Some(previous) => previous,
+        None => return Ok(paths.into_iter().map(|p| (p, None)).collect()),
+    };
+
+    // Re-run the failed variants, or every variant of vectors that failed to load.
+    let mut failed: HashMap<&Path, Option<HashSet<String>>> = HashMap::new();
+    for r in previous.failures(&config.rerun_classes) {
+        let variants = failed
+            .entry(r.path.as_path())
+            .or_insert_with(|| Some(HashSet::new()));
+        match (variants, &r.variant) {
+            (Some(ids), Some(id)) => {
+                ids.insert(id.clone());
+            }
+            (variants, _) => *variants = None,
+        }
+    }
+    Ok(paths
+        .into_iter()
+        .filter_map(|p| failed.remove(p.as_path()).map(|v| (p, v)))
+        .collect())
+}
+
+/// Runs the selected vectors, reporting each variant's result as it completes.
+pub fn run(config: &RunnerConfig, vectors: &VectorSelection) -> anyhow::Result<RunReport> {
+    let parallelism = config.parallelism.max(1);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallelism)
+        .build()?;
+    let engines = MultiEngine::new(parallelism as u32);
+    let stats = TestStatsGlobal::new_ref();
+    let tracer = config.trace_dir.clone().map(TestTraceExporter::new);
+
+    let start = Instant::now();
+    let results = pool.install(|| {
+        vectors
+            .par_iter()
+            .flat_map_iter(|(path, variants)| {
+                run_vector(
+                    config,
+                    &engines,
+                    path,
+                    variants.as_ref(),
+                    stats.clone(),
+                    tracer.clone(),
+                )
+            })
+            .collect()
+    });
+    let elapsed = start.elapsed();
+
+    if let Some(tracer) = &tracer {
+        tracer.export_tombstones()?;
+    }
+
+    if let Some(stats) = &stats {
+        let stats = stats.lock().unwrap();
+        log::info!(
+            "memory stats: init.min: {}, init.max: {}, exec.min: {}, exec.max: {}",
+            stats.init.min_desired_memory_bytes,
+            stats.init.max_desired_memory_bytes,
+            stats.exec.min_desired_memory_bytes,
+            stats.exec.max_desired_memory_bytes,
+        );
+    }
+
+    Ok(RunReport {
+        results,
+        elapsed_nanos: elapsed.as_nanos(),
+    })
+}
+
+/// Runs the (selected) variants of a single vector.
+fn run_vector(
+    config: &RunnerConfig,
+    engines: &MultiEngine,
+    path: &Path,
+    variants: Option<&HashSet<String>>,
+    stats: TestStatsRef,
+    tracer: Option<Arc<TestTraceExporter>>,
+) -> Vec<VariantOutcome> {
+    let start = Instant::now();
+    let harness_failure = |err: anyhow::Error| {
+        vec![finish(VariantOutcome {
+            path: path.to_owned(),
+            variant: None,
+            status: Status::Failed,
+            class: Some(FailureClass::Harness),
+            reason: Some(format!("{:#}", err)),
+            elapsed_nanos: start.elapsed().as_nanos(),
+            trace_path: None,
+        })]
+    };
+
+    let v = match MessageVector::from_file(path).context("failed to parse vector") {
+        Ok(v) => v,
+        Err(err) => return harness_failure(err),
+    };
+    let selected: Vec<_> = v
+        .preconditions
+        .variants
+        .iter()
+        .filter(|variant| variants.map_or(true, |ids| ids.contains(&variant.id)))
+        .collect();
+
+    if !v.is_supported() {
+        return selected
+            .into_iter()
+            .map(|variant| {
+                finish(VariantOutcome {
+                    path: path.to_owned(),
+                    variant: Some(variant.id.clone()),
+                    status: Status::Skipped,
+                    class: None,
+                    reason: Some("selector not supported".to_owned()),
+                    elapsed_nanos: 0,
+                    trace_path: None,
+                })
+            })
+            .collect();
+    }
+
+    // First import the blockstore and do some sanity checks.
+    let (bs, imported_root) = match block_on(v.seed_blockstore()) {
+        Ok(res) => res,
+        Err(err) => return harness_failure(err.context("failed to seed the blockstore")),
+    };
+    if !imported_root.contains(&v.preconditions.state_tree.root_cid) {
+        return harness_failure(anyhow!(
+            "imported roots ({}) do not contain precondition CID {}",
+            imported_root.iter().join(", "),
+            v.preconditions.state_tree.root_cid
+        ));
+    }
+    if !imported_root.contains(&v.postconditions.state_tree.root_cid) {
+        let msg = format!(
+            "imported roots ({}) do not contain postcondition CID {}",
+            imported_root.iter().join(", "),
+            v.postconditions.state_tree.root_cid
+        );
+        match config.postcondition_missing {
+            ErrorAction::Error => return harness_failure(anyhow!(msg)),
+            ErrorAction::Warn => eprintln!("WARN: {msg} in {}", path.display()),
+            ErrorAction::Ignore => (),
+        }
+    }
+
+    selected
+        .into_iter()
+        .map(|variant| {
+            let start = Instant::now();
+
+            // Capture the message results to write out the trace if the variant fails.
+            let captured: Rc<RefCell<Vec<(i32, ApplyRet)>>> = Default::default();
+            let capture = config.failure_trace_dir.as_ref().map(|_| {
+                let captured = captured.clone();
+                let f: CaptureApplyRetFun = Box::new(move |ret| {
+                    captured.borrow_mut().push(ret);
+                    Ok(())
+                });
+                f
+            });
+            let trace: Option<TestTraceFun> = match &tracer {
+                Some(tracer) => Some(
+                    tracer
+                        .clone()
+                        .export_fun(path.to_owned(), variant.id.clone()),
+                ),
+                // Enable tracing without exporting gas traces.
+                None if capture.is_some() => {
+                    let f: TestTraceFun = Box::new(|_| Ok(()));
+                    Some(f)
+                }
+                None => None,
+            };
+
+            let res = catch_unwind(AssertUnwindSafe(|| {
+                run_variant(
+                    bs.clone(),
+                    &v,
+                    variant,
+                    engines,
+                    true,
+                    stats.clone(),
+                    trace,
+                    capture,
+                )
+            }));
+            let (status, class, reason) = match res {
+                Ok(Ok(VariantResult::Ok { .. })) => (Status::Ok, None, None),
+                Ok(Ok(VariantResult::Skipped { reason, .. })) => {
+                    (Status::Skipped, None, Some(reason))
+                }
+                Ok(Ok(VariantResult::Failed { reason, class, .. })) => {
+                    (Status::Failed, Some(class), Some(format!("{:#}", reason)))
+                }
+                Ok(Err(err)) => (
+                    Status::Failed,
+                    Some(FailureClass::Harness),
+                    Some(format!("{:#}", err)),
+                ),
+                Err(panic) => (
+                    Status::Failed,
+                    Some(FailureClass::Harness),
+                    Some(format!("panicked: {}", panic_message(&*panic))),
+                ),
+            };
+
+            let trace_path = match (&config.failure_trace_dir, status) {
+                (Some(dir), Status::Failed) => {
+                    match write_failure_trace(dir, path, &variant.id, &captured.borrow()) {
+                        Ok(trace_path) => Some(trace_path),
+                        Err(err) => {
+                            log::error!("failed to write the trace of {}: {:#}", variant.id, err);
+                            None
+                        }
+                    }
+                }
+                _ => None,
+            };
+
+            finish(VariantOutcome {
+                path: path.to_owned(),
+                variant: Some(variant.id.clone()),
+                status,
+                class,
+                reason,
+                elapsed_nanos: start.elapsed().as_nanos(),
+                trace_path,
+            })
+        })
+        .collect()
+}
+
+/// Reports a variant's result to stdout as soon as it's known.
+fn finish(outcome: VariantOutcome) -> VariantOutcome {
+    let id = outcome.variant.as_deref().unwrap_or("-");
+    let path = outcome.path.display();
+    match outcome.status {
+        Status::Ok => {
+            report!("OK".on_green(), path, id);
+        }
+        Status::Failed => {
+            report!("FAIL".white().on_red(), path, id);
+        }
+        Status::Skipped => {
+            report!("SKIP".on_yellow(), path, id);
+        }
+    }
+    if let Some(reason) = &outcome.reason {
+        match outcome.class {
+            Some(class) => println!("\t|> reason ({}): {}", class, reason),
+            None => println!("\t|> reason: {}", reason),
+        }
+    }
+    outcome
+}
+
+/// Writes the execution traces of a failing variant's messages to a file in `dir`, returning its
+/// path.
+fn write_failure_trace(
+    dir: &Path,
+    vector: &Path,
+    variant: &str,
+    rets: &[(i32, ApplyRet)],
+) -> anyhow::Result<PathBuf> {
+    let mut out = String::new();
+    for (i, ret) in rets {
+        writeln!(
+            out,
+            "# message {}: exit code {}, gas used {}",
+            i, ret.msg_receipt.exit_code, ret.msg_receipt.gas_used
+        )?;
+        if let Some(failure) = &ret.failure_info {
+            writeln!(out, "# failure: {}", failure)?;
+        }
+        writeln!(out, "{:#?}", ret.exec_trace)?;
+    }
+
+    create_dir_all(dir)?;
+    let name = format!("{}-{}.trace", vector.to_string_lossy(), variant).replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '.',
+        "_",
+    );
+    let path = dir.join(name);
+    std::fs::write(&path, out)?;
+    Ok(path)
+}
+
+/// Extracts the message of a caught panic.
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(path: &str, variant: Option<&str>, class: Option<FailureClass>) -> VariantOutcome {
+        VariantOutcome {
+            path: path.into(),
+            variant: variant.map(str::to_owned),
+            status: if class.is_some() {
+                Status::Failed
+            } else {
+                Status::Ok
+            },
+            class,
+            reason: None,
+            elapsed_nanos: 0,
+            trace_path: None,
+        }
+    }
+
+    #[test]
+    fn parse_shard() {
+        assert_eq!("1/4".parse(), Ok(Shard { index: 1, count: 4 }));
+        assert!("4/4".parse::<Shard>().is_err());
+        assert!("0/0".parse::<Shard>().is_err());
+        assert!("1".parse::<Shard>().is_err());
+
+        let shard = Shard { index: 1, count: 3 };
+        assert_eq!(
+            (0..7).filter(|i| shard.contains(*i)).collect::<Vec<_>>(),
+            vec![1, 4]
+        );
+    }
+
+    #[test]
+    fn parse_failure_class() {
+        for class in FailureClass::ALL {
+            assert_eq!(class.name().parse(), Ok(class));
+            let json = serde_json::to_string(&class).unwrap();
+            assert_eq!(json, format!("\"{}\"", class.name()));
+        }
+        assert!("nope".parse::<FailureClass>().is_err());
+    }
+
+    #[test]
+    fn select_failures() {
+        let dir = std::env::temp_dir().join(format!("fvm-runner-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        for name in ["a.json", "b.json", "c.json", "d.json"] {
+            std::fs::write(dir.join(name), "{}").unwrap();
+        }
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        let report = RunReport {
+            results: vec![
+                outcome(&path("a.json"), Some("x"), Some(FailureClass::GasUsed)),
+                outcome(&path("a.json"), Some("y"), None),
+                outcome(&path("b.json"), Some("x"), Some(FailureClass::StateRoot)),
+                outcome(&path("c.json"), None, Some(FailureClass::Harness)),
+            ],
+            elapsed_nanos: 0,
+        };
+        // The results survive a round-trip through JSON.
+        let json = serde_json::to_string(&report).unwrap();
+        let report: RunReport = serde_json::from_str(&json).unwrap();
+
+        let mut config = RunnerConfig {
+            rerun: Some(report),
+            ..Default::default()
+        };
+        let selected = select_vectors(&dir, &config).unwrap();
+        assert_eq!(
+            selected,
+            vec![
+                (dir.join("a.json"), Some(HashSet::from(["x".to_owned()]))),
+                (dir.join("b.json"), Some(HashSet::from(["x".to_owned()]))),
+                (dir.join("c.json"), None),
+            ]
+        );
+
+        config.rerun_classes = vec![FailureClass::StateRoot, FailureClass::Harness];
+        config.shard = Some(Shard { index: 1, count: 2 });
+        let selected = select_vectors(&dir, &config).unwrap();
+        assert_eq!(
+            selected,
+            vec![(dir.join("b.json"), Some(HashSet::from(["x".to_owned()])))]
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,480 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! A soak test harness, replaying ranges of real chain messages.
+//!
+//! The input is a CAR file with the state the messages need (including the pre-state root of every
+//! batch), along with a JSON message list ([`MessageRange`]) splitting the messages into batches,
+//! usually one per tipset. Each batch is applied on top of its own pre-state root, so batches are
+//! independent and are replayed concurrently. The resulting state roots are compared with the
+//! expected post-state roots, and the timings and memory usage are collected into a
+//! [`SoakReport`].
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fmt, thread};
+
+use anyhow::{anyhow, Context};
+use cid::Cid;
+use flate2::read::GzDecoder;
+use futures::executor::block_on;
+use fvm::engine::MultiEngine;
+use fvm::executor::{ApplyKind, DefaultExecutor, Executor};
+use fvm::machine::Machine;
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_ipld_car::load_car;
+use fvm_ipld_encoding::from_slice;
+use fvm_shared::address::Protocol;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::SECP_SIG_LEN;
+use fvm_shared::message::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::tracing::{TestTrace, TestTraceExporter};
+use crate::vector::{base64_bytes, Randomness, TipsetCid};
+use crate::vm::{TestKernel, TestMachine, TestStatsGlobal, TestStatsRef};
+
+/// A range of chain messages, split into independently replayable batches.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MessageRange {
+    /// The network version the messages were applied at.
+    pub network_version: u32,
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// The randomness requested while applying the messages.
+    #[serde(default)]
+    pub randomness: Randomness,
+    /// The tipset CIDs looked up while applying the messages.
+    #[serde(default)]
+    pub tipset_cids: Vec<TipsetCid>,
+    pub batches: Vec<MessageBatch>,
+}
+
+/// The messages applied on top of a single state root (usually a tipset's messages).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MessageBatch {
+    pub epoch: ChainEpoch,
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub base_fee: Option<u128>,
+    #[serde(default)]
+    pub circ_supply: Option<u128>,
+    #[serde(with = "crate::cidjson")]
+    pub pre_state_root: Cid,
+    #[serde(with = "crate::cidjson")]
+    pub post_state_root: Cid,
+    pub messages: Vec<ReplayMessage>,
+}
+
+/// A message to replay.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ReplayMessage {
+    /// The serialized (unsigned) message.
+    #[serde(with = "base64_bytes")]
+    pub bytes: Vec<u8>,
+    /// Whether the message is an implicit (system) message, e.g., cron or reward.
+    #[serde(default)]
+    pub implicit: bool,
+}
+
+impl MessageRange {
+    /// Loads a message range from a JSON file.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+}
+
+/// Reads a (possibly gzipped) CAR file into memory.
+pub fn read_car(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = std::fs::read(path)?;
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decoded = Vec::new();
+        GzDecoder::new(&bytes[..]).read_to_end(&mut decoded)?;
+        bytes = decoded;
+    }
+    Ok(bytes)
+}
+
+/// Soak test settings.
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    /// The number of batches to replay at once.
+    pub concurrency: usize,
+    /// The number of times to replay the whole range.
+    pub iterations: usize,
+    /// Record execution traces, exporting the gas charges to the given directory.
+    pub trace_dir: Option<PathBuf>,
+    /// Replace the builtin actors with the bundled ones, for CARs that don't include the actor
+    /// code.
+    pub bundled_actors: bool,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        SoakConfig {
+            concurrency: num_cpus::get(),
+            iterations: 1,
+            trace_dir: None,
+            bundled_actors: false,
+        }
+    }
+}
+
+/// The results of a soak test.
+#[derive(Debug, Serialize)]
+pub struct SoakReport {
+    /// The number of batches replayed (across all iterations).
+    pub batches: usize,
+    /// The number of messages applied.
+    pub messages: usize,
+    /// The number of batches producing the expected state root.
+    pub matched: usize,
+    /// Batches producing an unexpected state root.
+    pub mismatches: Vec<BatchMismatch>,
+    /// Batches that couldn't be replayed.
+    pub failures: Vec<BatchFailure>,
+    /// The time taken to replay each batch.
+    pub batch_times: TimingSummary,
+    /// The time taken to apply each message.
+    pub message_times: TimingSummary,
+    /// The total time taken by the soak test.
+    pub elapsed_nanos: u128,
+    /// The peak resident memory of the process, if known.
+    pub peak_rss_bytes: Option<u64>,
+    /// The largest linear memory of any actor instance.
+    pub max_instance_memory_bytes: usize,
+}
+
+impl SoakReport {
+    /// Returns true if every batch produced the expected state root.
+    pub fn is_success(&self) -> bool {
+        self.mismatches.is_empty() && self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "replayed {} batches ({} messages) in {:?}",
+            self.batches,
+            self.messages,
+            Duration::from_nanos(self.elapsed_nanos as u64)
+        )?;
+        writeln!(
+            f,
+            "state roots: {} matched, {} mismatched, {} failed",
+            self.matched,
+            self.mismatches.len(),
+            self.failures.len()
+        )?;
+        for m in &self.mismatches {
+            writeln!(
+                f,
+                "  batch {} (epoch {}): expected {}, got {}",
+                m.batch, m.epoch, m.expected, m.actual
+            )?;
+        }
+        for e in &self.failures {
+            writeln!(f, "  batch {} (epoch {}): {}", e.batch, e.epoch, e.error)?;
+        }
+        writeln!(f, "batch times: {}", self.batch_times)?;
+        writeln!(f, "message times: {}", self.message_times)?;
+        if let Some(rss) = self.peak_rss_bytes {
+            writeln!(f, "peak resident memory: {} MiB", rss >> 20)?;
+        }
+        write!(
+            f,
+            "peak instance memory: {} KiB",
+            self.max_instance_memory_bytes >> 10
+        )
+    }
+}
+
+/// A batch producing an unexpected state root.
+#[derive(Debug, Serialize)]
+pub struct BatchMismatch {
+    pub batch: usize,
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::cidjson")]
+    pub expected: Cid,
+    #[serde(with = "crate::cidjson")]
+    pub actual: Cid,
+}
+
+/// A batch that couldn't be replayed.
+#[derive(Debug, Serialize)]
+pub struct BatchFailure {
+    pub batch: usize,
+    pub epoch: ChainEpoch,
+    pub error: String,
+}
+
+/// A summary of a distribution of durations.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct TimingSummary {
+    pub count: usize,
+    pub min_nanos: u128,
+    pub p50_nanos: u128,
+    pub p90_nanos: u128,
+    pub p99_nanos: u128,
+    pub max_nanos: u128,
+    pub mean_nanos: u128,
+}
+
+impl TimingSummary {
+    /// Summarizes the given durations.
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return TimingSummary::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_nanos();
+        let total: u128 = samples.iter().map(Duration::as_nanos).sum();
+        TimingSummary {
+            count: samples.len(),
+            min_nanos: samples[0].as_nanos(),
+            p50_nanos: percentile(50),
+            p90_nanos: percentile(90),
+            p99_nanos: percentile(99),
+            max_nanos: samples[samples.len() - 1].as_nanos(),
+            mean_nanos: total / samples.len() as u128,
+        }
+    }
+}
+
+impl fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = |nanos: u128| Duration::from_nanos(nanos as u64);
+        write!(
+            f,
+            "n={} min={:?} p50={:?} p90={:?} p99={:?} max={:?} mean={:?}",
+            self.count,
+            d(self.min_nanos),
+            d(self.p50_nanos),
+            d(self.p90_nanos),
+            d(self.p99_nanos),
+            d(self.max_nanos),
+            d(self.mean_nanos)
+        )
+    }
+}
+
+/// The outcome of replaying a single batch.
+struct BatchRun {
+    batch: usize,
+    elapsed: Duration,
+    message_times: Vec<Duration>,
+    result: anyhow::Result<Cid>,
+}
+
+/// Replays the message range on top of the state in the CAR, according to the config.
+pub fn replay(config: &SoakConfig, car: &[u8], range: &MessageRange) -> SoakReport {
+    let concurrency = config.concurrency.max(1);
+    let engines = MultiEngine::new(concurrency as u32);
+    let stats = TestStatsGlobal::new_ref();
+    let tracer = config.trace_dir.clone().map(TestTraceExporter::new);
+
+    let total = range.batches.len() * config.iterations;
+    let next = AtomicUsize::new(0);
+    let start = Instant::now();
+
+    let runs: Vec<BatchRun> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..concurrency)
+            .map(|_| {
+                scope.spawn(|| {
+                    // Each worker replays batches on its own copy of the state, reusing it between
+                    // batches unless the machine is lost to an error.
+                    let mut blockstore = None;
+                    let mut runs = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= total {
+                            break;
+                        }
+                        let batch = i % range.batches.len();
+                        let bs = match blockstore.take() {
+                            Some(bs) => bs,
+                            None => match load_blockstore(car) {
+                                Ok(bs) => bs,
+                                Err(e) => {
+                                    runs.push(BatchRun {
+                                        batch,
+                                        elapsed: Duration::ZERO,
+                                        message_times: Vec::new(),
+                                        result: Err(e),
+                                    });
+                                    continue;
+                                }
+                            },
+                        };
+                        let (run, bs) = replay_batch(
+                            config,
+                            &engines,
+                            bs,
+                            range,
+                            batch,
+                            stats.clone(),
+                            tracer.clone(),
+                            i,
+                        );
+                        blockstore = bs;
+                        runs.push(run);
+                    }
+                    runs
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("soak worker panicked"))
+            .collect()
+    });
+    let elapsed = start.elapsed();
+
+    if let Some(tracer) = &tracer {
+        if let Err(e) = tracer.export_tombstones() {
+            log::error!("failed to export trace tombstones: {}", e);
+        }
+    }
+
+    let mut report = SoakReport {
+        batches: runs.len(),
+        messages: runs.iter().map(|r| r.message_times.len()).sum(),
+        matched: 0,
+        mismatches: Vec::new(),
+        failures: Vec::new(),
+        batch_times: TimingSummary::new(runs.iter().map(|r| r.elapsed).collect()),
+        message_times: TimingSummary::new(
+            runs.iter()
+                .flat_map(|r| r.message_times.iter().copied())
+                .collect(),
+        ),
+        elapsed_nanos: elapsed.as_nanos(),
+        peak_rss_bytes: peak_rss_bytes(),
+        max_instance_memory_bytes: stats
+            .map(|s| s.lock().unwrap().exec.max_instance_memory_bytes)
+            .unwrap_or_default(),
+    };
+    for run in runs {
+        let expected = &range.batches[run.batch];
+        match run.result {
+            Ok(root) if root == expected.post_state_root => report.matched += 1,
+            Ok(root) => report.mismatches.push(BatchMismatch {
+                batch: run.batch,
+                epoch: expected.epoch,
+                expected: expected.post_state_root,
+                actual: root,
+            }),
+            Err(e) => report.failures.push(BatchFailure {
+                batch: run.batch,
+                epoch: expected.epoch,
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+    report.mismatches.sort_by_key(|m| m.batch);
+    report.failures.sort_by_key(|e| e.batch);
+    report
+}
+
+/// Loads the CAR into a new blockstore.
+fn load_blockstore(car: &[u8]) -> anyhow::Result<MemoryBlockstore> {
+    let bs = MemoryBlockstore::new();
+    block_on(load_car(&bs, car)).context("failed to load the CAR")?;
+    Ok(bs)
+}
+
+/// Replays a batch, returning the outcome and the blockstore (unless the machine was poisoned).
+#[allow(clippy::too_many_arguments)]
+fn replay_batch(
+    config: &SoakConfig,
+    engines: &MultiEngine,
+    bs: MemoryBlockstore,
+    range: &MessageRange,
+    batch: usize,
+    stats: TestStatsRef,
+    tracer: Option<Arc<TestTraceExporter>>,
+    run_index: usize,
+) -> (BatchRun, Option<MemoryBlockstore>) {
+    let start = Instant::now();
+    let mut message_times = Vec::new();
+    let mut traces: Vec<TestTrace> = Vec::new();
+    let mut blockstore = None;
+
+    let apply = || -> anyhow::Result<Cid> {
+        let b = &range.batches[batch];
+        let machine = TestMachine::new_for_batch(
+            range,
+            b,
+            bs,
+            stats,
+            tracer.is_some(),
+            config.bundled_actors,
+        )?;
+        let engine = engines
+            .get(&machine.context().network)
+            .map_err(|e| anyhow!(e))?;
+        engine.acquire().preload(
+            machine.blockstore(),
+            machine.builtin_actors().builtin_actor_codes(),
+        )?;
+
+        let mut exec: DefaultExecutor<TestKernel> = DefaultExecutor::new(engine, machine)?;
+        for (i, m) in b.messages.iter().enumerate() {
+            let msg: Message = from_slice(&m.bytes)?;
+            let (kind, raw_length) = if m.implicit {
+                (ApplyKind::Implicit, m.bytes.len())
+            } else if msg.from.protocol() == Protocol::Secp256k1 {
+                // 65 bytes signature + 1 byte type + 3 bytes for field info.
+                (ApplyKind::Explicit, m.bytes.len() + SECP_SIG_LEN + 4)
+            } else {
+                (ApplyKind::Explicit, m.bytes.len())
+            };
+
+            let start = Instant::now();
+            let ret = exec
+                .execute_message(msg, kind, raw_length)
+                .with_context(|| format!("failed to apply message {}", i))?;
+            message_times.push(start.elapsed());
+            if tracer.is_some() {
+                traces.push((start.elapsed(), ret));
+            }
+        }
+
+        let root = exec.flush().context("flushing executor failed")?;
+        let machine = exec.into_machine().context("machine poisoned")?;
+        blockstore = Some(machine.into_store().into_inner());
+        Ok(root)
+    };
+    let result = apply();
+    let elapsed = start.elapsed();
+
+    if let (Some(tracer), true) = (tracer, result.is_ok()) {
+        let path = PathBuf::from(format!("batch-{}", batch));
+        if let Err(e) = tracer.export_variant(path, run_index.to_string(), traces) {
+            log::error!("failed to export traces for batch {}: {}", batch, e);
+        }
+    }
+
+    let run = BatchRun {
+        batch,
+        elapsed,
+        message_times,
+        result,
+    };
+    (run, blockstore)
+}
+
+/// Returns the peak resident set size of the process, on Linux.
+fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib << 10)
+}
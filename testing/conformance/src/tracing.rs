@@ -37,7 +37,7 @@ pub struct TestMessageTombstone {
 ///
 /// Probably only the compute part of gas can have a relation to time,
 /// but it contains both so we can differentiate and see what happened.
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestGasCharge {
     pub name: String,
     pub compute_gas: i64,
@@ -13,6 +13,8 @@ use anyhow::{anyhow, Context as _};
 use cid::Cid;
 use flate2::bufread::GzDecoder;
 use futures::AsyncRead;
+use fvm::gas::GasCharge;
+use fvm::trace::{commitment, ExecutionEvent, TraceCommitment};
 use fvm_ipld_blockstore::MemoryBlockstore;
 use fvm_ipld_car::load_car;
 use fvm_ipld_encoding::tuple::*;
@@ -22,6 +24,14 @@ use fvm_shared::receipt::Receipt;
 use fvm_shared::ActorID;
 use serde::{Deserialize, Serialize, Deserializer, Serializer};
 
+use crate::tracing::TestGasCharge;
+
+/// The latest version of the test vector schema supported by the runner.
+///
+/// Version 2 adds the expected events roots of the receipts (checked for v2 vectors only), and
+/// optionally the expected gas traces of the messages ([`PostConditions::gas_traces`]).
+pub const SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct StateTreeVector {
     #[serde(with = "super::cidjson")]
@@ -63,10 +73,64 @@ pub struct PreConditions {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct PostConditions {
     pub state_tree: StateTreeVector,
+    /// The expected receipts. Since schema v2, their events roots are checked too.
     #[serde(with = "message_receipt_vec")]
     pub receipts: Vec<Receipt>,
     #[serde(default, with = "super::cidjson::vec")]
     pub receipts_roots: Vec<Cid>,
+    /// The expected gas traces of the messages, if recorded (schema v2).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gas_traces: Vec<Option<GasTraceVector>>,
+}
+
+/// The expected gas trace of a message.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GasTraceVector {
+    /// The digest of the message's gas charges (see [`gas_trace_digest`]).
+    #[serde(with = "base64_bytes")]
+    pub digest: Vec<u8>,
+    /// The gas charges themselves, used to report where the trace diverges.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charges: Option<Vec<TestGasCharge>>,
+}
+
+impl GasTraceVector {
+    /// Records the gas trace of a message from its execution trace, optionally including the
+    /// charges themselves (otherwise, only the digest is recorded).
+    pub fn from_trace(trace: &[ExecutionEvent], include_charges: bool) -> Self {
+        GasTraceVector {
+            digest: gas_trace_digest(trace).to_vec(),
+            charges: include_charges.then(|| {
+                gas_charges(trace)
+                    .map(|charge| TestGasCharge {
+                        name: charge.name.to_string(),
+                        compute_gas: charge.compute_gas.as_milligas(),
+                        other_gas: charge.other_gas.as_milligas(),
+                        elapsed_nanos: None,
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Returns the gas charges in an execution trace.
+pub fn gas_charges(trace: &[ExecutionEvent]) -> impl Iterator<Item = &GasCharge> {
+    trace.iter().filter_map(|event| match event {
+        ExecutionEvent::GasCharge(charge) => Some(charge),
+        _ => None,
+    })
+}
+
+/// Computes the digest of the gas charges in an execution trace: a
+/// [trace commitment](fvm::trace::commitment) over the gas charges alone, so it only changes if
+/// the charges (names and amounts) change.
+pub fn gas_trace_digest(trace: &[ExecutionEvent]) -> TraceCommitment {
+    let charges: Vec<_> = gas_charges(trace)
+        .cloned()
+        .map(ExecutionEvent::GasCharge)
+        .collect();
+    commitment(&charges)
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -142,6 +206,11 @@ pub struct MessageAddress {
 pub struct MessageVector {
     pub class: String,
 
+    /// The version of the vector schema (see [`SCHEMA_VERSION`]). Vectors without a version are
+    /// version 1.
+    #[serde(default = "MessageVector::default_schema_version")]
+    pub schema_version: u32,
+
     pub chain_id: Option<u64>,
 
     pub selector: Option<Selector>,
@@ -204,7 +273,28 @@ impl MessageVector {
         }
 
         let vector_json = serde_json::to_string(&vector)?;
-        Ok(serde_json::from_str(&vector_json)?)
+        let vector: Self = serde_json::from_str(&vector_json)?;
+        if vector.schema_version > SCHEMA_VERSION {
+            return Err(anyhow!(
+                "unsupported test vector schema version: {}",
+                vector.schema_version
+            ));
+        }
+        Ok(vector)
+    }
+
+    fn default_schema_version() -> u32 {
+        1
+    }
+
+    /// Returns true if the vector records the events roots of its receipts.
+    pub fn checks_events_roots(&self) -> bool {
+        self.schema_version >= 2
+    }
+
+    /// Returns true if the vector records the gas traces of (some of) its messages.
+    pub fn has_gas_traces(&self) -> bool {
+        self.postconditions.gas_traces.iter().any(Option::is_some)
     }
 
     /// Returns true if the vector is supported.
@@ -245,7 +335,7 @@ pub struct ApplyMessage {
     pub epoch_offset: Option<ChainEpoch>,
 }
 
-mod base64_bytes {
+pub(crate) mod base64_bytes {
     use std::borrow::Cow;
 
     use serde::de;
@@ -281,6 +371,12 @@ mod message_receipt_vec {
         #[serde(rename = "return", with = "base64_bytes")]
         return_value: Vec<u8>,
         gas_used: i64,
+        #[serde(
+            default,
+            with = "crate::cidjson::opt",
+            skip_serializing_if = "Option::is_none"
+        )]
+        events_root: Option<Cid>,
     }
 
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Receipt>, D::Error>
@@ -293,7 +389,7 @@ mod message_receipt_vec {
                 exit_code: v.exit_code,
                 return_data: RawBytes::new(v.return_value),
                 gas_used: v.gas_used,
-                events_root: None,
+                events_root: v.events_root,
             })
             .collect())
     }
@@ -308,6 +404,7 @@ mod message_receipt_vec {
                 exit_code: v.exit_code,
                 return_value: v.return_data.clone().into(),
                 gas_used: v.gas_used,
+                events_root: v.events_root,
             })
             .collect();
         output.serialize(serializer)
@@ -362,3 +459,144 @@ mod address_vec {
 //         ChainMessage::Unsigned(msg)
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use fvm::gas::Gas;
+    use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::IDENTITY_HASH;
+    use serde_json::Value;
+
+    use super::*;
+
+    fn cid(data: &[u8]) -> Cid {
+        Cid::new_v1(
+            DAG_CBOR,
+            multihash::MultihashGeneric::<64>::wrap(IDENTITY_HASH, data).unwrap(),
+        )
+    }
+
+    fn vector() -> MessageVector {
+        let trace = vec![
+            ExecutionEvent::GasCharge(GasCharge::new("OnChainMessage", Gas::new(10), Gas::new(2))),
+            ExecutionEvent::GasCharge(GasCharge::new(
+                "OnMethodInvocation",
+                Gas::new(5),
+                Gas::zero(),
+            )),
+        ];
+        MessageVector {
+            class: "message".into(),
+            schema_version: SCHEMA_VERSION,
+            chain_id: None,
+            selector: None,
+            meta: None,
+            car: Vec::new(),
+            preconditions: PreConditions {
+                state_tree: StateTreeVector {
+                    root_cid: cid(b"pre"),
+                },
+                basefee: None,
+                circ_supply: None,
+                variants: Vec::new(),
+            },
+            apply_messages: Vec::new(),
+            postconditions: PostConditions {
+                state_tree: StateTreeVector {
+                    root_cid: cid(b"post"),
+                },
+                receipts: vec![Receipt {
+                    exit_code: ExitCode::OK,
+                    return_data: RawBytes::default(),
+                    gas_used: 15,
+                    events_root: Some(cid(b"events")),
+                }],
+                receipts_roots: Vec::new(),
+                gas_traces: vec![Some(GasTraceVector::from_trace(&trace, true))],
+            },
+            skip_compare_gas_used: false,
+            skip_compare_addresses: None,
+            skip_compare_actor_ids: None,
+            additional_compare_addresses: None,
+            randomness: Vec::new(),
+            tipset_cids: None,
+        }
+    }
+
+    /// Writes the vector to a file, and loads it back.
+    fn load(name: &str, vector: &Value) -> anyhow::Result<MessageVector> {
+        let dir = std::env::temp_dir().join(format!("fvm-vector-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, serde_json::to_vec(vector).unwrap()).unwrap();
+        MessageVector::from_file(&path)
+    }
+
+    #[test]
+    fn schema_v1() {
+        // A v1 vector has no version, gas traces, or events roots.
+        let mut json = serde_json::to_value(vector()).unwrap();
+        let fields = json.as_object_mut().unwrap();
+        fields.remove("schema_version");
+        let post = fields["postconditions"].as_object_mut().unwrap();
+        post.remove("gas_traces");
+        post["receipts"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("events_root");
+
+        let v = load("v1.json", &json).unwrap();
+        assert_eq!(v.schema_version, 1);
+        assert!(!v.checks_events_roots());
+        assert!(!v.has_gas_traces());
+        assert_eq!(v.postconditions.receipts[0].events_root, None);
+    }
+
+    #[test]
+    fn schema_v2() {
+        let expected = vector();
+        let v = load("v2.json", &serde_json::to_value(&expected).unwrap()).unwrap();
+        assert_eq!(v.schema_version, 2);
+        assert!(v.checks_events_roots());
+        assert!(v.has_gas_traces());
+        assert_eq!(v.postconditions.receipts, expected.postconditions.receipts);
+
+        let (trace, expected_trace) = (
+            v.postconditions.gas_traces[0].as_ref().unwrap(),
+            expected.postconditions.gas_traces[0].as_ref().unwrap(),
+        );
+        assert_eq!(trace.digest, expected_trace.digest);
+        let charges: Vec<_> = trace
+            .charges
+            .iter()
+            .flatten()
+            .map(|c| (c.name.as_str(), c.compute_gas, c.other_gas))
+            .collect();
+        assert_eq!(
+            charges,
+            vec![
+                ("OnChainMessage", 10000, 2000),
+                ("OnMethodInvocation", 5000, 0)
+            ]
+        );
+
+        // The charges are optional.
+        let mut json = serde_json::to_value(&expected).unwrap();
+        json["postconditions"]["gas_traces"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("charges");
+        let v = load("v2-digest.json", &json).unwrap();
+        let trace = v.postconditions.gas_traces[0].as_ref().unwrap();
+        assert_eq!(trace.digest, expected_trace.digest);
+        assert!(trace.charges.is_none());
+    }
+
+    #[test]
+    fn unsupported_schema() {
+        let mut json = serde_json::to_value(vector()).unwrap();
+        json["schema_version"] = (SCHEMA_VERSION + 1).into();
+        assert!(load("v3.json", &json).is_err());
+    }
+}
@@ -8,21 +8,28 @@ use anyhow::anyhow;
 use cid::Cid;
 use futures::executor::block_on;
 use fvm::call_manager::{CallManager, DefaultCallManager, FinishRet, InvocationResult};
-use fvm::engine::Engine;
-use fvm::gas::{price_list_by_network_version, Gas, GasTimer, GasTracker, PriceList};
+use fvm::engine::{CodeInfo, Engine};
+use fvm::gas::{
+    price_list_by_network_version, Gas, GasTimer, GasTracker, InstructionClock, PriceList,
+};
 use fvm::kernel::*;
 use fvm::machine::limiter::MemoryLimiter;
-use fvm::machine::{DefaultMachine, Machine, MachineContext, Manifest, NetworkConfig};
+use fvm::machine::{
+    DefaultMachine, HeadCache, Machine, MachineContext, Manifest, NetworkConfig, Prefetcher,
+};
 use fvm::state_tree::{ActorState, StateTree};
+use fvm::trace::ExecutionEvent;
 use fvm::DefaultKernel;
 use fvm_ipld_blockstore::MemoryBlockstore;
 use fvm_ipld_car::load_car_unchecked;
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::crypto::merkle::MerkleProof;
 use fvm_shared::crypto::signature::{
     SignatureType, SECP_PUB_LEN, SECP_SIG_LEN, SECP_SIG_MESSAGE_HASH_SIZE,
 };
+use fvm_shared::crypto::snark::SnarkVerifyInfo;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::event::{ActorEvent, StampedEvent};
 use fvm_shared::piece::PieceInfo;
@@ -31,12 +38,15 @@ use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
     WindowPoStVerifyInfo,
 };
-use fvm_shared::sys::SendFlags;
+use fvm_shared::state::StateQuery;
+use fvm_shared::sys::{FlagOptions, SendFlags};
+use fvm_shared::token::TokenTransfer;
 use fvm_shared::version::NetworkVersion;
 use fvm_shared::{ActorID, MethodNum, TOTAL_FILECOIN};
 use multihash::MultihashGeneric;
 
 use crate::externs::TestExterns;
+use crate::soak::{MessageBatch, MessageRange};
 use crate::vector::{MessageVector, Variant};
 
 const DEFAULT_BASE_FEE: u64 = 100;
@@ -127,6 +137,10 @@ impl TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
         }
         mc.set_base_fee(base_fee);
         mc.tracing = tracing;
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = &*crate::driver::FAULT_CONFIG {
+            mc.inject_faults(faults.clone());
+        }
 
         let machine = DefaultMachine::new(&mc, blockstore, externs).unwrap();
 
@@ -148,6 +162,64 @@ impl TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
         Ok(machine)
     }
 
+    /// Creates a machine for replaying a batch of chain messages on top of the batch's pre-state
+    /// root. If `bundled_actors` is set, the builtin actors are replaced with the bundled ones.
+    pub fn new_for_batch(
+        range: &MessageRange,
+        batch: &MessageBatch,
+        blockstore: MemoryBlockstore,
+        stats: TestStatsRef,
+        tracing: bool,
+        bundled_actors: bool,
+    ) -> anyhow::Result<TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>>> {
+        let network_version = NetworkVersion::try_from(range.network_version)
+            .map_err(|_| anyhow!("unrecognized network version"))?;
+
+        let mut externs = TestExterns::new(&range.randomness);
+        externs.tipset_cids = range.tipset_cids.clone();
+
+        let mut nc = NetworkConfig::new(network_version);
+        if let Some(chain_id) = range.chain_id {
+            nc.chain_id = chain_id.into();
+        }
+        if bundled_actors {
+            let nv_actors = TestMachine::import_actors(&blockstore);
+            let builtin_actors = *nv_actors
+                .get(&network_version)
+                .ok_or_else(|| anyhow!("no builtin actors index for NV {network_version}"))?;
+            nc.override_actors(builtin_actors);
+        }
+
+        let epoch = batch.epoch;
+        let mut mc = nc.for_epoch(
+            epoch,
+            batch.timestamp.unwrap_or((epoch * 30) as u64),
+            batch.pre_state_root,
+        );
+        mc.set_base_fee(
+            batch
+                .base_fee
+                .map(TokenAmount::from_atto)
+                .unwrap_or_else(|| TokenAmount::from_atto(DEFAULT_BASE_FEE)),
+        );
+        mc.tracing = tracing;
+
+        let machine = DefaultMachine::new(&mc, blockstore, externs)?;
+        let price_list = machine.context().price_list.clone();
+
+        Ok(TestMachine {
+            machine: Box::new(machine),
+            data: TestData {
+                circ_supply: batch
+                    .circ_supply
+                    .map(TokenAmount::from_atto)
+                    .unwrap_or_else(|| TOTAL_FILECOIN.clone()),
+                price_list,
+            },
+            stats,
+        })
+    }
+
     pub fn import_actors(blockstore: &MemoryBlockstore) -> BTreeMap<NetworkVersion, Cid> {
         let bundles = [(NetworkVersion::V18, actors_v10::BUNDLE_CAR)];
         bundles
@@ -177,6 +249,10 @@ where
         self.machine.context()
     }
 
+    fn context_mut(&mut self) -> &mut MachineContext {
+        self.machine.context_mut()
+    }
+
     fn externs(&self) -> &Self::Externs {
         self.machine.externs()
     }
@@ -209,6 +285,14 @@ where
         self.machine.flush()
     }
 
+    fn join_flushes(&mut self) -> Result<()> {
+        self.machine.join_flushes()
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.machine.reset()
+    }
+
     fn machine_id(&self) -> &str {
         self.machine.machine_id()
     }
@@ -224,6 +308,14 @@ where
     fn commit_events(&self, events: &[StampedEvent]) -> Result<Option<Cid>> {
         self.machine.commit_events(events)
     }
+
+    fn head_cache(&self) -> Option<&HeadCache> {
+        self.machine.head_cache()
+    }
+
+    fn prefetcher(&self) -> Option<&Prefetcher> {
+        self.machine.prefetcher()
+    }
 }
 
 /// A CallManager that wraps kernels in an InterceptKernel.
@@ -241,7 +333,7 @@ where
     fn new(
         machine: Self::Machine,
         engine: Engine,
-        gas_limit: i64,
+        gas_limit: Option<i64>,
         origin: ActorID,
         origin_address: Address,
         nonce: u64,
@@ -273,6 +365,20 @@ where
             .send::<TestKernel<K>>(from, to, method, params, value, gas_limit)
     }
 
+    fn delegate_call<K: Kernel<CallManager = Self>>(
+        &mut self,
+        from: ActorID,
+        to: ActorID,
+        code: Cid,
+        method: MethodNum,
+        params: Option<Block>,
+        value: &TokenAmount,
+        gas_limit: Option<Gas>,
+    ) -> Result<InvocationResult> {
+        self.0
+            .delegate_call::<TestKernel<K>>(from, to, code, method, params, value, gas_limit)
+    }
+
     fn with_transaction(
         &mut self,
         read_only: bool,
@@ -374,6 +480,42 @@ where
     fn append_event(&mut self, evt: StampedEvent) {
         self.0.append_event(evt)
     }
+
+    fn trace(&mut self, event: ExecutionEvent) {
+        self.0.trace(event)
+    }
+
+    fn event_bytes(&self) -> usize {
+        self.0.event_bytes()
+    }
+
+    fn randomness_cache(&self) -> &RandomnessCache {
+        self.0.randomness_cache()
+    }
+
+    fn dev_randomness(&self) -> Option<&DevRandomness> {
+        self.0.dev_randomness()
+    }
+
+    fn syscall_memo(&self) -> &SyscallMemo {
+        self.0.syscall_memo()
+    }
+
+    fn link_tracker(&self) -> &LinkTracker {
+        self.0.link_tracker()
+    }
+
+    fn link_tracker_mut(&mut self) -> &mut LinkTracker {
+        self.0.link_tracker_mut()
+    }
+
+    fn hashers(&self) -> &HasherRegistry {
+        self.0.hashers()
+    }
+
+    fn flags(&self) -> &FlagRegistry {
+        self.0.flags()
+    }
 }
 
 /// A kernel for intercepting syscalls.
@@ -462,6 +604,10 @@ where
         self.0.get_code_cid_for_type(typ)
     }
 
+    fn get_code_info(&self, code_cid: &Cid) -> Result<CodeInfo> {
+        self.0.get_code_info(code_cid)
+    }
+
     #[cfg(feature = "m2-native")]
     fn install_actor(&mut self, _code_id: Cid) -> Result<()> {
         Ok(())
@@ -471,6 +617,10 @@ where
         todo!()
     }
 
+    fn nonce_of(&self, actor_id: ActorID) -> Result<u64> {
+        self.0.nonce_of(actor_id)
+    }
+
     fn lookup_delegated_address(&self, actor_id: ActorID) -> Result<Option<Address>> {
         self.0.lookup_delegated_address(actor_id)
     }
@@ -501,6 +651,18 @@ where
     fn block_stat(&self, id: BlockId) -> Result<BlockStat> {
         self.0.block_stat(id)
     }
+
+    fn block_close(&mut self, id: BlockId) -> Result<()> {
+        self.0.block_close(id)
+    }
+
+    fn block_graph_stat(&mut self, root: &Cid, max_blocks: u32) -> Result<GraphStat> {
+        self.0.block_graph_stat(root, max_blocks)
+    }
+
+    fn block_prefetch(&self, cid: &Cid) -> Result<()> {
+        self.0.block_prefetch(cid)
+    }
 }
 
 impl<M, C, K> CircSupplyOps for TestKernel<K>
@@ -526,6 +688,35 @@ where
         self.0.hash(code, data)
     }
 
+    // forwarded
+    fn hash_init(&self, code: u64) -> Result<HasherId> {
+        self.0.hash_init(code)
+    }
+
+    // forwarded
+    fn hash_update(&self, hasher: HasherId, data: &[u8]) -> Result<()> {
+        self.0.hash_update(hasher, data)
+    }
+
+    // forwarded
+    fn hash_finalize(&self, hasher: HasherId) -> Result<MultihashGeneric<64>> {
+        self.0.hash_finalize(hasher)
+    }
+
+    // forwarded
+    fn verify_merkle_proof(&self, proof: &MerkleProof) -> Result<bool> {
+        self.0.verify_merkle_proof(proof)
+    }
+
+    // forwarded
+    fn verify_groth16(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool> {
+        self.0.verify_groth16(vk, info)
+    }
+
+    fn verify_plonk(&self, vk: BlockId, info: &SnarkVerifyInfo) -> Result<bool> {
+        self.0.verify_plonk(vk, info)
+    }
+
     // forwarded
     fn compute_unsealed_sector_cid(
         &self,
@@ -645,6 +836,14 @@ where
     fn gas_available(&self) -> Gas {
         self.0.gas_available()
     }
+
+    fn record_syscall(&self, module: &'static str, name: &'static str) {
+        self.0.record_syscall(module, name)
+    }
+
+    fn instruction_clock(&self) -> Option<&InstructionClock> {
+        self.0.instruction_clock()
+    }
 }
 
 impl<M, C, K> MessageOps for TestKernel<K>
@@ -671,6 +870,10 @@ where
     fn tipset_cid(&self, epoch: ChainEpoch) -> Result<Cid> {
         self.0.tipset_cid(epoch)
     }
+
+    fn read_state(&self, query: &StateQuery) -> Result<Option<Vec<u8>>> {
+        self.0.read_state(query)
+    }
 }
 
 impl<M, C, K> RandomnessOps for TestKernel<K>
@@ -698,6 +901,14 @@ where
         self.0
             .get_randomness_from_beacon(personalization, rand_epoch, entropy)
     }
+
+    fn get_dev_randomness(
+        &self,
+        personalization: i64,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.0.get_dev_randomness(personalization, entropy)
+    }
 }
 
 impl<M, C, K> SelfOps for TestKernel<K>
@@ -721,6 +932,10 @@ where
     fn self_destruct(&mut self, beneficiary: &Address) -> Result<()> {
         self.0.self_destruct(beneficiary)
     }
+
+    fn self_state_stat(&mut self) -> Result<GraphStat> {
+        self.0.self_state_stat()
+    }
 }
 
 impl<M, C, K> SendOps for TestKernel<K>
@@ -741,6 +956,81 @@ where
         self.0
             .send(recipient, method, params, value, gas_limit, flags)
     }
+
+    fn delegate_call(
+        &mut self,
+        code: &Cid,
+        method: u64,
+        params: BlockId,
+        gas_limit: Option<Gas>,
+        flags: SendFlags,
+    ) -> Result<SendResult> {
+        self.0.delegate_call(code, method, params, gas_limit, flags)
+    }
+}
+
+impl<M, C, K> ReturnOps for TestKernel<K>
+where
+    C: CallManager<Machine = TestMachine<M>>,
+    K: Kernel<CallManager = TestCallManager<C>>,
+    M: Machine,
+{
+    fn return_push(&mut self, data: Vec<u8>) -> Result<()> {
+        self.0.return_push(data)
+    }
+
+    fn return_pop(&mut self) -> Result<Vec<u8>> {
+        self.0.return_pop()
+    }
+
+    fn return_len(&self) -> Option<u32> {
+        self.0.return_len()
+    }
+}
+
+impl<M, C, K> SyncOps for TestKernel<K>
+where
+    C: CallManager<Machine = TestMachine<M>>,
+    K: Kernel<CallManager = TestCallManager<C>>,
+    M: Machine,
+{
+    fn set_flag(&mut self, key: u64, options: FlagOptions) -> Result<bool> {
+        self.0.set_flag(key, options)
+    }
+
+    fn clear_flag(&mut self, key: u64) -> Result<bool> {
+        self.0.clear_flag(key)
+    }
+}
+
+impl<M, C, K> CronOps for TestKernel<K>
+where
+    C: CallManager<Machine = TestMachine<M>>,
+    K: Kernel<CallManager = TestCallManager<C>>,
+    M: Machine,
+{
+    fn cron_register(&mut self, method: MethodNum) -> Result<()> {
+        self.0.cron_register(method)
+    }
+
+    fn cron_unregister(&mut self, method: MethodNum) -> Result<()> {
+        self.0.cron_unregister(method)
+    }
+}
+
+impl<M, C, K> TokenOps for TestKernel<K>
+where
+    C: CallManager<Machine = TestMachine<M>>,
+    K: Kernel<CallManager = TestCallManager<C>>,
+    M: Machine,
+{
+    fn token_transfer_hook(
+        &mut self,
+        transfer: &TokenTransfer,
+        gas_limit: Option<Gas>,
+    ) -> Result<TokenTransferResult> {
+        self.0.token_transfer_hook(transfer, gas_limit)
+    }
 }
 
 impl<K> LimiterOps for TestKernel<K>
@@ -869,4 +1159,12 @@ where
         // We don't expect this to be called explicitly.
         panic!("explicit call to grow_memory")
     }
+
+    fn read_block(&mut self, size: usize) -> bool {
+        self.inner.read_block(size)
+    }
+
+    fn limit_state_reads(&mut self, max_blocks: usize, max_bytes: usize) {
+        self.inner.limit_state_reads(max_blocks, max_bytes)
+    }
 }
@@ -0,0 +1,42 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Snapshots the exit codes of messages failing with each kind of execution error, so the mapping
+//! can't change without an explicit snapshot update. Run with `UPDATE_SNAPSHOTS=1` to update the
+//! snapshot after an intended (network version gated) change.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::anyhow;
+use fvm::kernel::{ExecutionError, SyscallError};
+use fvm_shared::error::ErrorNumber;
+
+fn error_codes() -> String {
+    let mut out = String::new();
+    let mut errors = vec![
+        ("OutOfGas".to_owned(), ExecutionError::OutOfGas),
+        ("Fatal".to_owned(), ExecutionError::Fatal(anyhow!("fatal"))),
+    ];
+    for number in ErrorNumber::ALL {
+        errors.push((
+            format!("Syscall({:?}={})", number, number.value()),
+            ExecutionError::Syscall(SyscallError::new(number, "error")),
+        ));
+    }
+    for (name, error) in errors {
+        writeln!(out, "{} => {}", name, error.exit_code().value()).unwrap();
+    }
+    out
+}
+
+#[test]
+fn error_code_stability() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots/error_codes.txt");
+    let actual = error_codes();
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, expected, "error to exit code mappings changed");
+}
@@ -176,6 +176,7 @@ pub async fn export_test_vector_file(
     }];
     let test_vector = MessageVector {
         class: String::from_str("message")?,
+        schema_version: 1,
         chain_id: Some(input.chain_id.as_u64()),
         selector: None,
         meta: Some(MetaData {
@@ -208,6 +209,7 @@ pub async fn export_test_vector_file(
             },
             receipts: vec![receipt],
             receipts_roots: vec![],
+            gas_traces: vec![],
         },
         skip_compare_gas_used: true,
         skip_compare_addresses: Some(vec![
@@ -471,7 +473,7 @@ pub fn consume_test_vector(
                     return_data,
                 }
             }
-            VariantResult::Failed { reason, id } => {
+            VariantResult::Failed { reason, id, .. } => {
                 report!("FAIL".white().on_red(), test_vector_name, id);
                 println!("\t|> reason: {:#}", reason);
                 VariantTestResult {
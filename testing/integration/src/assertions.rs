@@ -0,0 +1,161 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+//! Fluent assertions over message results and actor state.
+//!
+//! ```ignore
+//! let ret = executor.execute_message(msg, ApplyKind::Explicit, 100)?;
+//! ret.expect_ok()
+//!     .returns(42u64)
+//!     .expect_event(actor_id, entries);
+//! tester.expect_state(actor_id, |s: &State| assert_eq!(s.count, 1));
+//! ```
+//!
+//! On failure, assertions panic with the message's execution trace and failure information.
+
+use std::fmt::{Debug, Write as _};
+
+use fvm::executor::ApplyRet;
+use fvm::trace::ExecutionEvent;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_shared::error::ExitCode;
+use fvm_shared::event::{ActorEvent, StampedEvent};
+use fvm_shared::ActorID;
+
+/// Entry point for assertions over an [`ApplyRet`].
+pub trait ExpectApplyRet {
+    /// Asserts that the message exited with [`ExitCode::OK`].
+    fn expect_ok(&self) -> Expect<'_>;
+
+    /// Asserts that the message exited with the given exit code.
+    fn expect_exit(&self, code: ExitCode) -> Expect<'_>;
+}
+
+impl ExpectApplyRet for ApplyRet {
+    fn expect_ok(&self) -> Expect<'_> {
+        self.expect_exit(ExitCode::OK)
+    }
+
+    fn expect_exit(&self, code: ExitCode) -> Expect<'_> {
+        let expect = Expect { ret: self };
+        let actual = self.msg_receipt.exit_code;
+        if actual != code {
+            expect.fail(format_args!("expected exit code {}, got {}", code, actual));
+        }
+        expect
+    }
+}
+
+/// Chained assertions over an [`ApplyRet`], created by [`ExpectApplyRet`].
+#[derive(Clone, Copy)]
+pub struct Expect<'a> {
+    ret: &'a ApplyRet,
+}
+
+impl<'a> Expect<'a> {
+    /// Asserts that the message returned the given value.
+    pub fn returns<T>(self, expected: T) -> Self
+    where
+        T: DeserializeOwned + PartialEq + Debug,
+    {
+        let actual: T = self.return_value();
+        if actual != expected {
+            self.fail(format_args!(
+                "expected return value {:?}, got {:?}",
+                expected, actual
+            ));
+        }
+        self
+    }
+
+    /// Decodes and returns the message's return value.
+    pub fn return_value<T: DeserializeOwned>(self) -> T {
+        match self.ret.msg_receipt.return_data.deserialize() {
+            Ok(v) => v,
+            Err(e) => self.fail(format_args!("failed to decode return value: {}", e)),
+        }
+    }
+
+    /// Asserts that the message emitted the given event from the given actor.
+    pub fn expect_event(self, emitter: ActorID, event: impl Into<ActorEvent>) -> Self {
+        let expected = StampedEvent::new(emitter, event.into());
+        if !self.ret.events.contains(&expected) {
+            self.fail(format_args!(
+                "expected event {:?}, got events {:?}",
+                expected, self.ret.events
+            ));
+        }
+        self
+    }
+
+    /// Asserts that the message emitted exactly `count` events.
+    pub fn expect_event_count(self, count: usize) -> Self {
+        if self.ret.events.len() != count {
+            self.fail(format_args!(
+                "expected {} events, got {:?}",
+                count, self.ret.events
+            ));
+        }
+        self
+    }
+
+    /// Returns the underlying result.
+    pub fn ret(self) -> &'a ApplyRet {
+        self.ret
+    }
+
+    fn fail(self, reason: std::fmt::Arguments) -> ! {
+        panic!("{}\n{}", reason, describe(self.ret))
+    }
+}
+
+/// Formats the failure information and execution trace of a message for display.
+pub fn describe(ret: &ApplyRet) -> String {
+    let mut out = String::new();
+    if let Some(info) = &ret.failure_info {
+        let _ = writeln!(out, "{}", info);
+    }
+    if ret.exec_trace.is_empty() {
+        out.push_str("(no execution trace)\n");
+    } else {
+        out.push_str("execution trace:\n");
+        out.push_str(&format_trace(&ret.exec_trace));
+    }
+    out
+}
+
+/// Formats an execution trace as an indented call tree. Gas charges are omitted.
+pub fn format_trace(trace: &[ExecutionEvent]) -> String {
+    let mut out = String::new();
+    let mut depth: usize = 0;
+    for event in trace {
+        let line = match event {
+            ExecutionEvent::GasCharge(_) => continue,
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                value,
+                ..
+            } => {
+                depth += 1;
+                format!("-> {from} calls {to}::{method} (value {value})")
+            }
+            ExecutionEvent::CallReturn(code, data) => {
+                format!("<- returned {code} ({} bytes)", data.len())
+            }
+            ExecutionEvent::CallError(err) => format!("<- error: {err}"),
+            ExecutionEvent::TransferFailed(failure) => format!("!! {failure}"),
+            ExecutionEvent::InstructionCount(count) => format!("   executed {count} instructions"),
+            ExecutionEvent::MemoryGrowth { from, to } => format!("   memory grew {from} -> {to}"),
+            _ => continue,
+        };
+        let _ = writeln!(out, "{}{}", "  ".repeat(depth), line);
+        if matches!(
+            event,
+            ExecutionEvent::CallReturn(..) | ExecutionEvent::CallError(_)
+        ) {
+            depth = depth.saturating_sub(1);
+        }
+    }
+    out
+}
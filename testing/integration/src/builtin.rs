@@ -4,7 +4,7 @@ use anyhow::{Context, Result};
 use cid::Cid;
 use fvm::machine::Manifest;
 use fvm::state_tree::{ActorState, StateTree};
-use fvm::{init_actor, system_actor};
+use fvm::{cron_actor, init_actor, system_actor};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::CborStore;
 use fvm_shared::ActorID;
@@ -12,12 +12,12 @@ use multihash::Code;
 
 use crate::error::Error::{FailedToLoadManifest, FailedToSetActor, FailedToSetState};
 
-// Retrieve system, init and accounts actors code CID
+// Retrieve system, init, accounts, placeholder, eam and cron actors code CID
 pub fn fetch_builtin_code_cid(
     blockstore: &impl Blockstore,
     builtin_actors: &Cid,
     ver: u32,
-) -> Result<(Cid, Cid, Cid, Cid, Cid)> {
+) -> Result<(Cid, Cid, Cid, Cid, Cid, Cid)> {
     let manifest = Manifest::load(blockstore, builtin_actors, ver).context(FailedToLoadManifest)?;
     Ok((
         *manifest.get_system_code(),
@@ -25,6 +25,7 @@ pub fn fetch_builtin_code_cid(
         *manifest.get_account_code(),
         *manifest.get_placeholder_code(),
         *manifest.get_eam_code(),
+        *manifest.get_cron_code(),
     ))
 }
 
@@ -96,3 +97,27 @@ pub fn set_eam_actor(state_tree: &mut StateTree<impl Blockstore>, eam_code_cid:
         .map_err(anyhow::Error::from)
         .context(FailedToSetActor("eam actor".to_owned()))
 }
+
+pub fn set_cron_actor(
+    state_tree: &mut StateTree<impl Blockstore>,
+    cron_code_cid: Cid,
+    cron_state: cron_actor::State,
+) -> Result<()> {
+    let cron_state_cid = state_tree
+        .store()
+        .put_cbor(&cron_state, Code::Blake2b256)
+        .context(FailedToSetState("cron actor".to_owned()))?;
+
+    let cron_actor_state = ActorState {
+        code: cron_code_cid,
+        state: cron_state_cid,
+        sequence: 0,
+        balance: Default::default(),
+        delegated_address: None,
+    };
+
+    state_tree
+        .set_actor(cron_actor::CRON_ACTOR_ID, cron_actor_state)
+        .map_err(anyhow::Error::from)
+        .context(FailedToSetActor("cron actor".to_owned()))
+}
@@ -1,8 +1,13 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
-use anyhow::anyhow;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
 use cid::Cid;
 use futures::executor::block_on;
+use futures::io::AllowStdIo;
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_car::load_car_unchecked;
 
@@ -13,3 +18,11 @@ pub fn import_bundle(blockstore: &impl Blockstore, bundle: &[u8]) -> anyhow::Res
         _ => Err(anyhow!("multiple root CIDs in bundle")),
     }
 }
+
+// Import a CAR file (e.g., a state snapshot), returning its roots
+pub fn import_car_file(blockstore: &impl Blockstore, path: &Path) -> anyhow::Result<Vec<Cid>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let reader = AllowStdIo::new(BufReader::new(file));
+    block_on(async { load_car_unchecked(blockstore, reader).await })
+        .with_context(|| format!("failed to import {}", path.display()))
+}
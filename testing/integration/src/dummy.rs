@@ -1,7 +1,7 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
 use cid::Cid;
-use fvm::externs::{Chain, Consensus, Externs, Rand};
+use fvm::externs::{Chain, Consensus, Externs, Rand, StateReader};
 use fvm_ipld_encoding::DAG_CBOR;
 use fvm_shared::IDENTITY_HASH;
 use multihash::Multihash;
@@ -62,3 +62,5 @@ impl Chain for DummyExterns {
         ))
     }
 }
+
+impl StateReader for DummyExterns {}
@@ -1,7 +1,9 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+pub mod assertions;
 mod builtin;
 pub mod bundle;
 pub mod dummy;
 pub mod error;
+pub mod signer;
 pub mod tester;
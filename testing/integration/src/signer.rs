@@ -0,0 +1,158 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+use anyhow::Result;
+use bls_signatures::Serialize as _;
+use cid::Cid;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::crypto::signature::{Signature, SECP_SIG_LEN};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::{ActorID, MethodNum};
+
+use crate::tester::Account;
+
+/// Default gas limit for messages built by a [`SigningAccount`].
+pub const DEFAULT_GAS_LIMIT: i64 = 1_000_000_000;
+
+/// The type of key backing a [`SigningAccount`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Secp256k1,
+    Bls,
+}
+
+/// A private key for one of the supported signature types.
+pub enum PrivateKey {
+    Secp256k1(libsecp256k1::SecretKey),
+    Bls(bls_signatures::PrivateKey),
+}
+
+impl PrivateKey {
+    /// Returns the public key address for this key.
+    pub fn address(&self) -> Result<Address> {
+        Ok(match self {
+            PrivateKey::Secp256k1(sk) => {
+                Address::new_secp256k1(&libsecp256k1::PublicKey::from_secret_key(sk).serialize())?
+            }
+            PrivateKey::Bls(sk) => Address::new_bls(&sk.public_key().as_bytes())?,
+        })
+    }
+
+    /// Signs the data as Filecoin would: secp256k1 signatures are over the blake2b-256 hash of
+    /// the data, BLS signatures are over the data itself.
+    pub fn sign(&self, data: &[u8]) -> Signature {
+        match self {
+            PrivateKey::Secp256k1(sk) => {
+                let hash: [u8; 32] = blake2b_simd::Params::new()
+                    .hash_length(32)
+                    .to_state()
+                    .update(data)
+                    .finalize()
+                    .as_bytes()
+                    .try_into()
+                    .expect("fixed array size");
+                let (sig, recovery_id) =
+                    libsecp256k1::sign(&libsecp256k1::Message::parse(&hash), sk);
+
+                let mut signature = vec![0u8; SECP_SIG_LEN];
+                signature[..64].copy_from_slice(&sig.serialize());
+                signature[64] = recovery_id.serialize();
+                Signature::new_secp256k1(signature)
+            }
+            PrivateKey::Bls(sk) => Signature::new_bls(sk.sign(data).as_bytes()),
+        }
+    }
+}
+
+/// A funded account holding its private key, which builds messages with automatically assigned
+/// nonces and signs them.
+pub struct SigningAccount {
+    /// The account's actor ID.
+    pub id: ActorID,
+    /// The account's public key address.
+    pub address: Address,
+    key: PrivateKey,
+    nonce: u64,
+}
+
+impl SigningAccount {
+    pub fn new(id: ActorID, key: PrivateKey) -> Result<Self> {
+        Ok(SigningAccount {
+            id,
+            address: key.address()?,
+            key,
+            nonce: 0,
+        })
+    }
+
+    /// Returns the account as an `(id, address)` pair.
+    pub fn account(&self) -> Account {
+        (self.id, self.address)
+    }
+
+    /// Returns the nonce that will be assigned to the next message.
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Overrides the nonce that will be assigned to the next message, e.g., after a message
+    /// built by this account was never applied.
+    pub fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+
+    /// Returns the type of this account's key.
+    pub fn key_type(&self) -> KeyType {
+        match self.key {
+            PrivateKey::Secp256k1(_) => KeyType::Secp256k1,
+            PrivateKey::Bls(_) => KeyType::Bls,
+        }
+    }
+
+    /// Builds a message from this account with the next nonce, and increments the nonce.
+    pub fn message(
+        &mut self,
+        to: Address,
+        method_num: MethodNum,
+        params: RawBytes,
+        value: TokenAmount,
+    ) -> Message {
+        let msg = Message {
+            from: self.address,
+            to,
+            sequence: self.nonce,
+            method_num,
+            params,
+            value,
+            gas_limit: DEFAULT_GAS_LIMIT,
+            ..Message::default()
+        };
+        self.nonce += 1;
+        msg
+    }
+
+    /// Signs the message's CID with this account's key.
+    pub fn sign(&self, msg: &Message) -> Result<Signature> {
+        Ok(self.key.sign(&message_cid(msg)?.to_bytes()))
+    }
+
+    /// Builds and signs a message from this account with the next nonce. See
+    /// [`SigningAccount::message`].
+    pub fn signed_message(
+        &mut self,
+        to: Address,
+        method_num: MethodNum,
+        params: RawBytes,
+        value: TokenAmount,
+    ) -> Result<(Message, Signature)> {
+        let msg = self.message(to, method_num, params, value);
+        let sig = self.sign(&msg)?;
+        Ok((msg, sig))
+    }
+}
+
+/// Returns the CID of a message, which is what gets signed. See [`Message::cid`].
+pub fn message_cid(msg: &Message) -> Result<Cid> {
+    msg.cid()
+}
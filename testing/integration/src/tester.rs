@@ -1,30 +1,44 @@
 // Copyright 2021-2023 Protocol Labs
 // SPDX-License-Identifier: Apache-2.0, MIT
+use std::path::Path;
+
 use anyhow::{anyhow, Context, Result};
 use cid::Cid;
 use fvm::call_manager::DefaultCallManager;
 use fvm::engine::EnginePool;
-use fvm::executor::DefaultExecutor;
+use fvm::executor::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
 use fvm::externs::Externs;
-use fvm::machine::{DefaultMachine, Machine, MachineContext, NetworkConfig};
+use fvm::machine::{DefaultMachine, Machine, MachineBuilder, MachineContext, NetworkConfig};
 use fvm::state_tree::{ActorState, StateTree};
-use fvm::{init_actor, system_actor, DefaultKernel};
+use fvm::{cron_actor, init_actor, system_actor, DefaultKernel};
 use fvm_ipld_blockstore::{Block, Blockstore};
-use fvm_ipld_encoding::{ser, CborStore};
+use fvm_ipld_encoding::{de, from_slice, ser, CborStore};
 use fvm_shared::address::{Address, Protocol};
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
 use fvm_shared::state::StateTreeVersion;
 use fvm_shared::version::NetworkVersion;
-use fvm_shared::{ActorID, IPLD_RAW};
+use fvm_shared::{ActorID, MethodNum, IPLD_RAW};
 use lazy_static::lazy_static;
 use libsecp256k1::{PublicKey, SecretKey};
 use multihash::Code;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
-use crate::builtin::{fetch_builtin_code_cid, set_eam_actor, set_init_actor, set_sys_actor};
+use crate::builtin::{
+    fetch_builtin_code_cid, set_cron_actor, set_eam_actor, set_init_actor, set_sys_actor,
+};
+use crate::bundle::import_car_file;
 use crate::error::Error::{FailedToFlushTree, NoManifestInformation};
+use crate::signer::{KeyType, PrivateKey, SigningAccount};
 
 const DEFAULT_BASE_FEE: u64 = 100;
 
+/// Seed for the keys of signing accounts, distinct from the one used by `create_accounts` so
+/// the two never produce the same key.
+const SIGNER_SEED: u64 = 9;
+
 lazy_static! {
     pub static ref INITIAL_ACCOUNT_BALANCE: TokenAmount = TokenAmount::from_atto(10000);
 }
@@ -49,8 +63,12 @@ pub struct Tester<B: Blockstore + 'static, E: Externs + 'static> {
     code_cids: Vec<Cid>,
     // Executor used to interact with deployed actors.
     pub executor: Option<IntegrationExecutor<B, E>>,
+    // Builder of the current Machine, used to rebuild it at a later epoch.
+    machine_builder: Option<MachineBuilder>,
     // State tree constructed before instantiating the Machine
     pub state_tree: Option<StateTree<B>>,
+    // Source of keys for signing accounts.
+    signer_rng: ChaCha8Rng,
 }
 
 impl<B, E> Tester<B, E>
@@ -71,36 +89,96 @@ where
             };
 
         // Get sys and init actors code cid
-        let (sys_code_cid, init_code_cid, accounts_code_cid, placeholder_code_cid, eam_code_cid) =
-            fetch_builtin_code_cid(&blockstore, &manifest_data_cid, manifest_version)?;
+        let (
+            sys_code_cid,
+            init_code_cid,
+            accounts_code_cid,
+            placeholder_code_cid,
+            eam_code_cid,
+            cron_code_cid,
+        ) = fetch_builtin_code_cid(&blockstore, &manifest_data_cid, manifest_version)?;
 
         // Initialize state tree
         let init_state = init_actor::State::new_test(&blockstore);
         let mut state_tree = StateTree::new(blockstore, stv).map_err(anyhow::Error::from)?;
 
-        // Deploy init, sys, and eam actors
+        // Deploy init, sys, eam, and cron actors
         let sys_state = system_actor::State { builtin_actors };
         set_sys_actor(&mut state_tree, sys_state, sys_code_cid)?;
         set_init_actor(&mut state_tree, init_code_cid, init_state)?;
         set_eam_actor(&mut state_tree, eam_code_cid)?;
+        set_cron_actor(&mut state_tree, cron_code_cid, Default::default())?;
 
         Ok(Tester {
             nv,
             builtin_actors,
             executor: None,
+            machine_builder: None,
             code_cids: vec![],
             state_tree: Some(state_tree),
             accounts_code_cid,
             placeholder_code_cid,
+            signer_rng: ChaCha8Rng::seed_from_u64(SIGNER_SEED),
+        })
+    }
+
+    /// Creates a tester over an imported snapshot (e.g., of mainnet), for testing interactions with
+    /// live builtin actor state. The CAR at `path` is imported into `blockstore`, and the machine
+    /// will be built over the state tree at `state_root` with the builtin actors installed in its
+    /// system actor.
+    ///
+    /// The machine's epoch defaults to 0, so tests of time-sensitive actors should set the
+    /// snapshot's epoch with [`Tester::instantiate_machine_with_config`]. Existing actors can send
+    /// messages with [`Tester::impersonate`].
+    pub fn from_car(
+        nv: NetworkVersion,
+        path: impl AsRef<Path>,
+        state_root: Cid,
+        blockstore: B,
+    ) -> Result<Self> {
+        import_car_file(&blockstore, path.as_ref())?;
+        let state_tree =
+            StateTree::new_from_root(blockstore, &state_root).map_err(anyhow::Error::from)?;
+
+        // Live networks record the manifest's data in the system actor, while testers record the
+        // versioned manifest. The machine is always given the latter. The two are told apart by
+        // their shape: the manifest's data is a list of `(name, code)` pairs, which never decodes
+        // as a `(version, data)` pair.
+        let (sys_state, _) = system_actor::State::load(&state_tree).map_err(anyhow::Error::from)?;
+        let manifest = state_tree
+            .store()
+            .get(&sys_state.builtin_actors)?
+            .ok_or(NoManifestInformation(sys_state.builtin_actors))?;
+        let (builtin_actors, (manifest_version, manifest_data_cid)) =
+            match from_slice::<(u32, Cid)>(&manifest) {
+                Ok(manifest) => (sys_state.builtin_actors, manifest),
+                Err(_) => {
+                    let manifest = (1, sys_state.builtin_actors);
+                    let cid = state_tree.store().put_cbor(&manifest, Code::Blake2b256)?;
+                    (cid, manifest)
+                }
+            };
+
+        let (_, _, accounts_code_cid, placeholder_code_cid, _, _) =
+            fetch_builtin_code_cid(state_tree.store(), &manifest_data_cid, manifest_version)?;
+
+        Ok(Tester {
+            nv,
+            builtin_actors,
+            executor: None,
+            machine_builder: None,
+            code_cids: vec![],
+            state_tree: Some(state_tree),
+            accounts_code_cid,
+            placeholder_code_cid,
+            signer_rng: ChaCha8Rng::seed_from_u64(SIGNER_SEED),
         })
     }
 
     /// Creates new accounts in the testing context
     /// Inserts the specified number of accounts in the state tree, all with 1000 FIL，returning their IDs and Addresses.
     pub fn create_accounts<const N: usize>(&mut self) -> Result<[Account; N]> {
-        use rand::SeedableRng;
-
-        let rng = &mut rand_chacha::ChaCha8Rng::seed_from_u64(8);
+        let rng = &mut ChaCha8Rng::seed_from_u64(8);
 
         let mut ret: [Account; N] = [(0, Address::default()); N];
         for account in ret.iter_mut().take(N) {
@@ -110,6 +188,30 @@ where
         Ok(ret)
     }
 
+    /// Creates new accounts with the given key type, each holding its private key so it can sign
+    /// messages and track its own nonce. Each account is funded with the default initial balance.
+    pub fn create_signing_accounts<const N: usize>(
+        &mut self,
+        key_type: KeyType,
+    ) -> Result<[SigningAccount; N]> {
+        let mut ret = Vec::with_capacity(N);
+        for _ in 0..N {
+            let key = match key_type {
+                KeyType::Secp256k1 => {
+                    PrivateKey::Secp256k1(SecretKey::random(&mut self.signer_rng))
+                }
+                KeyType::Bls => {
+                    PrivateKey::Bls(bls_signatures::PrivateKey::generate(&mut self.signer_rng))
+                }
+            };
+            let (id, _) = self.make_account(key.address()?, INITIAL_ACCOUNT_BALANCE.clone())?;
+            ret.push(SigningAccount::new(id, key)?);
+        }
+        Ok(ret
+            .try_into()
+            .unwrap_or_else(|_| unreachable!("created exactly {} accounts", N)))
+    }
+
     pub fn set_account_sequence(&mut self, id: ActorID, new_sequence: u64) -> anyhow::Result<()> {
         let state_tree = self
             .state_tree
@@ -248,24 +350,25 @@ where
         // Consume the state tree and take the blockstore.
         let blockstore = state_tree.into_store();
 
-        let mut nc = NetworkConfig::new(self.nv);
-        nc.override_actors(self.builtin_actors);
-        nc.enable_actor_debugging();
-
-        // Custom configuration.
-        configure_nc(&mut nc);
-
-        let mut mc = nc.for_epoch(0, 0, state_root);
-        mc.set_base_fee(TokenAmount::from_atto(DEFAULT_BASE_FEE))
-            .enable_tracing();
-
-        // Custom configuration.
-        configure_mc(&mut mc);
-
-        let engine = EnginePool::new_default((&mc.network.clone()).into())?;
+        let mut builder = MachineBuilder::new(self.nv, state_root)?;
+        builder
+            .override_actors(self.builtin_actors)
+            .base_fee(TokenAmount::from_atto(DEFAULT_BASE_FEE))
+            .configure_network(|nc| {
+                nc.enable_actor_debugging();
+                // Custom configuration.
+                configure_nc(nc);
+            })
+            .configure_context(|mc| {
+                mc.enable_tracing();
+                // Custom configuration.
+                configure_mc(mc);
+            });
+
+        let engine = EnginePool::new_default((&builder.context().network).into())?;
         engine.acquire().preload(&blockstore, &self.code_cids)?;
 
-        let machine = DefaultMachine::new(&mc, blockstore, externs)?;
+        let machine = builder.build(blockstore, externs)?;
 
         let executor =
             DefaultExecutor::<DefaultKernel<DefaultCallManager<DefaultMachine<B, E>>>>::new(
@@ -273,10 +376,128 @@ where
             )?;
 
         self.executor = Some(executor);
+        self.machine_builder = Some(builder);
 
         Ok(())
     }
 
+    /// Applies `message` on behalf of its sender, which may be any existing actor (e.g., a
+    /// multisig or a miner's owner in an imported snapshot), without its keys.
+    ///
+    /// The message is applied as an _implicit_ message ([`ApplyKind::Implicit`]), so it's
+    /// gas-free: the sender's balance isn't charged for gas (execution is still limited by, and
+    /// reports gas used against, the message's gas limit), and its nonce is neither checked nor
+    /// incremented. The FVM doesn't verify signatures, so none is needed. Use
+    /// [`Tester::executor`] to apply explicit messages, which pay for gas.
+    pub fn impersonate(&mut self, message: Message) -> Result<ApplyRet> {
+        self.executor
+            .as_mut()
+            .ok_or_else(|| anyhow!("the machine must be instantiated to impersonate actors"))?
+            .execute_message(message, ApplyKind::Implicit, 0)
+    }
+
+    /// Registers `method` on the actor `receiver` to be invoked by cron on every epoch. Like the
+    /// other setup methods, this must be called before the machine is instantiated.
+    ///
+    /// Fails if the method is a send or a constructor, or if it's already registered.
+    pub fn register_cron_entry(&mut self, receiver: ActorID, method: MethodNum) -> Result<()> {
+        self.update_cron_entries(|state| state.register(receiver, method))
+    }
+
+    /// Stops cron from invoking `method` on the actor `receiver`. This must be called before the
+    /// machine is instantiated.
+    pub fn unregister_cron_entry(&mut self, receiver: ActorID, method: MethodNum) -> Result<()> {
+        self.update_cron_entries(|state| state.unregister(receiver, method))
+    }
+
+    fn update_cron_entries<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut cron_actor::State) -> fvm::kernel::Result<()>,
+    {
+        let state_tree = self
+            .state_tree
+            .as_mut()
+            .ok_or_else(|| anyhow!("cron entries must be set before instantiating the machine"))?;
+        let (mut state, _) = cron_actor::State::load(state_tree).map_err(anyhow::Error::from)?;
+        f(&mut state).map_err(anyhow::Error::from)?;
+        state.save(state_tree).map_err(anyhow::Error::from)
+    }
+
+    /// Runs cron for the current epoch, invoking every registered entry, and commits the result.
+    pub fn run_cron(&mut self) -> Result<ApplyRet> {
+        self.executor
+            .as_mut()
+            .ok_or_else(|| anyhow!("the machine must be instantiated to run cron"))?
+            .run_cron()
+    }
+
+    /// Flushes the current state and rebuilds the machine at the given (later) epoch, keeping the
+    /// rest of its configuration. Along with [`Tester::run_cron`], this drives time-based actor
+    /// logic one epoch at a time.
+    pub fn advance_epoch(&mut self, epoch: ChainEpoch, externs: E) -> Result<()> {
+        let mut builder = self
+            .machine_builder
+            .clone()
+            .ok_or_else(|| anyhow!("the machine must be instantiated to advance the epoch"))?;
+        let current = builder.context().epoch;
+        if epoch <= current {
+            return Err(anyhow!(
+                "epoch must advance: {} is not after the current epoch {}",
+                epoch,
+                current
+            ));
+        }
+
+        let executor = self
+            .executor
+            .take()
+            .ok_or_else(|| anyhow!("the executor has been taken"))?;
+        let engine = executor.engine_pool().clone();
+        let mut machine = executor
+            .into_machine()
+            .ok_or_else(|| anyhow!("the machine was poisoned"))?;
+        let state_root = machine
+            .flush()
+            .map_err(anyhow::Error::from)
+            .context(FailedToFlushTree)?;
+        let blockstore = machine.into_store().into_inner();
+
+        builder
+            .epoch(epoch)
+            .configure_context(|mc| mc.initial_state_root = state_root);
+        let machine = builder.build(blockstore, externs)?;
+
+        self.executor = Some(DefaultExecutor::new(engine, machine)?);
+        self.machine_builder = Some(builder);
+
+        Ok(())
+    }
+
+    /// Loads the state of the given actor and passes it to `f`, which is expected to make
+    /// assertions about it. Panics if the actor or its state can't be loaded.
+    pub fn expect_state<S, F>(&self, actor: ActorID, f: F) -> &Self
+    where
+        S: de::DeserializeOwned,
+        F: FnOnce(&S),
+    {
+        let state_tree = match &self.executor {
+            Some(executor) => executor.state_tree(),
+            None => self.state_tree.as_ref().unwrap(),
+        };
+        let head = state_tree
+            .get_actor(actor)
+            .unwrap_or_else(|e| panic!("failed to load actor {}: {}", actor, e))
+            .unwrap_or_else(|| panic!("actor {} doesn't exist", actor))
+            .state;
+        let state: S = state_tree
+            .store()
+            .get_cbor(&head)
+            .unwrap_or_else(|e| panic!("failed to load the state of actor {}: {}", actor, e))
+            .unwrap_or_else(|| panic!("state of actor {} not found", actor));
+        f(&state);
+        self
+    }
+
     /// Get blockstore
     pub fn blockstore(&self) -> &dyn Blockstore {
         if self.executor.is_some() {
@@ -294,7 +515,15 @@ where
     ) -> Result<Account> {
         let pub_key = PublicKey::from_secret_key(&priv_key);
         let pub_key_addr = Address::new_secp256k1(&pub_key.serialize())?;
+        self.make_account(pub_key_addr, init_balance)
+    }
 
+    /// Put an account actor for the given public key address, with the specified balance.
+    pub fn make_account(
+        &mut self,
+        pub_key_addr: Address,
+        init_balance: TokenAmount,
+    ) -> Result<Account> {
         let state_tree = self
             .state_tree
             .as_mut()
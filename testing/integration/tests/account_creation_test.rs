@@ -0,0 +1,159 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use bundles::*;
+use fvm::executor::{ApplyKind, Executor};
+use fvm::machine::Machine;
+use fvm::state_tree::ActorState;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::{Account, IntegrationExecutor, Tester};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_shared::address::{Address, BLS_PUB_LEN, SECP_PUB_LEN};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::message::Message;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{ActorID, METHOD_SEND};
+
+const EAM_NAMESPACE: ActorID = 10;
+
+fn setup(
+    placeholder_namespaces: Option<Vec<ActorID>>,
+) -> (Account, Tester<MemoryBlockstore, DummyExterns>) {
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [sender] = tester.create_accounts().unwrap();
+
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |nc| {
+                if let Some(namespaces) = placeholder_namespaces {
+                    nc.placeholder_namespaces(namespaces);
+                }
+            },
+            |_| (),
+        )
+        .unwrap();
+
+    (sender, tester)
+}
+
+fn send(
+    executor: &mut IntegrationExecutor<MemoryBlockstore, DummyExterns>,
+    from: Address,
+    to: Address,
+    value: &TokenAmount,
+    sequence: u64,
+    kind: ApplyKind,
+) -> ExitCode {
+    let message = Message {
+        from,
+        to,
+        gas_limit: 1000000000,
+        method_num: METHOD_SEND,
+        sequence,
+        value: value.clone(),
+        ..Message::default()
+    };
+
+    let res = executor.execute_message(message, kind, 100).unwrap();
+    res.msg_receipt.exit_code
+}
+
+fn actor(
+    executor: &IntegrationExecutor<MemoryBlockstore, DummyExterns>,
+    addr: &Address,
+) -> Option<ActorState> {
+    executor.state_tree().get_actor_by_address(addr).unwrap()
+}
+
+#[test]
+fn send_creates_account_actors() {
+    let (sender, mut tester) = setup(None);
+    let executor = tester.executor.as_mut().unwrap();
+
+    let value = TokenAmount::from_atto(100);
+    let secp = Address::new_secp256k1(&[1; SECP_PUB_LEN]).unwrap();
+    let bls = Address::new_bls(&[1; BLS_PUB_LEN]).unwrap();
+
+    assert!(actor(executor, &secp).is_none());
+    assert!(actor(executor, &bls).is_none());
+
+    // Explicit message to a secp256k1 address.
+    assert_eq!(
+        send(executor, sender.1, secp, &value, 0, ApplyKind::Explicit),
+        ExitCode::OK
+    );
+    // Implicit message to a BLS address.
+    assert_eq!(
+        send(executor, sender.1, bls, &value, 1, ApplyKind::Implicit),
+        ExitCode::OK
+    );
+
+    for addr in [secp, bls] {
+        let state = actor(executor, &addr).expect("account actor should have been created");
+        assert!(executor.builtin_actors().is_account_actor(&state.code));
+        assert_eq!(state.balance, value);
+    }
+}
+
+#[test]
+fn send_creates_placeholder_actors() {
+    let (sender, mut tester) = setup(None);
+    let executor = tester.executor.as_mut().unwrap();
+
+    let value = TokenAmount::from_atto(100);
+    let f4 = Address::new_delegated(EAM_NAMESPACE, &[1; 20]).unwrap();
+
+    assert_eq!(
+        send(executor, sender.1, f4, &value, 0, ApplyKind::Explicit),
+        ExitCode::OK
+    );
+
+    let state = actor(executor, &f4).expect("placeholder actor should have been created");
+    assert!(executor.builtin_actors().is_placeholder_actor(&state.code));
+    assert_eq!(state.delegated_address, Some(f4));
+    assert_eq!(state.balance, value);
+}
+
+#[test]
+fn send_to_unconfigured_namespace_fails() {
+    const NAMESPACE: ActorID = 1234;
+    let value = TokenAmount::from_atto(100);
+    let f4 = Address::new_delegated(NAMESPACE, &[1; 20]).unwrap();
+
+    // By default, only the EAM's namespace gets placeholders.
+    {
+        let (sender, mut tester) = setup(None);
+        let executor = tester.executor.as_mut().unwrap();
+
+        for (sequence, kind) in [(0, ApplyKind::Explicit), (1, ApplyKind::Implicit)] {
+            assert_eq!(
+                send(executor, sender.1, f4, &value, sequence, kind),
+                ExitCode::SYS_INVALID_RECEIVER
+            );
+        }
+        assert!(actor(executor, &f4).is_none());
+    }
+
+    // Once configured, the namespace gets placeholders too.
+    {
+        let (sender, mut tester) = setup(Some(vec![EAM_NAMESPACE, NAMESPACE]));
+        let executor = tester.executor.as_mut().unwrap();
+
+        assert_eq!(
+            send(executor, sender.1, f4, &value, 0, ApplyKind::Explicit),
+            ExitCode::OK
+        );
+        let state = actor(executor, &f4).expect("placeholder actor should have been created");
+        assert!(executor.builtin_actors().is_placeholder_actor(&state.code));
+    }
+}
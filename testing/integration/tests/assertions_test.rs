@@ -0,0 +1,90 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use bundles::*;
+use fil_events_actor::WASM_BINARY as EVENTS_BINARY;
+use fvm::account_actor;
+use fvm::executor::{ApplyKind, ApplyRet, Executor};
+use fvm_integration_tests::assertions::ExpectApplyRet;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::{Account, Tester};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_ipld_encoding::to_vec;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::event::{Entry, Flags};
+use fvm_shared::message::Message;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::ActorID;
+use num_traits::Zero;
+
+const ACTOR_ID: ActorID = 10000;
+
+fn setup() -> (Account, Tester<MemoryBlockstore, DummyExterns>) {
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [sender] = tester.create_accounts().unwrap();
+
+    let state_cid = tester.set_state(&[(); 0]).unwrap();
+    tester
+        .set_actor_from_bin(
+            EVENTS_BINARY.unwrap(),
+            state_cid,
+            Address::new_id(ACTOR_ID),
+            TokenAmount::zero(),
+        )
+        .unwrap();
+
+    tester.instantiate_machine(DummyExterns).unwrap();
+    (sender, tester)
+}
+
+fn call(tester: &mut Tester<MemoryBlockstore, DummyExterns>, from: Address) -> ApplyRet {
+    let message = Message {
+        from,
+        to: Address::new_id(ACTOR_ID),
+        gas_limit: 1000000000,
+        method_num: 2,
+        ..Message::default()
+    };
+    tester
+        .executor
+        .as_mut()
+        .unwrap()
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap()
+}
+
+#[test]
+fn expect_ok_with_events_and_state() {
+    let (sender, mut tester) = setup();
+    let ret = call(&mut tester, sender.1);
+
+    let foo = vec![Entry {
+        flags: Flags::all(),
+        key: "foo".to_owned(),
+        value: to_vec(&("aaa111", "bbb111")).unwrap().into(),
+    }];
+    ret.expect_ok()
+        .expect_event_count(2)
+        .expect_event(ACTOR_ID, foo);
+
+    tester.expect_state(sender.0, |s: &account_actor::State| {
+        assert_eq!(s.address, sender.1)
+    });
+}
+
+#[test]
+#[should_panic(expected = "expected exit code 16, got 0")]
+fn expect_exit_mismatch() {
+    let (sender, mut tester) = setup();
+    call(&mut tester, sender.1).expect_exit(ExitCode::USR_ILLEGAL_ARGUMENT);
+}
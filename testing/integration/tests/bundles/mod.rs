@@ -12,10 +12,12 @@ use fvm_shared::version::NetworkVersion;
 use lazy_static::lazy_static;
 
 lazy_static! {
-    static ref BUNDLES: BTreeMap<NetworkVersion, &'static [u8]> =
-        [(NetworkVersion::V18, actors_v10::BUNDLE_CAR),]
-            .into_iter()
-            .collect();
+    static ref BUNDLES: BTreeMap<NetworkVersion, &'static [u8]> = [
+        (NetworkVersion::V18, actors_v10::BUNDLE_CAR),
+        (NetworkVersion::V19, actors_v10::BUNDLE_CAR),
+    ]
+    .into_iter()
+    .collect();
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,103 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use std::sync::{Arc, Mutex};
+
+use bundles::*;
+use fil_hello_world_actor::WASM_BINARY as HELLO_BINARY;
+use fvm::call_manager::NO_DATA_BLOCK_ID;
+use fvm::executor::Executor;
+use fvm::kernel::{BlockId, Kernel as _};
+use fvm::native::NativeAbort;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::IntegrationExecutor;
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{ActorID, METHOD_SEND};
+
+type Kernel = <IntegrationExecutor<MemoryBlockstore, DummyExterns> as Executor>::Kernel;
+
+#[test]
+fn scheduled_callbacks() {
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    // Deploy two actors, and register them with cron.
+    let state = tester.set_state(&()).unwrap();
+    let code = tester
+        .set_actor_from_bin(
+            HELLO_BINARY.unwrap(),
+            state,
+            Address::new_id(10000),
+            TokenAmount::default(),
+        )
+        .unwrap();
+    tester
+        .set_actor_from_bin(
+            HELLO_BINARY.unwrap(),
+            state,
+            Address::new_id(10001),
+            TokenAmount::default(),
+        )
+        .unwrap();
+    tester.register_cron_entry(10000, 2).unwrap();
+    tester.register_cron_entry(10001, 3).unwrap();
+    tester.register_cron_entry(10000, 4).unwrap();
+    tester.unregister_cron_entry(10000, 4).unwrap();
+
+    // Invalid entries are rejected.
+    tester.register_cron_entry(10000, METHOD_SEND).unwrap_err();
+    tester.register_cron_entry(10000, 2).unwrap_err();
+    tester.unregister_cron_entry(10001, 2).unwrap_err();
+
+    tester
+        .instantiate_machine_with_config(DummyExterns, |nc| nc.native_actors = true, |_| {})
+        .unwrap();
+    tester.register_cron_entry(10000, 5).unwrap_err();
+
+    // Record every invocation of the actors.
+    let calls: Arc<Mutex<Vec<(ActorID, ActorID, ChainEpoch)>>> = Default::default();
+    let recorded = calls.clone();
+    tester
+        .executor
+        .as_ref()
+        .unwrap()
+        .engine_pool()
+        .register_native_actor::<Kernel>(
+            code,
+            move |kernel: &mut Kernel, _: BlockId| -> Result<BlockId, NativeAbort> {
+                let msg = kernel.msg_context()?;
+                let epoch = kernel.network_context()?.epoch;
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((msg.caller, msg.receiver, epoch));
+                Ok(NO_DATA_BLOCK_ID)
+            },
+        );
+
+    for epoch in 1..=3 {
+        tester.advance_epoch(epoch, DummyExterns).unwrap();
+        let ret = tester.run_cron().unwrap();
+        assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+    }
+
+    // The epoch may only move forward.
+    tester.advance_epoch(3, DummyExterns).unwrap_err();
+
+    let calls = calls.lock().unwrap();
+    let expected: Vec<_> = (1..=3)
+        .flat_map(|epoch| [(3, 10000, epoch), (3, 10001, epoch)])
+        .collect();
+    assert_eq!(*calls, expected);
+}
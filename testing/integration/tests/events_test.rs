@@ -3,8 +3,8 @@
 mod bundles;
 use bundles::*;
 use fil_events_actor::WASM_BINARY as EVENTS_BINARY;
-use fvm::executor::{ApplyKind, Executor};
-use fvm::machine::Machine;
+use fvm::executor::{ApplyKind, BlockEvents, Executor};
+use fvm::machine::{Machine, NetworkConfig};
 use fvm_integration_tests::dummy::DummyExterns;
 use fvm_integration_tests::tester::IntegrationExecutor;
 use fvm_ipld_amt::Amt;
@@ -122,12 +122,90 @@ fn events_test() {
     assert_eq!(ExitCode::SYS_OUT_OF_GAS, res.msg_receipt.exit_code);
     assert!(res.msg_receipt.events_root.is_none());
     assert_eq!(0, res.events.len());
+
+    // === The executor accumulated the events of all five messages ===
+    let block_events = executor.take_block_events();
+    assert_eq!(5, block_events.len());
+    assert!(executor.block_events().is_empty());
+
+    let cid = block_events.flush(executor.blockstore()).unwrap();
+    let block = BlockEvents::load(executor.blockstore(), &cid).unwrap();
+    let found = block
+        .find(executor.blockstore(), 10000, Some("foo"))
+        .unwrap();
+    assert_eq!(Some(0), found.first().map(|e| e.message));
+    assert!(found.iter().all(|e| e.message != 1 && e.message != 4));
+    assert!(!block.may_contain(10001, None));
+}
+
+#[test]
+fn event_size_limit() {
+    // Measure the events emitted by method 2.
+    let (mut executor, sender_address, actor_address) = setup();
+    let message = Message {
+        from: sender_address,
+        to: actor_address,
+        gas_limit: 1000000000,
+        method_num: 2,
+        ..Message::default()
+    };
+    let res = executor
+        .execute_message(message.clone(), ApplyKind::Explicit, 100)
+        .unwrap();
+    assert_eq!(ExitCode::OK, res.msg_receipt.exit_code);
+    let bytes: usize = res.events.iter().map(|e| e.event().payload_size()).sum();
+
+    // The events fit exactly.
+    let (mut executor, _, _) = setup_with_config(|nc| {
+        nc.max_message_event_bytes(bytes as u64);
+    });
+    let res = executor
+        .execute_message(message.clone(), ApplyKind::Explicit, 100)
+        .unwrap();
+    assert_eq!(ExitCode::OK, res.msg_receipt.exit_code);
+    assert_eq!(2, res.events.len());
+
+    // One byte short, the second event can't be emitted, and the actor aborts.
+    let (mut executor, _, _) = setup_with_config(|nc| {
+        nc.max_message_event_bytes(bytes as u64 - 1);
+    });
+    let res = executor
+        .execute_message(message.clone(), ApplyKind::Explicit, 100)
+        .unwrap();
+    assert_ne!(ExitCode::OK, res.msg_receipt.exit_code);
+    assert!(res.events.is_empty());
+
+    // The limit applies to the whole message, across calls: each call in the chain emits as many
+    // bytes as method 2.
+    let (mut executor, _, _) = setup_with_config(|nc| {
+        nc.max_message_event_bytes(bytes as u64);
+    });
+    let message = Message {
+        method_num: 4,
+        params: to_vec(&2u64).unwrap().into(),
+        ..message
+    };
+    let res = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap();
+    assert_ne!(ExitCode::OK, res.msg_receipt.exit_code);
+    assert!(res.events.is_empty());
 }
 
 fn setup() -> (
     IntegrationExecutor<MemoryBlockstore, DummyExterns>,
     Address,
     Address,
+) {
+    setup_with_config(|_| {})
+}
+
+fn setup_with_config(
+    configure: impl FnOnce(&mut NetworkConfig),
+) -> (
+    IntegrationExecutor<MemoryBlockstore, DummyExterns>,
+    Address,
+    Address,
 ) {
     // Instantiate tester
     let mut tester = new_tester(
@@ -153,7 +231,9 @@ fn setup() -> (
         .unwrap();
 
     // Instantiate machine
-    tester.instantiate_machine(DummyExterns).unwrap();
+    tester
+        .instantiate_machine_with_config(DummyExterns, configure, |_| {})
+        .unwrap();
 
     let executor = tester.executor.unwrap();
     (executor, sender, actor)
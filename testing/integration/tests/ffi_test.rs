@@ -0,0 +1,181 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use std::mem::MaybeUninit;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bundles::*;
+use cid::Cid;
+use fvm::state_tree::StateTree;
+use fvm_ffi::bytes::{fvm_buffer_write, fvm_bytes_free, FvmBuffer, FvmBytes};
+use fvm_ffi::error::FvmStatus;
+use fvm_ffi::externs::{FvmCallbacks, FVM_CALLBACK_NOT_FOUND, FVM_CALLBACK_OK};
+use fvm_ffi::machine::{
+    fvm_apply_ret_free, fvm_machine_apply_message, fvm_machine_flush, fvm_machine_free,
+    fvm_machine_new, FvmApplyRet, FvmMachine, FvmMachineConfig, FVM_APPLY_EXPLICIT,
+};
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::{Tester, INITIAL_ACCOUNT_BALANCE};
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+use fvm_ipld_encoding::{from_slice, to_vec};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::receipt::Receipt;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::METHOD_SEND;
+
+/// Set once the machine drops the callbacks' context.
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+fn blockstore(ctx: *mut c_void) -> &'static MemoryBlockstore {
+    unsafe { &*(ctx as *const MemoryBlockstore) }
+}
+
+fn slice<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    unsafe { std::slice::from_raw_parts(data, len) }
+}
+
+extern "C" fn blockstore_get(
+    ctx: *mut c_void,
+    cid: *const u8,
+    cid_len: usize,
+    out: *mut FvmBuffer,
+) -> i32 {
+    let cid = Cid::try_from(slice(cid, cid_len)).unwrap();
+    match blockstore(ctx).get(&cid).unwrap() {
+        Some(data) => {
+            unsafe { fvm_buffer_write(out, data.as_ptr(), data.len()) };
+            FVM_CALLBACK_OK
+        }
+        None => FVM_CALLBACK_NOT_FOUND,
+    }
+}
+
+extern "C" fn blockstore_put(
+    ctx: *mut c_void,
+    cid: *const u8,
+    cid_len: usize,
+    data: *const u8,
+    data_len: usize,
+) -> i32 {
+    let cid = Cid::try_from(slice(cid, cid_len)).unwrap();
+    blockstore(ctx)
+        .put_keyed(&cid, slice(data, data_len))
+        .unwrap();
+    FVM_CALLBACK_OK
+}
+
+extern "C" fn drop_ctx(_: *mut c_void) {
+    DROPPED.store(true, Ordering::SeqCst);
+}
+
+#[test]
+fn apply_message_through_callbacks() {
+    let bs = MemoryBlockstore::default();
+    let mut tester: Tester<_, DummyExterns> =
+        new_tester(NetworkVersion::V18, StateTreeVersion::V5, &bs).unwrap();
+    let [(_, sender), (receiver_id, receiver)] = tester.create_accounts().unwrap();
+    let state_root = tester.state_tree.as_mut().unwrap().flush().unwrap();
+    drop(tester);
+
+    let state_root = state_root.to_bytes();
+    let base_fee = [0, 100];
+    let config = FvmMachineConfig {
+        network_version: NetworkVersion::V18 as u32,
+        state_root: state_root.as_ptr(),
+        state_root_len: state_root.len(),
+        epoch: 0,
+        timestamp: 0,
+        base_fee: base_fee.as_ptr(),
+        base_fee_len: base_fee.len(),
+        circulating_supply: ptr::null(),
+        circulating_supply_len: 0,
+        chain_id: 314,
+        actors_manifest: ptr::null(),
+        actors_manifest_len: 0,
+        tracing: true,
+    };
+    let callbacks = FvmCallbacks {
+        ctx: &bs as *const MemoryBlockstore as *mut c_void,
+        blockstore_get: Some(blockstore_get),
+        blockstore_put: Some(blockstore_put),
+        get_chain_randomness: None,
+        get_beacon_randomness: None,
+        verify_consensus_fault: None,
+        get_tipset_cid: None,
+        drop: Some(drop_ctx),
+    };
+
+    let message = to_vec(&Message {
+        from: sender,
+        to: receiver,
+        value: TokenAmount::from_atto(10),
+        method_num: METHOD_SEND,
+        gas_limit: 1000000000,
+        ..Message::default()
+    })
+    .unwrap();
+
+    let root = unsafe {
+        let mut machine: *mut FvmMachine = ptr::null_mut();
+        assert_eq!(
+            fvm_machine_new(&config, callbacks, &mut machine),
+            FvmStatus::Ok
+        );
+
+        // Malformed messages are rejected without affecting the machine.
+        let mut ret = MaybeUninit::<FvmApplyRet>::uninit();
+        assert_eq!(
+            fvm_machine_apply_message(
+                machine,
+                [0x80].as_ptr(),
+                1,
+                FVM_APPLY_EXPLICIT,
+                1,
+                ret.as_mut_ptr(),
+            ),
+            FvmStatus::InvalidArgument
+        );
+
+        assert_eq!(
+            fvm_machine_apply_message(
+                machine,
+                message.as_ptr(),
+                message.len(),
+                FVM_APPLY_EXPLICIT,
+                message.len(),
+                ret.as_mut_ptr(),
+            ),
+            FvmStatus::Ok
+        );
+        let mut ret = ret.assume_init();
+        assert_eq!(ret.exit_code, 0);
+        let receipt: Receipt = from_slice(slice(ret.receipt.data, ret.receipt.len)).unwrap();
+        assert_eq!(receipt.gas_used, ret.gas_used);
+        assert!(ret.failure_info.data.is_null());
+        assert!(!ret.trace.data.is_null());
+        fvm_apply_ret_free(&mut ret);
+        assert!(ret.receipt.data.is_null());
+
+        let mut root = FvmBytes::empty();
+        assert_eq!(fvm_machine_flush(machine, &mut root), FvmStatus::Ok);
+        let cid = Cid::try_from(slice(root.data, root.len)).unwrap();
+        fvm_bytes_free(root);
+
+        fvm_machine_free(machine);
+        cid
+    };
+    assert!(DROPPED.load(Ordering::SeqCst));
+
+    // The transfer was written to the embedder's blockstore.
+    let state_tree = StateTree::new_from_root(bs, &root).unwrap();
+    let receiver = state_tree.get_actor(receiver_id).unwrap().unwrap();
+    assert_eq!(
+        receiver.balance,
+        &*INITIAL_ACCOUNT_BALANCE + TokenAmount::from_atto(10)
+    );
+}
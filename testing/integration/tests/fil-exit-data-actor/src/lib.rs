@@ -5,6 +5,11 @@ include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
 use fvm_ipld_encoding::RawBytes;
 use fvm_sdk as sdk;
+use fvm_shared::address::Address;
+use fvm_shared::bigint::Zero;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::sys::SendFlags;
 
 /// Placeholder invoke for testing
 #[no_mangle]
@@ -18,6 +23,30 @@ fn invoke_method(_: u32) -> ! {
     let method = sdk::message::method_number();
     let exit_code = match method {
         0 | 1 | 2 => 0,
+        // Revert with typed data.
+        4 => sdk::vm::revert(
+            ExitCode::new(0x43),
+            &("insufficient allowance", 17u64),
+            None,
+        ),
+        // Call method 4 on ourselves, and decode the revert data.
+        5 => {
+            let resp = sdk::send::send(
+                &Address::new_id(sdk::message::receiver()),
+                4,
+                None,
+                TokenAmount::zero(),
+                None,
+                SendFlags::empty(),
+            )
+            .unwrap();
+            assert_eq!(resp.exit_code.value(), 0x43);
+            let data: Option<(String, u64)> = resp.revert_data().unwrap();
+            assert_eq!(data, Some(("insufficient allowance".into(), 17)));
+            0
+        }
+        // Revert with a success code, which aborts instead.
+        6 => sdk::vm::revert(ExitCode::OK, &("insufficient allowance", 17u64), None),
         _ => 0x42,
     };
 
@@ -12,8 +12,9 @@ use fvm_shared::address::{Address, SECP_PUB_LEN};
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::event::{Entry, Flags};
 use fvm_shared::sys::SendFlags;
+use fvm_shared::version::NetworkVersion;
 use fvm_shared::METHOD_SEND;
-use sdk::error::{ActorDeleteError, StateUpdateError};
+use sdk::error::{ActorDeleteError, EpochBoundsError, StateUpdateError};
 use sdk::sys::ErrorNumber;
 
 /// Placeholder invoke for testing
@@ -163,6 +164,20 @@ fn invoke_method(blk: u32, method: u64) -> u32 {
             let err =
                 sdk::sself::self_destruct(&Address::new_id(sdk::message::origin())).unwrap_err();
             assert_eq!(err, ActorDeleteError::ReadOnly);
+
+            // Should not be able to call extern-dependent syscalls, from network version 19.
+            if sdk::network::version() >= NetworkVersion::V19 {
+                assert_eq!(
+                    sdk::rand::get_chain_randomness(0, 0, &[]),
+                    Err(ErrorNumber::ReadOnly)
+                );
+                assert_eq!(sdk::network::tipset_cid(0), Err(EpochBoundsError::ReadOnly));
+            } else {
+                assert_ne!(
+                    sdk::rand::get_chain_randomness(0, 0, &[]),
+                    Err(ErrorNumber::ReadOnly)
+                );
+            }
         }
         4 => {
             assert!(sdk::vm::read_only());
@@ -34,12 +34,36 @@ pub fn invoke(_: u32) -> u32 {
         )
     }));
 
+    match sdk::message::method_number() {
+        1 => {}
+        2 => test_token_transfer_rejected(),
+        // Reject all token transfers to this actor.
+        fvm_shared::token::RECEIVER_HOOK_METHOD_NUM => sdk::vm::abort(
+            ExitCode::USR_UNHANDLED_MESSAGE.value(),
+            Some("tokens rejected"),
+        ),
+        _ => sdk::vm::abort(ExitCode::USR_UNHANDLED_MESSAGE.value(), None),
+    }
+
     test_expected_hash();
     test_hash_syscall();
+    test_streaming_hash();
     test_create_actor();
     test_network_context();
     test_message_context();
     test_balance();
+    test_nonce();
+    test_gas_prices();
+    test_delegate_call();
+    test_graph_stat();
+    test_state_stat();
+    test_prefetch();
+    test_merkle_proof();
+    test_code_info();
+    test_sync_flags();
+    test_snark();
+    test_cron();
+    test_token_transfer();
 
     #[cfg(coverage)]
     sdk::debug::store_artifact("syscall_actor.profraw", minicov::capture_coverage());
@@ -218,16 +242,73 @@ fn test_hash_syscall() {
     }
 }
 
+fn test_streaming_hash() {
+    use fvm_shared::error::ErrorNumber;
+    use sdk::crypto::StreamingHasher;
+    use sdk::sys::crypto;
+
+    let test_bytes = b"the quick fox jumped over the lazy dog";
+    for hasher in [
+        SharedSupportedHashes::Sha2_256,
+        SharedSupportedHashes::Blake2b512,
+        SharedSupportedHashes::Keccak256,
+    ] {
+        let mut streaming = StreamingHasher::new(hasher).unwrap();
+        for chunk in test_bytes.chunks(5) {
+            streaming.update(chunk);
+        }
+        assert_eq!(
+            streaming.finalize(),
+            sdk::crypto::hash_owned(hasher, test_bytes)
+        );
+    }
+
+    unsafe {
+        // invalid hash code
+        let e = crypto::hash_init(0xFF).expect_err("expected err from invalid code");
+        assert_eq!(e, ErrorNumber::IllegalArgument);
+
+        // finalized and unknown hashers
+        let handle = crypto::hash_init(SharedSupportedHashes::Sha2_256 as u64).unwrap();
+        let mut buffer = [0u8; 64];
+        crypto::hash_finalize(handle, buffer.as_mut_ptr(), buffer.len() as u32).unwrap();
+        for handle in [handle, 0, u32::MAX] {
+            let e = crypto::hash_update(handle, test_bytes.as_ptr(), test_bytes.len() as u32)
+                .expect_err("expected err from invalid handle");
+            assert_eq!(e, ErrorNumber::InvalidHandle);
+        }
+
+        // digest buffer out of bounds, which doesn't release the hasher
+        let handle = crypto::hash_init(SharedSupportedHashes::Sha2_256 as u64).unwrap();
+        let e = crypto::hash_finalize(handle, (u32::MAX) as *mut u8, buffer.len() as u32)
+            .expect_err("expected err from OOB digest buffer");
+        assert_eq!(e, ErrorNumber::IllegalArgument);
+        let written =
+            crypto::hash_finalize(handle, buffer.as_mut_ptr(), buffer.len() as u32).unwrap();
+        assert_eq!(
+            &buffer[..written as usize],
+            sdk::crypto::hash_owned(SharedSupportedHashes::Sha2_256, &[])
+        );
+    }
+}
+
 fn test_network_context() {
     use fvm_shared::econ::TokenAmount;
     use fvm_shared::version::NetworkVersion;
     assert_eq!(sdk::network::chain_id(), ChainID::from(1)); // hehe we are ETH now
     assert_eq!(sdk::network::curr_epoch(), 0);
-    assert_eq!(sdk::network::version(), NetworkVersion::V18);
+    assert_eq!(sdk::network::version(), NetworkVersion::V19);
     assert_eq!(sdk::network::tipset_timestamp(), 0);
     assert_eq!(sdk::network::base_fee(), TokenAmount::from_atto(100));
 }
 
+fn test_gas_prices() {
+    let prices = sdk::gas::prices();
+    let (send_base, syscall_base) = (prices.send_base, prices.syscall_base);
+    assert!(send_base > 0);
+    assert!(syscall_base > 0);
+}
+
 fn test_message_context() {
     assert_eq!(sdk::message::nonce(), 100);
     assert_eq!(sdk::message::origin(), 100);
@@ -248,3 +329,258 @@ fn test_balance() {
         Some(sdk::sself::current_balance())
     );
 }
+
+fn test_nonce() {
+    // Getting the nonce of a non-existent actor should return None.
+    assert_eq!(sdk::actor::nonce_of(9191919), None);
+
+    // We've never sent a message.
+    assert_eq!(sdk::actor::nonce_of(sdk::message::receiver()), Some(0));
+}
+
+fn test_delegate_call() {
+    // Delegate calls are disabled by default.
+    let code = sdk::actor::get_actor_code_cid(sdk::message::receiver()).unwrap();
+    assert_eq!(
+        sdk::send::delegate_call(&code, 2, None, None, Default::default()),
+        Err(fvm_shared::error::ErrorNumber::Forbidden)
+    );
+}
+
+fn test_graph_stat() {
+    use fvm_ipld_encoding::{to_vec, DAG_CBOR, IPLD_RAW};
+
+    let leaf = sdk::ipld::put(0xb220, 32, IPLD_RAW, b"leaf").unwrap();
+    let node = to_vec(&vec![leaf, leaf]).unwrap();
+    let root = sdk::ipld::put(0xb220, 32, DAG_CBOR, &node).unwrap();
+
+    // Only reachable blocks can be inspected.
+    assert_eq!(
+        sdk::ipld::graph_stat(&root, 10).unwrap_err(),
+        fvm_shared::error::ErrorNumber::NotFound
+    );
+    sdk::sself::set_root(&root).unwrap();
+
+    // The leaf is only counted once.
+    let stat = sdk::ipld::graph_stat(&root, 10).unwrap();
+    assert_eq!(
+        (stat.blocks, stat.size, stat.depth, stat.complete),
+        (2, node.len() as u64 + 4, 1, 1)
+    );
+
+    // The budget bounds the traversal.
+    let stat = sdk::ipld::graph_stat(&root, 1).unwrap();
+    assert_eq!((stat.blocks, stat.depth, stat.complete), (1, 0, 0));
+    assert_eq!(
+        sdk::ipld::graph_stat(&root, 0).unwrap_err(),
+        fvm_shared::error::ErrorNumber::IllegalArgument
+    );
+}
+
+fn test_state_stat() {
+    use fvm_ipld_encoding::{from_slice, to_vec, DAG_CBOR, IPLD_RAW};
+
+    let leaf = sdk::ipld::put(0xb220, 32, IPLD_RAW, b"state leaf").unwrap();
+    let node = to_vec(&vec![leaf, leaf]).unwrap();
+    let root = sdk::ipld::put(0xb220, 32, DAG_CBOR, &node).unwrap();
+    sdk::sself::set_root(&root).unwrap();
+
+    // The state is counted as a tree: the leaf is counted once per link.
+    let stat = sdk::sself::state_stat().unwrap();
+    assert_eq!(
+        (stat.blocks, stat.size, stat.depth, stat.complete),
+        (3, node.len() as u64 + 20, 1, 1)
+    );
+
+    // Links to missing blocks are skipped, and make the statistics incomplete.
+    let mut missing = to_vec(&leaf).unwrap();
+    *missing.last_mut().unwrap() ^= 1;
+    let node = to_vec(&vec![leaf, from_slice(&missing).unwrap()]).unwrap();
+    let root = sdk::ipld::put(0xb220, 32, DAG_CBOR, &node).unwrap();
+    sdk::sself::set_root(&root).unwrap();
+    let stat = sdk::sself::state_stat().unwrap();
+    assert_eq!(
+        (stat.blocks, stat.size, stat.depth, stat.complete),
+        (2, node.len() as u64 + 10, 1, 0)
+    );
+}
+
+fn test_prefetch() {
+    use fvm_ipld_encoding::IPLD_RAW;
+
+    // Hints are advisory, and repeated hints are fine.
+    let k = sdk::ipld::put(0xb220, 32, IPLD_RAW, b"prefetched").unwrap();
+    sdk::ipld::prefetch(&k).unwrap();
+    sdk::ipld::prefetch(&k).unwrap();
+    assert_eq!(sdk::ipld::get(&k).unwrap(), b"prefetched");
+}
+
+fn test_code_info() {
+    use fvm_ipld_encoding::IPLD_RAW;
+    use fvm_shared::error::ErrorNumber;
+
+    let acct_cid = sdk::actor::get_code_cid_for_type(Type::Account as i32);
+    let info = sdk::actor::get_code_info(&acct_cid).unwrap();
+    assert!(info.size > 0);
+    assert_eq!(info.abi_version, 1);
+
+    // Only builtin actors' code can be inspected.
+    let own_code =
+        sdk::actor::get_actor_code_cid(&Address::new_id(sdk::message::receiver())).unwrap();
+    assert_eq!(
+        sdk::actor::get_code_info(&own_code),
+        Err(ErrorNumber::NotFound)
+    );
+    let not_code = sdk::ipld::put(0xb220, 32, IPLD_RAW, b"not code").unwrap();
+    assert_eq!(
+        sdk::actor::get_code_info(&not_code),
+        Err(ErrorNumber::NotFound)
+    );
+}
+
+fn test_sync_flags() {
+    use sdk::sync::{CallGuard, FlagOptions};
+
+    let guard = CallGuard::acquire(1).unwrap();
+    assert!(CallGuard::acquire(1).is_none());
+    // Other locks are independent.
+    drop(CallGuard::acquire(2).unwrap());
+    drop(guard);
+    drop(CallGuard::acquire(1).unwrap());
+
+    assert!(sdk::sync::set_flag(3, FlagOptions::MESSAGE_SCOPE));
+    assert!(!sdk::sync::set_flag(3, FlagOptions::empty()));
+    assert!(sdk::sync::clear_flag(3));
+    assert!(!sdk::sync::clear_flag(3));
+
+    assert_eq!(
+        unsafe { sdk::sys::sync::set_flag(4, 0b10) },
+        Err(fvm_shared::error::ErrorNumber::IllegalArgument)
+    );
+}
+
+fn test_merkle_proof() {
+    use fvm_ipld_encoding::BytesDe;
+    use fvm_shared::crypto::merkle::{BinaryMerkleProof, MerkleProof};
+    use multihash::MultihashDigest;
+
+    let sha256 = |data: &[u8]| SupportedHashes::Sha2_256.digest(data).digest().to_vec();
+    let (left, right) = (sha256(b"left"), sha256(b"right"));
+    let root = sha256(&[&left[..], &right[..]].concat());
+
+    let mut proof = BinaryMerkleProof {
+        hash: SupportedHashes::Sha2_256.into(),
+        root,
+        leaf: right,
+        index: 1,
+        siblings: vec![BytesDe(left)],
+    };
+    assert!(sdk::crypto::verify_merkle_proof(&MerkleProof::Binary(proof.clone())).unwrap());
+
+    // The right leaf isn't on the left.
+    proof.index = 0;
+    assert!(!sdk::crypto::verify_merkle_proof(&MerkleProof::Binary(proof.clone())).unwrap());
+
+    // Malformed proofs are rejected.
+    proof.leaf.pop();
+    assert_eq!(
+        sdk::crypto::verify_merkle_proof(&MerkleProof::Binary(proof)).unwrap_err(),
+        fvm_shared::error::ErrorNumber::IllegalArgument
+    );
+}
+
+fn test_snark() {
+    use fvm_ipld_encoding::{to_vec, BytesDe, DAG_CBOR, IPLD_RAW};
+    use fvm_shared::crypto::snark::{SnarkCurve, SnarkVerifyInfo, SnarkVerifyingKey};
+    use fvm_shared::error::ErrorNumber;
+
+    let vk = SnarkVerifyingKey {
+        curve: SnarkCurve::Bls12_381,
+        key: vec![0; 64],
+    };
+    let vk = to_vec(&vk).unwrap();
+    let info = SnarkVerifyInfo {
+        proof: vec![0; 192],
+        public_inputs: vec![BytesDe(vec![0; 32])],
+    };
+
+    // The key is too short for a single public input.
+    let cid = sdk::ipld::put(0xb220, 32, DAG_CBOR, &vk).unwrap();
+    assert_eq!(
+        sdk::crypto::verify_groth16(&cid, &info).unwrap_err(),
+        ErrorNumber::IllegalArgument
+    );
+    assert_eq!(
+        sdk::crypto::verify_plonk(&cid, &info).unwrap_err(),
+        ErrorNumber::IllegalArgument
+    );
+
+    // The key must be DAG-CBOR.
+    let cid = sdk::ipld::put(0xb220, 32, IPLD_RAW, &vk).unwrap();
+    assert_eq!(
+        sdk::crypto::verify_groth16(&cid, &info).unwrap_err(),
+        ErrorNumber::IllegalArgument
+    );
+}
+
+fn test_cron() {
+    use fvm_shared::error::ErrorNumber;
+
+    // Only builtin actors can register cron entries.
+    assert_eq!(
+        sdk::cron::register_entry(2).unwrap_err(),
+        ErrorNumber::Forbidden
+    );
+    assert_eq!(
+        sdk::cron::unregister_entry(2).unwrap_err(),
+        ErrorNumber::Forbidden
+    );
+}
+
+fn test_token_transfer() {
+    use sdk::token::TransferReceipt;
+
+    // Accounts accept tokens.
+    assert_eq!(
+        sdk::token::transfer_hook(&token_transfer(Address::new_id(100)), None),
+        TransferReceipt {
+            from: 10000,
+            to: 100,
+            return_data: None,
+        }
+    );
+
+    // Recipients are created if they don't exist.
+    let to = Address::new_secp256k1(&[1u8; SECP_PUB_LEN]).unwrap();
+    let receipt = sdk::token::transfer_hook(&token_transfer(to), Some(10_000_000));
+    assert_eq!(sdk::actor::resolve_address(&to), Some(receipt.to));
+}
+
+/// Updates our state, and then transfers tokens to ourselves. We reject them, so this call is
+/// aborted, reverting the update.
+fn test_token_transfer_rejected() -> ! {
+    use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+
+    let state = sdk::ipld::put(0xb220, 32, DAG_CBOR, &to_vec(&(1u64,)).unwrap()).unwrap();
+    sdk::sself::set_root(&state).unwrap();
+
+    sdk::token::transfer_hook(
+        &token_transfer(Address::new_id(sdk::message::receiver())),
+        None,
+    );
+    panic!("the token transfer should have been rejected")
+}
+
+/// A transfer of one of our tokens, owned by ourselves.
+fn token_transfer(to: Address) -> sdk::token::TokenTransfer {
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::econ::TokenAmount;
+
+    sdk::token::TokenTransfer {
+        from: Address::new_id(sdk::message::receiver()),
+        to,
+        amount: TokenAmount::from_whole(1),
+        operator_data: RawBytes::default(),
+        token_data: RawBytes::default(),
+    }
+}
@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 use fil_malformed_syscall_actor::WASM_BINARY as MALFORMED_ACTOR_BINARY;
 use fvm::call_manager::backtrace::Cause;
+use fvm::engine::{EnginePool, UnknownImport, UnknownImportsError};
 use fvm::executor::{ApplyFailure, ApplyKind, Executor};
+use fvm::machine::NetworkConfig;
 use fvm_integration_tests::dummy::DummyExterns;
 use fvm_integration_tests::tester::{Account, Tester};
 use fvm_ipld_blockstore::MemoryBlockstore;
@@ -72,54 +74,42 @@ fn non_existing_syscall() {
     let wasm_bin = wat::parse_str(WAT_UNKNOWN_SYSCALL).unwrap();
 
     // Instantiate tester
-    let (sender, mut tester, actor_address) = instantiate_tester(&wasm_bin);
+    let (_, mut tester, _) = instantiate_tester(&wasm_bin);
 
-    // Instantiate machine
-    tester.instantiate_machine(DummyExterns).unwrap();
-
-    // Params setup
-    let params = RawBytes::new(Vec::<u8>::new());
-
-    // Send message to set
-    let message = Message {
-        from: sender.1,
-        to: actor_address,
-        gas_limit: 1000000000,
-        method_num: 1,
-        params,
-        ..Message::default()
+    // Instantiating the machine preloads the actor code, which should be rejected up-front
+    // because of the unknown import.
+    let err = match tester.instantiate_machine(DummyExterns) {
+        Ok(_) => panic!("expected loading code with an unknown import to fail"),
+        Err(e) => e,
     };
 
-    // Set inner state value
-    let res = tester
-        .executor
-        .as_mut()
-        .unwrap()
-        .execute_message(message, ApplyKind::Explicit, 100)
-        .unwrap();
-
-    // Should be an internal VM assertion failed exit code
+    let unknown = err
+        .chain()
+        .find_map(|e| e.downcast_ref::<UnknownImportsError>())
+        .expect("error should list the unknown imports");
     assert_eq!(
-        res.msg_receipt.exit_code.value(),
-        10,
-        "exit code should be internal VM assertion failed"
+        unknown.0,
+        vec![UnknownImport {
+            module: "vm".into(),
+            name: "do_not_exist".into(),
+        }]
     );
+}
 
-    // Should be unknown import
-    match res.failure_info.as_ref().unwrap() {
-        ApplyFailure::MessageBacktrace(backtrace) => {
-            assert!(
-                backtrace
-                    .cause
-                    .as_ref()
-                    .unwrap()
-                    .to_string()
-                    .contains("unknown import"),
-                "error cause should be unknown import"
-            );
-        }
-        _ => panic!("transaction result should have a backtrace"),
-    }
+#[test]
+fn validate_code() {
+    let engine = EnginePool::new_default((&NetworkConfig::new(NetworkVersion::V18)).into())
+        .unwrap()
+        .acquire();
+
+    let err = engine
+        .validate_code(&wat::parse_str(WAT_UNKNOWN_SYSCALL).unwrap())
+        .expect_err("expected code with an unknown import to be rejected");
+    assert!(err.downcast_ref::<UnknownImportsError>().is_some());
+
+    engine
+        .validate_code(MALFORMED_ACTOR_BINARY.unwrap())
+        .expect("actor code should only import known syscalls");
 }
 
 #[test]
@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 use std::cell::RefCell;
 use std::collections::HashSet;
+use std::io::Write;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 use anyhow::anyhow;
 use cid::Cid;
@@ -11,7 +13,8 @@ use fil_hello_world_actor::WASM_BINARY as HELLO_BINARY;
 use fil_ipld_actor::WASM_BINARY as IPLD_BINARY;
 use fil_stack_overflow_actor::WASM_BINARY as OVERFLOW_BINARY;
 use fil_syscall_actor::WASM_BINARY as SYSCALL_BINARY;
-use fvm::executor::{ApplyKind, Executor, ThreadedExecutor};
+use fvm::audit::{self, AuditLog};
+use fvm::executor::{ApplyKind, ApplyRet, Executor, ThreadedExecutor};
 use fvm_integration_tests::dummy::DummyExterns;
 use fvm_integration_tests::tester::{Account, IntegrationExecutor};
 use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
@@ -81,6 +84,70 @@ fn hello_world() {
     assert_eq!(res.msg_receipt.exit_code.value(), 16)
 }
 
+#[test]
+fn native_actor() {
+    use fvm::kernel::{BlockId, IpldBlockOps};
+    use fvm::native::NativeAbort;
+    use fvm_shared::IPLD_RAW;
+
+    type Kernel = <IntegrationExecutor<MemoryBlockstore, DummyExterns> as Executor>::Kernel;
+
+    // Invoke the hello world actor, optionally replaced by a native implementation.
+    fn run(native_actors: bool) -> (ExitCode, RawBytes) {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        let code = tester
+            .set_actor_from_bin(
+                HELLO_BINARY.unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| nc.native_actors = native_actors,
+                |_| {},
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+        executor.engine_pool().register_native_actor::<Kernel>(
+            code,
+            |kernel: &mut Kernel, _params: BlockId| -> Result<BlockId, NativeAbort> {
+                Ok(kernel.block_create(IPLD_RAW, b"native")?)
+            },
+        );
+
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: 1,
+            ..Message::default()
+        };
+        let res = executor
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+        (res.msg_receipt.exit_code, res.msg_receipt.return_data)
+    }
+
+    assert_eq!(run(true), (ExitCode::OK, RawBytes::new(b"native".to_vec())));
+
+    // Registered native actors are ignored unless enabled.
+    assert_eq!(run(false).0.value(), 16);
+}
+
 #[test]
 fn ipld() {
     // Instantiate tester
@@ -133,11 +200,322 @@ fn ipld() {
     }
 }
 
+#[test]
+fn memory_snapshots() {
+    // Invoke the same actor repeatedly, returning the gas used by each call.
+    fn run(memory_snapshots: bool) -> Vec<i64> {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        tester
+            .set_actor_from_bin(
+                IPLD_BINARY.unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    nc.memory_snapshots = memory_snapshots;
+                },
+                |_| {},
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        (0..3)
+            .map(|sequence| {
+                let message = Message {
+                    from: sender[0].1,
+                    to: actor_address,
+                    gas_limit: 1000000000,
+                    method_num: 1,
+                    sequence,
+                    ..Message::default()
+                };
+                let res = executor
+                    .execute_message(message, ApplyKind::Explicit, 100)
+                    .unwrap();
+                assert!(
+                    res.msg_receipt.exit_code.is_success(),
+                    "{:?}",
+                    res.failure_info
+                );
+                res.msg_receipt.gas_used
+            })
+            .collect()
+    }
+
+    // Starting from a memory snapshot must not change execution or gas.
+    assert_eq!(run(true), run(false));
+}
+
+#[test]
+fn instruction_counting() {
+    use fvm::trace::{frame_costs, ExecutionEvent};
+
+    // Invoke the IPLD actor once, returning the gas used and the execution trace.
+    fn run(instruction_counting: bool) -> (i64, Vec<ExecutionEvent>) {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        tester
+            .set_actor_from_bin(
+                IPLD_BINARY.unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    nc.instruction_counting = instruction_counting;
+                },
+                |mc| {
+                    mc.enable_tracing();
+                },
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: 1,
+            ..Message::default()
+        };
+        let res = executor
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+        assert!(
+            res.msg_receipt.exit_code.is_success(),
+            "{:?}",
+            res.failure_info
+        );
+        (res.msg_receipt.gas_used, res.exec_trace)
+    }
+
+    let (gas, trace) = run(true);
+    let costs = frame_costs(&trace);
+    assert_eq!(costs.len(), 1);
+    assert!(matches!(costs[0].instructions, Some(n) if n > 0));
+
+    // Counting instructions must not change execution or gas.
+    let (expected_gas, expected_trace) = run(false);
+    assert_eq!(gas, expected_gas);
+    assert_eq!(frame_costs(&expected_trace)[0].instructions, None);
+    assert_eq!(
+        fvm::trace::commitment(&trace),
+        fvm::trace::commitment(&expected_trace)
+    );
+}
+
+/// An audit log sink that can be read back after execution.
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn audit_log() {
+    // Invoke the IPLD actor twice, returning the gas used by each call.
+    fn run(log: Option<AuditLog>) -> Vec<i64> {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        tester
+            .set_actor_from_bin(
+                IPLD_BINARY.unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |_| {},
+                |mc| {
+                    if let Some(log) = log {
+                        mc.enable_audit_log(log);
+                    }
+                },
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        (0..2)
+            .map(|sequence| {
+                let message = Message {
+                    from: sender[0].1,
+                    to: actor_address,
+                    gas_limit: 1000000000,
+                    method_num: 1,
+                    sequence,
+                    ..Message::default()
+                };
+                let res = executor
+                    .execute_message(message, ApplyKind::Explicit, 100)
+                    .unwrap();
+                assert!(
+                    res.msg_receipt.exit_code.is_success(),
+                    "{:?}",
+                    res.failure_info
+                );
+                res.msg_receipt.gas_used
+            })
+            .collect()
+    }
+
+    // Auditing must not change execution or gas.
+    let buf = SharedBuf::default();
+    let gas = run(Some(AuditLog::new(buf.clone()).record_outcomes()));
+    assert_eq!(gas, run(None));
+
+    let entries = audit::read_entries(&buf.0.lock().unwrap()[..]).unwrap();
+    audit::verify(&entries).unwrap();
+    assert!(!entries.is_empty());
+    assert!(entries
+        .iter()
+        .all(|e| e.frame == 1 && e.actor == 10000 && e.outcome.is_some()));
+    assert_eq!(
+        entries.iter().map(|e| e.message).max(),
+        Some(1),
+        "expected syscalls from both messages"
+    );
+}
+
+#[test]
+fn replay_syscalls() {
+    use fvm::call_manager::DefaultCallManager;
+    use fvm::engine::EnginePool;
+    use fvm::executor::DefaultExecutor;
+    use fvm::kernel::replay::{ReplayKernel, SyscallTape};
+    use fvm::machine::{DefaultMachine, Machine};
+
+    type ReplayExecutor = DefaultExecutor<
+        ReplayKernel<DefaultCallManager<DefaultMachine<MemoryBlockstore, DummyExterns>>>,
+    >;
+
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+    let state_cid = tester.set_state(&State::default()).unwrap();
+    let actor_address = Address::new_id(10000);
+    tester
+        .set_actor_from_bin(
+            IPLD_BINARY.unwrap(),
+            state_cid,
+            actor_address,
+            TokenAmount::zero(),
+        )
+        .unwrap();
+
+    let buf = SharedBuf::default();
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |_| {},
+            |mc| {
+                mc.enable_audit_log(AuditLog::new(buf.clone()).record_outcomes());
+            },
+        )
+        .unwrap();
+
+    let message = Message {
+        from: sender[0].1,
+        to: actor_address,
+        gas_limit: 1000000000,
+        method_num: 1,
+        ..Message::default()
+    };
+
+    // Record the invocation.
+    let mut executor = tester.executor.take().unwrap();
+    let mut context = executor.context().clone();
+    let recorded = executor
+        .execute_message(message.clone(), ApplyKind::Explicit, 100)
+        .unwrap();
+    assert!(
+        recorded.msg_receipt.exit_code.is_success(),
+        "{:?}",
+        recorded.failure_info
+    );
+    let blockstore = executor.into_machine().unwrap().into_store();
+
+    // Replay it on the pre-state, serving the actor's syscalls from the tape.
+    let entries = audit::read_entries(&buf.0.lock().unwrap()[..]).unwrap();
+    let tape = SyscallTape::new(&entries, 0, 1).unwrap();
+    assert!(tape.remaining() > 0);
+    context.audit_log = None;
+    context.replay_syscalls(tape);
+
+    let engine = EnginePool::new_default((&context.network).into()).unwrap();
+    let machine = DefaultMachine::new(&context, blockstore, DummyExterns).unwrap();
+    let mut executor = ReplayExecutor::new(engine, machine).unwrap();
+    let replayed = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap();
+    assert!(
+        replayed.msg_receipt.exit_code.is_success(),
+        "{:?}",
+        replayed.failure_info
+    );
+    assert_eq!(recorded.msg_receipt, replayed.msg_receipt);
+}
+
 #[test]
 fn syscalls() {
     // Instantiate tester
     let mut tester = new_tester(
-        NetworkVersion::V18,
+        NetworkVersion::V19,
         StateTreeVersion::V5,
         MemoryBlockstore::default(),
     )
@@ -195,6 +573,60 @@ fn syscalls() {
     }
 }
 
+#[test]
+fn token_transfer_rejected() {
+    // Instantiate tester
+    let mut tester = new_tester(
+        NetworkVersion::V19,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+    let wasm_bin = SYSCALL_BINARY.unwrap();
+
+    // Set actor state
+    let actor_state = State::default();
+    let state_cid = tester.set_state(&actor_state).unwrap();
+
+    // Set actor
+    let actor_id = 10000;
+    let actor_address = Address::new_id(actor_id);
+
+    tester
+        .set_actor_from_bin(wasm_bin, state_cid, actor_address, TokenAmount::zero())
+        .unwrap();
+
+    // Instantiate machine
+    tester.instantiate_machine(DummyExterns).unwrap();
+
+    // The actor updates its state, and then transfers tokens to itself, rejecting them.
+    let message = Message {
+        from: sender[0].1,
+        to: actor_address,
+        gas_limit: 1000000000,
+        method_num: 2,
+        ..Message::default()
+    };
+
+    let executor = tester.executor.as_mut().unwrap();
+    let res = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap();
+
+    // The rejection aborts the actor, reverting the state update.
+    assert_eq!(
+        res.msg_receipt.exit_code,
+        ExitCode::USR_FORBIDDEN,
+        "{:?}",
+        res.failure_info
+    );
+    let actor = executor.state_tree().get_actor(actor_id).unwrap().unwrap();
+    assert_eq!(actor.state, state_cid);
+}
+
 #[test]
 fn exit_data() {
     // Instantiate tester
@@ -286,6 +718,7 @@ fn exit_data() {
 
         let res = tester
             .executor
+            .as_mut()
             .unwrap()
             .execute_message(message, ApplyKind::Explicit, 100)
             .unwrap();
@@ -296,6 +729,139 @@ fn exit_data() {
             RawBytes::from(vec![1u8, 2u8, 3u8, 3u8, 7u8])
         );
     }
+
+    {
+        // send method 4, which reverts with typed data
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: 4,
+            sequence: 3,
+            ..Message::default()
+        };
+
+        let res = tester
+            .executor
+            .as_mut()
+            .unwrap()
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+
+        assert_eq!(res.msg_receipt.exit_code.value(), 0x43);
+        let data: (String, u64) = res.msg_receipt.return_data.deserialize().unwrap();
+        assert_eq!(data, ("insufficient allowance".into(), 17));
+    }
+
+    {
+        // send method 5, which decodes the revert data from an internal send to method 4
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: 5,
+            sequence: 4,
+            ..Message::default()
+        };
+
+        let res = tester
+            .executor
+            .as_mut()
+            .unwrap()
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+
+        assert!(
+            res.msg_receipt.exit_code.is_success(),
+            "{:?}",
+            res.failure_info
+        );
+    }
+
+    {
+        // send method 6, which reverts with a success code
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: 6,
+            sequence: 5,
+            ..Message::default()
+        };
+
+        let res = tester
+            .executor
+            .unwrap()
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+
+        assert_eq!(res.msg_receipt.exit_code, ExitCode::USR_ASSERTION_FAILED);
+        assert!(res.msg_receipt.return_data.is_empty());
+    }
+}
+
+#[test]
+fn return_size_limits() {
+    // The exit data actor returns (or aborts with) 5 bytes. Applies `method` with the given soft
+    // and hard return size limits.
+    let apply = |method, soft, hard| {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+        let sender: [Account; 1] = tester.create_accounts().unwrap();
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        tester
+            .set_actor_from_bin(
+                EXIT_DATA_BINARY.unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    nc.return_size_limits(soft, hard);
+                },
+                |_| {},
+            )
+            .unwrap();
+
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: method,
+            ..Message::default()
+        };
+        tester
+            .executor
+            .unwrap()
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap()
+    };
+
+    // Within the limits.
+    let res = apply(2, u32::MAX, u32::MAX);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::OK);
+    let gas_used = res.msg_receipt.gas_used;
+
+    // Bytes beyond the soft limit are charged for.
+    let res = apply(2, 2, 5);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::OK);
+    assert!(res.msg_receipt.gas_used > gas_used);
+
+    // Returning more than the hard limit fails, whether the actor returns or aborts.
+    let res = apply(2, 2, 4);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_RETURN_TOO_LARGE);
+    assert!(res.msg_receipt.return_data.is_empty());
+    let res = apply(3, 2, 4);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_RETURN_TOO_LARGE);
 }
 
 #[test]
@@ -377,13 +943,13 @@ fn native_stack_overflow() {
 }
 
 fn test_exitcode(wat: &str, code: ExitCode) {
+    let res = execute_wat(NetworkVersion::V18, wat);
+    assert_eq!(res.msg_receipt.exit_code, code)
+}
+
+fn execute_wat(nv: NetworkVersion, wat: &str) -> ApplyRet {
     // Instantiate tester
-    let mut tester = new_tester(
-        NetworkVersion::V18,
-        StateTreeVersion::V5,
-        MemoryBlockstore::default(),
-    )
-    .unwrap();
+    let mut tester = new_tester(nv, StateTreeVersion::V5, MemoryBlockstore::default()).unwrap();
 
     let sender: [Account; 1] = tester.create_accounts().unwrap();
 
@@ -414,11 +980,24 @@ fn test_exitcode(wat: &str, code: ExitCode) {
     };
 
     let mut executor = ThreadedExecutor(tester.executor.unwrap());
-    let res = executor
+    executor
         .execute_message(message, ApplyKind::Explicit, 100)
-        .unwrap();
+        .unwrap()
+}
 
-    assert_eq!(res.msg_receipt.exit_code, code)
+#[test]
+fn wasm_frame_limit() {
+    // Recursing through a function without locals or operands still exhausts the Wasm stack.
+    test_exitcode(
+        r#"(module
+             (memory (export "memory") 1)
+             (func $recurse
+               (call $recurse))
+             (func (export "invoke") (param $x i32) (result i32)
+               (call $recurse)
+               (i32.const 0)))"#,
+        ExitCode::SYS_ILLEGAL_INSTRUCTION,
+    )
 }
 
 #[test]
@@ -493,8 +1072,59 @@ fn no_memory() {
 }
 
 #[test]
-fn backtraces() {
-    // Note: this test **does not actually assert anything**, but it's useful to
+fn reserved_exit_code() {
+    test_exitcode(
+        r#"(module
+             (type (;0;) (func (param i32 i32 i32 i32) (result i32)))
+             (import "vm" "exit" (func $fvm_sdk::sys::vm::exit::syscall (type 0)))
+             (memory (export "memory") 1)
+             (func (export "invoke") (param $x i32) (result i32)
+               (i32.const 7)
+               (i32.const 0)
+               (i32.const 0)
+               (i32.const 0)
+               (call $fvm_sdk::sys::vm::exit::syscall)
+               unreachable))"#,
+        ExitCode::SYS_ILLEGAL_EXIT_CODE,
+    )
+}
+
+#[test]
+fn reserved_exit_code_return_data() {
+    // Aborts with a reserved exit code, returning a raw block.
+    let wat = r#"(module
+             (type (;0;) (func (param i32 i64 i32 i32) (result i32)))
+             (type (;1;) (func (param i32 i32 i32 i32) (result i32)))
+             (import "ipld" "block_create" (func $fvm_sdk::sys::ipld::block_create::syscall (type 0)))
+             (import "vm" "exit" (func $fvm_sdk::sys::vm::exit::syscall (type 1)))
+             (memory (export "memory") 1)
+             (data (i32.const 16) "\01\02")
+             (func (export "invoke") (param $x i32) (result i32)
+               (drop (call $fvm_sdk::sys::ipld::block_create::syscall
+                 (i32.const 0)
+                 (i64.const 0x55)
+                 (i32.const 16)
+                 (i32.const 2)))
+               (i32.const 7)
+               (i32.load (i32.const 0))
+               (i32.const 0)
+               (i32.const 0)
+               (call $fvm_sdk::sys::vm::exit::syscall)
+               unreachable))"#;
+
+    // The return data is only dropped from network version 19.
+    let res = execute_wat(NetworkVersion::V18, wat);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_ILLEGAL_EXIT_CODE);
+    assert_eq!(res.msg_receipt.return_data.bytes(), &[1, 2]);
+
+    let res = execute_wat(NetworkVersion::V19, wat);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_ILLEGAL_EXIT_CODE);
+    assert!(res.msg_receipt.return_data.is_empty());
+}
+
+#[test]
+fn backtraces() {
+    // Note: this test **does not actually assert anything**, but it's useful to
     // let us peep into FVM backtrace generation under different scenarios.
     const WAT_ABORT: &str = r#"
     (module
@@ -632,6 +1262,1103 @@ fn backtraces() {
     println!("panic backtrace: {}", res.failure_info.unwrap());
 }
 
+#[test]
+fn symbolized_backtraces() {
+    use fvm::engine::DebugInfo;
+
+    const WAT_TRAP: &str = r#"
+    (module
+      (memory (export "memory") 1)
+      (func $inner unreachable)
+      (func $outer (call $inner))
+      (func (export "invoke") (param $x i32) (result i32)
+        (call $outer)
+        (i32.const 0)))
+    "#;
+
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+    let wasm = wat::parse_str(WAT_TRAP).unwrap();
+    let state_cid = tester.set_state(&State::default()).unwrap();
+    let actor_address = Address::new_id(10000);
+    let code = tester
+        .set_actor_from_bin(&wasm, state_cid, actor_address, TokenAmount::zero())
+        .unwrap();
+
+    // The tester enables actor debugging.
+    tester.instantiate_machine(DummyExterns).unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+    executor
+        .engine_pool()
+        .register_debug_info(DebugInfo::from_wasm(code, &wasm).unwrap());
+
+    let message = Message {
+        from: sender[0].1,
+        to: actor_address,
+        gas_limit: 1000000000,
+        method_num: 1,
+        ..Message::default()
+    };
+    let res = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap();
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_ILLEGAL_INSTRUCTION);
+
+    // The trap's backtrace is symbolized, from the innermost frame.
+    let backtrace = res.failure_info.unwrap().to_string();
+    assert!(
+        backtrace.contains("wasm backtrace:\n00: inner\n01: outer\n"),
+        "{}",
+        backtrace
+    );
+}
+
+#[test]
+fn insufficient_funds_trace() {
+    use fvm::trace::{ExecutionEvent, TransferFailure};
+    use fvm_integration_tests::tester::INITIAL_ACCOUNT_BALANCE;
+    use fvm_shared::METHOD_SEND;
+
+    // Instantiate tester
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [(sender_id, sender), (receiver_id, receiver)] = tester.create_accounts().unwrap();
+
+    // Instantiate machine
+    tester.instantiate_machine(DummyExterns).unwrap();
+
+    let executor = tester.executor.as_mut().unwrap();
+
+    let amount = INITIAL_ACCOUNT_BALANCE.clone() + TokenAmount::from_atto(1);
+    let message = Message {
+        from: sender,
+        to: receiver,
+        gas_limit: 1000000000,
+        method_num: METHOD_SEND,
+        value: amount.clone(),
+        ..Message::default()
+    };
+
+    let res = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap();
+
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_INSUFFICIENT_FUNDS);
+
+    let failure = res
+        .exec_trace
+        .iter()
+        .find_map(|evt| match evt {
+            ExecutionEvent::TransferFailed(failure) => Some(failure.clone()),
+            _ => None,
+        })
+        .expect("expected a failed transfer in the trace");
+
+    assert_eq!(failure.from, sender_id);
+    assert_eq!(failure.to, receiver_id);
+    assert_eq!(failure.amount, amount);
+    assert_eq!(failure.depth, 1);
+    // The message has a zero gas fee cap, so the sender's balance is untouched.
+    assert_eq!(failure.balance, *INITIAL_ACCOUNT_BALANCE);
+
+    let TransferFailure { depth, .. } = failure;
+    assert!(res
+        .failure_info
+        .unwrap()
+        .to_string()
+        .contains(&format!("insufficient funds at call depth {}", depth)));
+}
+
+#[test]
+fn message_batching() {
+    use fvm_integration_tests::tester::INITIAL_ACCOUNT_BALANCE;
+    use fvm_shared::METHOD_SEND;
+
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [(sender_id, sender), (receiver_id, receiver)] = tester.create_accounts().unwrap();
+
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |nc| {
+                nc.enable_message_batching();
+            },
+            |_| {},
+        )
+        .unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+
+    let send = |sequence, value| Message {
+        from: sender,
+        to: receiver,
+        gas_limit: 1000000000,
+        method_num: METHOD_SEND,
+        sequence,
+        value: TokenAmount::from_atto(value),
+        ..Message::default()
+    };
+    let balance = |executor: &IntegrationExecutor<MemoryBlockstore, DummyExterns>, id| {
+        executor
+            .state_tree()
+            .get_actor(id)
+            .unwrap()
+            .unwrap()
+            .balance
+    };
+
+    // Both transfers apply, with one receipt per message.
+    let res = executor
+        .execute_batch(vec![send(0, 100), send(1, 200)], ApplyKind::Explicit, 200)
+        .unwrap();
+    assert!(
+        res.apply_ret.msg_receipt.exit_code.is_success(),
+        "{:?}",
+        res.apply_ret.failure_info
+    );
+    assert_eq!(res.receipts.len(), 2);
+    assert!(res.receipts.iter().all(|r| r.exit_code.is_success()));
+    assert_eq!(
+        balance(executor, receiver_id),
+        INITIAL_ACCOUNT_BALANCE.clone() + TokenAmount::from_atto(300)
+    );
+
+    // The second transfer fails, so the first is reverted. The sequence still advances.
+    let res = executor
+        .execute_batch(
+            vec![send(2, 100), send(3, 1_000_000)],
+            ApplyKind::Explicit,
+            200,
+        )
+        .unwrap();
+    assert_eq!(
+        res.apply_ret.msg_receipt.exit_code,
+        ExitCode::SYS_INSUFFICIENT_FUNDS
+    );
+    assert_eq!(res.receipts.len(), 2);
+    assert!(res.receipts[0].exit_code.is_success());
+    assert_eq!(res.receipts[1].exit_code, ExitCode::SYS_INSUFFICIENT_FUNDS);
+    assert_eq!(
+        balance(executor, receiver_id),
+        INITIAL_ACCOUNT_BALANCE.clone() + TokenAmount::from_atto(300)
+    );
+    assert_eq!(
+        executor
+            .state_tree()
+            .get_actor(sender_id)
+            .unwrap()
+            .unwrap()
+            .sequence,
+        4
+    );
+
+    // Messages with gaps in their sequences can't be batched.
+    let res = executor
+        .execute_batch(vec![send(4, 1), send(6, 1)], ApplyKind::Explicit, 200)
+        .unwrap();
+    assert_eq!(
+        res.apply_ret.msg_receipt.exit_code,
+        ExitCode::SYS_SENDER_STATE_INVALID
+    );
+
+    // Each message is limited to its own gas limit, even if the batch has gas to spare.
+    let starved = Message {
+        gas_limit: 1,
+        ..send(5, 1)
+    };
+    let res = executor
+        .execute_batch(vec![send(4, 1), starved], ApplyKind::Explicit, 200)
+        .unwrap();
+    assert_eq!(
+        res.apply_ret.msg_receipt.exit_code,
+        ExitCode::SYS_OUT_OF_GAS
+    );
+    assert_eq!(res.receipts.len(), 2);
+    assert_eq!(res.receipts[1].exit_code, ExitCode::SYS_OUT_OF_GAS);
+}
+
+#[test]
+fn message_params_size_limit() {
+    use fvm_shared::METHOD_SEND;
+
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [(_, sender), (_, receiver)] = tester.create_accounts().unwrap();
+
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |nc| {
+                nc.max_message_params_size(64);
+            },
+            |_| {},
+        )
+        .unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+
+    let send = |sequence, params_size| Message {
+        from: sender,
+        to: receiver,
+        gas_limit: 1000000000,
+        method_num: METHOD_SEND,
+        sequence,
+        params: RawBytes::new(vec![1; params_size]),
+        ..Message::default()
+    };
+
+    // Params up to the limit are accepted.
+    let res = executor
+        .execute_message(send(0, 64), ApplyKind::Explicit, 200)
+        .unwrap();
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::OK);
+
+    // Larger params fail validation, and the miner is penalized.
+    let res = executor
+        .execute_message(send(1, 65), ApplyKind::Explicit, 200)
+        .unwrap();
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_PARAMS_TOO_LARGE);
+    assert!(res.penalty.is_positive());
+
+    // Implicit messages aren't limited.
+    let res = executor
+        .execute_message(send(1, 65), ApplyKind::Implicit, 200)
+        .unwrap();
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::OK);
+}
+
+#[test]
+fn account_abstraction() {
+    use fvm_shared::METHOD_SEND;
+
+    // Accepts every message, including every message it's asked to validate.
+    const ACCEPT: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "invoke") (param $x i32) (result i32)
+            (i32.const 0)
+        )
+    )"#;
+    // Traps on every message.
+    const REJECT: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "invoke") (param $x i32) (result i32)
+            (unreachable)
+        )
+    )"#;
+
+    let run = |wat: &str, enabled: bool| {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let [(receiver_id, receiver)] = tester.create_accounts().unwrap();
+
+        let wallet_id = 10000;
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        tester
+            .set_actor_from_bin(
+                &wat::parse_str(wat).unwrap(),
+                state_cid,
+                Address::new_id(wallet_id),
+                TokenAmount::from_atto(1000),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    if enabled {
+                        nc.enable_account_abstraction(10_000_000);
+                    }
+                },
+                |_| {},
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        let message = Message {
+            from: Address::new_id(wallet_id),
+            to: receiver,
+            gas_limit: 1000000000,
+            method_num: METHOD_SEND,
+            value: TokenAmount::from_atto(100),
+            ..Message::default()
+        };
+        let res = executor
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+        let wallet = executor.state_tree().get_actor(wallet_id).unwrap().unwrap();
+        let receiver = executor
+            .state_tree()
+            .get_actor(receiver_id)
+            .unwrap()
+            .unwrap();
+        (res, wallet, receiver)
+    };
+
+    // Without account abstraction, only accounts can send messages.
+    let (res, ..) = run(ACCEPT, false);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_SENDER_INVALID);
+
+    // The wallet validates the message, and pays for its validation.
+    let (res, wallet, _) = run(ACCEPT, true);
+    assert!(
+        res.msg_receipt.exit_code.is_success(),
+        "{:?}",
+        res.failure_info
+    );
+    assert!(res.msg_receipt.gas_used > 0);
+    assert_eq!(wallet.sequence, 1);
+    assert_eq!(wallet.balance, TokenAmount::from_atto(900));
+
+    // The wallet rejects the message.
+    let (res, wallet, receiver) = run(REJECT, true);
+    assert_eq!(res.msg_receipt.exit_code, ExitCode::SYS_SENDER_INVALID);
+    assert_eq!(wallet.sequence, 0);
+    assert_eq!(wallet.balance, TokenAmount::from_atto(1000));
+    assert_eq!(
+        receiver.balance,
+        fvm_integration_tests::tester::INITIAL_ACCOUNT_BALANCE.clone()
+    );
+}
+
+#[test]
+fn gas_subsidies() {
+    use fvm::gas::{GasSubsidy, SubsidyPayer};
+
+    const ACCEPT: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "invoke") (param $x i32) (result i32)
+            (i32.const 0)
+        )
+    )"#;
+
+    // Sends two messages to a subsidized method, with a quota only covering the first one.
+    let run = |network_payer: bool| {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let [(sender_id, sender), (network_id, _)] = tester.create_accounts().unwrap();
+
+        let actor_id = 10000;
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        tester
+            .set_actor_from_bin(
+                &wat::parse_str(ACCEPT).unwrap(),
+                state_cid,
+                Address::new_id(actor_id),
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        let payer = if network_payer {
+            SubsidyPayer::Account(network_id)
+        } else {
+            SubsidyPayer::Waived
+        };
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    nc.gas_subsidies([GasSubsidy {
+                        actor: actor_id,
+                        method: 2,
+                        payer,
+                        quota: 1_000_001,
+                    }]);
+                },
+                |_| {},
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        (0..2)
+            .map(|sequence| {
+                let message = Message {
+                    from: sender,
+                    to: Address::new_id(actor_id),
+                    gas_limit: 1_000_000,
+                    gas_fee_cap: TokenAmount::from_atto(100),
+                    method_num: 2,
+                    sequence,
+                    ..Message::default()
+                };
+                let res = executor
+                    .execute_message(message, ApplyKind::Explicit, 100)
+                    .unwrap();
+                assert!(
+                    res.msg_receipt.exit_code.is_success(),
+                    "{:?}",
+                    res.failure_info
+                );
+                let balance = |id| {
+                    executor
+                        .state_tree()
+                        .get_actor(id)
+                        .unwrap()
+                        .unwrap()
+                        .balance
+                };
+                (res, balance(sender_id), balance(network_id))
+            })
+            .collect::<Vec<_>>()
+    };
+    let initial = fvm_integration_tests::tester::INITIAL_ACCOUNT_BALANCE.clone();
+
+    // The gas of the first message is waived. The second one exceeds the quota.
+    let res = run(false);
+    let (first, sender_balance, _) = &res[0];
+    assert!(first.subsidy.is_positive());
+    assert!(first.base_fee_burn.is_zero());
+    assert!(first.miner_tip.is_zero());
+    assert_eq!(sender_balance, &initial);
+    let (second, sender_balance, _) = &res[1];
+    assert!(second.subsidy.is_zero());
+    assert!(second.base_fee_burn.is_positive());
+    assert!(sender_balance < &initial);
+
+    // The network account pays for the gas of the first message.
+    let res = run(true);
+    let (first, sender_balance, network_balance) = &res[0];
+    assert!(first.subsidy.is_positive());
+    assert!(first.base_fee_burn.is_positive());
+    assert_eq!(sender_balance, &initial);
+    assert_eq!(network_balance, &(&initial - &first.subsidy));
+    let (second, sender_balance, _) = &res[1];
+    assert!(second.subsidy.is_zero());
+    assert!(sender_balance < &initial);
+}
+
+#[test]
+fn transfer_hooks() {
+    use fvm::call_manager::{
+        HookPhase, TransferFilter, TransferHook, TransferHookParams, NO_DATA_BLOCK_ID,
+    };
+    use fvm::kernel::{BlockId, IpldBlockOps};
+    use fvm::native::NativeAbort;
+    use fvm_integration_tests::tester::INITIAL_ACCOUNT_BALANCE;
+    use fvm_shared::METHOD_SEND;
+
+    type Kernel = <IntegrationExecutor<MemoryBlockstore, DummyExterns> as Executor>::Kernel;
+
+    for phase in [HookPhase::Before, HookPhase::After] {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let [(sender_id, sender), (receiver_id, receiver)] = tester.create_accounts().unwrap();
+
+        // The hook is implemented by a native actor.
+        let hook_id = 10000;
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let hook_code = tester
+            .set_actor_from_bin(
+                IPLD_BINARY.unwrap(),
+                state_cid,
+                Address::new_id(hook_id),
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    nc.enable_native_actors().transfer_hooks([TransferHook {
+                        filter: TransferFilter {
+                            method: Some(METHOD_SEND),
+                            actor_type: None,
+                            with_value: true,
+                        },
+                        phase,
+                        actor: hook_id,
+                        method: 2,
+                    }]);
+                },
+                |_| {},
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        // Record the hooked sends, vetoing transfers of more than 100 atto.
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        executor.engine_pool().register_native_actor::<Kernel>(
+            hook_code,
+            move |kernel: &mut Kernel, params: BlockId| -> Result<BlockId, NativeAbort> {
+                let mut buf = vec![0; kernel.block_stat(params)?.size as usize];
+                kernel.block_read(params, 0, &mut buf)?;
+                let params: TransferHookParams = fvm_ipld_encoding::from_slice(&buf).unwrap();
+                let veto = params.value > TokenAmount::from_atto(100);
+                recorded.lock().unwrap().push(params);
+                if veto {
+                    return Err(NativeAbort::Exit(
+                        ExitCode::USR_FORBIDDEN,
+                        "transfer too large".into(),
+                        NO_DATA_BLOCK_ID,
+                    ));
+                }
+                Ok(NO_DATA_BLOCK_ID)
+            },
+        );
+
+        let send = |sequence, value| Message {
+            from: sender,
+            to: receiver,
+            gas_limit: 1000000000,
+            method_num: METHOD_SEND,
+            sequence,
+            value: TokenAmount::from_atto(value),
+            ..Message::default()
+        };
+        let balance = |executor: &IntegrationExecutor<MemoryBlockstore, DummyExterns>, id| {
+            executor
+                .state_tree()
+                .get_actor(id)
+                .unwrap()
+                .unwrap()
+                .balance
+        };
+
+        // The hook accepts the transfer.
+        let res = executor
+            .execute_message(send(0, 100), ApplyKind::Explicit, 100)
+            .unwrap();
+        assert!(
+            res.msg_receipt.exit_code.is_success(),
+            "{:?}",
+            res.failure_info
+        );
+        assert_eq!(
+            balance(executor, receiver_id),
+            INITIAL_ACCOUNT_BALANCE.clone() + TokenAmount::from_atto(100)
+        );
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![TransferHookParams {
+                from: sender_id,
+                to: receiver_id,
+                method: METHOD_SEND,
+                value: TokenAmount::from_atto(100),
+            }]
+        );
+
+        // The hook rejects the transfer, which fails with the hook's exit code (and is reverted).
+        let res = executor
+            .execute_message(send(1, 1000), ApplyKind::Explicit, 100)
+            .unwrap();
+        assert_eq!(res.msg_receipt.exit_code, ExitCode::USR_FORBIDDEN);
+        assert_eq!(
+            balance(executor, receiver_id),
+            INITIAL_ACCOUNT_BALANCE.clone() + TokenAmount::from_atto(100)
+        );
+        assert_eq!(calls.lock().unwrap().len(), 2);
+
+        // Sends without value don't match the filter.
+        let res = executor
+            .execute_message(send(2, 0), ApplyKind::Explicit, 100)
+            .unwrap();
+        assert!(res.msg_receipt.exit_code.is_success());
+        assert_eq!(calls.lock().unwrap().len(), 2);
+    }
+}
+
+#[test]
+fn memory_growth() {
+    use fvm::trace::ExecutionEvent;
+
+    // Grows the memory by a page, checks that both pages are zeroed, then dirties them.
+    const WAT_GROW: &str = r#"
+    (module
+      (memory (export "memory") 1)
+      (func (export "invoke") (param $x i32) (result i32)
+        (local $i i32)
+        (if (i32.ne (memory.grow (i32.const 1)) (i32.const 1))
+          (then unreachable))
+        (block $done
+          (loop $next
+            (br_if $done (i32.ge_u (local.get $i) (i32.const 131072)))
+            (if (i64.ne (i64.load (local.get $i)) (i64.const 0))
+              (then unreachable))
+            (i64.store (local.get $i) (i64.const -1))
+            (local.set $i (i32.add (local.get $i) (i32.const 8)))
+            (br $next)))
+        (i32.const 0)))
+    "#;
+
+    // Invoke the actor repeatedly, returning the exit codes and memory growth events.
+    fn run(max_growth: u64) -> Vec<(ExitCode, Vec<(u64, u64)>)> {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+
+        let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        tester
+            .set_actor_from_bin(
+                &wat::parse_str(WAT_GROW).unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(
+                DummyExterns,
+                |nc| {
+                    nc.max_memory_growth_bytes(max_growth);
+                },
+                |mc| {
+                    mc.enable_tracing();
+                },
+            )
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+
+        (0..3)
+            .map(|sequence| {
+                let message = Message {
+                    from: sender[0].1,
+                    to: actor_address,
+                    gas_limit: 1000000000,
+                    method_num: 1,
+                    sequence,
+                    ..Message::default()
+                };
+                let res = executor
+                    .execute_message(message, ApplyKind::Explicit, 100)
+                    .unwrap();
+                let growth = res
+                    .exec_trace
+                    .iter()
+                    .filter_map(|e| match e {
+                        ExecutionEvent::MemoryGrowth { from, to } => Some((*from, *to)),
+                        _ => None,
+                    })
+                    .collect();
+                (res.msg_receipt.exit_code, growth)
+            })
+            .collect()
+    }
+
+    // New pages are zeroed, even when the instance's memory was previously used (and dirtied) by
+    // another message.
+    let page = 65536;
+    for (exit_code, growth) in run(u64::MAX) {
+        assert_eq!(exit_code, ExitCode::OK);
+        assert_eq!(growth, vec![(0, page), (page, 2 * page)]);
+    }
+
+    // The initial page counts towards the growth limit, so growing the memory fails.
+    for (exit_code, growth) in run(page) {
+        assert_eq!(exit_code, ExitCode::SYS_ILLEGAL_INSTRUCTION);
+        assert_eq!(growth, vec![(0, page)]);
+    }
+}
+
+#[test]
+fn actor_gas_accounting() {
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let sender: [Account; 1] = tester.create_accounts().unwrap();
+
+    let state_cid = tester.set_state(&State::default()).unwrap();
+    let actor_address = Address::new_id(10000);
+    let code = tester
+        .set_actor_from_bin(
+            HELLO_BINARY.unwrap(),
+            state_cid,
+            actor_address,
+            TokenAmount::zero(),
+        )
+        .unwrap();
+
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |_| {},
+            |mc| {
+                mc.enable_actor_gas_accounting();
+            },
+        )
+        .unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+
+    // Invoke the actor twice, then send it funds.
+    let mut gas_used = 0;
+    for (sequence, method_num) in [(0, 1), (1, 1), (2, 0)] {
+        let message = Message {
+            from: sender[0].1,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num,
+            sequence,
+            value: TokenAmount::from_atto(method_num),
+            ..Message::default()
+        };
+        let res = executor
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+        gas_used += res.msg_receipt.gas_used;
+    }
+
+    let usage = executor.take_actor_gas_usage();
+    let invoke = usage.get(&code, 1).unwrap();
+    let send = usage.get(&code, 0).unwrap();
+    assert_eq!((invoke.calls, send.calls), (2, 1));
+    assert!(invoke.gas > send.gas);
+    assert!(send.gas > fvm::gas::Gas::zero());
+    // Message inclusion gas isn't attributed to any actor.
+    assert!(usage.total() < fvm::gas::Gas::new(gas_used));
+
+    // The usage is reset once taken.
+    assert!(executor.actor_gas_usage().is_empty());
+}
+
+#[test]
+fn supply_check() {
+    use fvm::executor::supply::SupplyViolation;
+    use fvm::machine::Machine;
+
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [(sender, sender_address), (_, receiver)] = tester.create_accounts().unwrap();
+
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |_| {},
+            |mc| {
+                mc.enable_supply_check();
+            },
+        )
+        .unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+
+    // Transfers and gas fees conserve the supply.
+    let message = Message {
+        from: sender_address,
+        to: receiver,
+        gas_limit: 1000000000,
+        value: TokenAmount::from_atto(100),
+        ..Message::default()
+    };
+    let res = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap();
+    assert!(res.msg_receipt.exit_code.is_success());
+    executor.flush().unwrap();
+
+    // Minting tokens out of band violates it, unless expected.
+    let mint = |executor: &mut IntegrationExecutor<MemoryBlockstore, DummyExterns>| {
+        executor
+            .state_tree_mut()
+            .mutate_actor(sender, |actor| {
+                actor.deposit_funds(&TokenAmount::from_atto(7));
+                Ok(())
+            })
+            .unwrap();
+    };
+    mint(executor);
+    let err = executor.flush().unwrap_err();
+    let violation = err.downcast_ref::<SupplyViolation>().unwrap();
+    assert_eq!(
+        &violation.actual - &violation.expected,
+        TokenAmount::from_atto(7)
+    );
+    assert_eq!(violation.deltas.len(), 1);
+    assert_eq!(violation.deltas[0].actor, sender);
+    assert_eq!(violation.deltas[0].change(), TokenAmount::from_atto(7));
+
+    mint(executor);
+    executor.expect_supply_change(&TokenAmount::from_atto(7));
+    executor.flush().unwrap();
+}
+
+#[test]
+fn cancellation() {
+    use fvm::cancel::{CancellationToken, Cancelled};
+    use fvm::executor::ExecutionProgress;
+    use fvm::kernel::{BlockId, IpldBlockOps};
+    use fvm::machine::Machine;
+    use fvm::native::NativeAbort;
+    use fvm_shared::{IPLD_RAW, METHOD_SEND};
+
+    type Kernel = <IntegrationExecutor<MemoryBlockstore, DummyExterns> as Executor>::Kernel;
+
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+    let [(sender, sender_address)] = tester.create_accounts().unwrap();
+
+    let state_cid = tester.set_state(&State::default()).unwrap();
+    let actor_address = Address::new_id(10000);
+    let code = tester
+        .set_actor_from_bin(
+            HELLO_BINARY.unwrap(),
+            state_cid,
+            actor_address,
+            TokenAmount::zero(),
+        )
+        .unwrap();
+
+    let token = CancellationToken::new();
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |nc| nc.native_actors = true,
+            |mc| {
+                mc.set_cancellation_token(token.clone());
+            },
+        )
+        .unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+
+    // A message applied before cancellation is counted in the progress.
+    let send = Message {
+        from: sender_address,
+        to: sender_address,
+        gas_limit: 1000000000,
+        method_num: METHOD_SEND,
+        ..Message::default()
+    };
+    let res = executor
+        .execute_message(send, ApplyKind::Explicit, 100)
+        .unwrap();
+    assert!(res.msg_receipt.exit_code.is_success());
+
+    // The actor is cancelled while it runs.
+    let cancel = token.clone();
+    executor.engine_pool().register_native_actor::<Kernel>(
+        code,
+        move |kernel: &mut Kernel, _params: BlockId| -> Result<BlockId, NativeAbort> {
+            cancel.cancel();
+            Ok(kernel.block_create(IPLD_RAW, b"native")?)
+        },
+    );
+
+    let message = Message {
+        from: sender_address,
+        to: actor_address,
+        gas_limit: 1000000000,
+        method_num: 1,
+        sequence: 1,
+        ..Message::default()
+    };
+    let before = executor.state_tree().get_actor(sender).unwrap().unwrap();
+    let err = executor
+        .execute_message(message.clone(), ApplyKind::Explicit, 100)
+        .unwrap_err();
+    let cancelled = err.downcast_ref::<Cancelled<ExecutionProgress>>().unwrap();
+    assert_eq!(cancelled.progress, ExecutionProgress { messages: 1 });
+
+    // The message was reverted, including the gas payment and nonce bump.
+    let after = executor.state_tree().get_actor(sender).unwrap().unwrap();
+    assert_eq!(before, after);
+
+    // Further messages are cancelled before they start.
+    let err = executor
+        .execute_message(message, ApplyKind::Explicit, 100)
+        .unwrap_err();
+    let cancelled = err.downcast_ref::<Cancelled<ExecutionProgress>>().unwrap();
+    assert_eq!(cancelled.progress, ExecutionProgress { messages: 1 });
+}
+
+#[test]
+fn result_cache() {
+    use fvm::executor::ResultCache;
+    use fvm::kernel::{BlockId, IpldBlockOps};
+    use fvm::native::NativeAbort;
+    use fvm_shared::IPLD_RAW;
+
+    type Kernel = <IntegrationExecutor<MemoryBlockstore, DummyExterns> as Executor>::Kernel;
+
+    let cache = ResultCache::new(16);
+
+    // Applies the same message to the same state, with a native actor that either returns a block
+    // or must not be invoked at all.
+    let apply = |invoke: bool| {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )
+        .unwrap();
+        let [(_, sender_address)] = tester.create_accounts().unwrap();
+
+        let state_cid = tester.set_state(&State::default()).unwrap();
+        let actor_address = Address::new_id(10000);
+        let code = tester
+            .set_actor_from_bin(
+                HELLO_BINARY.unwrap(),
+                state_cid,
+                actor_address,
+                TokenAmount::zero(),
+            )
+            .unwrap();
+
+        tester
+            .instantiate_machine_with_config(DummyExterns, |nc| nc.native_actors = true, |_| {})
+            .unwrap();
+        let executor = tester.executor.as_mut().unwrap();
+        executor.set_result_cache(cache.clone(), Cid::default());
+        executor.engine_pool().register_native_actor::<Kernel>(
+            code,
+            move |kernel: &mut Kernel, _params: BlockId| -> Result<BlockId, NativeAbort> {
+                assert!(invoke, "the cached result should have been used");
+                Ok(kernel.block_create(IPLD_RAW, b"native")?)
+            },
+        );
+
+        let message = Message {
+            from: sender_address,
+            to: actor_address,
+            gas_limit: 1000000000,
+            method_num: 1,
+            value: TokenAmount::from_atto(10),
+            ..Message::default()
+        };
+        let res = executor
+            .execute_message(message, ApplyKind::Explicit, 100)
+            .unwrap();
+        assert!(res.msg_receipt.exit_code.is_success());
+        (res.msg_receipt, executor.flush().unwrap())
+    };
+
+    let (receipt, root) = apply(true);
+    assert_eq!(cache.len(), 1);
+
+    // The second time around, the result and state changes come from the cache.
+    assert_eq!(apply(false), (receipt, root));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn machine_pool() {
+    use fvm::executor::MachinePool;
+    use fvm::machine::Machine;
+
+    type PoolExecutor = IntegrationExecutor<MemoryBlockstore, DummyExterns>;
+
+    let actor_address = Address::new_id(10000);
+
+    // Builds a machine with a funded account (the same one every time) and an actor to pay.
+    fn build() -> anyhow::Result<(Address, PoolExecutor)> {
+        let mut tester = new_tester(
+            NetworkVersion::V18,
+            StateTreeVersion::V5,
+            MemoryBlockstore::default(),
+        )?;
+        let [(_, sender_address)] = tester.create_accounts()?;
+        let state_cid = tester.set_state(&State::default())?;
+        tester.set_actor_from_bin(
+            HELLO_BINARY.unwrap(),
+            state_cid,
+            Address::new_id(10000),
+            TokenAmount::zero(),
+        )?;
+        tester.instantiate_machine(DummyExterns)?;
+        Ok((sender_address, tester.executor.take().unwrap()))
+    }
+
+    let (sender_address, _) = build().unwrap();
+    let pool = MachinePool::new(2, || build().map(|(_, executor)| executor)).unwrap();
+    assert_eq!(pool.size(), 2);
+    assert_eq!(pool.idle(), 2);
+
+    let transfer = Message {
+        from: sender_address,
+        to: actor_address,
+        gas_limit: 1000000000,
+        value: TokenAmount::from_atto(10),
+        ..Message::default()
+    };
+    let balance = |executor: &PoolExecutor| {
+        executor
+            .state_tree()
+            .get_actor(10000)
+            .unwrap()
+            .unwrap()
+            .balance
+    };
+
+    // The changes made by each call are discarded when the machine is returned to the pool.
+    for _ in 0..3 {
+        let res = pool.call_readonly(transfer.clone(), 100).unwrap();
+        assert!(res.msg_receipt.exit_code.is_success());
+        let res = pool.estimate_gas(transfer.clone(), 100).unwrap();
+        assert!(res.msg_receipt.exit_code.is_success());
+        assert!(res.msg_receipt.gas_used > 0);
+    }
+
+    let mut first = pool.checkout().unwrap();
+    let second = pool.try_checkout().unwrap().unwrap();
+    assert!(pool.try_checkout().unwrap().is_none());
+    assert_eq!(balance(&first), TokenAmount::zero());
+    assert_eq!(balance(&second), TokenAmount::zero());
+
+    // Checked out machines can be used directly.
+    let res = first
+        .execute_message(transfer, ApplyKind::Explicit, 100)
+        .unwrap();
+    assert!(res.msg_receipt.exit_code.is_success());
+    assert_eq!(balance(&first), TokenAmount::from_atto(10));
+    drop(first);
+    assert_eq!(pool.idle(), 1);
+    assert_eq!(balance(&pool.checkout().unwrap()), TokenAmount::zero());
+}
+
 #[derive(Default)]
 pub struct FailingBlockstore {
     fail_for: RefCell<HashSet<Cid>>,
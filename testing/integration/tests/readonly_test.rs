@@ -15,13 +15,15 @@ use num_traits::Zero;
 
 #[test]
 fn readonly_actor_tests() {
+    // Extern-dependent syscalls are only refused in read-only contexts from network version 19.
+    for nv in [NetworkVersion::V18, NetworkVersion::V19] {
+        run_readonly_actor(nv);
+    }
+}
+
+fn run_readonly_actor(nv: NetworkVersion) {
     // Instantiate tester
-    let mut tester = new_tester(
-        NetworkVersion::V18,
-        StateTreeVersion::V5,
-        MemoryBlockstore::default(),
-    )
-    .unwrap();
+    let mut tester = new_tester(nv, StateTreeVersion::V5, MemoryBlockstore::default()).unwrap();
 
     let [(_sender_id, sender_address)] = tester.create_accounts().unwrap();
 
@@ -0,0 +1,72 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use bundles::*;
+use fvm::executor::{ApplyKind, Executor};
+use fvm::machine::Machine;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::signer::{message_cid, KeyType};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_shared::address::Protocol;
+use fvm_shared::chainid::ChainID;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::message::SignedMessage;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::METHOD_SEND;
+
+#[test]
+fn multi_sender_transfers() {
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [mut alice, mut bob] = tester.create_signing_accounts(KeyType::Secp256k1).unwrap();
+    let [mut carol] = tester.create_signing_accounts(KeyType::Bls).unwrap();
+    assert_eq!(alice.address.protocol(), Protocol::Secp256k1);
+    assert_eq!(carol.address.protocol(), Protocol::BLS);
+    assert_ne!(alice.address, bob.address);
+
+    tester.instantiate_machine(DummyExterns).unwrap();
+    let executor = tester.executor.as_mut().unwrap();
+
+    // Interleave messages from all senders; nonces are tracked per account.
+    let value = TokenAmount::from_atto(10);
+    let (a, b, c) = (alice.address, bob.address, carol.address);
+    for _ in 0..3 {
+        for (from, to) in [(&mut alice, b), (&mut bob, c), (&mut carol, a)] {
+            let (msg, sig) = from
+                .signed_message(to, METHOD_SEND, Default::default(), value.clone())
+                .unwrap();
+
+            // The signature covers the message CID.
+            let cid = message_cid(&msg).unwrap();
+            sig.verify(&cid.to_bytes(), &from.address).unwrap();
+            let signed = SignedMessage {
+                message: msg,
+                signature: sig,
+            };
+            signed.verify(ChainID::from(0)).unwrap();
+
+            let res = executor
+                .execute_message(signed.message, ApplyKind::Explicit, 100)
+                .unwrap();
+            assert_eq!(res.msg_receipt.exit_code, ExitCode::OK);
+        }
+    }
+
+    for account in [&alice, &bob, &carol] {
+        assert_eq!(account.nonce(), 3);
+        let state = executor
+            .state_tree()
+            .get_actor(account.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(state.sequence, 3);
+    }
+}
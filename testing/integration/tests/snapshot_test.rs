@@ -0,0 +1,113 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use std::cell::RefCell;
+
+use anyhow::Result;
+use bundles::*;
+use cid::Cid;
+use futures::executor::block_on;
+use futures::io::AllowStdIo;
+use fvm::machine::Machine;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::{Tester, INITIAL_ACCOUNT_BALANCE};
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+use fvm_ipld_car::CarHeader;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::METHOD_SEND;
+
+/// A blockstore remembering the order blocks were written in, so they can be exported.
+#[derive(Default)]
+struct RecordingBlockstore {
+    blocks: MemoryBlockstore,
+    keys: RefCell<Vec<Cid>>,
+}
+
+impl Blockstore for RecordingBlockstore {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.blocks.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.keys.borrow_mut().push(*k);
+        self.blocks.put_keyed(k, block)
+    }
+}
+
+#[test]
+fn impersonate_from_snapshot() {
+    // Build some state, and export it as a snapshot.
+    let mut tester: Tester<_, DummyExterns> = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        RecordingBlockstore::default(),
+    )
+    .unwrap();
+    let [(sender, _), (_, receiver)] = tester.create_accounts().unwrap();
+    let mut state_tree = tester.state_tree.take().unwrap();
+    let state_root = state_tree.flush().unwrap();
+    let store = state_tree.into_store();
+
+    let path = std::env::temp_dir().join(format!("fvm-snapshot-{}.car", std::process::id()));
+    let blocks: Vec<_> = store
+        .keys
+        .borrow()
+        .iter()
+        .map(|k| (*k, store.get(k).unwrap().unwrap()))
+        .collect();
+    let mut file = AllowStdIo::new(std::fs::File::create(&path).unwrap());
+    block_on(
+        CarHeader::from(vec![state_root])
+            .write_stream_async(&mut file, &mut futures::stream::iter(blocks)),
+    )
+    .unwrap();
+    drop(file);
+
+    // Load the snapshot, and send funds from an account we don't hold the key to, with the wrong
+    // nonce.
+    let mut tester: Tester<_, DummyExterns> = Tester::from_car(
+        NetworkVersion::V18,
+        &path,
+        state_root,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    tester.instantiate_machine(DummyExterns).unwrap();
+
+    let to_send = TokenAmount::from_atto(1000);
+    let res = tester
+        .impersonate(Message {
+            from: Address::new_id(sender),
+            to: receiver,
+            gas_limit: 1_000_000_000,
+            method_num: METHOD_SEND,
+            sequence: 42,
+            value: to_send.clone(),
+            ..Message::default()
+        })
+        .unwrap();
+    assert!(
+        res.msg_receipt.exit_code.is_success(),
+        "{:?}",
+        res.failure_info
+    );
+
+    let state_tree = tester.executor.as_ref().unwrap().state_tree();
+    let sender_state = state_tree.get_actor(sender).unwrap().unwrap();
+    assert_eq!(sender_state.sequence, 0);
+    assert_eq!(
+        sender_state.balance,
+        INITIAL_ACCOUNT_BALANCE.clone() - &to_send
+    );
+    let receiver_state = state_tree.get_actor_by_address(&receiver).unwrap().unwrap();
+    assert_eq!(
+        receiver_state.balance,
+        INITIAL_ACCOUNT_BALANCE.clone() + to_send
+    );
+}
@@ -0,0 +1,167 @@
+// Copyright 2021-2023 Protocol Labs
+// SPDX-License-Identifier: Apache-2.0, MIT
+mod bundles;
+
+use bundles::*;
+use fvm::executor::{ApplyKind, Executor};
+use fvm::machine::{Machine, TombstonePolicy, BURNT_FUNDS_ACTOR_ID};
+use fvm::state_tree::ActorState;
+use fvm_integration_tests::dummy::DummyExterns;
+use fvm_integration_tests::tester::{Account, IntegrationExecutor, Tester};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::message::Message;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{MethodNum, METHOD_SEND};
+
+fn setup(
+    policy: Option<TombstonePolicy>,
+) -> (Account, Account, Tester<MemoryBlockstore, DummyExterns>) {
+    let mut tester = new_tester(
+        NetworkVersion::V18,
+        StateTreeVersion::V5,
+        MemoryBlockstore::default(),
+    )
+    .unwrap();
+
+    let [sender, deleted] = tester.create_accounts().unwrap();
+
+    // Deploy the burnt funds actor (an account), then delete one of the accounts.
+    let state_tree = tester.state_tree.as_mut().unwrap();
+    let account = state_tree.get_actor(deleted.0).unwrap().unwrap();
+    state_tree
+        .set_actor(
+            BURNT_FUNDS_ACTOR_ID,
+            ActorState::new_empty(account.code, None),
+        )
+        .unwrap();
+    state_tree.delete_actor(deleted.0).unwrap();
+
+    tester
+        .instantiate_machine_with_config(
+            DummyExterns,
+            |nc| {
+                if let Some(policy) = policy {
+                    nc.tombstone_policy(policy);
+                }
+            },
+            |_| (),
+        )
+        .unwrap();
+
+    (sender, deleted, tester)
+}
+
+fn send(
+    executor: &mut IntegrationExecutor<MemoryBlockstore, DummyExterns>,
+    from: Address,
+    to: Address,
+    method_num: MethodNum,
+    value: &TokenAmount,
+    kind: ApplyKind,
+) -> ExitCode {
+    let message = Message {
+        from,
+        to,
+        gas_limit: 1000000000,
+        method_num,
+        value: value.clone(),
+        ..Message::default()
+    };
+
+    let res = executor.execute_message(message, kind, 100).unwrap();
+    res.msg_receipt.exit_code
+}
+
+fn balance(
+    executor: &IntegrationExecutor<MemoryBlockstore, DummyExterns>,
+    addr: &Address,
+) -> TokenAmount {
+    executor
+        .state_tree()
+        .get_actor_by_address(addr)
+        .unwrap()
+        .map(|act| act.balance)
+        .unwrap_or_default()
+}
+
+#[test]
+fn send_to_deleted_actor_rejected() {
+    let (sender, deleted, mut tester) = setup(None);
+    let executor = tester.executor.as_mut().unwrap();
+
+    let value = TokenAmount::from_atto(100);
+    let burnt = Address::new_id(BURNT_FUNDS_ACTOR_ID);
+    let burnt_before = balance(executor, &burnt);
+
+    // Implicit messages don't bump the sender's nonce, or pay for gas.
+    let sender_before = balance(executor, &sender.1);
+    assert_eq!(
+        send(
+            executor,
+            sender.1,
+            deleted.1,
+            METHOD_SEND,
+            &value,
+            ApplyKind::Implicit
+        ),
+        ExitCode::SYS_INVALID_RECEIVER
+    );
+
+    // The funds stay with the sender, and the actor isn't resurrected.
+    assert_eq!(balance(executor, &sender.1), sender_before);
+    assert_eq!(balance(executor, &burnt), burnt_before);
+    assert!(executor
+        .state_tree()
+        .get_actor(deleted.0)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn send_to_deleted_actor_burnt() {
+    let (sender, deleted, mut tester) = setup(Some(TombstonePolicy::Burn));
+    let executor = tester.executor.as_mut().unwrap();
+
+    let value = TokenAmount::from_atto(100);
+    let burnt = Address::new_id(BURNT_FUNDS_ACTOR_ID);
+    let burnt_before = balance(executor, &burnt);
+    let sender_before = balance(executor, &sender.1);
+
+    // Plain value transfers are burnt.
+    assert_eq!(
+        send(
+            executor,
+            sender.1,
+            deleted.1,
+            METHOD_SEND,
+            &value,
+            ApplyKind::Implicit
+        ),
+        ExitCode::OK
+    );
+    assert_eq!(balance(executor, &sender.1), &sender_before - &value);
+    assert_eq!(balance(executor, &burnt), &burnt_before + &value);
+    assert!(executor
+        .state_tree()
+        .get_actor(deleted.0)
+        .unwrap()
+        .is_none());
+
+    // Method invocations are still rejected.
+    assert_eq!(
+        send(
+            executor,
+            sender.1,
+            deleted.1,
+            2,
+            &value,
+            ApplyKind::Implicit
+        ),
+        ExitCode::SYS_INVALID_RECEIVER
+    );
+    assert_eq!(balance(executor, &burnt), &burnt_before + &value);
+}